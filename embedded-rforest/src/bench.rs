@@ -0,0 +1,147 @@
+//! Cycle counts of predictions, measured on the target itself.
+//!
+//! [`bench_predict`] times [`Predict::predict`] on every input of a set, such
+//! as the vectors written by `forest-optimizer export-test-vectors`, with a
+//! [`CycleCounter`]. On Cortex-M3 and later cores, the `bench-dwt` feature
+//! adds [`Dwt`], which reads the cycle counter of the DWT unit; other targets
+//! implement [`CycleCounter`] with a timer of their own.
+//!
+//! ```ignore
+//! let mut peripherals = cortex_m::Peripherals::take().unwrap();
+//! let mut dwt = Dwt::new(&mut peripherals.DCB, peripherals.DWT).unwrap();
+//! let inputs = vectors::TEST_VECTORS.iter().map(|(features, _)| &features[..]);
+//! let report = bench_predict(&forest, inputs, &mut dwt).unwrap();
+//! ```
+
+use core::hint::black_box;
+
+use crate::forest::{BranchLayout, OptimizedForest, Predict, ProblemType, access::NodeAccess};
+
+/// Number of times the cost of reading the counter is measured, keeping the
+/// smallest
+const CALIBRATION_RUNS: u32 = 8;
+
+/// A free-running counter of cycles, or of any other unit of time.
+pub trait CycleCounter {
+    /// The current count. It may wrap around: only the difference between
+    /// two counts less than `2^32` cycles apart is used.
+    fn cycles(&mut self) -> u32;
+}
+
+/// Cycles taken by the predictions of [`bench_predict`], less the cost of
+/// reading the counter.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct BenchReport {
+    /// Number of predictions timed
+    pub runs: u32,
+    pub min: u32,
+    /// Mean, rounded down
+    pub mean: u32,
+    pub max: u32,
+    /// Mean divided by the number of trees of the forest, rounded down
+    pub cycles_per_tree: u32,
+    /// Cycles taken by reading the counter twice in a row, subtracted from
+    /// every prediction
+    pub overhead: u32,
+}
+
+/// Time one prediction of `forest` for every input of `inputs`, in the cycles
+/// of `counter`. `None` if `inputs` is empty.
+///
+/// Interrupts taken while predicting are counted too: disable them around
+/// the call for steady counts.
+pub fn bench_predict<'data, 'i, P, B, A, C>(
+    forest: &OptimizedForest<'data, P, B, A>,
+    inputs: impl IntoIterator<Item = &'i [f32]>,
+    counter: &mut C,
+) -> Option<BenchReport>
+where
+    P: ProblemType,
+    B: BranchLayout,
+    A: NodeAccess<Branch = B> + ?Sized,
+    OptimizedForest<'data, P, B, A>: Predict,
+    C: CycleCounter + ?Sized,
+{
+    let overhead = (0..CALIBRATION_RUNS)
+        .map(|_| {
+            let start = counter.cycles();
+            counter.cycles().wrapping_sub(start)
+        })
+        .min()
+        .unwrap_or(0);
+
+    let (mut runs, mut min, mut max, mut sum) = (0u32, u32::MAX, 0u32, 0u64);
+    for features in inputs {
+        let features = black_box(features);
+        let start = counter.cycles();
+        black_box(forest.predict(features));
+        let cycles = counter
+            .cycles()
+            .wrapping_sub(start)
+            .saturating_sub(overhead);
+
+        runs += 1;
+        min = min.min(cycles);
+        max = max.max(cycles);
+        sum += u64::from(cycles);
+    }
+
+    if runs == 0 {
+        return None;
+    }
+    // The mean of `u32`s is a `u32`
+    let mean = (sum / u64::from(runs)) as u32;
+    Some(BenchReport {
+        runs,
+        min,
+        mean,
+        max,
+        cycles_per_tree: mean / forest.num_trees().max(1),
+        overhead,
+    })
+}
+
+#[cfg(feature = "bench-dwt")]
+pub use self::dwt::Dwt;
+
+#[cfg(feature = "bench-dwt")]
+mod dwt {
+    use cortex_m::peripheral::{DCB, DWT};
+
+    use super::CycleCounter;
+
+    /// The cycle counter of the DWT unit, on Cortex-M3 and later cores.
+    pub struct Dwt {
+        dwt: DWT,
+    }
+
+    impl Dwt {
+        /// Enable tracing and the cycle counter. `None` if the core has no
+        /// cycle counter.
+        pub fn new(dcb: &mut DCB, mut dwt: DWT) -> Option<Self> {
+            if !DWT::has_cycle_counter() {
+                return None;
+            }
+            dcb.enable_trace();
+            // Some cores, such as the Cortex-M7 of STM32F7s, lock the DWT
+            // after a power cycle
+            DWT::unlock();
+            dwt.enable_cycle_counter();
+            Some(Self { dwt })
+        }
+
+        /// Stop the cycle counter, and give the DWT back.
+        pub fn free(mut self) -> DWT {
+            self.dwt.disable_cycle_counter();
+            self.dwt
+        }
+    }
+
+    impl CycleCounter for Dwt {
+        #[inline(always)]
+        fn cycles(&mut self) -> u32 {
+            DWT::cycle_count()
+        }
+    }
+}
@@ -1,32 +1,164 @@
-use core::{
-    fmt::{self, Debug},
-    marker::PhantomData,
-    num::NonZeroU8,
-};
+#[cfg(feature = "fmt")]
+use core::fmt;
+use core::{borrow::Borrow, marker::PhantomData, num::NonZeroU8, ops::Range};
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, TryFromBytes};
+
+use self::{abi::FORMAT_ABI, access::NodeAccess};
 
-use heapless::LinearMap;
-use zerocopy::{
-    FromBytes, Immutable, IntoBytes, KnownLayout, TryFromBytes,
-    byteorder::little_endian::{F32, U32},
+use crate::{
+    Error, MaybeDisplay,
+    ptr::{F32, NodeIndex, NodePointer, RelativeU16, U16, U32},
 };
 
-use crate::{Error, ptr::NodePointer};
+pub use any::AnyOptimizedForest;
+pub use bundle::ForestBundle;
+pub use calibration::Platt;
+pub use compact::CompactBranch;
+pub use integer::IntegerBranch;
+pub use session::PredictSession;
+pub use transform::Transform;
+pub use visit::{Child, NodeVisit};
+pub use votes::{MAX_CLASSIFICATION_TREES, MAX_TARGETS, Votes};
 
+pub mod abi;
+pub mod access;
+pub mod any;
+pub mod bundle;
+pub mod calibration;
+pub mod compact;
 pub mod deserialize;
+pub mod encryption;
+pub mod endian;
+pub mod fingerprint;
+pub mod integer;
+pub mod session;
+pub mod slot;
+pub mod support;
+pub mod transform;
+pub mod visit;
+pub mod votes;
 
 #[cfg(feature = "std")]
+pub mod quickscorer;
 pub mod serialize;
 
+#[cfg(feature = "avr-progmem")]
+pub mod progmem;
+
+#[cfg(feature = "soa")]
+pub mod soa;
+
+#[cfg(feature = "signature")]
+pub mod signature;
+
 pub trait ProblemType {
     type Output: Copy;
     const HAS_TARGETS: bool;
 }
 
+/// Predictions of a forest.
+///
+/// Predictions never allocate, whatever the features of the crate: they walk
+/// the borrowed nodes and count votes on the stack, so forests predict on
+/// targets without an allocator. Implementations must keep it that way.
+///
+/// Forests reading their branches from fallible storage, see [`access`],
+/// panic if a branch cannot be read: their `try_predict` fails instead.
 pub trait Predict {
     type ProblemType: ProblemType;
 
     /// Make a prediction based on input values (features)
+    #[must_use]
     fn predict(&self, features: &[f32]) -> <Self::ProblemType as ProblemType>::Output;
+
+    /// Make a prediction like [`Predict::predict`], also returning the number
+    /// of branches visited across all trees. Slower, meant for profiling.
+    #[must_use]
+    fn predict_counting(
+        &self,
+        features: &[f32],
+    ) -> (<Self::ProblemType as ProblemType>::Output, u32);
+}
+
+/// The kind of problem a serialized forest solves, known only at runtime.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub enum ProblemKind {
+    Classification,
+    Regression,
+}
+
+/// How a forest combines the predictions of its trees, recorded in the
+/// header of a serialized forest, see [`SUM`](deserialize::SUM) and
+/// [`ORDINAL`](deserialize::ORDINAL).
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub enum Aggregation {
+    /// The class most trees predict, as classification forests do by
+    /// default
+    MajorityVote,
+    /// The median of the classes the trees predict, for classes with a
+    /// natural order such as severity levels, see [`Votes::median`]
+    OrdinalMedian,
+    /// The mean of the tree predictions, as random forests regress
+    MeanValue,
+    /// `base` plus the sum of the tree predictions, as boosted ensembles such
+    /// as XGBoost or LightGBM regress
+    Sum { base: f32 },
+}
+
+/// Base scores are finite: forests with others are rejected.
+impl Eq for Aggregation {}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregation::MajorityVote => write!(f, "majority vote"),
+            Aggregation::OrdinalMedian => write!(f, "ordinal median"),
+            Aggregation::MeanValue => write!(f, "mean value"),
+            Aggregation::Sum { base } => write!(f, "sum, base score {base}"),
+        }
+    }
+}
+
+/// How a branch compares a feature to its threshold, recorded in the
+/// header of a serialized forest, see
+/// [`LESS_THAN`](deserialize::LESS_THAN).
+#[derive(Clone, Copy, Default, PartialEq, Eq, TryFromBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(u8)]
+pub enum SplitRule {
+    /// `feature <= threshold` goes left, as in R and scikit-learn, and in
+    /// forests written before the rule was recorded
+    #[default]
+    LessOrEqual = 0,
+    /// `feature < threshold` goes left, ties going right, as in XGBoost and
+    /// LightGBM
+    Less = 1,
+}
+
+impl SplitRule {
+    /// Whether `value` goes to the left child of a branch splitting at
+    /// `threshold`. NaN always goes right.
+    #[inline(always)]
+    pub fn goes_left<T: PartialOrd>(self, value: T, threshold: T) -> bool {
+        match self {
+            SplitRule::LessOrEqual => value <= threshold,
+            SplitRule::Less => value < threshold,
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for SplitRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitRule::LessOrEqual => write!(f, "<="),
+            SplitRule::Less => write!(f, "<"),
+        }
+    }
 }
 
 pub struct Classification {
@@ -52,67 +184,139 @@ impl ProblemType for Regression {
     const HAS_TARGETS: bool = false;
 }
 
-#[repr(transparent)]
-#[derive(IntoBytes, Clone, KnownLayout, Immutable, FromBytes)]
-pub struct Flags(U32);
-
-impl Flags {
-    fn new(split_var_idx: u32, left_is_prediction: bool, right_is_prediction: bool) -> Self {
-        assert!(split_var_idx <= u32::MAX >> 2);
+/// Layout of the branches of a serialized forest, recorded in its header.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(u8)]
+pub enum NodeLayout {
+    /// 16-byte [`Branch`]es, with 32-bit pointers
+    Standard = 0,
+    /// 8-byte [`CompactBranch`]es
+    Compact = 1,
+    /// 12-byte [`Branch<U16>`]es, with 16-bit pointers
+    Narrow = 2,
+    /// 12-byte [`Branch<RelativeU16>`]es, with 16-bit pointers relative to
+    /// their tree. The header is always followed by the offset of every tree
+    /// in the node array, see [`TREE_TABLE`](deserialize::TREE_TABLE).
+    Relative = 3,
+    /// 16-byte [`IntegerBranch`]es, with fixed-point thresholds, predicting
+    /// with [`OptimizedForest::predict_i32`]
+    Integer = 4,
+}
 
-        let val = split_var_idx
-            | ((left_is_prediction as u32) << (32 - 1))
-            | ((right_is_prediction as u32) << (32 - 2));
-        Self(U32::new(val))
+impl NodeLayout {
+    /// Size of a branch of this layout, in bytes.
+    pub const fn branch_size(self) -> usize {
+        match self {
+            NodeLayout::Standard => size_of::<Branch>(),
+            NodeLayout::Compact => size_of::<CompactBranch>(),
+            NodeLayout::Narrow => size_of::<Branch<U16>>(),
+            NodeLayout::Relative => size_of::<Branch<RelativeU16>>(),
+            NodeLayout::Integer => size_of::<IntegerBranch>(),
+        }
     }
+}
 
-    fn left_prediction(&self) -> bool {
-        (self.0 >> (32 - 1)) & 1 != 0
+#[cfg(feature = "fmt")]
+impl fmt::Display for NodeLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeLayout::Standard => write!(f, "standard"),
+            NodeLayout::Compact => write!(f, "compact"),
+            NodeLayout::Narrow => write!(f, "narrow"),
+            NodeLayout::Relative => write!(f, "relative"),
+            NodeLayout::Integer => write!(f, "integer"),
+        }
     }
+}
 
-    fn right_prediction(&self) -> bool {
-        (self.0 >> (32 - 2)) & 1 != 0
-    }
+impl TryFrom<u8> for NodeLayout {
+    type Error = Error;
 
-    fn split_var_idx(&self) -> u32 {
-        (self.0 & (u32::MAX >> 2)).get()
+    fn try_from(value: u8) -> Result<Self, Error> {
+        match value {
+            0 => Ok(NodeLayout::Standard),
+            1 => Ok(NodeLayout::Compact),
+            2 => Ok(NodeLayout::Narrow),
+            3 => Ok(NodeLayout::Relative),
+            4 => Ok(NodeLayout::Integer),
+            _ => Err(Error::MalformedForest),
+        }
     }
 }
 
-impl Debug for Flags {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "Flags {{ left is leaf: {}, right is leaf: {}, split var: {} }}",
-            self.left_prediction(),
-            self.right_prediction(),
-            self.split_var_idx()
-        )
-    }
+/// A branch of an optimized forest, as traversed by [`Predict`].
+pub trait BranchLayout: FromBytes + KnownLayout + Immutable + MaybeDisplay {
+    /// NodeLayout recorded in the header of forests made of this branch
+    const NODE_LAYOUT: NodeLayout;
+
+    /// Whether branch pointers are relative to the first branch of their
+    /// tree
+    const RELATIVE: bool = false;
+
+    /// Index of the feature this branch splits on
+    fn split_with(&self) -> u32;
+
+    /// Threshold: features smaller or equal go left
+    fn split_at(&self) -> f32;
+
+    /// Raw left pointer: a branch index, or a prediction
+    fn left(&self) -> u32;
+
+    /// Raw right pointer: a branch index, or a prediction
+    fn right(&self) -> u32;
+
+    fn left_is_prediction(&self) -> bool;
+
+    fn right_is_prediction(&self) -> bool;
+
+    /// Regression prediction held by a raw leaf pointer, for layouts whose
+    /// leaf pointers can hold predictions rather than leaf table indices
+    fn inline_leaf(ptr: u32) -> Option<f32>;
+
+    /// Hand the bytes of the serialized branch to `write`, in order.
+    fn write_bytes(&self, write: impl FnMut(&[u8]));
 }
 
-#[derive(Debug, Clone, IntoBytes, KnownLayout, Immutable, FromBytes)]
+/// A branch of an optimized forest, with child pointers of type `Ptr`.
+///
+/// The top bit of a pointer tells whether it points at a leaf, so 32-bit
+/// pointers hold indices of up to 31 bits. 16-bit pointers make for 12-byte
+/// branches, but only fit 32768 branches, classes or leaf table values.
+/// Regression leaves always go through the leaf table.
+#[derive(Clone, KnownLayout, Immutable, FromBytes)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
 #[repr(C, align(4))]
-pub struct Branch {
-    left: NodePointer,
-    right: NodePointer,
+pub struct Branch<Ptr: NodeIndex = U32> {
+    left: Ptr,
+    right: Ptr,
     split_at: F32,
-    flags: Flags,
+    split_with: U32,
 }
 
-impl Branch {
+abi::assert_layout!(
+    Branch,
+    FORMAT_ABI.standard_branch,
+    [left: U32, right: U32, split_at: F32, split_with: U32]
+);
+abi::assert_layout!(
+    Branch<U16>,
+    FORMAT_ABI.narrow_branch,
+    [left: U16, right: U16, split_at: F32, split_with: U32]
+);
+abi::assert_layout!(
+    Branch<RelativeU16>,
+    FORMAT_ABI.narrow_branch,
+    [left: RelativeU16, right: RelativeU16, split_at: F32, split_with: U32]
+);
+
+impl<Ptr: NodeIndex> Branch<Ptr> {
+    /// Build a branch from child pointers tagged with
+    /// [`NodeIndex::tagged`].
     #[inline]
-    pub fn new(
-        split_with: u32,
-        split_at: f32,
-        left: NodePointer,
-        right: NodePointer,
-        left_leaf: bool,
-        right_leaf: bool,
-    ) -> Self {
-        let flags = Flags::new(split_with, left_leaf, right_leaf);
+    pub fn from_ptrs(split_with: u32, split_at: f32, left: Ptr, right: Ptr) -> Self {
         Self {
-            flags,
+            split_with: U32::new(split_with),
             split_at: F32::new(split_at),
             left,
             right,
@@ -121,7 +325,7 @@ impl Branch {
 
     #[inline]
     pub fn split_with(&self) -> u32 {
-        self.flags.split_var_idx()
+        self.split_with.get()
     }
 
     #[inline]
@@ -129,77 +333,632 @@ impl Branch {
         self.split_at.get()
     }
 
+    /// Whether the left pointer holds a prediction rather than a branch index.
+    #[inline]
+    pub fn left_is_prediction(&self) -> bool {
+        self.left.is_leaf()
+    }
+
+    /// Whether the right pointer holds a prediction rather than a branch index.
+    #[inline]
+    pub fn right_is_prediction(&self) -> bool {
+        self.right.is_leaf()
+    }
+}
+
+impl Branch {
+    #[inline]
+    pub fn new(split_with: u32, split_at: f32, left: NodePointer, right: NodePointer) -> Self {
+        Self::from_ptrs(
+            split_with,
+            split_at,
+            U32::new(left.as_ptr()),
+            U32::new(right.as_ptr()),
+        )
+    }
+
     #[inline]
     pub fn left_ptr(&self) -> NodePointer {
-        self.left
+        NodePointer::read_from_bytes(self.left.as_bytes()).unwrap()
     }
 
     #[inline]
     pub fn right_ptr(&self) -> NodePointer {
-        self.right
+        NodePointer::read_from_bytes(self.right.as_bytes()).unwrap()
     }
 }
 
-impl fmt::Display for Branch {
+impl<Ptr: NodeIndex> BranchLayout for Branch<Ptr> {
+    const NODE_LAYOUT: NodeLayout = Ptr::NODE_LAYOUT;
+    const RELATIVE: bool = Ptr::RELATIVE;
+
+    #[inline(always)]
+    fn split_with(&self) -> u32 {
+        self.split_with()
+    }
+
+    #[inline(always)]
+    fn split_at(&self) -> f32 {
+        self.split_at()
+    }
+
+    #[inline(always)]
+    fn left(&self) -> u32 {
+        self.left.index()
+    }
+
+    #[inline(always)]
+    fn right(&self) -> u32 {
+        self.right.index()
+    }
+
+    #[inline(always)]
+    fn left_is_prediction(&self) -> bool {
+        self.left_is_prediction()
+    }
+
+    #[inline(always)]
+    fn right_is_prediction(&self) -> bool {
+        self.right_is_prediction()
+    }
+
+    /// Leaf pointers are too narrow for a prediction.
+    #[inline(always)]
+    fn inline_leaf(_: u32) -> Option<f32> {
+        None
+    }
+
+    fn write_bytes(&self, mut write: impl FnMut(&[u8])) {
+        // Field by field: with a generic pointer, zerocopy cannot prove the
+        // branch has no padding
+        write(self.left.as_bytes());
+        write(self.right.as_bytes());
+        write(self.split_at.as_bytes());
+        write(self.split_with.as_bytes());
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl<Ptr: NodeIndex> fmt::Display for Branch<Ptr> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let child = |ptr: Ptr| (if ptr.is_leaf() { "leaf " } else { "" }, ptr.index());
+        let (left, right) = (child(self.left), child(self.right));
         write!(
             f,
-            "Branch | split var: {}, split: {}, left: {}, right: {}",
-            self.flags.split_var_idx(),
-            self.split_at,
-            self.left,
-            self.right
+            "Branch | split var: {}, split: {}, left: {}{}, right: {}{}",
+            self.split_with, self.split_at, left.0, left.1, right.0, right.1
         )
     }
 }
 
-/// An array-backed, optimized random forest model
+/// An array-backed, optimized random forest model, made of branches of
+/// layout `B` read through `A`: by default the node array of the serialized
+/// forest, see [`access`] for other storages.
 #[repr(C, align(4))]
 #[derive(TryFromBytes, KnownLayout, Immutable)]
-pub struct OptimizedForest<'data, P: ProblemType> {
+pub struct OptimizedForest<
+    'data,
+    P: ProblemType,
+    B: BranchLayout = Branch,
+    A: NodeAccess<Branch = B> + ?Sized = [B],
+> {
     num_trees: U32,
     num_features: u8,
     /// If num_targets is Some, we have a classification problem.
     /// Otherwise, we have a regression problem.
     num_targets: Option<NonZeroU8>,
-    _padding: [u8; 2],
-    nodes: &'data [Branch],
+    /// Whether the tree predictions are summed, see [`Aggregation::Sum`].
+    /// Regression only
+    sum: bool,
+    /// Whether the votes are combined by their median, see
+    /// [`Aggregation::OrdinalMedian`]. Classification only
+    ordinal: bool,
+    /// Fingerprint of the feature and target names, 0 if not recorded
+    fingerprint: U32,
+    /// Base score added to the sum of the tree predictions, if `sum`
+    base_score: F32,
+    /// Transform of the combined output. Regression only
+    transform: Transform,
+    /// How branches compare features to their thresholds
+    split_rule: SplitRule,
+    nodes: &'data A,
+    /// Regression leaf values, indexed by leaf pointers. Empty if leaves are
+    /// stored in the pointers themselves.
+    leaves: &'data [F32],
+    /// Index of the first branch of every tree, whose root it is. Empty if
+    /// the forest has no tree table, and roots are stored at the index of
+    /// their tree.
+    tree_offsets: &'data [U32],
+    /// Calibration of the probability of every class. Empty if
+    /// probabilities are raw vote fractions, always for regression
+    calibration: &'data [Platt],
+    /// Number of training samples of the leaf of each side of each branch,
+    /// indexed by leaf id. Empty if not recorded, see [`support`]
+    support: &'data [U16],
     _problem: PhantomData<P>,
 }
 
-impl<P: ProblemType> OptimizedForest<'_, P> {
-    pub fn nodes(&self) -> &[Branch] {
+impl<P: ProblemType, B: BranchLayout> OptimizedForest<'_, P, B> {
+    pub fn nodes(&self) -> &[B] {
         self.nodes
     }
 
+    /// Branches of every tree, in order, root first. Yields nothing if the
+    /// forest has no tree table.
+    pub fn iter_trees(&self) -> impl Iterator<Item = &[B]> {
+        (0..self.tree_offsets.len() as u32)
+            .filter_map(|tree| self.tree_range(tree))
+            .map(|range| &self.nodes[range])
+    }
+}
+
+impl<P: ProblemType, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    OptimizedForest<'_, P, B, A>
+{
     pub fn num_features(&self) -> u8 {
         self.num_features
     }
 
-    fn next_left(&self, branch: &Branch) -> &Branch {
-        &self.nodes[branch.left_ptr().as_ptr() as usize]
+    pub fn num_trees(&self) -> u32 {
+        self.num_trees.get()
+    }
+
+    /// Size of this forest once serialized, in bytes.
+    pub fn serialized_len(&self) -> usize {
+        deserialize::serialized_len_with(
+            B::NODE_LAYOUT,
+            self.tree_offsets.len(),
+            self.fingerprint.get() != 0,
+            self.sum,
+            self.transform != Transform::Identity,
+            self.calibration.len(),
+            self.nodes.len(),
+            self.leaves.len(),
+            !self.support.is_empty(),
+        )
+    }
+
+    /// Bytes the forest occupies in flash, for forests embedded as written:
+    /// its header, tables, branches and leaves, the length of the buffer it
+    /// was deserialized from. The same as [`OptimizedForest::serialized_len`].
+    pub fn size_in_bytes(&self) -> usize {
+        self.serialized_len()
+    }
+
+    /// Number of branches of the forest.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn problem_kind(&self) -> ProblemKind {
+        if self.num_targets.is_some() {
+            ProblemKind::Classification
+        } else {
+            ProblemKind::Regression
+        }
+    }
+
+    /// How the forest combines the predictions of its trees.
+    pub fn aggregation(&self) -> Aggregation {
+        match (self.problem_kind(), self.sum) {
+            (ProblemKind::Classification, _) if self.ordinal => Aggregation::OrdinalMedian,
+            (ProblemKind::Classification, _) => Aggregation::MajorityVote,
+            (ProblemKind::Regression, false) => Aggregation::MeanValue,
+            (ProblemKind::Regression, true) => Aggregation::Sum {
+                base: self.base_score.get(),
+            },
+        }
+    }
+
+    /// Transform of the combined output of the trees: always
+    /// [`Transform::Identity`] for classification.
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// How the branches compare features to their thresholds.
+    pub fn split_rule(&self) -> SplitRule {
+        self.split_rule
+    }
+
+    /// Calibration of the probability of every class, in class order. Empty
+    /// if [`OptimizedForest::predict_proba`] returns raw vote fractions.
+    pub fn calibration(&self) -> &[Platt] {
+        self.calibration
+    }
+
+    /// Bytes of stack [`Predict::predict`] keeps while predicting with this
+    /// forest, on top of the frames of its calls (a few dozen bytes, depending
+    /// on the target): the [`MAX_TARGETS`] vote counters of classification,
+    /// whatever the number of classes, or the sum of the leaves of regression.
+    ///
+    /// [`OptimizedForest::predict_with`] counts votes in
+    /// `size_of::<Votes<N>>()` bytes instead, at least two per class.
+    pub fn predict_ram_estimate(&self) -> usize {
+        match self.problem_kind() {
+            ProblemKind::Classification => size_of::<Votes>(),
+            ProblemKind::Regression => size_of::<f32>(),
+        }
+    }
+
+    /// [`Fingerprint`](fingerprint::fingerprint) of the feature and target
+    /// names the forest was written with, or 0 if it was written without
+    /// one.
+    pub fn feature_fingerprint(&self) -> u32 {
+        self.fingerprint.get()
+    }
+
+    /// Check that the forest was written for the features and targets of
+    /// the `expected` fingerprint, e.g. computed by
+    /// [`fingerprint::fingerprint`] from the features the firmware fills
+    /// in. Fails with [`Error::WrongFingerprint`] otherwise, including for
+    /// forests written without a fingerprint.
+    pub fn check_fingerprint(&self, expected: u32) -> Result<(), Error> {
+        if self.fingerprint.get() == expected {
+            Ok(())
+        } else {
+            Err(Error::WrongFingerprint)
+        }
+    }
+
+    /// Index of the first branch of every tree, if the forest has a tree
+    /// table, as relative layouts always do. Empty otherwise, as the root of
+    /// every tree is the branch at the index of the tree.
+    pub fn tree_offsets(&self) -> &[U32] {
+        self.tree_offsets
+    }
+
+    /// Index of the root branch of tree `tree_id`.
+    #[inline(always)]
+    pub fn tree_root(&self, tree_id: u32) -> usize {
+        // Roots are stored at the tree index, unless trees are stored one
+        // after the other
+        if self.tree_offsets.is_empty() {
+            tree_id as usize
+        } else {
+            self.tree_offsets[tree_id as usize].get() as usize
+        }
+    }
+
+    /// Indices of the branches of tree `tree_id`, root first, or `None` if
+    /// the forest has no tree table or no such tree.
+    pub fn tree_range(&self, tree_id: u32) -> Option<Range<usize>> {
+        let start = self.tree_offsets.get(tree_id as usize)?.get() as usize;
+        let end = self
+            .tree_offsets
+            .get(tree_id as usize + 1)
+            .map_or(self.nodes.len(), |end| end.get() as usize);
+        Some(start..end)
+    }
+
+    /// Index the branch pointers of tree `tree_id` are relative to: its root
+    /// for relative layouts, 0 for absolute ones.
+    #[inline(always)]
+    pub fn tree_base(&self, tree_id: u32) -> usize {
+        if B::RELATIVE {
+            self.tree_offsets[tree_id as usize].get() as usize
+        } else {
+            0
+        }
+    }
+
+    /// Leaf table of a regression forest. Empty if leaves are stored in the
+    /// child pointers.
+    pub fn leaves(&self) -> &[F32] {
+        self.leaves
+    }
+
+    /// Regression prediction of a leaf pointer.
+    #[inline(always)]
+    pub fn leaf_value(&self, ptr: u32) -> f32 {
+        match B::inline_leaf(ptr) {
+            Some(value) if self.leaves.is_empty() => value,
+            _ => self.leaves[ptr as usize].get(),
+        }
     }
 
-    fn next_right(&self, branch: &Branch) -> &Branch {
-        &self.nodes[branch.right_ptr().as_ptr() as usize]
+    pub fn layout(&self) -> NodeLayout {
+        B::NODE_LAYOUT
+    }
+
+    /// Check the structural integrity of the forest: every tree root must be
+    /// present, every child pointer must stay inside the node array (inside
+    /// its own tree, if the forest has a tree table) and point past its
+    /// branch, every branch must split on one of the `num_features` features,
+    /// and every classification leaf must name a valid target. Trees of the
+    /// table must not overlap. Classification forests hold at most
+    /// [`MAX_CLASSIFICATION_TREES`] trees.
+    ///
+    /// Predictions of a valid forest therefore visit each branch at most
+    /// once per tree, and never panic on rows of at least `num_features`
+    /// features.
+    pub fn validate(&self) -> Result<(), Error> {
+        let num_trees = self.num_trees.get() as usize;
+        if num_trees > self.nodes.len() {
+            return Err(Error::MalformedForest);
+        }
+
+        if self.num_targets.is_some() && self.num_trees.get() > MAX_CLASSIFICATION_TREES {
+            return Err(Error::MalformedForest);
+        }
+
+        if self.num_targets.is_some() && !self.leaves.is_empty() {
+            return Err(Error::MalformedForest);
+        }
+
+        // Integer forests predict the rounded mean of their leaves
+        if self.sum
+            && (self.num_targets.is_some()
+                || B::NODE_LAYOUT == NodeLayout::Integer
+                || !self.base_score.get().is_finite())
+        {
+            return Err(Error::MalformedForest);
+        }
+        if self.ordinal && self.num_targets.is_none() {
+            return Err(Error::MalformedForest);
+        }
+        let transform = self.transform != Transform::Identity;
+        if transform
+            && (self.num_targets.is_some()
+                || B::NODE_LAYOUT == NodeLayout::Integer
+                || !self.transform.is_valid())
+        {
+            return Err(Error::MalformedForest);
+        }
+        let num_targets = self.num_targets.map_or(0, |t| usize::from(t.get()));
+        if !self.calibration.is_empty()
+            && (self.calibration.len() != num_targets
+                || !self.calibration.iter().all(Platt::is_valid))
+        {
+            return Err(Error::MalformedForest);
+        }
+        if !self.support.is_empty() && self.support.len() != 2 * self.nodes.len() {
+            return Err(Error::MalformedForest);
+        }
+
+        // Branches only point further down their tree, so that walking a
+        // tree always reaches a leaf. Leaves which do not decode fail
+        // visiting their branch
+        let check_tree = |tree: Range<usize>| -> Result<bool, Error> {
+            let base = if B::RELATIVE { tree.start } else { 0 };
+            let points_down = |child: Child, at: usize| match child {
+                Child::Branch(index) => index as usize > at && tree.contains(&(index as usize)),
+                Child::LeafClass(_) | Child::LeafValue(_) => true,
+            };
+            for at in tree.clone() {
+                let branch = self.visit_branch(at, base)?;
+                if branch.split_with >= u32::from(self.num_features)
+                    || !points_down(branch.left, at)
+                    || !points_down(branch.right, at)
+                {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+
+        if self.tree_offsets.is_empty() && !B::RELATIVE {
+            if !check_tree(0..self.nodes.len())? {
+                return Err(Error::MalformedForest);
+            }
+            return Ok(());
+        }
+
+        // Trees follow each other from the start of the node array, without
+        // overlapping, and their offsets fit the header
+        if self.tree_offsets.len() != num_trees
+            || deserialize::header_len_with(
+                num_trees,
+                self.fingerprint.get() != 0,
+                self.sum,
+                transform,
+                self.calibration.len(),
+            ) > usize::from(u16::MAX)
+            || self
+                .tree_offsets
+                .first()
+                .is_some_and(|start| start.get() != 0)
+        {
+            return Err(Error::MalformedForest);
+        }
+        for tree in 0..self.num_trees.get() {
+            let Some(range) = self.tree_range(tree) else {
+                return Err(Error::MalformedForest);
+            };
+            if range.is_empty() || range.end > self.nodes.len() || !check_tree(range)? {
+                return Err(Error::MalformedForest);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Walk a tree from its root down to a prediction, adding the number of
+    /// branches visited to `visits`. Returns the raw leaf pointer.
+    #[inline(always)]
+    fn walk(&self, tree_id: u32, features: &[f32], visits: &mut u32) -> Result<u32, Error> {
+        let base = self.tree_base(tree_id);
+        let mut node = self.nodes.branch(self.tree_root(tree_id))?;
+
+        loop {
+            *visits += 1;
+            let branch = node.borrow();
+            let test = self
+                .split_rule
+                .goes_left(features[branch.split_with() as usize], branch.split_at());
+
+            let next = if test {
+                if branch.left_is_prediction() {
+                    break Ok(branch.left());
+                }
+                branch.left()
+            } else {
+                if branch.right_is_prediction() {
+                    break Ok(branch.right());
+                }
+                branch.right()
+            };
+            node = self.nodes.branch(base + next as usize)?;
+        }
+    }
+
+    /// Write to `out` the id of the leaf tree `i` reaches from `features`,
+    /// for every tree `i`: twice the index in [`Self::nodes`] of the last
+    /// branch walked, plus 1 if its right side was taken. Every leaf has its
+    /// own id, even where the leaf table stores its value once, and both
+    /// ids of the branch of a single-leaf tree stand for its leaf.
+    ///
+    /// Ids depend on the order of the branches, so only compare ids of the
+    /// same serialized forest. The host maps them to the nodes of the
+    /// forest the optimized forest was made from.
+    ///
+    /// Fails with [`Error::WrongOutputLength`] if `out` does not hold one id
+    /// per tree, and with [`Error::Storage`] if a branch cannot be read.
+    pub fn predict_leaf_ids(&self, features: &[f32], out: &mut [u32]) -> Result<(), Error> {
+        if out.len() != self.num_trees.get() as usize {
+            return Err(Error::WrongOutputLength);
+        }
+        for (tree_id, id) in (0..).zip(out) {
+            (_, *id) = self.reach(tree_id, features)?;
+        }
+
+        Ok(())
+    }
+
+    /// Raw leaf pointer and id of the leaf tree `tree_id` reaches, see
+    /// [`Self::predict_leaf_ids`]
+    fn reach(&self, tree_id: u32, features: &[f32]) -> Result<(u32, u32), Error> {
+        let base = self.tree_base(tree_id);
+        let mut index = self.tree_root(tree_id);
+
+        loop {
+            let node = self.nodes.branch(index)?;
+            let branch = node.borrow();
+            let (next, is_leaf, side) = if self
+                .split_rule
+                .goes_left(features[branch.split_with() as usize], branch.split_at())
+            {
+                (branch.left(), branch.left_is_prediction(), 0)
+            } else {
+                (branch.right(), branch.right_is_prediction(), 1)
+            };
+            if is_leaf {
+                break Ok((next, 2 * index as u32 + side));
+            }
+            index = base + next as usize;
+        }
+    }
+}
+
+/// Value of a prediction reading branches through [`NodeAccess`], which
+/// only fails for fallible storage.
+#[inline(always)]
+fn read_or_panic<T>(prediction: Result<T, Error>) -> T {
+    match prediction {
+        Ok(prediction) => prediction,
+        #[cfg(feature = "fmt")]
+        Err(e) => panic!("Could not read a branch: {e:?}"),
+        #[cfg(not(feature = "fmt"))]
+        Err(_) => panic!("Could not read a branch"),
+    }
+}
+
+impl<'data, P: ProblemType, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    OptimizedForest<'data, P, B, A>
+{
+    /// This forest, with a tree table: tree `i` is made of the branches from
+    /// `tree_offsets[i]`, its root, up to the next tree. Fails if the trees
+    /// overlap, or point outside of themselves.
+    pub fn with_tree_offsets(self, tree_offsets: &'data [U32]) -> Result<Self, Error> {
+        let forest = Self {
+            tree_offsets,
+            ..self
+        };
+        forest.validate()?;
+
+        Ok(forest)
+    }
+
+    /// This forest, recording the [`fingerprint`](fingerprint::fingerprint)
+    /// of its feature and target names once serialized. 0 records none.
+    pub fn with_fingerprint(self, fingerprint: u32) -> Self {
+        Self {
+            fingerprint: U32::new(fingerprint),
+            ..self
+        }
+    }
+
+    /// This forest, comparing features to thresholds by `split_rule`.
+    pub fn with_split_rule(self, split_rule: SplitRule) -> Self {
+        Self { split_rule, ..self }
+    }
+
+    /// This forest, reading its branches through `nodes` instead, e.g. from
+    /// a copy of its node array in external flash. The branches are checked
+    /// like by [`validate`](Self::validate), reading each of them once.
+    /// Fails with [`Error::MalformedForest`] if `nodes` holds another number
+    /// of branches.
+    pub fn with_node_access<'n, N: NodeAccess<Branch = B> + ?Sized>(
+        self,
+        nodes: &'n N,
+    ) -> Result<OptimizedForest<'n, P, B, N>, Error>
+    where
+        'data: 'n,
+    {
+        if nodes.len() != self.nodes.len() {
+            return Err(Error::MalformedForest);
+        }
+
+        let forest = OptimizedForest {
+            num_trees: self.num_trees,
+            num_features: self.num_features,
+            num_targets: self.num_targets,
+            sum: self.sum,
+            ordinal: self.ordinal,
+            fingerprint: self.fingerprint,
+            base_score: self.base_score,
+            transform: self.transform,
+            split_rule: self.split_rule,
+            nodes,
+            leaves: self.leaves,
+            tree_offsets: self.tree_offsets,
+            calibration: self.calibration,
+            support: self.support,
+            _problem: PhantomData,
+        };
+        forest.validate()?;
+
+        Ok(forest)
     }
 }
 
-impl<'data> OptimizedForest<'data, Classification> {
+impl<'data, B: BranchLayout> OptimizedForest<'data, Classification, B> {
     pub fn new(
         num_trees: u32,
-        nodes: &'data [Branch],
+        nodes: &'data [B],
         num_features: u8,
         problem: Classification,
     ) -> Result<Self, Error> {
-        Ok(Self {
+        let forest = Self {
             num_trees: U32::new(num_trees),
             nodes,
             num_features,
             num_targets: Some(problem.num_targets),
-            _padding: [0; 2],
+            sum: false,
+            ordinal: false,
+            fingerprint: U32::new(0),
+            base_score: F32::new(0.0),
+            transform: Transform::Identity,
+            split_rule: SplitRule::LessOrEqual,
+            leaves: &[],
+            tree_offsets: &[],
+            calibration: &[],
+            support: &[],
             _problem: PhantomData,
-        })
+        };
+        forest.validate()?;
+
+        Ok(forest)
     }
 
     pub fn num_targets(&self) -> Option<NonZeroU8> {
@@ -207,100 +966,362 @@ impl<'data> OptimizedForest<'data, Classification> {
     }
 }
 
-impl Predict for OptimizedForest<'_, Classification> {
-    type ProblemType = Classification;
+impl<'data> OptimizedForest<'data, Classification, Branch<RelativeU16>> {
+    /// A forest whose tree `i` starts at branch `tree_offsets[i]`, which its
+    /// branch pointers are relative to.
+    pub fn new_relative(
+        nodes: &'data [Branch<RelativeU16>],
+        tree_offsets: &'data [U32],
+        num_features: u8,
+        problem: Classification,
+    ) -> Result<Self, Error> {
+        let forest = Self {
+            num_trees: U32::new(tree_offsets.len() as u32),
+            nodes,
+            num_features,
+            num_targets: Some(problem.num_targets),
+            sum: false,
+            ordinal: false,
+            fingerprint: U32::new(0),
+            base_score: F32::new(0.0),
+            transform: Transform::Identity,
+            split_rule: SplitRule::LessOrEqual,
+            leaves: &[],
+            tree_offsets,
+            calibration: &[],
+            support: &[],
+            _problem: PhantomData,
+        };
+        forest.validate()?;
+
+        Ok(forest)
+    }
+}
+
+impl<B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    OptimizedForest<'_, Classification, B, A>
+{
+    #[inline(always)]
+    fn classify<const N: usize>(&self, features: &[f32], visits: &mut u32) -> Result<u32, Error> {
+        let mut votes = Votes::<N>::new();
+        for tree_id in 0..self.num_trees.get() {
+            votes.add(self.walk(tree_id, features, visits)?);
+        }
+        Ok(self.decide(&votes))
+    }
+
+    /// The class `votes` elect, following [`OptimizedForest::aggregation`].
+    #[inline(always)]
+    pub(crate) fn decide<const N: usize>(&self, votes: &Votes<N>) -> u32 {
+        if self.ordinal {
+            votes.median()
+        } else {
+            votes.winner()
+        }
+    }
+
+    /// Make a prediction like [`Predict::predict`], failing with
+    /// [`Error::Storage`] rather than panicking if a branch cannot be read.
+    pub fn try_predict(&self, features: &[f32]) -> Result<u32, Error> {
+        self.classify::<MAX_TARGETS>(features, &mut 0)
+    }
+
+    /// Write to `out` the probability of every class: the fraction of the
+    /// trees voting for it, or, if the forest has a
+    /// [`calibration`](calibration), its calibrated probability. Votes are
+    /// counted in `out` itself, without any other stack.
+    ///
+    /// Fails with [`Error::WrongOutputLength`] if `out` does not hold one
+    /// probability per class, and with [`Error::Storage`] if a branch cannot
+    /// be read.
+    pub fn predict_proba(&self, features: &[f32], out: &mut [f32]) -> Result<(), Error> {
+        if self.num_targets.map(|t| usize::from(t.get())) != Some(out.len()) {
+            return Err(Error::WrongOutputLength);
+        }
+
+        out.fill(0.0);
+        for tree_id in 0..self.num_trees.get() {
+            out[self.walk(tree_id, features, &mut 0)? as usize] += 1.0;
+        }
+        let num_trees = self.num_trees.get() as f32;
+        for p in out.iter_mut() {
+            *p /= num_trees;
+        }
+        calibration::calibrate(self.calibration, out);
+
+        Ok(())
+    }
 
+    /// Make a prediction like [`Predict::predict`], counting votes in `N`
+    /// counters instead of [`MAX_TARGETS`]: less stack for forests known to
+    /// have few classes.
+    ///
+    /// # Panics
+    ///
+    /// If `N` is smaller than the number of targets of the forest, or a
+    /// branch cannot be read.
     #[must_use]
+    pub fn predict_with<const N: usize>(&self, features: &[f32]) -> u32 {
+        assert!(self.num_targets.is_some_and(|t| usize::from(t.get()) <= N));
+        read_or_panic(self.classify::<N>(features, &mut 0))
+    }
+}
+
+impl<'data, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    OptimizedForest<'data, Classification, B, A>
+{
+    /// This forest, calibrating the probabilities of
+    /// [`OptimizedForest::predict_proba`] with one [`Platt`] scaling per
+    /// class, in class order, once serialized. An empty `calibration`
+    /// removes it. Fails with [`Error::MalformedForest`] if it does not
+    /// hold one scaling per class, or parameters which are not finite.
+    pub fn with_calibration(self, calibration: &'data [Platt]) -> Result<Self, Error> {
+        let forest = Self {
+            calibration,
+            ..self
+        };
+        forest.validate()?;
+
+        Ok(forest)
+    }
+
+    /// This forest, combining the votes of its trees following
+    /// `aggregation` once serialized. Fails with [`Error::WrongProblemType`]
+    /// for the aggregations of regression forests.
+    pub fn with_aggregation(self, aggregation: Aggregation) -> Result<Self, Error> {
+        let ordinal = match aggregation {
+            Aggregation::MajorityVote => false,
+            Aggregation::OrdinalMedian => true,
+            Aggregation::MeanValue | Aggregation::Sum { .. } => {
+                return Err(Error::WrongProblemType);
+            }
+        };
+
+        Ok(Self { ordinal, ..self })
+    }
+}
+
+impl<B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized> Predict
+    for OptimizedForest<'_, Classification, B, A>
+{
+    type ProblemType = Classification;
+
     #[inline(never)]
     fn predict(&self, features: &[f32]) -> <Self::ProblemType as ProblemType>::Output {
-        let mut votes = LinearMap::<_, _, 255>::new();
+        read_or_panic(self.classify::<MAX_TARGETS>(features, &mut 0))
+    }
 
-        for tree_id in 0..self.num_trees.get() {
-            let mut node = &self.nodes[tree_id as usize];
-
-            let prediction = loop {
-                let test = features[node.split_with() as usize] <= node.split_at();
-
-                if test {
-                    if node.flags.left_prediction() {
-                        break node.left_ptr().as_ptr();
-                    } else {
-                        node = self.next_left(node);
-                    }
-                } else if node.flags.right_prediction() {
-                    break node.right_ptr().as_ptr();
-                } else {
-                    node = self.next_right(node);
-                }
-            };
+    fn predict_counting(&self, features: &[f32]) -> (u32, u32) {
+        let mut visits = 0;
+        let prediction = read_or_panic(self.classify::<MAX_TARGETS>(features, &mut visits));
+        (prediction, visits)
+    }
+}
 
-            // Register the vote for this tree's prediction
-            let vote = votes.get_mut(&prediction);
-            if let Some(v) = vote {
-                *v += 1;
-            } else {
-                votes.insert(prediction, 0).unwrap();
-            }
+/// A classification forest known to predict exactly `C` classes, which
+/// counts votes in `C` counters instead of [`MAX_TARGETS`].
+///
+/// Built from a forest with [`TryFrom`], which fails with
+/// [`Error::WrongTargetCount`] if the forest has another number of targets.
+/// Predicts exactly like the forest otherwise.
+pub struct ClassifierN<'forest, 'data, const C: usize, B: BranchLayout = Branch> {
+    forest: &'forest OptimizedForest<'data, Classification, B>,
+}
+
+impl<'forest, 'data, const C: usize, B: BranchLayout>
+    TryFrom<&'forest OptimizedForest<'data, Classification, B>>
+    for ClassifierN<'forest, 'data, C, B>
+{
+    type Error = Error;
+
+    fn try_from(forest: &'forest OptimizedForest<'data, Classification, B>) -> Result<Self, Error> {
+        match forest.num_targets {
+            Some(targets) if usize::from(targets.get()) == C => Ok(Self { forest }),
+            _ => Err(Error::WrongTargetCount),
         }
+    }
+}
 
-        votes
-            .into_iter()
-            .max_by_key(|&(_, count)| count)
-            .map(|(num, _)| num)
-            .copied()
-            .unwrap()
+impl<'data, const C: usize, B: BranchLayout> ClassifierN<'_, 'data, C, B> {
+    pub fn forest(&self) -> &OptimizedForest<'data, Classification, B> {
+        self.forest
     }
 }
 
-impl<'data> OptimizedForest<'data, Regression> {
-    pub fn new(num_trees: u32, nodes: &'data [Branch], num_features: u8) -> Result<Self, Error> {
-        Ok(Self {
+impl<const C: usize, B: BranchLayout> Predict for ClassifierN<'_, '_, C, B> {
+    type ProblemType = Classification;
+
+    #[inline(never)]
+    fn predict(&self, features: &[f32]) -> u32 {
+        read_or_panic(self.forest.classify::<C>(features, &mut 0))
+    }
+
+    fn predict_counting(&self, features: &[f32]) -> (u32, u32) {
+        let mut visits = 0;
+        let prediction = read_or_panic(self.forest.classify::<C>(features, &mut visits));
+        (prediction, visits)
+    }
+}
+
+impl<'data, B: BranchLayout> OptimizedForest<'data, Regression, B> {
+    /// A forest whose leaf pointers hold the predictions themselves, which
+    /// only [`CompactBranch`]es can.
+    pub fn new(num_trees: u32, nodes: &'data [B], num_features: u8) -> Result<Self, Error> {
+        Self::with_leaves(num_trees, nodes, num_features, &[])
+    }
+
+    /// A forest whose leaf pointers index `leaves`. An empty table means the
+    /// pointers hold the predictions, as with [`OptimizedForest::new`].
+    /// [`Branch`]es always need a table.
+    pub fn with_leaves(
+        num_trees: u32,
+        nodes: &'data [B],
+        num_features: u8,
+        leaves: &'data [F32],
+    ) -> Result<Self, Error> {
+        let forest = Self {
             num_trees: U32::new(num_trees),
             nodes,
             num_features,
             num_targets: None,
-            _padding: [0; 2],
+            sum: false,
+            ordinal: false,
+            fingerprint: U32::new(0),
+            base_score: F32::new(0.0),
+            transform: Transform::Identity,
+            split_rule: SplitRule::LessOrEqual,
+            leaves,
+            tree_offsets: &[],
+            calibration: &[],
+            support: &[],
             _problem: PhantomData,
-        })
+        };
+        forest.validate()?;
+
+        Ok(forest)
     }
 }
 
-impl Predict for OptimizedForest<'_, Regression> {
-    type ProblemType = Regression;
+impl<'data> OptimizedForest<'data, Regression, Branch<RelativeU16>> {
+    /// A forest whose tree `i` starts at branch `tree_offsets[i]`, which its
+    /// branch pointers are relative to, and whose leaf pointers index
+    /// `leaves`.
+    pub fn new_relative(
+        nodes: &'data [Branch<RelativeU16>],
+        tree_offsets: &'data [U32],
+        num_features: u8,
+        leaves: &'data [F32],
+    ) -> Result<Self, Error> {
+        let forest = Self {
+            num_trees: U32::new(tree_offsets.len() as u32),
+            nodes,
+            num_features,
+            num_targets: None,
+            sum: false,
+            ordinal: false,
+            fingerprint: U32::new(0),
+            base_score: F32::new(0.0),
+            transform: Transform::Identity,
+            split_rule: SplitRule::LessOrEqual,
+            leaves,
+            tree_offsets,
+            calibration: &[],
+            support: &[],
+            _problem: PhantomData,
+        };
+        forest.validate()?;
 
-    #[must_use]
-    #[inline(never)]
-    fn predict(&self, features: &[f32]) -> f32 {
+        Ok(forest)
+    }
+}
+
+impl<B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized> OptimizedForest<'_, Regression, B, A> {
+    /// This forest, combining the predictions of its trees following
+    /// `aggregation` once serialized. Fails with [`Error::WrongProblemType`]
+    /// for the aggregations of classification forests, and with
+    /// [`Error::MalformedForest`] for a sum of integer branches or a base
+    /// score which is not finite.
+    pub fn with_aggregation(self, aggregation: Aggregation) -> Result<Self, Error> {
+        let (sum, base) = match aggregation {
+            Aggregation::MajorityVote | Aggregation::OrdinalMedian => {
+                return Err(Error::WrongProblemType);
+            }
+            Aggregation::MeanValue => (false, 0.0),
+            Aggregation::Sum { base } => (true, base),
+        };
+        let forest = Self {
+            sum,
+            base_score: F32::new(base),
+            ..self
+        };
+        forest.validate()?;
+
+        Ok(forest)
+    }
+
+    /// This forest, transforming its combined output with `transform` once
+    /// serialized. Fails with [`Error::MalformedForest`] for integer
+    /// branches, or parameters which are not finite.
+    pub fn with_transform(self, transform: Transform) -> Result<Self, Error> {
+        let forest = Self { transform, ..self };
+        forest.validate()?;
+
+        Ok(forest)
+    }
+
+    /// Combine `sum`, the sum of the predictions of every tree, following
+    /// [`OptimizedForest::aggregation`], then apply
+    /// [`OptimizedForest::transform`].
+    #[inline(always)]
+    pub(crate) fn aggregate(&self, sum: f32) -> f32 {
+        let combined = if self.sum {
+            self.base_score.get() + sum
+        } else {
+            sum / self.num_trees.get() as f32
+        };
+        self.transform.apply(combined)
+    }
+
+    #[inline(always)]
+    fn regress(&self, features: &[f32], visits: &mut u32) -> Result<f32, Error> {
         let mut result = 0.0;
 
         for tree_id in 0..self.num_trees.get() {
-            let mut node = &self.nodes[tree_id as usize];
-
-            let prediction = loop {
-                let test = features[node.split_with() as usize] <= node.split_at();
-
-                if test {
-                    if node.flags.left_prediction() {
-                        break node.left_ptr().as_f32();
-                    } else {
-                        node = self.next_left(node);
-                    }
-                } else if node.flags.right_prediction() {
-                    break node.right_ptr().as_f32();
-                } else {
-                    node = self.next_right(node);
-                }
-            };
+            let prediction = self.leaf_value(self.walk(tree_id, features, visits)?);
 
             // Register the vote for this tree's prediction
             result += prediction;
         }
 
-        result / self.num_trees.get() as f32
+        Ok(self.aggregate(result))
+    }
+
+    /// Make a prediction like [`Predict::predict`], failing with
+    /// [`Error::Storage`] rather than panicking if a branch cannot be read.
+    pub fn try_predict(&self, features: &[f32]) -> Result<f32, Error> {
+        self.regress(features, &mut 0)
     }
 }
 
-impl<P: ProblemType> fmt::Display for OptimizedForest<'_, P> {
+impl<B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized> Predict
+    for OptimizedForest<'_, Regression, B, A>
+{
+    type ProblemType = Regression;
+
+    #[inline(never)]
+    fn predict(&self, features: &[f32]) -> f32 {
+        read_or_panic(self.regress(features, &mut 0))
+    }
+
+    fn predict_counting(&self, features: &[f32]) -> (f32, u32) {
+        let mut visits = 0;
+        let prediction = read_or_panic(self.regress(features, &mut visits));
+        (prediction, visits)
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl<P: ProblemType, B: BranchLayout> fmt::Display for OptimizedForest<'_, P, B> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(tgts) = self.num_targets {
             writeln!(
@@ -321,8 +1342,19 @@ impl<P: ProblemType> fmt::Display for OptimizedForest<'_, P> {
             )?;
         }
 
-        for (i, node) in self.nodes.iter().enumerate() {
-            writeln!(f, "\t{i}: {node}")?;
+        let mut written = Ok(());
+        let visited = self.visit(|branch| {
+            if written.is_ok() {
+                written = writeln!(
+                    f,
+                    "\t{}: Branch | split var: {}, split: {}, left: {}, right: {}",
+                    branch.index, branch.split_with, branch.split_at, branch.left, branch.right
+                );
+            }
+        });
+        written?;
+        if let Err(e) = visited {
+            writeln!(f, "\t(could not decode the branches: {e:?})")?;
         }
         writeln!(f, "------------")?;
         Ok(())
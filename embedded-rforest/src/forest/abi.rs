@@ -0,0 +1,348 @@
+//! Layout of the serialized format of [`FORMAT_VERSION`], for tools which
+//! read or write forests without this crate.
+//!
+//! Forests are read in place, so the size, alignment and field offsets of
+//! the header and branches *are* the format. Each type asserts at compile
+//! time that it is laid out as [`FORMAT_ABI`] describes, so a change to a
+//! type which would break deployed forests fails the build instead.
+//!
+//! All multi-byte fields are unaligned integers, little-endian unless the
+//! [`BIG_ENDIAN`](super::deserialize::BIG_ENDIAN) flag says otherwise for
+//! the nodes.
+
+use super::{NodeLayout, deserialize::FORMAT_VERSION};
+
+/// A field of a [`StructAbi`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct FieldAbi {
+    pub name: &'static str,
+    /// Offset from the start of the struct, in bytes
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Layout of a struct of the format, fields in order.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct StructAbi {
+    pub name: &'static str,
+    pub size: usize,
+    pub align: usize,
+    pub fields: &'static [FieldAbi],
+}
+
+impl StructAbi {
+    /// The field named `name`.
+    ///
+    /// # Panics
+    ///
+    /// If the struct has no such field, which fails the build in constants.
+    pub const fn field(&self, name: &str) -> &FieldAbi {
+        let mut i = 0;
+        while i < self.fields.len() {
+            if str_eq(self.fields[i].name, name) {
+                return &self.fields[i];
+            }
+            i += 1;
+        }
+        panic!("No such field")
+    }
+
+    /// Whether the fields follow each other without gaps or overlaps, and
+    /// fill the struct.
+    const fn is_packed(&self) -> bool {
+        let mut end = 0;
+        let mut i = 0;
+        while i < self.fields.len() {
+            if self.fields[i].offset != end {
+                return false;
+            }
+            end += self.fields[i].size;
+            i += 1;
+        }
+        end == self.size && self.size.is_multiple_of(self.align)
+    }
+}
+
+/// A bit of a flags byte.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct FlagAbi {
+    pub name: &'static str,
+    pub mask: u8,
+}
+
+/// Layout of the serialized format, see [`FORMAT_ABI`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct FormatAbi {
+    pub version: u8,
+    /// Alignment of the buffer, and of the node array within it
+    pub buffer_align: usize,
+    /// [`RawHeader`](super::deserialize::RawHeader)
+    pub header: StructAbi,
+    /// Bits of the `flags` of the header
+    pub header_flags: &'static [FlagAbi],
+    /// Bits of the `extra_flags` of the header
+    pub extra_flags: &'static [FlagAbi],
+    /// [`EncryptionHeader`](super::encryption::EncryptionHeader), following
+    /// the calibration of encrypted forests
+    pub encryption: StructAbi,
+    /// [`NodePointer`](crate::ptr::NodePointer), the 32-bit child pointer
+    pub node_pointer: StructAbi,
+    /// Bit of a 32-bit child pointer set for leaves
+    pub leaf_tag: u32,
+    /// Bit of a 16-bit child pointer set for leaves
+    pub narrow_leaf_tag: u16,
+    /// [`Branch`](super::Branch) of [`NodeLayout::Standard`]
+    pub standard_branch: StructAbi,
+    /// [`Branch`](super::Branch) of [`NodeLayout::Narrow`] and
+    /// [`NodeLayout::Relative`], with 16-bit pointers
+    pub narrow_branch: StructAbi,
+    /// [`CompactBranch`](super::CompactBranch)
+    pub compact_branch: StructAbi,
+    /// [`IntegerBranch`](super::IntegerBranch)
+    pub integer_branch: StructAbi,
+    /// Bits of the `flags` of compact and integer branches
+    pub branch_flags: &'static [FlagAbi],
+}
+
+impl FormatAbi {
+    /// Layout of the branches of `layout`
+    pub const fn branch(&self, layout: NodeLayout) -> &StructAbi {
+        match layout {
+            NodeLayout::Standard => &self.standard_branch,
+            NodeLayout::Narrow | NodeLayout::Relative => &self.narrow_branch,
+            NodeLayout::Compact => &self.compact_branch,
+            NodeLayout::Integer => &self.integer_branch,
+        }
+    }
+
+    /// Mask of the header flag named `name`.
+    ///
+    /// # Panics
+    ///
+    /// If there is no such flag, which fails the build in constants.
+    pub const fn header_flag(&self, name: &str) -> u8 {
+        flag(self.header_flags, name)
+    }
+
+    /// Mask of the extra header flag named `name`.
+    ///
+    /// # Panics
+    ///
+    /// If there is no such flag, which fails the build in constants.
+    pub const fn extra_flag(&self, name: &str) -> u8 {
+        flag(self.extra_flags, name)
+    }
+
+    /// Mask of the branch flag named `name`.
+    ///
+    /// # Panics
+    ///
+    /// If there is no such flag, which fails the build in constants.
+    pub const fn branch_flag(&self, name: &str) -> u8 {
+        flag(self.branch_flags, name)
+    }
+}
+
+const fn flag(flags: &[FlagAbi], name: &str) -> u8 {
+    let mut i = 0;
+    while i < flags.len() {
+        if str_eq(flags[i].name, name) {
+            return flags[i].mask;
+        }
+        i += 1;
+    }
+    panic!("No such flag")
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn field(name: &'static str, offset: usize, size: usize) -> FieldAbi {
+    FieldAbi { name, offset, size }
+}
+
+/// Layout of the serialized format of [`FORMAT_VERSION`].
+pub const FORMAT_ABI: FormatAbi = FormatAbi {
+    version: FORMAT_VERSION,
+    buffer_align: 8,
+    header: StructAbi {
+        name: "RawHeader",
+        size: 16,
+        align: 1,
+        fields: &[
+            field("num_trees", 0, 4),
+            field("num_features", 4, 1),
+            field("num_targets", 5, 1),
+            field("layout", 6, 1),
+            field("version", 7, 1),
+            field("header_len", 8, 2),
+            field("flags", 10, 1),
+            field("extra_flags", 11, 1),
+            field("num_leaves", 12, 4),
+        ],
+    },
+    header_flags: &[
+        FlagAbi {
+            name: "tree_table",
+            mask: 1,
+        },
+        FlagAbi {
+            name: "big_endian",
+            mask: 1 << 1,
+        },
+        FlagAbi {
+            name: "fingerprint",
+            mask: 1 << 2,
+        },
+        FlagAbi {
+            name: "sum",
+            mask: 1 << 3,
+        },
+        FlagAbi {
+            name: "transform",
+            mask: 1 << 4,
+        },
+        FlagAbi {
+            name: "calibration",
+            mask: 1 << 5,
+        },
+        FlagAbi {
+            name: "ordinal",
+            mask: 1 << 6,
+        },
+        FlagAbi {
+            name: "support",
+            mask: 1 << 7,
+        },
+    ],
+    extra_flags: &[
+        FlagAbi {
+            name: "less_than",
+            mask: 1,
+        },
+        FlagAbi {
+            name: "encrypted",
+            mask: 1 << 1,
+        },
+    ],
+    encryption: StructAbi {
+        name: "EncryptionHeader",
+        size: 24,
+        align: 1,
+        fields: &[
+            field("nonce", 0, 12),
+            field("key_check", 12, 8),
+            field("checksum", 20, 4),
+        ],
+    },
+    node_pointer: StructAbi {
+        name: "NodePointer",
+        size: 4,
+        align: 1,
+        fields: &[field("0", 0, 4)],
+    },
+    leaf_tag: 1 << 31,
+    narrow_leaf_tag: 1 << 15,
+    standard_branch: StructAbi {
+        name: "Branch",
+        size: 16,
+        align: 4,
+        fields: &[
+            field("left", 0, 4),
+            field("right", 4, 4),
+            field("split_at", 8, 4),
+            field("split_with", 12, 4),
+        ],
+    },
+    narrow_branch: StructAbi {
+        name: "Branch<U16>",
+        size: 12,
+        align: 4,
+        fields: &[
+            field("left", 0, 2),
+            field("right", 2, 2),
+            field("split_at", 4, 4),
+            field("split_with", 8, 4),
+        ],
+    },
+    compact_branch: StructAbi {
+        name: "CompactBranch",
+        size: 8,
+        align: 4,
+        fields: &[
+            field("left", 0, 2),
+            field("right", 2, 2),
+            field("split_at", 4, 2),
+            field("split_with", 6, 1),
+            field("flags", 7, 1),
+        ],
+    },
+    integer_branch: StructAbi {
+        name: "IntegerBranch",
+        size: 16,
+        align: 4,
+        fields: &[
+            field("left", 0, 4),
+            field("right", 4, 4),
+            field("split_at", 8, 4),
+            field("split_with", 12, 1),
+            field("flags", 13, 1),
+            field("_padding", 14, 2),
+        ],
+    },
+    branch_flags: &[
+        FlagAbi {
+            name: "left_is_prediction",
+            mask: 1,
+        },
+        FlagAbi {
+            name: "right_is_prediction",
+            mask: 1 << 1,
+        },
+    ],
+};
+
+const _: () = {
+    let abi = &FORMAT_ABI;
+    assert!(abi.header.is_packed() && abi.node_pointer.is_packed());
+    assert!(abi.standard_branch.is_packed() && abi.narrow_branch.is_packed());
+    assert!(abi.compact_branch.is_packed() && abi.integer_branch.is_packed());
+    assert!(abi.encryption.is_packed());
+};
+
+/// Assert at compile time that `$ty` has the size and alignment of the
+/// [`StructAbi`] `$abi`, and that each field is at its offset and of its
+/// size. Every field of `$abi` must be listed.
+macro_rules! assert_layout {
+    ($ty:ty, $abi:expr, [$($field:tt: $field_ty:ty),* $(,)?]) => {
+        const _: () = {
+            let abi: &$crate::forest::abi::StructAbi = &$abi;
+            assert!(size_of::<$ty>() == abi.size);
+            assert!(align_of::<$ty>() == abi.align);
+            assert!(abi.fields.len() == [$(stringify!($field)),*].len());
+            $(
+                let field = abi.field(stringify!($field));
+                assert!(core::mem::offset_of!($ty, $field) == field.offset);
+                assert!(size_of::<$field_ty>() == field.size);
+            )*
+        };
+    };
+}
+
+pub(crate) use assert_layout;
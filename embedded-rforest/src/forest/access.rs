@@ -0,0 +1,176 @@
+//! Access to the branches of a forest wherever they are stored.
+//!
+//! Predictions read branches through [`NodeAccess`], one at a time, so the
+//! node array does not have to be addressable memory. Three storages are
+//! supported:
+//!
+//! - a slice of branches, as [`OptimizedForest::deserialize`] borrows from
+//!   its buffer, in flash or RAM. This is the default, and compiles down to
+//!   indexing the slice,
+//! - [`CachedNodes`], reading branches from [`NodeStorage`] such as SPI
+//!   flash, and keeping the last ones read,
+//! - a copy of the node array in RAM, made by [`copy_to_ram`], which is a
+//!   slice again.
+//!
+//! [`OptimizedForest::deserialize`]: super::OptimizedForest::deserialize
+
+use core::{borrow::Borrow, cell::RefCell};
+
+use zerocopy::FromBytes;
+
+use super::BranchLayout;
+use crate::Error;
+
+/// Largest size of a branch of any [`NodeLayout`](super::NodeLayout), in
+/// bytes.
+pub const MAX_BRANCH_SIZE: usize = 16;
+
+/// Branches of a forest, read by index.
+pub trait NodeAccess {
+    type Branch: BranchLayout;
+
+    /// A branch read: a reference into the storage, or a copy of the branch
+    type Ref<'a>: Borrow<Self::Branch>
+    where
+        Self: 'a;
+
+    /// Number of branches
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read the branch at `index`. Fails with [`Error::Storage`] if the
+    /// storage could not be read.
+    fn branch(&self, index: usize) -> Result<Self::Ref<'_>, Error>;
+}
+
+impl<B: BranchLayout> NodeAccess for [B] {
+    type Branch = B;
+    type Ref<'a>
+        = &'a B
+    where
+        B: 'a;
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        <[B]>::len(self)
+    }
+
+    /// Never fails, so that predictions compile down to indexing the slice.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of the slice, which it never is for validated
+    /// forests.
+    #[inline(always)]
+    fn branch(&self, index: usize) -> Result<&B, Error> {
+        Ok(&self[index])
+    }
+}
+
+/// Storage holding the serialized node array of a forest, and nothing else:
+/// offset 0 is the first byte of the first branch.
+pub trait NodeStorage {
+    /// Fill `bytes` with the bytes stored from `offset`. Fails with
+    /// [`Error::Storage`] if the storage could not be read.
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error>;
+}
+
+impl NodeStorage for &[u8] {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        let start = offset as usize;
+        let stored = self.get(start..start + bytes.len()).ok_or(Error::Storage)?;
+        bytes.copy_from_slice(stored);
+        Ok(())
+    }
+}
+
+/// Branches read from [`NodeStorage`], such as SPI flash, one at a time.
+///
+/// The last branches read are kept in `LINES` lines, branch `i` in line
+/// `i % LINES`: the top of every tree, visited by every prediction, stays
+/// cached in as many lines as the forest has trees. Branches are returned
+/// as copies.
+pub struct CachedNodes<S: NodeStorage, B: BranchLayout + Clone, const LINES: usize> {
+    len: usize,
+    state: RefCell<CacheState<S, B, LINES>>,
+}
+
+struct CacheState<S, B, const LINES: usize> {
+    storage: S,
+    lines: [Option<(usize, B)>; LINES],
+}
+
+impl<S: NodeStorage, B: BranchLayout + Clone, const LINES: usize> CachedNodes<S, B, LINES> {
+    /// The `len` branches stored in `storage`.
+    pub fn new(storage: S, len: usize) -> Self {
+        const { assert!(LINES > 0) };
+        const { assert!(size_of::<B>() <= MAX_BRANCH_SIZE) };
+        Self {
+            len,
+            state: RefCell::new(CacheState {
+                storage,
+                lines: [const { None }; LINES],
+            }),
+        }
+    }
+
+    /// The storage, once the branches are no longer needed.
+    pub fn into_storage(self) -> S {
+        self.state.into_inner().storage
+    }
+}
+
+impl<S: NodeStorage, B: BranchLayout + Clone, const LINES: usize> NodeAccess
+    for CachedNodes<S, B, LINES>
+{
+    type Branch = B;
+    type Ref<'a>
+        = B
+    where
+        Self: 'a;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn branch(&self, index: usize) -> Result<B, Error> {
+        if index >= self.len {
+            return Err(Error::MalformedForest);
+        }
+
+        let mut state = self.state.borrow_mut();
+        let line = index % LINES;
+        if let Some((cached, branch)) = &state.lines[line]
+            && *cached == index
+        {
+            return Ok(branch.clone());
+        }
+
+        let mut bytes = [0; MAX_BRANCH_SIZE];
+        let bytes = &mut bytes[..size_of::<B>()];
+        let offset = u32::try_from(index * size_of::<B>()).map_err(|_| Error::Storage)?;
+        state.storage.read(offset, bytes)?;
+        let branch = B::read_from_bytes(bytes).map_err(|_| Error::MalformedForest)?;
+        state.lines[line] = Some((index, branch.clone()));
+
+        Ok(branch)
+    }
+}
+
+/// Copy the `len` branches stored in `storage` to `buffer`, and borrow them
+/// from there, e.g. to predict from RAM with a forest kept in external
+/// flash. `buffer` must be aligned for `B`, and hold at least `len`
+/// branches.
+pub fn copy_to_ram<'buf, S: NodeStorage, B: BranchLayout>(
+    storage: &mut S,
+    len: usize,
+    buffer: &'buf mut [u8],
+) -> Result<&'buf [B], Error> {
+    let size = len * size_of::<B>();
+    let bytes = buffer.get_mut(..size).ok_or(Error::MalformedForest)?;
+    storage.read(0, bytes)?;
+    <[B]>::ref_from_bytes(bytes).map_err(|_| Error::Misaligned)
+}
@@ -0,0 +1,135 @@
+use crate::{
+    Error,
+    ptr::{RelativeU16, U16, U32},
+};
+
+use super::{
+    Branch, Classification, CompactBranch, IntegerBranch, NodeLayout, OptimizedForest, Predict,
+    ProblemType, Regression, deserialize::ForestHeader,
+};
+
+/// An optimized forest whose branch layout is only known at runtime, from
+/// the header of the serialized forest.
+pub enum AnyOptimizedForest<'data, P: ProblemType> {
+    Standard(OptimizedForest<'data, P, Branch<U32>>),
+    Narrow(OptimizedForest<'data, P, Branch<U16>>),
+    Relative(OptimizedForest<'data, P, Branch<RelativeU16>>),
+    Compact(OptimizedForest<'data, P, CompactBranch>),
+    Integer(OptimizedForest<'data, P, IntegerBranch>),
+}
+
+/// Evaluate `$body` with `$forest` bound to the inner forest, whatever its
+/// layout.
+macro_rules! dispatch {
+    ($any:expr, $forest:ident => $body:expr) => {
+        match $any {
+            AnyOptimizedForest::Standard($forest) => $body,
+            AnyOptimizedForest::Narrow($forest) => $body,
+            AnyOptimizedForest::Relative($forest) => $body,
+            AnyOptimizedForest::Compact($forest) => $body,
+            AnyOptimizedForest::Integer($forest) => $body,
+        }
+    };
+}
+
+impl<'data, P: ProblemType> AnyOptimizedForest<'data, P> {
+    /// Deserialize a forest of any layout, as recorded in its header.
+    pub fn deserialize(buffer: &'data [u8]) -> Result<Self, Error> {
+        let header = ForestHeader::peek(buffer)?;
+
+        Ok(match header.layout {
+            NodeLayout::Standard => Self::Standard(OptimizedForest::deserialize(buffer)?),
+            NodeLayout::Narrow => Self::Narrow(OptimizedForest::deserialize(buffer)?),
+            NodeLayout::Relative => Self::Relative(OptimizedForest::deserialize(buffer)?),
+            NodeLayout::Compact => Self::Compact(OptimizedForest::deserialize(buffer)?),
+            NodeLayout::Integer => Self::Integer(OptimizedForest::deserialize(buffer)?),
+        })
+    }
+
+    pub fn layout(&self) -> NodeLayout {
+        dispatch!(self, forest => forest.layout())
+    }
+
+    pub fn num_features(&self) -> u8 {
+        dispatch!(self, forest => forest.num_features())
+    }
+
+    pub fn num_trees(&self) -> u32 {
+        dispatch!(self, forest => forest.num_trees())
+    }
+
+    /// Number of branches of the forest.
+    pub fn node_count(&self) -> usize {
+        dispatch!(self, forest => forest.node_count())
+    }
+
+    /// Size of this forest once serialized, in bytes.
+    pub fn serialized_len(&self) -> usize {
+        dispatch!(self, forest => forest.serialized_len())
+    }
+
+    /// See [`OptimizedForest::feature_fingerprint`].
+    pub fn feature_fingerprint(&self) -> u32 {
+        dispatch!(self, forest => forest.feature_fingerprint())
+    }
+
+    /// See [`OptimizedForest::check_fingerprint`].
+    pub fn check_fingerprint(&self, expected: u32) -> Result<(), Error> {
+        dispatch!(self, forest => forest.check_fingerprint(expected))
+    }
+
+    /// See [`OptimizedForest::validate`].
+    pub fn validate(&self) -> Result<(), Error> {
+        dispatch!(self, forest => forest.validate())
+    }
+
+    /// See [`OptimizedForest::serialize_into`].
+    pub fn serialize_into(&self, out: &mut [u8]) -> Result<usize, Error> {
+        dispatch!(self, forest => forest.serialize_into(out))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn to_bytes(&self) -> aligned_vec::AVec<u8> {
+        dispatch!(self, forest => forest.to_bytes())
+    }
+}
+
+impl Predict for AnyOptimizedForest<'_, Classification> {
+    type ProblemType = Classification;
+
+    fn predict(&self, features: &[f32]) -> u32 {
+        dispatch!(self, forest => forest.predict(features))
+    }
+
+    fn predict_counting(&self, features: &[f32]) -> (u32, u32) {
+        dispatch!(self, forest => forest.predict_counting(features))
+    }
+}
+
+impl Predict for AnyOptimizedForest<'_, Regression> {
+    type ProblemType = Regression;
+
+    fn predict(&self, features: &[f32]) -> f32 {
+        dispatch!(self, forest => forest.predict(features))
+    }
+
+    fn predict_counting(&self, features: &[f32]) -> (f32, u32) {
+        dispatch!(self, forest => forest.predict_counting(features))
+    }
+}
+
+impl AnyOptimizedForest<'_, Classification> {
+    /// See [`OptimizedForest::predict_with_support`].
+    #[must_use]
+    pub fn predict_with_support(&self, features: &[f32]) -> (u32, u32) {
+        dispatch!(self, forest => forest.predict_with_support(features))
+    }
+}
+
+impl AnyOptimizedForest<'_, Regression> {
+    /// See [`OptimizedForest::predict_with_support`].
+    #[must_use]
+    pub fn predict_with_support(&self, features: &[f32]) -> (f32, u32) {
+        dispatch!(self, forest => forest.predict_with_support(features))
+    }
+}
@@ -0,0 +1,258 @@
+//! Several serialized forests in one buffer, looked up by name.
+//!
+//! A bundle starts with a [`RawBundleHeader`], followed by a table of
+//! [`RawBundleEntry`], one per forest, and then the forests themselves,
+//! each at a multiple of [`BUFFER_ALIGN`] from the start of the bundle.
+
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, Ref,
+    byteorder::little_endian::{U16, U32},
+};
+
+use crate::Error;
+
+use super::{
+    AnyOptimizedForest, Branch, BranchLayout, OptimizedForest, ProblemKind, ProblemType,
+    deserialize::{BUFFER_ALIGN, ForestHeader},
+};
+
+/// First bytes of every bundle.
+pub const BUNDLE_MAGIC: [u8; 4] = *b"RFBU";
+
+/// Version of the bundle container written by this crate. The forests it
+/// holds have their own [`FORMAT_VERSION`](super::deserialize::FORMAT_VERSION).
+pub const BUNDLE_VERSION: u8 = 1;
+
+/// Longest name of a forest of a bundle, in bytes.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Header of a bundle, as laid out at the start of the buffer.
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(C)]
+pub struct RawBundleHeader {
+    /// [`BUNDLE_MAGIC`]
+    pub magic: [u8; 4],
+    /// [`BUNDLE_VERSION`]
+    pub version: u8,
+    _padding: u8,
+    /// Number of entries of the table following the header
+    pub num_entries: U16,
+    /// Size of the whole bundle, in bytes
+    pub len: U32,
+    _reserved: [u8; 4],
+}
+
+impl RawBundleHeader {
+    pub fn new(num_entries: u16, len: u32) -> Self {
+        Self {
+            magic: BUNDLE_MAGIC,
+            version: BUNDLE_VERSION,
+            _padding: 0,
+            num_entries: U16::new(num_entries),
+            len: U32::new(len),
+            _reserved: [0; 4],
+        }
+    }
+}
+
+/// A forest of a bundle, as laid out in the entry table.
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(C)]
+pub struct RawBundleEntry {
+    /// UTF-8 name of the forest, padded with zeros
+    pub name: [u8; MAX_NAME_LEN],
+    /// Offset of the forest from the start of the bundle, in bytes
+    pub offset: U32,
+    /// Size of the forest, in bytes
+    pub len: U32,
+    /// 0 for classification, 1 for regression
+    pub problem: u8,
+    _padding: [u8; 7],
+}
+
+impl RawBundleEntry {
+    /// An entry for the forest `name`, or `None` if the name is empty or
+    /// longer than [`MAX_NAME_LEN`] bytes.
+    pub fn new(name: &str, offset: u32, len: u32, problem: ProblemKind) -> Option<Self> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return None;
+        }
+        let mut padded = [0; MAX_NAME_LEN];
+        padded[..name.len()].copy_from_slice(name.as_bytes());
+
+        Some(Self {
+            name: padded,
+            offset: U32::new(offset),
+            len: U32::new(len),
+            problem: match problem {
+                ProblemKind::Classification => 0,
+                ProblemKind::Regression => 1,
+            },
+            _padding: [0; 7],
+        })
+    }
+
+    /// Name of the forest, or `None` if it is not valid UTF-8.
+    pub fn name(&self) -> Option<&str> {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(MAX_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).ok()
+    }
+
+    pub fn problem_kind(&self) -> Option<ProblemKind> {
+        match self.problem {
+            0 => Some(ProblemKind::Classification),
+            1 => Some(ProblemKind::Regression),
+            _ => None,
+        }
+    }
+}
+
+/// Size of the header of a bundle of `num_entries` forests, in bytes: the
+/// offset of its first forest.
+pub const fn bundle_header_len(num_entries: usize) -> usize {
+    (size_of::<RawBundleHeader>() + num_entries * size_of::<RawBundleEntry>())
+        .next_multiple_of(BUFFER_ALIGN)
+}
+
+/// Several serialized forests in one buffer, looked up by name without
+/// copying them.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct ForestBundle<'data> {
+    entries: &'data [RawBundleEntry],
+    buffer: &'data [u8],
+}
+
+impl<'data> ForestBundle<'data> {
+    /// Read the entry table of a bundle, and check that every entry has a
+    /// unique name and lies, aligned, inside the buffer. The forests
+    /// themselves are only checked when they are looked up.
+    ///
+    /// `buffer` must be aligned to [`BUFFER_ALIGN`], or this fails with
+    /// [`Error::Misaligned`].
+    pub fn deserialize(buffer: &'data [u8]) -> Result<Self, Error> {
+        if !(buffer.as_ptr() as usize).is_multiple_of(BUFFER_ALIGN) {
+            return Err(Error::Misaligned);
+        }
+
+        let Ok((header, rest)) = Ref::<_, RawBundleHeader>::from_prefix(buffer) else {
+            return Err(Error::MalformedForest);
+        };
+        if header.magic != BUNDLE_MAGIC {
+            return Err(Error::MalformedForest);
+        }
+        if header.version != BUNDLE_VERSION {
+            return Err(Error::UnsupportedVersion {
+                found: header.version,
+                max_supported: BUNDLE_VERSION,
+            });
+        }
+        if header.len.get() as usize != buffer.len() {
+            return Err(Error::MalformedForest);
+        }
+
+        let num_entries = usize::from(header.num_entries.get());
+        let (entries, _) = <[RawBundleEntry]>::ref_from_prefix_with_elems(rest, num_entries)
+            .map_err(|_| Error::MalformedForest)?;
+
+        let forests_start = bundle_header_len(num_entries);
+        for (i, entry) in entries.iter().enumerate() {
+            let name = entry.name().ok_or(Error::MalformedForest)?;
+            if name.is_empty() || entry.problem_kind().is_none() {
+                return Err(Error::MalformedForest);
+            }
+            if entries[..i].iter().any(|other| other.name() == Some(name)) {
+                return Err(Error::DuplicateForest);
+            }
+
+            let offset = entry.offset.get() as usize;
+            if !offset.is_multiple_of(BUFFER_ALIGN) {
+                return Err(Error::Misaligned);
+            }
+            let in_range = offset
+                .checked_add(entry.len.get() as usize)
+                .is_some_and(|end| offset >= forests_start && end <= buffer.len());
+            if !in_range {
+                return Err(Error::MalformedForest);
+            }
+        }
+
+        Ok(Self { entries, buffer })
+    }
+
+    /// Number of forests of the bundle.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &'data [RawBundleEntry] {
+        self.entries
+    }
+
+    /// Names of the forests of the bundle, in order.
+    pub fn names(&self) -> impl Iterator<Item = &'data str> + 'data {
+        // Names were checked by `deserialize`
+        self.entries.iter().filter_map(RawBundleEntry::name)
+    }
+
+    /// Bytes of the forest `name`, and the problem it solves.
+    pub fn bytes(&self, name: &str) -> Result<(&'data [u8], ProblemKind), Error> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.name() == Some(name))
+            .ok_or(Error::UnknownForest)?;
+        let offset = entry.offset.get() as usize;
+        let bytes = &self.buffer[offset..offset + entry.len.get() as usize];
+
+        // The table must agree with the header of the forest
+        let kind = entry.problem_kind().ok_or(Error::MalformedForest)?;
+        if ForestHeader::peek(bytes)?.problem_kind() != kind {
+            return Err(Error::MalformedForest);
+        }
+
+        Ok((bytes, kind))
+    }
+
+    /// The forest `name`, made of standard branches. Problem types are
+    /// checked like [`OptimizedForest::deserialize`] does.
+    pub fn get<P: ProblemType>(&self, name: &str) -> Result<OptimizedForest<'data, P>, Error> {
+        self.get_layout::<P, Branch>(name)
+    }
+
+    /// The forest `name`, made of branches of layout `B`.
+    pub fn get_layout<P: ProblemType, B: BranchLayout>(
+        &self,
+        name: &str,
+    ) -> Result<OptimizedForest<'data, P, B>, Error> {
+        let (bytes, kind) = self.bytes(name)?;
+        if (kind == ProblemKind::Classification) != P::HAS_TARGETS {
+            return Err(Error::WrongProblemType);
+        }
+
+        OptimizedForest::deserialize(bytes)
+    }
+
+    /// The forest `name`, whatever the layout of its branches.
+    pub fn get_any<P: ProblemType>(
+        &self,
+        name: &str,
+    ) -> Result<AnyOptimizedForest<'data, P>, Error> {
+        let (bytes, kind) = self.bytes(name)?;
+        if (kind == ProblemKind::Classification) != P::HAS_TARGETS {
+            return Err(Error::WrongProblemType);
+        }
+
+        AnyOptimizedForest::deserialize(bytes)
+    }
+}
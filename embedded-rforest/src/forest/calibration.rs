@@ -0,0 +1,76 @@
+//! Calibration of the class probabilities of a classification forest,
+//! recorded in its header with the
+//! [`CALIBRATION`](super::deserialize::CALIBRATION) flag, so that
+//! [`predict_proba`](super::OptimizedForest::predict_proba) returns
+//! calibrated probabilities rather than raw vote fractions.
+//!
+//! The header section holds the [`Platt`] parameters of every class, in
+//! class order, as pairs of little-endian `f32`s. Each vote fraction goes
+//! through the sigmoid of its class, and the results are normalized to sum
+//! to 1, as scikit-learn does with one-vs-rest calibrators. The sigmoid is
+//! [`transform::sigmoid`](super::transform::sigmoid), which targets without
+//! `std` can compute.
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, byteorder::little_endian::F32};
+
+use super::transform;
+
+/// Parameters of the Platt scaling of one class: the vote fraction `f`
+/// becomes `1 / (1 + exp(a * f + b))`, whose `a` is negative for classes
+/// voted for more often when they are right.
+#[derive(Clone, Copy, PartialEq, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(C)]
+pub struct Platt {
+    a: F32,
+    b: F32,
+}
+
+impl Platt {
+    pub const fn new(a: f32, b: f32) -> Self {
+        Self {
+            a: F32::new(a),
+            b: F32::new(b),
+        }
+    }
+
+    pub fn a(&self) -> f32 {
+        self.a.get()
+    }
+
+    pub fn b(&self) -> f32 {
+        self.b.get()
+    }
+
+    /// Calibrated probability of a vote fraction `f`.
+    #[inline(always)]
+    pub fn apply(&self, f: f32) -> f32 {
+        transform::sigmoid(-(self.a() * f + self.b()))
+    }
+
+    /// Whether both parameters are finite.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.a().is_finite() && self.b().is_finite()
+    }
+}
+
+/// Calibrate the vote fractions `probabilities` with one [`Platt`] scaling
+/// per class, then normalize them. Probabilities stay as they are without
+/// calibration. If every calibrated probability is 0, classes are equally
+/// likely.
+#[inline(always)]
+pub(crate) fn calibrate(calibration: &[Platt], probabilities: &mut [f32]) {
+    if calibration.is_empty() {
+        return;
+    }
+
+    let mut sum = 0.0;
+    for (p, platt) in probabilities.iter_mut().zip(calibration) {
+        *p = platt.apply(*p);
+        sum += *p;
+    }
+    let len = probabilities.len() as f32;
+    for p in probabilities {
+        *p = if sum > 0.0 { *p / sum } else { 1.0 / len };
+    }
+}
@@ -0,0 +1,172 @@
+#[cfg(feature = "fmt")]
+use core::fmt::{self, Debug};
+
+use half::f16;
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+use super::{
+    BranchLayout, NodeLayout,
+    abi::{self, FORMAT_ABI},
+};
+use crate::ptr::U16;
+
+const LEFT_IS_PREDICTION: u8 = 1;
+const RIGHT_IS_PREDICTION: u8 = 1 << 1;
+
+const _: () = assert!(LEFT_IS_PREDICTION == FORMAT_ABI.branch_flag("left_is_prediction"));
+const _: () = assert!(RIGHT_IS_PREDICTION == FORMAT_ABI.branch_flag("right_is_prediction"));
+
+/// An 8-byte branch, for forests of at most 65536 branches whose thresholds
+/// survive half precision.
+///
+/// Child pointers are 16 bits wide. A leaf pointer holds the class index for
+/// classification, or the bits of an `f16` prediction for regression.
+#[derive(Clone, IntoBytes, KnownLayout, Immutable, FromBytes)]
+#[repr(C, align(4))]
+pub struct CompactBranch {
+    left: U16,
+    right: U16,
+    /// Bits of an `f16`
+    split_at: U16,
+    split_with: u8,
+    flags: u8,
+}
+
+abi::assert_layout!(
+    CompactBranch,
+    FORMAT_ABI.compact_branch,
+    [left: U16, right: U16, split_at: U16, split_with: u8, flags: u8]
+);
+
+impl CompactBranch {
+    #[inline]
+    pub fn new(
+        split_with: u8,
+        split_at: f16,
+        left: u16,
+        right: u16,
+        left_leaf: bool,
+        right_leaf: bool,
+    ) -> Self {
+        let mut flags = 0;
+        if left_leaf {
+            flags |= LEFT_IS_PREDICTION;
+        }
+        if right_leaf {
+            flags |= RIGHT_IS_PREDICTION;
+        }
+
+        Self {
+            left: U16::new(left),
+            right: U16::new(right),
+            split_at: U16::new(split_at.to_bits()),
+            split_with,
+            flags,
+        }
+    }
+
+    #[inline]
+    pub fn split_with(&self) -> u32 {
+        self.split_with.into()
+    }
+
+    #[inline]
+    pub fn split_at(&self) -> f32 {
+        f16::from_bits(self.split_at.get()).to_f32()
+    }
+
+    /// Raw left pointer: a branch index, or a prediction.
+    #[inline]
+    pub fn left_ptr(&self) -> u16 {
+        self.left.get()
+    }
+
+    /// Raw right pointer: a branch index, or a prediction.
+    #[inline]
+    pub fn right_ptr(&self) -> u16 {
+        self.right.get()
+    }
+
+    /// Whether the left pointer holds a prediction rather than a branch index.
+    #[inline]
+    pub fn left_is_prediction(&self) -> bool {
+        self.flags & LEFT_IS_PREDICTION != 0
+    }
+
+    /// Whether the right pointer holds a prediction rather than a branch index.
+    #[inline]
+    pub fn right_is_prediction(&self) -> bool {
+        self.flags & RIGHT_IS_PREDICTION != 0
+    }
+}
+
+impl BranchLayout for CompactBranch {
+    const NODE_LAYOUT: NodeLayout = NodeLayout::Compact;
+
+    #[inline(always)]
+    fn split_with(&self) -> u32 {
+        self.split_with()
+    }
+
+    #[inline(always)]
+    fn split_at(&self) -> f32 {
+        self.split_at()
+    }
+
+    #[inline(always)]
+    fn left(&self) -> u32 {
+        self.left_ptr().into()
+    }
+
+    #[inline(always)]
+    fn right(&self) -> u32 {
+        self.right_ptr().into()
+    }
+
+    #[inline(always)]
+    fn left_is_prediction(&self) -> bool {
+        self.left_is_prediction()
+    }
+
+    #[inline(always)]
+    fn right_is_prediction(&self) -> bool {
+        self.right_is_prediction()
+    }
+
+    #[inline(always)]
+    fn inline_leaf(ptr: u32) -> Option<f32> {
+        Some(f16::from_bits(ptr as u16).to_f32())
+    }
+
+    fn write_bytes(&self, mut write: impl FnMut(&[u8])) {
+        write(self.as_bytes());
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Debug for CompactBranch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompactBranch")
+            .field("left", &self.left_ptr())
+            .field("right", &self.right_ptr())
+            .field("split_at", &self.split_at())
+            .field("split_with", &self.split_with)
+            .field("left_is_prediction", &self.left_is_prediction())
+            .field("right_is_prediction", &self.right_is_prediction())
+            .finish()
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for CompactBranch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CompactBranch | split var: {}, split: {}, left: {}, right: {}",
+            self.split_with,
+            self.split_at(),
+            self.left_ptr(),
+            self.right_ptr()
+        )
+    }
+}
@@ -1,14 +1,468 @@
-use core::{marker::PhantomData, num::NonZeroU8, ops::Deref};
+use core::{
+    marker::PhantomData,
+    mem::offset_of,
+    num::{NonZeroU8, NonZeroU32},
+    ops::{Deref, DerefMut},
+};
 
-use zerocopy::byteorder::little_endian::U32;
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, Ref,
+    byteorder::little_endian::{F32, U16, U32},
+};
 
-use crate::Error;
+use crate::{Error, ptr};
 
-use super::{Branch, OptimizedForest, ProblemType};
+use super::{
+    Aggregation, BranchLayout, NodeLayout, OptimizedForest, ProblemKind, ProblemType, SplitRule,
+    abi::{self, FORMAT_ABI},
+    access::NodeAccess,
+    calibration::Platt,
+    encryption::ENCRYPTION_LEN,
+    endian::{ByteOrder, NODE_BYTE_ORDER},
+    transform::Transform,
+};
 
+/// Version of the serialized format written by this crate, the only one it
+/// reads: nodes are read in place, and older versions encode them
+/// differently. Forests of other versions are rejected with
+/// [`Error::UnsupportedVersion`]; `forest-optimizer migrate` upgrades older
+/// ones.
+///
+/// Each version adds to the one before:
+///
+/// 0. The 8-byte header of the first releases: the tree, feature and target
+///    counts and the layout, then a padding byte, read as the version. The
+///    top two bits of the split variable flag leaf children, and regression
+///    leaves are stored in the pointers.
+/// 1. The 16-byte [`RawHeader`], with the version and the offset of the
+///    node array, aligned to [`BUFFER_ALIGN`].
+/// 2. The leaf table of regression forests, of `num_leaves` values.
+/// 3. Leaf pointers tagged with their top bit, the split variable taking
+///    the whole word, and the `flags` of optional sections, 0 in forests
+///    written before they existed.
+pub const FORMAT_VERSION: u8 = 3;
+
+/// Size of the header of [`FORMAT_VERSION`] 0, the shortest header with a
+/// version byte, in bytes.
+pub const LEGACY_HEADER_LEN: usize = 8;
+
+/// Alignment a buffer holding a serialized forest must have, on every target.
+/// The node array starts at this alignment too, as the header length is a
+/// multiple of it.
+pub const BUFFER_ALIGN: usize = 8;
+
+/// Header of a serialized forest, as laid out at the start of the buffer.
+///
+/// All fields are little-endian and unaligned, so the header can be read from
+/// any buffer, whatever the byte order of the nodes following it.
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(C)]
+pub struct RawHeader {
+    pub num_trees: U32,
+    pub num_features: u8,
+    /// 0 for regression
+    pub num_targets: u8,
+    /// [`NodeLayout`] of the nodes
+    pub layout: u8,
+    /// [`FORMAT_VERSION`]
+    pub version: u8,
+    /// Offset of the node array from the start of the buffer, in bytes
+    pub header_len: U16,
+    /// Optional sections following the header, such as [`TREE_TABLE`],
+    /// [`FINGERPRINT`], [`SUM`], [`TRANSFORM`] and [`CALIBRATION`], and
+    /// properties of the forest, [`BIG_ENDIAN`] and [`ORDINAL`]. 0 in forests
+    /// written before flags existed
+    pub flags: u8,
+    /// More flags, once every bit of `flags` was taken: [`LESS_THAN`] and
+    /// [`ENCRYPTED`]. 0 in
+    /// forests written before it existed, where the byte was padding
+    pub extra_flags: u8,
+    /// Number of `f32` values of the leaf table following the node array.
+    /// 0 if leaves are stored in the child pointers, always for
+    /// classification
+    pub num_leaves: U32,
+}
+
+/// Size of the header written by this crate, in bytes.
+pub const HEADER_LEN: usize = size_of::<RawHeader>();
+
+/// Flag of forests whose header is followed by the offset of every tree in
+/// the node array, as `u32`s. Trees are then stored one after the other,
+/// root first. Always set for the [`NodeLayout::Relative`] layout, which
+/// needs the offsets.
+pub const TREE_TABLE: u8 = 1;
+
+/// Flag of forests whose tree table, nodes, leaf table and support table
+/// are big-endian. The header itself is always little-endian.
+pub const BIG_ENDIAN: u8 = 1 << 1;
+
+/// Flag of forests whose header, after the tree table if any, holds the
+/// [`fingerprint`](super::fingerprint) of their feature and target names,
+/// as a little-endian `u32`, whatever the byte order of the nodes.
+pub const FINGERPRINT: u8 = 1 << 2;
+
+/// Flag of regression forests which sum the predictions of their trees,
+/// see [`Aggregation::Sum`], rather than average them. Their header, after
+/// the fingerprint if any, holds the base score added to the sum, as a
+/// little-endian `f32`. Forests without it keep the aggregation of their
+/// problem type, as forests written before the flag existed.
+pub const SUM: u8 = 1 << 3;
+
+/// Flag of regression forests which transform their output, see
+/// [`transform`](super::transform) for the section it adds to the header,
+/// after the base score if any. Forests without it predict the combined
+/// output of their trees.
+pub const TRANSFORM: u8 = 1 << 4;
+
+/// Size of the section of [`TRANSFORM`]: its kind, scale and offset
+const TRANSFORM_LEN: usize = 3 * size_of::<U32>();
+
+/// Flag of classification forests which calibrate their class
+/// probabilities, see [`calibration`](super::calibration) for the section
+/// it adds to the header, after the transform if any. Forests without it
+/// return raw vote fractions.
+pub const CALIBRATION: u8 = 1 << 5;
+
+/// Flag of classification forests whose classes have a natural order, in
+/// the order of their indices, and which take the median of the votes of
+/// their trees, see [`Aggregation::OrdinalMedian`], rather than the class
+/// with the most. It adds no section to the header.
+pub const ORDINAL: u8 = 1 << 6;
+
+/// Flag of forests whose leaf table is followed by the number of training
+/// samples of every leaf, see [`support`](super::support) for the table.
+/// Forests without it report no support.
+pub const SUPPORT: u8 = 1 << 7;
+
+/// Flag of `extra_flags` of forests whose branches go left when a feature
+/// is less than their threshold, see [`SplitRule::Less`]. Forests without
+/// it go left when it is less or equal, as forests written before the flag
+/// existed.
+pub const LESS_THAN: u8 = 1;
+
+/// Flag of `extra_flags` of forests whose nodes, leaf table and support
+/// table are encrypted, see [`encryption`](super::encryption) for the
+/// section it adds to the header, after the calibration if any. The header
+/// and its other sections stay readable.
+pub const ENCRYPTED: u8 = 1 << 1;
+
+/// Bits of `extra_flags` this crate reads. Forests with others are
+/// rejected, as they would predict otherwise than written.
+const KNOWN_EXTRA_FLAGS: u8 = LESS_THAN | ENCRYPTED;
+
+/// Size of the entries of the [`SUPPORT`] table of each branch, one per
+/// side, in bytes.
+pub const SUPPORT_PER_BRANCH: usize = 2 * size_of::<ptr::U16>();
+
+const _: () = assert!(HEADER_LEN.is_multiple_of(BUFFER_ALIGN));
+const _: () = assert!(BUFFER_ALIGN == FORMAT_ABI.buffer_align);
+
+abi::assert_layout!(
+    RawHeader,
+    FORMAT_ABI.header,
+    [
+        num_trees: U32,
+        num_features: u8,
+        num_targets: u8,
+        layout: u8,
+        version: u8,
+        header_len: U16,
+        flags: u8,
+        extra_flags: u8,
+        num_leaves: U32,
+    ]
+);
+
+const _: () = {
+    let abi = &FORMAT_ABI;
+    assert!(TREE_TABLE == abi.header_flag("tree_table"));
+    assert!(BIG_ENDIAN == abi.header_flag("big_endian"));
+    assert!(FINGERPRINT == abi.header_flag("fingerprint"));
+    assert!(SUM == abi.header_flag("sum"));
+    assert!(TRANSFORM == abi.header_flag("transform"));
+    assert!(CALIBRATION == abi.header_flag("calibration"));
+    assert!(ORDINAL == abi.header_flag("ordinal"));
+    assert!(SUPPORT == abi.header_flag("support"));
+    assert!(LESS_THAN == abi.extra_flag("less_than"));
+    assert!(ENCRYPTED == abi.extra_flag("encrypted"));
+};
+
+impl RawHeader {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        num_trees: u32,
+        num_features: u8,
+        num_targets: u8,
+        layout: NodeLayout,
+        num_leaves: u32,
+        tree_table: bool,
+        fingerprint: bool,
+        base_score: bool,
+        transform: bool,
+        calibration: bool,
+        ordinal: bool,
+        support: bool,
+        less_than: bool,
+    ) -> Self {
+        let num_offsets = if tree_table { num_trees as usize } else { 0 };
+        let calibrated = if calibration { num_targets as usize } else { 0 };
+        let flags = if tree_table { TREE_TABLE } else { 0 }
+            | if fingerprint { FINGERPRINT } else { 0 }
+            | if base_score { SUM } else { 0 }
+            | if transform { TRANSFORM } else { 0 }
+            | if calibration { CALIBRATION } else { 0 }
+            | if ordinal { ORDINAL } else { 0 }
+            | if support { SUPPORT } else { 0 }
+            | NODE_BYTE_ORDER.flag();
+        Self {
+            num_trees: U32::new(num_trees),
+            num_features,
+            num_targets,
+            layout: layout as u8,
+            version: FORMAT_VERSION,
+            header_len: U16::new(header_len_with(
+                num_offsets,
+                fingerprint,
+                base_score,
+                transform,
+                calibrated,
+            ) as u16),
+            flags,
+            extra_flags: if less_than { LESS_THAN } else { 0 },
+            num_leaves: U32::new(num_leaves),
+        }
+    }
+}
+
+/// Length of the header of a forest whose tree table holds `num_offsets`
+/// offsets (0 without a table), without a fingerprint, in bytes: the
+/// [`RawHeader`], followed by the table, padded to [`BUFFER_ALIGN`].
+pub const fn header_len(num_offsets: usize) -> usize {
+    header_len_with(num_offsets, false, false, false, 0)
+}
+
+/// [`header_len`] of a forest with or without a [`FINGERPRINT`], which
+/// follows the tree table, with or without the base score of a [`SUM`],
+/// which follows the fingerprint, with or without a [`TRANSFORM`], which
+/// follows the base score, and with the [`CALIBRATION`] of
+/// `calibrated_classes` classes (0 without one), which follows the
+/// transform.
+pub const fn header_len_with(
+    num_offsets: usize,
+    fingerprint: bool,
+    base_score: bool,
+    transform: bool,
+    calibrated_classes: usize,
+) -> usize {
+    encryption_offset(
+        num_offsets,
+        fingerprint,
+        base_score,
+        transform,
+        calibrated_classes,
+    )
+    .next_multiple_of(BUFFER_ALIGN)
+}
+
+/// Offset of the [`ENCRYPTED`] section in the header, which follows the
+/// [`CALIBRATION`] of `calibrated_classes` classes: the length of the
+/// sections before it, unpadded.
+const fn encryption_offset(
+    num_offsets: usize,
+    fingerprint: bool,
+    base_score: bool,
+    transform: bool,
+    calibrated_classes: usize,
+) -> usize {
+    calibration_offset(num_offsets, fingerprint, base_score, transform)
+        + calibrated_classes * size_of::<Platt>()
+}
+
+/// Offset of the [`CALIBRATION`] section in the header: the length of the
+/// sections before it, unpadded.
+const fn calibration_offset(
+    num_offsets: usize,
+    fingerprint: bool,
+    base_score: bool,
+    transform: bool,
+) -> usize {
+    let fingerprint = if fingerprint { size_of::<U32>() } else { 0 };
+    let base_score = if base_score { size_of::<f32>() } else { 0 };
+    let transform = if transform { TRANSFORM_LEN } else { 0 };
+    HEADER_LEN + num_offsets * size_of::<U32>() + fingerprint + base_score + transform
+}
+
+/// Size of a serialized forest of `node_count` standard nodes and
+/// `num_leaves` leaf values, without a tree table nor a fingerprint, in
+/// bytes.
+pub const fn serialized_len(node_count: usize, num_leaves: usize) -> usize {
+    serialized_len_with(
+        NodeLayout::Standard,
+        0,
+        false,
+        false,
+        false,
+        0,
+        node_count,
+        num_leaves,
+        false,
+    )
+}
+
+/// Size of a serialized forest whose tree table holds `num_offsets`
+/// offsets, with or without a [`FINGERPRINT`], the base score of a [`SUM`]
+/// and a [`TRANSFORM`], with the [`CALIBRATION`] of `calibrated_classes`
+/// classes, of `node_count` nodes of `layout` and `num_leaves` leaf values,
+/// with or without a [`SUPPORT`] table, in bytes.
+#[allow(clippy::too_many_arguments)]
+pub const fn serialized_len_with(
+    layout: NodeLayout,
+    num_offsets: usize,
+    fingerprint: bool,
+    base_score: bool,
+    transform: bool,
+    calibrated_classes: usize,
+    node_count: usize,
+    num_leaves: usize,
+    support: bool,
+) -> usize {
+    let support = if support { SUPPORT_PER_BRANCH } else { 0 };
+    header_len_with(
+        num_offsets,
+        fingerprint,
+        base_score,
+        transform,
+        calibrated_classes,
+    ) + node_count * (layout.branch_size() + support)
+        + num_leaves * size_of::<ptr::F32>()
+}
+
+/// Format version of the serialized forest in `buffer`, whatever the
+/// version, so that older forests can be told apart from malformed ones. 0
+/// for the forests of the first releases, whose version byte was padding.
+pub fn peek_version(buffer: &[u8]) -> Result<u8, Error> {
+    if buffer.len() < LEGACY_HEADER_LEN {
+        return Err(Error::MalformedForest);
+    }
+    Ok(buffer[offset_of!(RawHeader, version)])
+}
+
+/// Check, in const context, that `buffer` starts with the header of a
+/// forest of this [`FORMAT_VERSION`].
+const fn check_raw_header(buffer: &[u8]) {
+    if buffer.len() < HEADER_LEN {
+        panic!("Not a serialized forest: shorter than its header");
+    }
+    if buffer[offset_of!(RawHeader, version)] != FORMAT_VERSION {
+        panic!("Forest serialized in another format version: convert it again");
+    }
+}
+
+/// Number of trees of the serialized forest in `buffer`. Unlike
+/// [`ForestHeader::peek`], usable in const context, e.g. on the output of
+/// [`static_storage!`](crate::static_storage), see
+/// [`forest_consts!`](crate::forest_consts).
+///
+/// # Panics
+///
+/// If `buffer` does not start with the header of a forest of this
+/// [`FORMAT_VERSION`]; at compile time in const context.
+pub const fn peek_num_trees(buffer: &[u8]) -> u32 {
+    check_raw_header(buffer);
+    let at = offset_of!(RawHeader, num_trees);
+    u32::from_le_bytes([buffer[at], buffer[at + 1], buffer[at + 2], buffer[at + 3]])
+}
+
+/// Number of features of the serialized forest in `buffer`, see
+/// [`peek_num_trees`].
+pub const fn peek_num_features(buffer: &[u8]) -> u8 {
+    check_raw_header(buffer);
+    buffer[offset_of!(RawHeader, num_features)]
+}
+
+/// Number of targets of the serialized forest in `buffer`, 0 for
+/// regression, see [`peek_num_trees`].
+pub const fn peek_num_targets(buffer: &[u8]) -> u8 {
+    check_raw_header(buffer);
+    buffer[offset_of!(RawHeader, num_targets)]
+}
+
+/// [`fingerprint`](super::fingerprint) of the feature and target names of
+/// the serialized forest in `buffer`, 0 if it records none, see
+/// [`peek_num_trees`].
+pub const fn peek_fingerprint(buffer: &[u8]) -> u32 {
+    check_raw_header(buffer);
+    let flags = buffer[offset_of!(RawHeader, flags)];
+    if flags & FINGERPRINT == 0 {
+        return 0;
+    }
+
+    // The fingerprint follows the tree table, if any
+    let tree_table = flags & TREE_TABLE != 0
+        || buffer[offset_of!(RawHeader, layout)] == NodeLayout::Relative as u8;
+    let num_offsets = if tree_table {
+        peek_num_trees(buffer) as usize
+    } else {
+        0
+    };
+    let at = HEADER_LEN + num_offsets * size_of::<U32>();
+    if buffer.len() < at + size_of::<U32>() {
+        panic!("Not a serialized forest: shorter than its header");
+    }
+    u32::from_le_bytes([buffer[at], buffer[at + 1], buffer[at + 2], buffer[at + 3]])
+}
+
+/// Declare a module of constants describing the serialized forest `$buf`,
+/// which must be const-evaluable, such as a `const` holding the output of
+/// [`static_storage!`](crate::static_storage):
+///
+/// - `NUM_FEATURES`, to size the feature arrays of predictions,
+/// - `NUM_TARGETS`, 0 for regression,
+/// - `NUM_TREES`,
+/// - `FINGERPRINT`, 0 if the forest records none, e.g. to check at compile
+///   time the index constants `forest-optimizer` emits for its features.
+///
+/// ```ignore
+/// const MODEL: &[u8] = static_storage!("model.rforest");
+/// forest_consts!(pub mod model = MODEL);
+///
+/// let features = [0.0; model::NUM_FEATURES];
+/// ```
+///
+/// Buffers which do not hold a forest of this
+/// [`FORMAT_VERSION`](crate::forest::deserialize::FORMAT_VERSION) fail to
+/// compile.
+#[macro_export]
+macro_rules! forest_consts {
+    ($vis:vis mod $name:ident = $buf:expr) => {
+        $vis mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            const BYTES: &[u8] = $buf;
+
+            pub const NUM_FEATURES: usize =
+                $crate::forest::deserialize::peek_num_features(BYTES) as usize;
+            pub const NUM_TARGETS: usize =
+                $crate::forest::deserialize::peek_num_targets(BYTES) as usize;
+            pub const NUM_TREES: usize =
+                $crate::forest::deserialize::peek_num_trees(BYTES) as usize;
+            pub const FINGERPRINT: u32 =
+                $crate::forest::deserialize::peek_fingerprint(BYTES);
+        }
+    };
+}
+
+/// Embed the serialized forest at `$file` in a [`BackingStorage`], and
+/// borrow its bytes. `$file` is anything `include_bytes!` takes: a path
+/// relative to the calling file, or e.g.
+/// `concat!(env!("OUT_DIR"), "/forest.rforest")` for a forest written by a
+/// build script. An optional `unsafe(link_section = "...")` places it in
+/// that section.
 #[macro_export]
 macro_rules! static_storage {
-    ($file:literal $(, unsafe(link_section = $section:literal))?) => {{
+    ($file:expr $(, unsafe(link_section = $section:literal))? $(,)?) => {{
         const BYTES_LEN: usize = include_bytes!($file).len();
 
         $(#[unsafe(link_section = $section)])?
@@ -18,11 +472,9 @@ macro_rules! static_storage {
     }};
 }
 
-#[cfg_attr(
-    any(target_pointer_width = "32", target_pointer_width = "16"),
-    repr(align(4))
-)]
-#[cfg_attr(target_pointer_width = "64", repr(align(8)))]
+/// Bytes of a serialized forest, aligned to [`BUFFER_ALIGN`] whatever the
+/// target, so they can be passed to [`OptimizedForest::deserialize`].
+#[repr(align(8))]
 pub struct BackingStorage<const N: usize>([u8; N]);
 
 impl<const N: usize> BackingStorage<N> {
@@ -43,74 +495,451 @@ impl<const N: usize> Deref for BackingStorage<N> {
     }
 }
 
-impl<'a, P: ProblemType> OptimizedForest<'a, P> {
-    pub fn deserialize(buffer: &'a [u8]) -> Result<Self, Error> {
-        let base_ptr = buffer.as_ptr();
-
-        // Ensure alignment
-        assert_eq!(base_ptr as usize % align_of::<Self>(), 0);
-
-        // Ensure we have enough data for the fixed-size part of ConcreteType
-        let header_size = size_of::<u32>()  // num_trees
-            + size_of::<u8>()               // num_features
-            + size_of::<u8>()               // num_targets
-            + 2                             // padding
-            + size_of::<Branch>(); // At least 1 node
-
-        // Ensure we at least have enough data for all fields
-        assert!(buffer.len() >= header_size);
-
-        unsafe {
-            // Number of trees (4 bytes)
-            let a_ptr = base_ptr as *const u32;
-            let num_trees = U32::new(*a_ptr);
-
-            // Number of features (1 byte)
-            let b_ptr = a_ptr.add(1) as *const u8;
-            let num_features = *b_ptr;
-
-            // Number of targets (1 byte)
-            let c_ptr = b_ptr.add(1);
-            let num_targets = if *c_ptr == 0 {
-                None
-            } else {
-                Some(NonZeroU8::new_unchecked(*c_ptr))
-            };
+/// Writable, to hold the parts of a forest copied to RAM from other storage,
+/// such as program memory.
+impl<const N: usize> DerefMut for BackingStorage<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
 
-            // Check that the forest is of the correct problem type according to the P type parameter
-            if (num_targets.is_some() && !P::HAS_TARGETS)
-                || (num_targets.is_none() && P::HAS_TARGETS)
-            {
-                return Err(Error::WrongProblemType);
-            }
+/// The fixed-size header of a serialized forest.
+///
+/// Obtained with [`ForestHeader::peek`], which only looks at the header bytes
+/// and the buffer length: the node array is neither aligned-checked nor
+/// validated, so this works on buffers which [`OptimizedForest::deserialize`]
+/// would reject.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct ForestHeader {
+    pub num_trees: u32,
+    pub num_features: u8,
+    /// If num_targets is Some, we have a classification problem.
+    /// Otherwise, we have a regression problem.
+    pub num_targets: Option<NonZeroU8>,
+    /// NodeLayout of the nodes following the header.
+    pub layout: NodeLayout,
+    /// Offset of the node array, in bytes.
+    pub header_len: usize,
+    /// Whether the header is followed by the offset of every tree, see
+    /// [`TREE_TABLE`].
+    pub tree_table: bool,
+    /// Byte order of everything following the header, see [`BIG_ENDIAN`].
+    pub byte_order: ByteOrder,
+    /// Fingerprint of the feature and target names, if recorded, see
+    /// [`FINGERPRINT`].
+    pub fingerprint: Option<NonZeroU32>,
+    /// How the predictions of the trees combine, see [`SUM`] and
+    /// [`ORDINAL`].
+    pub aggregation: Aggregation,
+    /// Transform of the combined output, see [`TRANSFORM`].
+    pub transform: Transform,
+    /// Whether the class probabilities are calibrated, see [`CALIBRATION`].
+    pub calibrated: bool,
+    /// Whether the leaf table is followed by the number of training samples
+    /// of every leaf, see [`SUPPORT`].
+    pub support: bool,
+    /// How branches compare features to their thresholds, see
+    /// [`LESS_THAN`].
+    pub split_rule: SplitRule,
+    /// Whether everything following the header is encrypted, see
+    /// [`ENCRYPTED`].
+    pub encrypted: bool,
+    /// Number of whole nodes following the header.
+    pub node_count: usize,
+    /// Number of values of the leaf table following the nodes.
+    pub num_leaves: usize,
+}
+
+impl ForestHeader {
+    /// Parse the header of a serialized forest.
+    pub fn peek(buffer: &[u8]) -> Result<Self, Error> {
+        Self::peek_with_len(buffer, buffer.len())
+    }
+
+    /// Parse the header of a serialized forest of `serialized_len` bytes, of
+    /// which `buffer` only needs to hold the header, up to `header_len`.
+    pub fn peek_with_len(buffer: &[u8], serialized_len: usize) -> Result<Self, Error> {
+        let found = peek_version(buffer)?;
+        if found != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion {
+                found,
+                max_supported: FORMAT_VERSION,
+            });
+        }
+        let Ok((header, _)) = Ref::<_, RawHeader>::from_prefix(buffer) else {
+            return Err(Error::MalformedForest);
+        };
+
+        // Later versions may append fields, but never move the node array
+        // off its alignment
+        let header_len = usize::from(header.header_len.get());
+        if header_len < HEADER_LEN
+            || !header_len.is_multiple_of(BUFFER_ALIGN)
+            || header_len > buffer.len()
+        {
+            return Err(Error::MalformedForest);
+        }
+        let num_leaves = header.num_leaves.get() as usize;
+        if num_leaves != 0 && header.num_targets != 0 {
+            return Err(Error::MalformedForest);
+        }
+        // Checked, as `num_leaves` comes from the buffer: the table of a
+        // huge count would wrap around on 32-bit targets
+        let Some(nodes) = num_leaves
+            .checked_mul(size_of::<ptr::F32>())
+            .and_then(|leaves| leaves.checked_add(header_len))
+            .and_then(|tables| serialized_len.checked_sub(tables))
+        else {
+            return Err(Error::MalformedForest);
+        };
 
-            // Get start of node slice and skip padding (2 bytes)
-            let header_len = size_of::<u32>() + size_of::<u8>() * 2 + 2;
-            let slice_size = buffer.len() - header_len;
-            assert_eq!(slice_size % size_of::<Branch>(), 0);
-
-            let slice_len = slice_size / size_of::<Branch>();
-            let slice_ptr = (base_ptr.byte_add(header_len)) as *const Branch;
-            let branch_slice = core::slice::from_raw_parts(slice_ptr, slice_len);
-
-            for branch in branch_slice.iter() {
-                if !branch.flags.left_prediction() && (branch.left.as_ptr() as usize) >= slice_len {
-                    return Err(Error::MalformedForest);
-                }
-                if !branch.flags.right_prediction() && (branch.right.as_ptr() as usize) >= slice_len
-                {
-                    return Err(Error::MalformedForest);
-                };
+        let layout = NodeLayout::try_from(header.layout)?;
+        if header.extra_flags & !KNOWN_EXTRA_FLAGS != 0 {
+            return Err(Error::MalformedForest);
+        }
+        let split_rule = if header.extra_flags & LESS_THAN != 0 {
+            SplitRule::Less
+        } else {
+            SplitRule::LessOrEqual
+        };
+        // Every bit of the flags is taken, so none is unknown. The support
+        // table follows the leaves, with entries for every branch
+        let support = header.flags & SUPPORT != 0;
+        let branch_len = layout.branch_size() + if support { SUPPORT_PER_BRANCH } else { 0 };
+        let tree_table = header.flags & TREE_TABLE != 0 || layout == NodeLayout::Relative;
+        let num_offsets = if tree_table {
+            header.num_trees.get() as usize
+        } else {
+            0
+        };
+        // The tree table is inside the header, whose length is a u16. Larger
+        // counts from the buffer would overflow the offsets below on 32-bit
+        // targets
+        if num_offsets > usize::from(u16::MAX) / size_of::<U32>() {
+            return Err(Error::MalformedForest);
+        }
+        let has_fingerprint = header.flags & FINGERPRINT != 0;
+        let has_base_score = header.flags & SUM != 0;
+        let has_transform = header.flags & TRANSFORM != 0;
+        let calibrated = header.flags & CALIBRATION != 0;
+        let ordinal = header.flags & ORDINAL != 0;
+        let calibrated_classes = if calibrated {
+            usize::from(header.num_targets)
+        } else {
+            0
+        };
+        let encrypted = header.extra_flags & ENCRYPTED != 0;
+        let sections_len = encryption_offset(
+            num_offsets,
+            has_fingerprint,
+            has_base_score,
+            has_transform,
+            calibrated_classes,
+        ) + if encrypted { ENCRYPTION_LEN } else { 0 };
+        if nodes % branch_len != 0
+            || header_len < sections_len.next_multiple_of(BUFFER_ALIGN)
+            || ((has_base_score || has_transform) && header.num_targets != 0)
+            || ((calibrated || ordinal) && header.num_targets == 0)
+        {
+            return Err(Error::MalformedForest);
+        }
+
+        // Inside the header, which the buffer was checked to hold
+        let mut at = HEADER_LEN + num_offsets * size_of::<U32>();
+        let fingerprint = if has_fingerprint {
+            let Ok((fingerprint, _)) = U32::read_from_prefix(&buffer[at..]) else {
+                return Err(Error::MalformedForest);
+            };
+            let Some(fingerprint) = NonZeroU32::new(fingerprint.get()) else {
+                return Err(Error::MalformedForest);
+            };
+            at += size_of::<U32>();
+            Some(fingerprint)
+        } else {
+            None
+        };
+        let aggregation = if has_base_score {
+            let Ok((base, _)) = F32::read_from_prefix(&buffer[at..]) else {
+                return Err(Error::MalformedForest);
+            };
+            if !base.get().is_finite() {
+                return Err(Error::MalformedForest);
             }
+            at += size_of::<F32>();
+            Aggregation::Sum { base: base.get() }
+        } else if ordinal {
+            Aggregation::OrdinalMedian
+        } else if header.num_targets != 0 {
+            Aggregation::MajorityVote
+        } else {
+            Aggregation::MeanValue
+        };
+        let transform = if has_transform {
+            let Ok(([kind, scale, offset], _)) = <[U32; 3]>::read_from_prefix(&buffer[at..]) else {
+                return Err(Error::MalformedForest);
+            };
+            at += TRANSFORM_LEN;
+            Transform::from_parts(
+                kind.get(),
+                f32::from_bits(scale.get()),
+                f32::from_bits(offset.get()),
+            )?
+        } else {
+            Transform::Identity
+        };
+        if calibrated {
+            let Ok((calibration, _)) =
+                <[Platt]>::ref_from_prefix_with_elems(&buffer[at..], calibrated_classes)
+            else {
+                return Err(Error::MalformedForest);
+            };
+            if !calibration.iter().all(Platt::is_valid) {
+                return Err(Error::MalformedForest);
+            }
+        }
+
+        Ok(Self {
+            num_trees: header.num_trees.get(),
+            num_features: header.num_features,
+            num_targets: NonZeroU8::new(header.num_targets),
+            layout,
+            header_len,
+            tree_table,
+            byte_order: ByteOrder::from_flags(header.flags),
+            fingerprint,
+            aggregation,
+            transform,
+            calibrated,
+            support,
+            split_rule,
+            encrypted,
+            node_count: nodes / branch_len,
+            num_leaves,
+        })
+    }
+
+    pub fn problem_kind(&self) -> ProblemKind {
+        if self.num_targets.is_some() {
+            ProblemKind::Classification
+        } else {
+            ProblemKind::Regression
+        }
+    }
+
+    /// Size of the serialized forest described by this header, in bytes.
+    pub fn serialized_len(&self) -> usize {
+        self.header_len
+            + self.node_count * self.layout.branch_size()
+            + self.num_leaves * size_of::<ptr::F32>()
+            + self.support_len()
+    }
+
+    /// Offset of the section of an [`ENCRYPTED`] forest in its header.
+    /// `None` if the forest is not encrypted.
+    pub fn encryption_offset(&self) -> Option<usize> {
+        let num_offsets = if self.tree_table {
+            self.num_trees as usize
+        } else {
+            0
+        };
+        let calibrated_classes = match self.num_targets {
+            Some(targets) if self.calibrated => usize::from(targets.get()),
+            _ => 0,
+        };
+        self.encrypted.then(|| {
+            encryption_offset(
+                num_offsets,
+                self.fingerprint.is_some(),
+                matches!(self.aggregation, Aggregation::Sum { .. }),
+                self.transform != Transform::Identity,
+                calibrated_classes,
+            )
+        })
+    }
+
+    /// Size of the [`SUPPORT`] table, in bytes. 0 without one.
+    pub fn support_len(&self) -> usize {
+        if self.support {
+            self.node_count * SUPPORT_PER_BRANCH
+        } else {
+            0
+        }
+    }
+}
 
-            Ok(OptimizedForest {
-                num_trees,
-                num_features,
-                num_targets,
-                _padding: [0; 2],
-                nodes: branch_slice,
-                _problem: PhantomData,
-            })
+impl<'a, P: ProblemType, B: BranchLayout> OptimizedForest<'a, P, B> {
+    /// Deserialize a forest whose header records the layout of `B`.
+    ///
+    /// `buffer` must be aligned to [`BUFFER_ALIGN`], e.g. by storing it in a
+    /// [`BackingStorage`], or this fails with [`Error::Misaligned`]. Its
+    /// nodes must be in [`NODE_BYTE_ORDER`], or this fails with
+    /// [`Error::WrongByteOrder`]: convert them first with
+    /// [`swap_byte_order`](super::endian::swap_byte_order). Encrypted
+    /// forests fail with [`Error::Encrypted`]: decrypt them first, see
+    /// [`encryption`](super::encryption).
+    pub fn deserialize(buffer: &'a [u8]) -> Result<Self, Error> {
+        let forest = Self::parse(buffer)?;
+        forest.validate()?;
+
+        Ok(forest)
+    }
+
+    /// Deserialize a forest like [`OptimizedForest::deserialize`], checking
+    /// its header but not its nodes: the O(n) pass of
+    /// [`validate`](OptimizedForest::validate) is skipped, to save boot time
+    /// on large forests.
+    ///
+    /// # Safety
+    ///
+    /// Unless its header fails the checks, `buffer` must hold a forest which
+    /// passed [`OptimizedForest::deserialize`] with the same `P` and `B`, such
+    /// as the
+    /// bytes of a Rust module emitted by `forest-optimizer`, whose
+    /// `_VALIDATED` constant records it. Predictions of any other forest may
+    /// panic, loop forever or, with future optimizations relying on
+    /// validation, read out of bounds.
+    pub unsafe fn deserialize_unchecked(buffer: &'a [u8]) -> Result<Self, Error> {
+        Self::parse(buffer)
+    }
+
+    /// Check the header of a serialized forest, and borrow its parts from
+    /// `buffer`, leaving its nodes unchecked.
+    fn parse(buffer: &'a [u8]) -> Result<Self, Error> {
+        // Ensure alignment, on 32-bit targets as well
+        if !(buffer.as_ptr() as usize).is_multiple_of(BUFFER_ALIGN) {
+            return Err(Error::Misaligned);
         }
+
+        let header = ForestHeader::peek(buffer)?;
+        check_header::<P, B>(&header)?;
+
+        // The slices borrow `buffer`, so they cannot outlive it. `zerocopy`
+        // checks that each lies inside it and is aligned for its element
+        // type, and `peek` that they do not overlap: the nodes start at
+        // `header_len`, past the tree table, and the leaves at the end of the
+        // last whole node, followed by the support table
+        let (nodes, tables) =
+            <[B]>::ref_from_prefix_with_elems(&buffer[header.header_len..], header.node_count)
+                .map_err(|_| Error::MalformedForest)?;
+
+        Self::from_parts(buffer, &header, nodes, tables)
+    }
+}
+
+impl<'a, P: ProblemType, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    OptimizedForest<'a, P, B, A>
+{
+    /// Deserialize a forest whose branches are read through `nodes` rather
+    /// than borrowed from the buffer, e.g. from program memory, see
+    /// [`access`](super::access). `buffer` holds the rest of the serialized
+    /// forest: its header, up to `header_len`, directly followed by its leaf
+    /// table and support table. Checks the forest like [`OptimizedForest::deserialize`],
+    /// reading every branch once.
+    pub fn deserialize_with_nodes(buffer: &'a [u8], nodes: &'a A) -> Result<Self, Error> {
+        if !(buffer.as_ptr() as usize).is_multiple_of(BUFFER_ALIGN) {
+            return Err(Error::Misaligned);
+        }
+
+        let serialized_len = buffer.len() + nodes.len() * B::NODE_LAYOUT.branch_size();
+        // The support table of the branches is in `buffer`
+        let header = ForestHeader::peek_with_len(buffer, serialized_len)?;
+        check_header::<P, B>(&header)?;
+        if header.node_count != nodes.len() {
+            return Err(Error::MalformedForest);
+        }
+
+        let forest = Self::from_parts(buffer, &header, nodes, &buffer[header.header_len..])?;
+        forest.validate()?;
+
+        Ok(forest)
     }
+
+    /// A forest of `nodes`, whose tree table is read from `buffer`, and leaf
+    /// and support tables from `tables`, as described by `header`. Left to
+    /// validate.
+    fn from_parts(
+        buffer: &'a [u8],
+        header: &ForestHeader,
+        nodes: &'a A,
+        tables: &'a [u8],
+    ) -> Result<Self, Error> {
+        let num_offsets = if header.tree_table {
+            header.num_trees
+        } else {
+            0
+        };
+        let (tree_offsets, _) =
+            <[ptr::U32]>::ref_from_prefix_with_elems(&buffer[HEADER_LEN..], num_offsets as usize)
+                .map_err(|_| Error::MalformedForest)?;
+        let (leaves, support) = <[ptr::F32]>::ref_from_prefix_with_elems(tables, header.num_leaves)
+            .map_err(|_| Error::MalformedForest)?;
+        let support = <[ptr::U16]>::ref_from_bytes(support).map_err(|_| Error::MalformedForest)?;
+        if size_of_val(support) != header.support_len() {
+            return Err(Error::MalformedForest);
+        }
+        let calibrated_classes = match header.num_targets {
+            Some(targets) if header.calibrated => usize::from(targets.get()),
+            _ => 0,
+        };
+        let at = calibration_offset(
+            num_offsets as usize,
+            header.fingerprint.is_some(),
+            matches!(header.aggregation, Aggregation::Sum { .. }),
+            header.transform != Transform::Identity,
+        );
+        let (calibration, _) =
+            <[Platt]>::ref_from_prefix_with_elems(&buffer[at..], calibrated_classes)
+                .map_err(|_| Error::MalformedForest)?;
+
+        Ok(OptimizedForest {
+            num_trees: ptr::U32::new(header.num_trees),
+            num_features: header.num_features,
+            num_targets: header.num_targets,
+            sum: matches!(header.aggregation, Aggregation::Sum { .. }),
+            ordinal: header.aggregation == Aggregation::OrdinalMedian,
+            fingerprint: ptr::U32::new(header.fingerprint.map_or(0, NonZeroU32::get)),
+            base_score: ptr::F32::new(match header.aggregation {
+                Aggregation::Sum { base } => base,
+                _ => 0.0,
+            }),
+            transform: header.transform,
+            split_rule: header.split_rule,
+            nodes,
+            calibration,
+            leaves,
+            support,
+            tree_offsets,
+            _problem: PhantomData,
+        })
+    }
+}
+
+/// Check that a forest described by `header` can be read as an
+/// [`OptimizedForest<P, B>`].
+fn check_header<P: ProblemType, B: BranchLayout>(header: &ForestHeader) -> Result<(), Error> {
+    // Check that the forest is of the correct problem type according to the P type parameter
+    if header.num_targets.is_some() != P::HAS_TARGETS {
+        return Err(Error::WrongProblemType);
+    }
+
+    // Check that the nodes are of the layout of the B type parameter
+    if header.layout != B::NODE_LAYOUT {
+        return Err(Error::WrongLayout);
+    }
+
+    if header.byte_order != NODE_BYTE_ORDER {
+        return Err(Error::WrongByteOrder);
+    }
+
+    if header.encrypted {
+        return Err(Error::Encrypted);
+    }
+
+    // At least one node
+    if header.node_count == 0 {
+        return Err(Error::MalformedForest);
+    }
+
+    Ok(())
 }
@@ -0,0 +1,185 @@
+//! Encryption of the nodes of a forest, for forests stored where anyone can
+//! read them out, such as external flash, recorded in its header with the
+//! [`ENCRYPTED`](super::deserialize::ENCRYPTED) flag.
+//!
+//! Everything following the header of an encrypted forest, its nodes, leaf
+//! table and support table, is encrypted with ChaCha20 (RFC 8439) under a
+//! 256-bit key, from the second block of its keystream on. The header and
+//! its sections stay readable, so tools can still report the counts,
+//! fingerprint or transform of the forest. It holds an [`EncryptionHeader`]
+//! after the calibration: the nonce, the first 8 bytes of the first block
+//! of the keystream, to tell a wrong key, and the FNV-1a hash of the
+//! plaintext, see [`fingerprint`](super::fingerprint), to tell a corrupt
+//! forest.
+//!
+//! With the `encryption` feature,
+//! [`OptimizedForest::deserialize_encrypted`] decrypts a forest into RAM of
+//! the caller, and
+//! [`OptimizedForest::deserialize_encrypted_in_place`] where it is, before
+//! deserializing it. A wrong key fails with
+//! [`Error::WrongKey`](crate::Error::WrongKey), never with a forest of
+//! garbage nodes.
+//!
+//! This keeps the forest from being read out of a dump of its storage, not
+//! from being replaced: the checksum is not a MAC, and the key has to be in
+//! the firmware.
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, byteorder::little_endian::U32};
+
+#[cfg(feature = "encryption")]
+use chacha20::{
+    ChaCha20,
+    cipher::{KeyIvInit, StreamCipher, StreamCipherSeek},
+};
+
+#[cfg(feature = "encryption")]
+use crate::Error;
+
+use super::abi::{self, FORMAT_ABI};
+#[cfg(feature = "encryption")]
+use super::{
+    BranchLayout, OptimizedForest, ProblemType,
+    deserialize::{BUFFER_ALIGN, ENCRYPTED, ForestHeader, RawHeader},
+    fingerprint::{FNV_OFFSET_BASIS, hash_bytes},
+};
+
+/// Size of the key, in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// Size of the nonce, in bytes.
+pub const NONCE_LEN: usize = 12;
+
+/// Size of the section of
+/// [`ENCRYPTED`](super::deserialize::ENCRYPTED) forests, in bytes.
+pub const ENCRYPTION_LEN: usize = size_of::<EncryptionHeader>();
+
+/// Size of a block of the ChaCha20 keystream, in bytes. The key check is
+/// the start of the first one, the plaintext is encrypted from the second.
+#[cfg(feature = "encryption")]
+const BLOCK_LEN: u32 = 64;
+
+/// The section of the header of an
+/// [`ENCRYPTED`](super::deserialize::ENCRYPTED) forest.
+#[derive(Clone, Copy, PartialEq, Eq, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(C)]
+pub struct EncryptionHeader {
+    /// Nonce the forest was encrypted with, never to be used twice with the
+    /// same key
+    pub nonce: [u8; NONCE_LEN],
+    /// First bytes of the keystream, which only the right key gives
+    pub key_check: [u8; 8],
+    /// FNV-1a hash of everything following the header, decrypted
+    pub checksum: U32,
+}
+
+abi::assert_layout!(
+    EncryptionHeader,
+    FORMAT_ABI.encryption,
+    [nonce: [u8; 12], key_check: [u8; 8], checksum: U32]
+);
+
+#[cfg(feature = "encryption")]
+impl EncryptionHeader {
+    /// Encrypt `payload`, everything following the header of a forest, in
+    /// place with `key` and `nonce`, and return the section recording them.
+    pub fn encrypt(key: &[u8; KEY_LEN], nonce: [u8; NONCE_LEN], payload: &mut [u8]) -> Self {
+        let checksum = hash_bytes(FNV_OFFSET_BASIS, payload);
+        let mut cipher = ChaCha20::new(key.into(), (&nonce).into());
+        let mut key_check = [0; 8];
+        cipher.apply_keystream(&mut key_check);
+        cipher.seek(BLOCK_LEN);
+        cipher.apply_keystream(payload);
+
+        Self {
+            nonce,
+            key_check,
+            checksum: U32::new(checksum),
+        }
+    }
+
+    /// Decrypt `payload`, encrypted by [`EncryptionHeader::encrypt`], in
+    /// place with `key`.
+    ///
+    /// Fails with [`Error::WrongKey`] if `key` is not the one the payload
+    /// was encrypted with, leaving it untouched, and with
+    /// [`Error::MalformedForest`] if it does not decrypt to its checksum.
+    pub fn decrypt(&self, key: &[u8; KEY_LEN], payload: &mut [u8]) -> Result<(), Error> {
+        let mut cipher = ChaCha20::new(key.into(), (&self.nonce).into());
+        let mut key_check = [0; 8];
+        cipher.apply_keystream(&mut key_check);
+        if key_check != self.key_check {
+            return Err(Error::WrongKey);
+        }
+        cipher.seek(BLOCK_LEN);
+        cipher.apply_keystream(payload);
+
+        if hash_bytes(FNV_OFFSET_BASIS, payload) != self.checksum.get() {
+            return Err(Error::MalformedForest);
+        }
+        Ok(())
+    }
+}
+
+/// Decrypt the serialized forest in `buffer` in place with `key`, and clear
+/// its [`ENCRYPTED`] flag. Forests which are not encrypted are left as
+/// they are.
+///
+/// Only the header is checked, see [`EncryptionHeader::decrypt`] for the
+/// errors of decryption: the forest must still be deserialized to be
+/// validated.
+#[cfg(feature = "encryption")]
+pub fn decrypt_in_place(buffer: &mut [u8], key: &[u8; KEY_LEN]) -> Result<(), Error> {
+    let header = ForestHeader::peek(buffer)?;
+    let Some(offset) = header.encryption_offset() else {
+        return Ok(());
+    };
+    // Inside the header, which `peek` checked to be long enough
+    let Ok((section, _)) = EncryptionHeader::read_from_prefix(&buffer[offset..]) else {
+        return Err(Error::MalformedForest);
+    };
+    section.decrypt(key, &mut buffer[header.header_len..])?;
+
+    buffer[core::mem::offset_of!(RawHeader, extra_flags)] &= !ENCRYPTED;
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+impl<'a, P: ProblemType, B: BranchLayout> OptimizedForest<'a, P, B> {
+    /// Decrypt the forest in `buffer` with `key` into `scratch`, and
+    /// deserialize it from there like [`OptimizedForest::deserialize`], see
+    /// [`mod@self`]. `buffer` is left encrypted, and need not be aligned.
+    ///
+    /// `scratch` must be aligned to [`BUFFER_ALIGN`], e.g. a
+    /// [`BackingStorage`](super::deserialize::BackingStorage) in RAM, or
+    /// this fails with [`Error::Misaligned`], and hold at least as many
+    /// bytes as `buffer`, or this fails with [`Error::BufferTooSmall`].
+    pub fn deserialize_encrypted(
+        buffer: &[u8],
+        key: &[u8; KEY_LEN],
+        scratch: &'a mut [u8],
+    ) -> Result<Self, Error> {
+        let needed = buffer.len();
+        let Some(scratch) = scratch.get_mut(..needed) else {
+            return Err(Error::BufferTooSmall { needed });
+        };
+        scratch.copy_from_slice(buffer);
+
+        Self::deserialize_encrypted_in_place(scratch, key)
+    }
+
+    /// [`OptimizedForest::deserialize_encrypted`] without a copy: the forest
+    /// is decrypted in `buffer`, which must be aligned to [`BUFFER_ALIGN`].
+    pub fn deserialize_encrypted_in_place(
+        buffer: &'a mut [u8],
+        key: &[u8; KEY_LEN],
+    ) -> Result<Self, Error> {
+        // Before decrypting, which `deserialize` would only check after
+        if !(buffer.as_ptr() as usize).is_multiple_of(BUFFER_ALIGN) {
+            return Err(Error::Misaligned);
+        }
+        decrypt_in_place(buffer, key)?;
+
+        Self::deserialize(buffer)
+    }
+}
@@ -0,0 +1,116 @@
+//! Byte order of the nodes of a serialized forest.
+//!
+//! Forests are little-endian unless the [`BIG_ENDIAN`] flag of their header
+//! is set. By default, this crate reads little-endian nodes on every target,
+//! which costs a byte swap per field on big-endian targets. With the
+//! `native-endian` feature, nodes are read in the byte order of the target
+//! instead, without swapping: big-endian targets then need forests written
+//! big-endian, e.g. with `forest-optimizer convert --endianness big`, or
+//! converted once with [`swap_byte_order`].
+
+use zerocopy::FromBytes;
+
+use crate::Error;
+
+use super::{
+    NodeLayout,
+    deserialize::{BIG_ENDIAN, ForestHeader, HEADER_LEN, RawHeader},
+};
+
+/// Byte order of the tree table, nodes, leaf table and support table of a
+/// forest.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    /// Byte order recorded in the flags of a [`RawHeader`].
+    pub const fn from_flags(flags: u8) -> Self {
+        if flags & BIG_ENDIAN != 0 {
+            Self::Big
+        } else {
+            Self::Little
+        }
+    }
+
+    /// Flag recording this byte order in a [`RawHeader`].
+    pub const fn flag(self) -> u8 {
+        match self {
+            Self::Little => 0,
+            Self::Big => BIG_ENDIAN,
+        }
+    }
+}
+
+/// Byte order of the forests this build reads and writes: the order of the
+/// target with the `native-endian` feature, little-endian otherwise.
+pub const NODE_BYTE_ORDER: ByteOrder =
+    if cfg!(all(feature = "native-endian", target_endian = "big")) {
+        ByteOrder::Big
+    } else {
+        ByteOrder::Little
+    };
+
+/// Size of each field of a branch of `layout`, in bytes, in the order they
+/// are laid out.
+const fn branch_fields(layout: NodeLayout) -> &'static [usize] {
+    match layout {
+        // left, right, split_at, split_with
+        NodeLayout::Standard => &[4, 4, 4, 4],
+        NodeLayout::Narrow | NodeLayout::Relative => &[2, 2, 4, 4],
+        // left, right, split_at, split_with, flags
+        NodeLayout::Compact => &[2, 2, 2, 1, 1],
+        // left, right, split_at, split_with, flags, padding
+        NodeLayout::Integer => &[4, 4, 4, 1, 1, 2],
+    }
+}
+
+/// Convert a serialized forest between little- and big-endian, in place,
+/// and update the [`BIG_ENDIAN`] flag of its header.
+///
+/// Only the header is checked: the forest must still be deserialized to be
+/// validated. Encrypted forests fail with [`Error::Encrypted`], as their
+/// byte order is fixed once encrypted.
+pub fn swap_byte_order(buffer: &mut [u8]) -> Result<(), Error> {
+    let header = ForestHeader::peek(buffer)?;
+    if header.encrypted {
+        return Err(Error::Encrypted);
+    }
+    let num_offsets = if header.tree_table {
+        header.num_trees as usize
+    } else {
+        0
+    };
+
+    let swap_each = |bytes: &mut [u8], size: usize| {
+        bytes.chunks_exact_mut(size).for_each(<[u8]>::reverse);
+    };
+
+    swap_each(&mut buffer[HEADER_LEN..HEADER_LEN + num_offsets * 4], 4);
+
+    let nodes_end = header.header_len + header.node_count * header.layout.branch_size();
+    let fields = branch_fields(header.layout);
+    for branch in buffer[header.header_len..nodes_end].chunks_exact_mut(header.layout.branch_size())
+    {
+        let mut rest = branch;
+        for &size in fields {
+            let (field, tail) = rest.split_at_mut(size);
+            field.reverse();
+            rest = tail;
+        }
+    }
+
+    let leaves_end = nodes_end + header.num_leaves * 4;
+    swap_each(&mut buffer[nodes_end..leaves_end], 4);
+    swap_each(&mut buffer[leaves_end..header.serialized_len()], 2);
+
+    let Ok((raw, _)) = RawHeader::mut_from_prefix(buffer) else {
+        return Err(Error::MalformedForest);
+    };
+    raw.flags ^= BIG_ENDIAN;
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Fingerprint of the feature and target names of a forest, recorded in its
+//! header with the [`FINGERPRINT`](super::deserialize::FINGERPRINT) flag, so
+//! that firmware can check at startup that a forest expects its features in
+//! the order the firmware fills them in.
+//!
+//! The fingerprint is the 32-bit FNV-1a hash of:
+//!
+//! 1. every feature name, by feature index, each followed by a `0x00` byte,
+//! 2. a `0xFF` byte, which no UTF-8 name contains,
+//! 3. every class name, by class index, each followed by a `0x00` byte. None
+//!    for regression.
+//!
+//! with 1 standing for a hash of 0, which records the lack of a fingerprint.
+//! The same names in another order give another fingerprint.
+
+pub(crate) const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+pub(crate) const fn hash_bytes(mut hash: u32, bytes: &[u8]) -> u32 {
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u32).wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    hash
+}
+
+const fn hash_names(mut hash: u32, names: &[&str]) -> u32 {
+    let mut i = 0;
+    while i < names.len() {
+        hash = hash_bytes(hash, names[i].as_bytes());
+        hash = hash_bytes(hash, &[0x00]);
+        i += 1;
+    }
+    hash
+}
+
+/// Fingerprint of a forest of the given feature names and, for
+/// classification, class names, both positioned by index. Never 0.
+///
+/// Being `const`, firmware can compute the fingerprint it expects from its
+/// own list of features, and pass it to
+/// [`OptimizedForest::check_fingerprint`](super::OptimizedForest::check_fingerprint).
+pub const fn fingerprint(features: &[&str], targets: &[&str]) -> u32 {
+    let hash = hash_names(FNV_OFFSET_BASIS, features);
+    let hash = hash_bytes(hash, &[0xFF]);
+    match hash_names(hash, targets) {
+        0 => 1,
+        hash => hash,
+    }
+}
@@ -0,0 +1,302 @@
+//! Forests predicting with integer arithmetic only, for targets without a
+//! floating-point unit.
+//!
+//! Every feature `j` has a fixed-point scale `2^shift[j]`, chosen by the
+//! optimizer and recorded in the metadata of the forest: thresholds are
+//! stored as `floor(threshold * 2^shift[j])`, and
+//! [`OptimizedForest::predict_i32`] expects features converted the same way,
+//! see [`to_fixed`]. For integer sensor readings, that is `reading << shift`,
+//! or `reading >> -shift` for negative shifts. Regression leaves are stored
+//! in a scale of their own, `2^leaf_shift`, which predictions are made in.
+
+use core::borrow::Borrow;
+#[cfg(feature = "fmt")]
+use core::fmt::{self, Debug};
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+use super::{
+    BranchLayout, Classification, MAX_TARGETS, NodeLayout, OptimizedForest, ProblemType,
+    Regression, Votes,
+    abi::{self, FORMAT_ABI},
+    access::NodeAccess,
+    read_or_panic,
+};
+use crate::{
+    Error,
+    ptr::{I32, U16, U32},
+};
+
+const LEFT_IS_PREDICTION: u8 = 1;
+const RIGHT_IS_PREDICTION: u8 = 1 << 1;
+
+const _: () = assert!(LEFT_IS_PREDICTION == FORMAT_ABI.branch_flag("left_is_prediction"));
+const _: () = assert!(RIGHT_IS_PREDICTION == FORMAT_ABI.branch_flag("right_is_prediction"));
+
+/// Smallest and largest shifts [`to_fixed`] converts with
+pub const SHIFT_RANGE: core::ops::RangeInclusive<i8> = -126..=126;
+
+/// A 16-byte branch with an integer threshold, in the fixed-point scale of
+/// its feature.
+///
+/// Child pointers are 32 bits wide. A leaf pointer holds the class index for
+/// classification, or the bits of an `i32` prediction, in the scale of the
+/// leaves, for regression.
+#[derive(Clone, IntoBytes, KnownLayout, Immutable, FromBytes)]
+#[repr(C, align(4))]
+pub struct IntegerBranch {
+    left: U32,
+    right: U32,
+    split_at: I32,
+    split_with: u8,
+    flags: u8,
+    _padding: U16,
+}
+
+abi::assert_layout!(
+    IntegerBranch,
+    FORMAT_ABI.integer_branch,
+    [left: U32, right: U32, split_at: I32, split_with: u8, flags: u8, _padding: U16]
+);
+
+impl IntegerBranch {
+    #[inline]
+    pub fn new(
+        split_with: u8,
+        split_at: i32,
+        left: u32,
+        right: u32,
+        left_leaf: bool,
+        right_leaf: bool,
+    ) -> Self {
+        let mut flags = 0;
+        if left_leaf {
+            flags |= LEFT_IS_PREDICTION;
+        }
+        if right_leaf {
+            flags |= RIGHT_IS_PREDICTION;
+        }
+
+        Self {
+            left: U32::new(left),
+            right: U32::new(right),
+            split_at: I32::new(split_at),
+            split_with,
+            flags,
+            _padding: U16::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn split_with(&self) -> u32 {
+        self.split_with.into()
+    }
+
+    /// Threshold, in the fixed-point scale of the feature
+    #[inline]
+    pub fn split_at(&self) -> i32 {
+        self.split_at.get()
+    }
+
+    /// Raw left pointer: a branch index, or a prediction.
+    #[inline]
+    pub fn left_ptr(&self) -> u32 {
+        self.left.get()
+    }
+
+    /// Raw right pointer: a branch index, or a prediction.
+    #[inline]
+    pub fn right_ptr(&self) -> u32 {
+        self.right.get()
+    }
+
+    /// Whether the left pointer holds a prediction rather than a branch index.
+    #[inline]
+    pub fn left_is_prediction(&self) -> bool {
+        self.flags & LEFT_IS_PREDICTION != 0
+    }
+
+    /// Whether the right pointer holds a prediction rather than a branch index.
+    #[inline]
+    pub fn right_is_prediction(&self) -> bool {
+        self.flags & RIGHT_IS_PREDICTION != 0
+    }
+}
+
+/// Thresholds and leaves are read as the floats of their integer values, so
+/// [`Predict::predict`](super::Predict::predict) takes features already
+/// scaled, and predicts in the scale of the leaves.
+impl BranchLayout for IntegerBranch {
+    const NODE_LAYOUT: NodeLayout = NodeLayout::Integer;
+
+    #[inline(always)]
+    fn split_with(&self) -> u32 {
+        self.split_with()
+    }
+
+    #[inline(always)]
+    fn split_at(&self) -> f32 {
+        self.split_at() as f32
+    }
+
+    #[inline(always)]
+    fn left(&self) -> u32 {
+        self.left_ptr()
+    }
+
+    #[inline(always)]
+    fn right(&self) -> u32 {
+        self.right_ptr()
+    }
+
+    #[inline(always)]
+    fn left_is_prediction(&self) -> bool {
+        self.left_is_prediction()
+    }
+
+    #[inline(always)]
+    fn right_is_prediction(&self) -> bool {
+        self.right_is_prediction()
+    }
+
+    #[inline(always)]
+    fn inline_leaf(ptr: u32) -> Option<f32> {
+        Some(ptr as i32 as f32)
+    }
+
+    fn write_bytes(&self, mut write: impl FnMut(&[u8])) {
+        write(self.as_bytes());
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Debug for IntegerBranch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntegerBranch")
+            .field("left", &self.left_ptr())
+            .field("right", &self.right_ptr())
+            .field("split_at", &self.split_at())
+            .field("split_with", &self.split_with)
+            .field("left_is_prediction", &self.left_is_prediction())
+            .field("right_is_prediction", &self.right_is_prediction())
+            .finish()
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for IntegerBranch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "IntegerBranch | split var: {}, split: {}, left: {}, right: {}",
+            self.split_with,
+            self.split_at(),
+            self.left_ptr(),
+            self.right_ptr()
+        )
+    }
+}
+
+/// `value` in the fixed-point scale `2^shift`: `floor(value * 2^shift)`,
+/// saturated to the range of an `i32`, and 0 for NaN. This is how the
+/// optimizer converts thresholds, so features converted with it take the
+/// same branches as their floats, up to the resolution of the scale.
+///
+/// # Panics
+///
+/// If `shift` is outside of [`SHIFT_RANGE`].
+pub fn to_fixed(value: f32, shift: i8) -> i32 {
+    assert!(SHIFT_RANGE.contains(&shift));
+    // 2^shift, exactly
+    let scale = f32::from_bits(((127 + i32::from(shift)) as u32) << 23);
+    let scaled = value * scale;
+    let truncated = scaled as i32;
+    if (truncated as f32) > scaled {
+        truncated.saturating_sub(1)
+    } else {
+        truncated
+    }
+}
+
+impl<P: ProblemType, A: NodeAccess<Branch = IntegerBranch> + ?Sized>
+    OptimizedForest<'_, P, IntegerBranch, A>
+{
+    /// Walk a tree from its root down to a prediction, comparing integers
+    /// only. Returns the raw leaf pointer.
+    #[inline(always)]
+    fn walk_fixed(&self, tree_id: u32, features: &[i32]) -> Result<u32, Error> {
+        let base = self.tree_base(tree_id);
+        let mut node = self.nodes.branch(self.tree_root(tree_id))?;
+
+        loop {
+            let branch = node.borrow();
+            let test = self
+                .split_rule
+                .goes_left(features[branch.split_with() as usize], branch.split_at());
+
+            let next = if test {
+                if branch.left_is_prediction() {
+                    break Ok(branch.left_ptr());
+                }
+                branch.left_ptr()
+            } else {
+                if branch.right_is_prediction() {
+                    break Ok(branch.right_ptr());
+                }
+                branch.right_ptr()
+            };
+            node = self.nodes.branch(base + next as usize)?;
+        }
+    }
+}
+
+impl<A: NodeAccess<Branch = IntegerBranch> + ?Sized>
+    OptimizedForest<'_, Classification, IntegerBranch, A>
+{
+    /// Make a prediction from features in the fixed-point scales of the
+    /// forest, with integer arithmetic only.
+    ///
+    /// # Panics
+    ///
+    /// If a branch cannot be read.
+    #[inline(never)]
+    #[must_use]
+    pub fn predict_i32(&self, features: &[i32]) -> u32 {
+        let mut votes = Votes::<MAX_TARGETS>::new();
+        for tree_id in 0..self.num_trees.get() {
+            votes.add(read_or_panic(self.walk_fixed(tree_id, features)));
+        }
+        self.decide(&votes)
+    }
+}
+
+impl<A: NodeAccess<Branch = IntegerBranch> + ?Sized>
+    OptimizedForest<'_, Regression, IntegerBranch, A>
+{
+    /// Make a prediction from features in the fixed-point scales of the
+    /// forest, with integer arithmetic only. The prediction is in the scale
+    /// of the leaves: the mean of the leaves reached, rounded to the nearest
+    /// integer, halves away from zero.
+    ///
+    /// # Panics
+    ///
+    /// If a branch cannot be read.
+    #[inline(never)]
+    #[must_use]
+    pub fn predict_i32(&self, features: &[i32]) -> i32 {
+        let mut sum = 0i64;
+        for tree_id in 0..self.num_trees.get() {
+            let leaf = read_or_panic(self.walk_fixed(tree_id, features));
+            sum += i64::from(leaf as i32);
+        }
+
+        let num_trees = i64::from(self.num_trees.get());
+        let half = if sum < 0 {
+            -num_trees / 2
+        } else {
+            num_trees / 2
+        };
+        // The mean of `i32`s is an `i32`
+        ((sum + half) / num_trees) as i32
+    }
+}
@@ -0,0 +1,250 @@
+//! Forests kept in the program memory of Harvard-architecture targets such
+//! as AVR, behind the `avr-progmem` feature.
+//!
+//! Program memory is an address space of its own there: a `&[u8]` or
+//! `&[Branch]` pointing into it reads data memory at the same addresses.
+//! [`Progmem`] therefore never hands out references, and reads its bytes
+//! with a [`ProgmemRead`], the `lpm` instruction on AVR ([`Lpm`]):
+//!
+//! - the header, tree table, leaf table and support table are copied to
+//!   RAM by [`Progmem::deserialize`],
+//! - branches are read one at a time by [`ProgmemNodes`], which returns
+//!   copies, so predictions only access the fields of branches in RAM.
+//!
+//! ```ignore
+//! let forest = static_progmem!("forest.rforest");
+//! let nodes = forest.nodes::<Branch>()?;
+//! let mut ram = BackingStorage::new([0; 256]);
+//! let forest = forest.deserialize::<Classification, _>(&nodes, &mut ram[..])?;
+//! ```
+
+use core::marker::PhantomData;
+
+use zerocopy::FromBytes;
+
+use super::{
+    BranchLayout, NodeLayout, OptimizedForest, ProblemType,
+    access::{MAX_BRANCH_SIZE, NodeAccess, NodeStorage},
+    deserialize::{HEADER_LEN, RawHeader, SUPPORT, SUPPORT_PER_BRANCH},
+};
+use crate::{Error, ptr};
+
+/// Reads bytes of program memory.
+pub trait ProgmemRead {
+    /// The byte at `addr` in program memory
+    fn read_byte(&self, addr: usize) -> u8;
+}
+
+/// Reads program memory with the `lpm` instruction.
+#[cfg(target_arch = "avr")]
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct Lpm;
+
+#[cfg(target_arch = "avr")]
+impl ProgmemRead for Lpm {
+    #[inline(always)]
+    fn read_byte(&self, addr: usize) -> u8 {
+        let byte: u8;
+        // SAFETY: `lpm` only loads a byte of program memory into a register,
+        // whatever the address
+        unsafe {
+            core::arch::asm!(
+                "lpm {byte}, Z",
+                byte = out(reg) byte,
+                in("Z") addr as u16,
+                options(pure, readonly, nostack, preserves_flags),
+            );
+        }
+        byte
+    }
+}
+
+/// The bytes of a serialized forest in program memory, placed there by
+/// `static_progmem!` on AVR.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct Progmem<R: ProgmemRead> {
+    reader: R,
+    addr: usize,
+    len: usize,
+}
+
+impl<R: ProgmemRead> Progmem<R> {
+    /// The `len` bytes at `addr` in program memory, read with `reader`.
+    pub const fn new(reader: R, addr: usize, len: usize) -> Self {
+        Self { reader, addr, len }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Fill `bytes` with the bytes from `offset`. Fails with
+    /// [`Error::MalformedForest`] past the end.
+    pub fn copy(&self, offset: usize, bytes: &mut [u8]) -> Result<(), Error> {
+        if offset
+            .checked_add(bytes.len())
+            .is_none_or(|end| end > self.len)
+        {
+            return Err(Error::MalformedForest);
+        }
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.reader.read_byte(self.addr + offset + i);
+        }
+        Ok(())
+    }
+
+    /// The header of the forest, at the start of its bytes.
+    fn raw_header(&self) -> Result<RawHeader, Error> {
+        let mut bytes = [0; HEADER_LEN];
+        self.copy(0, &mut bytes)?;
+        RawHeader::read_from_bytes(&bytes).map_err(|_| Error::MalformedForest)
+    }
+
+    /// Number of bytes [`Progmem::deserialize`] copies to RAM: the header,
+    /// tree table included, the leaf table and the support table.
+    pub fn ram_len(&self) -> Result<usize, Error> {
+        let header = self.raw_header()?;
+        let tables = usize::from(header.header_len.get())
+            + header.num_leaves.get() as usize * size_of::<ptr::F32>();
+        if header.flags & SUPPORT == 0 {
+            return Ok(tables);
+        }
+
+        // The support table has entries for every branch
+        let branch_size = NodeLayout::try_from(header.layout)?.branch_size();
+        let branches = self.len.checked_sub(tables).ok_or(Error::MalformedForest)?
+            / (branch_size + SUPPORT_PER_BRANCH);
+        Ok(tables + branches * SUPPORT_PER_BRANCH)
+    }
+}
+
+impl<R: ProgmemRead + Clone> Progmem<R> {
+    /// The branches of the forest, read as `B`. Fails with
+    /// [`Error::MalformedForest`] if its node array is not made of whole
+    /// `B`s; [`Progmem::deserialize`] checks the rest.
+    pub fn nodes<B: BranchLayout>(&self) -> Result<ProgmemNodes<R, B>, Error> {
+        const { assert!(size_of::<B>() <= MAX_BRANCH_SIZE) };
+        let offset = usize::from(self.raw_header()?.header_len.get());
+        let node_bytes = self
+            .len
+            .checked_sub(self.ram_len()?)
+            .ok_or(Error::MalformedForest)?;
+        if !node_bytes.is_multiple_of(size_of::<B>()) {
+            return Err(Error::MalformedForest);
+        }
+
+        Ok(ProgmemNodes {
+            progmem: self.clone(),
+            offset,
+            len: node_bytes / size_of::<B>(),
+            _branch: PhantomData,
+        })
+    }
+
+    /// Copy the header, leaf and support tables of the forest to `ram`, and
+    /// deserialize it with its branches read through `nodes`, see
+    /// [`OptimizedForest::deserialize_with_nodes`]. `ram` must be aligned to
+    /// [`BUFFER_ALIGN`](super::deserialize::BUFFER_ALIGN), e.g. a
+    /// [`BackingStorage`](super::deserialize::BackingStorage), and hold at
+    /// least [`Progmem::ram_len`] bytes.
+    pub fn deserialize<'a, P: ProblemType, B: BranchLayout>(
+        &self,
+        nodes: &'a ProgmemNodes<R, B>,
+        ram: &'a mut [u8],
+    ) -> Result<OptimizedForest<'a, P, B, ProgmemNodes<R, B>>, Error> {
+        let header_len = nodes.offset;
+        let ram = ram
+            .get_mut(..self.ram_len()?)
+            .ok_or(Error::MalformedForest)?;
+        let leaves_len = ram.len() - header_len;
+        let (header, leaves) = ram.split_at_mut(header_len);
+        self.copy(0, header)?;
+        self.copy(self.len - leaves_len, leaves)?;
+
+        OptimizedForest::deserialize_with_nodes(ram, nodes)
+    }
+}
+
+impl<R: ProgmemRead> NodeStorage for Progmem<R> {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        self.copy(offset as usize, bytes)
+            .map_err(|_| Error::Storage)
+    }
+}
+
+/// Branches of a forest in program memory, from [`Progmem::nodes`], read
+/// one at a time and returned as copies.
+#[derive(Clone)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct ProgmemNodes<R: ProgmemRead, B> {
+    progmem: Progmem<R>,
+    /// Offset of the first branch in the forest
+    offset: usize,
+    len: usize,
+    _branch: PhantomData<B>,
+}
+
+impl<R: ProgmemRead, B: BranchLayout> NodeAccess for ProgmemNodes<R, B> {
+    type Branch = B;
+    type Ref<'a>
+        = B
+    where
+        Self: 'a;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn branch(&self, index: usize) -> Result<B, Error> {
+        if index >= self.len {
+            return Err(Error::MalformedForest);
+        }
+
+        let mut bytes = [0; MAX_BRANCH_SIZE];
+        let bytes = &mut bytes[..size_of::<B>()];
+        self.progmem
+            .copy(self.offset + index * size_of::<B>(), bytes)?;
+        B::read_from_bytes(bytes).map_err(|_| Error::MalformedForest)
+    }
+}
+
+/// The node array alone, to be cached with
+/// [`CachedNodes`](super::access::CachedNodes) or copied to RAM with
+/// [`copy_to_ram`](super::access::copy_to_ram).
+impl<R: ProgmemRead, B> NodeStorage for ProgmemNodes<R, B> {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        let start = offset as usize;
+        if start + bytes.len() > self.len * size_of::<B>() {
+            return Err(Error::Storage);
+        }
+        self.progmem
+            .copy(self.offset + start, bytes)
+            .map_err(|_| Error::Storage)
+    }
+}
+
+/// Place a serialized forest in program memory, and return its
+/// [`Progmem`] bytes, read with [`Lpm`]. `$file` is anything
+/// `include_bytes!` takes, like for `static_storage!`. AVR only.
+#[macro_export]
+#[cfg(target_arch = "avr")]
+macro_rules! static_progmem {
+    ($file:expr $(,)?) => {{
+        const BYTES_LEN: usize = include_bytes!($file).len();
+
+        #[unsafe(link_section = ".progmem.data")]
+        static BUF: ::embedded_rforest::forest::deserialize::BackingStorage<BYTES_LEN> =
+            ::embedded_rforest::forest::deserialize::BackingStorage::new(*include_bytes!($file));
+        ::embedded_rforest::forest::progmem::Progmem::new(
+            ::embedded_rforest::forest::progmem::Lpm,
+            &raw const BUF as usize,
+            BYTES_LEN,
+        )
+    }};
+}
@@ -0,0 +1,273 @@
+//! QuickScorer evaluation of a forest, for scoring many rows on the host or
+//! on targets with memory to spare: rather than walking every tree down its
+//! branches, each row tests the conditions of the whole forest feature by
+//! feature, in threshold order, and clears the leaves each false condition
+//! rules out from a bitvector per tree. The exit leaf of a tree is then the
+//! leftmost leaf left.
+//!
+//! The leaves of each tree are numbered from left to right, so the leaves a
+//! branch rules out when its condition is false, those of its left subtree,
+//! are a range of bits: conditions keep that range rather than a whole mask.
+//! Trees may hold at most a given number of leaves, since every prediction
+//! allocates a copy of the bitvectors of all trees: unlike
+//! [`Predict`](super::Predict), it is not meant for targets without an
+//! allocator.
+
+use core::borrow::Borrow;
+
+use crate::Error;
+
+use super::{
+    Branch, BranchLayout, Classification, MAX_TARGETS, OptimizedForest, ProblemType, Regression,
+    Votes, access::NodeAccess,
+};
+
+/// Condition of a branch, `feature <= threshold` or `feature < threshold`
+/// by the [`SplitRule`](super::SplitRule) of the forest, and the leaves of
+/// its tree it rules out when false.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+struct Condition {
+    feature: u32,
+    threshold: f32,
+    /// First bitvector word of the tree of the branch
+    word: u32,
+    /// Leaves of the left subtree of the branch, numbered within its tree
+    left: (u32, u32),
+}
+
+/// Step of the depth-first walk of a tree, see [`QuickScorer::new`].
+enum Step {
+    Branch(usize),
+    Leaf(u32),
+    /// The left subtree of the condition at this index is walked
+    EndLeft(usize),
+}
+
+/// A forest prepared for [QuickScorer](self) evaluation, borrowing the
+/// forest for its leaves and the way it combines them.
+pub struct QuickScorer<
+    'f,
+    'd,
+    P: ProblemType,
+    B: BranchLayout = Branch,
+    A: NodeAccess<Branch = B> + ?Sized = [B],
+> {
+    forest: &'f OptimizedForest<'d, P, B, A>,
+    /// Conditions of every branch, by feature then by increasing threshold
+    conditions: Vec<Condition>,
+    /// Conditions on feature `i` are those from `feature_starts[i]` up to
+    /// `feature_starts[i + 1]`
+    feature_starts: Vec<usize>,
+    /// Bitvectors of every tree, with every leaf set, but those ruled out by
+    /// conditions on a NaN threshold, which are always false
+    initial: Vec<u64>,
+    /// First bitvector word of every tree, then the total
+    tree_words: Vec<u32>,
+    /// Raw leaf pointers of every tree, from left to right
+    leaves: Vec<u32>,
+    /// First leaf of every tree in `leaves`
+    tree_leaves: Vec<u32>,
+}
+
+impl<'f, 'd, P: ProblemType, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    QuickScorer<'f, 'd, P, B, A>
+{
+    /// Prepare `forest`, whose trees must have at most `max_leaves` leaves
+    /// each. Fails with [`Error::TooManyLeaves`] otherwise, and with
+    /// [`Error::Storage`] if a branch cannot be read.
+    pub fn new(forest: &'f OptimizedForest<'d, P, B, A>, max_leaves: usize) -> Result<Self, Error> {
+        let mut conditions = Vec::<Condition>::new();
+        let mut leaves = Vec::new();
+        let mut tree_words = vec![0];
+        let mut tree_leaves = Vec::new();
+
+        for tree in 0..forest.num_trees() {
+            let base = forest.tree_base(tree);
+            let first = leaves.len();
+            let word = *tree_words.last().unwrap_or(&0);
+            let numbered = |leaves: &Vec<u32>| (leaves.len() - first) as u32;
+
+            // Left subtrees first, so that leaves are numbered from left to
+            // right
+            let mut stack = vec![Step::Branch(forest.tree_root(tree))];
+            while let Some(step) = stack.pop() {
+                match step {
+                    Step::Leaf(ptr) => leaves.push(ptr),
+                    Step::EndLeft(condition) => conditions[condition].left.1 = numbered(&leaves),
+                    Step::Branch(index) => {
+                        let node = forest.nodes.branch(index)?;
+                        let branch = node.borrow();
+                        let child = |ptr, is_leaf| {
+                            if is_leaf {
+                                Step::Leaf(ptr)
+                            } else {
+                                Step::Branch(base + ptr as usize)
+                            }
+                        };
+
+                        conditions.push(Condition {
+                            feature: branch.split_with(),
+                            threshold: branch.split_at(),
+                            word,
+                            left: (numbered(&leaves), 0),
+                        });
+                        stack.push(child(branch.right(), branch.right_is_prediction()));
+                        stack.push(Step::EndLeft(conditions.len() - 1));
+                        stack.push(child(branch.left(), branch.left_is_prediction()));
+                    }
+                }
+            }
+
+            let count = leaves.len() - first;
+            if count > max_leaves {
+                return Err(Error::TooManyLeaves);
+            }
+            tree_leaves.push(first as u32);
+            tree_words.push(word + count.div_ceil(64) as u32);
+        }
+
+        let mut initial = vec![0; *tree_words.last().unwrap_or(&0) as usize];
+        for tree in 0..tree_leaves.len() {
+            let end = tree_leaves
+                .get(tree + 1)
+                .map_or(leaves.len(), |&e| e as usize);
+            let count = (end - tree_leaves[tree] as usize) as u32;
+            set_bits(&mut initial[tree_words[tree] as usize..], (0, count));
+        }
+
+        // `x <= NaN` and `x < NaN` never hold, so those conditions are always
+        // false
+        conditions.retain(|condition| {
+            if condition.threshold.is_nan() {
+                clear_bits(&mut initial[condition.word as usize..], condition.left);
+            }
+            !condition.threshold.is_nan()
+        });
+        conditions.sort_by(|a, b| {
+            a.feature
+                .cmp(&b.feature)
+                .then(a.threshold.total_cmp(&b.threshold))
+        });
+        let feature_starts = (0..=u32::from(forest.num_features()))
+            .map(|feature| conditions.partition_point(|c| c.feature < feature))
+            .collect();
+
+        Ok(Self {
+            forest,
+            conditions,
+            feature_starts,
+            initial,
+            tree_words,
+            leaves,
+            tree_leaves,
+        })
+    }
+
+    /// Number of conditions tested across the forest, one per branch whose
+    /// threshold is not NaN.
+    pub fn condition_count(&self) -> usize {
+        self.conditions.len()
+    }
+
+    /// Bytes of the bitvectors every prediction copies and clears.
+    pub fn bitvector_len(&self) -> usize {
+        size_of_val(self.initial.as_slice())
+    }
+
+    /// Raw leaf pointer of the exit leaf of every tree, passed to `leaf` in
+    /// tree order. Returns the number of false conditions.
+    fn exit_leaves(&self, features: &[f32], mut leaf: impl FnMut(u32)) -> u32 {
+        let mut bits = self.initial.clone();
+        let mut false_conditions = 0;
+        let split_rule = self.forest.split_rule;
+
+        for (feature, bounds) in self.feature_starts.windows(2).enumerate() {
+            let value = features[feature];
+            // Thresholds increase, so conditions stay false up to the first
+            // one which holds, and none holds for NaN
+            for condition in &self.conditions[bounds[0]..bounds[1]] {
+                if split_rule.goes_left(value, condition.threshold) {
+                    break;
+                }
+                clear_bits(&mut bits[condition.word as usize..], condition.left);
+                false_conditions += 1;
+            }
+        }
+
+        for (tree, words) in self.tree_words.windows(2).enumerate() {
+            let words = &bits[words[0] as usize..words[1] as usize];
+            // Every tree keeps the leaf its walk reaches
+            let exit = words
+                .iter()
+                .enumerate()
+                .find(|(_, word)| **word != 0)
+                .map_or(0, |(i, word)| i as u32 * 64 + word.trailing_zeros());
+            leaf(self.leaves[(self.tree_leaves[tree] + exit) as usize]);
+        }
+
+        false_conditions
+    }
+}
+
+/// Set bits `start..end` of `words`.
+fn set_bits(words: &mut [u64], (start, end): (u32, u32)) {
+    for bit in start..end {
+        words[bit as usize / 64] |= 1 << (bit % 64);
+    }
+}
+
+/// Clear bits `start..end` of `words`, a word at a time.
+#[inline(always)]
+fn clear_bits(words: &mut [u64], (start, end): (u32, u32)) {
+    let mut bit = start;
+    while bit < end {
+        let offset = bit % 64;
+        let len = (end - bit).min(64 - offset);
+        let mask = if len == 64 {
+            u64::MAX
+        } else {
+            ((1 << len) - 1) << offset
+        };
+        words[bit as usize / 64] &= !mask;
+        bit += len;
+    }
+}
+
+impl<B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    QuickScorer<'_, '_, Classification, B, A>
+{
+    /// Make the prediction of [`Predict::predict`](super::Predict::predict) on the forest, by
+    /// QuickScorer evaluation.
+    #[must_use]
+    pub fn predict_qs(&self, features: &[f32]) -> u32 {
+        self.predict_qs_counting(features).0
+    }
+
+    /// Make a prediction like [`QuickScorer::predict_qs`], also returning
+    /// the number of false conditions, whose leaves were cleared.
+    #[must_use]
+    pub fn predict_qs_counting(&self, features: &[f32]) -> (u32, u32) {
+        let mut votes = Votes::<MAX_TARGETS>::new();
+        let false_conditions = self.exit_leaves(features, |class| votes.add(class));
+        (self.forest.decide(&votes), false_conditions)
+    }
+}
+
+impl<B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized> QuickScorer<'_, '_, Regression, B, A> {
+    /// Make the prediction of [`Predict::predict`](super::Predict::predict) on the forest, by
+    /// QuickScorer evaluation.
+    #[must_use]
+    pub fn predict_qs(&self, features: &[f32]) -> f32 {
+        self.predict_qs_counting(features).0
+    }
+
+    /// Make a prediction like [`QuickScorer::predict_qs`], also returning
+    /// the number of false conditions, whose leaves were cleared.
+    #[must_use]
+    pub fn predict_qs_counting(&self, features: &[f32]) -> (f32, u32) {
+        let mut sum = 0.0;
+        let false_conditions = self.exit_leaves(features, |ptr| sum += self.forest.leaf_value(ptr));
+        (self.forest.aggregate(sum), false_conditions)
+    }
+}
@@ -1,36 +1,113 @@
+//! Writing an optimized forest back into the format
+//! [`OptimizedForest::deserialize`] reads, e.g. to forward it to a peer.
+//!
+//! [`OptimizedForest::serialize_into`] needs no allocator: it writes into a
+//! buffer of the caller, sized with [`OptimizedForest::serialized_len`].
+
+#[cfg(feature = "std")]
 use aligned_vec::AVec;
+
 use zerocopy::IntoBytes;
 
-use super::{OptimizedForest, ProblemType};
+#[cfg(feature = "std")]
+use super::deserialize::BUFFER_ALIGN;
+use super::{
+    BranchLayout, OptimizedForest, ProblemType, SplitRule, Transform, deserialize::RawHeader,
+};
+use crate::Error;
 
-impl<P: ProblemType> OptimizedForest<'_, P> {
-    pub fn to_bytes(&self) -> AVec<u8> {
-        let mut bytes = AVec::<u8>::with_capacity(4, 8);
+/// Writes bytes one after the other into a buffer known to be large enough.
+struct Cursor<'a> {
+    out: &'a mut [u8],
+    written: usize,
+}
 
-        // Number of trees (4 bytes)
-        bytes.extend_from_slice(self.num_trees.to_bytes().as_slice());
+impl Cursor<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.out[self.written..self.written + bytes.len()].copy_from_slice(bytes);
+        self.written += bytes.len();
+    }
 
-        // Number of features (1 byte)
-        bytes.push(self.num_features);
+    /// Write zeros up to `len` bytes from the start.
+    fn pad_to(&mut self, len: usize) {
+        self.out[self.written..len].fill(0);
+        self.written = len;
+    }
+}
 
-        // Number of targets (1 byte)
-        if let Some(b) = self.num_targets {
-            bytes.push(b.get());
-        } else {
-            bytes.push(0);
-        }
+impl<P: ProblemType, B: BranchLayout> OptimizedForest<'_, P, B> {
+    /// Write this forest into the start of `out`, as
+    /// [`OptimizedForest::deserialize`] reads it, and return the number of
+    /// bytes written, [`OptimizedForest::serialized_len`].
+    ///
+    /// `out` need not be aligned, but the forest can only be deserialized
+    /// from a buffer aligned to
+    /// [`BUFFER_ALIGN`](super::deserialize::BUFFER_ALIGN). Fails with
+    /// [`Error::BufferTooSmall`] if `out` is shorter than the forest, without
+    /// writing anything.
+    pub fn serialize_into(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let needed = self.serialized_len();
+        let Some(out) = out.get_mut(..needed) else {
+            return Err(Error::BufferTooSmall { needed });
+        };
+        let mut out = Cursor { out, written: 0 };
 
-        // Padding
-        bytes.extend_from_slice(&[0; 2]);
+        let header = RawHeader::new(
+            self.num_trees.get(),
+            self.num_features,
+            self.num_targets.map_or(0, |t| t.get()),
+            B::NODE_LAYOUT,
+            self.leaves.len() as u32,
+            !self.tree_offsets.is_empty(),
+            self.fingerprint.get() != 0,
+            self.sum,
+            self.transform != Transform::Identity,
+            !self.calibration.is_empty(),
+            self.ordinal,
+            !self.support.is_empty(),
+            self.split_rule == SplitRule::Less,
+        );
+        out.write(header.as_bytes());
 
-        // Performance: reserve some extra space in the vec for all our nodes
-        bytes.reserve(size_of_val(self.nodes));
+        // The tree table, the fingerprint, the base score, the transform and
+        // the calibration, padded to the node array
+        out.write(self.tree_offsets.as_bytes());
+        if self.fingerprint.get() != 0 {
+            out.write(&self.fingerprint.get().to_le_bytes());
+        }
+        if self.sum {
+            out.write(&self.base_score.get().to_le_bytes());
+        }
+        if self.transform != Transform::Identity {
+            let (scale, offset) = self.transform.parameters();
+            out.write(&self.transform.kind().to_le_bytes());
+            out.write(&scale.to_le_bytes());
+            out.write(&offset.to_le_bytes());
+        }
+        out.write(self.calibration.as_bytes());
+        out.pad_to(usize::from(header.header_len.get()));
 
         // Insert all the nodes
         for node in self.nodes {
-            bytes.extend_from_slice(node.as_bytes());
+            node.write_bytes(|b| out.write(b));
         }
 
+        // And the leaf and support tables
+        out.write(self.leaves.as_bytes());
+        out.write(self.support.as_bytes());
+
+        debug_assert_eq!(out.written, needed);
+        Ok(out.written)
+    }
+
+    /// [`OptimizedForest::serialize_into`] a new buffer, aligned to
+    /// [`BUFFER_ALIGN`].
+    #[cfg(feature = "std")]
+    pub fn to_bytes(&self) -> AVec<u8> {
+        let mut bytes = AVec::<u8>::with_capacity(BUFFER_ALIGN, self.serialized_len());
+        bytes.resize(self.serialized_len(), 0);
+        self.serialize_into(&mut bytes)
+            .expect("the buffer is as long as the forest");
         bytes
     }
 }
@@ -0,0 +1,201 @@
+//! Predictions made a few trees at a time, for cooperative schedulers that
+//! cannot block for a whole forest.
+//!
+//! A [`PredictSession`] holds what the trees evaluated so far predicted, in
+//! fixed-size state it owns, and evaluates up to `max_trees` more trees on
+//! every [`PredictSession::poll`]:
+//!
+//! ```ignore
+//! let mut session = PredictSession::new(&forest, &features);
+//! let class = loop {
+//!     if let Poll::Ready(class) = session.poll(8) {
+//!         break class;
+//!     }
+//!     yield_now().await;
+//! };
+//! ```
+
+use core::task::Poll;
+
+use super::{
+    BranchLayout, Classification, MAX_TARGETS, OptimizedForest, ProblemType, Regression, Votes,
+    access::NodeAccess, read_or_panic,
+};
+use crate::Error;
+
+/// Problem types whose predictions add up tree by tree.
+pub trait Tally: ProblemType + Sized {
+    /// What the trees evaluated so far predicted, for forests of at most `N`
+    /// classes
+    type State<const N: usize>;
+
+    /// The state before any tree is evaluated
+    fn start<const N: usize>() -> Self::State<N>;
+
+    /// Add the leaf a tree reached to `state`
+    fn add<const N: usize, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>(
+        forest: &OptimizedForest<'_, Self, B, A>,
+        state: &mut Self::State<N>,
+        leaf: u32,
+    );
+
+    /// The prediction of the forest, once every tree is in `state`
+    fn finish<const N: usize, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>(
+        forest: &OptimizedForest<'_, Self, B, A>,
+        state: &Self::State<N>,
+    ) -> Self::Output;
+}
+
+/// Votes of the trees, like [`Predict::predict`](super::Predict::predict).
+impl Tally for Classification {
+    type State<const N: usize> = Votes<N>;
+
+    fn start<const N: usize>() -> Votes<N> {
+        Votes::new()
+    }
+
+    #[inline(always)]
+    fn add<const N: usize, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>(
+        _forest: &OptimizedForest<'_, Self, B, A>,
+        votes: &mut Votes<N>,
+        leaf: u32,
+    ) {
+        votes.add(leaf);
+    }
+
+    fn finish<const N: usize, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>(
+        forest: &OptimizedForest<'_, Self, B, A>,
+        votes: &Votes<N>,
+    ) -> u32 {
+        forest.decide(votes)
+    }
+}
+
+/// Sum of the leaf values, aggregated once every tree is evaluated.
+impl Tally for Regression {
+    type State<const N: usize> = f32;
+
+    fn start<const N: usize>() -> f32 {
+        0.0
+    }
+
+    #[inline(always)]
+    fn add<const N: usize, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>(
+        forest: &OptimizedForest<'_, Self, B, A>,
+        sum: &mut f32,
+        leaf: u32,
+    ) {
+        *sum += forest.leaf_value(leaf);
+    }
+
+    fn finish<const N: usize, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>(
+        forest: &OptimizedForest<'_, Self, B, A>,
+        sum: &f32,
+    ) -> f32 {
+        forest.aggregate(*sum)
+    }
+}
+
+/// A prediction of `forest` for `features`, made a few trees at a time by
+/// [`PredictSession::poll`]. Predicts exactly like
+/// [`Predict::predict`](super::Predict::predict) once done.
+///
+/// Classification sessions count votes in `N` counters, [`MAX_TARGETS`]
+/// unless made by [`PredictSession::new_with`].
+pub struct PredictSession<
+    'a,
+    'data,
+    P: Tally,
+    B: BranchLayout = super::Branch,
+    A: NodeAccess<Branch = B> + ?Sized = [B],
+    const N: usize = MAX_TARGETS,
+> {
+    forest: &'a OptimizedForest<'data, P, B, A>,
+    features: &'a [f32],
+    /// The next tree to evaluate
+    next_tree: u32,
+    state: P::State<N>,
+    /// The prediction, once every tree is evaluated
+    result: Option<P::Output>,
+}
+
+impl<'a, 'data, P: Tally, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    PredictSession<'a, 'data, P, B, A>
+{
+    /// Start predicting `features` with `forest`, no tree evaluated yet.
+    pub fn new(forest: &'a OptimizedForest<'data, P, B, A>, features: &'a [f32]) -> Self {
+        Self::new_with::<MAX_TARGETS>(forest, features)
+    }
+
+    /// Start predicting like [`PredictSession::new`], counting votes in `M`
+    /// counters instead of [`MAX_TARGETS`]: a smaller session for forests
+    /// known to have few classes.
+    ///
+    /// # Panics
+    ///
+    /// If the forest classifies in more than `M` classes.
+    pub fn new_with<const M: usize>(
+        forest: &'a OptimizedForest<'data, P, B, A>,
+        features: &'a [f32],
+    ) -> PredictSession<'a, 'data, P, B, A, M> {
+        assert!(forest.num_targets.is_none_or(|t| usize::from(t.get()) <= M));
+        PredictSession {
+            forest,
+            features,
+            next_tree: 0,
+            state: P::start(),
+            result: None,
+        }
+    }
+}
+
+impl<P: Tally, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized, const N: usize>
+    PredictSession<'_, '_, P, B, A, N>
+{
+    /// Evaluate up to `max_trees` more trees. Ready with the prediction once
+    /// every tree is evaluated, and on every later poll.
+    ///
+    /// # Panics
+    ///
+    /// If a branch cannot be read, see [`PredictSession::try_poll`].
+    pub fn poll(&mut self, max_trees: u32) -> Poll<P::Output> {
+        self.try_poll(max_trees).map(read_or_panic)
+    }
+
+    /// Evaluate up to `max_trees` more trees like [`PredictSession::poll`],
+    /// failing with [`Error::Storage`] rather than panicking if a branch
+    /// cannot be read. The tree that failed is evaluated again on the next
+    /// poll.
+    pub fn try_poll(&mut self, max_trees: u32) -> Poll<Result<P::Output, Error>> {
+        if let Some(result) = self.result {
+            return Poll::Ready(Ok(result));
+        }
+
+        let num_trees = self.forest.num_trees.get();
+        let last = self.next_tree.saturating_add(max_trees).min(num_trees);
+        while self.next_tree < last {
+            let leaf = match self.forest.walk(self.next_tree, self.features, &mut 0) {
+                Ok(leaf) => leaf,
+                Err(e) => return Poll::Ready(Err(e)),
+            };
+            P::add(self.forest, &mut self.state, leaf);
+            self.next_tree += 1;
+        }
+
+        if self.next_tree < num_trees {
+            return Poll::Pending;
+        }
+        let result = P::finish(self.forest, &self.state);
+        self.result = Some(result);
+        Poll::Ready(Ok(result))
+    }
+
+    /// Number of trees evaluated so far.
+    pub fn trees_done(&self) -> u32 {
+        self.next_tree
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.result.is_some()
+    }
+}
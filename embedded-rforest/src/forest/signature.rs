@@ -0,0 +1,116 @@
+//! Ed25519 signatures of forests, for devices to check that a forest they
+//! received, e.g. over the air, was written by whoever holds the private
+//! key before loading it.
+//!
+//! `forest-optimizer sign` signs exactly the bytes of a serialized forest,
+//! the ones later given to [`OptimizedForest::deserialize`], or to
+//! [`deserialize_encrypted`](super::encryption) for encrypted forests. The
+//! signature (RFC 8032) is either written to a file of its own, checked
+//! with [`verify_detached`], or appended to the forest in a
+//! [`SignatureTrailer`], checked with [`verify_signature`].
+//!
+//! The trailer is read from the end of the blob, so the forest before it
+//! keeps its offsets and alignment, and is not part of the signed bytes:
+//! [`split_signature`] returns the forest to deserialize once verified.
+//! Verify before decrypting, the signature covers the encrypted bytes.
+//!
+//! The whole module is behind the `signature` feature, so firmware which
+//! does not verify forests does not link the verifier.
+//!
+//! [`OptimizedForest::deserialize`]: super::OptimizedForest::deserialize
+
+use ed25519_compact::{PublicKey, Signature};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, byteorder::little_endian::U16};
+
+use crate::Error;
+
+/// Size of an Ed25519 signature, in bytes.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Size of an Ed25519 public key, in bytes.
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Size of the [`SignatureTrailer`], in bytes.
+pub const TRAILER_LEN: usize = size_of::<SignatureTrailer>();
+
+/// [`SignatureTrailer::magic`], the last bytes of a signed blob.
+pub const TRAILER_MAGIC: [u8; 4] = *b"RFSG";
+
+/// [`SignatureTrailer::kind`] of Ed25519 signatures, the only kind so far.
+pub const ED25519: u8 = 1;
+
+/// The signature appended to a forest, a type-length-value record read
+/// backwards from the end of the blob.
+#[derive(Clone, Copy, PartialEq, Eq, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(C)]
+pub struct SignatureTrailer {
+    /// Signature of every byte of the blob before the trailer
+    pub signature: [u8; SIGNATURE_LEN],
+    /// Algorithm of the signature, [`ED25519`]
+    pub kind: u8,
+    /// Always 0
+    pub reserved: u8,
+    /// Length of the signature, in bytes
+    pub len: U16,
+    /// [`TRAILER_MAGIC`], telling signed blobs from bare forests
+    pub magic: [u8; 4],
+}
+
+const _: () = assert!(TRAILER_LEN == 72 && align_of::<SignatureTrailer>() == 1);
+
+impl SignatureTrailer {
+    /// Trailer holding the Ed25519 `signature` of a forest.
+    pub fn new(signature: [u8; SIGNATURE_LEN]) -> Self {
+        Self {
+            signature,
+            kind: ED25519,
+            reserved: 0,
+            len: U16::new(SIGNATURE_LEN as u16),
+            magic: TRAILER_MAGIC,
+        }
+    }
+}
+
+/// Split a signed `blob` into the forest and its signature, without
+/// verifying it.
+///
+/// Fails with [`Error::Unsigned`] if `blob` does not end with a
+/// [`SignatureTrailer`], and with [`Error::BadSignature`] if the trailer
+/// holds a signature of another kind.
+pub fn split_signature(blob: &[u8]) -> Result<(&[u8], &[u8; SIGNATURE_LEN]), Error> {
+    let Ok((forest, trailer)) = SignatureTrailer::ref_from_suffix(blob) else {
+        return Err(Error::Unsigned);
+    };
+    if trailer.magic != TRAILER_MAGIC {
+        return Err(Error::Unsigned);
+    }
+    if trailer.kind != ED25519 || usize::from(trailer.len.get()) != SIGNATURE_LEN {
+        return Err(Error::BadSignature);
+    }
+    Ok((forest, &trailer.signature))
+}
+
+/// Verify the signature embedded in `blob` with `public_key`, see
+/// [`split_signature`] for the forest to deserialize afterwards.
+///
+/// Fails with [`Error::Unsigned`] if `blob` holds no signature, and with
+/// [`Error::BadSignature`] if it does not verify: the forest was modified,
+/// or signed with another key.
+pub fn verify_signature(blob: &[u8], public_key: &[u8; PUBLIC_KEY_LEN]) -> Result<(), Error> {
+    let (forest, signature) = split_signature(blob)?;
+    verify_detached(forest, signature, public_key)
+}
+
+/// Verify the detached `signature` of `forest` with `public_key`.
+///
+/// Fails with [`Error::BadSignature`] if it does not verify.
+pub fn verify_detached(
+    forest: &[u8],
+    signature: &[u8; SIGNATURE_LEN],
+    public_key: &[u8; PUBLIC_KEY_LEN],
+) -> Result<(), Error> {
+    PublicKey::new(*public_key)
+        .verify(forest, &Signature::new(*signature))
+        .map_err(|_| Error::BadSignature)
+}
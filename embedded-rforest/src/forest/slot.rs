@@ -0,0 +1,182 @@
+//! Two regions of RAM holding the current model and the next one, for
+//! firmware which receives new models over the air and must never predict
+//! with a half-written one.
+//!
+//! A [`ModelSlot`] predicts with the model of its active region, while the
+//! other one receives the next model:
+//!
+//! ```ignore
+//! let mut slot = ModelSlot::<Classification>::new(&mut first, &mut second);
+//! // On every update: write the model to the inactive region, check it...
+//! let region = slot.inactive_region();
+//! let len = receive(slot.region_mut(region)?)?;
+//! slot.stage(region, len)?;
+//! // ...and switch to it
+//! slot.commit();
+//! let class = slot.active().unwrap().predict(&features);
+//! ```
+//!
+//! [`ModelSlot::stage`] deserializes the model, validating it, and checks
+//! its fingerprint and, with the `signature` feature, its signature, if the
+//! slot expects them. A model which fails leaves the active one as it was.
+//! [`ModelSlot::commit`] then only flips the index of the active region.
+//!
+//! The slot has a single writer: staging and committing borrow it mutably,
+//! so no forest returned by [`ModelSlot::active`] is alive while the model
+//! changes. Firmware sharing the slot between an interrupt and the main loop
+//! keeps it in a critical section mutex, as any other shared state.
+
+use core::marker::PhantomData;
+
+use crate::Error;
+
+#[cfg(feature = "signature")]
+use super::signature::{PUBLIC_KEY_LEN, split_signature, verify_signature};
+use super::{Branch, BranchLayout, OptimizedForest, ProblemType};
+
+/// The current model and the next one, see [`mod@self`].
+pub struct ModelSlot<'a, P: ProblemType, B: BranchLayout = Branch> {
+    regions: [&'a mut [u8]; 2],
+    /// Region and length of the model predictions are made with
+    active: Option<(usize, usize)>,
+    /// Region and length of the model [`ModelSlot::commit`] switches to
+    staged: Option<(usize, usize)>,
+    fingerprint: Option<u32>,
+    #[cfg(feature = "signature")]
+    public_key: Option<[u8; PUBLIC_KEY_LEN]>,
+    _problem: PhantomData<(P, B)>,
+}
+
+impl<'a, P: ProblemType, B: BranchLayout> ModelSlot<'a, P, B> {
+    /// Slot without a model, alternating between `first` and `second`, which
+    /// must be aligned to
+    /// [`BUFFER_ALIGN`](super::deserialize::BUFFER_ALIGN) and large enough
+    /// for any model, e.g. two
+    /// [`BackingStorage`](super::deserialize::BackingStorage).
+    pub fn new(first: &'a mut [u8], second: &'a mut [u8]) -> Self {
+        Self {
+            regions: [first, second],
+            active: None,
+            staged: None,
+            fingerprint: None,
+            #[cfg(feature = "signature")]
+            public_key: None,
+            _problem: PhantomData,
+        }
+    }
+
+    /// Only stage models of this
+    /// [`fingerprint`](super::fingerprint::fingerprint), see
+    /// [`OptimizedForest::check_fingerprint`].
+    pub fn with_fingerprint(mut self, expected: u32) -> Self {
+        self.fingerprint = Some(expected);
+        self
+    }
+
+    /// Only stage models signed with the private key of `public_key`, whose
+    /// regions hold the model followed by its signature, see
+    /// [`verify_signature`].
+    #[cfg(feature = "signature")]
+    pub fn with_public_key(mut self, public_key: [u8; PUBLIC_KEY_LEN]) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    /// Index of the region the next model goes to: the one not holding the
+    /// active model.
+    pub fn inactive_region(&self) -> usize {
+        match self.active {
+            Some((0, _)) => 1,
+            _ => 0,
+        }
+    }
+
+    /// Index of the region holding the active model, if any.
+    pub fn active_region(&self) -> Option<usize> {
+        self.active.map(|(region, _)| region)
+    }
+
+    /// Region `region`, to write the next model to. Any model staged in it
+    /// has to be staged again.
+    ///
+    /// Fails with [`Error::RegionInUse`] if it holds the active model.
+    ///
+    /// # Panics
+    ///
+    /// If `region` is neither 0 nor 1.
+    pub fn region_mut(&mut self, region: usize) -> Result<&mut [u8], Error> {
+        self.unstage(region)?;
+        Ok(self.regions[region])
+    }
+
+    /// Check the `bytes_written` first bytes of `region`, and stage them to
+    /// become the active model on the next [`ModelSlot::commit`].
+    ///
+    /// Fails with [`Error::RegionInUse`] if `region` holds the active model,
+    /// with [`Error::BufferTooSmall`] if it is shorter than `bytes_written`,
+    /// and with the errors of [`OptimizedForest::deserialize`],
+    /// [`OptimizedForest::check_fingerprint`] and [`verify_signature`],
+    /// leaving nothing staged.
+    ///
+    /// # Panics
+    ///
+    /// If `region` is neither 0 nor 1.
+    pub fn stage(&mut self, region: usize, bytes_written: usize) -> Result<(), Error> {
+        self.unstage(region)?;
+        let Some(bytes) = self.regions[region].get(..bytes_written) else {
+            return Err(Error::BufferTooSmall {
+                needed: bytes_written,
+            });
+        };
+
+        #[cfg(feature = "signature")]
+        let bytes = match &self.public_key {
+            Some(public_key) => {
+                verify_signature(bytes, public_key)?;
+                split_signature(bytes)?.0
+            }
+            None => bytes,
+        };
+        let forest = OptimizedForest::<P, B>::deserialize(bytes)?;
+        if let Some(expected) = self.fingerprint {
+            forest.check_fingerprint(expected)?;
+        }
+
+        self.staged = Some((region, bytes.len()));
+        Ok(())
+    }
+
+    /// Make the staged model the active one, if a model is staged. Returns
+    /// whether the active model changed.
+    pub fn commit(&mut self) -> bool {
+        match self.staged.take() {
+            Some(staged) => {
+                self.active = Some(staged);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The model predictions are made with, `None` until a model is
+    /// committed.
+    pub fn active(&self) -> Option<OptimizedForest<'_, P, B>> {
+        let (region, len) = self.active?;
+        // SAFETY: the region passed `deserialize` with the same `P` and `B`
+        // in `stage`, and can only be written through `region_mut`, which
+        // refuses the active region
+        unsafe { OptimizedForest::deserialize_unchecked(&self.regions[region][..len]) }.ok()
+    }
+
+    /// Forget the model staged in `region`, which is about to change.
+    fn unstage(&mut self, region: usize) -> Result<(), Error> {
+        assert!(region < 2, "a slot has regions 0 and 1");
+        if self.active_region() == Some(region) {
+            return Err(Error::RegionInUse);
+        }
+        if matches!(self.staged, Some((staged, _)) if staged == region) {
+            self.staged = None;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,452 @@
+//! Experimental structure-of-arrays representation of a forest, to measure
+//! whether parallel arrays of thresholds, flags and child pointers predict
+//! faster than an array of branches.
+//!
+//! Not part of the `.rforest` format negotiation: its layout byte is not a
+//! [`NodeLayout`](super::NodeLayout), so
+//! [`OptimizedForest::deserialize`](super::OptimizedForest::deserialize) and
+//! [`AnyOptimizedForest::deserialize`](super::AnyOptimizedForest::deserialize)
+//! reject it, and it may change or go away in any release.
+
+#[cfg(feature = "fmt")]
+use core::fmt::{self, Debug};
+use core::{marker::PhantomData, num::NonZeroU8};
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+use crate::{
+    Error,
+    ptr::{F32, U32},
+};
+
+#[cfg(feature = "std")]
+use super::{Branch, NodeLayout, OptimizedForest};
+use super::{
+    Classification, Predict, ProblemType, Regression, SplitRule,
+    deserialize::{BUFFER_ALIGN, FORMAT_VERSION, LESS_THAN, RawHeader},
+    votes::{MAX_CLASSIFICATION_TREES, MAX_TARGETS, Votes},
+};
+#[cfg(feature = "std")]
+use crate::ptr::NodeIndex;
+#[cfg(feature = "std")]
+use zerocopy::byteorder::little_endian::U16;
+
+/// Layout byte of a structure-of-arrays forest.
+pub const SOA_LAYOUT: u8 = 0x80;
+
+/// Header of a serialized structure-of-arrays forest: the common header,
+/// followed by the length of each section.
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(C)]
+pub struct SoAHeader {
+    pub base: RawHeader,
+    /// Length of the threshold, flag, left and right sections, in bytes, in
+    /// the order they follow the header
+    pub sections: [U32; 4],
+}
+
+/// Size of the header of a structure-of-arrays forest, in bytes.
+pub const SOA_HEADER_LEN: usize = size_of::<SoAHeader>();
+
+const _: () = assert!(SOA_HEADER_LEN.is_multiple_of(BUFFER_ALIGN));
+
+/// Split variable of a branch, with whether each of its children is a
+/// prediction in the top two bits. Child pointers stay free to hold a whole
+/// `f32` prediction.
+#[repr(transparent)]
+#[derive(IntoBytes, Clone, KnownLayout, Immutable, FromBytes)]
+struct Flags(U32);
+
+impl Flags {
+    #[cfg(feature = "std")]
+    fn new(split_var_idx: u32, left_is_prediction: bool, right_is_prediction: bool) -> Self {
+        assert!(split_var_idx <= u32::MAX >> 2);
+
+        let val = split_var_idx
+            | ((left_is_prediction as u32) << (32 - 1))
+            | ((right_is_prediction as u32) << (32 - 2));
+        Self(U32::new(val))
+    }
+
+    fn left_prediction(&self) -> bool {
+        (self.0 >> (32 - 1)) & 1 != 0
+    }
+
+    fn right_prediction(&self) -> bool {
+        (self.0 >> (32 - 2)) & 1 != 0
+    }
+
+    fn split_var_idx(&self) -> u32 {
+        (self.0 & (u32::MAX >> 2)).get()
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl Debug for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Flags {{ left is leaf: {}, right is leaf: {}, split var: {} }}",
+            self.left_prediction(),
+            self.right_prediction(),
+            self.split_var_idx()
+        )
+    }
+}
+
+/// An optimized forest whose branches are split into parallel arrays. Node
+/// `i` is made of `thresholds[i]`, `flags[i]`, `left[i]` and `right[i]`.
+pub struct SoAForest<'data, P: ProblemType> {
+    num_trees: u32,
+    num_features: u8,
+    num_targets: Option<NonZeroU8>,
+    split_rule: SplitRule,
+    thresholds: &'data [F32],
+    flags: &'data [Flags],
+    left: &'data [U32],
+    right: &'data [U32],
+    _problem: PhantomData<P>,
+}
+
+impl<'data, P: ProblemType> SoAForest<'data, P> {
+    /// Deserialize a forest written by [`SoAForest::to_bytes`] or
+    /// [`OptimizedForest::to_soa_bytes`](super::OptimizedForest::to_soa_bytes).
+    /// Fails with [`Error::Misaligned`] if `buffer` is not aligned to
+    /// [`BUFFER_ALIGN`].
+    pub fn deserialize(buffer: &'data [u8]) -> Result<Self, Error> {
+        if !(buffer.as_ptr() as usize).is_multiple_of(BUFFER_ALIGN) {
+            return Err(Error::Misaligned);
+        }
+
+        let Ok((header, _)) = SoAHeader::read_from_prefix(buffer) else {
+            return Err(Error::MalformedForest);
+        };
+        if header.base.version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion {
+                found: header.base.version,
+                max_supported: FORMAT_VERSION,
+            });
+        }
+        if header.base.layout != SOA_LAYOUT {
+            return Err(Error::WrongLayout);
+        }
+        if (header.base.num_targets != 0) != P::HAS_TARGETS {
+            return Err(Error::WrongProblemType);
+        }
+
+        let header_len = usize::from(header.base.header_len.get());
+        if header_len < SOA_HEADER_LEN || header.base.extra_flags & !LESS_THAN != 0 {
+            return Err(Error::MalformedForest);
+        }
+        let mut rest = buffer.get(header_len..).ok_or(Error::MalformedForest)?;
+        let [thresholds, flags, left, right] = header.sections;
+        let thresholds = section::<F32>(&mut rest, thresholds)?;
+        let flags = section::<Flags>(&mut rest, flags)?;
+        let left = section::<U32>(&mut rest, left)?;
+        let right = section::<U32>(&mut rest, right)?;
+
+        if !rest.is_empty() || thresholds.is_empty() {
+            return Err(Error::MalformedForest);
+        }
+
+        let forest = Self {
+            num_trees: header.base.num_trees.get(),
+            num_features: header.base.num_features,
+            num_targets: NonZeroU8::new(header.base.num_targets),
+            split_rule: if header.base.extra_flags & LESS_THAN != 0 {
+                SplitRule::Less
+            } else {
+                SplitRule::LessOrEqual
+            },
+            thresholds,
+            flags,
+            left,
+            right,
+            _problem: PhantomData,
+        };
+        forest.validate()?;
+
+        Ok(forest)
+    }
+
+    pub fn num_features(&self) -> u8 {
+        self.num_features
+    }
+
+    pub fn num_trees(&self) -> u32 {
+        self.num_trees
+    }
+
+    /// Number of branches of the forest.
+    pub fn node_count(&self) -> usize {
+        self.thresholds.len()
+    }
+
+    /// See [`OptimizedForest::validate`](super::OptimizedForest::validate). The four arrays must also have the
+    /// same length.
+    pub fn validate(&self) -> Result<(), Error> {
+        let len = self.thresholds.len();
+        if self.flags.len() != len
+            || self.left.len() != len
+            || self.right.len() != len
+            || self.num_trees as usize > len
+            || (self.num_targets.is_some() && self.num_trees > MAX_CLASSIFICATION_TREES)
+        {
+            return Err(Error::MalformedForest);
+        }
+
+        let num_targets = self.num_targets.map(|t| t.get() as u32);
+        let check = |ptr: u32, is_prediction: bool| match (is_prediction, num_targets) {
+            (false, _) => (ptr as usize) < len,
+            (true, Some(targets)) => ptr < targets,
+            (true, None) => true,
+        };
+
+        for ((flags, left), right) in self.flags.iter().zip(self.left).zip(self.right) {
+            if !check(left.get(), flags.left_prediction())
+                || !check(right.get(), flags.right_prediction())
+            {
+                return Err(Error::MalformedForest);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn to_bytes(&self) -> aligned_vec::AVec<u8> {
+        write_sections(
+            self.num_trees,
+            self.num_features,
+            self.num_targets,
+            self.split_rule,
+            self.thresholds,
+            self.flags,
+            self.left,
+            self.right,
+        )
+    }
+
+    /// Walk a tree from its root down to a prediction, adding the number of
+    /// branches visited to `visits`. Returns the raw leaf pointer.
+    #[inline(always)]
+    fn walk(&self, tree_id: u32, features: &[f32], visits: &mut u32) -> u32 {
+        let mut node = tree_id as usize;
+
+        loop {
+            *visits += 1;
+            let flags = &self.flags[node];
+
+            if self.split_rule.goes_left(
+                features[flags.split_var_idx() as usize],
+                self.thresholds[node].get(),
+            ) {
+                if flags.left_prediction() {
+                    break self.left[node].get();
+                }
+                node = self.left[node].get() as usize;
+            } else if flags.right_prediction() {
+                break self.right[node].get();
+            } else {
+                node = self.right[node].get() as usize;
+            }
+        }
+    }
+}
+
+/// Split the next section, of `len` bytes, off `rest`.
+fn section<'data, T: FromBytes + KnownLayout + Immutable>(
+    rest: &mut &'data [u8],
+    len: U32,
+) -> Result<&'data [T], Error> {
+    let (bytes, tail) = rest
+        .split_at_checked(len.get() as usize)
+        .ok_or(Error::MalformedForest)?;
+    *rest = tail;
+    <[T]>::ref_from_bytes(bytes).map_err(|_| Error::MalformedForest)
+}
+
+#[cfg(feature = "std")]
+impl<P: ProblemType> OptimizedForest<'_, P, Branch> {
+    /// Serialize this forest as a [`SoAForest`].
+    ///
+    /// # Panics
+    ///
+    /// If the forest sums the predictions of its trees, see
+    /// [`Aggregation::Sum`](super::Aggregation::Sum), or transforms its
+    /// output, see [`Transform`](super::Transform), or calibrates its class
+    /// probabilities, see [`calibration`](super::calibration), or takes the
+    /// median of its votes, see
+    /// [`Aggregation::OrdinalMedian`](super::Aggregation::OrdinalMedian), or
+    /// records the number of training samples of its leaves, see
+    /// [`support`](super::support), which SoA forests do not support.
+    pub fn to_soa_bytes(&self) -> aligned_vec::AVec<u8> {
+        assert!(!self.sum, "SoA forests only average their trees");
+        assert!(!self.ordinal, "SoA forests take the majority vote");
+        assert!(
+            self.support.is_empty(),
+            "SoA forests do not record the support of their leaves"
+        );
+        assert!(
+            self.transform == super::Transform::Identity,
+            "SoA forests do not transform their output"
+        );
+        assert!(
+            self.calibration.is_empty(),
+            "SoA forests do not calibrate their probabilities"
+        );
+
+        // Roots first, as SoA forests find them at the index of their tree
+        let mut is_root = vec![false; self.nodes().len()];
+        for tree in 0..self.num_trees() {
+            is_root[self.tree_root(tree)] = true;
+        }
+        let order = (0..self.num_trees())
+            .map(|tree| self.tree_root(tree))
+            .chain((0..is_root.len()).filter(|&index| !is_root[index]))
+            .collect::<Vec<_>>();
+        let mut new_index = vec![0; order.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old] = new as u32;
+        }
+
+        let nodes = order.iter().map(|&index| &self.nodes()[index]);
+        let thresholds = nodes.clone().map(|b| b.split_at).collect::<Vec<_>>();
+        let flags = nodes
+            .clone()
+            .map(|b| {
+                Flags::new(
+                    b.split_with(),
+                    b.left_is_prediction(),
+                    b.right_is_prediction(),
+                )
+            })
+            .collect::<Vec<_>>();
+        // Leaf tables are not supported: resolve regression leaves back into
+        // the pointers
+        let child = |ptr: U32| match (ptr.is_leaf(), P::HAS_TARGETS) {
+            (true, false) => U32::new(self.leaf_value(ptr.index()).to_bits()),
+            (true, true) => U32::new(ptr.index()),
+            (false, _) => U32::new(new_index[ptr.index() as usize]),
+        };
+        let left = nodes.clone().map(|b| child(b.left)).collect::<Vec<_>>();
+        let right = nodes.map(|b| child(b.right)).collect::<Vec<_>>();
+
+        write_sections(
+            self.num_trees(),
+            self.num_features(),
+            self.num_targets,
+            self.split_rule,
+            &thresholds,
+            &flags,
+            &left,
+            &right,
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+fn write_sections(
+    num_trees: u32,
+    num_features: u8,
+    num_targets: Option<NonZeroU8>,
+    split_rule: SplitRule,
+    thresholds: &[F32],
+    flags: &[Flags],
+    left: &[U32],
+    right: &[U32],
+) -> aligned_vec::AVec<u8> {
+    let sections = [
+        thresholds.as_bytes(),
+        flags.as_bytes(),
+        left.as_bytes(),
+        right.as_bytes(),
+    ];
+
+    let mut base = RawHeader::new(
+        num_trees,
+        num_features,
+        num_targets.map_or(0, |t| t.get()),
+        NodeLayout::Standard,
+        0,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        false,
+        split_rule == SplitRule::Less,
+    );
+    base.layout = SOA_LAYOUT;
+    base.header_len = U16::new(SOA_HEADER_LEN as u16);
+    let header = SoAHeader {
+        base,
+        sections: sections.map(|s| U32::new(s.len() as u32)),
+    };
+
+    let len = SOA_HEADER_LEN + sections.iter().map(|s| s.len()).sum::<usize>();
+    let mut bytes = aligned_vec::AVec::<u8>::with_capacity(BUFFER_ALIGN, len);
+    bytes.extend_from_slice(header.as_bytes());
+    for section in sections {
+        bytes.extend_from_slice(section);
+    }
+
+    bytes
+}
+
+impl SoAForest<'_, Classification> {
+    #[inline(always)]
+    fn classify(&self, features: &[f32], visits: &mut u32) -> u32 {
+        let mut votes = Votes::<MAX_TARGETS>::new();
+        for tree_id in 0..self.num_trees {
+            votes.add(self.walk(tree_id, features, visits));
+        }
+        votes.winner()
+    }
+}
+
+impl Predict for SoAForest<'_, Classification> {
+    type ProblemType = Classification;
+
+    #[inline(never)]
+    fn predict(&self, features: &[f32]) -> u32 {
+        self.classify(features, &mut 0)
+    }
+
+    fn predict_counting(&self, features: &[f32]) -> (u32, u32) {
+        let mut visits = 0;
+        let prediction = self.classify(features, &mut visits);
+        (prediction, visits)
+    }
+}
+
+impl SoAForest<'_, Regression> {
+    #[inline(always)]
+    fn regress(&self, features: &[f32], visits: &mut u32) -> f32 {
+        let mut result = 0.0;
+
+        for tree_id in 0..self.num_trees {
+            result += f32::from_bits(self.walk(tree_id, features, visits));
+        }
+
+        result / self.num_trees as f32
+    }
+}
+
+impl Predict for SoAForest<'_, Regression> {
+    type ProblemType = Regression;
+
+    #[inline(never)]
+    fn predict(&self, features: &[f32]) -> f32 {
+        self.regress(features, &mut 0)
+    }
+
+    fn predict_counting(&self, features: &[f32]) -> (f32, u32) {
+        let mut visits = 0;
+        let prediction = self.regress(features, &mut visits);
+        (prediction, visits)
+    }
+}
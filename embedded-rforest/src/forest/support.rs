@@ -0,0 +1,110 @@
+//! Number of training samples behind each leaf of a forest, recorded after
+//! its leaf table with the [`SUPPORT`](super::deserialize::SUPPORT) flag, so
+//! that the device can tell how many training samples support a prediction,
+//! e.g. to flag those made from a handful of them.
+//!
+//! The table holds a count for each side of every branch, in the order of
+//! the branches: the entry of a leaf is at its id, as
+//! [`predict_leaf_ids`](super::OptimizedForest::predict_leaf_ids) reports
+//! it, and sides leading to another branch have 0. Counts are `u16`s in the
+//! byte order of the nodes, saturated at `u16::MAX`, and the support of a
+//! prediction, their sum over the trees, at `u32::MAX`.
+
+use crate::{Error, ptr::U16};
+
+use super::{
+    BranchLayout, Classification, MAX_TARGETS, OptimizedForest, ProblemType, Regression,
+    access::NodeAccess, read_or_panic,
+};
+
+impl<'data, P: ProblemType, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    OptimizedForest<'data, P, B, A>
+{
+    /// This forest, with the number of training samples of every leaf,
+    /// indexed by leaf id, once serialized. An empty `support` removes them.
+    /// Fails with [`Error::MalformedForest`] if it does not hold two entries
+    /// per branch.
+    pub fn with_support(self, support: &'data [U16]) -> Result<Self, Error> {
+        let forest = Self { support, ..self };
+        forest.validate()?;
+
+        Ok(forest)
+    }
+
+    /// Number of training samples of every leaf, indexed by leaf id. Empty if
+    /// not recorded.
+    pub fn support(&self) -> &[U16] {
+        self.support
+    }
+
+    /// Number of training samples of the leaf of id `leaf_id`, 0 if not
+    /// recorded.
+    pub fn leaf_support(&self, leaf_id: u32) -> u32 {
+        self.support
+            .get(leaf_id as usize)
+            .map_or(0, |count| u32::from(count.get()))
+    }
+}
+
+impl<B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    OptimizedForest<'_, Classification, B, A>
+{
+    /// Make a prediction like [`Predict::predict`](super::Predict::predict),
+    /// along with its support: the number of training samples of the leaves
+    /// of the trees voting for the predicted class. Trees of an ordinal
+    /// forest may all vote for other classes than their median. The support
+    /// is 0 if not recorded.
+    ///
+    /// Walks every tree twice, to count the votes and then the samples,
+    /// rather than keeping a count per class.
+    ///
+    /// # Panics
+    ///
+    /// If a branch cannot be read.
+    #[must_use]
+    pub fn predict_with_support(&self, features: &[f32]) -> (u32, u32) {
+        read_or_panic(self.classify_with_support(features))
+    }
+
+    fn classify_with_support(&self, features: &[f32]) -> Result<(u32, u32), Error> {
+        let prediction = self.classify::<MAX_TARGETS>(features, &mut 0)?;
+        if self.support.is_empty() {
+            return Ok((prediction, 0));
+        }
+
+        let mut support = 0;
+        for tree_id in 0..self.num_trees.get() {
+            let (class, leaf_id) = self.reach(tree_id, features)?;
+            if class == prediction {
+                support += self.leaf_support(leaf_id);
+            }
+        }
+
+        Ok((prediction, support))
+    }
+}
+
+impl<B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized> OptimizedForest<'_, Regression, B, A> {
+    /// Make a prediction like [`Predict::predict`](super::Predict::predict),
+    /// along with its support: the number of training samples of the leaves
+    /// reached in every tree. The support is 0 if not recorded.
+    ///
+    /// # Panics
+    ///
+    /// If a branch cannot be read.
+    #[must_use]
+    pub fn predict_with_support(&self, features: &[f32]) -> (f32, u32) {
+        read_or_panic(self.regress_with_support(features))
+    }
+
+    fn regress_with_support(&self, features: &[f32]) -> Result<(f32, u32), Error> {
+        let (mut sum, mut support) = (0.0, 0u32);
+        for tree_id in 0..self.num_trees.get() {
+            let (leaf, leaf_id) = self.reach(tree_id, features)?;
+            sum += self.leaf_value(leaf);
+            support = support.saturating_add(self.leaf_support(leaf_id));
+        }
+
+        Ok((self.aggregate(sum), support))
+    }
+}
@@ -0,0 +1,158 @@
+//! Transform applied to the output of a regression forest once its trees are
+//! combined, recorded in its header with the
+//! [`TRANSFORM`](super::deserialize::TRANSFORM) flag, so that firmware gets
+//! the probability of a boosted binary classifier, or the prediction of a
+//! forest trained on log targets, without transforming it itself.
+//!
+//! The header section holds the kind of transform, as a little-endian `u32`
+//! (1 for [`Transform::Sigmoid`], 2 for [`Transform::Exp`], 3 for
+//! [`Transform::Custom`]), followed by the scale and the offset of
+//! [`Transform::Custom`] as little-endian `f32`s, 0 for other kinds.
+//!
+//! Targets have no `exp` without `std`, so [`exp`] approximates it with a
+//! polynomial, within a relative error of 2.5e-7 (about 2 ulp).
+
+#[cfg(feature = "fmt")]
+use core::fmt;
+
+use zerocopy::{Immutable, KnownLayout, TryFromBytes};
+
+use crate::Error;
+
+/// Transform of the combined output of the trees of a regression forest.
+/// Discriminants are the kinds of the header.
+#[derive(Clone, Copy, Default, PartialEq, TryFromBytes, KnownLayout, Immutable)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(u8)]
+pub enum Transform {
+    /// The combined output itself
+    #[default]
+    Identity = 0,
+    /// `1 / (1 + exp(-x))`, turning the margin of a boosted binary
+    /// classifier into the probability of its positive class
+    Sigmoid = 1,
+    /// `exp(x)`, for forests trained on the logarithm of their target
+    Exp = 2,
+    /// `scale * x + offset`, e.g. to undo the scaling of the target
+    Custom { scale: f32, offset: f32 } = 3,
+}
+
+/// Scales and offsets are finite: forests with others are rejected.
+impl Eq for Transform {}
+
+impl Transform {
+    /// Apply the transform to `x`.
+    #[inline(always)]
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            Transform::Identity => x,
+            Transform::Sigmoid => sigmoid(x),
+            Transform::Exp => exp(x),
+            Transform::Custom { scale, offset } => scale * x + offset,
+        }
+    }
+
+    /// Kind of the transform in the header, 0 for [`Transform::Identity`],
+    /// which is not recorded.
+    pub(crate) fn kind(self) -> u32 {
+        match self {
+            Transform::Identity => 0,
+            Transform::Sigmoid => 1,
+            Transform::Exp => 2,
+            Transform::Custom { .. } => 3,
+        }
+    }
+
+    /// Transform of the given kind, with the scale and offset of
+    /// [`Transform::Custom`]. Fails for unknown kinds, non-finite
+    /// parameters, and 0, which is not recorded.
+    pub(crate) fn from_parts(kind: u32, scale: f32, offset: f32) -> Result<Self, Error> {
+        let transform = match kind {
+            1 => Transform::Sigmoid,
+            2 => Transform::Exp,
+            3 => Transform::Custom { scale, offset },
+            _ => return Err(Error::MalformedForest),
+        };
+        if !transform.is_valid() {
+            return Err(Error::MalformedForest);
+        }
+
+        Ok(transform)
+    }
+
+    /// Scale and offset of [`Transform::Custom`], recorded in the header.
+    /// 0 for other kinds.
+    pub(crate) fn parameters(self) -> (f32, f32) {
+        match self {
+            Transform::Custom { scale, offset } => (scale, offset),
+            _ => (0.0, 0.0),
+        }
+    }
+
+    /// Whether the parameters of the transform are finite.
+    pub(crate) fn is_valid(self) -> bool {
+        let (scale, offset) = self.parameters();
+        scale.is_finite() && offset.is_finite()
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::Identity => write!(f, "identity"),
+            Transform::Sigmoid => write!(f, "sigmoid"),
+            Transform::Exp => write!(f, "exp"),
+            Transform::Custom { scale, offset } => write!(f, "{scale} * x + {offset}"),
+        }
+    }
+}
+
+/// `ln(2)` split in a part whose product with any exponent of `f32` is
+/// exact, and the rest
+const LN_2_HI: f32 = 0.693_145_75;
+const LN_2_LO: f32 = 1.428_606_8e-6;
+
+/// `e^x`, within a relative error of 2.5e-7. Infinite above 88.72, 0 below
+/// -103.97, where `e^x` does not fit an `f32`.
+///
+/// `x` is reduced to `n * ln(2) + r`, with `|r| <= ln(2) / 2`, so that
+/// `e^x = 2^n * e^r`. `e^r` is its Taylor series up to `r^6`, which is
+/// within `r^7 / 7!`, 1.2e-7, of it.
+pub fn exp(x: f32) -> f32 {
+    if x.is_nan() {
+        return x;
+    }
+    if x > 88.722_84 {
+        return f32::INFINITY;
+    }
+    if x < -103.972_08 {
+        return 0.0;
+    }
+
+    // Rounded to the nearest
+    let n = (x * core::f32::consts::LOG2_E + if x < 0.0 { -0.5 } else { 0.5 }) as i32;
+    let r = x - n as f32 * LN_2_HI - n as f32 * LN_2_LO;
+    let e_r = 1.0
+        + r * (1.0
+            + r * (1.0 / 2.0 + r * (1.0 / 6.0 + r * (1.0 / 24.0 + r * (1.0 / 120.0 + r / 720.0)))));
+
+    // 2^n only fits an `f32` for n in -126..=127
+    if n > 127 {
+        e_r * pow2(127) * pow2(n - 127)
+    } else if n < -126 {
+        e_r * pow2(-126) * pow2(n + 126)
+    } else {
+        e_r * pow2(n)
+    }
+}
+
+/// `2^n`, for n in -126..=127
+fn pow2(n: i32) -> f32 {
+    f32::from_bits(((n + 127) as u32) << 23)
+}
+
+/// `1 / (1 + e^-x)`, within an absolute error of 1e-7, with [`exp`].
+pub fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + exp(-x))
+}
@@ -0,0 +1,137 @@
+//! Walk the branches of an optimized forest with their children decoded,
+//! for tools which inspect forests rather than predict with them.
+//!
+//! Decoding a child needs the problem type, the leaf table and, for relative
+//! layouts, the tree of the branch: [`OptimizedForest::visit`] and
+//! [`OptimizedForest::children`] do it once for every layout and storage,
+//! and never allocate.
+
+use core::borrow::Borrow;
+#[cfg(feature = "fmt")]
+use core::fmt;
+
+use super::{BranchLayout, OptimizedForest, ProblemType, access::NodeAccess};
+use crate::Error;
+
+/// A child of a branch, decoded from its pointer.
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub enum Child {
+    /// Index of a branch in the node array. Pointers relative to their tree
+    /// are resolved.
+    Branch(u32),
+    /// Class a classification leaf predicts
+    LeafClass(u32),
+    /// Value a regression leaf predicts, from the leaf table or the pointer
+    LeafValue(f32),
+}
+
+#[cfg(feature = "fmt")]
+impl fmt::Display for Child {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Child::Branch(index) => write!(f, "branch {index}"),
+            Child::LeafClass(class) => write!(f, "class {class}"),
+            Child::LeafValue(value) => write!(f, "leaf {value}"),
+        }
+    }
+}
+
+/// A branch of an optimized forest with its children decoded, see
+/// [`OptimizedForest::visit`].
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct NodeVisit {
+    /// Index of the branch in the node array
+    pub index: u32,
+    /// Index of the feature the branch splits on
+    pub split_with: u32,
+    /// Threshold: features smaller or equal go left, or only smaller ones
+    /// under [`SplitRule::Less`](super::SplitRule::Less)
+    pub split_at: f32,
+    pub left: Child,
+    pub right: Child,
+}
+
+impl<P: ProblemType, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>
+    OptimizedForest<'_, P, B, A>
+{
+    /// Call `f` with every branch, in the order of the node array.
+    ///
+    /// Fails with [`Error::MalformedForest`] if a child does not decode,
+    /// e.g. a class the forest does not predict, which a validated forest
+    /// never has, and with [`Error::Storage`] if a branch cannot be read.
+    pub fn visit(&self, mut f: impl FnMut(NodeVisit)) -> Result<(), Error> {
+        if !B::RELATIVE {
+            for index in 0..self.nodes.len() {
+                f(self.visit_branch(index, 0)?);
+            }
+            return Ok(());
+        }
+
+        for tree in 0..self.tree_offsets.len() as u32 {
+            let range = self.tree_range(tree).ok_or(Error::MalformedForest)?;
+            let base = range.start;
+            for index in range {
+                f(self.visit_branch(index, base)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Left and right children of the branch at `index` in the node array.
+    ///
+    /// Fails like [`OptimizedForest::visit`], and with
+    /// [`Error::MalformedForest`] if there is no such branch.
+    pub fn children(&self, index: usize) -> Result<(Child, Child), Error> {
+        let base = if B::RELATIVE {
+            // The tree of the branch is the last one starting at or before it
+            let trees = self
+                .tree_offsets
+                .partition_point(|start| start.get() as usize <= index);
+            let tree = trees.checked_sub(1).ok_or(Error::MalformedForest)?;
+            self.tree_offsets[tree].get() as usize
+        } else {
+            0
+        };
+
+        let visit = self.visit_branch(index, base)?;
+        Ok((visit.left, visit.right))
+    }
+
+    /// The branch at `index`, whose branch pointers are relative to `base`.
+    pub(super) fn visit_branch(&self, index: usize, base: usize) -> Result<NodeVisit, Error> {
+        if index >= self.nodes.len() {
+            return Err(Error::MalformedForest);
+        }
+        let branch = self.nodes.branch(index)?;
+        let branch = branch.borrow();
+
+        Ok(NodeVisit {
+            index: index as u32,
+            split_with: branch.split_with(),
+            split_at: branch.split_at(),
+            left: self.child(branch.left(), branch.left_is_prediction(), base)?,
+            right: self.child(branch.right(), branch.right_is_prediction(), base)?,
+        })
+    }
+
+    fn child(&self, ptr: u32, is_prediction: bool, base: usize) -> Result<Child, Error> {
+        match (is_prediction, self.num_targets) {
+            (false, _) => (base as u32)
+                .checked_add(ptr)
+                .map(Child::Branch)
+                .ok_or(Error::MalformedForest),
+            (true, Some(targets)) if ptr < u32::from(targets.get()) => Ok(Child::LeafClass(ptr)),
+            (true, Some(_)) => Err(Error::MalformedForest),
+            (true, None) => match B::inline_leaf(ptr) {
+                Some(value) if self.leaves.is_empty() => Ok(Child::LeafValue(value)),
+                _ => self
+                    .leaves
+                    .get(ptr as usize)
+                    .map(|value| Child::LeafValue(value.get()))
+                    .ok_or(Error::MalformedForest),
+            },
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Vote counting of classification forests.
+
+/// Most classes a classification forest can have, as its number of targets
+/// is a `u8`.
+pub const MAX_TARGETS: usize = u8::MAX as usize;
+
+/// Most trees a classification forest can have, so that a class cannot get
+/// more votes than its counter holds.
+pub const MAX_CLASSIFICATION_TREES: u32 = u16::MAX as u32;
+
+/// The votes of the trees of a forest, one counter per class.
+///
+/// `N` must be at least the number of targets of the forest: forests of a
+/// known number of classes can count in a smaller buffer with
+/// [`OptimizedForest::predict_with`](super::OptimizedForest::predict_with),
+/// while [`Predict::predict`](super::Predict::predict) counts in
+/// [`MAX_TARGETS`] counters, 510 bytes of stack.
+#[derive(Clone)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct Votes<const N: usize = MAX_TARGETS> {
+    counts: [u16; N],
+}
+
+impl<const N: usize> Votes<N> {
+    pub const fn new() -> Self {
+        Self { counts: [0; N] }
+    }
+
+    /// Register the vote of a tree for `class`, which must be below `N`.
+    #[inline(always)]
+    pub fn add(&mut self, class: u32) {
+        self.counts[class as usize] += 1;
+    }
+
+    /// The class with the most votes, the lowest one on ties.
+    pub fn winner(&self) -> u32 {
+        let mut best = 0;
+        for (class, &count) in self.counts.iter().enumerate() {
+            if count > self.counts[best] {
+                best = class;
+            }
+        }
+        best as u32
+    }
+
+    /// The median vote, taking each vote as its class index, for classes
+    /// with a natural order: with an even number of votes, the mean of the
+    /// two middle ones, rounded down. Class 0 if there are no votes.
+    pub fn median(&self) -> u32 {
+        let total = self.counts.iter().map(|&c| u32::from(c)).sum::<u32>();
+        if total == 0 {
+            return 0;
+        }
+
+        // Class of the vote at `position`, with votes sorted by class
+        let nth = |position: u32| {
+            let mut seen = 0;
+            for (class, &count) in self.counts.iter().enumerate() {
+                seen += u32::from(count);
+                if seen > position {
+                    return class as u32;
+                }
+            }
+            unreachable!("position is below the number of votes")
+        };
+        (nth((total - 1) / 2) + nth(total / 2)) / 2
+    }
+
+    /// Number of votes for each class.
+    pub fn counts(&self) -> &[u16; N] {
+        &self.counts
+    }
+}
+
+impl<const N: usize> Default for Votes<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,10 +1,253 @@
 #![cfg_attr(all(not(test), not(feature = "std")), no_std)]
+#![cfg_attr(
+    all(target_arch = "avr", feature = "avr-progmem"),
+    feature(asm_experimental_arch)
+)]
 
+#[cfg(feature = "bench")]
+pub mod bench;
 pub mod forest;
 pub mod ptr;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
 pub enum Error {
     WrongProblemType,
     MalformedForest,
+    /// The forest is made of branches of another layout
+    WrongLayout,
+    /// The forest was serialized in a version of the format this crate does
+    /// not read, see
+    /// [`FORMAT_VERSION`](forest::deserialize::FORMAT_VERSION)
+    UnsupportedVersion {
+        found: u8,
+        max_supported: u8,
+    },
+    /// The buffer is not aligned to
+    /// [`BUFFER_ALIGN`](forest::deserialize::BUFFER_ALIGN)
+    Misaligned,
+    /// The forest predicts another number of classes than expected
+    WrongTargetCount,
+    /// No forest of the bundle has this name
+    UnknownForest,
+    /// Two forests of the bundle have the same name
+    DuplicateForest,
+    /// The nodes of the forest are not in the byte order of
+    /// [`NODE_BYTE_ORDER`](forest::endian::NODE_BYTE_ORDER)
+    WrongByteOrder,
+    /// The forest was written for other features or targets than expected,
+    /// see [`fingerprint`](forest::fingerprint)
+    WrongFingerprint,
+    /// Branches could not be read from their storage, see
+    /// [`NodeStorage`](forest::access::NodeStorage)
+    Storage,
+    /// An output slice does not hold one value per tree
+    WrongOutputLength,
+    /// A tree has more leaves than a
+    /// [`QuickScorer`](forest::quickscorer::QuickScorer) takes
+    TooManyLeaves,
+    /// The buffer is shorter than the `needed` bytes of the serialized
+    /// forest, see
+    /// [`serialize_into`](forest::OptimizedForest::serialize_into)
+    BufferTooSmall {
+        needed: usize,
+    },
+    /// The nodes of the forest are encrypted: decrypt them first, see
+    /// [`encryption`](forest::encryption)
+    Encrypted,
+    /// The key does not decrypt the forest, see
+    /// [`encryption`](forest::encryption)
+    WrongKey,
+    /// The blob holds no signature, see [`signature`](forest::signature)
+    Unsigned,
+    /// The signature does not verify with the public key, see
+    /// [`signature`](forest::signature)
+    BadSignature,
+    /// The region holds the model predictions are made with, see
+    /// [`ModelSlot`](forest::slot::ModelSlot)
+    RegionInUse,
 }
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let description = self.kind().description();
+        write!(f, "{description} ({self:?})")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl Error {
+    /// What went wrong, without the details of the variant
+    pub const fn kind(&self) -> ErrorKind {
+        match self {
+            Error::WrongProblemType => ErrorKind::WrongProblemType,
+            Error::MalformedForest => ErrorKind::MalformedForest,
+            Error::WrongLayout => ErrorKind::WrongLayout,
+            Error::UnsupportedVersion { .. } => ErrorKind::UnsupportedVersion,
+            Error::Misaligned => ErrorKind::Misaligned,
+            Error::WrongTargetCount => ErrorKind::WrongTargetCount,
+            Error::UnknownForest => ErrorKind::UnknownForest,
+            Error::DuplicateForest => ErrorKind::DuplicateForest,
+            Error::WrongByteOrder => ErrorKind::WrongByteOrder,
+            Error::WrongFingerprint => ErrorKind::WrongFingerprint,
+            Error::Storage => ErrorKind::Storage,
+            Error::WrongOutputLength => ErrorKind::WrongOutputLength,
+            Error::TooManyLeaves => ErrorKind::TooManyLeaves,
+            Error::BufferTooSmall { .. } => ErrorKind::BufferTooSmall,
+            Error::Encrypted => ErrorKind::Encrypted,
+            Error::WrongKey => ErrorKind::WrongKey,
+            Error::Unsigned => ErrorKind::Unsigned,
+            Error::BadSignature => ErrorKind::BadSignature,
+            Error::RegionInUse => ErrorKind::RegionInUse,
+        }
+    }
+
+    /// Number of the error, for logging it without formatting, see
+    /// [`ErrorKind`] for the table of codes.
+    pub const fn code(&self) -> u8 {
+        self.kind().code()
+    }
+}
+
+/// The variants of [`Error`] without their details, numbered by their
+/// [`code`](Error::code).
+///
+/// Codes are stable across releases: a new variant takes the next unused
+/// code, and the code of a removed variant is never given again. 0 is never
+/// a code.
+///
+/// | Code | Error |
+/// |-----:|-------|
+/// |    1 | [`WrongProblemType`](Error::WrongProblemType) |
+/// |    2 | [`MalformedForest`](Error::MalformedForest) |
+/// |    3 | [`WrongLayout`](Error::WrongLayout) |
+/// |    4 | [`UnsupportedVersion`](Error::UnsupportedVersion) |
+/// |    5 | [`Misaligned`](Error::Misaligned) |
+/// |    6 | [`WrongTargetCount`](Error::WrongTargetCount) |
+/// |    7 | [`UnknownForest`](Error::UnknownForest) |
+/// |    8 | [`DuplicateForest`](Error::DuplicateForest) |
+/// |    9 | [`WrongByteOrder`](Error::WrongByteOrder) |
+/// |   10 | [`WrongFingerprint`](Error::WrongFingerprint) |
+/// |   11 | [`Storage`](Error::Storage) |
+/// |   12 | [`WrongOutputLength`](Error::WrongOutputLength) |
+/// |   13 | [`TooManyLeaves`](Error::TooManyLeaves) |
+/// |   14 | [`BufferTooSmall`](Error::BufferTooSmall) |
+/// |   15 | [`Encrypted`](Error::Encrypted) |
+/// |   16 | [`WrongKey`](Error::WrongKey) |
+/// |   17 | [`Unsigned`](Error::Unsigned) |
+/// |   18 | [`BadSignature`](Error::BadSignature) |
+/// |   19 | [`RegionInUse`](Error::RegionInUse) |
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[repr(u8)]
+pub enum ErrorKind {
+    WrongProblemType = 1,
+    MalformedForest = 2,
+    WrongLayout = 3,
+    UnsupportedVersion = 4,
+    Misaligned = 5,
+    WrongTargetCount = 6,
+    UnknownForest = 7,
+    DuplicateForest = 8,
+    WrongByteOrder = 9,
+    WrongFingerprint = 10,
+    Storage = 11,
+    WrongOutputLength = 12,
+    TooManyLeaves = 13,
+    BufferTooSmall = 14,
+    Encrypted = 15,
+    WrongKey = 16,
+    Unsigned = 17,
+    BadSignature = 18,
+    RegionInUse = 19,
+}
+
+impl ErrorKind {
+    pub const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// The kind of error numbered `code`, e.g. to decode the codes firmware
+    /// logged. `None` for codes no error has.
+    pub const fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            1 => ErrorKind::WrongProblemType,
+            2 => ErrorKind::MalformedForest,
+            3 => ErrorKind::WrongLayout,
+            4 => ErrorKind::UnsupportedVersion,
+            5 => ErrorKind::Misaligned,
+            6 => ErrorKind::WrongTargetCount,
+            7 => ErrorKind::UnknownForest,
+            8 => ErrorKind::DuplicateForest,
+            9 => ErrorKind::WrongByteOrder,
+            10 => ErrorKind::WrongFingerprint,
+            11 => ErrorKind::Storage,
+            12 => ErrorKind::WrongOutputLength,
+            13 => ErrorKind::TooManyLeaves,
+            14 => ErrorKind::BufferTooSmall,
+            15 => ErrorKind::Encrypted,
+            16 => ErrorKind::WrongKey,
+            17 => ErrorKind::Unsigned,
+            18 => ErrorKind::BadSignature,
+            19 => ErrorKind::RegionInUse,
+            _ => return None,
+        })
+    }
+
+    /// What the error means, as [`Error`] displays it. Unlike `Display`,
+    /// available without the `fmt` feature.
+    pub const fn description(self) -> &'static str {
+        match self {
+            ErrorKind::WrongProblemType => "the forest solves another problem type",
+            ErrorKind::MalformedForest => "the forest is malformed",
+            ErrorKind::WrongLayout => "the forest is made of branches of another layout",
+            ErrorKind::UnsupportedVersion => {
+                "the forest was serialized in another version of the format"
+            }
+            ErrorKind::Misaligned => "the buffer is not aligned to 8 bytes",
+            ErrorKind::WrongTargetCount => {
+                "the forest predicts another number of classes than expected"
+            }
+            ErrorKind::UnknownForest => "no forest of the bundle has this name",
+            ErrorKind::DuplicateForest => "two forests of the bundle have the same name",
+            ErrorKind::WrongByteOrder => "the nodes of the forest are in another byte order",
+            ErrorKind::WrongFingerprint => {
+                "the forest was written for other features or targets than expected"
+            }
+            ErrorKind::Storage => "branches could not be read from their storage",
+            ErrorKind::WrongOutputLength => "an output slice does not hold one value per tree",
+            ErrorKind::TooManyLeaves => "a tree has more leaves than allowed",
+            ErrorKind::BufferTooSmall => "the buffer is too small for the serialized forest",
+            ErrorKind::Encrypted => "the nodes of the forest are encrypted",
+            ErrorKind::WrongKey => "the key does not decrypt the forest",
+            ErrorKind::Unsigned => "the blob holds no signature",
+            ErrorKind::BadSignature => "the signature of the forest does not verify",
+            ErrorKind::RegionInUse => "the region holds the active model",
+        }
+    }
+}
+
+/// [`Debug`](core::fmt::Debug) with the `fmt` feature, and implemented by
+/// every type without it, so that bounds do not pull in `core::fmt`.
+#[cfg(feature = "fmt")]
+pub trait MaybeDebug: core::fmt::Debug {}
+#[cfg(feature = "fmt")]
+impl<T: core::fmt::Debug> MaybeDebug for T {}
+#[cfg(not(feature = "fmt"))]
+pub trait MaybeDebug {}
+#[cfg(not(feature = "fmt"))]
+impl<T> MaybeDebug for T {}
+
+/// [`Display`](core::fmt::Display) with the `fmt` feature, and implemented
+/// by every type without it, see [`MaybeDebug`].
+#[cfg(feature = "fmt")]
+pub trait MaybeDisplay: core::fmt::Display {}
+#[cfg(feature = "fmt")]
+impl<T: core::fmt::Display> MaybeDisplay for T {}
+#[cfg(not(feature = "fmt"))]
+pub trait MaybeDisplay {}
+#[cfg(not(feature = "fmt"))]
+impl<T> MaybeDisplay for T {}
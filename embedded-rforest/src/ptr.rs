@@ -1,9 +1,134 @@
+#[cfg(feature = "fmt")]
 use core::fmt;
-use zerocopy::{
-    byteorder::little_endian::{F32, U32},
-    FromBytes, Immutable, IntoBytes, KnownLayout,
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// The pointer types of a [`Branch`](crate::forest::Branch), and the type of
+/// its thresholds and leaf table values, and of the thresholds of
+/// [`IntegerBranch`](crate::forest::integer::IntegerBranch)es.
+///
+/// Little-endian, unless the `native-endian` feature is enabled: nodes are
+/// then read in the byte order of the target, see
+/// [`NODE_BYTE_ORDER`](crate::forest::endian::NODE_BYTE_ORDER).
+#[cfg(not(feature = "native-endian"))]
+pub use zerocopy::byteorder::little_endian::{F32, I32, U16, U32};
+#[cfg(feature = "native-endian")]
+pub use zerocopy::byteorder::native_endian::{F32, I32, U16, U32};
+
+use crate::{
+    MaybeDebug,
+    forest::{
+        NodeLayout,
+        abi::{self, FORMAT_ABI},
+    },
 };
 
+/// Integer type of the child pointers of a [`Branch`](crate::forest::Branch).
+///
+/// The top bit of a pointer tells whether it points at a leaf or a branch.
+/// The other bits hold the index of the next branch, or of the leaf: a class
+/// index, or an index into the leaf table of a regression forest.
+pub trait NodeIndex: Copy + MaybeDebug + FromBytes + IntoBytes + KnownLayout + Immutable {
+    /// Layout recorded in the header of forests whose branches have pointers
+    /// of this type
+    const NODE_LAYOUT: NodeLayout;
+
+    /// Largest index a pointer can hold
+    const MAX: u32;
+
+    /// Whether branch indices are relative to the first branch of their
+    /// tree, see [`NodeLayout::Relative`]
+    const RELATIVE: bool = false;
+
+    /// Bit set in pointers to a leaf
+    const LEAF_TAG: u32 = Self::MAX + 1;
+
+    /// Pointer holding the raw bits `raw`, tag included, if they fit.
+    fn from_u32(raw: u32) -> Option<Self>;
+
+    /// Raw bits of the pointer, tag included.
+    fn to_u32(self) -> u32;
+
+    /// Pointer to the branch or leaf `index`, if the index fits.
+    #[inline(always)]
+    fn tagged(index: u32, is_leaf: bool) -> Option<Self> {
+        if index > Self::MAX {
+            return None;
+        }
+
+        Self::from_u32(if is_leaf {
+            index | Self::LEAF_TAG
+        } else {
+            index
+        })
+    }
+
+    /// Index of the branch or leaf pointed at, without the tag.
+    #[inline(always)]
+    fn index(self) -> u32 {
+        self.to_u32() & Self::MAX
+    }
+
+    #[inline(always)]
+    fn is_leaf(self) -> bool {
+        self.to_u32() & Self::LEAF_TAG != 0
+    }
+}
+
+impl NodeIndex for U32 {
+    const NODE_LAYOUT: NodeLayout = NodeLayout::Standard;
+    const MAX: u32 = u32::MAX >> 1;
+
+    #[inline(always)]
+    fn from_u32(raw: u32) -> Option<Self> {
+        Some(U32::new(raw))
+    }
+
+    #[inline(always)]
+    fn to_u32(self) -> u32 {
+        self.get()
+    }
+}
+
+impl NodeIndex for U16 {
+    const NODE_LAYOUT: NodeLayout = NodeLayout::Narrow;
+    const MAX: u32 = (u16::MAX >> 1) as u32;
+
+    #[inline(always)]
+    fn from_u32(raw: u32) -> Option<Self> {
+        u16::try_from(raw).ok().map(U16::new)
+    }
+
+    #[inline(always)]
+    fn to_u32(self) -> u32 {
+        self.get().into()
+    }
+}
+
+/// A 16-bit pointer whose branch indices are relative to the first branch of
+/// its tree, so that only trees, not the whole forest, are limited to 32768
+/// branches. Leaf indices are absolute.
+#[repr(transparent)]
+#[derive(Clone, Copy, IntoBytes, KnownLayout, Immutable, FromBytes)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct RelativeU16(U16);
+
+impl NodeIndex for RelativeU16 {
+    const NODE_LAYOUT: NodeLayout = NodeLayout::Relative;
+    const MAX: u32 = U16::MAX;
+    const RELATIVE: bool = true;
+
+    #[inline(always)]
+    fn from_u32(raw: u32) -> Option<Self> {
+        U16::from_u32(raw).map(Self)
+    }
+
+    #[inline(always)]
+    fn to_u32(self) -> u32 {
+        self.0.to_u32()
+    }
+}
+
 /// A specialized relative pointer for use with optimized trees.
 ///
 /// It contains an `u32`, and can hold up to 31 bits of data. The data is
@@ -15,47 +140,62 @@ use zerocopy::{
 #[derive(Clone, Copy, IntoBytes, KnownLayout, Immutable, FromBytes)]
 pub struct NodePointer(U32);
 
+abi::assert_layout!(NodePointer, FORMAT_ABI.node_pointer, [0: U32]);
+const _: () = assert!(U32::LEAF_TAG == FORMAT_ABI.leaf_tag);
+const _: () = assert!(U16::LEAF_TAG == FORMAT_ABI.narrow_leaf_tag as u32);
+
 impl NodePointer {
-    pub fn new_ptr(ptr: u32) -> Self {
-        Self(U32::new(ptr))
+    /// Pointer to the branch at `index`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` does not fit 31 bits.
+    pub fn new_branch(index: u32) -> Self {
+        Self(U32::tagged(index, false).expect("branch index does not fit 31 bits"))
     }
 
-    pub fn new_f32(float: f32) -> Self {
-        let float = F32::new(float);
-        Self(U32::from_bytes(float.to_bytes()))
+    /// Pointer to the leaf `index`: a class index, or an index into the leaf
+    /// table.
+    ///
+    /// # Panics
+    ///
+    /// If `index` does not fit 31 bits.
+    pub fn new_leaf(index: u32) -> Self {
+        Self(U32::tagged(index, true).expect("leaf index does not fit 31 bits"))
     }
 
-    /// Return the pointer representation as a raw integer.
-    pub fn as_ptr(&self) -> u32 {
-        self.0.get()
+    /// Index of the branch or leaf pointed at, without the tag.
+    pub fn index(&self) -> u32 {
+        self.0.index()
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.0.is_leaf()
     }
 
-    pub fn as_f32(&self) -> F32 {
-        let bytes = self.0.to_bytes();
-        F32::from_bytes(bytes)
+    /// Return the pointer representation as a raw integer, tag included.
+    pub fn as_ptr(&self) -> u32 {
+        self.0.get()
     }
 }
 
+#[cfg(feature = "fmt")]
 impl fmt::Debug for NodePointer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "NodePointer: {{ bytes: {:?}, (as_u32: {}, as_f32: {}) }}",
+            "NodePointer: {{ bytes: {:?}, (leaf: {}, index: {}) }}",
             self.0.as_bytes(),
-            self.as_ptr(),
-            self.as_f32()
+            self.is_leaf(),
+            self.index()
         )
     }
 }
 
+#[cfg(feature = "fmt")]
 impl fmt::Display for NodePointer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "NodePointer: {:?} (u32: {}, f32: {})",
-            self.0.as_bytes(),
-            self.as_ptr(),
-            self.as_f32()
-        )
+        let kind = if self.is_leaf() { "leaf" } else { "branch" };
+        write!(f, "NodePointer: {kind} {}", self.index())
     }
 }
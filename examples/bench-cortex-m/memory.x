@@ -0,0 +1,6 @@
+/* STM32F411CE: 512 KB of flash, 128 KB of RAM */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 512K
+  RAM : ORIGIN = 0x20000000, LENGTH = 128K
+}
@@ -0,0 +1,92 @@
+//! Cycle counts of iris predictions on a Cortex-M4F board, see the "How to
+//! benchmark an optimized forest" section of the README.
+//!
+//! Flash it with `cargo run --release` from this directory, then read
+//! `REPORT` with the debugger once the core stops at the breakpoint.
+#![no_std]
+#![no_main]
+
+use core::{fmt::Write, panic::PanicInfo};
+
+use cortex_m::{asm, interrupt};
+use cortex_m_rt::entry;
+use embedded_rforest::{
+    bench::{BenchReport, Dwt, bench_predict},
+    forest::{Classification, OptimizedForest},
+    static_storage,
+};
+
+/// Features of a few iris flowers, in the order of the forest. Vectors
+/// written by `forest-optimizer export-test-vectors` make a more
+/// representative set.
+const INPUTS: [[f32; 4]; 6] = [
+    [5.1, 3.5, 1.4, 0.2],
+    [4.9, 3.0, 1.4, 0.2],
+    [7.0, 3.2, 4.7, 1.4],
+    [5.7, 2.8, 4.1, 1.3],
+    [6.3, 3.3, 6.0, 2.5],
+    [6.5, 3.0, 5.2, 2.0],
+];
+
+#[unsafe(no_mangle)]
+static mut REPORT: Option<BenchReport> = None;
+
+/// Message of the panic which stopped the core, truncated, for the debugger
+/// to read
+#[unsafe(no_mangle)]
+static mut PANIC_MESSAGE: [u8; 128] = [0; 128];
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = cortex_m::Peripherals::take().unwrap();
+    let mut dwt = Dwt::new(&mut peripherals.DCB, peripherals.DWT).unwrap();
+
+    let bytes =
+        static_storage!("../../../forest-optimizer/tests/test-forests/forest_iris_5.rforest");
+    // With `fmt`, the error is formatted into the panic message. Without
+    // it, errors cannot be formatted, and the message is a constant
+    #[cfg(feature = "fmt")]
+    let forest = OptimizedForest::<Classification>::deserialize(bytes).unwrap();
+    #[cfg(not(feature = "fmt"))]
+    let Ok(forest) = OptimizedForest::<Classification>::deserialize(bytes) else {
+        panic!("the forest does not deserialize");
+    };
+
+    let report = interrupt::free(|_| {
+        bench_predict(
+            &forest,
+            INPUTS.iter().map(|features| &features[..]),
+            &mut dwt,
+        )
+    });
+    // SAFETY: nothing else accesses `REPORT`
+    unsafe { REPORT = report };
+
+    loop {
+        asm::bkpt();
+    }
+}
+
+/// Writes into [`PANIC_MESSAGE`], dropping what does not fit
+struct MessageWriter(usize);
+
+impl Write for MessageWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // SAFETY: only the panic handler writes it, with interrupts disabled
+        let message = unsafe { &mut *core::ptr::addr_of_mut!(PANIC_MESSAGE) };
+        let len = s.len().min(message.len() - self.0);
+        message[self.0..self.0 + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.0 += len;
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    interrupt::disable();
+    let _ = write!(MessageWriter(0), "{}", info.message());
+
+    loop {
+        asm::bkpt();
+    }
+}
@@ -0,0 +1,11 @@
+//! Converts `model.csv` on every build where it changed, so the optimized
+//! forest cannot go stale.
+
+use forest_optimizer::build::Build;
+
+fn main() {
+    // Errors are reported to Cargo by `compile`
+    if Build::new("model.csv").with_rust_module().compile().is_err() {
+        std::process::exit(1);
+    }
+}
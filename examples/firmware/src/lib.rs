@@ -0,0 +1,26 @@
+//! A firmware-style crate whose forest is converted from `model.csv` by its
+//! build script, see `build.rs`.
+#![no_std]
+
+use embedded_rforest::{
+    Error,
+    forest::{Classification, OptimizedForest},
+    static_storage,
+};
+
+/// The Rust module written next to the forest
+pub mod model {
+    include!(concat!(env!("OUT_DIR"), "/model.rs"));
+}
+
+// The optimizer validated the forest it wrote
+const _: () = assert!(model::MODEL_VALIDATED);
+
+/// The forest converted at build time, checked against the features the
+/// module was generated for.
+pub fn forest() -> Result<OptimizedForest<'static, Classification>, Error> {
+    let bytes = static_storage!(concat!(env!("OUT_DIR"), "/model.rforest"));
+    let forest = OptimizedForest::deserialize(bytes)?;
+    forest.check_fingerprint(model::MODEL_FINGERPRINT)?;
+    Ok(forest)
+}
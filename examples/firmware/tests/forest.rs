@@ -0,0 +1,16 @@
+use embedded_rforest::forest::{Classification, OptimizedForest, Predict};
+
+#[test]
+fn forest_converted_by_the_build_script_deserializes() {
+    let forest = firmware_example::forest().unwrap();
+    assert_eq!(forest.num_trees(), 5);
+    assert_eq!(forest.num_features(), 4);
+
+    // The module holds the same forest
+    let module =
+        OptimizedForest::<Classification>::deserialize(firmware_example::model::MODEL.to_slice())
+            .unwrap();
+    for row in [[0.1; 4], [5.0; 4], [1.0, 2.0, 3.0, 4.0]] {
+        assert_eq!(forest.predict(&row), module.predict(&row));
+    }
+}
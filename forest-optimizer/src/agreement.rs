@@ -0,0 +1,149 @@
+//! Agreement of a forest with the predictions its trainer recorded in a
+//! dataset: the trainer, the host [`Forest`] built from the forest
+//! definition, and the [`OptimizedForest`] converted from it are compared
+//! two by two over every row.
+
+use std::fmt;
+use std::path::Path;
+
+use color_eyre::{Result, eyre::Context};
+use embedded_rforest::forest::{OptimizedForest, Predict};
+
+use crate::{
+    compare::{Comparison, PredictLike, compare_recorded, compare_within},
+    dataset::read_labeled,
+    forest::Forest,
+    prune::Prune,
+    write_forest::WriteForest,
+};
+
+/// A row on which the trainer and the two forests do not all agree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisagreeingRow {
+    /// Index of the row in the dataset, header excluded
+    pub row: usize,
+    /// Feature values, positioned by feature index
+    pub features: Vec<f32>,
+    pub trainer: String,
+    pub forest: String,
+    pub optimized: String,
+}
+
+/// Three-way agreement between the trainer, the host forest and the
+/// optimized forest over a dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Agreement {
+    /// Feature names, positioned by feature index
+    pub features: Vec<String>,
+    pub trainer_vs_forest: Comparison,
+    pub trainer_vs_optimized: Comparison,
+    pub forest_vs_optimized: Comparison,
+    /// The first rows on which any two disagree, in dataset order
+    pub first_disagreements: Vec<DisagreeingRow>,
+}
+
+impl Agreement {
+    /// Whether all three agree on every row
+    pub fn is_unanimous(&self) -> bool {
+        self.trainer_vs_forest.is_identical()
+            && self.trainer_vs_optimized.is_identical()
+            && self.forest_vs_optimized.is_identical()
+    }
+}
+
+impl fmt::Display for Agreement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let comparisons = [
+            ("Trainer vs forest", &self.trainer_vs_forest),
+            ("Trainer vs optimized", &self.trainer_vs_optimized),
+            ("Forest vs optimized", &self.forest_vs_optimized),
+        ];
+        for (name, comparison) in comparisons {
+            write!(f, "--- {name} ---\n{comparison}")?;
+        }
+
+        for row in &self.first_disagreements {
+            writeln!(
+                f,
+                "Row {}: trainer {}, forest {}, optimized {}",
+                row.row, row.trainer, row.forest, row.optimized
+            )?;
+            for (name, value) in self.features.iter().zip(&row.features) {
+                writeln!(f, "  {name} = {value}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that `forest` and its optimized form predict what the trainer
+/// recorded in `prediction_column` of the dataset at `path`, whose other
+/// columns are mapped onto features by name.
+///
+/// Regression predictions differing by at most `tolerance` agree. Up to
+/// `max_rows` disagreeing rows are listed.
+pub fn check_agreement<P>(
+    forest: &Forest<P>,
+    path: impl AsRef<Path>,
+    prediction_column: &str,
+    tolerance: f64,
+    max_rows: usize,
+) -> Result<Agreement>
+where
+    P: Prune,
+    P::Output: Into<f64>,
+    for<'a> OptimizedForest<'a, P::OptimizedType>: Predict<ProblemType = P::OptimizedType>,
+{
+    let metadata = P::metadata(forest);
+    let dataset = read_labeled(path, &metadata.features, prediction_column)?;
+    let trainer = P::parse_labels(forest, &dataset.labels)?;
+
+    let buffer = <P as WriteForest>::serialize(forest)?;
+    let optimized =
+        OptimizedForest::<P::OptimizedType>::deserialize(&buffer).context("Malformed forest")?;
+
+    let rows = &dataset.rows;
+    let trainer_vs_forest = compare_recorded(&trainer, forest, rows, tolerance);
+    let trainer_vs_optimized = compare_recorded(&trainer, &optimized, rows, tolerance);
+    let forest_vs_optimized = compare_within(forest, &optimized, rows, tolerance);
+
+    let mut disagreeing = [
+        &trainer_vs_forest,
+        &trainer_vs_optimized,
+        &forest_vs_optimized,
+    ]
+    .iter()
+    .flat_map(|comparison| comparison.disagreements.iter().copied())
+    .collect::<Vec<_>>();
+    disagreeing.sort_unstable();
+    disagreeing.dedup();
+
+    // Class indices are shown as their label
+    let describe = |output: P::Output| {
+        let output: f64 = output.into();
+        match &metadata.targets {
+            Some(targets) => targets[output as usize].clone(),
+            None => output.to_string(),
+        }
+    };
+    let first_disagreements = disagreeing
+        .into_iter()
+        .take(max_rows)
+        .map(|row| DisagreeingRow {
+            row,
+            features: rows[row].clone(),
+            trainer: describe(trainer[row]),
+            forest: describe(forest.predict_like(&rows[row])),
+            optimized: describe(optimized.predict(&rows[row])),
+        })
+        .collect();
+
+    Ok(Agreement {
+        features: metadata.features,
+        trainer_vs_forest,
+        trainer_vs_optimized,
+        forest_vs_optimized,
+        first_disagreements,
+    })
+}
@@ -0,0 +1,902 @@
+use std::fmt;
+
+use std::path::{Path, PathBuf};
+
+use embedded_rforest::forest::Branch;
+
+use color_eyre::eyre::eyre;
+use color_eyre::{Result, eyre::Context};
+use embedded_rforest::forest::{
+    AnyOptimizedForest, BranchLayout, Child, Classification, NodeLayout, OptimizedForest,
+    ProblemKind, ProblemType, Regression,
+    deserialize::{FORMAT_VERSION, ForestHeader, peek_version, serialized_len_with},
+};
+
+pub use crate::forest::stats::{FeatureUsage, LeafDistribution};
+
+use crate::{
+    dataset::read_labeled,
+    encryption::encrypted_len,
+    forest::{
+        Forest,
+        monotonic::MonotonicityCheck,
+        stats::{LeafStats, TreeShape},
+    },
+    inspect::{ForestInfo, inspect},
+    latency::{LatencyEstimate, LatencyModel, aggregation_cycles, node_visits},
+    metadata::ForestMetadata,
+    problem_type::PredictionType,
+    prune::{Prune, Validation},
+    sections::{SectionSize, SizeTable, size_breakdown},
+    serialized_forest::{
+        ReadOptions, SerializedClassificationNode, SerializedForest, SerializedNode,
+        SerializedRegressionNode, resolve_problem_type,
+    },
+    write_forest::{NodeEncoding, NodeOrder, PointerWidth, ProfileSource, WriteForest},
+};
+
+/// Version of the JSON schema of [`Analysis`]. Bumped whenever a field is
+/// renamed, removed, or changes meaning.
+pub const ANALYSIS_SCHEMA_VERSION: u32 = 1;
+
+/// Size of an unoptimized node, in bytes: its kind, then the feature,
+/// threshold and children of a branch, or the prediction of a leaf, 4 bytes
+/// each. Sample counts and impurities, which only some definitions have, are
+/// left out.
+pub const UNOPTIMIZED_NODE_SIZE: usize = 20;
+
+/// Size comparison of a forest before and after optimization.
+///
+/// Printed as is, or written as JSON for other tools to read.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Analysis {
+    /// See [`ANALYSIS_SCHEMA_VERSION`]
+    pub schema_version: u32,
+    /// Forest definition file (CSV) the forest was read from, if any
+    pub input: Option<PathBuf>,
+    pub problem_type: PredictionType,
+    /// Number of nodes of the unoptimized forest
+    pub nodes: usize,
+    pub branches: usize,
+    pub leaves: usize,
+    /// In-memory size of the unoptimized nodes, without their statistics,
+    /// in bytes: [`UNOPTIMIZED_NODE_SIZE`] per node
+    pub unoptimized_size: usize,
+    /// Number of nodes of the optimized forest. All of them are branches.
+    pub optimized_nodes: usize,
+    /// Size of the serialized optimized forest, in bytes
+    pub serialized_size: usize,
+    /// Bytes taken by every section of the serialized forest, in order
+    pub sections: Vec<SectionSize>,
+    /// Leaf table of a regression forest, included in `serialized_size`
+    pub leaf_table: Option<LeafTableStats>,
+    /// Distribution of the leaf values of a regression forest, and the
+    /// error quantizing them would introduce
+    pub leaf_distribution: Option<LeafDistribution>,
+    /// Layout of the narrowest pointers the forest fits, as picked by
+    /// `--pointer-width auto`: narrow, relative if only its trees fit 16-bit
+    /// pointers, or standard
+    pub pointer_layout: String,
+    /// How the outputs of the trees are combined, e.g. `majority vote`
+    pub aggregation: String,
+    /// Percentage of the nodes removed by the optimization
+    pub pruned_percent: f32,
+    /// Branches of the optimized forest which take flash for nothing
+    pub reachability: Reachability,
+    /// Time of a prediction on the target, with the defaults of
+    /// `pointer_layout` unless a model is given
+    pub latency: LatencyEstimate,
+    /// Size and depth of the trees, over the whole forest
+    pub tree_summary: TreeSummary,
+    /// Statistics of each tree, in order
+    pub trees: Vec<TreeStats>,
+    /// How often each feature is split on, in feature order
+    pub feature_usage: Vec<FeatureUsage>,
+    /// Score of the forest on a labeled dataset, if one was given
+    pub validation: Option<ValidationSummary>,
+    /// Verdict of each feature checked for monotonicity, if any
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub monotonicity: Vec<MonotonicityCheck>,
+}
+
+/// Size of the table of distinct leaf values of a regression forest.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct LeafTableStats {
+    /// Number of leaves of the optimized forest
+    pub leaves: usize,
+    /// Number of distinct leaf values, each stored once in the table
+    pub entries: usize,
+    /// Size of the table, in bytes
+    pub size: usize,
+    /// Number of leaves per table entry
+    pub dedup_ratio: f64,
+}
+
+/// Size and shape of one tree of a forest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct TreeStats {
+    pub index: usize,
+    pub nodes: usize,
+    pub branches: usize,
+    pub leaves: usize,
+    /// Number of branches on the longest path from the root to a leaf
+    pub depth: usize,
+    /// Bytes taken by the tree in the optimized forest. Only branches are
+    /// kept, leaves are folded into their parents.
+    pub optimized_size: usize,
+}
+
+/// Branches of an optimized forest which could be removed, see
+/// [`reachability`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Reachability {
+    /// Indices of the branches no tree root leads to, in order
+    pub unreachable: Vec<u32>,
+    /// Indices of the reachable branches, tree roots aside, whose children
+    /// are leaves predicting the same output, in order. Their parent could
+    /// point to that leaf instead.
+    pub constant: Vec<u32>,
+}
+
+/// Walk every tree of `forest` from its root, and report the branches it
+/// never reaches and those which predict the same output whichever way
+/// they go.
+///
+/// Roots may be constant, as single-leaf trees are stored as a branch to
+/// the same leaf on both sides, so they are not reported.
+pub fn reachability<P: ProblemType, B: BranchLayout>(
+    forest: &OptimizedForest<'_, P, B>,
+) -> Result<Reachability> {
+    let children = |index: usize| {
+        forest
+            .children(index)
+            .map_err(|e| eyre!("Branch {index} does not decode: {e:?}"))
+    };
+
+    let mut reached = vec![false; forest.nodes().len()];
+    let mut constant = Vec::new();
+    for tree in 0..forest.num_trees() {
+        let root = forest.tree_root(tree);
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            if std::mem::replace(&mut reached[index], true) {
+                continue;
+            }
+            let (left, right) = children(index)?;
+            if index != root && left == right && !matches!(left, Child::Branch(_)) {
+                constant.push(index as u32);
+            }
+            for child in [left, right] {
+                if let Child::Branch(child) = child {
+                    stack.push(child as usize);
+                }
+            }
+        }
+    }
+    constant.sort_unstable();
+
+    let unreachable = (0..reached.len() as u32)
+        .filter(|&index| !reached[index as usize])
+        .collect();
+    Ok(Reachability {
+        unreachable,
+        constant,
+    })
+}
+
+/// Smallest, largest and mean size and depth of the trees of a forest.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct TreeSummary {
+    pub min_nodes: usize,
+    pub max_nodes: usize,
+    pub mean_nodes: f64,
+    pub min_depth: usize,
+    pub max_depth: usize,
+    pub mean_depth: f64,
+}
+
+impl TreeSummary {
+    pub fn new(trees: &[TreeStats]) -> Self {
+        let mean = |total: usize| total as f64 / trees.len().max(1) as f64;
+
+        Self {
+            min_nodes: trees.iter().map(|t| t.nodes).min().unwrap_or(0),
+            max_nodes: trees.iter().map(|t| t.nodes).max().unwrap_or(0),
+            mean_nodes: mean(trees.iter().map(|t| t.nodes).sum()),
+            min_depth: trees.iter().map(|t| t.depth).min().unwrap_or(0),
+            max_depth: trees.iter().map(|t| t.depth).max().unwrap_or(0),
+            mean_depth: mean(trees.iter().map(|t| t.depth).sum()),
+        }
+    }
+}
+
+/// How often each feature of a forest is split on, in feature
+/// order.
+pub fn feature_usage<P: WriteForest + LeafStats>(forest: &Forest<P>) -> Vec<FeatureUsage> {
+    forest.stats().feature_usage
+}
+
+/// Score of a forest on a labeled dataset.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ValidationSummary {
+    /// Labeled dataset (CSV)
+    pub data: PathBuf,
+    pub rows: usize,
+    /// Name of the score
+    pub metric: &'static str,
+    pub score: f64,
+}
+
+/// Score a forest on a labeled dataset (CSV) whose expected outputs are in
+/// `label_column`.
+pub fn verify<P: Prune>(
+    forest: &Forest<P>,
+    data: impl AsRef<Path>,
+    label_column: &str,
+) -> Result<ValidationSummary> {
+    let data = data.as_ref();
+    let dataset = read_labeled(data, &forest.features_by_index(), label_column)?;
+    let validation = Validation::new(forest, dataset)?;
+
+    Ok(ValidationSummary {
+        data: data.to_path_buf(),
+        rows: validation.num_rows(),
+        metric: P::SCORE,
+        score: validation.score(forest),
+    })
+}
+
+/// Statistics of every tree of a forest, in order.
+pub fn tree_stats<P: WriteForest + LeafStats>(forest: &Forest<P>) -> Vec<TreeStats> {
+    optimized_tree_stats(&forest.stats().trees)
+}
+
+/// [`TreeStats`] of trees of the given shapes.
+fn optimized_tree_stats(trees: &[TreeShape]) -> Vec<TreeStats> {
+    trees
+        .iter()
+        .map(|tree| TreeStats {
+            index: tree.index,
+            nodes: tree.nodes,
+            branches: tree.branches,
+            leaves: tree.leaves,
+            depth: tree.depth,
+            // Single-leaf trees are optimized as a single branch
+            optimized_size: tree.branches.max(1) * size_of::<Branch>(),
+        })
+        .collect()
+}
+
+/// Write per-tree statistics as CSV, one row per tree followed by a totals
+/// row.
+pub fn write_tree_csv(trees: &[TreeStats], path: impl AsRef<Path>) -> Result<()> {
+    let mut wtr = csv::Writer::from_path(path.as_ref()).context("Could not create CSV file")?;
+    wtr.write_record([
+        "tree",
+        "nodes",
+        "branches",
+        "leaves",
+        "depth",
+        "optimized_bytes",
+    ])?;
+
+    let mut totals = [0; 5];
+    for tree in trees {
+        let row = [
+            tree.nodes,
+            tree.branches,
+            tree.leaves,
+            tree.depth,
+            tree.optimized_size,
+        ];
+        for (total, value) in totals.iter_mut().zip(row) {
+            *total += value;
+        }
+        wtr.write_record(
+            std::iter::once(tree.index.to_string()).chain(row.iter().map(|v| v.to_string())),
+        )?;
+    }
+
+    // The depth of the forest is the largest depth of its trees
+    totals[3] = trees.iter().map(|t| t.depth).max().unwrap_or(0);
+    wtr.write_record(
+        std::iter::once("total".to_string()).chain(totals.iter().map(|v| v.to_string())),
+    )?;
+    wtr.flush()?;
+
+    Ok(())
+}
+
+impl Analysis {
+    /// Fraction of the nodes removed by the optimization
+    pub fn pruned(&self) -> f32 {
+        (self.nodes as f32 - self.optimized_nodes as f32) / (self.nodes as f32)
+    }
+}
+
+/// Optimize a forest and compare its size before and after.
+pub fn analyze<P: WriteForest + LeafStats>(forest: &Forest<P>) -> Result<Analysis> {
+    let stats = forest.stats();
+
+    let serialized = <P as WriteForest>::serialize(forest)?;
+    let optimized = OptimizedForest::<P::OptimizedType>::deserialize(&serialized)
+        .context("Malformed forest")?;
+    let header = ForestHeader::peek(&serialized).context("Malformed forest")?;
+    let narrowest = P::serialize_with(forest, PointerWidth::Auto)?;
+    let pointer_layout = ForestHeader::peek(&narrowest)
+        .context("Malformed forest")?
+        .layout;
+
+    let leaf_table = (header.num_leaves > 0).then(|| {
+        let leaves = optimized
+            .nodes()
+            .iter()
+            .map(|b| usize::from(b.left_is_prediction()) + usize::from(b.right_is_prediction()))
+            .sum();
+        LeafTableStats {
+            leaves,
+            entries: header.num_leaves,
+            size: size_of_val(optimized.leaves()),
+            dedup_ratio: leaves as f64 / header.num_leaves as f64,
+        }
+    });
+
+    // Optimization keeps every branch of every tree, in order
+    let trees = optimized_tree_stats(&stats.trees);
+    debug_assert_eq!(
+        trees.iter().map(|t| t.optimized_size).sum::<usize>(),
+        header.node_count * size_of::<Branch>()
+    );
+
+    let (nodes, branches) = (stats.nodes(), stats.branches());
+    Ok(Analysis {
+        schema_version: ANALYSIS_SCHEMA_VERSION,
+        input: None,
+        problem_type: P::TYPE,
+        nodes,
+        branches,
+        leaves: nodes - branches,
+        unoptimized_size: forest.nodes().len() * UNOPTIMIZED_NODE_SIZE,
+        optimized_nodes: header.node_count,
+        serialized_size: serialized.len(),
+        sections: size_breakdown(&header),
+        leaf_table,
+        leaf_distribution: stats.leaf_distribution,
+        pointer_layout: pointer_layout.to_string(),
+        aggregation: header.aggregation.to_string(),
+        pruned_percent: (nodes - header.node_count) as f32 / nodes as f32 * 100.0,
+        reachability: reachability(&optimized)?,
+        latency: LatencyEstimate::new(
+            LatencyModel::for_layout(pointer_layout),
+            node_visits(forest, None)?,
+            aggregation_cycles(
+                forest.num_trees(),
+                header.num_targets.map(|t| usize::from(t.get())),
+            ),
+        ),
+        tree_summary: TreeSummary::new(&trees),
+        trees,
+        feature_usage: stats.feature_usage,
+        validation: None,
+        monotonicity: Vec::new(),
+    })
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(input) = &self.input {
+            writeln!(f, "Input: {}", input.display())?;
+        }
+        writeln!(f, "Forest is a {} problem.\n\n", self.problem_type)?;
+
+        writeln!(
+            f,
+            "--- Unoptimized forest ---\nTotal length: {} | Branches: {} , leaves: {} | Size: {} bytes\n--------------------------\n\n",
+            self.nodes, self.branches, self.leaves, self.unoptimized_size
+        )?;
+
+        writeln!(
+            f,
+            "--- Optimized forest ---\nTotal length: {} | Branches: {} , leaves: {} | Size: {}",
+            self.optimized_nodes, self.optimized_nodes, 0, self.serialized_size
+        )?;
+        write!(f, "Narrowest pointers: {} layout", self.pointer_layout)?;
+        if self.pointer_layout == NodeLayout::Relative.to_string() {
+            write!(
+                f,
+                " (too many branches for 16-bit pointers, but not per tree)"
+            )?;
+        }
+        write!(f, "\nAggregation: {}", self.aggregation)?;
+        write!(f, "\n{}", SizeTable(&self.sections))?;
+        writeln!(f, "--------------------------\n\n")?;
+
+        if let Some(table) = &self.leaf_table {
+            writeln!(
+                f,
+                "--- Leaf table ---\n{} values for {} leaves ({:.2} leaves per value) | Size: {} bytes\n--------------------------\n\n",
+                table.entries, table.leaves, table.dedup_ratio, table.size
+            )?;
+        }
+
+        if let Some(distribution) = &self.leaf_distribution {
+            writeln!(
+                f,
+                "--- Leaf values ---\n{distribution}--------------------------\n\n"
+            )?;
+        }
+
+        let pruned = self.pruned();
+        writeln!(
+            f,
+            "--- Analysis results ---\nPruned {:.2}%, Kept {:.2}%",
+            pruned * 100.0,
+            (1.0 - pruned) * 100.0,
+        )?;
+        let reachability = &self.reachability;
+        writeln!(
+            f,
+            "Unreachable branches: {}{}",
+            reachability.unreachable.len(),
+            Indices(&reachability.unreachable)
+        )?;
+        writeln!(
+            f,
+            "Constant branches: {}{}",
+            reachability.constant.len(),
+            Indices(&reachability.constant)
+        )?;
+        writeln!(f, "--------------------------\n\n")?;
+
+        writeln!(
+            f,
+            "--- Latency estimate ---\n{}--------------------------\n\n",
+            self.latency
+        )?;
+
+        let mut largest = self.trees.iter().collect::<Vec<_>>();
+        largest.sort_by_key(|t| std::cmp::Reverse(t.optimized_size));
+        writeln!(f, "--- Largest trees ---")?;
+        writeln!(f, "Tree   Nodes  Branches  Leaves  Depth  Optimized bytes")?;
+        for tree in largest.into_iter().take(10) {
+            writeln!(
+                f,
+                "{:<5}  {:>5}  {:>8}  {:>6}  {:>5}  {:>15}",
+                tree.index, tree.nodes, tree.branches, tree.leaves, tree.depth, tree.optimized_size
+            )?;
+        }
+        let summary = &self.tree_summary;
+        writeln!(
+            f,
+            "Nodes per tree: {} to {}, {:.1} on average | Depth: {} to {}, {:.1} on average",
+            summary.min_nodes,
+            summary.max_nodes,
+            summary.mean_nodes,
+            summary.min_depth,
+            summary.max_depth,
+            summary.mean_depth
+        )?;
+        writeln!(f, "--------------------------\n\n")?;
+
+        writeln!(
+            f,
+            "--- Feature usage ---\n{}--------------------------",
+            FeatureTable(&self.feature_usage)
+        )?;
+
+        if let Some(validation) = &self.validation {
+            writeln!(
+                f,
+                "\n\n--- Validation ---\n{}: {:.3} on {} rows of {}\n--------------------------",
+                validation.metric,
+                validation.score,
+                validation.rows,
+                validation.data.display()
+            )?;
+        }
+
+        if !self.monotonicity.is_empty() {
+            writeln!(f, "\n\n--- Monotonicity ---")?;
+            for check in &self.monotonicity {
+                write!(f, "{check}")?;
+            }
+            writeln!(f, "--------------------------")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The first few branch indices of a [`Reachability`] list, if any.
+struct Indices<'a>(&'a [u32]);
+
+impl fmt::Display for Indices<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const SHOWN: usize = 10;
+
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let shown = self.0[..self.0.len().min(SHOWN)]
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let more = if self.0.len() > SHOWN { ", ..." } else { "" };
+        write!(f, " (branches {shown}{more})")
+    }
+}
+
+/// Feature usage, from the most to the least used feature.
+struct FeatureTable<'a>(&'a [FeatureUsage]);
+
+impl fmt::Display for FeatureTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Least used features last, as candidates to stop computing
+        let mut usage = self.0.iter().collect::<Vec<_>>();
+        usage.sort_by_key(|u| (std::cmp::Reverse(u.branches), std::cmp::Reverse(u.trees)));
+        writeln!(f, "{:<20}  Branches   Trees  Min depth", "Feature")?;
+        for feature in usage {
+            let min_depth = feature
+                .min_depth
+                .map_or_else(|| "-".to_string(), |d| d.to_string());
+            writeln!(
+                f,
+                "{:<20}  {:>8}  {:>5.1}%  {:>9}",
+                feature.name,
+                feature.branches,
+                feature.tree_fraction * 100.0,
+                min_depth
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What [`BinaryAnalysis`] cannot report, as optimization drops what it
+/// would need: the leaves, and the nodes of the forest definition
+pub const UNAVAILABLE_FROM_BINARY: &[&str] = &[
+    "unoptimized nodes and size",
+    "pruned percentage",
+    "leaf value distribution",
+    "latency estimate",
+];
+
+/// What can be told of a serialized forest (`.rforest`) alone, without the
+/// forest definition it was converted from.
+#[derive(Debug, serde::Serialize)]
+pub struct BinaryAnalysis {
+    /// See [`ANALYSIS_SCHEMA_VERSION`]
+    pub schema_version: u32,
+    /// Serialized forest the analysis was made of, if read from a file
+    pub input: Option<PathBuf>,
+    /// Header, size and validation of the forest
+    pub info: ForestInfo,
+    /// Branches of the forest which take flash for nothing. `None` if the
+    /// forest failed validation
+    pub reachability: Option<Reachability>,
+    /// Statistics of each tree, in order. Empty if the forest failed
+    /// validation
+    pub trees: Vec<BinaryTreeStats>,
+    /// How often each feature is split on, in feature order. Features are
+    /// named after the metadata sidecar of the forest, if any, and by their
+    /// index otherwise. Empty if the forest failed validation
+    pub feature_usage: Vec<FeatureUsage>,
+    /// See [`UNAVAILABLE_FROM_BINARY`]
+    pub unavailable: &'static [&'static str],
+}
+
+/// Size and shape of one tree of a serialized forest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct BinaryTreeStats {
+    pub index: usize,
+    /// Index of the root branch in the node array
+    pub root: usize,
+    pub branches: usize,
+    /// Number of branches on the longest path from the root to a leaf, 0
+    /// for single leaves
+    pub depth: usize,
+    /// Bytes taken by the branches of the tree
+    pub optimized_size: usize,
+}
+
+/// Whether `path` is a serialized forest rather than a forest definition
+/// (CSV): it has the `.rforest` extension, or the format version of a header
+/// where a header would have it, which is a control character in text.
+/// Older versions count, for `analyze` to say they need migrating.
+pub fn is_binary_forest(path: impl AsRef<Path>) -> Result<bool> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|ext| ext == "rforest") {
+        return Ok(true);
+    }
+
+    let mut start = [0; 16];
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let read = std::io::Read::read(&mut file, &mut start)?;
+    Ok(peek_version(&start[..read]).is_ok_and(|version| version <= FORMAT_VERSION))
+}
+
+/// Analyze a serialized forest, naming its features after `metadata` if it
+/// belongs to the forest.
+///
+/// Only a malformed header is an error; a forest which fails validation is
+/// still reported, as [`inspect`] does, without the statistics which need
+/// its nodes.
+pub fn analyze_binary(buffer: &[u8], metadata: Option<&ForestMetadata>) -> Result<BinaryAnalysis> {
+    let info = inspect(buffer)?;
+    let header = ForestHeader::peek(buffer).context("Malformed forest header")?;
+    // Metadata of another forest, or without fingerprint for older ones,
+    // as long as the number of features matches
+    let names = metadata
+        .filter(|metadata| {
+            metadata.features.len() == usize::from(header.num_features)
+                && header
+                    .fingerprint
+                    .is_none_or(|f| metadata.fingerprint == 0 || metadata.fingerprint == f.get())
+        })
+        .map_or(&[][..], |metadata| &metadata.features);
+
+    let mut analysis = BinaryAnalysis {
+        schema_version: ANALYSIS_SCHEMA_VERSION,
+        input: None,
+        info,
+        reachability: None,
+        trees: Vec::new(),
+        feature_usage: Vec::new(),
+        unavailable: UNAVAILABLE_FROM_BINARY,
+    };
+    if analysis.info.is_valid() {
+        let (trees, feature_usage, reachability) = match header.problem_kind() {
+            ProblemKind::Classification => any_binary_stats::<Classification>(buffer, names)?,
+            ProblemKind::Regression => any_binary_stats::<Regression>(buffer, names)?,
+        };
+        analysis.trees = trees;
+        analysis.feature_usage = feature_usage;
+        analysis.reachability = Some(reachability);
+    }
+
+    Ok(analysis)
+}
+
+type BinaryStats = (Vec<BinaryTreeStats>, Vec<FeatureUsage>, Reachability);
+
+/// [`binary_stats`] of a forest of any layout.
+fn any_binary_stats<P: ProblemType>(buffer: &[u8], names: &[String]) -> Result<BinaryStats> {
+    let forest = AnyOptimizedForest::<P>::deserialize(buffer)
+        .map_err(|e| eyre!("Malformed forest: {e:?}"))?;
+    match &forest {
+        AnyOptimizedForest::Standard(forest) => binary_stats(forest, names),
+        AnyOptimizedForest::Narrow(forest) => binary_stats(forest, names),
+        AnyOptimizedForest::Relative(forest) => binary_stats(forest, names),
+        AnyOptimizedForest::Compact(forest) => binary_stats(forest, names),
+        AnyOptimizedForest::Integer(forest) => binary_stats(forest, names),
+    }
+}
+
+/// Walk every tree of a validated `forest` from its root for the shape of
+/// the trees and the usage of the features.
+fn binary_stats<P: ProblemType, B: BranchLayout>(
+    forest: &OptimizedForest<'_, P, B>,
+    names: &[String],
+) -> Result<BinaryStats> {
+    let mut visits = Vec::with_capacity(forest.nodes().len());
+    forest
+        .visit(|visit| visits.push(visit))
+        .map_err(|e| eyre!("Malformed forest: {e:?}"))?;
+
+    let num_trees = forest.num_trees();
+    let mut usage = (0..usize::from(forest.num_features()))
+        .map(|index| FeatureUsage {
+            index,
+            name: names
+                .get(index)
+                .cloned()
+                .unwrap_or_else(|| format!("feature {index}")),
+            branches: 0,
+            trees: 0,
+            tree_fraction: 0.0,
+            min_depth: None,
+        })
+        .collect::<Vec<_>>();
+    let mut trees = Vec::with_capacity(num_trees as usize);
+    for tree in 0..num_trees {
+        let root = forest.tree_root(tree);
+        let (mut branches, mut depth) = (0, 0);
+        let mut used = vec![false; usage.len()];
+        let mut stack = vec![(root, 0)];
+        while let Some((index, level)) = stack.pop() {
+            let visit = &visits[index];
+            branches += 1;
+            // Single leaves are stored as a root going to the leaf both ways
+            if index == root && visit.left == visit.right && !matches!(visit.left, Child::Branch(_))
+            {
+                continue;
+            }
+
+            depth = depth.max(level + 1);
+            let feature = &mut usage[visit.split_with as usize];
+            feature.branches += 1;
+            feature.min_depth = Some(feature.min_depth.map_or(level, |d| d.min(level)));
+            used[feature.index] = true;
+            for child in [visit.left, visit.right] {
+                if let Child::Branch(child) = child {
+                    stack.push((child as usize, level + 1));
+                }
+            }
+        }
+
+        for (feature, used) in usage.iter_mut().zip(used) {
+            feature.trees += usize::from(used);
+        }
+        trees.push(BinaryTreeStats {
+            index: tree as usize,
+            root,
+            branches,
+            depth,
+            optimized_size: branches * B::NODE_LAYOUT.branch_size(),
+        });
+    }
+    for feature in &mut usage {
+        feature.tree_fraction = feature.trees as f64 / f64::from(num_trees.max(1));
+    }
+
+    Ok((trees, usage, reachability(forest)?))
+}
+
+impl fmt::Display for BinaryAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(input) = &self.input {
+            writeln!(f, "Input: {}", input.display())?;
+        }
+        writeln!(f, "{}\n", self.info)?;
+
+        if let Some(reachability) = &self.reachability {
+            writeln!(
+                f,
+                "--- Analysis results ---\nUnreachable branches: {}{}\nConstant branches: {}{}\n--------------------------\n\n",
+                reachability.unreachable.len(),
+                Indices(&reachability.unreachable),
+                reachability.constant.len(),
+                Indices(&reachability.constant)
+            )?;
+        }
+
+        if !self.trees.is_empty() {
+            let mut largest = self.trees.iter().collect::<Vec<_>>();
+            largest.sort_by_key(|t| std::cmp::Reverse(t.optimized_size));
+            writeln!(f, "--- Largest trees ---")?;
+            writeln!(f, "Tree   Root  Branches  Depth  Optimized bytes")?;
+            for tree in largest.into_iter().take(10) {
+                writeln!(
+                    f,
+                    "{:<5}  {:>4}  {:>8}  {:>5}  {:>15}",
+                    tree.index, tree.root, tree.branches, tree.depth, tree.optimized_size
+                )?;
+            }
+            writeln!(f, "--------------------------\n\n")?;
+        }
+
+        if !self.feature_usage.is_empty() {
+            writeln!(
+                f,
+                "--- Feature usage ---\n{}--------------------------\n\n",
+                FeatureTable(&self.feature_usage)
+            )?;
+        }
+
+        writeln!(
+            f,
+            "Unavailable without the forest definition (CSV): {}",
+            self.unavailable.join(", ")
+        )
+    }
+}
+
+/// Expected size of a forest once optimized and serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeReport {
+    /// Number of nodes of the unoptimized forest
+    pub nodes: usize,
+    /// Number of nodes of the optimized forest
+    pub optimized_nodes: usize,
+    /// Size of the serialized optimized forest, in bytes
+    pub serialized_size: usize,
+}
+
+impl SizeReport {
+    /// Fraction of the nodes removed by the optimization
+    pub fn pruned(&self) -> f32 {
+        (self.nodes as f32 - self.optimized_nodes as f32) / (self.nodes as f32)
+    }
+}
+
+/// Optimize a forest and compute the exact size it would have once
+/// serialized, without serializing it.
+pub fn estimate_serialized_size<P: WriteForest>(forest: &Forest<P>) -> SizeReport {
+    let (optimized, leaves) = P::optimize(forest);
+
+    SizeReport {
+        nodes: forest.nodes().len(),
+        optimized_nodes: optimized.len(),
+        // Serialized forests have a tree table and a fingerprint, and average
+        // or vote on their trees without transforming the outcome nor
+        // calibrating it, nor recording the support of their leaves
+        serialized_size: serialized_len_with(
+            NodeLayout::Standard,
+            forest.num_trees(),
+            true,
+            false,
+            false,
+            0,
+            optimized.len(),
+            leaves.len(),
+            false,
+        ),
+    }
+}
+
+/// Size of a forest definition file (CSV) once converted: read following
+/// `options` and serialized with `encoding`, as `convert` would, without
+/// writing anything. Its problem type is detected from its header, or given
+/// by `problem_type`.
+pub fn estimate_file(
+    input: impl AsRef<Path>,
+    problem_type: Option<PredictionType>,
+    options: &ReadOptions,
+    encoding: &NodeEncoding,
+) -> Result<SizeReport> {
+    fn estimate<N>(
+        input: impl AsRef<Path>,
+        options: &ReadOptions,
+        encoding: &NodeEncoding,
+    ) -> Result<SizeReport>
+    where
+        N: SerializedNode,
+        N::ProblemType: WriteForest,
+    {
+        let serialized = SerializedForest::<N>::read_with(input, options)
+            .context("Could not read forest definition file (CSV).")?;
+        serialized.check()?;
+        let forest = Forest::from_serialized(options.prepare(serialized)?)?;
+        let serialized = N::ProblemType::serialize_encoded(&forest, encoding)?;
+        let serialized_size = match encoding.encryption {
+            Some(_) => encrypted_len(&serialized)?,
+            None => serialized.len(),
+        };
+
+        Ok(SizeReport {
+            nodes: forest.nodes().len(),
+            optimized_nodes: N::ProblemType::optimize(&forest).0.len(),
+            serialized_size,
+        })
+    }
+
+    // Nothing is written, not even the profile of the layout
+    let mut encoding = encoding.clone();
+    if let NodeOrder::Profiled(ProfileSource::Data { save, .. }) = &mut encoding.layout {
+        *save = None;
+    }
+
+    match resolve_problem_type(&input, problem_type)? {
+        PredictionType::Classification => {
+            estimate::<SerializedClassificationNode>(input, options, &encoding)
+        }
+        PredictionType::Regression => {
+            estimate::<SerializedRegressionNode>(input, options, &encoding)
+        }
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Serialized size: {} bytes", self.serialized_size)?;
+        writeln!(
+            f,
+            "Nodes: {} unoptimized, {} optimized (pruned {:.2}%)",
+            self.nodes,
+            self.optimized_nodes,
+            self.pruned() * 100.0
+        )
+    }
+}
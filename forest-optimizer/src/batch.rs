@@ -0,0 +1,145 @@
+//! Conversion of every forest definition file (CSV) of a directory.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+};
+
+use color_eyre::{Result, eyre::Context};
+
+use crate::{
+    emit::{FormatSpec, artifact_paths, check_overwrite},
+    metadata::ForestMetadata,
+    problem_type::PredictionType,
+    serialized_forest::ReadOptions,
+    write_forest::{NodeEncoding, convert_file},
+};
+
+/// Outcome of the conversion of one file
+#[derive(Debug)]
+pub struct BatchEntry {
+    pub input: PathBuf,
+    /// Size of the serialized forest in bytes, or the reason the conversion
+    /// failed
+    pub result: Result<usize, String>,
+}
+
+/// Outcome of a batch conversion, sorted by input file
+#[derive(Debug)]
+pub struct BatchSummary {
+    pub entries: Vec<BatchEntry>,
+}
+
+impl BatchSummary {
+    pub fn failures(&self) -> usize {
+        self.entries.iter().filter(|e| e.result.is_err()).count()
+    }
+}
+
+/// Forest definition files (`*.csv`) of a directory, sorted by name
+pub fn discover(input_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(input_dir.as_ref()).context("Could not read input directory")? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "csv") {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+
+    Ok(inputs)
+}
+
+/// Convert every forest definition file of `input_dir` into `output_dir`,
+/// using up to `jobs` threads.
+///
+/// Each `<stem>.csv` is written as `<stem>.<ext>` in every requested format,
+/// along with `<stem>.rforest.meta.json`. Files which already exist are only
+/// replaced if `force`, otherwise their input fails. A file failing to
+/// convert does not stop the others.
+#[allow(clippy::too_many_arguments)]
+pub fn convert_dir(
+    input_dir: impl AsRef<Path>,
+    output_dir: impl AsRef<Path>,
+    problem_type: Option<PredictionType>,
+    options: &ReadOptions,
+    encoding: NodeEncoding,
+    formats: &[FormatSpec],
+    jobs: usize,
+    force: bool,
+) -> Result<BatchSummary> {
+    let output_dir = output_dir.as_ref();
+    fs::create_dir_all(output_dir).context("Could not create output directory")?;
+
+    let inputs = Mutex::new(discover(input_dir)?.into_iter());
+    let entries = Mutex::new(Vec::new());
+
+    let convert = |input: &Path| -> Result<usize> {
+        let stem = input.file_stem().unwrap_or_default();
+        let output = output_dir.join(stem).with_extension("rforest");
+        let (artifacts, metadata_path) = (
+            artifact_paths(&output, formats),
+            ForestMetadata::sidecar_path(&output),
+        );
+        if !force {
+            check_overwrite(&artifacts, &metadata_path)?;
+        }
+
+        convert_file(
+            input,
+            problem_type,
+            options,
+            encoding.clone(),
+            &artifacts,
+            metadata_path,
+        )
+    };
+
+    thread::scope(|s| {
+        for _ in 0..jobs.max(1) {
+            s.spawn(|| {
+                loop {
+                    let Some(input) = inputs.lock().unwrap().next() else {
+                        break;
+                    };
+                    let result = convert(&input).map_err(|e| format!("{e:#}"));
+                    entries.lock().unwrap().push(BatchEntry { input, result });
+                }
+            });
+        }
+    });
+
+    let mut entries = entries.into_inner().unwrap();
+    entries.sort_by(|a, b| a.input.cmp(&b.input));
+
+    Ok(BatchSummary { entries })
+}
+
+impl fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self
+            .entries
+            .iter()
+            .map(|e| e.input.file_name().unwrap_or_default().to_string_lossy())
+            .collect::<Vec<_>>();
+        let width = names.iter().map(|n| n.len()).max().unwrap_or(0).max(4);
+
+        writeln!(f, "{:<width$}  Status  Size / error", "File")?;
+        for (name, entry) in names.iter().zip(&self.entries) {
+            match &entry.result {
+                Ok(size) => writeln!(f, "{name:<width$}  ok      {size} bytes")?,
+                Err(e) => writeln!(f, "{name:<width$}  FAILED  {e}")?,
+            }
+        }
+
+        let failures = self.failures();
+        writeln!(
+            f,
+            "\n{} converted, {} failed",
+            self.entries.len() - failures,
+            failures
+        )
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    fmt,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use embedded_rforest::forest::Predict;
+
+/// Prediction performance of a forest over a dataset.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct BenchReport {
+    /// Number of timed predictions
+    pub predictions: usize,
+    /// Mean latency of a prediction, in nanoseconds
+    pub mean_ns: f64,
+    /// Median latency of a prediction, in nanoseconds
+    pub median_ns: f64,
+    /// 99th percentile latency of a prediction, in nanoseconds
+    pub p99_ns: f64,
+    /// Predictions per second, over the whole run
+    pub throughput: f64,
+    /// Average number of branches visited by a prediction
+    pub mean_visits: f64,
+}
+
+/// Time `iterations` passes of predictions over `rows`.
+///
+/// Every prediction is timed individually. Branch visits are counted in a
+/// separate, untimed pass so the counting does not skew latencies.
+pub fn bench<F: Predict>(forest: &F, rows: &[Vec<f32>], iterations: usize) -> BenchReport {
+    bench_with(
+        rows,
+        iterations,
+        |row| forest.predict(row),
+        |row| forest.predict_counting(row).1,
+    )
+}
+
+/// [`bench`] of any way of predicting, `predict`, whose work on a row
+/// `count` returns in place of branch visits.
+pub fn bench_with<T>(
+    rows: &[Vec<f32>],
+    iterations: usize,
+    predict: impl Fn(&[f32]) -> T,
+    count: impl Fn(&[f32]) -> u32,
+) -> BenchReport {
+    let mut latencies = Vec::with_capacity(rows.len() * iterations);
+
+    let run = Instant::now();
+    for _ in 0..iterations {
+        for row in rows {
+            let start = Instant::now();
+            let _ = black_box(predict(black_box(row)));
+            latencies.push(start.elapsed());
+        }
+    }
+    let total = run.elapsed();
+
+    let visits = rows.iter().map(|row| count(row) as u64).sum::<u64>();
+
+    latencies.sort_unstable();
+    let percentile = |p: f64| match latencies.len() {
+        0 => 0.0,
+        len => nanos(latencies[((len - 1) as f64 * p).round() as usize]),
+    };
+    let count = latencies.len();
+
+    BenchReport {
+        predictions: count,
+        mean_ns: ratio(latencies.iter().map(|&l| nanos(l)).sum(), count),
+        median_ns: percentile(0.5),
+        p99_ns: percentile(0.99),
+        throughput: if total.is_zero() {
+            0.0
+        } else {
+            count as f64 / total.as_secs_f64()
+        },
+        mean_visits: ratio(visits as f64, rows.len()),
+    }
+}
+
+/// Mean time of `iterations` calls of `load`, such as deserializing a
+/// forest, in nanoseconds.
+pub fn bench_load<T>(iterations: usize, mut load: impl FnMut() -> T) -> f64 {
+    let run = Instant::now();
+    for _ in 0..iterations {
+        black_box(load());
+    }
+    ratio(nanos(run.elapsed()), iterations)
+}
+
+fn nanos(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e9
+}
+
+fn ratio(numerator: f64, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator / denominator as f64
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Predictions:    {}", self.predictions)?;
+        writeln!(f, "Mean latency:   {:.1} ns", self.mean_ns)?;
+        writeln!(f, "Median latency: {:.1} ns", self.median_ns)?;
+        writeln!(f, "p99 latency:    {:.1} ns", self.p99_ns)?;
+        writeln!(f, "Throughput:     {:.0} predictions/s", self.throughput)?;
+        writeln!(f, "Branch visits:  {:.2} per prediction", self.mean_visits)
+    }
+}
@@ -1,38 +1,29 @@
-use clap::{Parser, ValueEnum};
-use color_eyre::Result;
-use forest_optimizer::write_forest::{write_classification, write_regression};
+//! Deprecated alias of `forest-optimizer convert`, kept for existing scripts.
 
-use std::path::PathBuf;
+use std::process::ExitCode;
 
-/// Modes for the application
-#[derive(Debug, Clone, ValueEnum)]
-enum ProblemType {
-    Classification,
-    Regression,
-}
+use clap::Parser;
+use color_eyre::Result;
+use forest_optimizer::cli::{
+    Verbosity,
+    convert::{self, ConvertArgs},
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// Input file
-    #[arg(short = 'i', long = "input", value_name = "INPUT_FILE")]
-    input: PathBuf,
-
-    /// Output file
-    #[arg(short = 'o', long = "output", value_name = "OUTPUT_FILE")]
-    output: PathBuf,
+    #[command(flatten)]
+    verbosity: Verbosity,
 
-    /// Problem type
-    #[arg(short = 'p', long = "problem-type", value_enum)]
-    problem_type: ProblemType,
+    #[command(flatten)]
+    args: ConvertArgs,
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     color_eyre::install()?;
-    let args = Cli::parse();
+    eprintln!("warning: `optimize_forest` is deprecated, use `forest-optimizer convert` instead");
 
-    match args.problem_type {
-        ProblemType::Classification => write_classification(args.input, args.output),
-        ProblemType::Regression => write_regression(args.input, args.output),
-    }
+    let cli = Cli::parse();
+    cli.verbosity.init_tracing();
+    convert::run(cli.args)
 }
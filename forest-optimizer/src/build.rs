@@ -0,0 +1,151 @@
+//! Conversion of forests from the build script of a firmware crate, so that
+//! the forest definition is the single source of truth and the optimized
+//! forest never goes stale:
+//!
+//! ```no_run
+//! // In `main` of build.rs. Errors are reported to Cargo by `compile`
+//! if forest_optimizer::build::compile("model.csv").is_err() {
+//!     std::process::exit(1);
+//! }
+//! ```
+//!
+//! The forest is then embedded with
+//! `static_storage!(concat!(env!("OUT_DIR"), "/model.rforest"))`.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+
+use crate::{
+    emit::OutputFormat,
+    metadata::ForestMetadata,
+    serialized_forest::{IndexOrder, ReadOptions},
+    write_forest::{NodeEncoding, convert_file},
+};
+
+/// Convert the forest definition at `input` into `OUT_DIR`, see
+/// [`Build::compile`].
+pub fn compile(input: impl AsRef<Path>) -> Result<PathBuf> {
+    Build::new(input).compile()
+}
+
+/// Conversion of a forest definition from a build script.
+#[derive(Debug, Clone)]
+pub struct Build {
+    input: PathBuf,
+    out_dir: Option<PathBuf>,
+    options: ReadOptions,
+    encoding: NodeEncoding,
+    rust_module: bool,
+}
+
+impl Build {
+    /// Convert the forest definition at `input`, whose problem type is read
+    /// from its header.
+    pub fn new(input: impl AsRef<Path>) -> Self {
+        Self {
+            input: input.as_ref().to_path_buf(),
+            out_dir: None,
+            options: ReadOptions::default(),
+            encoding: NodeEncoding::default(),
+            rust_module: false,
+        }
+    }
+
+    /// Write to `out_dir` rather than the `OUT_DIR` Cargo gives build
+    /// scripts.
+    pub fn with_out_dir(self, out_dir: impl AsRef<Path>) -> Self {
+        Self {
+            out_dir: Some(out_dir.as_ref().to_path_buf()),
+            ..self
+        }
+    }
+
+    /// Pin feature and target indices, like `convert --feature-order`.
+    pub fn with_order(self, order: IndexOrder) -> Self {
+        Self {
+            options: ReadOptions {
+                order,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Pointer width and byte order of the branches, like
+    /// `convert --pointer-width` and `--endianness`.
+    pub fn with_encoding(self, encoding: impl Into<NodeEncoding>) -> Self {
+        Self {
+            encoding: encoding.into(),
+            ..self
+        }
+    }
+
+    /// Also write the forest as a Rust module, `<stem>.rs`, to be included
+    /// with `include!(concat!(env!("OUT_DIR"), "/<stem>.rs"))`.
+    pub fn with_rust_module(self) -> Self {
+        Self {
+            rust_module: true,
+            ..self
+        }
+    }
+
+    /// Convert the forest, and write it to `<stem>.rforest` in the output
+    /// directory, along with its metadata and, if asked, its Rust module.
+    /// Returns the path of the `.rforest`.
+    ///
+    /// Prints the `cargo::rerun-if-changed` line of the input, so that Cargo
+    /// converts it again whenever it changes. On failure, the error is also
+    /// printed as a `cargo::error` line, which Cargo shows as a diagnostic
+    /// of the build script.
+    pub fn compile(self) -> Result<PathBuf> {
+        println!("cargo::rerun-if-changed={}", self.input.display());
+
+        self.convert().inspect_err(|e| {
+            // Cargo reads one diagnostic per line
+            let message = format!("{e:#}").replace('\n', " ");
+            println!("cargo::error={message}");
+        })
+    }
+
+    fn convert(self) -> Result<PathBuf> {
+        let out_dir = match self.out_dir {
+            Some(out_dir) => out_dir,
+            None => env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(|| eyre!("OUT_DIR is not set: compile forests from build scripts"))?,
+        };
+        let stem = self
+            .input
+            .file_stem()
+            .ok_or_else(|| eyre!("{} is not a file", self.input.display()))?;
+        let output = out_dir
+            .join(stem)
+            .with_extension(OutputFormat::Rforest.extension());
+
+        let mut artifacts = vec![(OutputFormat::Rforest, output.clone())];
+        if self.rust_module {
+            artifacts.push((
+                OutputFormat::RustModule,
+                output.with_extension(OutputFormat::RustModule.extension()),
+            ));
+        }
+        convert_file(
+            &self.input,
+            None,
+            &self.options,
+            self.encoding.clone(),
+            &artifacts,
+            ForestMetadata::sidecar_path(&output),
+        )
+        .with_context(|| format!("Could not compile forest {}", self.input.display()))?;
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,61 @@
+//! Bundles of serialized forests, read on the device with [`ForestBundle`].
+
+use aligned_vec::AVec;
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::forest::{
+    ForestBundle,
+    bundle::{MAX_NAME_LEN, RawBundleEntry, RawBundleHeader, bundle_header_len},
+    deserialize::{BUFFER_ALIGN, ForestHeader},
+};
+use zerocopy::IntoBytes;
+
+/// Bundle serialized forests into one buffer, in order, each under its
+/// name.
+///
+/// Every forest must have a valid header, and a unique name of at most
+/// [`MAX_NAME_LEN`] bytes.
+pub fn bundle(forests: &[(&str, &[u8])]) -> Result<AVec<u8>> {
+    let num_entries = forests
+        .len()
+        .try_into()
+        .map_err(|_| eyre!("A bundle holds at most {} forests", u16::MAX))?;
+
+    let mut entries = Vec::with_capacity(forests.len());
+    let mut offset = bundle_header_len(forests.len());
+    for (i, &(name, bytes)) in forests.iter().enumerate() {
+        if forests[..i].iter().any(|&(other, _)| other == name) {
+            return Err(eyre!("Forest '{name}' is bundled twice"));
+        }
+        let header = ForestHeader::peek(bytes)
+            .with_context(|| format!("Forest '{name}' is not a serialized forest"))?;
+
+        let entry = RawBundleEntry::new(
+            name,
+            u32::try_from(offset)?,
+            u32::try_from(bytes.len())?,
+            header.problem_kind(),
+        )
+        .ok_or_else(|| {
+            eyre!("Forest name '{name}' must have between 1 and {MAX_NAME_LEN} bytes")
+        })?;
+        entries.push(entry);
+        offset = (offset + bytes.len()).next_multiple_of(BUFFER_ALIGN);
+    }
+
+    let len = u32::try_from(offset).map_err(|_| eyre!("Bundle is larger than 4 GiB"))?;
+    let mut buffer = AVec::with_capacity(BUFFER_ALIGN, offset);
+    buffer.extend_from_slice(RawBundleHeader::new(num_entries, len).as_bytes());
+    buffer.extend_from_slice(entries.as_bytes());
+    for &(_, bytes) in forests {
+        buffer.resize(buffer.len().next_multiple_of(BUFFER_ALIGN), 0);
+        buffer.extend_from_slice(bytes);
+    }
+    buffer.resize(offset, 0);
+
+    ForestBundle::deserialize(&buffer).context("Malformed bundle")?;
+
+    Ok(buffer)
+}
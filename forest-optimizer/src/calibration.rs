@@ -0,0 +1,89 @@
+//! Platt calibration of the class probabilities of a classification forest,
+//! fitted offline and recorded in the serialized forest, see
+//! [`embedded_rforest::forest::calibration`].
+//!
+//! Calibrations are read from JSON files mapping every class name to its
+//! parameters:
+//!
+//! ```json
+//! {
+//!   "setosa": { "a": -6.2, "b": 3.1 },
+//!   "versicolor": { "a": -5.8, "b": 2.9 },
+//!   "virginica": { "a": -6.0, "b": 3.0 }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::forest::Platt;
+
+use crate::{forest::Forest, problem_type::Classification};
+
+/// Parameters of the Platt scaling of one class: its vote fraction `f`
+/// becomes `1 / (1 + exp(a * f + b))`.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlattParameters {
+    pub a: f32,
+    pub b: f32,
+}
+
+/// Platt scaling of every class, by class name.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Calibration {
+    classes: BTreeMap<String, PlattParameters>,
+}
+
+impl Calibration {
+    pub fn new(classes: impl IntoIterator<Item = (String, PlattParameters)>) -> Self {
+        Self {
+            classes: classes.into_iter().collect(),
+        }
+    }
+
+    /// Read a calibration from a JSON file.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read(path)
+            .with_context(|| format!("Could not read calibration file {}", path.display()))?;
+        serde_json::from_slice(&json)
+            .with_context(|| format!("Could not parse calibration file {}", path.display()))
+    }
+
+    /// Parameters of every class of `forest`, in class order. Fails if a
+    /// class of the forest has none, if the calibration names a class the
+    /// forest does not have, or if a parameter is not finite.
+    pub fn for_forest(&self, forest: &Forest<Classification>) -> Result<Vec<Platt>> {
+        if let Some(unknown) = self
+            .classes
+            .keys()
+            .find(|name| !forest.targets().contains(name))
+        {
+            return Err(eyre!("The forest has no class '{unknown}' to calibrate"));
+        }
+
+        (0..forest.num_targets() as u32)
+            .map(|class| {
+                let name = forest
+                    .class_name(class)
+                    .expect("Targets are indexed from 0");
+                let parameters = self
+                    .classes
+                    .get(name)
+                    .ok_or_else(|| eyre!("The calibration has no parameters for class '{name}'"))?;
+                if !(parameters.a.is_finite() && parameters.b.is_finite()) {
+                    return Err(eyre!(
+                        "The calibration parameters of class '{name}' are not finite"
+                    ));
+                }
+                Ok(Platt::new(parameters.a, parameters.b))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,212 @@
+//! Command line interface of the `forest-optimizer` binary.
+//!
+//! Each subcommand lives in its own module, with its arguments and a `run`
+//! function which only glues library calls together.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use color_eyre::{Result, eyre::Context};
+use tracing::level_filters::LevelFilter;
+
+use crate::{
+    metadata::ForestMetadata, problem_type::PredictionType, serialized_forest::resolve_problem_type,
+};
+
+pub mod analyze;
+pub mod bench;
+pub mod bundle;
+pub mod check_agreement;
+pub mod convert;
+pub mod diff;
+pub mod export_test_vectors;
+pub mod info;
+pub mod migrate;
+pub mod prune;
+pub mod quantize;
+pub mod rewrite;
+pub mod sign;
+pub mod validate;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct Cli {
+    #[command(flatten)]
+    pub verbosity: Verbosity,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Convert a forest definition (CSV) into an optimized forest (.rforest)
+    Convert(Box<convert::ConvertArgs>),
+    /// Report how much a forest definition (CSV) shrinks when optimized, or
+    /// what a serialized forest (.rforest) holds
+    Analyze(analyze::AnalyzeArgs),
+    /// Inspect a serialized forest (.rforest) file
+    Info(info::InfoArgs),
+    /// Compare two serialized forest (.rforest) files
+    Diff(diff::DiffArgs),
+    /// Measure the accuracy of a serialized forest against a labeled dataset
+    Validate(validate::ValidateArgs),
+    /// Check that a forest definition (CSV) and its optimized form predict
+    /// what the trainer recorded in a dataset
+    CheckAgreement(check_agreement::CheckAgreementArgs),
+    /// Measure the prediction speed of a serialized forest on this host
+    Bench(bench::BenchArgs),
+    /// Shrink a forest definition (CSV) by removing redundant branches and
+    /// trees, and write it as an optimized forest (.rforest)
+    Prune(prune::PruneArgs),
+    /// Round the thresholds and leaves of a forest definition (CSV) to
+    /// narrower types, and write it as an optimized forest (.rforest)
+    Quantize(quantize::QuantizeArgs),
+    /// Export sample inputs and the outputs a serialized forest predicts for
+    /// them, as fixtures for firmware tests
+    ExportTestVectors(export_test_vectors::ExportTestVectorsArgs),
+    /// Bundle serialized forests (.rforest) into one file, in which the
+    /// device looks them up by name
+    Bundle(bundle::BundleArgs),
+    /// Upgrade a serialized forest (.rforest) of an older format version to
+    /// the current one
+    Migrate(migrate::MigrateArgs),
+    /// Rewrite a serialized forest (.rforest) without some of its optional
+    /// sections
+    Rewrite(rewrite::RewriteArgs),
+    /// Sign a serialized forest (.rforest) with an Ed25519 private key, for
+    /// devices to verify it before loading it
+    Sign(sign::SignArgs),
+}
+
+impl Command {
+    pub fn run(self) -> Result<ExitCode> {
+        match self {
+            Command::Convert(args) => convert::run(*args),
+            Command::Analyze(args) => analyze::run(args),
+            Command::Info(args) => info::run(args),
+            Command::Diff(args) => diff::run(args),
+            Command::Validate(args) => validate::run(args),
+            Command::CheckAgreement(args) => check_agreement::run(args),
+            Command::Bench(args) => bench::run(args),
+            Command::Prune(args) => prune::run(args),
+            Command::Quantize(args) => quantize::run(args),
+            Command::ExportTestVectors(args) => export_test_vectors::run(args),
+            Command::Bundle(args) => bundle::run(args),
+            Command::Migrate(args) => migrate::run(args),
+            Command::Rewrite(args) => rewrite::run(args),
+            Command::Sign(args) => sign::run(args),
+        }
+    }
+}
+
+/// Amount of log messages printed to stderr. Warnings and errors are printed
+/// by default.
+#[derive(Args)]
+pub struct Verbosity {
+    /// Print more log messages: -v for progress, -vv for debugging, -vvv for
+    /// everything
+    #[arg(short = 'v', long = "verbose", action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only print errors
+    #[arg(short = 'q', long = "quiet", global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+impl Verbosity {
+    pub fn level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::ERROR,
+            (false, 0) => LevelFilter::WARN,
+            (false, 1) => LevelFilter::INFO,
+            (false, 2) => LevelFilter::DEBUG,
+            (false, _) => LevelFilter::TRACE,
+        }
+    }
+
+    /// Print the log messages of the library to stderr, up to this level.
+    pub fn init_tracing(&self) {
+        tracing_subscriber::fmt()
+            .with_max_level(self.level())
+            .with_writer(std::io::stderr)
+            .init();
+    }
+}
+
+/// Problem solved by a forest
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ProblemType {
+    Classification,
+    Regression,
+}
+
+impl From<ProblemType> for PredictionType {
+    fn from(problem_type: ProblemType) -> Self {
+        match problem_type {
+            ProblemType::Classification => Self::Classification,
+            ProblemType::Regression => Self::Regression,
+        }
+    }
+}
+
+/// A forest definition file (CSV) to read
+#[derive(Args)]
+pub struct ForestInput {
+    /// Input file
+    #[arg(short = 'i', long = "input", value_name = "INPUT_FILE")]
+    pub input: PathBuf,
+
+    /// Problem type. Detected from the header of the input file if omitted
+    #[arg(short = 'p', long = "problem-type", value_enum)]
+    pub problem_type: Option<ProblemType>,
+}
+
+impl ForestInput {
+    /// Problem type of the input file, taken from `--problem-type` or from the
+    /// file's header. Both must agree if both are present.
+    pub fn resolve_problem_type(&self) -> Result<PredictionType> {
+        resolve_problem_type(&self.input, self.problem_type.map(PredictionType::from))
+    }
+}
+
+/// Comma-separated names, or `@FILE` to read them from a file, separated by
+/// commas or newlines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameList(pub Vec<String>);
+
+impl FromStr for NameList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let names = match s.strip_prefix('@') {
+            Some(path) => {
+                std::fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?
+            }
+            None => s.to_string(),
+        };
+
+        Ok(Self(
+            names
+                .split([',', '\n'])
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect(),
+        ))
+    }
+}
+
+/// Metadata of a serialized forest, needed to map dataset columns onto its
+/// features.
+pub(crate) fn read_metadata(model: &Path) -> Result<ForestMetadata> {
+    let path = ForestMetadata::sidecar_path(model);
+    ForestMetadata::read(&path).with_context(|| {
+        format!(
+            "Feature names are needed to map the dataset columns. Provide the metadata file {}.",
+            path.display()
+        )
+    })
+}
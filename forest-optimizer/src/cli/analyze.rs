@@ -0,0 +1,225 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+
+use super::ForestInput;
+use crate::{
+    analyze::{analyze, analyze_binary, is_binary_forest, verify, write_tree_csv},
+    forest::{
+        cache::read_definition,
+        monotonic::{Direction, Monotonicity},
+        profile::TraversalProfile,
+        stats::{DEFAULT_LEAF_BINS, LeafStats},
+    },
+    inspect::read_model,
+    latency::{LatencyEstimate, node_visits},
+    metadata::ForestMetadata,
+    problem_type::PredictionType,
+    prune::Prune,
+    serialized_forest::SerializedNode,
+    serialized_forest::{SerializedClassificationNode, SerializedRegressionNode},
+};
+
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    #[command(flatten)]
+    pub forest: ForestInput,
+
+    /// Read the input through a cache next to it, written on first use and
+    /// rebuilt whenever the input changes
+    #[arg(long = "cache")]
+    pub cache: bool,
+
+    /// Print forest
+    #[arg(long = "print")]
+    pub print: bool,
+
+    /// Write the size and depth of every tree to this CSV file
+    #[arg(long = "per-tree-csv", value_name = "CSV_FILE")]
+    pub per_tree_csv: Option<PathBuf>,
+
+    /// Write the analysis as JSON to this file
+    #[arg(long = "report-json", value_name = "JSON_FILE")]
+    pub report_json: Option<PathBuf>,
+
+    /// Number of bins of the histogram of the leaf values of a regression
+    /// forest
+    #[arg(
+        long = "leaf-bins",
+        value_name = "BINS",
+        default_value_t = DEFAULT_LEAF_BINS as u16,
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    pub leaf_bins: u16,
+
+    /// Cycles the target takes to visit one branch, for the latency
+    /// estimate. Defaults to a rough figure for the narrowest layout the
+    /// forest fits
+    #[arg(long = "cycles-per-node", value_name = "CYCLES", value_parser = parse_positive)]
+    pub cycles_per_node: Option<f64>,
+
+    /// Clock of the target, in MHz, for the latency estimate [default: 64]
+    #[arg(long = "clock-mhz", value_name = "MHZ", value_parser = parse_positive)]
+    pub clock_mhz: Option<f64>,
+
+    /// Profile (JSON) saved by `convert --save-profile`, weighting the paths
+    /// of the expected latency by how many rows take them
+    #[arg(long = "profile", value_name = "JSON_FILE")]
+    pub profile: Option<PathBuf>,
+
+    /// Labeled dataset (CSV) to score the forest on
+    #[arg(
+        long = "verify-data",
+        value_name = "DATA_FILE",
+        requires = "label_column"
+    )]
+    pub verify_data: Option<PathBuf>,
+
+    /// Column of the --verify-data dataset holding the expected output
+    #[arg(short = 'l', long = "label-column", value_name = "COLUMN")]
+    pub label_column: Option<String>,
+
+    /// Check that the output of a regression forest never decreases (inc)
+    /// or never increases (dec) with these features, e.g. `f1:inc,f2:dec`.
+    /// Exits with an error if a tree breaks the constraint.
+    #[arg(
+        long = "check-monotonic",
+        value_name = "FEATURE:DIRECTION",
+        value_delimiter = ',',
+        value_parser = parse_constraint
+    )]
+    pub check_monotonic: Vec<(String, Direction)>,
+}
+
+pub fn run(args: AnalyzeArgs) -> Result<ExitCode> {
+    if is_binary_forest(&args.forest.input)? {
+        return analyze_binary_file(&args);
+    }
+
+    match args.forest.resolve_problem_type()? {
+        PredictionType::Classification => analyze_file::<SerializedClassificationNode>(&args),
+        PredictionType::Regression => analyze_file::<SerializedRegressionNode>(&args),
+    }
+}
+
+fn analyze_file<N>(args: &AnalyzeArgs) -> Result<ExitCode>
+where
+    N: SerializedNode,
+    N::ProblemType: Prune + LeafStats + Monotonicity,
+{
+    let forest = read_definition::<N>(&args.forest.input, args.cache)?;
+
+    if args.print {
+        println!("Forest: {forest:?}");
+    }
+
+    let mut analysis = analyze(&forest)?;
+    analysis.input = Some(args.forest.input.clone());
+    let leaf_bins = usize::from(args.leaf_bins);
+    if leaf_bins != DEFAULT_LEAF_BINS {
+        analysis.leaf_distribution = N::ProblemType::leaf_distribution(&forest, leaf_bins);
+    }
+    let latency = &analysis.latency;
+    let mut model = latency.model;
+    model.cycles_per_node = args.cycles_per_node.unwrap_or(model.cycles_per_node);
+    model.clock_mhz = args.clock_mhz.unwrap_or(model.clock_mhz);
+    let profile = args
+        .profile
+        .as_ref()
+        .map(TraversalProfile::read)
+        .transpose()?;
+    let visits = match &profile {
+        Some(profile) => node_visits(&forest, Some(profile))?,
+        None => latency.visits,
+    };
+    analysis.latency = LatencyEstimate::new(model, visits, latency.aggregation_cycles);
+    if let Some((data, label_column)) = args.verify_data.as_ref().zip(args.label_column.as_ref()) {
+        analysis.validation = Some(verify(&forest, data, label_column)?);
+    }
+    for (feature, direction) in &args.check_monotonic {
+        let check = N::ProblemType::check_monotonic(&forest, feature, *direction)?;
+        analysis.monotonicity.push(check);
+    }
+    print!("{analysis}");
+
+    if let Some(path) = &args.per_tree_csv {
+        write_tree_csv(&analysis.trees, path)?;
+    }
+
+    if let Some(path) = &args.report_json {
+        let json = serde_json::to_string_pretty(&analysis)?;
+        std::fs::write(path, json + "\n")
+            .with_context(|| format!("Could not write {}", path.display()))?;
+    }
+
+    if analysis.monotonicity.iter().all(|c| c.is_monotonic()) {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Analyze a serialized forest (`.rforest`), which only some of the
+/// options apply to.
+fn analyze_binary_file(args: &AnalyzeArgs) -> Result<ExitCode> {
+    let definition_only = [
+        ("--cache", args.cache),
+        ("--print", args.print),
+        ("--per-tree-csv", args.per_tree_csv.is_some()),
+        ("--verify-data", args.verify_data.is_some()),
+        ("--check-monotonic", !args.check_monotonic.is_empty()),
+        ("--cycles-per-node", args.cycles_per_node.is_some()),
+        ("--clock-mhz", args.clock_mhz.is_some()),
+        ("--profile", args.profile.is_some()),
+    ];
+    if let Some((option, _)) = definition_only.iter().find(|(_, given)| *given) {
+        return Err(eyre!(
+            "{option} needs the forest definition (CSV), not a serialized forest"
+        ));
+    }
+
+    let input = &args.forest.input;
+    let buffer = read_model(input)?;
+    let sidecar = ForestMetadata::sidecar_path(input);
+    let metadata = sidecar
+        .exists()
+        .then(|| ForestMetadata::read(&sidecar))
+        .transpose()?;
+
+    let mut analysis = analyze_binary(&buffer, metadata.as_ref())?;
+    analysis.input = Some(input.clone());
+    print!("{analysis}");
+
+    if let Some(path) = &args.report_json {
+        let json = serde_json::to_string_pretty(&analysis)?;
+        std::fs::write(path, json + "\n")
+            .with_context(|| format!("Could not write {}", path.display()))?;
+    }
+
+    if analysis.info.is_valid() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Parse a `FEATURE:DIRECTION` value of `--check-monotonic`.
+fn parse_constraint(s: &str) -> Result<(String, Direction), String> {
+    let (feature, direction) = s
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected FEATURE:DIRECTION, got '{s}'"))?;
+    Ok((feature.to_string(), direction.parse()?))
+}
+
+/// Parse a strictly positive number of cycles or MHz.
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!("expected a positive number, got '{s}'")),
+    }
+}
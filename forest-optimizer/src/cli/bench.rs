@@ -0,0 +1,792 @@
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Branch, Classification, OptimizedForest, Predict, ProblemKind, ProblemType,
+    Regression, access::CachedNodes, deserialize::ForestHeader, quickscorer::QuickScorer,
+};
+
+use super::read_metadata;
+use crate::{
+    bench::{BenchReport, bench, bench_load, bench_with},
+    dataset::read_mapped_rows,
+    forest::flatten::group_by_tree,
+    inspect::read_model,
+};
+
+/// Branches kept by the cache of `--compare-storage`
+const CACHE_LINES: usize = 64;
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Serialized forest file
+    #[arg(short = 'm', long = "model", value_name = "MODEL")]
+    #[cfg_attr(
+        not(feature = "parallel"),
+        arg(required_unless_present_any = ["optimize", "flatten", "compare_layout"])
+    )]
+    #[cfg_attr(
+        feature = "parallel",
+        arg(required_unless_present_any = [
+            "compare_parallel", "optimize", "flatten", "compare_layout"
+        ])
+    )]
+    pub model: Option<PathBuf>,
+
+    /// Dataset (CSV), with one column per feature
+    #[arg(short = 'd', long = "data", value_name = "DATA_FILE")]
+    #[cfg_attr(
+        not(feature = "parallel"),
+        arg(required_unless_present_any = ["optimize", "flatten"])
+    )]
+    #[cfg_attr(
+        feature = "parallel",
+        arg(required_unless_present_any = ["compare_parallel", "optimize", "flatten"])
+    )]
+    pub data: Option<PathBuf>,
+
+    /// Number of passes over the dataset
+    #[arg(short = 'n', long = "iterations", default_value_t = 100)]
+    pub iterations: usize,
+
+    /// Print the report as JSON
+    #[arg(long = "json")]
+    pub json: bool,
+
+    /// Also bench the forest converted to the experimental
+    /// structure-of-arrays layout. Standard layout only
+    #[cfg(feature = "soa")]
+    #[arg(long = "compare-soa")]
+    pub compare_soa: bool,
+
+    /// Also bench the forest reading its branches through a cache, as
+    /// from external flash, rather than straight from memory. Standard
+    /// layout only
+    #[arg(long = "compare-storage")]
+    pub compare_storage: bool,
+
+    /// Also bench the forest evaluated by QuickScorer, testing the
+    /// conditions of every tree feature by feature rather than walking the
+    /// trees. Standard layout only
+    #[arg(long = "compare-quickscorer")]
+    pub compare_quickscorer: bool,
+
+    /// Most leaves a tree may have for `--compare-quickscorer`
+    #[arg(
+        long = "qs-max-leaves",
+        default_value_t = 256,
+        requires = "compare_quickscorer"
+    )]
+    pub qs_max_leaves: usize,
+
+    /// Time loading the forest with `deserialize`, and with
+    /// `deserialize_unchecked`, which skips validating its nodes, instead of
+    /// predicting. Standard layout only
+    #[arg(long = "compare-load")]
+    pub compare_load: bool,
+
+    /// Time optimizing the nodes of a forest definition (CSV), and report the
+    /// memory the pass allocates next to the forest, instead of predicting
+    #[arg(long = "optimize", value_name = "DEFINITION")]
+    pub optimize: Option<PathBuf>,
+
+    /// Time grouping the rows of a forest definition (CSV) by tree and
+    /// normalizing them, instead of predicting
+    #[arg(long = "flatten", value_name = "DEFINITION")]
+    pub flatten: Option<PathBuf>,
+
+    /// Repeat the trees of the definition of `--flatten` this many times, to
+    /// time larger forests
+    #[arg(long = "repeat", default_value_t = 1, requires = "flatten")]
+    pub repeat: usize,
+
+    /// Bench a forest definition (CSV) converted with its branches in
+    /// depth-first order, and in the order the rows of --data profile,
+    /// instead of a serialized forest
+    #[arg(long = "compare-layout", value_name = "DEFINITION", requires = "data")]
+    pub compare_layout: Option<PathBuf>,
+
+    /// Time converting a forest definition (CSV) on one thread and on every
+    /// thread, instead of predicting
+    #[cfg(feature = "parallel")]
+    #[arg(long = "compare-parallel", value_name = "DEFINITION")]
+    pub compare_parallel: Option<PathBuf>,
+}
+
+pub fn run(args: BenchArgs) -> Result<ExitCode> {
+    #[cfg(feature = "parallel")]
+    if let Some(definition) = &args.compare_parallel {
+        let comparison = compare_parallel(definition, args.iterations)?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&comparison)?);
+        } else {
+            print!("{comparison}");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(definition) = &args.optimize {
+        let report = bench_optimize(definition, args.iterations)?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{report}");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(definition) = &args.flatten {
+        let report = bench_flatten(definition, args.repeat, args.iterations)?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{report}");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let (Some(definition), Some(data)) = (&args.compare_layout, &args.data) {
+        let comparison = compare_layout(definition, data, args.iterations)?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&comparison)?);
+        } else {
+            print!("{comparison}");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Required by clap, unless timing conversions
+    let (Some(model), Some(data)) = (&args.model, &args.data) else {
+        return Err(eyre!("Both --model and --data are required"));
+    };
+    let buffer = read_model(model)?;
+    let header = ForestHeader::peek(&buffer).context("Malformed forest")?;
+    let metadata = read_metadata(model)?;
+    let rows = read_mapped_rows(data, &metadata.features)?;
+
+    #[cfg(feature = "soa")]
+    if args.compare_soa {
+        let reports = compare_soa(&buffer, header.problem_kind(), &rows, args.iterations)?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        } else {
+            print!("{reports}");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.compare_storage {
+        let reports = compare_storage(&buffer, header, &rows, args.iterations)?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        } else {
+            print!("{reports}");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.compare_quickscorer {
+        let reports = compare_quickscorer(
+            &buffer,
+            header.problem_kind(),
+            &rows,
+            args.iterations,
+            args.qs_max_leaves,
+        )?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        } else {
+            print!("{reports}");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.compare_load {
+        let comparison = compare_load(&buffer, header.problem_kind(), args.iterations)?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&comparison)?);
+        } else {
+            print!("{comparison}");
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let report = match header.problem_kind() {
+        ProblemKind::Classification => {
+            let forest = AnyOptimizedForest::<Classification>::deserialize(&buffer)
+                .context("Malformed forest")?;
+            bench(&forest, &rows, args.iterations)
+        }
+        ProblemKind::Regression => {
+            let forest = AnyOptimizedForest::<Regression>::deserialize(&buffer)
+                .context("Malformed forest")?;
+            bench(&forest, &rows, args.iterations)
+        }
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Bench a standard forest, and the same forest in the structure-of-arrays
+/// layout, on the same rows.
+#[cfg(feature = "soa")]
+fn compare_soa(
+    buffer: &[u8],
+    kind: ProblemKind,
+    rows: &[Vec<f32>],
+    iterations: usize,
+) -> Result<SoAComparison> {
+    use embedded_rforest::forest::{OptimizedForest, soa::SoAForest};
+
+    fn compare<P: ProblemType>(
+        buffer: &[u8],
+        rows: &[Vec<f32>],
+        iterations: usize,
+    ) -> Result<SoAComparison>
+    where
+        for<'a> OptimizedForest<'a, P>: Predict<ProblemType = P>,
+        for<'a> SoAForest<'a, P>: Predict<ProblemType = P>,
+    {
+        let aos = OptimizedForest::<P>::deserialize(buffer)
+            .context("--compare-soa needs a forest of standard layout")?;
+        let soa = aos.to_soa_bytes();
+        let soa = SoAForest::<P>::deserialize(&soa).context("Malformed forest")?;
+
+        Ok(SoAComparison {
+            aos: bench(&aos, rows, iterations),
+            soa: bench(&soa, rows, iterations),
+        })
+    }
+
+    match kind {
+        ProblemKind::Classification => compare::<Classification>(buffer, rows, iterations),
+        ProblemKind::Regression => compare::<Regression>(buffer, rows, iterations),
+    }
+}
+
+#[cfg(feature = "soa")]
+#[derive(serde::Serialize)]
+struct SoAComparison {
+    aos: BenchReport,
+    soa: BenchReport,
+}
+
+#[cfg(feature = "soa")]
+impl std::fmt::Display for SoAComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Array of structures")?;
+        write!(f, "{}", self.aos)?;
+        writeln!(f, "\nStructure of arrays (experimental)")?;
+        write!(f, "{}", self.soa)?;
+        writeln!(
+            f,
+            "\nSpeedup:        {:.2}x",
+            self.aos.mean_ns / self.soa.mean_ns
+        )
+    }
+}
+
+/// Bench a standard forest reading its node array from memory, and the same
+/// forest reading it through a [`CachedNodes`], on the same rows.
+fn compare_storage(
+    buffer: &[u8],
+    header: ForestHeader,
+    rows: &[Vec<f32>],
+    iterations: usize,
+) -> Result<StorageComparison> {
+    type Cached<'a> = CachedNodes<&'a [u8], Branch, CACHE_LINES>;
+
+    fn compare<P: ProblemType>(
+        buffer: &[u8],
+        header: ForestHeader,
+        rows: &[Vec<f32>],
+        iterations: usize,
+    ) -> Result<StorageComparison>
+    where
+        for<'a> OptimizedForest<'a, P>: Predict<ProblemType = P>,
+        for<'a, 'b> OptimizedForest<'a, P, Branch, Cached<'b>>: Predict<ProblemType = P>,
+    {
+        let forest = OptimizedForest::<P>::deserialize(buffer)
+            .context("--compare-storage needs a forest of standard layout")?;
+        let slice = bench(&forest, rows, iterations);
+
+        let nodes = &buffer[header.header_len..][..header.node_count * size_of::<Branch>()];
+        let cached = Cached::new(nodes, header.node_count);
+        let forest = forest
+            .with_node_access(&cached)
+            .context("Malformed forest")?;
+
+        Ok(StorageComparison {
+            slice,
+            cached: bench(&forest, rows, iterations),
+        })
+    }
+
+    match header.problem_kind() {
+        ProblemKind::Classification => compare::<Classification>(buffer, header, rows, iterations),
+        ProblemKind::Regression => compare::<Regression>(buffer, header, rows, iterations),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct StorageComparison {
+    slice: BenchReport,
+    cached: BenchReport,
+}
+
+impl std::fmt::Display for StorageComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Node array in memory")?;
+        write!(f, "{}", self.slice)?;
+        writeln!(f, "\nCached reads, {CACHE_LINES} lines")?;
+        write!(f, "{}", self.cached)?;
+        writeln!(
+            f,
+            "\nSlowdown:       {:.2}x",
+            self.cached.mean_ns / self.slice.mean_ns
+        )
+    }
+}
+
+/// Bench a standard forest walking its trees, and evaluated by
+/// [`QuickScorer`], on the same rows.
+fn compare_quickscorer(
+    buffer: &[u8],
+    kind: ProblemKind,
+    rows: &[Vec<f32>],
+    iterations: usize,
+    max_leaves: usize,
+) -> Result<QuickScorerComparison> {
+    let malformed = "--compare-quickscorer needs a forest of standard layout";
+    let prepare = "Could not prepare the forest for QuickScorer";
+
+    let (walk, quickscorer) = match kind {
+        ProblemKind::Classification => {
+            let forest =
+                OptimizedForest::<Classification>::deserialize(buffer).context(malformed)?;
+            let scorer = QuickScorer::new(&forest, max_leaves).context(prepare)?;
+            (
+                bench(&forest, rows, iterations),
+                bench_with(
+                    rows,
+                    iterations,
+                    |row| scorer.predict_qs(row),
+                    |row| scorer.predict_qs_counting(row).1,
+                ),
+            )
+        }
+        ProblemKind::Regression => {
+            let forest = OptimizedForest::<Regression>::deserialize(buffer).context(malformed)?;
+            let scorer = QuickScorer::new(&forest, max_leaves).context(prepare)?;
+            (
+                bench(&forest, rows, iterations),
+                bench_with(
+                    rows,
+                    iterations,
+                    |row| scorer.predict_qs(row),
+                    |row| scorer.predict_qs_counting(row).1,
+                ),
+            )
+        }
+    };
+
+    Ok(QuickScorerComparison { walk, quickscorer })
+}
+
+#[derive(serde::Serialize)]
+struct QuickScorerComparison {
+    walk: BenchReport,
+    /// Counts false conditions in place of branch visits
+    quickscorer: BenchReport,
+}
+
+impl std::fmt::Display for QuickScorerComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Tree walks")?;
+        write!(f, "{}", self.walk)?;
+        writeln!(f, "\nQuickScorer (branch visits are false conditions)")?;
+        write!(f, "{}", self.quickscorer)?;
+        writeln!(
+            f,
+            "\nSpeedup:        {:.2}x",
+            self.quickscorer.throughput / self.walk.throughput
+        )
+    }
+}
+
+/// Time loading a standard forest with and without validating its nodes.
+fn compare_load(buffer: &[u8], kind: ProblemKind, iterations: usize) -> Result<LoadComparison> {
+    fn compare<P: ProblemType>(buffer: &[u8], iterations: usize) -> Result<LoadComparison> {
+        OptimizedForest::<P>::deserialize(buffer)
+            .context("--compare-load needs a valid forest of standard layout")?;
+
+        Ok(LoadComparison {
+            checked_ns: bench_load(iterations, || {
+                OptimizedForest::<P>::deserialize(black_box(buffer))
+            }),
+            // SAFETY: the forest was just validated by `deserialize`
+            unchecked_ns: bench_load(iterations, || unsafe {
+                OptimizedForest::<P>::deserialize_unchecked(black_box(buffer))
+            }),
+        })
+    }
+
+    match kind {
+        ProblemKind::Classification => compare::<Classification>(buffer, iterations),
+        ProblemKind::Regression => compare::<Regression>(buffer, iterations),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct LoadComparison {
+    /// Mean time of `deserialize`, in nanoseconds
+    checked_ns: f64,
+    /// Mean time of `deserialize_unchecked`, in nanoseconds
+    unchecked_ns: f64,
+}
+
+impl std::fmt::Display for LoadComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Checked load:   {:.1} ns", self.checked_ns)?;
+        writeln!(f, "Unchecked load: {:.1} ns", self.unchecked_ns)?;
+        writeln!(
+            f,
+            "Speedup:        {:.2}x",
+            self.checked_ns / self.unchecked_ns
+        )
+    }
+}
+
+/// Bench the forest definition at `path` serialized with its branches in
+/// depth-first order, and in the order [`Forest::profile`] gives on the rows
+/// of `data`, on those rows.
+///
+/// [`Forest::profile`]: crate::forest::Forest::profile
+fn compare_layout(
+    path: &std::path::Path,
+    data: &std::path::Path,
+    iterations: usize,
+) -> Result<LayoutComparison> {
+    use crate::{
+        forest::Forest,
+        problem_type::PredictionType,
+        serialized_forest::{
+            SerializedClassificationNode, SerializedForest, SerializedNode,
+            SerializedRegressionNode, resolve_problem_type,
+        },
+        write_forest::{PointerWidth, WriteForest},
+    };
+
+    type Optimized<N> =
+        <<N as SerializedNode>::ProblemType as crate::problem_type::ProblemType>::OptimizedType;
+
+    fn compare<N: SerializedNode>(
+        path: &std::path::Path,
+        data: &std::path::Path,
+        iterations: usize,
+    ) -> Result<LayoutComparison>
+    where
+        N::ProblemType: WriteForest,
+        for<'a> OptimizedForest<'a, Optimized<N>>: Predict,
+    {
+        let forest = Forest::from_serialized(SerializedForest::<N>::read(path)?)?;
+        let rows = read_mapped_rows(data, &forest.features_by_index())?;
+        let profile = forest.profile(&rows);
+
+        let depth_first = N::ProblemType::serialize(&forest)?;
+        let profiled = N::ProblemType::serialize_ordered(
+            &forest,
+            PointerWidth::U32,
+            &profile.hot_right(&forest)?,
+        )?;
+        let bench_bytes = |bytes| {
+            OptimizedForest::<Optimized<N>>::deserialize(bytes)
+                .map(|forest| bench(&forest, &rows, iterations))
+                .context("Malformed forest")
+        };
+
+        Ok(LayoutComparison {
+            depth_first: bench_bytes(&depth_first)?,
+            profiled: bench_bytes(&profiled)?,
+            depth_first_sequential: profile.sequential_share(false),
+            profiled_sequential: profile.sequential_share(true),
+        })
+    }
+
+    match resolve_problem_type(path, None)? {
+        PredictionType::Classification => {
+            compare::<SerializedClassificationNode>(path, data, iterations)
+        }
+        PredictionType::Regression => compare::<SerializedRegressionNode>(path, data, iterations),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct LayoutComparison {
+    depth_first: BenchReport,
+    profiled: BenchReport,
+    /// Share of the branch visits going to the next branch in memory, in
+    /// depth-first order
+    depth_first_sequential: f64,
+    /// Share of the branch visits going to the next branch in memory, in
+    /// profiled order
+    profiled_sequential: f64,
+}
+
+impl std::fmt::Display for LayoutComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Depth first")?;
+        write!(f, "{}", self.depth_first)?;
+        writeln!(
+            f,
+            "Sequential:     {:.1}% of visits",
+            self.depth_first_sequential * 100.0
+        )?;
+        writeln!(f, "\nProfiled")?;
+        write!(f, "{}", self.profiled)?;
+        writeln!(
+            f,
+            "Sequential:     {:.1}% of visits",
+            self.profiled_sequential * 100.0
+        )?;
+        writeln!(
+            f,
+            "\nSpeedup:        {:.2}x",
+            self.profiled.throughput / self.depth_first.throughput
+        )
+    }
+}
+
+/// Time [`Forest::optimize_nodes`](crate::forest::Forest::optimize_nodes) on
+/// the forest definition at `path`.
+fn bench_optimize(path: &std::path::Path, iterations: usize) -> Result<OptimizeReport> {
+    use crate::{
+        conversion::ToOptimized,
+        forest::Forest,
+        problem_type::PredictionType,
+        serialized_forest::{
+            SerializedClassificationNode, SerializedForest, SerializedNode,
+            SerializedRegressionNode, resolve_problem_type,
+        },
+    };
+
+    fn report<N: SerializedNode>(
+        path: &std::path::Path,
+        iterations: usize,
+    ) -> Result<OptimizeReport>
+    where
+        N::ProblemType: ToOptimized,
+    {
+        let forest = Forest::from_serialized(SerializedForest::<N>::read(path)?)?;
+        let (branches, leaves) = forest.optimize_nodes();
+
+        Ok(OptimizeReport {
+            nodes: forest.nodes().len(),
+            branches: branches.len(),
+            forest_bytes: size_of_val(forest.nodes()),
+            // A branch id per node, then the branches and leaf values
+            allocated_bytes: forest.nodes().len() * size_of::<u32>()
+                + size_of_val(&branches[..])
+                + size_of_val(&leaves[..]),
+            mean_ns: bench_load(iterations, || black_box(&forest).optimize_nodes()),
+        })
+    }
+
+    match resolve_problem_type(path, None)? {
+        PredictionType::Classification => report::<SerializedClassificationNode>(path, iterations),
+        PredictionType::Regression => report::<SerializedRegressionNode>(path, iterations),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct OptimizeReport {
+    /// Nodes of the forest
+    nodes: usize,
+    /// Branches of the optimized forest
+    branches: usize,
+    /// Size of the nodes of the forest, in bytes
+    forest_bytes: usize,
+    /// Size of what optimizing the nodes allocates, in bytes, besides the
+    /// index of the leaf table from values to their position
+    allocated_bytes: usize,
+    /// Mean time of an optimization, in nanoseconds
+    mean_ns: f64,
+}
+
+impl std::fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Nodes:          {}", self.nodes)?;
+        writeln!(f, "Branches:       {}", self.branches)?;
+        writeln!(f, "Forest:         {} bytes", self.forest_bytes)?;
+        writeln!(
+            f,
+            "Allocated:      {} bytes ({:.2}x the forest)",
+            self.allocated_bytes,
+            self.allocated_bytes as f64 / self.forest_bytes as f64
+        )?;
+        writeln!(f, "Mean time:      {:.3} ms", self.mean_ns / 1e6)
+    }
+}
+
+/// Time [`group_by_tree`] then [`GroupedTrees::normalize`] on the forest
+/// definition at `path`, its trees repeated `repeat` times.
+///
+/// [`GroupedTrees::normalize`]: crate::forest::flatten::GroupedTrees::normalize
+fn bench_flatten(
+    path: &std::path::Path,
+    repeat: usize,
+    iterations: usize,
+) -> Result<FlattenReport> {
+    use crate::{
+        forest::{Forest, flatten::NodeHoles},
+        problem_type::PredictionType,
+        serialized_forest::{
+            SerializedClassificationNode, SerializedForest, SerializedNode,
+            SerializedRegressionNode, resolve_problem_type,
+        },
+    };
+
+    fn report<N: SerializedNode>(
+        path: &std::path::Path,
+        repeat: usize,
+        iterations: usize,
+    ) -> Result<FlattenReport> {
+        let forest = Forest::from_serialized(SerializedForest::<N>::read(path)?)?;
+        let trees = forest
+            .trees()
+            .map(|tree| tree.to_nodes())
+            .collect::<Vec<_>>();
+        let trees = trees
+            .iter()
+            .cycle()
+            .take(trees.len() * repeat)
+            .cloned()
+            .collect();
+        let serialized = SerializedForest::<N>::from_forest(&Forest::from_trees(
+            trees,
+            forest.problem().clone(),
+        ));
+        let flatten = || {
+            group_by_tree(black_box(serialized.nodes()))
+                .normalize(serialized.problem(), NodeHoles::Reject)
+        };
+
+        Ok(FlattenReport {
+            trees: flatten()?.len(),
+            nodes: serialized.nodes().len(),
+            mean_ns: bench_load(iterations, flatten),
+        })
+    }
+
+    match resolve_problem_type(path, None)? {
+        PredictionType::Classification => {
+            report::<SerializedClassificationNode>(path, repeat, iterations)
+        }
+        PredictionType::Regression => report::<SerializedRegressionNode>(path, repeat, iterations),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FlattenReport {
+    /// Trees of the forest, with their repeats
+    trees: usize,
+    /// Rows of the forest definition, with the repeated trees
+    nodes: usize,
+    /// Mean time of grouping and normalizing the rows, in nanoseconds
+    mean_ns: f64,
+}
+
+impl std::fmt::Display for FlattenReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Trees:          {}", self.trees)?;
+        writeln!(f, "Rows:           {}", self.nodes)?;
+        writeln!(f, "Mean time:      {:.3} ms", self.mean_ns / 1e6)
+    }
+}
+
+/// Time converting the forest definition at `path` with a pool of one thread,
+/// and with the global pool of every thread.
+#[cfg(feature = "parallel")]
+fn compare_parallel(path: &std::path::Path, iterations: usize) -> Result<ParallelComparison> {
+    use crate::{
+        forest::Forest,
+        problem_type::PredictionType,
+        serialized_forest::{
+            SerializedClassificationNode, SerializedForest, SerializedNode,
+            SerializedRegressionNode, resolve_problem_type,
+        },
+    };
+
+    fn compare<N: SerializedNode>(
+        path: &std::path::Path,
+        iterations: usize,
+    ) -> Result<ParallelComparison> {
+        let definition = std::fs::read(path)?;
+        let convert = || -> Result<Forest<N::ProblemType>> {
+            let serialized = SerializedForest::<N>::from_reader(black_box(&definition[..]))?;
+            Forest::from_serialized(serialized)
+        };
+        convert()?;
+
+        let serial = rayon::ThreadPoolBuilder::new().num_threads(1).build()?;
+        Ok(ParallelComparison {
+            threads: rayon::current_num_threads(),
+            serial_ns: serial.install(|| bench_load(iterations, convert)),
+            parallel_ns: bench_load(iterations, convert),
+        })
+    }
+
+    match resolve_problem_type(path, None)? {
+        PredictionType::Classification => compare::<SerializedClassificationNode>(path, iterations),
+        PredictionType::Regression => compare::<SerializedRegressionNode>(path, iterations),
+    }
+}
+
+#[cfg(feature = "parallel")]
+#[derive(serde::Serialize)]
+struct ParallelComparison {
+    /// Threads of the global pool
+    threads: usize,
+    /// Mean time of a conversion on one thread, in nanoseconds
+    serial_ns: f64,
+    /// Mean time of a conversion on every thread, in nanoseconds
+    parallel_ns: f64,
+}
+
+#[cfg(feature = "parallel")]
+impl std::fmt::Display for ParallelComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "One thread:     {:.3} ms", self.serial_ns / 1e6)?;
+        writeln!(
+            f,
+            "{:<15} {:.3} ms",
+            format!("{} threads:", self.threads),
+            self.parallel_ns / 1e6
+        )?;
+        writeln!(
+            f,
+            "Speedup:        {:.2}x",
+            self.serial_ns / self.parallel_ns
+        )
+    }
+}
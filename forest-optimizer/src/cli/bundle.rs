@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use clap::Args;
+use color_eyre::{Result, eyre::Context};
+use embedded_rforest::forest::deserialize::ForestHeader;
+
+use crate::{bundle::bundle, inspect::read_model, problem_type::PredictionType};
+
+#[derive(Args)]
+pub struct BundleArgs {
+    /// Serialized forest to bundle, as NAME=FILE, or FILE to name it after
+    /// the file stem. May be repeated; forests are bundled in order
+    #[arg(
+        short = 'i',
+        long = "input",
+        value_name = "[NAME=]MODEL",
+        required = true
+    )]
+    pub inputs: Vec<BundleInput>,
+
+    /// Bundle file to write
+    #[arg(short = 'o', long = "output", value_name = "OUTPUT_FILE")]
+    pub output: PathBuf,
+}
+
+/// A forest of a bundle, and its name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleInput {
+    pub name: String,
+    pub model: PathBuf,
+}
+
+impl FromStr for BundleInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, model)) => Ok(Self {
+                name: name.to_string(),
+                model: model.into(),
+            }),
+            None => {
+                let model = PathBuf::from(s);
+                let name = model
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .ok_or_else(|| format!("Cannot name the forest {s}, use NAME=FILE"))?;
+                Ok(Self {
+                    name: name.to_string(),
+                    model,
+                })
+            }
+        }
+    }
+}
+
+pub fn run(args: BundleArgs) -> Result<ExitCode> {
+    let models = args
+        .inputs
+        .iter()
+        .map(|input| read_model(&input.model))
+        .collect::<Result<Vec<_>>>()?;
+    let forests = args
+        .inputs
+        .iter()
+        .zip(&models)
+        .map(|(input, model)| (input.name.as_str(), model.as_slice()))
+        .collect::<Vec<_>>();
+
+    let bundled = bundle(&forests)?;
+    std::fs::write(&args.output, &bundled[..]).context("Could not write bundle file")?;
+
+    for (name, model) in &forests {
+        let header = ForestHeader::peek(model).expect("Bundled forests have valid headers");
+        println!(
+            "{name:<32} {:<14} {:>10} bytes",
+            PredictionType::from(header.problem_kind()),
+            model.len()
+        );
+    }
+    println!("Wrote {} bytes to {}", bundled.len(), args.output.display());
+
+    Ok(ExitCode::SUCCESS)
+}
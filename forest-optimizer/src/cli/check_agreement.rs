@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::{Result, eyre::Context};
+
+use super::ForestInput;
+use crate::{
+    agreement::check_agreement,
+    forest::Forest,
+    problem_type::PredictionType,
+    serialized_forest::{SerializedClassificationNode, SerializedRegressionNode},
+    serialized_forest::{SerializedForest, SerializedNode},
+};
+
+#[derive(Args)]
+pub struct CheckAgreementArgs {
+    #[command(flatten)]
+    pub forest: ForestInput,
+
+    /// Dataset (CSV), with one column per feature and the predictions of the
+    /// trainer
+    #[arg(short = 'd', long = "data", value_name = "DATA_FILE")]
+    pub data: PathBuf,
+
+    /// Dataset column holding the predictions of the trainer
+    #[arg(
+        long = "prediction-column",
+        value_name = "COLUMN",
+        default_value = "Predicted"
+    )]
+    pub prediction_column: String,
+
+    /// Largest difference between two regression predictions which still
+    /// agree
+    #[arg(long = "tolerance", value_name = "X", default_value_t = 0.0)]
+    pub tolerance: f64,
+
+    /// Number of disagreeing rows to list
+    #[arg(long = "max-rows", value_name = "K", default_value_t = 10)]
+    pub max_rows: usize,
+}
+
+pub fn run(args: CheckAgreementArgs) -> Result<ExitCode> {
+    let agreement = match args.forest.resolve_problem_type()? {
+        PredictionType::Classification => check_agreement(
+            &read_forest::<SerializedClassificationNode>(&args.forest.input)?,
+            &args.data,
+            &args.prediction_column,
+            args.tolerance,
+            args.max_rows,
+        )?,
+        PredictionType::Regression => check_agreement(
+            &read_forest::<SerializedRegressionNode>(&args.forest.input)?,
+            &args.data,
+            &args.prediction_column,
+            args.tolerance,
+            args.max_rows,
+        )?,
+    };
+    print!("{agreement}");
+
+    Ok(if agreement.is_unanimous() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn read_forest<N: SerializedNode>(path: &Path) -> Result<Forest<N::ProblemType>> {
+    let serialized = SerializedForest::<N>::read(path)
+        .context("Could not read forest definition file (CSV).")?;
+    serialized.check()?;
+    Forest::from_serialized(serialized)
+}
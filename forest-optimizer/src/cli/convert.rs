@@ -0,0 +1,510 @@
+use std::fs::File;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, ValueEnum};
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::forest::Transform;
+
+use super::{NameList, ProblemType};
+use crate::{
+    analyze::estimate_file,
+    batch::convert_dir,
+    calibration::Calibration,
+    compact::compact_file,
+    emit::{FormatSpec, OutputFormat, artifact_paths, check_overwrite},
+    encryption::EncryptionKey,
+    forest::profile::TraversalProfile,
+    integer::integer_file,
+    metadata::ForestMetadata,
+    problem_type::PredictionType,
+    serialized_forest::{IndexOrder, NameOrder, ReadOptions},
+    write_forest::{
+        Destination, Endianness, NodeEncoding, NodeOrder, PointerWidth, ProfileSource,
+        convert_file, convert_reader,
+    },
+};
+
+/// Path standing for stdin as input, and stdout as output
+const STDIO: &str = "-";
+
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// Input file, or `-` for stdin
+    #[arg(
+        short = 'i',
+        long = "input",
+        value_name = "INPUT_FILE",
+        required_unless_present = "input_dir"
+    )]
+    pub input: Option<PathBuf>,
+
+    /// Problem type. Detected from the header of the input file if omitted
+    #[arg(short = 'p', long = "problem-type", value_enum)]
+    pub problem_type: Option<ProblemType>,
+
+    /// Feature names in the order of their indices, instead of the order
+    /// they appear in the input. Comma-separated, or `@FILE`
+    #[arg(long = "feature-order", value_name = "NAMES")]
+    pub feature_order: Option<NameList>,
+
+    /// Target names in the order of their indices, for classification.
+    /// Comma-separated, or `@FILE`
+    #[arg(long = "target-order", value_name = "NAMES")]
+    pub target_order: Option<NameList>,
+
+    /// Order of the feature and target indices not pinned by
+    /// --feature-order or --target-order: encounter, the order names appear
+    /// in the input, or alphabetical
+    #[arg(long = "order", value_enum, default_value = "encounter")]
+    pub order: NameOrder,
+
+    /// Only warn when the trees, features or targets of the input do not
+    /// match the counts its header declares, instead of failing
+    #[arg(long = "allow-count-mismatch")]
+    pub allow_count_mismatch: bool,
+
+    /// Read the split var column as indices of the columns of the training
+    /// data, from 1, like R's `getTree(..., labelVar = FALSE)` writes them.
+    /// Declared in the header by `"split_var": "index"`
+    #[arg(long = "split-var-as-index")]
+    pub split_var_as_index: bool,
+
+    /// Names of the columns of the training data, to name the features
+    /// split vars index. Comma-separated, or `@FILE`. Defaults to the
+    /// `"feature_names"` of the header, then to --feature-order
+    #[arg(long = "feature-names", value_name = "NAMES")]
+    pub feature_names: Option<NameList>,
+
+    /// Output file, or `-` for stdout. With several formats, artifacts are
+    /// written next to it with the extension of their format
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "OUTPUT_FILE",
+        required_unless_present_any = ["output_dir", "dry_run"]
+    )]
+    pub output: Option<PathBuf>,
+
+    /// Convert every `*.csv` file of this directory
+    #[arg(
+        long = "input-dir",
+        value_name = "INPUT_DIR",
+        conflicts_with_all = ["input", "output"],
+        requires = "output_dir"
+    )]
+    pub input_dir: Option<PathBuf>,
+
+    /// Directory to write the converted files of --input-dir to
+    #[arg(long = "output-dir", value_name = "OUTPUT_DIR", requires = "input_dir")]
+    pub output_dir: Option<PathBuf>,
+
+    /// Number of files of --input-dir to convert in parallel
+    #[arg(short = 'j', long = "jobs", default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Output format: rforest, c-header, rust-module, json, rust-indices or
+    /// c-indices, optionally followed by `=PATH`. May be repeated. Defaults
+    /// to rforest
+    #[arg(short = 'f', long = "format", value_name = "FORMAT[=PATH]")]
+    pub formats: Vec<FormatSpec>,
+
+    /// Also write the indices of the features and classes as constants, in
+    /// rust (`<stem>_indices.rs`), c (`<stem>_indices.h`) or both, next to
+    /// the output
+    #[arg(long = "emit-index-consts", value_enum, value_name = "LANGUAGE")]
+    pub emit_index_consts: Option<IndexLanguage>,
+
+    /// Report the size of the optimized forest without writing anything
+    #[arg(long = "dry-run", conflicts_with = "input_dir")]
+    pub dry_run: bool,
+
+    /// With --dry-run, fail if the optimized forest is larger than this
+    #[arg(long = "max-size-bytes", value_name = "BYTES", requires = "dry_run")]
+    pub max_size_bytes: Option<usize>,
+
+    /// Overwrite existing output files, and write a binary forest to stdout
+    /// even if it is a terminal
+    #[arg(long = "force")]
+    pub force: bool,
+
+    /// Width of the child pointers of branches: 32 (16-byte branches), 16
+    /// (12-byte branches, up to 32768 branches), relative (16 bits relative
+    /// to each tree, up to 32768 branches per tree) or auto, the narrowest
+    /// that fits the forest
+    #[arg(
+        long = "pointer-width",
+        value_enum,
+        default_value = "32",
+        conflicts_with = "layout_pass"
+    )]
+    pub pointer_width: PointerWidth,
+
+    /// Byte order of the branches: little, read by default on every
+    /// target, or big, for big-endian targets reading forests with the
+    /// `native-endian` feature
+    #[arg(
+        long = "endianness",
+        value_enum,
+        default_value = "little",
+        conflicts_with = "layout_pass"
+    )]
+    pub endianness: Endianness,
+
+    /// Transform of the output of a regression forest, applied on the device
+    /// once its trees are combined: identity, sigmoid, exp, or
+    /// `custom=SCALE,OFFSET` for `SCALE * x + OFFSET`
+    #[arg(
+        long = "transform",
+        value_name = "TRANSFORM",
+        default_value = "identity",
+        value_parser = parse_transform,
+        conflicts_with = "layout_pass"
+    )]
+    pub transform: Transform,
+
+    /// JSON file of the Platt parameters of every class of a classification
+    /// forest, `{"class": {"a": A, "b": B}, ...}`, applied on the device to
+    /// the probabilities of `predict_proba`
+    #[arg(
+        long = "calibration",
+        value_name = "JSON_FILE",
+        conflicts_with = "layout_pass"
+    )]
+    pub calibration: Option<PathBuf>,
+
+    /// Take the median of the classes the trees vote for, rather than the
+    /// class with the most votes, for classes with a natural order such as
+    /// severity levels. The order is the one --target-order pins
+    #[arg(
+        long = "ordinal",
+        requires = "target_order",
+        conflicts_with = "layout_pass"
+    )]
+    pub ordinal: bool,
+
+    /// Record the number of training samples of every leaf, from the sample
+    /// count column of the input, so that the device can tell how many
+    /// samples support each prediction
+    #[arg(long = "support", conflicts_with = "layout_pass")]
+    pub support: bool,
+
+    /// Encrypt the nodes, leaf table and support table with this 256-bit
+    /// key, 64 hex digits or `@FILE` holding them, for devices to decrypt
+    /// with `OptimizedForest::deserialize_encrypted`. The header stays
+    /// readable
+    #[arg(
+        long = "encrypt-key",
+        value_name = "HEX",
+        conflicts_with = "layout_pass"
+    )]
+    pub encrypt_key: Option<EncryptionKey>,
+
+    /// Order of the branches of every tree: depth-first, every branch
+    /// followed by its left subtree, or profiled, by the subtree most rows
+    /// of --profile-data or --profile go to
+    #[arg(
+        long = "layout",
+        value_enum,
+        default_value = "depth-first",
+        conflicts_with = "layout_pass"
+    )]
+    pub layout: Layout,
+
+    /// With `--layout profiled`, dataset (CSV) whose rows are counted at
+    /// every branch
+    #[arg(
+        long = "profile-data",
+        value_name = "DATA_FILE",
+        conflicts_with = "profile"
+    )]
+    pub profile_data: Option<PathBuf>,
+
+    /// With `--layout profiled`, profile (JSON) saved by --save-profile for
+    /// the same forest definition
+    #[arg(long = "profile", value_name = "JSON_FILE")]
+    pub profile: Option<PathBuf>,
+
+    /// Save the profile of --profile-data to this JSON file, for later
+    /// conversions of the same forest definition
+    #[arg(
+        long = "save-profile",
+        value_name = "JSON_FILE",
+        requires = "profile_data",
+        conflicts_with = "input_dir"
+    )]
+    pub save_profile: Option<PathBuf>,
+
+    /// Write 8-byte branches with half-precision thresholds if the forest
+    /// qualifies, and 16-byte branches otherwise
+    #[arg(
+        long = "compact",
+        group = "layout_pass",
+        conflicts_with_all = ["input_dir", "dry_run"]
+    )]
+    pub compact: bool,
+
+    /// Write 16-byte branches with fixed-point thresholds, predicting with
+    /// integer arithmetic only, and report the scales chosen
+    #[arg(
+        long = "integer",
+        group = "layout_pass",
+        conflicts_with_all = ["input_dir", "dry_run"]
+    )]
+    pub integer: bool,
+
+    /// With --compact or --integer, labeled dataset (CSV) to measure the
+    /// effect of the rounding on the score of the forest
+    #[arg(
+        long = "validation",
+        value_name = "DATA_FILE",
+        requires_all = ["layout_pass", "label_column"]
+    )]
+    pub validation: Option<PathBuf>,
+
+    /// Column of the validation dataset holding the expected outputs
+    #[arg(short = 'l', long = "label-column", requires = "validation")]
+    pub label_column: Option<String>,
+
+    /// Largest drop of the validation score allowed for the compact layout:
+    /// percentage points of accuracy, or percent of RMSE. Defaults to 0, and
+    /// to no limit with --integer, which fails rather than falling back
+    #[arg(long = "max-metric-drop", value_name = "DROP", requires = "validation")]
+    pub max_metric_drop: Option<f64>,
+}
+
+pub fn run(args: ConvertArgs) -> Result<ExitCode> {
+    let mut formats = args.formats;
+    if formats.is_empty() {
+        formats.push(FormatSpec::new(OutputFormat::Rforest));
+    }
+    if let Some(language) = args.emit_index_consts {
+        formats.extend(language.formats().into_iter().map(FormatSpec::new));
+    }
+    let problem_type = args.problem_type.map(PredictionType::from);
+    let options = ReadOptions {
+        order: IndexOrder {
+            features: args.feature_order.map(|names| names.0),
+            targets: args.target_order.map(|names| names.0),
+            unlisted: args.order,
+        },
+        allow_count_mismatch: args.allow_count_mismatch,
+        split_var_as_index: args.split_var_as_index,
+        feature_names: args.feature_names.map(|names| names.0),
+    };
+    let encoding = NodeEncoding {
+        width: args.pointer_width,
+        endianness: args.endianness,
+        transform: args.transform,
+        calibration: args.calibration.map(Calibration::read).transpose()?,
+        ordinal: args.ordinal,
+        support: args.support,
+        encryption: args.encrypt_key,
+        layout: match (args.layout, args.profile_data, args.profile) {
+            (Layout::DepthFirst, None, None) => NodeOrder::DepthFirst,
+            (Layout::Profiled, Some(path), None) => NodeOrder::Profiled(ProfileSource::Data {
+                path,
+                save: args.save_profile,
+            }),
+            (Layout::Profiled, None, Some(profile)) => {
+                NodeOrder::Profiled(ProfileSource::Saved(TraversalProfile::read(profile)?))
+            }
+            (Layout::Profiled, ..) => {
+                return Err(eyre!("--layout profiled needs --profile-data or --profile"));
+            }
+            (Layout::DepthFirst, ..) => {
+                return Err(eyre!("--profile-data and --profile need --layout profiled"));
+            }
+        },
+    };
+
+    if let (Some(input_dir), Some(output_dir)) = (args.input_dir, args.output_dir) {
+        if formats.iter().any(|spec| spec.path.is_some()) {
+            return Err(eyre!("--format cannot be given a path with --input-dir"));
+        }
+
+        let summary = convert_dir(
+            input_dir,
+            output_dir,
+            problem_type,
+            &options,
+            encoding,
+            &formats,
+            args.jobs,
+            args.force,
+        )?;
+        print!("{summary}");
+
+        return Ok(if summary.failures() == 0 {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    if args.dry_run {
+        // Required without --input-dir
+        let input = args.input.unwrap();
+        if input == Path::new(STDIO) {
+            return Err(eyre!("--dry-run cannot read the forest from stdin"));
+        }
+        let report = estimate_file(input, problem_type, &options, &encoding)?;
+        print!("{report}");
+
+        return Ok(match args.max_size_bytes {
+            Some(max) if report.serialized_size > max => {
+                println!("Exceeds the maximum size of {max} bytes");
+                ExitCode::FAILURE
+            }
+            _ => ExitCode::SUCCESS,
+        });
+    }
+
+    // Both are required without --input-dir
+    let (input, output) = args.input.zip(args.output).unwrap();
+    let (stdin, stdout) = (input == Path::new(STDIO), output == Path::new(STDIO));
+    if !stdout && !args.force {
+        let metadata_path = ForestMetadata::sidecar_path(&output);
+        check_overwrite(&artifact_paths(&output, &formats), &metadata_path)?;
+    }
+    if args.compact {
+        if stdin || stdout {
+            return Err(eyre!("--compact cannot be used with stdin or stdout"));
+        }
+
+        let report = compact_file(
+            input,
+            problem_type,
+            &options,
+            args.validation.as_deref().zip(args.label_column.as_deref()),
+            args.max_metric_drop,
+            &artifact_paths(&output, &formats),
+            ForestMetadata::sidecar_path(&output),
+        )?;
+        print!("{report}");
+
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.integer {
+        if stdin || stdout {
+            return Err(eyre!("--integer cannot be used with stdin or stdout"));
+        }
+
+        let report = integer_file(
+            input,
+            problem_type,
+            &options,
+            args.validation.as_deref().zip(args.label_column.as_deref()),
+            args.max_metric_drop,
+            &artifact_paths(&output, &formats),
+            ForestMetadata::sidecar_path(&output),
+        )?;
+        print!("{report}");
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if !stdin && !stdout {
+        convert_file(
+            input,
+            problem_type,
+            &options,
+            encoding,
+            &artifact_paths(&output, &formats),
+            ForestMetadata::sidecar_path(&output),
+        )?;
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let (reader, name): (Box<dyn Read>, String) = if stdin {
+        (Box::new(io::stdin().lock()), "stdin".to_string())
+    } else {
+        let file =
+            File::open(&input).with_context(|| format!("Could not open {}", input.display()))?;
+        (Box::new(file), input.display().to_string())
+    };
+
+    if stdout {
+        let [spec] = formats.as_slice() else {
+            return Err(eyre!("Only one --format can be written to stdout"));
+        };
+        if spec.path.is_some() {
+            return Err(eyre!("--format cannot be given a path with `-o -`"));
+        }
+        if spec.format == OutputFormat::Rforest && io::stdout().is_terminal() && !args.force {
+            return Err(eyre!(
+                "Refusing to write a binary forest to a terminal. Redirect stdout, or pass --force"
+            ));
+        }
+
+        let destination = Destination::Stream {
+            format: spec.format,
+            out: &mut io::stdout().lock(),
+        };
+        convert_reader(reader, &name, problem_type, &options, encoding, destination)?;
+    } else {
+        let destination = Destination::Files {
+            artifacts: &artifact_paths(&output, &formats),
+            metadata_path: &ForestMetadata::sidecar_path(&output),
+        };
+        convert_reader(reader, &name, problem_type, &options, encoding, destination)?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Order of the branches of every tree, see [`NodeOrder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Layout {
+    DepthFirst,
+    Profiled,
+}
+
+/// Languages of the index constants of `--emit-index-consts`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IndexLanguage {
+    Rust,
+    C,
+    Both,
+}
+
+impl IndexLanguage {
+    fn formats(self) -> Vec<OutputFormat> {
+        match self {
+            Self::Rust => vec![OutputFormat::RustIndices],
+            Self::C => vec![OutputFormat::CIndices],
+            Self::Both => vec![OutputFormat::RustIndices, OutputFormat::CIndices],
+        }
+    }
+}
+
+/// Parse the value of `--transform`.
+fn parse_transform(s: &str) -> Result<Transform, String> {
+    match s {
+        "identity" => return Ok(Transform::Identity),
+        "sigmoid" => return Ok(Transform::Sigmoid),
+        "exp" => return Ok(Transform::Exp),
+        _ => {}
+    }
+
+    let parameters = s
+        .strip_prefix("custom=")
+        .ok_or_else(|| format!("unknown transform '{s}'"))?;
+    let (scale, offset) = parameters
+        .split_once(',')
+        .ok_or_else(|| format!("expected custom=SCALE,OFFSET, got '{s}'"))?;
+    let parse = |value: &str| match value.trim().parse::<f32>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(format!("'{value}' is not a finite number")),
+    };
+
+    Ok(Transform::Custom {
+        scale: parse(scale)?,
+        offset: parse(offset)?,
+    })
+}
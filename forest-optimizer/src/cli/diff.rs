@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::{Result, eyre::Context};
+use embedded_rforest::forest::{
+    Classification, OptimizedForest, Predict, ProblemKind, ProblemType, Regression,
+};
+
+use crate::{
+    dataset::read_feature_rows,
+    diff::{HeaderDiff, diff_behavior, diff_structure},
+    inspect::read_model,
+};
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// Serialized forest to compare from
+    #[arg(value_name = "OLD_MODEL")]
+    pub old: PathBuf,
+
+    /// Serialized forest to compare to
+    #[arg(value_name = "NEW_MODEL")]
+    pub new: PathBuf,
+
+    /// List every structural change instead of only counting them
+    #[arg(long = "details")]
+    pub details: bool,
+
+    /// Dataset (CSV of feature vectors) on which to compare predictions
+    #[arg(short = 'd', long = "data", value_name = "DATA_FILE")]
+    pub data: Option<PathBuf>,
+
+    /// Largest difference between two regression predictions still
+    /// considered an agreement
+    #[arg(long = "tolerance", default_value_t = 0.0)]
+    pub tolerance: f64,
+}
+
+pub fn run(args: DiffArgs) -> Result<ExitCode> {
+    let old = read_model(args.old)?;
+    let new = read_model(args.new)?;
+    let rows = args.data.map(read_feature_rows).transpose()?;
+
+    let header = HeaderDiff::new(&old, &new)?;
+    println!("--- Header ---\n{header}");
+
+    if !header.is_comparable() {
+        println!("Forests solve different problem types, skipping tree comparison.");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let identical = match header.old.problem_kind() {
+        ProblemKind::Classification => {
+            diff_forests::<Classification>(&old, &new, args.details, rows.as_deref(), 0.0)?
+        }
+        ProblemKind::Regression => {
+            diff_forests::<Regression>(&old, &new, args.details, rows.as_deref(), args.tolerance)?
+        }
+    };
+
+    Ok(if identical && (rows.is_some() || header.is_identical()) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Print the structural and (if rows are given) behavioral differences
+/// between two forests. Returns whether the forests are identical: on the
+/// given rows if any, structurally otherwise.
+fn diff_forests<'a, P>(
+    old: &'a [u8],
+    new: &'a [u8],
+    details: bool,
+    rows: Option<&[Vec<f32>]>,
+    tolerance: f64,
+) -> Result<bool>
+where
+    P: ProblemType,
+    P::Output: Into<f64>,
+    OptimizedForest<'a, P>: Predict<ProblemType = P>,
+{
+    let old = OptimizedForest::<P>::deserialize(old).context("Old forest")?;
+    let new = OptimizedForest::<P>::deserialize(new).context("New forest")?;
+
+    let structure = diff_structure(&old, &new);
+    println!("--- Structure ---\n{structure}");
+    if details {
+        print!("{}", structure.details());
+    }
+
+    match rows {
+        Some(rows) => {
+            let behavior = diff_behavior(&old, &new, rows, tolerance)?;
+            println!("--- Behavior ---\n{behavior}");
+            Ok(behavior.is_identical())
+        }
+        None => Ok(structure.is_identical()),
+    }
+}
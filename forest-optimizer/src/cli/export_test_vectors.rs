@@ -0,0 +1,74 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::{Result, eyre::Context};
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, ProblemKind, Regression, deserialize::ForestHeader,
+};
+
+use super::read_metadata;
+use crate::{
+    dataset::read_mapped_rows,
+    inspect::read_model,
+    test_vectors::{VectorFormat, sample},
+};
+
+#[derive(Args)]
+pub struct ExportTestVectorsArgs {
+    /// Serialized forest file
+    #[arg(short = 'm', long = "model", value_name = "MODEL")]
+    pub model: PathBuf,
+
+    /// Dataset (CSV), with one column per feature
+    #[arg(short = 'd', long = "data", value_name = "DATA_FILE")]
+    pub data: PathBuf,
+
+    /// Number of rows to sample
+    #[arg(short = 'n', long = "count", default_value_t = 32)]
+    pub count: usize,
+
+    /// Seed of the sampling. The same seed, dataset and count always give the
+    /// same vectors
+    #[arg(long = "seed", default_value_t = 0)]
+    pub seed: u64,
+
+    /// Output file
+    #[arg(short = 'o', long = "output", value_name = "OUTPUT_FILE")]
+    pub output: PathBuf,
+
+    /// Output format
+    #[arg(short = 'f', long = "format", value_enum, default_value = "rust")]
+    pub format: VectorFormat,
+}
+
+pub fn run(args: ExportTestVectorsArgs) -> Result<ExitCode> {
+    let buffer = read_model(&args.model)?;
+    let header = ForestHeader::peek(&buffer).context("Malformed forest")?;
+    let metadata = read_metadata(&args.model)?;
+    let rows = read_mapped_rows(&args.data, &metadata.features)?;
+
+    let file = File::create(&args.output)
+        .with_context(|| format!("Could not create {}", args.output.display()))?;
+    let mut out = BufWriter::new(file);
+
+    match header.problem_kind() {
+        ProblemKind::Classification => {
+            let forest = AnyOptimizedForest::<Classification>::deserialize(&buffer)
+                .context("Malformed forest")?;
+            sample(&forest, &metadata, &rows, args.count, args.seed)
+                .write(args.format, &mut out)?;
+        }
+        ProblemKind::Regression => {
+            let forest = AnyOptimizedForest::<Regression>::deserialize(&buffer)
+                .context("Malformed forest")?;
+            sample(&forest, &metadata, &rows, args.count, args.seed)
+                .write(args.format, &mut out)?;
+        }
+    }
+    out.flush()?;
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::Result;
+
+use crate::{
+    encryption::EncryptionKey,
+    inspect::{inspect_with, read_model},
+};
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Serialized forest file
+    #[arg(value_name = "MODEL")]
+    pub model: PathBuf,
+
+    /// Print the report as JSON
+    #[arg(long = "json")]
+    pub json: bool,
+
+    /// Key the forest was encrypted with by `convert --encrypt-key`, to
+    /// validate it decrypted
+    #[arg(long = "decrypt-key", value_name = "HEX")]
+    pub decrypt_key: Option<EncryptionKey>,
+}
+
+pub fn run(args: InfoArgs) -> Result<ExitCode> {
+    let buffer = read_model(args.model)?;
+    let info = inspect_with(&buffer, args.decrypt_key.as_ref())?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        print!("{info}");
+    }
+
+    Ok(if info.is_valid() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
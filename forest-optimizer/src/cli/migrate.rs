@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::{Result, eyre::Context};
+use embedded_rforest::forest::deserialize::{FORMAT_VERSION, peek_version};
+
+use crate::{inspect::read_model, metadata::ForestMetadata, migrate::migrate};
+
+#[derive(Args)]
+pub struct MigrateArgs {
+    /// Serialized forest to upgrade
+    #[arg(value_name = "OLD_MODEL")]
+    pub old: PathBuf,
+
+    /// Upgraded forest to write, along with a copy of the metadata file of
+    /// the old one. The old forest is overwritten if omitted
+    #[arg(value_name = "NEW_MODEL")]
+    pub new: Option<PathBuf>,
+}
+
+pub fn run(args: MigrateArgs) -> Result<ExitCode> {
+    let old = read_model(&args.old)?;
+    let upgraded =
+        migrate(&old).with_context(|| format!("Could not upgrade {}", args.old.display()))?;
+    let version = peek_version(&old)?;
+
+    let new = args.new.as_ref().unwrap_or(&args.old);
+    std::fs::write(new, &upgraded[..]).context("Could not write serialized forest file")?;
+    let metadata = ForestMetadata::sidecar_path(&args.old);
+    if new != &args.old && metadata.exists() {
+        std::fs::copy(&metadata, ForestMetadata::sidecar_path(new))
+            .context("Could not copy metadata file")?;
+    }
+
+    println!(
+        "Upgraded {} from format version {version} to {FORMAT_VERSION}: {} -> {} bytes",
+        new.display(),
+        old.len(),
+        upgraded.len()
+    );
+
+    Ok(ExitCode::SUCCESS)
+}
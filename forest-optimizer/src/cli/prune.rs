@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::Result;
+
+use super::ProblemType;
+use crate::{
+    problem_type::PredictionType,
+    prune::{PruneOptions, prune_file},
+};
+
+#[derive(Args)]
+pub struct PruneArgs {
+    /// Input file
+    #[arg(short = 'i', long = "input", value_name = "INPUT_FILE")]
+    pub input: PathBuf,
+
+    /// Problem type. Detected from the header of the input file if omitted
+    #[arg(short = 'p', long = "problem-type", value_enum)]
+    pub problem_type: Option<ProblemType>,
+
+    /// Read the input through a cache next to it, written on first use and
+    /// rebuilt whenever the input changes
+    #[arg(long = "cache")]
+    pub cache: bool,
+
+    /// Output file
+    #[arg(short = 'o', long = "output", value_name = "OUTPUT_FILE")]
+    pub output: PathBuf,
+
+    /// Replace branches N branches below the root of their tree with a leaf:
+    /// the majority class or mean value of the validation rows reaching them,
+    /// or of the leaves below them without a validation dataset
+    #[arg(long = "max-depth", value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// Replace branches whose two sides make the same prediction with a leaf.
+    /// Predictions are unchanged
+    #[arg(long = "collapse-redundant")]
+    pub collapse_redundant: bool,
+
+    /// Keep only the first N trees
+    #[arg(long = "max-trees", value_name = "N")]
+    pub max_trees: Option<usize>,
+
+    /// Labeled dataset (CSV) to score the forest on, before and after pruning
+    #[arg(
+        long = "validation",
+        value_name = "DATA_FILE",
+        requires = "label_column"
+    )]
+    pub validation: Option<PathBuf>,
+
+    /// Column of the validation dataset holding the expected output
+    #[arg(short = 'l', long = "label-column", value_name = "COLUMN")]
+    pub label_column: Option<String>,
+
+    /// Remove trees as long as the validation score does not get worse by
+    /// more than this: percentage points of accuracy for classification,
+    /// percent of RMSE for regression
+    #[arg(long = "max-accuracy-drop", value_name = "X", requires = "validation")]
+    pub max_accuracy_drop: Option<f64>,
+
+    /// Also write the before/after report as JSON to this file
+    #[arg(long = "report-json", value_name = "FILE")]
+    pub report_json: Option<PathBuf>,
+}
+
+pub fn run(args: PruneArgs) -> Result<ExitCode> {
+    let options = PruneOptions {
+        max_depth: args.max_depth,
+        collapse_redundant: args.collapse_redundant,
+        max_trees: args.max_trees,
+        max_loss: args.max_accuracy_drop,
+    };
+    let validation = args.validation.as_deref().zip(args.label_column.as_deref());
+
+    let report = prune_file(
+        &args.input,
+        args.problem_type.map(PredictionType::from),
+        args.cache,
+        &options,
+        validation,
+        &args.output,
+    )?;
+    print!("{report}");
+
+    if let Some(path) = args.report_json {
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
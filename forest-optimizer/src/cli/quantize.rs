@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::Result;
+
+use super::ProblemType;
+use crate::{
+    problem_type::PredictionType,
+    quantize::{LeafType, QuantizeOptions, ThresholdType, quantize_file},
+};
+
+#[derive(Args)]
+pub struct QuantizeArgs {
+    /// Input file
+    #[arg(short = 'i', long = "input", value_name = "INPUT_FILE")]
+    pub input: PathBuf,
+
+    /// Problem type. Detected from the header of the input file if omitted
+    #[arg(short = 'p', long = "problem-type", value_enum)]
+    pub problem_type: Option<ProblemType>,
+
+    /// Read the input through a cache next to it, written on first use and
+    /// rebuilt whenever the input changes
+    #[arg(long = "cache")]
+    pub cache: bool,
+
+    /// Output file
+    #[arg(short = 'o', long = "output", value_name = "OUTPUT_FILE")]
+    pub output: PathBuf,
+
+    /// Round thresholds to this type
+    #[arg(long = "thresholds", value_enum)]
+    pub thresholds: Option<ThresholdType>,
+
+    /// Round leaves to this type
+    #[arg(long = "leaves", value_enum)]
+    pub leaves: Option<LeafType>,
+
+    /// Labeled dataset (CSV) to measure the effect of the quantization on
+    #[arg(
+        long = "validation",
+        value_name = "DATA_FILE",
+        requires = "label_column"
+    )]
+    pub validation: Option<PathBuf>,
+
+    /// Column of the validation dataset holding the expected output
+    #[arg(short = 'l', long = "label-column", value_name = "COLUMN")]
+    pub label_column: Option<String>,
+
+    /// Do not write the output if the validation score gets worse by more
+    /// than this: percentage points of accuracy for classification, percent
+    /// of RMSE for regression
+    #[arg(long = "max-metric-drop", value_name = "X", requires = "validation")]
+    pub max_metric_drop: Option<f64>,
+
+    /// Write the output even if --max-metric-drop is exceeded
+    #[arg(long = "force", requires = "max_metric_drop")]
+    pub force: bool,
+
+    /// Print the report as JSON
+    #[arg(long = "json")]
+    pub json: bool,
+}
+
+pub fn run(args: QuantizeArgs) -> Result<ExitCode> {
+    let options = QuantizeOptions {
+        thresholds: args.thresholds,
+        leaves: args.leaves,
+    };
+    let validation = args.validation.as_deref().zip(args.label_column.as_deref());
+
+    let report = quantize_file(
+        &args.input,
+        args.problem_type.map(PredictionType::from),
+        args.cache,
+        &options,
+        validation,
+        args.max_metric_drop,
+        args.force,
+        &args.output,
+    )?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+
+    Ok(if report.written {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
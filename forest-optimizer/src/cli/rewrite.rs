@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::{Result, eyre::Context};
+
+use crate::{
+    inspect::read_model,
+    metadata::ForestMetadata,
+    sections::{Section, strip},
+};
+
+#[derive(Args)]
+pub struct RewriteArgs {
+    /// Serialized forest to rewrite
+    #[arg(value_name = "MODEL")]
+    pub model: PathBuf,
+
+    /// Rewritten forest to write, along with a copy of the metadata file of
+    /// the original. The original is overwritten if omitted
+    #[arg(value_name = "NEW_MODEL")]
+    pub new: Option<PathBuf>,
+
+    /// Optional section to leave out: fingerprint, transform, calibration
+    /// or support. May be repeated
+    #[arg(long = "strip", value_name = "SECTION", value_enum, required = true)]
+    pub strip: Vec<Section>,
+}
+
+pub fn run(args: RewriteArgs) -> Result<ExitCode> {
+    let model = read_model(&args.model)?;
+    let stripped = strip(&model, &args.strip)
+        .with_context(|| format!("Could not rewrite {}", args.model.display()))?;
+
+    let new = args.new.as_ref().unwrap_or(&args.model);
+    std::fs::write(new, &stripped[..]).context("Could not write serialized forest file")?;
+    let metadata = ForestMetadata::sidecar_path(&args.model);
+    if new != &args.model && metadata.exists() {
+        std::fs::copy(&metadata, ForestMetadata::sidecar_path(new))
+            .context("Could not copy metadata file")?;
+    }
+
+    let sections = args
+        .strip
+        .iter()
+        .map(Section::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!(
+        "Stripped the {sections} of {}: {} -> {} bytes",
+        new.display(),
+        model.len(),
+        stripped.len()
+    );
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+
+use crate::{
+    inspect::read_model,
+    signature::{embed_signature, public_key, read_signing_key, sign},
+};
+
+#[derive(Args)]
+pub struct SignArgs {
+    /// Serialized forest to sign
+    #[arg(short = 'm', long = "model", value_name = "MODEL")]
+    pub model: PathBuf,
+
+    /// Ed25519 private key (PEM), e.g. from `openssl genpkey -algorithm
+    /// ed25519`
+    #[arg(short = 'k', long = "key", value_name = "PRIVATE_KEY")]
+    pub key: PathBuf,
+
+    /// Append the signature to a copy of the forest rather than writing it
+    /// to a file of its own
+    #[arg(long = "embed")]
+    pub embed: bool,
+
+    /// File to write. Defaults to MODEL with the extension `sig`, or
+    /// `signed.rforest` with --embed
+    #[arg(short = 'o', long = "output", value_name = "OUTPUT")]
+    pub output: Option<PathBuf>,
+
+    /// Overwrite the output file if it exists
+    #[arg(long = "force")]
+    pub force: bool,
+}
+
+pub fn run(args: SignArgs) -> Result<ExitCode> {
+    let model = read_model(&args.model)?;
+    let key = read_signing_key(&args.key)?;
+    let signature =
+        sign(&model, &key).with_context(|| format!("Could not sign {}", args.model.display()))?;
+
+    let extension = if args.embed { "signed.rforest" } else { "sig" };
+    let output = args
+        .output
+        .unwrap_or_else(|| args.model.with_extension(extension));
+    if output.exists() && !args.force {
+        return Err(eyre!(
+            "{} already exists. Pass --force to overwrite it",
+            output.display()
+        ));
+    }
+    if args.embed {
+        std::fs::write(&output, &embed_signature(&model, signature)[..])
+    } else {
+        std::fs::write(&output, signature)
+    }
+    .context("Could not write signature file")?;
+
+    let public_key = public_key(&key)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    println!("Signed {} into {}", args.model.display(), output.display());
+    println!("Public key: {public_key}");
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Args;
+use color_eyre::Result;
+use color_eyre::eyre::{Context, eyre};
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, ProblemKind, Regression, deserialize::ForestHeader,
+};
+use serde::Serialize;
+
+use super::read_metadata;
+use crate::{
+    compare::compare,
+    dataset::read_labeled,
+    evaluate::{evaluate_classification, evaluate_regression},
+    forest::Forest,
+    inspect::read_model,
+    serialized_forest::{SerializedClassificationNode, SerializedForest, SerializedRegressionNode},
+    write_forest::WriteForest,
+};
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// Serialized forest file
+    #[arg(short = 'm', long = "model", value_name = "MODEL")]
+    pub model: PathBuf,
+
+    /// Labeled dataset (CSV), with one column per feature
+    #[arg(short = 'd', long = "data", value_name = "DATA_FILE")]
+    pub data: PathBuf,
+
+    /// Dataset column holding the expected output
+    #[arg(short = 'l', long = "label-column", value_name = "COLUMN")]
+    pub label_column: String,
+
+    /// Original forest definition (CSV) the model was built from. Its
+    /// predictions are compared against the model's, and its feature
+    /// names are used instead of the model's metadata file.
+    #[arg(long = "csv", value_name = "FOREST_FILE")]
+    pub csv: Option<PathBuf>,
+
+    /// Also write the metrics as JSON to this file
+    #[arg(long = "report-json", value_name = "FILE")]
+    pub report_json: Option<PathBuf>,
+}
+
+/// Metrics of a forest on a dataset, as written by `--report-json`.
+#[derive(Serialize)]
+struct ValidationReport<'a, M> {
+    /// Class labels, positioned by class index as in the metrics
+    #[serde(skip_serializing_if = "Option::is_none")]
+    classes: Option<&'a [String]>,
+    #[serde(flatten)]
+    metrics: &'a M,
+}
+
+fn write_report<M: Serialize>(
+    path: Option<&Path>,
+    classes: Option<&[String]>,
+    metrics: &M,
+) -> Result<()> {
+    if let Some(path) = path {
+        let json = serde_json::to_string_pretty(&ValidationReport { classes, metrics })?;
+        std::fs::write(path, json + "\n")
+            .with_context(|| format!("Could not write {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+pub fn run(args: ValidateArgs) -> Result<ExitCode> {
+    let ValidateArgs {
+        model,
+        data,
+        label_column,
+        csv,
+        report_json,
+    } = args;
+    let buffer = read_model(&model)?;
+    let header = ForestHeader::peek(&buffer).context("Malformed forest")?;
+
+    match header.problem_kind() {
+        ProblemKind::Classification => {
+            let optimized = AnyOptimizedForest::<Classification>::deserialize(&buffer)
+                .context("Malformed forest")?;
+            let forest = csv
+                .map(|path| {
+                    let serialized = SerializedForest::<SerializedClassificationNode>::read(path)
+                        .context("Could not read forest definition file (CSV).")?;
+                    Forest::from_serialized(serialized)
+                })
+                .transpose()?;
+            let metadata = match &forest {
+                Some(f) => WriteForest::metadata(f),
+                None => read_metadata(&model)?,
+            };
+            let targets = metadata
+                .targets
+                .ok_or_else(|| eyre!("Forest metadata lists no targets"))?;
+
+            let dataset = read_labeled(data, &metadata.features, &label_column)?;
+            let metrics = evaluate_classification(&optimized, &dataset, &targets)?;
+            print!("{}", metrics.report(Some(&targets)));
+            write_report(report_json.as_deref(), Some(&targets), &metrics)?;
+
+            if let Some(forest) = forest {
+                let comparison = compare(&forest, &optimized, &dataset.rows);
+                print!("--- CSV forest vs optimized forest ---\n{comparison}");
+                if !comparison.is_identical() {
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+        ProblemKind::Regression => {
+            let optimized = AnyOptimizedForest::<Regression>::deserialize(&buffer)
+                .context("Malformed forest")?;
+            let forest = csv
+                .map(|path| {
+                    let serialized = SerializedForest::<SerializedRegressionNode>::read(path)
+                        .context("Could not read forest definition file (CSV).")?;
+                    Forest::from_serialized(serialized)
+                })
+                .transpose()?;
+            let metadata = match &forest {
+                Some(f) => WriteForest::metadata(f),
+                None => read_metadata(&model)?,
+            };
+
+            let dataset = read_labeled(data, &metadata.features, &label_column)?;
+            let metrics = evaluate_regression(&optimized, &dataset)?;
+            print!("{metrics}");
+            write_report(report_json.as_deref(), None, &metrics)?;
+
+            if let Some(forest) = forest {
+                let comparison = compare(&forest, &optimized, &dataset.rows);
+                print!("--- CSV forest vs optimized forest ---\n{comparison}");
+                if !comparison.is_identical() {
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
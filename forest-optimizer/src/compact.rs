@@ -0,0 +1,376 @@
+//! Conversion of a forest to the compact layout: 8-byte branches with 16-bit
+//! child pointers, 8-bit feature indices and half-precision thresholds.
+//!
+//! A forest only qualifies if it fits those fields and, when a validation
+//! dataset is given, if rounding its thresholds (and regression leaves) to
+//! half precision keeps its score within the allowed drop. Otherwise it is
+//! written in the standard layout, and the report says why.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use aligned_vec::AVec;
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::{
+    forest::{self as embedded, Branch, CompactBranch, NodeLayout, OptimizedForest},
+    ptr::NodePointer,
+};
+use half::f16;
+use tracing::warn;
+
+use crate::{
+    analyze::estimate_serialized_size,
+    emit::{OutputFormat, emit_all},
+    forest::{Forest, Node},
+    problem_type::{Classification, PredictionType, Regression},
+    prune::{LayoutWriter, Prune, Validation, layout_file},
+    quantize::{QuantizationError, ThresholdType, ValidationChange, quantize_thresholds},
+    serialized_forest::ReadOptions,
+    write_forest::{
+        Endianness, WriteForest, fingerprint, group_trees, num_features, num_trees, write_artifacts,
+    },
+};
+
+/// Problem types whose forests can be serialized in the compact layout.
+pub trait Compact: Prune {
+    /// Round every leaf to what a compact leaf pointer can hold. Returns the
+    /// root mean squared difference between the leaves and their rounded
+    /// values, if leaves are approximated at all.
+    fn round_leaves(forest: &mut Forest<Self>) -> Result<Option<f64>>;
+
+    /// Optimize a forest whose values fit the compact layout, and serialize
+    /// it in that layout.
+    fn serialize_compact(forest: &Forest<Self>) -> Result<AVec<u8>>;
+}
+
+impl Compact for Classification {
+    /// Class indices always fit a leaf pointer.
+    fn round_leaves(_: &mut Forest<Self>) -> Result<Option<f64>> {
+        Ok(None)
+    }
+
+    fn serialize_compact(forest: &Forest<Self>) -> Result<AVec<u8>> {
+        let (nodes, tree_offsets) =
+            group_trees(&Self::optimize(forest).0, forest.num_trees(), &[])?;
+        let nodes = compact_branches(&nodes, |ptr| {
+            u16::try_from(ptr.index())
+                .map_err(|_| eyre!("Class {} does not fit 16 bits", ptr.index()))
+        })?;
+        let num_targets = forest
+            .num_targets()
+            .try_into()
+            .map_err(|_| eyre!("Forest has more than 255 targets"))?;
+        let problem =
+            embedded::Classification::new(num_targets).context("Forest has no targets")?;
+
+        let optimized = OptimizedForest::<embedded::Classification, CompactBranch>::new(
+            num_trees(forest)?,
+            &nodes,
+            num_features(forest)?,
+            problem,
+        )
+        .and_then(|optimized| optimized.with_tree_offsets(&tree_offsets))
+        .map(|optimized| {
+            optimized
+                .with_fingerprint(fingerprint(forest))
+                .with_split_rule(forest.split_rule())
+        })
+        .context("Malformed forest")?;
+
+        Ok(optimized.to_bytes())
+    }
+}
+
+impl Compact for Regression {
+    /// Leaves are stored as `f16`.
+    fn round_leaves(forest: &mut Forest<Self>) -> Result<Option<f64>> {
+        let (mut squared, mut count) = (0.0f64, 0);
+        for node in forest.nodes_mut() {
+            let Node::Leaf(leaf) = node else {
+                continue;
+            };
+
+            let rounded = f16::from_f32(leaf.prediction).to_f32();
+            if rounded.is_infinite() && leaf.prediction.is_finite() {
+                return Err(eyre!(
+                    "Leaf {} is out of the range of half precision",
+                    leaf.prediction
+                ));
+            }
+
+            squared += (f64::from(leaf.prediction) - f64::from(rounded)).powi(2);
+            count += 1;
+            leaf.prediction = rounded;
+        }
+
+        Ok(Some(if count == 0 {
+            0.0
+        } else {
+            (squared / count as f64).sqrt()
+        }))
+    }
+
+    fn serialize_compact(forest: &Forest<Self>) -> Result<AVec<u8>> {
+        // Compact leaf pointers hold the predictions rather than table indices
+        let (nodes, leaves) = Self::optimize(forest);
+        let (nodes, tree_offsets) = group_trees(&nodes, forest.num_trees(), &[])?;
+        let nodes = compact_branches(&nodes, |ptr| {
+            Ok(f16::from_f32(leaves[ptr.index() as usize].get()).to_bits())
+        })?;
+
+        let optimized = OptimizedForest::<embedded::Regression, CompactBranch>::new(
+            num_trees(forest)?,
+            &nodes,
+            num_features(forest)?,
+        )
+        .and_then(|optimized| optimized.with_tree_offsets(&tree_offsets))
+        .map(|optimized| {
+            optimized
+                .with_fingerprint(fingerprint(forest))
+                .with_split_rule(forest.split_rule())
+        })
+        .context("Malformed forest")?;
+
+        Ok(optimized.to_bytes())
+    }
+}
+
+/// Convert standard branches to compact ones. `leaf` converts a leaf
+/// pointer.
+fn compact_branches(
+    nodes: &[Branch],
+    leaf: impl Fn(NodePointer) -> Result<u16>,
+) -> Result<Vec<CompactBranch>> {
+    if nodes.len() > usize::from(u16::MAX) + 1 {
+        return Err(eyre!("{} branches do not fit 16-bit pointers", nodes.len()));
+    }
+
+    let child = |ptr: NodePointer, is_prediction: bool| {
+        if is_prediction {
+            leaf(ptr)
+        } else {
+            // Fits, as the number of branches does
+            Ok(ptr.index() as u16)
+        }
+    };
+
+    nodes
+        .iter()
+        .map(|branch| {
+            let split_with = u8::try_from(branch.split_with())
+                .map_err(|_| eyre!("Feature index {} does not fit 8 bits", branch.split_with()))?;
+
+            Ok(CompactBranch::new(
+                split_with,
+                f16::from_f32(branch.split_at()),
+                child(branch.left_ptr(), branch.left_is_prediction())?,
+                child(branch.right_ptr(), branch.right_is_prediction())?,
+                branch.left_is_prediction(),
+                branch.right_is_prediction(),
+            ))
+        })
+        .collect()
+}
+
+/// Round the thresholds and leaves of a forest to half precision.
+fn round<P: Compact>(forest: &mut Forest<P>) -> Result<QuantizationError> {
+    Ok(QuantizationError {
+        thresholds: Some(quantize_thresholds(forest, ThresholdType::F16)?),
+        leaf_rmse: P::round_leaves(forest)?,
+    })
+}
+
+/// Outcome of writing a forest in the compact layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactReport {
+    /// Layout the forest was written in
+    pub layout: NodeLayout,
+    /// Why the forest was written in the standard layout, if it was
+    pub fallback: Option<String>,
+    /// Error introduced by rounding to half precision, if it could be done
+    pub error: Option<QuantizationError>,
+    /// Name of the validation score
+    pub score: &'static str,
+    /// Effect of the rounding on the validation set, if any
+    pub validation: Option<ValidationChange>,
+    /// Size of the forest in the standard layout, in bytes
+    pub standard_size: usize,
+    /// Size of the written forest, in bytes
+    pub written_size: usize,
+}
+
+impl fmt::Display for CompactReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Layout:             {} ({}-byte branches)",
+            self.layout,
+            self.layout.branch_size()
+        )?;
+        if let Some(reason) = &self.fallback {
+            writeln!(f, "Not compact:        {reason}")?;
+        }
+
+        if let Some(thresholds) = self.error.and_then(|e| e.thresholds) {
+            writeln!(
+                f,
+                "Threshold rounding: max {:.4e}, mean {:.4e}",
+                thresholds.max, thresholds.mean
+            )?;
+        }
+        if let Some(leaf_rmse) = self.error.and_then(|e| e.leaf_rmse) {
+            writeln!(f, "Leaf RMSE delta:    {leaf_rmse:.4e}")?;
+        }
+        if let Some(validation) = self.validation {
+            writeln!(
+                f,
+                "{}: {:.3} -> {:.3}",
+                self.score, validation.before, validation.after
+            )?;
+            writeln!(
+                f,
+                "Prediction flips:   {} of {}",
+                validation.flips, validation.rows
+            )?;
+        }
+
+        if self.written_size == self.standard_size {
+            writeln!(f, "Size:               {} bytes", self.written_size)
+        } else {
+            writeln!(
+                f,
+                "Size:               {} -> {} bytes ({:.1}% smaller)",
+                self.standard_size,
+                self.written_size,
+                (1.0 - self.written_size as f64 / self.standard_size as f64) * 100.0
+            )
+        }
+    }
+}
+
+/// Write a forest in the compact layout if it qualifies, and in the standard
+/// layout otherwise, along with its metadata.
+///
+/// With `validation`, the forest only qualifies if rounding it to half
+/// precision does not drop its score by more than `max_drop` (see
+/// [`Prune::is_acceptable`]).
+pub fn write_compact<P: Compact>(
+    forest: &Forest<P>,
+    validation: Option<&Validation<P>>,
+    max_drop: f64,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: impl AsRef<Path>,
+) -> Result<CompactReport> {
+    let standard_size = estimate_serialized_size(forest).serialized_size;
+    let mut report = CompactReport {
+        layout: NodeLayout::Standard,
+        fallback: None,
+        error: None,
+        score: P::SCORE,
+        validation: None,
+        standard_size,
+        written_size: standard_size,
+    };
+
+    let mut rounded = forest.clone();
+    let compact = round(&mut rounded).and_then(|error| {
+        report.error = Some(error);
+
+        if let Some(validation) = validation {
+            let change = ValidationChange::new(
+                validation,
+                &validation.predict(forest),
+                &validation.predict(&rounded),
+            );
+            report.validation = Some(change);
+
+            if !P::is_acceptable(change.before, change.after, max_drop) {
+                return Err(eyre!(
+                    "{} drops from {:.3} to {:.3}",
+                    P::SCORE,
+                    change.before,
+                    change.after
+                ));
+            }
+        }
+
+        P::serialize_compact(&rounded)
+    });
+
+    match compact {
+        Ok(serialized) => {
+            let optimized =
+                OptimizedForest::<P::OptimizedType, CompactBranch>::deserialize(&serialized)
+                    .context("Malformed forest")?;
+            emit_all(
+                &optimized,
+                &P::metadata(forest),
+                Endianness::Little,
+                None,
+                artifacts,
+                metadata_path.as_ref(),
+            )?;
+
+            report.layout = NodeLayout::Compact;
+            report.written_size = serialized.len();
+        }
+        Err(reason) => {
+            warn!(%reason, "Falling back to the standard layout");
+            report.fallback = Some(reason.to_string());
+            report.written_size = write_artifacts(forest, artifacts, metadata_path)?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// [`write_compact`] for a forest definition file (CSV) whose problem type is
+/// detected from its header, or given by `problem_type`.
+///
+/// `validation` is a labeled dataset (CSV) and the name of its label column.
+/// `max_drop` defaults to no drop at all.
+pub fn compact_file(
+    input: impl AsRef<Path>,
+    problem_type: Option<PredictionType>,
+    options: &ReadOptions,
+    validation: Option<(&Path, &str)>,
+    max_drop: Option<f64>,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: impl AsRef<Path>,
+) -> Result<CompactReport> {
+    layout_file::<CompactWriter, _>(
+        input.as_ref(),
+        problem_type,
+        options,
+        validation,
+        max_drop,
+        artifacts,
+        metadata_path.as_ref(),
+    )
+}
+
+/// [`LayoutWriter`] of [`write_compact`].
+struct CompactWriter;
+
+impl<P: Compact> LayoutWriter<P> for CompactWriter {
+    type Report = CompactReport;
+
+    fn write(
+        forest: &Forest<P>,
+        validation: Option<&Validation<P>>,
+        max_drop: Option<f64>,
+        artifacts: &[(OutputFormat, PathBuf)],
+        metadata_path: &Path,
+    ) -> Result<CompactReport> {
+        write_compact(
+            forest,
+            validation,
+            max_drop.unwrap_or(0.0),
+            artifacts,
+            metadata_path,
+        )
+    }
+}
@@ -0,0 +1,180 @@
+//! Differential testing: run two forests over the same rows and summarize
+//! where their predictions disagree. Host forests and optimized forests of
+//! every layout can be compared with each other, through [`PredictLike`].
+
+use std::fmt;
+
+use embedded_rforest::forest::{Predict, ProblemType};
+
+use crate::{forest::Forest, prune::Prune};
+
+/// Anything predicting the outputs of the (optimized) problem type `P`:
+/// optimized forests and their views, and host [`Forest`]s, whose classes
+/// are mapped to their class index.
+///
+/// Code generic over it runs on every forest and problem type alike. Class
+/// names of host forests are looked up with [`Forest::class_name`].
+pub trait PredictLike<P: ProblemType> {
+    fn predict_like(&self, features: &[f32]) -> P::Output;
+}
+
+impl<F: Predict> PredictLike<F::ProblemType> for F {
+    fn predict_like(&self, features: &[f32]) -> <F::ProblemType as ProblemType>::Output {
+        self.predict(features)
+    }
+}
+
+/// The trees vote like those of the optimized forest: the lowest class wins
+/// ties, and regression trees are averaged in order.
+impl<P: Prune> PredictLike<P::OptimizedType> for Forest<P> {
+    fn predict_like(&self, features: &[f32]) -> P::Output {
+        P::aggregate(self.trees().map(|tree| tree.predict(features)))
+    }
+}
+
+/// Prediction-level comparison of two forests over a set of rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub rows: usize,
+    /// Indices of the rows on which the forests disagree
+    pub disagreements: Vec<usize>,
+    /// Largest absolute difference between two predictions. Always zero for
+    /// classification, whose class indices are not quantities
+    pub max_difference: f64,
+    /// Mean absolute difference between two predictions, over every row.
+    /// Always zero for classification
+    pub mean_difference: f64,
+}
+
+impl Comparison {
+    pub fn is_identical(&self) -> bool {
+        self.disagreements.is_empty()
+    }
+
+    /// Fraction of rows on which the two forests disagree
+    pub fn disagreement_rate(&self) -> f64 {
+        if self.rows == 0 {
+            0.0
+        } else {
+            self.disagreements.len() as f64 / self.rows as f64
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Disagreements: {} / {} rows ({:.2}%)",
+            self.disagreements.len(),
+            self.rows,
+            self.disagreement_rate() * 100.0
+        )?;
+        if self.max_difference > 0.0 {
+            writeln!(
+                f,
+                "Max difference: {}, mean difference: {}",
+                self.max_difference, self.mean_difference
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run both forests over every row and record where their predictions
+/// differ. Every row must hold the features of both forests.
+pub fn compare<P>(a: &impl PredictLike<P>, b: &impl PredictLike<P>, rows: &[Vec<f32>]) -> Comparison
+where
+    P: ProblemType,
+    P::Output: Into<f64>,
+{
+    compare_within(a, b, rows, 0.0)
+}
+
+/// Like [`compare`], but regression predictions differing by at most
+/// `tolerance` still agree. Class indices are compared exactly.
+pub fn compare_within<P>(
+    a: &impl PredictLike<P>,
+    b: &impl PredictLike<P>,
+    rows: &[Vec<f32>],
+    tolerance: f64,
+) -> Comparison
+where
+    P: ProblemType,
+    P::Output: Into<f64>,
+{
+    let predictions = rows
+        .iter()
+        .map(|row| (a.predict_like(row), b.predict_like(row)));
+    compare_outputs::<P>(predictions, tolerance)
+}
+
+/// Like [`compare_within`], against predictions recorded beforehand, e.g.
+/// by the trainer of the forest, positioned by row.
+///
+/// # Panics
+///
+/// Panics if there are not as many recorded predictions as rows.
+pub fn compare_recorded<P>(
+    recorded: &[P::Output],
+    forest: &impl PredictLike<P>,
+    rows: &[Vec<f32>],
+    tolerance: f64,
+) -> Comparison
+where
+    P: ProblemType,
+    P::Output: Into<f64>,
+{
+    assert_eq!(
+        recorded.len(),
+        rows.len(),
+        "Recorded prediction and row counts differ"
+    );
+
+    let predictions = recorded
+        .iter()
+        .zip(rows)
+        .map(|(&recorded, row)| (recorded, forest.predict_like(row)));
+    compare_outputs::<P>(predictions, tolerance)
+}
+
+/// Compare pairs of predictions, one pair per row.
+fn compare_outputs<P>(
+    predictions: impl ExactSizeIterator<Item = (P::Output, P::Output)>,
+    tolerance: f64,
+) -> Comparison
+where
+    P: ProblemType,
+    P::Output: Into<f64>,
+{
+    let mut comparison = Comparison {
+        rows: predictions.len(),
+        disagreements: Vec::new(),
+        max_difference: 0.0,
+        mean_difference: 0.0,
+    };
+
+    let mut total = 0.0;
+    for (i, (a, b)) in predictions.enumerate() {
+        let (a, b): (f64, f64) = (a.into(), b.into());
+        let difference = (a - b).abs();
+        let agree = if P::HAS_TARGETS {
+            a == b
+        } else {
+            // NaN differences are disagreements
+            difference <= tolerance
+        };
+        if !agree {
+            comparison.disagreements.push(i);
+        }
+        if !P::HAS_TARGETS && difference.is_finite() {
+            total += difference;
+            comparison.max_difference = comparison.max_difference.max(difference);
+        }
+    }
+    if !P::HAS_TARGETS && comparison.rows > 0 {
+        comparison.mean_difference = total / comparison.rows as f64;
+    }
+
+    comparison
+}
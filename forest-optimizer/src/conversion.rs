@@ -0,0 +1,516 @@
+//! Conversions between the nodes of a [`Forest`] and the branches of an
+//! [`OptimizedForest`], both ways, so that an optimized forest can be read
+//! back on the host to be verified.
+
+use std::{collections::HashMap, fmt};
+
+use color_eyre::{Result, eyre::eyre};
+use embedded_rforest::{
+    forest::{self as embedded, BranchLayout, OptimizedForest},
+    ptr::{F32, NodePointer, U16},
+};
+use tracing::debug;
+
+use crate::{
+    forest::{BranchNode, Forest, LeafNode, Node},
+    problem_type::{Classification, FeatureIdx, ProblemType, Regression},
+};
+
+/// A child of a branch of an optimized forest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Child {
+    /// A leaf pointer: a class, or an index into the leaf table
+    Leaf(u32),
+    /// The index of a branch, relative to the root of its tree for
+    /// relative layouts
+    Branch(u32),
+}
+
+/// A branch of an optimized forest of any layout, with its child pointers
+/// decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HostBranch {
+    pub split_with: u32,
+    pub split_at: f32,
+    pub left: Child,
+    pub right: Child,
+}
+
+impl<B: BranchLayout> From<&B> for HostBranch {
+    fn from(branch: &B) -> Self {
+        let child = |ptr, is_leaf| {
+            if is_leaf {
+                Child::Leaf(ptr)
+            } else {
+                Child::Branch(ptr)
+            }
+        };
+
+        Self {
+            split_with: branch.split_with(),
+            split_at: branch.split_at(),
+            left: child(branch.left(), branch.left_is_prediction()),
+            right: child(branch.right(), branch.right_is_prediction()),
+        }
+    }
+}
+
+/// Distinct regression leaf values, in order of first appearance. Values are
+/// compared bit for bit, so every leaf is kept exactly.
+#[derive(Default)]
+pub struct LeafTable {
+    values: Vec<F32>,
+    indices: HashMap<u32, u32>,
+}
+
+impl LeafTable {
+    /// Index of `value` in the table, adding it if it is new.
+    pub fn index(&mut self, value: f32) -> u32 {
+        *self.indices.entry(value.to_bits()).or_insert_with(|| {
+            self.values.push(F32::new(value));
+            self.values.len() as u32 - 1
+        })
+    }
+
+    pub fn values(&self) -> &[F32] {
+        &self.values
+    }
+}
+
+/// Problem types whose forests convert to and from optimized forests, by
+/// the way their predictions are stored in leaf pointers.
+pub trait ToOptimized: ProblemType {
+    /// Leaf pointer of `prediction`, adding it to `leaves` if predictions
+    /// are stored there.
+    fn leaf_pointer(prediction: Self::Output, leaves: &mut LeafTable) -> u32;
+
+    /// Prediction of the leaf pointer `ptr` of `forest`.
+    fn leaf_prediction<B: BranchLayout>(
+        forest: &OptimizedForest<'_, Self::OptimizedType, B>,
+        ptr: u32,
+    ) -> Self::Output;
+}
+
+impl ToOptimized for Classification {
+    /// Leaf pointers hold class indices.
+    fn leaf_pointer(prediction: u32, _: &mut LeafTable) -> u32 {
+        prediction
+    }
+
+    fn leaf_prediction<B: BranchLayout>(
+        _: &OptimizedForest<'_, embedded::Classification, B>,
+        ptr: u32,
+    ) -> u32 {
+        ptr
+    }
+}
+
+impl ToOptimized for Regression {
+    /// Leaf pointers hold indices into the leaf table.
+    fn leaf_pointer(prediction: f32, leaves: &mut LeafTable) -> u32 {
+        leaves.index(prediction)
+    }
+
+    fn leaf_prediction<B: BranchLayout>(
+        forest: &OptimizedForest<'_, embedded::Regression, B>,
+        ptr: u32,
+    ) -> f32 {
+        forest.leaf_value(ptr)
+    }
+}
+
+impl BranchNode {
+    /// Branch standing for the single-leaf tree whose root is the leaf of
+    /// index `leaf`: both of its sides are the leaf, so it predicts the leaf
+    /// whatever the features.
+    pub(crate) fn constant(leaf: u32) -> Self {
+        Self::new(FeatureIdx(0), 0.0, leaf, leaf)
+    }
+
+    /// Optimized branch of this node of `nodes`, folding leaf children into
+    /// its pointers. `branch_ids` holds the index of every node among the
+    /// branches of the forest.
+    fn to_optimized<P: ToOptimized>(
+        &self,
+        nodes: &[Node<P>],
+        branch_ids: &[u32],
+        leaves: &mut LeafTable,
+    ) -> embedded::Branch {
+        let mut child = |idx: u32| match &nodes[idx as usize] {
+            Node::Leaf(leaf) => {
+                NodePointer::new_leaf(P::leaf_pointer(leaf.prediction.into(), leaves))
+            }
+            Node::Branch(_) => NodePointer::new_branch(branch_ids[idx as usize]),
+        };
+        let left = child(self.left);
+        let right = child(self.right);
+
+        embedded::Branch::new(self.split_with.into(), self.split_at, left, right)
+    }
+}
+
+impl<P: ToOptimized> Forest<P> {
+    /// Turn this [`Forest`] into the branches of an [`OptimizedForest`], and
+    /// the leaf table of a regression forest (empty for classification).
+    #[tracing::instrument(name = "optimize", skip_all)]
+    pub fn optimize_nodes(&self) -> (Vec<embedded::Branch>, Vec<F32>) {
+        // Roots stay at their tree index, even those of single-leaf trees,
+        // which become a branch whose both sides are the leaf
+        let is_branch = |i: usize, node: &Node<P>| node.is_branch() || i < self.num_trees();
+
+        // Branches keep their order, so a branch's index is the number of
+        // branches before it
+        let branch_ids = self
+            .nodes()
+            .iter()
+            .enumerate()
+            .scan(0, |next, (i, node)| {
+                let id = *next;
+                *next += u32::from(is_branch(i, node));
+                Some(id)
+            })
+            .collect::<Vec<_>>();
+
+        // Every branch is known from the ids, so the output is allocated
+        // once, at its final size
+        let count = branch_ids
+            .last()
+            .zip(self.nodes().last())
+            .map_or(0, |(&id, node)| {
+                id as usize + usize::from(is_branch(self.nodes().len() - 1, node))
+            });
+        let mut leaves = LeafTable::default();
+        let mut optimized = Vec::with_capacity(count);
+        for (i, node) in self.nodes().iter().enumerate() {
+            let branch = match node {
+                Node::Branch(b) => b,
+                Node::Leaf(_) if is_branch(i, node) => &BranchNode::constant(i as u32),
+                Node::Leaf(_) => continue,
+            };
+            optimized.push(branch.to_optimized(self.nodes(), &branch_ids, &mut leaves));
+        }
+        debug!(
+            nodes = self.nodes().len(),
+            branches = optimized.len(),
+            leaf_values = leaves.values.len(),
+            "Folded leaves into their parents"
+        );
+
+        (optimized, leaves.values)
+    }
+
+    /// Read an optimized forest back into a [`Forest`] solving `problem`,
+    /// which names its features and targets.
+    ///
+    /// The nodes of each tree are stored depth first, so they may be ordered
+    /// differently than in the forest the optimized forest was made from,
+    /// but the trees and their predictions are the same.
+    pub fn from_optimized<B: BranchLayout>(
+        optimized: &OptimizedForest<'_, P::OptimizedType, B>,
+        problem: P,
+    ) -> Result<Self> {
+        if usize::from(optimized.num_features()) > problem.features().len() {
+            return Err(eyre!(
+                "Optimized forest has {} features, but only {} are named",
+                optimized.num_features(),
+                problem.features().len()
+            ));
+        }
+
+        let trees = (0..optimized.num_trees())
+            .map(|tree| {
+                let base = optimized.tree_base(tree);
+                let mut nodes = Vec::new();
+                // Branches left to convert, with the child slot of their
+                // parent
+                let mut stack = vec![(optimized.tree_root(tree), None)];
+                while let Some((branch, slot)) = stack.pop() {
+                    let index = nodes.len() as u32;
+                    if let Some(slot) = slot {
+                        set_child(&mut nodes, slot, index);
+                    }
+
+                    let branch = HostBranch::from(&optimized.nodes()[branch]);
+                    nodes.push(Node::Branch(BranchNode::new(
+                        FeatureIdx(branch.split_with),
+                        branch.split_at,
+                        0,
+                        0,
+                    )));
+
+                    // Leaves directly follow their parent, then come the
+                    // subtrees, left first
+                    let children = [(branch.left, true), (branch.right, false)];
+                    for (child, is_left) in children {
+                        if let Child::Leaf(ptr) = child {
+                            let leaf = nodes.len() as u32;
+                            nodes.push(Node::Leaf(LeafNode::new(
+                                P::leaf_prediction(optimized, ptr).into(),
+                            )));
+                            set_child(&mut nodes, (index, is_left), leaf);
+                        }
+                    }
+                    for (child, is_left) in children.into_iter().rev() {
+                        if let Child::Branch(ptr) = child {
+                            stack.push((base + ptr as usize, Some((index, is_left))));
+                        }
+                    }
+                }
+                nodes
+            })
+            .collect();
+
+        Ok(Self::from_trees(trees, problem).with_split_rule(optimized.split_rule()))
+    }
+}
+
+/// Point the left or right child of the branch `parent` at `child`.
+fn set_child<P: ProblemType>(nodes: &mut [Node<P>], (parent, is_left): (u32, bool), child: u32) {
+    if let Node::Branch(branch) = &mut nodes[parent as usize] {
+        if is_left {
+            branch.left = child;
+        } else {
+            branch.right = child;
+        }
+    }
+}
+
+/// How a node of an optimized forest differs from the node of the
+/// [`Forest`] at the same position, see [`verify_optimization`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// The optimized forest holds a different number of trees
+    TreeCount {
+        expected: usize,
+        found: usize,
+    },
+    SplitWith {
+        expected: u32,
+        found: u32,
+    },
+    /// Thresholds are compared bit for bit
+    SplitAt {
+        expected: f32,
+        found: f32,
+    },
+    /// A leaf predicts something else, both predictions formatted
+    Prediction {
+        expected: String,
+        found: String,
+    },
+    /// A leaf stands where a branch is expected, or the other way around
+    ChildKind {
+        expected_leaf: bool,
+    },
+}
+
+/// First node at which an optimized forest differs from the [`Forest`] it
+/// was made from, located in both forests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub tree: usize,
+    /// Turns taken from the root of the tree, left (`L`) or right (`R`)
+    pub path: String,
+    /// Index of the node in [`Forest::nodes`]
+    pub node: usize,
+    /// Index of the branch in [`OptimizedForest::nodes`], or of the branch
+    /// pointing at it for a leaf
+    pub branch: usize,
+    pub mismatch: Mismatch,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tree {}, path '{}' (node {}, optimized branch {}): ",
+            self.tree, self.path, self.node, self.branch
+        )?;
+        match &self.mismatch {
+            Mismatch::TreeCount { expected, found } => {
+                write!(f, "{found} trees instead of {expected}")
+            }
+            Mismatch::SplitWith { expected, found } => {
+                write!(f, "splits on feature {found} instead of {expected}")
+            }
+            Mismatch::SplitAt { expected, found } => {
+                write!(f, "splits at {found} instead of {expected}")
+            }
+            Mismatch::Prediction { expected, found } => {
+                write!(f, "predicts {found} instead of {expected}")
+            }
+            Mismatch::ChildKind {
+                expected_leaf: true,
+            } => {
+                write!(f, "branch instead of a leaf")
+            }
+            Mismatch::ChildKind {
+                expected_leaf: false,
+            } => write!(f, "leaf instead of a branch"),
+        }
+    }
+}
+
+/// Check that `optimized` is exactly `forest`: walk every tree of both in
+/// lockstep from the root, comparing the split feature and threshold bits
+/// of every branch and the prediction of every leaf. Unlike comparing
+/// predictions over a dataset, this covers paths no row takes.
+///
+/// Thresholds of compact forests are rounded, so they only match forests
+/// whose thresholds were quantized beforehand.
+pub fn verify_optimization<P: ToOptimized, B: BranchLayout>(
+    forest: &Forest<P>,
+    optimized: &OptimizedForest<'_, P::OptimizedType, B>,
+) -> Result<(), Divergence> {
+    let nodes = forest.nodes();
+    if optimized.num_trees() as usize != forest.num_trees() {
+        return Err(Divergence {
+            tree: 0,
+            path: String::new(),
+            node: 0,
+            branch: 0,
+            mismatch: Mismatch::TreeCount {
+                expected: forest.num_trees(),
+                found: optimized.num_trees() as usize,
+            },
+        });
+    }
+
+    for tree in forest.trees() {
+        let base = optimized.tree_base(tree.index() as u32);
+        let root = optimized.tree_root(tree.index() as u32);
+        let diverge = |path: &str, node, branch, mismatch| Divergence {
+            tree: tree.index(),
+            path: path.to_owned(),
+            node,
+            branch,
+            mismatch,
+        };
+
+        // Pairs of branches left to compare, with their path. Only roots
+        // can be leaves, of single-leaf trees, optimized as a constant branch
+        let constant = BranchNode::constant(tree.index() as u32);
+        let mut stack = vec![(tree.index(), root, String::new())];
+        while let Some((node, branch, path)) = stack.pop() {
+            let expected = match &nodes[node] {
+                Node::Branch(expected) => expected,
+                Node::Leaf(_) => &constant,
+            };
+            let found = HostBranch::from(&optimized.nodes()[branch]);
+
+            if FeatureIdx(found.split_with) != expected.split_with {
+                let mismatch = Mismatch::SplitWith {
+                    expected: expected.split_with.into(),
+                    found: found.split_with,
+                };
+                return Err(diverge(&path, node, branch, mismatch));
+            }
+            if found.split_at.to_bits() != expected.split_at.to_bits() {
+                let mismatch = Mismatch::SplitAt {
+                    expected: expected.split_at,
+                    found: found.split_at,
+                };
+                return Err(diverge(&path, node, branch, mismatch));
+            }
+
+            // Push the right side first so the left side is checked first
+            let sides = [
+                (expected.left, found.left, 'L'),
+                (expected.right, found.right, 'R'),
+            ];
+            for (child, ptr, turn) in sides.into_iter().rev() {
+                let path = format!("{path}{turn}");
+                let child = child as usize;
+                match (&nodes[child], ptr) {
+                    (Node::Branch(_), Child::Branch(ptr)) => {
+                        stack.push((child, base + ptr as usize, path))
+                    }
+                    (Node::Leaf(leaf), Child::Leaf(ptr)) => {
+                        let prediction = P::leaf_prediction(optimized, ptr);
+                        if prediction != leaf.prediction.into() {
+                            let mismatch = Mismatch::Prediction {
+                                expected: leaf.prediction.to_string(),
+                                found: prediction.to_string(),
+                            };
+                            return Err(diverge(&path, child, branch, mismatch));
+                        }
+                    }
+                    (expected, _) => {
+                        let mismatch = Mismatch::ChildKind {
+                            expected_leaf: expected.is_leaf(),
+                        };
+                        return Err(diverge(&path, child, branch, mismatch));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Node of `forest` of every leaf id of `optimized`, see
+/// [`OptimizedForest::predict_leaf_ids`]: the index in [`Forest::nodes`]
+/// [`Forest::predict_leaf_ids`] gives for the same leaf, so that statistics
+/// of the leaves computed on the host apply to the ids the device reports.
+///
+/// Fails like [`verify_optimization`] if `optimized` was not made from
+/// `forest`.
+pub fn leaf_ids<P: ToOptimized, B: BranchLayout>(
+    forest: &Forest<P>,
+    optimized: &OptimizedForest<'_, P::OptimizedType, B>,
+) -> Result<HashMap<u32, usize>, Divergence> {
+    verify_optimization(forest, optimized)?;
+
+    let nodes = forest.nodes();
+    let mut ids = HashMap::new();
+    for tree in forest.trees() {
+        let base = optimized.tree_base(tree.index() as u32);
+        // The branch of a single-leaf tree has the leaf on both sides
+        let constant = BranchNode::constant(tree.index() as u32);
+        let mut stack = vec![(tree.index(), optimized.tree_root(tree.index() as u32))];
+        while let Some((node, branch)) = stack.pop() {
+            let expected = match &nodes[node] {
+                Node::Branch(expected) => expected,
+                Node::Leaf(_) => &constant,
+            };
+            let found = HostBranch::from(&optimized.nodes()[branch]);
+
+            let sides = [(expected.left, found.left), (expected.right, found.right)];
+            for (side, (child, ptr)) in (0..).zip(sides) {
+                match ptr {
+                    Child::Leaf(_) => {
+                        ids.insert(2 * branch as u32 + side, child as usize);
+                    }
+                    Child::Branch(ptr) => stack.push((child as usize, base + ptr as usize)),
+                }
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Number of training samples of every leaf of `forest`, indexed by the
+/// leaf ids of `optimized`, see [`support`](embedded_rforest::forest::support):
+/// the table [`OptimizedForest::with_support`] records. Counts above
+/// `u16::MAX` are saturated.
+///
+/// Fails if a leaf has no sample count, or like [`verify_optimization`] if
+/// `optimized` was not made from `forest`.
+pub fn support_table<P: ToOptimized, B: BranchLayout>(
+    forest: &Forest<P>,
+    optimized: &OptimizedForest<'_, P::OptimizedType, B>,
+) -> Result<Vec<U16>> {
+    let ids = leaf_ids(forest, optimized).map_err(|e| eyre!("{e}"))?;
+
+    let mut support = vec![U16::ZERO; 2 * optimized.node_count()];
+    for (id, node) in ids {
+        let samples = forest.nodes()[node]
+            .samples()
+            .ok_or_else(|| eyre!("Leaf {node} of the forest has no sample count"))?;
+        support[id as usize] = U16::new(samples.min(u32::from(u16::MAX)) as u16);
+    }
+
+    Ok(support)
+}
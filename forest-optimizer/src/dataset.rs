@@ -0,0 +1,105 @@
+use std::path::Path;
+
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+
+/// Read a CSV file of feature vectors, one row per observation.
+///
+/// The file must start with a header row, and every column must be numeric.
+/// Columns are taken in file order, so they must already follow the model's
+/// feature indices.
+pub fn read_feature_rows(path: impl AsRef<Path>) -> Result<Vec<Vec<f32>>> {
+    let mut rdr = csv::Reader::from_path(path.as_ref()).context("Could not open dataset file")?;
+
+    let mut rows = Vec::new();
+    for (i, record) in rdr.records().enumerate() {
+        let record = record?;
+        let row = record
+            .iter()
+            .map(|field| field.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| eyre!("Row {} of the dataset is not numeric: {e}", i + 1))?;
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// A dataset whose feature columns have been mapped onto a model's feature
+/// indices, along with the expected output of every row.
+#[derive(Debug, Clone)]
+pub struct LabeledDataset {
+    /// Feature vectors, positioned by feature index
+    pub rows: Vec<Vec<f32>>,
+    /// Expected output of each row, as written in the file
+    pub labels: Vec<String>,
+}
+
+/// Read a CSV dataset, mapping its columns onto features by name.
+///
+/// `features` lists the model's feature names positioned by feature index;
+/// every one of them must be a column of the file. Other columns are
+/// ignored.
+pub fn read_mapped_rows(
+    path: impl AsRef<Path>,
+    features: &[impl AsRef<str>],
+) -> Result<Vec<Vec<f32>>> {
+    Ok(read_mapped(path, features, None)?.rows)
+}
+
+/// Read a CSV dataset, mapping its columns onto features by name.
+///
+/// `features` lists the model's feature names positioned by feature index;
+/// every one of them must be a column of the file. Other columns, except
+/// `label_column`, are ignored.
+pub fn read_labeled(
+    path: impl AsRef<Path>,
+    features: &[impl AsRef<str>],
+    label_column: &str,
+) -> Result<LabeledDataset> {
+    read_mapped(path, features, Some(label_column))
+}
+
+/// Read a CSV dataset, with labels only if `label_column` is given.
+fn read_mapped(
+    path: impl AsRef<Path>,
+    features: &[impl AsRef<str>],
+    label_column: Option<&str>,
+) -> Result<LabeledDataset> {
+    let mut rdr = csv::Reader::from_path(path.as_ref()).context("Could not open dataset file")?;
+
+    let headers = rdr.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| eyre!("Dataset has no column named '{name}'"))
+    };
+    let feature_columns = features
+        .iter()
+        .map(|f| column(f.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    let label_column = label_column.map(column).transpose()?;
+
+    let mut dataset = LabeledDataset {
+        rows: Vec::new(),
+        labels: Vec::new(),
+    };
+    for (i, record) in rdr.records().enumerate() {
+        let record = record?;
+        let row = feature_columns
+            .iter()
+            .map(|&c| record[c].trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| eyre!("Row {} of the dataset is not numeric: {e}", i + 1))?;
+
+        dataset.rows.push(row);
+        if let Some(c) = label_column {
+            dataset.labels.push(record[c].to_string());
+        }
+    }
+
+    Ok(dataset)
+}
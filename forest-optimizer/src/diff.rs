@@ -0,0 +1,302 @@
+use std::fmt;
+
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::forest::{
+    Branch, OptimizedForest, Predict, ProblemType, deserialize::ForestHeader,
+};
+
+use crate::{
+    compare::{Comparison, compare_within},
+    conversion::{Child, HostBranch},
+};
+
+/// Header-level comparison of two serialized forests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderDiff {
+    pub old: ForestHeader,
+    pub new: ForestHeader,
+}
+
+impl HeaderDiff {
+    pub fn new(old: &[u8], new: &[u8]) -> Result<Self> {
+        let old = ForestHeader::peek(old).context("Malformed old forest")?;
+        let new = ForestHeader::peek(new).context("Malformed new forest")?;
+        Ok(Self { old, new })
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.old == self.new
+    }
+
+    /// Whether the two forests can be compared tree by tree: they must solve
+    /// the same kind of problem.
+    pub fn is_comparable(&self) -> bool {
+        self.old.problem_kind() == self.new.problem_kind()
+    }
+}
+
+impl fmt::Display for HeaderDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let targets = |h: &ForestHeader| h.num_targets.map_or(0, |t| t.get());
+        let rows = [
+            (
+                "Trees",
+                self.old.num_trees as usize,
+                self.new.num_trees as usize,
+            ),
+            (
+                "Features",
+                self.old.num_features.into(),
+                self.new.num_features.into(),
+            ),
+            (
+                "Targets",
+                targets(&self.old).into(),
+                targets(&self.new).into(),
+            ),
+            ("Nodes", self.old.node_count, self.new.node_count),
+            ("Leaves", self.old.num_leaves, self.new.num_leaves),
+        ];
+
+        for (name, old, new) in rows {
+            let marker = if old == new { " " } else { "*" };
+            writeln!(f, "{marker} {name:<9} {old:>10} -> {new:<10}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A single structural difference between two trees.
+///
+/// `path` locates the branch whose node or child slot differs, as the
+/// sequence of left (`L`) and right (`R`) turns taken from the tree root.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    SplitChanged { path: String, old: u32, new: u32 },
+    ThresholdChanged { path: String, old: f32, new: f32 },
+    LeafChanged { path: String },
+    SubtreeAdded { path: String },
+    SubtreeRemoved { path: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let root = |p: &str| {
+            if p.is_empty() {
+                "root".to_string()
+            } else {
+                p.to_string()
+            }
+        };
+        match self {
+            Change::SplitChanged { path, old, new } => {
+                write!(f, "{}: split var {old} -> {new}", root(path))
+            }
+            Change::ThresholdChanged { path, old, new } => {
+                write!(f, "{}: threshold {old} -> {new}", root(path))
+            }
+            Change::LeafChanged { path } => write!(f, "{}: prediction changed", root(path)),
+            Change::SubtreeAdded { path } => write!(f, "{}: subtree added", root(path)),
+            Change::SubtreeRemoved { path } => write!(f, "{}: subtree removed", root(path)),
+        }
+    }
+}
+
+/// Structural differences of a single tree present in both forests.
+#[derive(Debug, Clone, Default)]
+pub struct TreeDiff {
+    pub tree: u32,
+    pub changes: Vec<Change>,
+}
+
+impl TreeDiff {
+    fn count(&self, pred: impl Fn(&Change) -> bool) -> usize {
+        self.changes.iter().filter(|c| pred(c)).count()
+    }
+}
+
+/// Tree-by-tree structural comparison of two forests.
+#[derive(Debug, Clone, Default)]
+pub struct StructuralDiff {
+    /// Trees present in both forests which differ in any way
+    pub trees: Vec<TreeDiff>,
+    pub trees_added: u32,
+    pub trees_removed: u32,
+}
+
+impl StructuralDiff {
+    pub fn is_identical(&self) -> bool {
+        self.trees.is_empty() && self.trees_added == 0 && self.trees_removed == 0
+    }
+
+    fn count(&self, pred: impl Fn(&Change) -> bool + Copy) -> usize {
+        self.trees.iter().map(|t| t.count(pred)).sum()
+    }
+
+    pub fn splits_changed(&self) -> usize {
+        self.count(|c| matches!(c, Change::SplitChanged { .. }))
+    }
+
+    pub fn thresholds_changed(&self) -> usize {
+        self.count(|c| matches!(c, Change::ThresholdChanged { .. }))
+    }
+
+    pub fn leaves_changed(&self) -> usize {
+        self.count(|c| matches!(c, Change::LeafChanged { .. }))
+    }
+
+    pub fn subtrees_added(&self) -> usize {
+        self.count(|c| matches!(c, Change::SubtreeAdded { .. }))
+    }
+
+    pub fn subtrees_removed(&self) -> usize {
+        self.count(|c| matches!(c, Change::SubtreeRemoved { .. }))
+    }
+
+    /// Detailed listing of every change, tree by tree
+    pub fn details(&self) -> impl fmt::Display + '_ {
+        StructuralDetails(self)
+    }
+}
+
+impl fmt::Display for StructuralDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Trees changed:      {}", self.trees.len())?;
+        writeln!(f, "Trees added:        {}", self.trees_added)?;
+        writeln!(f, "Trees removed:      {}", self.trees_removed)?;
+        writeln!(f, "Splits changed:     {}", self.splits_changed())?;
+        writeln!(f, "Thresholds changed: {}", self.thresholds_changed())?;
+        writeln!(f, "Leaves changed:     {}", self.leaves_changed())?;
+        writeln!(f, "Subtrees added:     {}", self.subtrees_added())?;
+        writeln!(f, "Subtrees removed:   {}", self.subtrees_removed())
+    }
+}
+
+struct StructuralDetails<'a>(&'a StructuralDiff);
+
+impl fmt::Display for StructuralDetails<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for tree in &self.0.trees {
+            writeln!(f, "Tree {}:", tree.tree)?;
+            for change in &tree.changes {
+                writeln!(f, "\t{change}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Children of a branch of `forest`. Regression leaves are compared by the
+/// bits of their value, as leaf table indices differ between forests.
+fn children<P: ProblemType>(forest: &OptimizedForest<'_, P>, branch: &Branch) -> [Child; 2] {
+    let branch = HostBranch::from(branch);
+    let child = |child| match child {
+        Child::Leaf(ptr) if !P::HAS_TARGETS => Child::Leaf(forest.leaf_value(ptr).to_bits()),
+        child => child,
+    };
+
+    [child(branch.left), child(branch.right)]
+}
+
+/// Compare two forests tree by tree.
+///
+/// Trees are matched by index, and each pair of trees is walked in lockstep
+/// from the root: branches at the same position are compared by split
+/// variable and threshold, and child slots by kind and prediction.
+pub fn diff_structure<P: ProblemType>(
+    old: &OptimizedForest<'_, P>,
+    new: &OptimizedForest<'_, P>,
+) -> StructuralDiff {
+    let common = old.num_trees().min(new.num_trees());
+
+    let mut trees = Vec::new();
+    for tree in 0..common {
+        let diff = diff_tree(old, new, tree);
+        if !diff.changes.is_empty() {
+            trees.push(diff);
+        }
+    }
+
+    StructuralDiff {
+        trees,
+        trees_added: new.num_trees().saturating_sub(old.num_trees()),
+        trees_removed: old.num_trees().saturating_sub(new.num_trees()),
+    }
+}
+
+fn diff_tree<P: ProblemType>(
+    old: &OptimizedForest<'_, P>,
+    new: &OptimizedForest<'_, P>,
+    tree: u32,
+) -> TreeDiff {
+    let mut changes = Vec::new();
+    let mut stack = vec![(old.tree_root(tree), new.tree_root(tree), String::new())];
+
+    while let Some((old_idx, new_idx, path)) = stack.pop() {
+        let (old_branch, new_branch) = (&old.nodes()[old_idx], &new.nodes()[new_idx]);
+
+        if old_branch.split_with() != new_branch.split_with() {
+            changes.push(Change::SplitChanged {
+                path: path.clone(),
+                old: old_branch.split_with(),
+                new: new_branch.split_with(),
+            });
+        } else if old_branch.split_at().to_bits() != new_branch.split_at().to_bits() {
+            changes.push(Change::ThresholdChanged {
+                path: path.clone(),
+                old: old_branch.split_at(),
+                new: new_branch.split_at(),
+            });
+        }
+
+        // Push the right side first so the left side is reported first
+        let sides = children(old, old_branch)
+            .into_iter()
+            .zip(children(new, new_branch));
+        for ((old_child, new_child), turn) in sides.zip(['L', 'R']).rev() {
+            let path = format!("{path}{turn}");
+            match (old_child, new_child) {
+                (Child::Branch(a), Child::Branch(b)) => stack.push((a as usize, b as usize, path)),
+                (Child::Leaf(a), Child::Leaf(b)) if a != b => {
+                    changes.push(Change::LeafChanged { path })
+                }
+                (Child::Leaf(_), Child::Leaf(_)) => {}
+                (Child::Leaf(_), Child::Branch(_)) => changes.push(Change::SubtreeAdded { path }),
+                (Child::Branch(_), Child::Leaf(_)) => changes.push(Change::SubtreeRemoved { path }),
+            }
+        }
+    }
+
+    TreeDiff { tree, changes }
+}
+
+/// Run both forests over every row and record where their predictions
+/// differ by more than `tolerance`, with [`compare_within`]. Fails if a row
+/// lacks features of either forest.
+pub fn diff_behavior<'a, P>(
+    old: &OptimizedForest<'a, P>,
+    new: &OptimizedForest<'a, P>,
+    rows: &[Vec<f32>],
+    tolerance: f64,
+) -> Result<Comparison>
+where
+    P: ProblemType,
+    P::Output: Into<f64>,
+    OptimizedForest<'a, P>: Predict<ProblemType = P>,
+{
+    let needed = old.num_features().max(new.num_features()) as usize;
+
+    if let Some(i) = rows.iter().position(|row| row.len() < needed) {
+        return Err(eyre!(
+            "Row {} has {} features, the forests need {needed}",
+            i + 1,
+            rows[i].len()
+        ));
+    }
+
+    Ok(compare_within(old, new, rows, tolerance))
+}
@@ -0,0 +1,703 @@
+//! Output artifacts of a converted forest.
+//!
+//! A forest is optimized once, then handed to one emitter per requested
+//! [`OutputFormat`]. Every artifact is written to a temporary file first, and
+//! only moved into place once all of them succeeded.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use aligned_vec::AVec;
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::forest::{
+    BranchLayout, OptimizedForest, ProblemType,
+    endian::{ByteOrder, NODE_BYTE_ORDER, swap_byte_order},
+};
+use tempfile::NamedTempFile;
+
+use crate::{
+    encryption::{EncryptionKey, encrypt},
+    metadata::ForestMetadata,
+    write_forest::Endianness,
+};
+
+/// Format of an output artifact
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Binary forest, loadable with `OptimizedForest::deserialize`
+    Rforest,
+    /// C header holding the binary forest as a byte array
+    CHeader,
+    /// Rust module holding the binary forest as a `BackingStorage`
+    RustModule,
+    /// Human-readable JSON dump of the optimized nodes
+    Json,
+    /// Rust constants of the feature and class indices, see
+    /// [`IndexConsts`]
+    RustIndices,
+    /// C defines of the feature and class indices, see [`IndexConsts`]
+    CIndices,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Rforest => "rforest",
+            Self::CHeader => "h",
+            Self::RustModule => "rs",
+            Self::Json => "json",
+            Self::RustIndices => "rs",
+            Self::CIndices => "h",
+        }
+    }
+
+    /// Path of the artifact of this format written next to `output`: with
+    /// the extension of the format, and for index constants, the suffix
+    /// `_indices`, so they do not overwrite the source code of the forest.
+    pub fn path_next_to(&self, output: &Path) -> PathBuf {
+        match self {
+            Self::RustIndices | Self::CIndices => {
+                let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+                output.with_file_name(format!("{stem}_indices.{}", self.extension()))
+            }
+            _ => output.with_extension(self.extension()),
+        }
+    }
+
+    /// Write `forest` in this format, its nodes in the byte order
+    /// `endianness`, and encrypted with `key` if given, see
+    /// [`encryption`](crate::encryption). `name` is used to derive
+    /// identifiers in source code artifacts.
+    ///
+    /// Fails for [`OutputFormat::Json`] with a key, as it would dump the
+    /// nodes in the clear.
+    pub fn emit<P: ProblemType, B: BranchLayout>(
+        &self,
+        forest: &OptimizedForest<'_, P, B>,
+        metadata: &ForestMetadata,
+        endianness: Endianness,
+        key: Option<&EncryptionKey>,
+        name: &str,
+        out: &mut (impl Write + ?Sized),
+    ) -> Result<()> {
+        let encrypted = key.is_some();
+        match self {
+            Self::Rforest => out.write_all(&forest_bytes(forest, endianness, key)?)?,
+            Self::CHeader => {
+                let bytes = forest_bytes(forest, endianness, key)?;
+                let header = c_header(forest, &bytes, encrypted, metadata, name)?;
+                out.write_all(header.as_bytes())?
+            }
+            Self::RustModule => {
+                let bytes = forest_bytes(forest, endianness, key)?;
+                let module = rust_module(forest, &bytes, encrypted, metadata, name)?;
+                out.write_all(module.as_bytes())?
+            }
+            Self::Json if encrypted => {
+                return Err(eyre!(
+                    "A JSON dump of an encrypted forest would hold its nodes in the clear"
+                ));
+            }
+            Self::Json => serde_json::to_writer_pretty(out, &JsonForest::new(forest, metadata))?,
+            Self::RustIndices => out.write_all(rust_indices(metadata)?.as_bytes())?,
+            Self::CIndices => out.write_all(c_indices(metadata, name)?.as_bytes())?,
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rforest => write!(f, "rforest"),
+            Self::CHeader => write!(f, "c-header"),
+            Self::RustModule => write!(f, "rust-module"),
+            Self::Json => write!(f, "json"),
+            Self::RustIndices => write!(f, "rust-indices"),
+            Self::CIndices => write!(f, "c-indices"),
+        }
+    }
+}
+
+/// An artifact to emit: a format, and optionally the path to write it to.
+///
+/// Parsed from `FORMAT` or `FORMAT=PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatSpec {
+    pub format: OutputFormat,
+    pub path: Option<PathBuf>,
+}
+
+impl FormatSpec {
+    pub fn new(format: OutputFormat) -> Self {
+        Self { format, path: None }
+    }
+}
+
+impl FromStr for FormatSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use clap::ValueEnum;
+
+        let (format, path) = match s.split_once('=') {
+            Some((format, path)) => (format, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        let format = OutputFormat::from_str(format, true)?;
+
+        Ok(Self { format, path })
+    }
+}
+
+/// Where to write each artifact.
+///
+/// A single artifact without an explicit path is written to `output` as is.
+/// Otherwise, artifacts without an explicit path are written next to
+/// `output`, see [`OutputFormat::path_next_to`].
+pub fn artifact_paths(output: &Path, specs: &[FormatSpec]) -> Vec<(OutputFormat, PathBuf)> {
+    match specs {
+        [FormatSpec { format, path: None }] => vec![(*format, output.to_path_buf())],
+        specs => specs
+            .iter()
+            .map(|spec| {
+                let path = spec
+                    .path
+                    .clone()
+                    .unwrap_or_else(|| spec.format.path_next_to(output));
+                (spec.format, path)
+            })
+            .collect(),
+    }
+}
+
+/// Fail if any of `artifacts`, or the metadata sidecar at `metadata_path`,
+/// already exists.
+pub fn check_overwrite(artifacts: &[(OutputFormat, PathBuf)], metadata_path: &Path) -> Result<()> {
+    let existing = artifacts
+        .iter()
+        .map(|(_, path)| path.as_path())
+        .chain([metadata_path])
+        .find(|path| path.exists());
+
+    match existing {
+        Some(path) => Err(eyre!(
+            "{} already exists. Pass --force to overwrite it",
+            path.display()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Emit every artifact, then the metadata sidecar. Nothing is written unless
+/// all of them could be produced.
+pub fn emit_all<P: ProblemType, B: BranchLayout>(
+    forest: &OptimizedForest<'_, P, B>,
+    metadata: &ForestMetadata,
+    endianness: Endianness,
+    key: Option<&EncryptionKey>,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: &Path,
+) -> Result<()> {
+    let mut pending = Vec::with_capacity(artifacts.len() + 1);
+
+    for (format, path) in artifacts {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "forest".to_string());
+
+        let mut file = temp_file_for(path)?;
+        format
+            .emit(forest, metadata, endianness, key, &name, &mut file)
+            .with_context(|| format!("Could not write {format} output"))?;
+        pending.push((file, path.as_path()));
+    }
+
+    let mut file = temp_file_for(metadata_path)?;
+    serde_json::to_writer_pretty(&mut file, metadata)?;
+    pending.push((file, metadata_path));
+
+    for (file, path) in pending {
+        file.persist(path)
+            .with_context(|| format!("Could not create output file {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Temporary file in the directory of `path`, so it can be renamed onto it
+fn temp_file_for(path: &Path) -> Result<NamedTempFile> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    NamedTempFile::new_in(dir)
+        .with_context(|| format!("Could not create output file {}", path.display()))
+}
+
+/// `forest` serialized with its nodes in the byte order `endianness`, then
+/// encrypted with `key` if given
+fn forest_bytes<P: ProblemType, B: BranchLayout>(
+    forest: &OptimizedForest<'_, P, B>,
+    endianness: Endianness,
+    key: Option<&EncryptionKey>,
+) -> Result<AVec<u8>> {
+    let mut bytes = forest.to_bytes();
+    if ByteOrder::from(endianness) != NODE_BYTE_ORDER {
+        swap_byte_order(&mut bytes).context("Could not swap byte order")?;
+    }
+    match key {
+        Some(key) => encrypt(&bytes, key),
+        None => Ok(bytes),
+    }
+}
+
+/// `name` turned into a valid C or Rust identifier
+fn identifier(name: &str) -> String {
+    let mut ident = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+/// Bytes as comma-separated hex literals, 16 per line
+fn byte_array(bytes: &[u8], indent: &str) -> Result<String> {
+    let mut out = String::new();
+    for line in bytes.chunks(16) {
+        out.push_str(indent);
+        for byte in line {
+            write!(out, "0x{byte:02x}, ")?;
+        }
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn c_header<P: ProblemType, B: BranchLayout>(
+    forest: &OptimizedForest<'_, P, B>,
+    bytes: &[u8],
+    encrypted: bool,
+    metadata: &ForestMetadata,
+    name: &str,
+) -> Result<String> {
+    let ident = identifier(name).to_lowercase();
+    let guard = identifier(name).to_uppercase();
+
+    let mut out = String::new();
+    writeln!(out, "/* Generated by forest-optimizer. Do not edit. */")?;
+    writeln!(
+        out,
+        "/* {} forest: {} trees, {} features, {} nodes */",
+        metadata.problem_type,
+        forest.num_trees(),
+        forest.num_features(),
+        forest.nodes().len()
+    )?;
+    writeln!(out, "#ifndef {guard}_H")?;
+    writeln!(out, "#define {guard}_H\n")?;
+    writeln!(out, "#include <stddef.h>")?;
+    writeln!(out, "#include <stdint.h>\n")?;
+    if encrypted {
+        writeln!(
+            out,
+            "/* Encrypted: load with OptimizedForest::deserialize_encrypted */"
+        )?;
+    }
+    writeln!(
+        out,
+        "static const uint8_t {ident}_data[{}] __attribute__((aligned(8))) = {{",
+        bytes.len()
+    )?;
+    out.push_str(&byte_array(bytes, "    ")?);
+    writeln!(out, "}};")?;
+    writeln!(out, "static const size_t {ident}_len = {};", bytes.len())?;
+    if forest.feature_fingerprint() != 0 {
+        writeln!(
+            out,
+            "/* Fingerprint of the feature and target names, see embedded_rforest::forest::fingerprint */"
+        )?;
+        writeln!(
+            out,
+            "static const uint32_t {ident}_fingerprint = {:#010x}u;",
+            forest.feature_fingerprint()
+        )?;
+        writeln!(
+            out,
+            "#define {guard}_FINGERPRINT {:#010x}u",
+            forest.feature_fingerprint()
+        )?;
+    }
+    if let Some(scales) = &metadata.fixed_point {
+        writeln!(
+            out,
+            "/* Fixed-point shift of every feature: pass feature j as floor(value * 2^shift[j]) */"
+        )?;
+        writeln!(
+            out,
+            "static const int8_t {ident}_feature_shifts[{}] = {{",
+            scales.feature_shifts.len()
+        )?;
+        for (shift, feature) in scales.feature_shifts.iter().zip(&metadata.features) {
+            writeln!(out, "    {shift}, /* {feature} */")?;
+        }
+        writeln!(out, "}};")?;
+        if let Some(leaf_shift) = scales.leaf_shift {
+            writeln!(
+                out,
+                "/* Shift of the predictions: p stands for p / 2^leaf_shift */"
+            )?;
+            writeln!(
+                out,
+                "static const int8_t {ident}_leaf_shift = {leaf_shift};"
+            )?;
+        }
+    }
+    writeln!(out)?;
+    writeln!(out, "#endif /* {guard}_H */")?;
+
+    Ok(out)
+}
+
+fn rust_module<P: ProblemType, B: BranchLayout>(
+    forest: &OptimizedForest<'_, P, B>,
+    bytes: &[u8],
+    encrypted: bool,
+    metadata: &ForestMetadata,
+    name: &str,
+) -> Result<String> {
+    let ident = identifier(name).to_uppercase();
+    // Vouched for by the `_VALIDATED` constant
+    forest.validate().context("Malformed forest")?;
+
+    let mut out = String::new();
+    // Plain comments, so the module can also be `include!`d
+    writeln!(out, "// Generated by forest-optimizer. Do not edit.")?;
+    writeln!(
+        out,
+        "// {} forest: {} trees, {} features, {} nodes",
+        metadata.problem_type,
+        forest.num_trees(),
+        forest.num_features(),
+        forest.nodes().len()
+    )?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "use embedded_rforest::forest::deserialize::BackingStorage;\n"
+    )?;
+    writeln!(
+        out,
+        "pub static {ident}: BackingStorage<{len}> = BackingStorage::new([",
+        len = bytes.len()
+    )?;
+    out.push_str(&byte_array(bytes, "    ")?);
+    writeln!(out, "]);")?;
+    writeln!(out)?;
+    if encrypted {
+        writeln!(
+            out,
+            "/// `{ident}` is encrypted: load it with\n\
+             /// `OptimizedForest::deserialize_encrypted`"
+        )?;
+        writeln!(out, "pub const {ident}_ENCRYPTED: bool = true;")?;
+    } else {
+        writeln!(
+            out,
+            "/// `{ident}` passed `OptimizedForest::deserialize` when generated, so it\n\
+             /// may be loaded with `OptimizedForest::deserialize_unchecked`"
+        )?;
+        writeln!(out, "pub const {ident}_VALIDATED: bool = true;")?;
+    }
+    if forest.feature_fingerprint() != 0 {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "/// Fingerprint of the feature and target names the forest expects, to\n\
+             /// pass to `OptimizedForest::check_fingerprint`"
+        )?;
+        writeln!(
+            out,
+            "pub const {ident}_FINGERPRINT: u32 = {:#010x};",
+            forest.feature_fingerprint()
+        )?;
+    }
+    if let Some(scales) = &metadata.fixed_point {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "/// Fixed-point shift of every feature, by index: pass feature `j` to\n\
+             /// `predict_i32` as `floor(value * 2^shift[j])`, see\n\
+             /// `embedded_rforest::forest::integer::to_fixed`"
+        )?;
+        writeln!(
+            out,
+            "pub const {ident}_FEATURE_SHIFTS: [i8; {}] = [",
+            scales.feature_shifts.len()
+        )?;
+        for (shift, feature) in scales.feature_shifts.iter().zip(&metadata.features) {
+            writeln!(out, "    {shift}, // {feature}")?;
+        }
+        writeln!(out, "];")?;
+        if let Some(leaf_shift) = scales.leaf_shift {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "/// Shift of the predictions of `predict_i32`: `p` stands for\n\
+                 /// `p / 2^{ident}_LEAF_SHIFT`"
+            )?;
+            writeln!(out, "pub const {ident}_LEAF_SHIFT: i8 = {leaf_shift};")?;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Index constants of the features and classes of a forest, for firmware
+/// to fill feature arrays and read predictions without magic numbers.
+///
+/// Names become identifiers with [`const_identifier`], prefixed with
+/// `FEATURE_` or `CLASS_`. The fingerprint of the names is stamped along,
+/// so that stale constants can be told from those of the forest at build
+/// time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexConsts {
+    /// [`fingerprint`](embedded_rforest::forest::fingerprint) of the
+    /// feature and target names
+    pub fingerprint: u32,
+    /// Identifier of every feature, by index
+    pub features: Vec<String>,
+    /// Identifier of every class, by index. Empty for regression
+    pub classes: Vec<String>,
+}
+
+impl IndexConsts {
+    /// Constants of the features and classes of `metadata`.
+    ///
+    /// Fails if a name has no letter or digit, or if two features, or two
+    /// classes, map to the same identifier.
+    pub fn new(metadata: &ForestMetadata) -> Result<Self> {
+        Ok(Self {
+            fingerprint: metadata.fingerprint,
+            features: prefixed_identifiers(&metadata.features, "FEATURE_", "Features")?,
+            classes: prefixed_identifiers(
+                metadata.targets.as_deref().unwrap_or_default(),
+                "CLASS_",
+                "Classes",
+            )?,
+        })
+    }
+}
+
+/// `name` as the identifier of a constant: its ASCII letters uppercased,
+/// and every run of other characters than ASCII letters and digits turned
+/// into a single `_`, without leading or trailing `_`. `sepal.length`
+/// becomes `SEPAL_LENGTH` and `Iris-setosa` `IRIS_SETOSA`. `None` if no
+/// letter or digit is left, as in `--`.
+pub fn const_identifier(name: &str) -> Option<String> {
+    let ident = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect::<Vec<_>>()
+        .join("_");
+    (!ident.is_empty()).then_some(ident)
+}
+
+/// [`const_identifier`] of every name, prefixed with `prefix`, checked for
+/// collisions
+fn prefixed_identifiers(names: &[String], prefix: &str, kind: &str) -> Result<Vec<String>> {
+    let mut seen = HashMap::new();
+    names
+        .iter()
+        .map(|name| {
+            let ident = const_identifier(name)
+                .map(|ident| format!("{prefix}{ident}"))
+                .ok_or_else(|| {
+                    eyre!("Cannot name a constant after '{name}': it has no letter or digit")
+                })?;
+            if let Some(other) = seen.insert(ident.clone(), name) {
+                return Err(eyre!(
+                    "{kind} '{other}' and '{name}' would both be named {ident}. Rename one of them"
+                ));
+            }
+            Ok(ident)
+        })
+        .collect()
+}
+
+fn rust_indices(metadata: &ForestMetadata) -> Result<String> {
+    let consts = IndexConsts::new(metadata)?;
+
+    let mut out = String::new();
+    writeln!(out, "// Generated by forest-optimizer. Do not edit.")?;
+    writeln!(
+        out,
+        "// Indices of the features and classes of the {} forest",
+        metadata.problem_type
+    )?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/// Fingerprint of the feature and target names. Indices of another\n\
+         /// version of the forest differ from its `FINGERPRINT`, see\n\
+         /// `embedded_rforest::forest_consts!`"
+    )?;
+    writeln!(
+        out,
+        "pub const FINGERPRINT: u32 = {:#010x};",
+        consts.fingerprint
+    )?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "pub const NUM_FEATURES: usize = {};",
+        consts.features.len()
+    )?;
+    writeln!(
+        out,
+        "pub const NUM_TARGETS: usize = {};",
+        consts.classes.len()
+    )?;
+    for idents in [&consts.features, &consts.classes] {
+        if !idents.is_empty() {
+            writeln!(out)?;
+        }
+        for (index, ident) in idents.iter().enumerate() {
+            writeln!(out, "pub const {ident}: usize = {index};")?;
+        }
+    }
+
+    Ok(out)
+}
+
+fn c_indices(metadata: &ForestMetadata, name: &str) -> Result<String> {
+    let consts = IndexConsts::new(metadata)?;
+    let guard = format!("ERF_{}_H", identifier(name).to_uppercase());
+
+    let mut out = String::new();
+    writeln!(out, "/* Generated by forest-optimizer. Do not edit. */")?;
+    writeln!(
+        out,
+        "/* Indices of the features and classes of the {} forest */",
+        metadata.problem_type
+    )?;
+    writeln!(out, "#ifndef {guard}")?;
+    writeln!(out, "#define {guard}\n")?;
+    writeln!(
+        out,
+        "/* Fingerprint of the feature and target names. Indices of another\n   \
+         version of the forest differ from the _FINGERPRINT of its header */"
+    )?;
+    writeln!(
+        out,
+        "#define ERF_FINGERPRINT {:#010x}u\n",
+        consts.fingerprint
+    )?;
+    writeln!(out, "#define ERF_NUM_FEATURES {}", consts.features.len())?;
+    writeln!(out, "#define ERF_NUM_TARGETS {}", consts.classes.len())?;
+    for idents in [&consts.features, &consts.classes] {
+        if !idents.is_empty() {
+            writeln!(out)?;
+        }
+        for (index, ident) in idents.iter().enumerate() {
+            writeln!(out, "#define ERF_{ident} {index}")?;
+        }
+    }
+    writeln!(out)?;
+    writeln!(out, "#endif /* {guard} */")?;
+
+    Ok(out)
+}
+
+/// JSON representation of an optimized forest
+#[derive(serde::Serialize)]
+struct JsonForest<'a> {
+    #[serde(flatten)]
+    metadata: &'a ForestMetadata,
+    num_trees: u32,
+    num_features: u8,
+    /// `<=` or `<`: features go left if they compare so to the threshold
+    split_rule: String,
+    /// Index of the root of every tree, if not the index of the tree
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tree_offsets: Option<Vec<u32>>,
+    nodes: Vec<JsonBranch>,
+}
+
+#[derive(serde::Serialize)]
+struct JsonBranch {
+    split_var: u32,
+    split_at: f32,
+    left: JsonChild,
+    right: JsonChild,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JsonChild {
+    Branch(u32),
+    Class(u32),
+    Value(f32),
+}
+
+impl<'a> JsonForest<'a> {
+    fn new<P: ProblemType, B: BranchLayout>(
+        forest: &OptimizedForest<'_, P, B>,
+        metadata: &'a ForestMetadata,
+    ) -> Self {
+        let tree_offsets = forest
+            .tree_offsets()
+            .iter()
+            .map(|offset| offset.get())
+            .collect::<Vec<_>>();
+        // Branch indices are absolute in JSON, even if the forest stores them
+        // relative to the root of their tree
+        let base = |index: usize| match tree_offsets.partition_point(|&o| o as usize <= index) {
+            tree if B::RELATIVE && tree > 0 => tree_offsets[tree - 1],
+            _ => 0,
+        };
+        let child = |ptr: u32, is_prediction: bool, base: u32| match (is_prediction, P::HAS_TARGETS)
+        {
+            (false, _) => JsonChild::Branch(base + ptr),
+            (true, true) => JsonChild::Class(ptr),
+            (true, false) => JsonChild::Value(forest.leaf_value(ptr)),
+        };
+
+        let nodes = forest
+            .nodes()
+            .iter()
+            .enumerate()
+            .map(|(index, node): (usize, &B)| JsonBranch {
+                split_var: node.split_with(),
+                split_at: node.split_at(),
+                left: child(node.left(), node.left_is_prediction(), base(index)),
+                right: child(node.right(), node.right_is_prediction(), base(index)),
+            })
+            .collect();
+
+        Self {
+            metadata,
+            num_trees: forest.num_trees(),
+            num_features: forest.num_features(),
+            split_rule: forest.split_rule().to_string(),
+            tree_offsets: (!tree_offsets.is_empty()).then_some(tree_offsets),
+            nodes,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Encryption of serialized forests with `convert --encrypt-key`, which
+//! devices decrypt with
+//! [`OptimizedForest::deserialize_encrypted`](embedded_rforest::forest::OptimizedForest::deserialize_encrypted),
+//! see [`encryption`](embedded_rforest::forest::encryption) for the format.
+//!
+//! The forest is serialized as usual, in the byte order of its target,
+//! then everything following its header is encrypted, and the section of
+//! the encryption inserted at the end of the header. Every forest gets a
+//! random nonce, so the same key can encrypt several.
+
+use std::{fmt, str::FromStr};
+
+use aligned_vec::AVec;
+use color_eyre::{Result, eyre::eyre};
+use embedded_rforest::forest::{
+    abi::FORMAT_ABI,
+    deserialize::{BUFFER_ALIGN, ENCRYPTED, ForestHeader},
+    encryption::{ENCRYPTION_LEN, EncryptionHeader, KEY_LEN, NONCE_LEN},
+};
+use zerocopy::IntoBytes;
+
+use crate::sections::{Section, size_breakdown};
+
+/// Key of an encrypted forest, parsed from 64 hex digits, or `@FILE`
+/// holding them. Not printed by `Debug`, so it stays out of logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey(pub [u8; KEY_LEN]);
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl FromStr for EncryptionKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = match s.strip_prefix('@') {
+            Some(path) => {
+                std::fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?
+            }
+            None => s.to_string(),
+        };
+        let hex = hex.trim();
+        if hex.len() != 2 * KEY_LEN || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!(
+                "The key must be {} hex digits, for {KEY_LEN} bytes",
+                2 * KEY_LEN
+            ));
+        }
+
+        let mut key = [0; KEY_LEN];
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            // ASCII hex digits, checked above
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap();
+        }
+        Ok(Self(key))
+    }
+}
+
+/// Copy of the serialized forest in `buffer` encrypted with `key`, under a
+/// random nonce.
+pub fn encrypt(buffer: &[u8], key: &EncryptionKey) -> Result<AVec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::fill(&mut nonce).map_err(|e| eyre!("Could not draw a nonce: {e}"))?;
+    encrypt_with_nonce(buffer, key, nonce)
+}
+
+/// Size of the serialized forest in `buffer` once [`encrypt`]ed, without
+/// encrypting it.
+pub fn encrypted_len(buffer: &[u8]) -> Result<usize> {
+    let header = ForestHeader::peek(buffer).map_err(|e| eyre!("Malformed forest header: {e:?}"))?;
+    if header.encrypted {
+        return Err(eyre!("The forest is already encrypted"));
+    }
+    let (_, header_len) = encrypted_header(&header);
+    Ok(header_len + buffer.len() - header.header_len)
+}
+
+/// Where the section of the encryption goes in the header of a forest, and
+/// the length of the header with it.
+fn encrypted_header(header: &ForestHeader) -> (usize, usize) {
+    // The section follows the other sections of the header, and the nodes
+    // are padded again after it
+    let offset = size_breakdown(header)
+        .iter()
+        .find(|size| matches!(size.section, Section::Padding | Section::Nodes))
+        .map_or(header.header_len, |size| size.offset);
+    (
+        offset,
+        (offset + ENCRYPTION_LEN).next_multiple_of(BUFFER_ALIGN),
+    )
+}
+
+/// [`encrypt`] under `nonce`, which must never be used twice with the same
+/// key: the forests it encrypts could be told apart from each other.
+pub fn encrypt_with_nonce(
+    buffer: &[u8],
+    key: &EncryptionKey,
+    nonce: [u8; NONCE_LEN],
+) -> Result<AVec<u8>> {
+    let header = ForestHeader::peek(buffer).map_err(|e| eyre!("Malformed forest header: {e:?}"))?;
+    if header.encrypted {
+        return Err(eyre!("The forest is already encrypted"));
+    }
+
+    let (offset, header_len) = encrypted_header(&header);
+    let mut payload = buffer[header.header_len..].to_vec();
+    let section = EncryptionHeader::encrypt(&key.0, nonce, &mut payload);
+
+    let mut encrypted = AVec::with_capacity(BUFFER_ALIGN, header_len + payload.len());
+    encrypted.extend_from_slice(&buffer[..offset]);
+    encrypted.extend_from_slice(section.as_bytes());
+    encrypted.resize(header_len, 0);
+    encrypted.extend_from_slice(&payload);
+
+    let header_len_at = FORMAT_ABI.header.field("header_len").offset;
+    let header_len = u16::try_from(header_len).map_err(|_| eyre!("Header is too long"))?;
+    encrypted[header_len_at..header_len_at + 2].copy_from_slice(&header_len.to_le_bytes());
+    encrypted[FORMAT_ABI.header.field("extra_flags").offset] |= ENCRYPTED;
+
+    ForestHeader::peek(&encrypted).map_err(|e| eyre!("Encrypted forest is malformed: {e:?}"))?;
+    Ok(encrypted)
+}
@@ -0,0 +1,61 @@
+use color_eyre::{Result, eyre::eyre};
+use embedded_rforest::forest::{Classification, Regression};
+
+use crate::{
+    compare::PredictLike,
+    dataset::LabeledDataset,
+    metrics::{
+        ClassificationMetrics, RegressionMetrics, classification_metrics, regression_metrics,
+    },
+};
+
+/// Measure the accuracy of a classification forest, host or optimized, on a
+/// labeled dataset.
+///
+/// `targets` lists the forest's class labels positioned by class index, and
+/// is used to map the dataset's labels onto classes.
+pub fn evaluate_classification(
+    forest: &impl PredictLike<Classification>,
+    dataset: &LabeledDataset,
+    targets: &[String],
+) -> Result<ClassificationMetrics> {
+    let truth = dataset
+        .labels
+        .iter()
+        .map(|label| {
+            targets
+                .iter()
+                .position(|t| t == label)
+                .map(|idx| idx as u32)
+                .ok_or_else(|| eyre!("Dataset label '{label}' is not a target of the forest"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let predicted = dataset
+        .rows
+        .iter()
+        .map(|row| forest.predict_like(row))
+        .collect::<Vec<_>>();
+
+    Ok(classification_metrics(&predicted, &truth, targets.len()))
+}
+
+/// Measure the error of a regression forest, host or optimized, on a labeled
+/// dataset.
+pub fn evaluate_regression(
+    forest: &impl PredictLike<Regression>,
+    dataset: &LabeledDataset,
+) -> Result<RegressionMetrics> {
+    let truth = dataset
+        .labels
+        .iter()
+        .map(|label| label.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| eyre!("Dataset label is not numeric: {e}"))?;
+    let predicted = dataset
+        .rows
+        .iter()
+        .map(|row| forest.predict_like(row))
+        .collect::<Vec<_>>();
+
+    Ok(regression_metrics(&predicted, &truth))
+}
@@ -1,21 +1,76 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
+use std::iter;
+use std::ops::Range;
 
-use color_eyre::Result;
-use embedded_rforest::ptr::NodePointer;
+use color_eyre::{Result, eyre::eyre};
+use embedded_rforest::forest::SplitRule;
+use tracing::warn;
 
+use self::cache::Trees;
+use self::flatten::{NodeHoles, apply_offsets, check_invariants, flatten, group_by_tree};
 use crate::{
-    problem_type::{Classification, Map, ProblemType, Regression},
+    compare::PredictLike,
+    problem_type::{
+        Classification, FeatureIdx, FeatureMap, ProblemType, Regression, TargetIdx, TargetMap,
+    },
     serialized_forest::{SerializedForest, SerializedNode},
 };
 
-#[derive(Debug, Clone)]
+pub mod cache;
+pub mod flatten;
+pub mod monotonic;
+pub mod profile;
+pub mod stats;
+pub mod stream;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BranchNode {
-    pub(super) split_with: u32,
+    pub(super) split_with: FeatureIdx,
     pub(super) split_at: f32,
     pub(super) left: u32,
     pub(super) right: u32,
+    /// Training samples which reached this branch, if the definition has
+    /// them
+    pub(super) samples: Option<u32>,
+    /// Impurity of this branch, e.g. its Gini index, if the definition has it
+    pub(super) impurity: Option<f32>,
+}
+
+impl BranchNode {
+    /// A branch going `left` if feature `split_with` is at most `split_at`,
+    /// and `right` otherwise. Children are node indices.
+    pub fn new(split_with: FeatureIdx, split_at: f32, left: u32, right: u32) -> Self {
+        Self {
+            split_with,
+            split_at,
+            left,
+            right,
+            samples: None,
+            impurity: None,
+        }
+    }
+
+    /// Number of training samples which reached this branch, if the
+    /// definition has a sample count column.
+    pub fn samples(&self) -> Option<u32> {
+        self.samples
+    }
+
+    /// Impurity of this branch, if the definition has an impurity column.
+    pub fn impurity(&self) -> Option<f32> {
+        self.impurity
+    }
+}
+
+/// Branches are equal if they split alike, whatever their statistics
+impl PartialEq for BranchNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.split_with == other.split_with
+            && self.split_at == other.split_at
+            && self.left == other.left
+            && self.right == other.right
+    }
 }
 
 impl fmt::Display for BranchNode {
@@ -28,17 +83,60 @@ impl fmt::Display for BranchNode {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(bound = "")]
 pub struct LeafNode<P: ProblemType> {
-    pub(super) prediction: P::Output,
+    pub(super) prediction: P::Prediction,
+    /// Training samples which reached this leaf, if the definition has them
+    pub(super) samples: Option<u32>,
+    /// Impurity of this leaf, if the definition has it
+    pub(super) impurity: Option<f32>,
 }
 
-#[derive(Debug, Clone)]
+impl<P: ProblemType> LeafNode<P> {
+    pub fn new(prediction: P::Prediction) -> Self {
+        Self {
+            prediction,
+            samples: None,
+            impurity: None,
+        }
+    }
+
+    /// Number of training samples which reached this leaf, if the definition
+    /// has a sample count column.
+    pub fn samples(&self) -> Option<u32> {
+        self.samples
+    }
+
+    /// Impurity of this leaf, if the definition has an impurity column.
+    pub fn impurity(&self) -> Option<f32> {
+        self.impurity
+    }
+}
+
+impl<P: ProblemType> PartialEq for LeafNode<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.prediction == other.prediction
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(bound = "")]
 pub enum Node<P: ProblemType> {
     Leaf(LeafNode<P>),
     Branch(BranchNode),
 }
 
+impl<P: ProblemType> PartialEq for Node<P> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Node::Leaf(a), Node::Leaf(b)) => a == b,
+            (Node::Branch(a), Node::Branch(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 impl<P: ProblemType> Node<P> {
     pub fn is_branch(&self) -> bool {
         matches!(self, Self::Branch(_))
@@ -55,6 +153,15 @@ impl<P: ProblemType> Node<P> {
         }
     }
 
+    /// Number of training samples which reached this node, see
+    /// [`BranchNode::samples`].
+    pub fn samples(&self) -> Option<u32> {
+        match self {
+            Node::Leaf(l) => l.samples,
+            Node::Branch(b) => b.samples,
+        }
+    }
+
     /// Calculate by how much we need to offset a branch's left and right
     /// pointers, given that the trees are getting disjoined from their root,
     /// which is stored at the front of the forest.
@@ -85,23 +192,23 @@ impl<P: ProblemType> fmt::Display for Node<P> {
     }
 }
 
-#[derive(Debug)]
-struct Tree<P: ProblemType> {
-    nodes: Vec<Node<P>>,
-}
-
-impl<P: ProblemType> Tree<P> {
-    pub fn new(nodes: Vec<Node<P>>) -> Self {
-        Self { nodes }
-    }
-}
+/// Trees deeper than this are reported with a warning when a forest is
+/// loaded: they are slow to evaluate and usually a sign of overfitting.
+pub const DEEP_TREE_WARNING: usize = 32;
 
 /// An array-backed, non-optimized random forest model
-#[derive(Debug)]
+///
+/// It serializes as its problem and the nodes of every tree, and checks them
+/// like a forest definition when deserialized, see [`mod@cache`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(into = "Trees<P>", try_from = "Trees<P>", bound = "")]
 pub struct Forest<P: ProblemType> {
     num_trees: usize,
+    /// Number of nodes of each tree
+    tree_sizes: Vec<usize>,
     nodes: Vec<Node<P>>,
     problem: P,
+    split_rule: SplitRule,
 }
 
 impl<P> Forest<P>
@@ -111,148 +218,184 @@ where
     /// Convert a [`SerializedForest`] into a [`Forest`].
     ///
     /// In practice, this method flattens the nodes, putting all tree roots in
-    /// front of the array.
+    /// front of the array. See [`mod@flatten`] for the stages.
     pub fn from_serialized<N: SerializedNode<ProblemType = P>>(
         serialized: SerializedForest<N>,
     ) -> Result<Self> {
-        let problem = serialized.problem();
-
-        // Find all nodes which have an index of 1. These are our tree roots.
-        let mut tree_roots: Vec<_> = serialized
-            .nodes()
-            .iter()
-            .filter_map(|n| {
-                if n.node_idx() == 1 {
-                    Some(n.tree_idx())
-                } else {
-                    None
-                }
-            })
-            .collect();
-        tree_roots.sort();
-
-        // Check that all tree roots are numbered sequentially
-        assert!(
-            tree_roots.iter().enumerate().all(|(i, &v)| v == i + 1),
-            "Mismatch within tree indices"
-        );
-
-        // Create an array with enough space for all our trees
-        let mut trees = Vec::with_capacity(tree_roots.len());
-
-        // Descend into each tree and create the array structure
-        for i in 0..tree_roots.len() {
-            let tree_idx = i + 1;
-
-            // Collect just the nodes belonging to this tree, and place them in order
-            let tree_nodes = {
-                let mut nodes = serialized
-                    .nodes()
-                    .iter()
-                    .filter_map(|n| {
-                        if n.tree_idx() == tree_idx {
-                            Some((n.node_idx(), n.clone().normalize(problem)))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>();
-                nodes.sort_by(|(a, _), (b, _)| a.cmp(b));
-                nodes
-                    .into_iter()
-                    .map(|(_, n)| n)
-                    .collect::<Result<Vec<_>, _>>()?
-            };
-
-            trees.push(Tree::new(tree_nodes));
-        }
+        Self::from_serialized_with(serialized, NodeHoles::Reject)
+    }
 
-        // Collect the size of each tree in a vector
-        let tree_sizes = trees.iter().map(|t| t.nodes.len()).collect::<Vec<_>>();
+    /// [`Forest::from_serialized`], tolerating trees whose node indices skip
+    /// some numbers if `holes` is [`NodeHoles::Renumber`].
+    #[tracing::instrument(name = "flatten", skip_all)]
+    pub fn from_serialized_with<N: SerializedNode<ProblemType = P>>(
+        serialized: SerializedForest<N>,
+        holes: NodeHoles,
+    ) -> Result<Self> {
+        let trees = group_by_tree(serialized.nodes()).normalize(serialized.problem(), holes)?;
 
-        // forest_nodes will store the flattened collection of all nodes in this forest
-        let mut forest_nodes = Vec::with_capacity(tree_sizes.iter().sum());
+        let forest = Self::from_trees(trees, serialized.problem().clone())
+            .with_split_rule(serialized.split_rule());
+        forest.warn_suspicious();
 
-        // Combine all trees into a flat forest structure
-        // Start by adding the root of each tree to the beginning of the array
-        for (i, tree) in trees.iter().enumerate() {
-            let node = tree.nodes[0].clone().offset(&tree_sizes, i);
-            forest_nodes.push(node);
-        }
+        Ok(forest)
+    }
 
-        // Then add the rest of the nodes
-        for (i, tree) in trees.into_iter().enumerate() {
-            // Skipping the root node, as it is already inserted at the start of the forest
-            for node in tree.nodes.into_iter().skip(1) {
-                forest_nodes.push(node.offset(&tree_sizes, i));
-            }
+    /// Flatten trees into a [`Forest`].
+    ///
+    /// Each tree's nodes are indexed from its root, at index 0, and every
+    /// branch must point to nodes further down its tree.
+    pub(crate) fn from_trees(trees: Vec<Vec<Node<P>>>, problem: P) -> Self {
+        let forest = flatten(apply_offsets(trees));
+        check_invariants(&forest);
+
+        Self {
+            num_trees: forest.tree_sizes.len(),
+            tree_sizes: forest.tree_sizes,
+            nodes: forest.nodes,
+            problem,
+            split_rule: SplitRule::default(),
         }
+    }
 
-        for (i, node) in forest_nodes.iter().enumerate() {
-            // Verify that our forest size fits in an u32
-            let i: u32 = i.try_into().expect("Index overflow");
+    /// This forest with its branches comparing features to their
+    /// thresholds following `split_rule`, `<=` unless set.
+    pub fn with_split_rule(self, split_rule: SplitRule) -> Self {
+        Self { split_rule, ..self }
+    }
 
-            // Ensure that every node only ever branches to another node further down the
-            // vec
-            if let Node::Branch(b) = node {
-                assert!(b.left > i && b.right > i);
-            }
+    /// The forest made of the trees at indices `trees` only, in the order
+    /// listed, with the same features and targets.
+    ///
+    /// Fails if `trees` is empty, or lists an index out of range or more
+    /// than once.
+    pub fn subset(&self, trees: &[usize]) -> Result<Self> {
+        if trees.is_empty() {
+            return Err(eyre!("A forest needs at least one tree"));
         }
 
-        Ok(Self {
-            num_trees: tree_sizes.len(),
-            nodes: forest_nodes,
-            problem: serialized.problem().clone(),
-        })
+        let all = self.trees().collect::<Vec<_>>();
+        let mut listed = HashSet::new();
+        let trees = trees
+            .iter()
+            .map(|&index| {
+                let tree = all.get(index).ok_or_else(|| {
+                    eyre!(
+                        "Tree {index} is out of range, the forest has {} trees",
+                        all.len()
+                    )
+                })?;
+                if !listed.insert(index) {
+                    return Err(eyre!("Tree {index} is listed more than once"));
+                }
+                Ok(tree.to_nodes())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Flattened again, and checked like any other forest
+        Ok(Self::from_trees(trees, self.problem.clone()).with_split_rule(self.split_rule))
     }
 
-    /// Turn this [`Forest`] into an [`OptimizedForest`].
-    #[expect(private_bounds)]
-    pub fn optimize_nodes(&self) -> Vec<embedded_rforest::forest::Branch>
-    where
-        P: UpdatePointers,
-    {
-        // Start by collecing branch indices, incrementing the branch index only if the
-        // node is a branch.
-        let mut branch_idx = 0;
-        let nodes = self
-            .nodes
-            .clone()
-            .into_iter()
-            .map(|n| {
-                if n.is_branch() {
-                    branch_idx += 1;
+    /// Log warnings about parts of the forest which are valid, but likely
+    /// unintended.
+    fn warn_suspicious(&self) {
+        let mut used_features = HashSet::new();
+
+        for tree in self.trees() {
+            let mut depth = 0;
+            for (idx, d) in tree.walk() {
+                depth = depth.max(d);
+                if let Node::Branch(b) = &self.nodes[idx] {
+                    used_features.insert(b.split_with);
                 }
-                RefCell::new(TransitionBranch::from_node(&self.nodes, n, branch_idx - 1))
-            })
-            .collect::<Vec<_>>();
+            }
 
-        // Descend the tree, replacing each decision with an optimized node pointer.
-        let nodes = nodes
-            .iter()
-            .map(|n| P::update_pointers(&nodes, n))
-            .filter_map(|mut n| n.take())
-            .collect::<Vec<_>>();
+            if depth > DEEP_TREE_WARNING {
+                warn!(
+                    tree = tree.index(),
+                    depth,
+                    "tree {} has depth {depth}",
+                    tree.index()
+                );
+            }
+        }
 
-        nodes
+        for (idx, name) in self.features().iter() {
+            if !used_features.contains(&idx) {
+                warn!(feature = name, "feature '{name}' declared but never used");
+            }
+        }
     }
 
     pub fn nodes(&self) -> &[Node<P>] {
         &self.nodes
     }
 
+    /// Nodes of the forest, for passes which change their values but not
+    /// the shape of the trees.
+    pub(crate) fn nodes_mut(&mut self) -> &mut [Node<P>] {
+        &mut self.nodes
+    }
+
     pub fn num_trees(&self) -> usize {
         self.num_trees
     }
 
+    /// Iterate over the trees of the forest, in order.
+    pub fn trees(&self) -> impl ExactSizeIterator<Item = TreeRef<'_, P>> {
+        let mut start = self.num_trees;
+        self.tree_sizes
+            .iter()
+            .enumerate()
+            .map(move |(index, &size)| {
+                // The root is stored at the tree index, the rest of the tree after all roots
+                let rest = start..start + size - 1;
+                start = rest.end;
+                TreeRef {
+                    forest: self,
+                    index,
+                    rest,
+                }
+            })
+    }
+
     pub fn num_features(&self) -> usize {
         self.problem.features().len()
     }
 
-    pub fn features(&self) -> &Map {
+    pub fn features(&self) -> &FeatureMap {
         self.problem.features()
     }
 
+    /// Feature names, positioned by feature index
+    pub fn features_by_index(&self) -> Vec<&str> {
+        self.features().names()
+    }
+
+    /// Index of the feature named `name`, if the forest has one
+    pub fn feature_index(&self, name: &str) -> Option<FeatureIdx> {
+        self.features().index_of(name)
+    }
+
+    pub fn problem(&self) -> &P {
+        &self.problem
+    }
+
+    /// How the branches compare features to their thresholds, see
+    /// [`SplitRule`]
+    pub fn split_rule(&self) -> SplitRule {
+        self.split_rule
+    }
+
+    /// Index in [`Forest::nodes`] of the leaf each tree reaches from
+    /// `features`, in tree order. [`conversion::leaf_ids`] maps the leaf ids
+    /// of an optimized forest to these indices.
+    ///
+    /// [`conversion::leaf_ids`]: crate::conversion::leaf_ids
+    pub fn predict_leaf_ids(&self, features: &[f32]) -> Vec<usize> {
+        self.trees().map(|tree| tree.leaf_index(features)).collect()
+    }
+
     fn next_left(&self, branch: &BranchNode) -> &Node<P> {
         &self.nodes[branch.left as usize]
     }
@@ -262,43 +405,125 @@ where
     }
 }
 
-struct TransitionBranch<P: ProblemType> {
-    id: u32,
-    split_with: u32,
-    split_at: f32,
-    left: TransitionNode<P>,
-    right: TransitionNode<P>,
+/// One tree of a [`Forest`].
+///
+/// Its root is stored at the tree index, and the rest of its nodes are stored
+/// contiguously after the roots of all trees.
+pub struct TreeRef<'a, P: ProblemType> {
+    forest: &'a Forest<P>,
+    index: usize,
+    rest: Range<usize>,
 }
 
-enum TransitionNode<P: ProblemType> {
-    Leaf(P::Output),
-    Branch(u32),
-}
+impl<'a, P: ProblemType> TreeRef<'a, P> {
+    /// Index of the tree in the forest
+    pub fn index(&self) -> usize {
+        self.index
+    }
 
-impl<P: ProblemType> TransitionBranch<P> {
-    fn from_node(nodes: &[Node<P>], node: Node<P>, id: u32) -> Option<Self> {
-        // Only transform branch nodes by looking ahead to find out if the next
-        // left/right nodes contain a prediction
-        let Node::Branch(branch) = node else {
-            return None;
-        };
-
-        let left = match &nodes[branch.left as usize] {
-            Node::Leaf(leaf) => TransitionNode::Leaf(leaf.prediction),
-            Node::Branch(_) => TransitionNode::Branch(branch.left),
-        };
-
-        let right = match &nodes[branch.right as usize] {
-            Node::Leaf(leaf) => TransitionNode::Leaf(leaf.prediction),
-            Node::Branch(_) => TransitionNode::Branch(branch.right),
-        };
-
-        Some(TransitionBranch {
-            id,
-            split_with: branch.split_with,
-            split_at: branch.split_at,
-            left,
-            right,
+    /// Indices of the nodes of this tree in [`Forest::nodes`], root first
+    pub fn node_indices(&self) -> impl Iterator<Item = usize> + use<P> {
+        iter::once(self.index).chain(self.rest.clone())
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &'a Node<P>> + use<'a, P> {
+        let nodes = &self.forest.nodes;
+        self.node_indices().map(move |i| &nodes[i])
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        1 + self.rest.len()
+    }
+
+    pub fn num_branches(&self) -> usize {
+        self.nodes().filter(|n| n.is_branch()).count()
+    }
+
+    /// Nodes of this tree, root first, with child pointers relative to the
+    /// root as expected by [`Forest::from_trees`]
+    pub fn to_nodes(&self) -> Vec<Node<P>> {
+        let local = |idx: u32| idx - (self.rest.start - 1) as u32;
+
+        self.nodes()
+            .cloned()
+            .map(|node| match node {
+                Node::Branch(b) => Node::Branch(BranchNode {
+                    left: local(b.left),
+                    right: local(b.right),
+                    ..b
+                }),
+                leaf => leaf,
+            })
+            .collect()
+    }
+
+    /// Prediction of this tree alone
+    pub fn predict(&self, features: &[f32]) -> P::Output {
+        let mut node = &self.forest.nodes[self.index];
+        loop {
+            match node {
+                Node::Branch(b) if self.goes_left(b, features) => node = self.forest.next_left(b),
+                Node::Branch(b) => node = self.forest.next_right(b),
+                Node::Leaf(l) => return l.prediction.into(),
+            }
+        }
+    }
+
+    /// Index in [`Forest::nodes`] of the leaf this tree reaches from
+    /// `features`
+    pub fn leaf_index(&self, features: &[f32]) -> usize {
+        self.trace(features, |_, _| {})
+    }
+
+    /// [`TreeRef::leaf_index`], calling `visit` with the index in
+    /// [`Forest::nodes`] of every branch on the way, and whether it goes
+    /// right from it
+    pub fn trace(&self, features: &[f32], mut visit: impl FnMut(usize, bool)) -> usize {
+        let mut index = self.index;
+        loop {
+            match &self.forest.nodes[index] {
+                Node::Branch(b) => {
+                    let left = self.goes_left(b, features);
+                    visit(index, !left);
+                    index = if left { b.left } else { b.right } as usize;
+                }
+                Node::Leaf(_) => return index,
+            }
+        }
+    }
+
+    /// Whether `features` go left at `branch`
+    fn goes_left(&self, branch: &BranchNode, features: &[f32]) -> bool {
+        let value = features[branch.split_with.as_usize()];
+        self.forest.split_rule.goes_left(value, branch.split_at)
+    }
+
+    /// Number of branches on the longest path from the root to a leaf
+    pub fn depth(&self) -> usize {
+        self.walk().map(|(_, depth)| depth).max().unwrap_or(0)
+    }
+
+    /// Number of branches on the path from the root to every leaf, left
+    /// to right
+    pub fn leaf_depths(&self) -> impl Iterator<Item = usize> + use<'a, P> {
+        let nodes = &self.forest.nodes;
+        self.walk()
+            .filter(|&(idx, _)| nodes[idx].is_leaf())
+            .map(|(_, depth)| depth)
+    }
+
+    /// Nodes reachable from the root, as their index in [`Forest::nodes`]
+    /// and their depth
+    fn walk(&self) -> impl Iterator<Item = (usize, usize)> + use<'a, P> {
+        let nodes = &self.forest.nodes;
+        let mut stack = vec![(self.index, 0)];
+        iter::from_fn(move || {
+            let (idx, depth) = stack.pop()?;
+            if let Node::Branch(b) = &nodes[idx] {
+                stack.push((b.right as usize, depth + 1));
+                stack.push((b.left as usize, depth + 1));
+            }
+            Some((idx, depth))
         })
     }
 }
@@ -308,91 +533,53 @@ impl Forest<Classification> {
         self.problem.targets().len()
     }
 
-    pub fn targets(&self) -> &Map {
+    pub fn targets(&self) -> &TargetMap {
         self.problem.targets()
     }
 
-    /// Make a prediction based on input values (features)
-    pub fn predict(&self, features: &[f32]) -> String {
-        // Reserve space to store each tree's prediction
-        let mut results = Vec::with_capacity(self.num_trees);
-
-        // Descend into each tree to make a prediction
-        for tree_id in 0..self.num_trees {
-            // The tree root is stored at the tree index
-            let mut node = &self.nodes[tree_id];
-
-            let prediction = loop {
-                match node {
-                    Node::Branch(b) => {
-                        let test = features[b.split_with as usize] <= b.split_at;
-                        if test {
-                            node = self.next_left(b)
-                        } else {
-                            node = self.next_right(b)
-                        }
-                    }
-                    Node::Leaf(l) => {
-                        break l.prediction;
-                    }
-                }
-            };
-
-            results.push(prediction);
-        }
+    /// Class names, positioned by class index
+    pub fn targets_by_index(&self) -> Vec<&str> {
+        self.targets().names()
+    }
 
-        // Count the number of votes for each category
-        let mut votes = HashMap::new();
-        for &target in results.iter() {
-            *votes.entry(target).or_insert(0) += 1;
-        }
+    /// Name of the class of index `class`, if the forest has one
+    pub fn class_name(&self, class: u32) -> Option<&str> {
+        self.targets().name_of(TargetIdx(class))
+    }
 
-        let best_result = votes
-            .into_iter()
-            .max_by_key(|&(_, count)| count)
-            .map(|(num, _)| num)
-            .unwrap();
+    /// Make a prediction based on input values (features), as the name of
+    /// the class. [`PredictLike::predict_like`] predicts its index.
+    pub fn predict(&self, features: &[f32]) -> String {
+        let class = self.predict_like(features);
+        self.class_name(class)
+            .expect("Leaves predict classes of the forest")
+            .to_string()
+    }
 
-        self.targets()
-            .iter()
-            .find(|(_, t)| **t == best_result)
-            .unwrap()
-            .0
-            .clone()
+    /// Make a prediction like an optimized forest taking the
+    /// [`Aggregation::OrdinalMedian`](embedded_rforest::forest::Aggregation::OrdinalMedian)
+    /// of the votes of its trees, as the index of the class: the median of
+    /// the class indices the trees predict, the mean of the two middle ones
+    /// rounded down for an even number of trees. Classes must be indexed in
+    /// their natural order, e.g. pinned with
+    /// [`IndexOrder::targets`](crate::serialized_forest::IndexOrder::targets).
+    pub fn predict_ordinal(&self, features: &[f32]) -> u32 {
+        let mut votes = self
+            .trees()
+            .map(|tree| tree.predict(features))
+            .collect::<Vec<_>>();
+        votes.sort_unstable();
+        match votes.len() {
+            0 => 0,
+            len => (votes[(len - 1) / 2] + votes[len / 2]) / 2,
+        }
     }
 }
 
 impl Forest<Regression> {
     /// Make a prediction based on input values (features)
     pub fn predict(&self, features: &[f32]) -> f32 {
-        // Reserve space to store each tree's prediction
-        let mut result = 0.0;
-
-        // Descend into each tree to make a prediction
-        for tree_id in 0..self.num_trees {
-            // The tree root is stored at the tree index
-            let mut node = &self.nodes[tree_id];
-
-            let prediction = loop {
-                match node {
-                    Node::Branch(b) => {
-                        let test = features[b.split_with as usize] <= b.split_at;
-                        if test {
-                            node = self.next_left(b)
-                        } else {
-                            node = self.next_right(b)
-                        }
-                    }
-                    Node::Leaf(l) => {
-                        break l.prediction;
-                    }
-                }
-            };
-
-            result += prediction;
-        }
-
-        result / self.num_trees as f32
+        self.predict_like(features)
     }
 }
 
@@ -411,20 +598,14 @@ impl fmt::Display for Forest<Classification> {
         }
         writeln!(f, "------------")?;
 
-        let mut features_ordered = self.problem.features().iter().collect::<Vec<_>>();
-        features_ordered.sort_by(|a, b| a.1.cmp(b.1));
-
         writeln!(f, "Features: ")?;
-        for feat in features_ordered.iter() {
-            writeln!(f, "\t{}: {}", feat.1, feat.0)?;
+        for (idx, name) in self.features_by_index().into_iter().enumerate() {
+            writeln!(f, "\t{idx}: {name}")?;
         }
 
-        let mut targets_ordered = self.problem.targets().iter().collect::<Vec<_>>();
-        targets_ordered.sort_by(|a, b| a.1.cmp(b.1));
-
         writeln!(f, "Targets: ")?;
-        for t in targets_ordered.iter() {
-            writeln!(f, "\t{}: {}", t.1, t.0)?;
+        for (idx, name) in self.targets_by_index().into_iter().enumerate() {
+            writeln!(f, "\t{idx}: {name}")?;
         }
 
         writeln!(f, "------------")?;
@@ -447,12 +628,9 @@ impl fmt::Display for Forest<Regression> {
         }
         writeln!(f, "------------")?;
 
-        let mut features_ordered = self.problem.features().iter().collect::<Vec<_>>();
-        features_ordered.sort_by(|a, b| a.1.cmp(b.1));
-
         writeln!(f, "Features: ")?;
-        for feat in features_ordered.iter() {
-            writeln!(f, "\t{}: {}", feat.1, feat.0)?;
+        for (idx, name) in self.features_by_index().into_iter().enumerate() {
+            writeln!(f, "\t{idx}: {name}")?;
         }
 
         writeln!(f, "------------")?;
@@ -460,80 +638,3 @@ impl fmt::Display for Forest<Regression> {
         Ok(())
     }
 }
-
-trait UpdatePointers: ProblemType {
-    fn update_pointers(
-        nodes: &[RefCell<Option<TransitionBranch<Self>>>],
-        branch: &RefCell<Option<TransitionBranch<Self>>>,
-    ) -> Option<embedded_rforest::forest::Branch>;
-}
-
-impl UpdatePointers for Classification {
-    fn update_pointers(
-        nodes: &[RefCell<Option<TransitionBranch<Self>>>],
-        branch: &RefCell<Option<TransitionBranch<Self>>>,
-    ) -> Option<embedded_rforest::forest::Branch> {
-        let branch = branch.borrow();
-        let branch = branch.as_ref()?;
-
-        let (left_pred, left_val) = match branch.left {
-            TransitionNode::Leaf(l) => (true, l),
-            TransitionNode::Branch(b) => {
-                let next = nodes[b as usize].borrow().as_ref()?.id;
-                (false, next)
-            }
-        };
-
-        let (right_pred, right_val) = match branch.right {
-            TransitionNode::Leaf(l) => (true, l),
-            TransitionNode::Branch(b) => {
-                let next = nodes[b as usize].borrow().as_ref()?.id;
-                (false, next)
-            }
-        };
-
-        Some(embedded_rforest::forest::Branch::new(
-            branch.split_with,
-            branch.split_at,
-            NodePointer::new_ptr(left_val),
-            NodePointer::new_ptr(right_val),
-            left_pred,
-            right_pred,
-        ))
-    }
-}
-
-impl UpdatePointers for Regression {
-    fn update_pointers(
-        nodes: &[RefCell<Option<TransitionBranch<Self>>>],
-        branch: &RefCell<Option<TransitionBranch<Self>>>,
-    ) -> Option<embedded_rforest::forest::Branch> {
-        let branch = branch.borrow();
-        let branch = branch.as_ref()?;
-
-        let (left_pred, left_ptr) = match branch.left {
-            TransitionNode::Leaf(l) => (true, NodePointer::new_f32(l)),
-            TransitionNode::Branch(b) => {
-                let next = nodes[b as usize].borrow().as_ref()?.id;
-                (false, NodePointer::new_ptr(next))
-            }
-        };
-
-        let (right_pred, right_ptr) = match branch.right {
-            TransitionNode::Leaf(l) => (true, NodePointer::new_f32(l)),
-            TransitionNode::Branch(b) => {
-                let next = nodes[b as usize].borrow().as_ref()?.id;
-                (false, NodePointer::new_ptr(next))
-            }
-        };
-
-        Some(embedded_rforest::forest::Branch::new(
-            branch.split_with,
-            branch.split_at,
-            left_ptr,
-            right_ptr,
-            left_pred,
-            right_pred,
-        ))
-    }
-}
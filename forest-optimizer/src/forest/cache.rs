@@ -0,0 +1,333 @@
+//! Saving a [`Forest`] to a file, and caching the forest of a definition file
+//! (CSV) next to it, so that large definitions are only parsed and flattened
+//! again when they change.
+//!
+//! Files are [postcard](https://docs.rs/postcard) encoded: a header, with the
+//! format version, the problem type and, for caches, a stamp of the
+//! definition file, then the forest. A forest is encoded as its problem and
+//! the nodes of every tree, indexed from the tree root. When read back, the
+//! trees are checked like those of a forest definition before being flattened
+//! again, so a corrupted or tampered file is rejected rather than trusted.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use color_eyre::{
+    Report, Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::forest::SplitRule;
+use tracing::{debug, warn};
+
+use super::{Forest, Node};
+use crate::{
+    problem_type::{PredictionType, ProblemType},
+    serialized_forest::{SerializedForest, SerializedNode},
+};
+
+/// Version of the file format, bumped whenever the encoding of a forest
+/// changes. Files of another version are rejected, and caches rebuilt.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Extension appended to a definition file's name to name its cache.
+pub const CACHE_EXTENSION: &str = "forest-cache";
+
+/// How a [`Forest`] is serialized: every tree on its own, indexed from its
+/// root like [`Forest::from_trees`] expects.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(bound = "")]
+pub(super) struct Trees<P: ProblemType> {
+    problem: P,
+    trees: Vec<Vec<Node<P>>>,
+    /// Whether branches go left below their threshold only, see
+    /// [`SplitRule`]. Defaults to `<=` in self-describing formats
+    #[serde(default)]
+    less_than: bool,
+}
+
+impl<P: ProblemType> From<Forest<P>> for Trees<P> {
+    fn from(forest: Forest<P>) -> Self {
+        Self {
+            trees: forest.trees().map(|tree| tree.to_nodes()).collect(),
+            less_than: forest.split_rule == SplitRule::Less,
+            problem: forest.problem,
+        }
+    }
+}
+
+impl<P: ProblemType> TryFrom<Trees<P>> for Forest<P> {
+    type Error = Report;
+
+    fn try_from(
+        Trees {
+            problem,
+            trees,
+            less_than,
+        }: Trees<P>,
+    ) -> Result<Self> {
+        if trees.is_empty() {
+            return Err(eyre!("A forest needs at least one tree"));
+        }
+        let nodes = trees.iter().map(Vec::len).sum::<usize>();
+        if u32::try_from(nodes).is_err() {
+            return Err(eyre!("The forest has {nodes} nodes, more than u32 indexes"));
+        }
+        for (index, tree) in trees.iter().enumerate() {
+            check_tree(&problem, tree, index)?;
+        }
+
+        let split_rule = if less_than {
+            SplitRule::Less
+        } else {
+            SplitRule::LessOrEqual
+        };
+        Ok(Self::from_trees(trees, problem).with_split_rule(split_rule))
+    }
+}
+
+/// Check that the branches of a tree split on features of `problem` and point
+/// to nodes further down the tree, that every node but the root has exactly
+/// one parent, and that its leaves predict outputs of `problem`.
+fn check_tree<P: ProblemType>(problem: &P, tree: &[Node<P>], index: usize) -> Result<()> {
+    if tree.is_empty() {
+        return Err(eyre!("Tree {index} has no nodes"));
+    }
+
+    let mut parents = vec![0; tree.len()];
+    for (i, node) in tree.iter().enumerate() {
+        match node {
+            Node::Branch(branch) => {
+                if branch.split_with.as_usize() >= problem.features().len() {
+                    return Err(eyre!(
+                        "Node {i} of tree {index} splits on unknown feature {}",
+                        branch.split_with
+                    ));
+                }
+                for child in [branch.left, branch.right] {
+                    let child = child as usize;
+                    if child <= i || child >= tree.len() {
+                        return Err(eyre!(
+                            "Node {i} of tree {index} points to node {child}, outside of the \
+                             nodes below it"
+                        ));
+                    }
+                    parents[child] += 1;
+                }
+            }
+            Node::Leaf(leaf) if !problem.is_valid_output(leaf.prediction.into()) => {
+                return Err(eyre!(
+                    "Node {i} of tree {index} predicts unknown output {}",
+                    leaf.prediction
+                ));
+            }
+            Node::Leaf(_) => {}
+        }
+    }
+
+    match parents.iter().enumerate().skip(1).find(|(_, p)| **p != 1) {
+        Some((i, p)) => Err(eyre!(
+            "Node {i} of tree {index} has {p} parents instead of 1"
+        )),
+        None => Ok(()),
+    }
+}
+
+/// What a file starts with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct Header {
+    version: u32,
+    problem_type: PredictionType,
+    /// Stamp of the definition file a cache was made from, none for files
+    /// written by [`Forest::save`]
+    source: Option<SourceStamp>,
+}
+
+impl Header {
+    /// Check that a forest of problem `P` follows this header.
+    fn check<P: ProblemType>(&self, path: &Path) -> Result<()> {
+        if self.version != FORMAT_VERSION {
+            return Err(eyre!(
+                "{} is of format version {}, expected {FORMAT_VERSION}",
+                path.display(),
+                self.version
+            ));
+        }
+        if self.problem_type != P::TYPE {
+            return Err(eyre!(
+                "{} holds a {} forest, not a {} one",
+                path.display(),
+                self.problem_type,
+                P::TYPE
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Size, modification time and contents hash of a definition file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct SourceStamp {
+    len: u64,
+    /// Nanoseconds since the Unix epoch, if the platform records it
+    modified: Option<u128>,
+    /// 64-bit FNV-1a hash of the contents
+    hash: u64,
+}
+
+impl SourceStamp {
+    fn new(metadata: &fs::Metadata, contents: &[u8]) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: modified(metadata),
+            hash: hash(contents),
+        }
+    }
+
+    /// Whether the file at `path` is the one stamped: it has the same size,
+    /// and the same modification time or, when that changed without the
+    /// contents necessarily changing (e.g. after a checkout), the same hash.
+    fn matches(&self, path: &Path) -> Result<bool> {
+        let metadata = fs::metadata(path)?;
+        if metadata.len() != self.len {
+            return Ok(false);
+        }
+        if self.modified.is_some() && modified(&metadata) == self.modified {
+            return Ok(true);
+        }
+        Ok(hash(&fs::read(path)?) == self.hash)
+    }
+}
+
+fn modified(metadata: &fs::Metadata) -> Option<u128> {
+    let modified = metadata.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Encode `forest` after a header of `source`, and write it to `path`.
+fn write<P: ProblemType>(
+    forest: &Forest<P>,
+    source: Option<SourceStamp>,
+    path: &Path,
+) -> Result<()> {
+    let header = Header {
+        version: FORMAT_VERSION,
+        problem_type: P::TYPE,
+        source,
+    };
+    let bytes = postcard::to_stdvec(&(header, forest)).context("Could not encode the forest")?;
+    fs::write(path, bytes).with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// Decode the header of `bytes`, read from `path`, and the bytes after it.
+fn read_header<'a>(bytes: &'a [u8], path: &Path) -> Result<(Header, &'a [u8])> {
+    postcard::take_from_bytes(bytes).with_context(|| format!("{} has no header", path.display()))
+}
+
+/// Decode the forest which follows a header, and check it.
+fn read_forest<P: ProblemType>(bytes: &[u8], path: &Path) -> Result<Forest<P>> {
+    let trees = postcard::from_bytes::<Trees<P>>(bytes)
+        .with_context(|| format!("{} holds no valid forest", path.display()))?;
+    Forest::try_from(trees).with_context(|| format!("{} holds an invalid forest", path.display()))
+}
+
+impl<P: ProblemType> Forest<P> {
+    /// Write this forest to `path`, to be read back with [`Forest::load`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        write(self, None, path.as_ref())
+    }
+
+    /// Read a forest written by [`Forest::save`]. Fails if the file is of
+    /// another format version or problem type, or if its trees are not
+    /// valid.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+        let (header, rest) = read_header(&bytes, path)?;
+        header.check::<P>(path)?;
+
+        read_forest(rest, path)
+    }
+}
+
+/// Where the cache of the definition file `input` is written: next to it, with
+/// [`CACHE_EXTENSION`] appended to its name.
+pub fn cache_path(input: impl AsRef<Path>) -> PathBuf {
+    let mut path = input.as_ref().as_os_str().to_owned();
+    path.push(".");
+    path.push(CACHE_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// The cached forest of `input`, if its cache is of this format version and
+/// problem type, and was made from `input` as it is now.
+fn read_fresh_cache<P: ProblemType>(input: &Path, cache: &Path) -> Result<Option<Forest<P>>> {
+    let bytes = match fs::read(cache) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let (header, rest) = read_header(&bytes, cache)?;
+    if header.check::<P>(cache).is_err() {
+        return Ok(None);
+    }
+    match header.source {
+        Some(source) if source.matches(input)? => read_forest(rest, cache).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Read the forest definition file (CSV) `input` through its cache at
+/// [`cache_path`].
+///
+/// The cache is used if it was made from `input` as it is now, see
+/// [`mod@self`]. Otherwise, or if it cannot be read, `input` is read and the
+/// cache written again. Failing to write the cache is only logged.
+pub fn read_cached<N: SerializedNode>(input: impl AsRef<Path>) -> Result<Forest<N::ProblemType>> {
+    let input = input.as_ref();
+    let cache = cache_path(input);
+
+    match read_fresh_cache(input, &cache) {
+        Ok(Some(forest)) => {
+            debug!(cache = %cache.display(), "Read forest from cache");
+            return Ok(forest);
+        }
+        Ok(None) => debug!(cache = %cache.display(), "No fresh cache"),
+        Err(e) => warn!(cache = %cache.display(), "Ignoring unreadable cache: {e:#}"),
+    }
+
+    let contents = fs::read(input).context("Could not read forest definition file (CSV).")?;
+    let stamp = SourceStamp::new(&fs::metadata(input)?, &contents);
+    let serialized = SerializedForest::<N>::from_reader(contents.as_slice())
+        .context("Could not read forest definition file (CSV).")?;
+    serialized.check()?;
+    let forest = Forest::from_serialized(serialized)?;
+
+    if let Err(e) = write(&forest, Some(stamp), &cache) {
+        warn!(cache = %cache.display(), "Could not write cache: {e:#}");
+    }
+
+    Ok(forest)
+}
+
+/// Read the forest definition file (CSV) `input`, through its cache if
+/// `cache` is set, see [`read_cached`].
+pub fn read_definition<N: SerializedNode>(
+    input: impl AsRef<Path>,
+    cache: bool,
+) -> Result<Forest<N::ProblemType>> {
+    if cache {
+        return read_cached::<N>(input);
+    }
+
+    let serialized = SerializedForest::<N>::read(input)
+        .context("Could not read forest definition file (CSV).")?;
+    serialized.check()?;
+    Forest::from_serialized(serialized)
+}
@@ -0,0 +1,289 @@
+//! Stages of [`Forest::from_serialized`](super::Forest::from_serialized),
+//! which turn the rows of a forest definition into the flat node array of a
+//! [`Forest`](super::Forest):
+//!
+//! 1. [`group_by_tree`] collects the nodes of each tree, in node order, and
+//!    numbers the trees densely,
+//! 2. [`GroupedTrees::normalize`] checks the node indices of each tree, and
+//!    converts its nodes into [`Node`]s indexed from the tree root,
+//! 3. [`apply_offsets`] makes child pointers index the flat array,
+//! 4. [`flatten`] puts every root in front, then the rest of every tree,
+//! 5. [`check_invariants`] checks that every branch points further down.
+
+use std::collections::HashMap;
+
+use color_eyre::{Result, eyre::eyre};
+use tracing::{debug, warn};
+
+use super::Node;
+use crate::{problem_type::ProblemType, serialized_forest::SerializedNode};
+
+/// Serialized nodes grouped by tree.
+#[derive(Debug)]
+pub struct GroupedTrees<'a, N> {
+    /// Index of each tree in the forest definition, in increasing order.
+    /// Trees without a root are kept, and rejected by
+    /// [`normalize`](GroupedTrees::normalize)
+    pub tree_indices: Vec<usize>,
+    /// Nodes of each tree, positioned like `tree_indices`, sorted by node
+    /// index
+    pub trees: Vec<Vec<&'a N>>,
+}
+
+/// Group the nodes of a forest definition by tree.
+///
+/// Takes a single pass over the nodes, then sorts the trees and the nodes
+/// of each tree. Trees are renumbered from 0 in the order of their index: gaps between
+/// indices, e.g. of trees filtered out of an export, are logged rather than
+/// rejected.
+pub fn group_by_tree<N: SerializedNode>(nodes: &[N]) -> GroupedTrees<'_, N> {
+    // Definitions list the nodes of a tree together, so a node mostly joins
+    // the tree of the node before it, without looking its index up
+    let mut positions = HashMap::new();
+    let mut grouped: Vec<(usize, Vec<&N>)> = Vec::new();
+    let mut current = None;
+    for node in nodes {
+        let index = node.tree_idx();
+        let position = match current {
+            Some((last, position)) if last == index => position,
+            _ => *positions.entry(index).or_insert_with(|| {
+                grouped.push((index, Vec::new()));
+                grouped.len() - 1
+            }),
+        };
+        current = Some((index, position));
+        grouped[position].1.push(node);
+    }
+    // Linear if the trees are listed in order, like the nodes of each tree
+    // below
+    grouped.sort_by_key(|(index, _)| *index);
+    let (tree_indices, mut trees): (Vec<_>, Vec<_>) = grouped.into_iter().unzip();
+
+    warn_missing_trees(&tree_indices);
+
+    #[cfg(not(feature = "parallel"))]
+    for tree in &mut trees {
+        tree.sort_by_key(|n| n.node_idx());
+    }
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        trees
+            .par_iter_mut()
+            .for_each(|tree| tree.sort_by_key(|n| n.node_idx()));
+    }
+
+    GroupedTrees {
+        tree_indices,
+        trees,
+    }
+}
+
+/// Log the ranges of indices missing from the sorted `tree_indices`, whose
+/// trees are renumbered densely.
+pub(crate) fn warn_missing_trees(tree_indices: &[usize]) {
+    let missing = missing_indices(tree_indices.iter().copied());
+    if !missing.is_empty() {
+        warn!(
+            trees = tree_indices.len(),
+            "Tree indices {} are missing, renumbering trees",
+            missing.join(", ")
+        );
+    }
+}
+
+/// Ranges of indices missing from `indices`, sorted and numbered from 1.
+fn missing_indices(indices: impl IntoIterator<Item = usize>) -> Vec<String> {
+    let mut missing = Vec::new();
+    let mut expected = 1;
+    for index in indices {
+        match index.saturating_sub(expected) {
+            0 => {}
+            1 => missing.push(expected.to_string()),
+            _ => missing.push(format!("{expected}..={}", index - 1)),
+        }
+        expected = index + 1;
+    }
+    missing
+}
+
+/// What to do with a tree whose node indices skip some numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NodeHoles {
+    /// Fail, naming the missing indices
+    #[default]
+    Reject,
+    /// Number the nodes densely, their child pointers along with them, and
+    /// log the missing indices. Pointers to a missing node still fail
+    Renumber,
+}
+
+/// Check that the node indices of a tree, sorted, are unique and start at 1.
+/// Returns the ranges of missing indices, if any.
+fn check_node_indices<N: SerializedNode>(tree: &[&N], index: usize) -> Result<Vec<String>> {
+    let indices = || tree.iter().map(|n| n.node_idx());
+
+    let mut duplicates = tree
+        .windows(2)
+        .filter(|w| w[0].node_idx() == w[1].node_idx())
+        .map(|w| w[0].node_idx().to_string())
+        .collect::<Vec<_>>();
+    duplicates.dedup();
+    if !duplicates.is_empty() {
+        return Err(eyre!(
+            "Tree {index} has several nodes of index {}",
+            duplicates.join(", ")
+        ));
+    }
+
+    match indices().next() {
+        Some(1) => Ok(missing_indices(indices())),
+        Some(first) => Err(eyre!(
+            "Tree {index} has no root: its nodes start at index {first} instead of 1"
+        )),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl<N: SerializedNode> GroupedTrees<'_, N> {
+    /// Convert every node, see [`SerializedNode::normalize`]. Fails on the
+    /// first tree whose node indices are duplicated, do not start at 1, or
+    /// skip some numbers unless `holes` is [`NodeHoles::Renumber`], and on
+    /// the first node which does not convert, tree by tree and in node order.
+    ///
+    /// With the `parallel` feature, trees are converted concurrently, still
+    /// failing on the first tree in order.
+    pub fn normalize(
+        self,
+        problem: &N::ProblemType,
+        holes: NodeHoles,
+    ) -> Result<Vec<Vec<Node<N::ProblemType>>>> {
+        let normalize_tree = |(i, (tree, index)): (usize, (Vec<&N>, usize))| {
+            let missing = check_node_indices(&tree, index)?;
+            if !missing.is_empty() && holes == NodeHoles::Reject {
+                return Err(eyre!(
+                    "Tree {index} is missing nodes {}",
+                    missing.join(", ")
+                ));
+            }
+
+            let mut nodes = tree
+                .iter()
+                .map(|n| (*n).clone().normalize(problem))
+                .collect::<Result<Vec<_>>>()?;
+            if !missing.is_empty() {
+                warn!(
+                    tree = index,
+                    "Tree {index} is missing nodes {}, renumbering its nodes",
+                    missing.join(", ")
+                );
+                renumber(&mut nodes, &tree, index)?;
+            }
+            debug!(tree = i, nodes = nodes.len(), "Flattened tree");
+            Ok(nodes)
+        };
+
+        let trees = self.trees.into_iter().zip(self.tree_indices).enumerate();
+        #[cfg(not(feature = "parallel"))]
+        return trees.map(normalize_tree).collect();
+
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+
+            let trees = trees.collect::<Vec<_>>();
+            let trees = trees
+                .into_par_iter()
+                .map(normalize_tree)
+                .collect::<Vec<_>>();
+            trees.into_iter().collect()
+        }
+    }
+}
+
+/// Make the child pointers of `nodes`, node indices of the serialized
+/// `tree` counted from 0, point to positions in `nodes` instead.
+fn renumber<P: ProblemType, N: SerializedNode>(
+    nodes: &mut [Node<P>],
+    tree: &[&N],
+    index: usize,
+) -> Result<()> {
+    let position = |pointer: u32| {
+        let node_idx = pointer as usize + 1;
+        tree.binary_search_by_key(&node_idx, |n| n.node_idx())
+            .map(|p| p as u32)
+            .map_err(|_| eyre!("Tree {index} points to missing node {node_idx}"))
+    };
+
+    for node in nodes {
+        if let Node::Branch(branch) = node {
+            branch.left = position(branch.left)?;
+            branch.right = position(branch.right)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Offset the child pointers of every tree, indexed from its root, so that
+/// they index the array [`flatten`] makes of the trees. See
+/// [`Node::offset`].
+pub fn apply_offsets<P: ProblemType>(trees: Vec<Vec<Node<P>>>) -> Vec<Vec<Node<P>>> {
+    let tree_sizes = trees.iter().map(Vec::len).collect::<Vec<_>>();
+
+    trees
+        .into_iter()
+        .enumerate()
+        .map(|(i, tree)| {
+            tree.into_iter()
+                .map(|node| node.offset(&tree_sizes, i))
+                .collect()
+        })
+        .collect()
+}
+
+/// The nodes of a forest in a single array: the root of every tree, then the
+/// rest of every tree.
+#[derive(Debug, Clone)]
+pub struct FlattenedForest<P: ProblemType> {
+    /// Number of nodes of each tree
+    pub tree_sizes: Vec<usize>,
+    pub nodes: Vec<Node<P>>,
+}
+
+/// Put the root of every tree in front, then the rest of every tree, in tree
+/// order. Nodes are moved as they are, so child pointers must already have
+/// been offset by [`apply_offsets`].
+pub fn flatten<P: ProblemType>(trees: Vec<Vec<Node<P>>>) -> FlattenedForest<P> {
+    let tree_sizes = trees.iter().map(Vec::len).collect::<Vec<_>>();
+    let mut nodes = Vec::with_capacity(tree_sizes.iter().sum());
+
+    let mut rests = Vec::with_capacity(trees.len());
+    for tree in trees {
+        let mut tree = tree.into_iter();
+        nodes.extend(tree.next());
+        rests.push(tree);
+    }
+    for rest in rests {
+        nodes.extend(rest);
+    }
+
+    FlattenedForest { tree_sizes, nodes }
+}
+
+/// Check that the forest is indexable with `u32`, and that every branch
+/// points to nodes further down the array, so that walking a tree always
+/// ends.
+///
+/// # Panics
+///
+/// Panics if either does not hold.
+pub fn check_invariants<P: ProblemType>(forest: &FlattenedForest<P>) {
+    for (i, node) in forest.nodes.iter().enumerate() {
+        let i: u32 = i.try_into().expect("Index overflow");
+
+        if let Node::Branch(b) = node {
+            assert!(b.left > i && b.right > i);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Structural check that the output of a regression forest never decreases
+//! (or never increases) with a feature, whatever the other features.
+//!
+//! Two inputs which only differ in the feature take the same path down a
+//! tree until its first branch splitting on the feature, below which the
+//! smaller value goes left. A tree is thus monotonic in the feature if, at
+//! every such branch, no leaf of the left subtree predicts more than a leaf
+//! of the right subtree (less, for [`Direction::Decreasing`]). The mean and
+//! the sum of monotonic trees are monotonic too.
+//!
+//! The check ignores the ranges the branches above a subtree restrict the
+//! features to, so it may report leaves no input reaches together: it never
+//! misses a violation, but may flag forests which are monotonic in practice.
+//!
+//! Classification forests vote, and are not checked: a change of vote is
+//! not ordered unless the classes are.
+
+use std::fmt;
+use std::str::FromStr;
+
+use color_eyre::{Result, eyre::eyre};
+
+use super::{Forest, Node};
+use crate::problem_type::{Classification, ProblemType, Regression};
+
+/// Direction in which the output must move when a feature increases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// The output never decreases
+    Increasing,
+    /// The output never increases
+    Decreasing,
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inc" | "increasing" => Ok(Direction::Increasing),
+            "dec" | "decreasing" => Ok(Direction::Decreasing),
+            _ => Err(format!("unknown direction '{s}', expected inc or dec")),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Increasing => write!(f, "increasing"),
+            Direction::Decreasing => write!(f, "decreasing"),
+        }
+    }
+}
+
+/// Branch splitting on the checked feature whose left subtree has a leaf on
+/// the wrong side of a leaf of its right subtree.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Violation {
+    pub tree: usize,
+    /// Index of the branch in [`Forest::nodes`]
+    pub node: usize,
+    /// Number of branches above the branch
+    pub depth: usize,
+    pub split_at: f32,
+    /// Largest leaf value of the left subtree, or smallest if decreasing
+    pub left: f32,
+    /// Smallest leaf value of the right subtree, or largest if decreasing
+    pub right: f32,
+}
+
+/// Verdict of the check of one feature, computed by
+/// [`Monotonicity::check_monotonic`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct MonotonicityCheck {
+    pub feature: String,
+    pub direction: Direction,
+    /// Violations of every tree, in tree order
+    pub violations: Vec<Violation>,
+}
+
+impl MonotonicityCheck {
+    /// Whether the forest is monotonic in the feature
+    pub fn is_monotonic(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Number of trees with at least one violation
+    pub fn violating_trees(&self) -> usize {
+        let mut trees = self.violations.iter().map(|v| v.tree).collect::<Vec<_>>();
+        trees.dedup();
+        trees.len()
+    }
+}
+
+impl fmt::Display for MonotonicityCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_monotonic() {
+            return writeln!(f, "{} ({}): monotonic", self.feature, self.direction);
+        }
+
+        writeln!(
+            f,
+            "{} ({}): {} violations in {} trees",
+            self.feature,
+            self.direction,
+            self.violations.len(),
+            self.violating_trees()
+        )?;
+        for v in &self.violations {
+            writeln!(
+                f,
+                "  tree {}, node {} (depth {}), split at {}: left leaf {} vs right leaf {}",
+                v.tree, v.node, v.depth, v.split_at, v.left, v.right
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Problem types whose forests can be checked for monotonicity.
+pub trait Monotonicity: ProblemType {
+    fn check_monotonic(
+        forest: &Forest<Self>,
+        feature: &str,
+        direction: Direction,
+    ) -> Result<MonotonicityCheck>;
+}
+
+impl Monotonicity for Regression {
+    fn check_monotonic(
+        forest: &Forest<Self>,
+        feature: &str,
+        direction: Direction,
+    ) -> Result<MonotonicityCheck> {
+        Ok(MonotonicityCheck {
+            feature: feature.to_string(),
+            direction,
+            violations: forest.verify_monotonic(feature, direction)?,
+        })
+    }
+}
+
+impl Monotonicity for Classification {
+    fn check_monotonic(
+        _forest: &Forest<Self>,
+        _feature: &str,
+        _direction: Direction,
+    ) -> Result<MonotonicityCheck> {
+        Err(eyre!(
+            "Only regression forests can be checked for monotonicity, not classification ones"
+        ))
+    }
+}
+
+impl Forest<Regression> {
+    /// Branches on `feature` which break the monotonicity of their tree in
+    /// `direction`, in tree order. See [`mod@self`] for the check.
+    pub fn verify_monotonic(&self, feature: &str, direction: Direction) -> Result<Vec<Violation>> {
+        let split_with = self
+            .features()
+            .index_of(feature)
+            .ok_or_else(|| eyre!("The forest has no feature '{feature}'"))?;
+
+        // Smallest and largest leaf value under each node, children first
+        let mut ranges = vec![(f32::INFINITY, f32::NEG_INFINITY); self.nodes.len()];
+        let mut violations = Vec::new();
+        for tree in self.trees() {
+            let walk = tree.walk().collect::<Vec<_>>();
+            for &(idx, _) in walk.iter().rev() {
+                ranges[idx] = match &self.nodes[idx] {
+                    Node::Leaf(l) => (l.prediction, l.prediction),
+                    Node::Branch(b) => {
+                        let (left, right) = (ranges[b.left as usize], ranges[b.right as usize]);
+                        (left.0.min(right.0), left.1.max(right.1))
+                    }
+                };
+            }
+
+            for &(idx, depth) in &walk {
+                let Node::Branch(b) = &self.nodes[idx] else {
+                    continue;
+                };
+                if b.split_with != split_with {
+                    continue;
+                }
+                let (left, right) = (ranges[b.left as usize], ranges[b.right as usize]);
+                let (left, right, violated) = match direction {
+                    Direction::Increasing => (left.1, right.0, left.1 > right.0),
+                    Direction::Decreasing => (left.0, right.1, left.0 < right.1),
+                };
+                if violated {
+                    violations.push(Violation {
+                        tree: tree.index(),
+                        node: idx,
+                        depth,
+                        split_at: b.split_at,
+                        left,
+                        right,
+                    });
+                }
+            }
+        }
+
+        Ok(violations)
+    }
+}
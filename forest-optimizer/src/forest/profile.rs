@@ -0,0 +1,115 @@
+//! Traversal profiles of a [`Forest`]: how often the rows of a
+//! representative dataset go left and right at every branch, so that the
+//! serialized forest can store the child most rows go to right after its
+//! parent, see [`NodeOrder::Profiled`].
+//!
+//! Profiles are saved as JSON, to lay out later conversions of the same
+//! forest definition without the dataset:
+//!
+//! ```json
+//! { "rows": 150, "counts": [[103, 47], [0, 0], ...] }
+//! ```
+//!
+//! [`NodeOrder::Profiled`]: crate::write_forest::NodeOrder::Profiled
+
+use std::path::Path;
+
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+
+use super::Forest;
+use crate::problem_type::ProblemType;
+
+/// Number of rows going left and right at every node of a forest.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraversalProfile {
+    /// Rows of the profiled dataset
+    pub rows: u64,
+    /// Rows going left, then right, from every node, by index in
+    /// [`Forest::nodes`]. Zero for leaves
+    pub counts: Vec<[u64; 2]>,
+}
+
+impl TraversalProfile {
+    /// Read a profile from a JSON file.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read(path)
+            .with_context(|| format!("Could not read profile file {}", path.display()))?;
+        serde_json::from_slice(&json)
+            .with_context(|| format!("Could not parse profile file {}", path.display()))
+    }
+
+    /// Write the profile to a JSON file.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Could not write profile file {}", path.display()))
+    }
+
+    /// Whether more rows go right than left at every branch of the
+    /// optimized `forest`, in the order of [`Forest::optimize_nodes`]. Ties
+    /// keep the left child first.
+    ///
+    /// Fails if the profile was not made from a forest of the same shape.
+    pub fn hot_right<P: ProblemType>(&self, forest: &Forest<P>) -> Result<Vec<bool>> {
+        let nodes = forest.nodes();
+        if self.counts.len() != nodes.len()
+            || nodes
+                .iter()
+                .zip(&self.counts)
+                .any(|(node, &counts)| node.is_leaf() && counts != [0, 0])
+        {
+            return Err(eyre!(
+                "The profile does not match the forest: it was made from another forest"
+            ));
+        }
+
+        // Roots are branches of the optimized forest, even single leaves
+        Ok(nodes
+            .iter()
+            .zip(&self.counts)
+            .enumerate()
+            .filter(|(i, (node, _))| node.is_branch() || *i < forest.num_trees())
+            .map(|(_, (_, [left, right]))| right > left)
+            .collect())
+    }
+
+    /// Share of the branch visits going to the child stored right after its
+    /// branch, when the left child is (`hot` false), or when the child most
+    /// rows go to is (`hot` true). 0 if no branch was visited.
+    pub fn sequential_share(&self, hot: bool) -> f64 {
+        let (sequential, visits) =
+            self.counts
+                .iter()
+                .fold((0, 0), |(sequential, visits), &[left, right]| {
+                    let next = if hot { left.max(right) } else { left };
+                    (sequential + next, visits + left + right)
+                });
+        if visits == 0 {
+            0.0
+        } else {
+            sequential as f64 / visits as f64
+        }
+    }
+}
+
+impl<P: ProblemType> Forest<P> {
+    /// Count how many of `rows` go left and right at every branch.
+    pub fn profile(&self, rows: &[Vec<f32>]) -> TraversalProfile {
+        let mut counts = vec![[0; 2]; self.nodes().len()];
+        for row in rows {
+            for tree in self.trees() {
+                tree.trace(row, |branch, right| counts[branch][usize::from(right)] += 1);
+            }
+        }
+
+        TraversalProfile {
+            rows: rows.len() as u64,
+            counts,
+        }
+    }
+}
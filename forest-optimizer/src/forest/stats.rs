@@ -0,0 +1,419 @@
+//! Shape statistics of a [`Forest`]: the size and depth of its trees, the
+//! features it splits on and what its leaves predict.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use super::{Forest, Node};
+use crate::problem_type::{Classification, ProblemType, Regression};
+use crate::quantize::{LeafType, ThresholdType};
+
+/// Bins of the histogram of [`LeafDistribution`], unless given.
+pub const DEFAULT_LEAF_BINS: usize = 10;
+
+/// Size and depth of one tree of a forest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct TreeShape {
+    pub index: usize,
+    pub nodes: usize,
+    pub branches: usize,
+    pub leaves: usize,
+    /// Number of branches on the longest path from the root to a leaf
+    pub depth: usize,
+}
+
+/// Number of trees of a given depth or size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct HistogramBin {
+    pub value: usize,
+    pub trees: usize,
+}
+
+/// How often, and how close to the roots, a feature is split on.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FeatureUsage {
+    pub index: usize,
+    pub name: String,
+    /// Number of branches splitting on the feature
+    pub branches: usize,
+    /// Number of trees splitting on the feature at least once
+    pub trees: usize,
+    /// Fraction of the trees splitting on the feature at least once
+    pub tree_fraction: f64,
+    /// Depth of the shallowest branch splitting on the feature, roots
+    /// being at depth 0. `None` if no branch does.
+    pub min_depth: Option<usize>,
+}
+
+/// Number of leaves predicting a class.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ClassLeaves {
+    pub index: usize,
+    pub name: String,
+    pub leaves: usize,
+}
+
+/// What the leaves of a forest predict.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeafPredictions {
+    /// Number of leaves predicting each class, in class order
+    Classes(Vec<ClassLeaves>),
+    /// Smallest, largest and mean value of the regression leaves
+    Values { min: f32, max: f32, mean: f64 },
+}
+
+/// Leaves of a regression forest whose value falls in `start..end`, or
+/// `start..=end` for the last bin.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct LeafBin {
+    pub start: f32,
+    pub end: f32,
+    pub leaves: usize,
+}
+
+/// Difference between the leaves of a forest and the values a narrower
+/// type would store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct LeafError {
+    /// Largest absolute difference
+    pub max: f64,
+    /// Root mean squared difference
+    pub rms: f64,
+}
+
+impl LeafError {
+    /// Error of rounding each of `leaves` with `round`.
+    fn of(leaves: &[f32], round: impl Fn(f32) -> f32) -> Self {
+        let (mut max, mut squared) = (0.0f64, 0.0);
+        for &leaf in leaves {
+            let error = (f64::from(leaf) - f64::from(round(leaf))).abs();
+            max = max.max(error);
+            squared += error * error;
+        }
+
+        Self {
+            max,
+            rms: (squared / leaves.len().max(1) as f64).sqrt(),
+        }
+    }
+}
+
+/// Distribution of the leaf values of a regression forest, to choose how to
+/// quantize them.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct LeafDistribution {
+    pub leaves: usize,
+    /// Number of distinct values, each stored once in the leaf table
+    pub distinct: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f64,
+    /// Bins of equal width from `min` to `max`
+    pub histogram: Vec<LeafBin>,
+    /// Error of storing leaves as 16-bit levels from `min` to `max`, like
+    /// `quantize --leaves u16`
+    pub u16_error: LeafError,
+    /// Error of storing leaves as IEEE 754 half precision numbers
+    pub f16_error: LeafError,
+}
+
+impl LeafDistribution {
+    /// Distribution of `leaves` over `bins` bins. `bins` must not be 0.
+    pub fn new(leaves: &[f32], bins: usize) -> Self {
+        let (min, max) = leaves
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &leaf| {
+                (min.min(leaf), max.max(leaf))
+            });
+        let mean =
+            leaves.iter().map(|&leaf| f64::from(leaf)).sum::<f64>() / leaves.len().max(1) as f64;
+
+        let width = (max - min) / bins as f32;
+        let mut histogram = (0..bins)
+            .map(|bin| LeafBin {
+                start: min + bin as f32 * width,
+                end: if bin + 1 == bins {
+                    max
+                } else {
+                    min + (bin + 1) as f32 * width
+                },
+                leaves: 0,
+            })
+            .collect::<Vec<_>>();
+        for &leaf in leaves {
+            // The largest values belong to the last bin
+            let bin = if width > 0.0 {
+                (((leaf - min) / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            histogram[bin].leaves += 1;
+        }
+
+        Self {
+            leaves: leaves.len(),
+            distinct: leaves
+                .iter()
+                .map(|leaf| leaf.to_bits())
+                .collect::<HashSet<_>>()
+                .len(),
+            min,
+            max,
+            mean,
+            histogram,
+            u16_error: LeafError::of(leaves, |leaf| LeafType::U16.round_affine(leaf, min, max)),
+            f16_error: LeafError::of(leaves, |leaf| ThresholdType::F16.round(leaf)),
+        }
+    }
+}
+
+impl fmt::Display for LeafDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} leaves, {} distinct values | {} to {}, {:.4} on average",
+            self.leaves, self.distinct, self.min, self.max, self.mean
+        )?;
+        writeln!(f, "From            To              Leaves")?;
+        for bin in &self.histogram {
+            writeln!(f, "{:<14}  {:<14}  {:>6}", bin.start, bin.end, bin.leaves)?;
+        }
+        for (name, error) in [("u16", self.u16_error), ("f16", self.f16_error)] {
+            writeln!(
+                f,
+                "As {name}: max error {:.4e}, RMS {:.4e}",
+                error.max, error.rms
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Problem types whose leaf predictions can be summarized.
+pub trait LeafStats: ProblemType {
+    fn leaf_predictions(forest: &Forest<Self>) -> LeafPredictions;
+
+    /// Distribution of the leaf values over `bins` bins, for regression
+    /// forests only.
+    fn leaf_distribution(forest: &Forest<Self>, bins: usize) -> Option<LeafDistribution>;
+}
+
+/// Predictions of every leaf of `forest`.
+fn leaves<P: ProblemType>(forest: &Forest<P>) -> impl Iterator<Item = P::Output> {
+    forest
+        .nodes()
+        .iter()
+        .filter_map(|node| node.take_leaf().map(|leaf| leaf.prediction.into()))
+}
+
+impl LeafStats for Classification {
+    fn leaf_predictions(forest: &Forest<Self>) -> LeafPredictions {
+        let mut classes = forest
+            .targets_by_index()
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| ClassLeaves {
+                index,
+                name: name.to_string(),
+                leaves: 0,
+            })
+            .collect::<Vec<_>>();
+        for class in leaves(forest) {
+            if let Some(class) = classes.get_mut(class as usize) {
+                class.leaves += 1;
+            }
+        }
+
+        LeafPredictions::Classes(classes)
+    }
+
+    fn leaf_distribution(_: &Forest<Self>, _: usize) -> Option<LeafDistribution> {
+        None
+    }
+}
+
+impl LeafStats for Regression {
+    fn leaf_predictions(forest: &Forest<Self>) -> LeafPredictions {
+        let (mut min, mut max, mut sum, mut count) = (f32::INFINITY, f32::NEG_INFINITY, 0.0, 0);
+        for value in leaves(forest) {
+            min = min.min(value);
+            max = max.max(value);
+            sum += f64::from(value);
+            count += 1;
+        }
+
+        LeafPredictions::Values {
+            min,
+            max,
+            mean: sum / f64::from(count.max(1)),
+        }
+    }
+
+    fn leaf_distribution(forest: &Forest<Self>, bins: usize) -> Option<LeafDistribution> {
+        Some(LeafDistribution::new(
+            &leaves(forest).collect::<Vec<_>>(),
+            bins,
+        ))
+    }
+}
+
+/// Statistics of a forest, computed by [`Forest::stats`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ForestStats {
+    /// Size and depth of each tree, in order
+    pub trees: Vec<TreeShape>,
+    /// Number of trees of each depth, by increasing depth. Depths no tree
+    /// has are left out.
+    pub depth_histogram: Vec<HistogramBin>,
+    /// Number of trees of each number of nodes, by increasing size. Sizes no
+    /// tree has are left out.
+    pub size_histogram: Vec<HistogramBin>,
+    /// How often each feature is split on, in feature order
+    pub feature_usage: Vec<FeatureUsage>,
+    pub leaf_predictions: LeafPredictions,
+    /// Distribution of the leaf values over [`DEFAULT_LEAF_BINS`] bins.
+    /// Regression only
+    pub leaf_distribution: Option<LeafDistribution>,
+}
+
+/// Bins of the values of `trees`.
+fn histogram(trees: impl Iterator<Item = usize>) -> Vec<HistogramBin> {
+    let mut bins = BTreeMap::new();
+    for value in trees {
+        *bins.entry(value).or_default() += 1;
+    }
+
+    bins.into_iter()
+        .map(|(value, trees)| HistogramBin { value, trees })
+        .collect()
+}
+
+impl<P: LeafStats> Forest<P> {
+    pub fn stats(&self) -> ForestStats {
+        let trees = self
+            .trees()
+            .map(|tree| {
+                let branches = tree.num_branches();
+                TreeShape {
+                    index: tree.index(),
+                    nodes: tree.num_nodes(),
+                    branches,
+                    leaves: tree.num_nodes() - branches,
+                    depth: tree.depth(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut feature_usage = self
+            .features_by_index()
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| FeatureUsage {
+                index,
+                name: name.to_string(),
+                branches: 0,
+                trees: 0,
+                tree_fraction: 0.0,
+                min_depth: None,
+            })
+            .collect::<Vec<_>>();
+        for tree in self.trees() {
+            // Shallowest depth of each feature in this tree
+            let mut depths = vec![None; feature_usage.len()];
+            for (idx, depth) in tree.walk() {
+                if let Node::Branch(b) = &self.nodes()[idx]
+                    && let Some(feature) = feature_usage.get_mut(b.split_with.as_usize())
+                {
+                    feature.branches += 1;
+                    let shallowest = &mut depths[feature.index];
+                    *shallowest = Some(shallowest.map_or(depth, |d: usize| d.min(depth)));
+                }
+            }
+            for (feature, depth) in feature_usage.iter_mut().zip(depths) {
+                if let Some(depth) = depth {
+                    feature.trees += 1;
+                    feature.min_depth = Some(feature.min_depth.map_or(depth, |d| d.min(depth)));
+                }
+            }
+        }
+        for feature in &mut feature_usage {
+            feature.tree_fraction = feature.trees as f64 / trees.len().max(1) as f64;
+        }
+
+        ForestStats {
+            depth_histogram: histogram(trees.iter().map(|t| t.depth)),
+            size_histogram: histogram(trees.iter().map(|t| t.nodes)),
+            trees,
+            feature_usage,
+            leaf_predictions: P::leaf_predictions(self),
+            leaf_distribution: P::leaf_distribution(self, DEFAULT_LEAF_BINS),
+        }
+    }
+}
+
+impl ForestStats {
+    /// Number of nodes of the forest
+    pub fn nodes(&self) -> usize {
+        self.trees.iter().map(|t| t.nodes).sum()
+    }
+
+    pub fn branches(&self) -> usize {
+        self.trees.iter().map(|t| t.branches).sum()
+    }
+
+    pub fn leaves(&self) -> usize {
+        self.trees.iter().map(|t| t.leaves).sum()
+    }
+
+    /// Largest depth of a tree
+    pub fn max_depth(&self) -> usize {
+        self.trees.iter().map(|t| t.depth).max().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for ForestStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Trees: {} | Nodes: {} | Branches: {}, leaves: {} | Max depth: {}",
+            self.trees.len(),
+            self.nodes(),
+            self.branches(),
+            self.leaves(),
+            self.max_depth()
+        )?;
+
+        writeln!(f, "Depth  Trees")?;
+        for bin in &self.depth_histogram {
+            writeln!(f, "{:>5}  {:>5}", bin.value, bin.trees)?;
+        }
+        writeln!(f, "Nodes  Trees")?;
+        for bin in &self.size_histogram {
+            writeln!(f, "{:>5}  {:>5}", bin.value, bin.trees)?;
+        }
+
+        writeln!(f, "Feature splits:")?;
+        for feature in &self.feature_usage {
+            writeln!(f, "{:<20}  {:>8} branches", feature.name, feature.branches)?;
+        }
+
+        match &self.leaf_predictions {
+            LeafPredictions::Classes(classes) => {
+                writeln!(f, "Leaves per class:")?;
+                for class in classes {
+                    writeln!(f, "{:<20}  {:>8} leaves", class.name, class.leaves)?;
+                }
+            }
+            LeafPredictions::Values { min, max, mean } => {
+                writeln!(f, "Leaf values: {min} to {max}, {mean:.4} on average")?;
+            }
+        }
+        if let Some(distribution) = &self.leaf_distribution {
+            write!(f, "Leaf distribution: {distribution}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+//! Conversion of a forest definition one tree at a time, see
+//! [`Forest::from_csv_streaming`].
+//!
+//! [`Forest::from_serialized`] needs every row of the definition in memory,
+//! each with its own names. Streaming instead reads the rows of one tree,
+//! checks and normalizes them, and drops them before reading the next tree,
+//! so at most the rows of the largest tree are held at once, besides the
+//! normalized nodes. This requires the rows of each tree to be contiguous,
+//! and trees to come in increasing index order, like the exporter writes
+//! them.
+
+use std::{fs, io, path::Path};
+
+use color_eyre::{Result, eyre::eyre};
+
+use super::{
+    Forest, Node,
+    flatten::{GroupedTrees, NodeHoles, warn_missing_trees},
+};
+use crate::{
+    problem_type::ProblemType,
+    serialized_forest::{
+        HeaderCounts, IndexOrder, ReadOptions, SerializedForest, SerializedNode, read_rows,
+        validate::{diagnose_counts, report},
+    },
+};
+
+/// Size of a definition file, in bytes, above which `convert` reads it one
+/// tree at a time, unless its names are reordered.
+pub const STREAMING_THRESHOLD: u64 = 256 * 1024 * 1024;
+
+/// Rows of the tree being read.
+struct PendingTree<N> {
+    tree_idx: usize,
+    nodes: Vec<N>,
+    lines: Vec<u64>,
+}
+
+impl<N: SerializedNode> PendingTree<N> {
+    fn new(tree_idx: usize) -> Self {
+        Self {
+            tree_idx,
+            nodes: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Check the rows of this tree, see [`SerializedForest::check`], and
+    /// normalize them like [`GroupedTrees::normalize`] does.
+    fn finish(self, problem: &N::ProblemType) -> Result<Vec<Node<N::ProblemType>>> {
+        let serialized = SerializedForest::from_rows(self.nodes, self.lines);
+        report(&serialized.validate())?;
+
+        let mut tree = serialized.nodes().iter().collect::<Vec<_>>();
+        tree.sort_by_key(|n| n.node_idx());
+        let grouped = GroupedTrees {
+            tree_indices: vec![self.tree_idx],
+            trees: vec![tree],
+        };
+
+        let mut trees = grouped.normalize(problem, NodeHoles::Reject)?;
+        Ok(trees.pop().unwrap_or_default())
+    }
+}
+
+impl<P> Forest<P>
+where
+    P: ProblemType,
+{
+    /// Read and convert the forest definition (CSV) at `path` one tree at a
+    /// time, see [`mod@self`]. The forest is the same as
+    /// [`Forest::from_serialized`] makes of it.
+    pub fn from_csv_streaming<N: SerializedNode<ProblemType = P>>(
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        Self::from_csv_streaming_with::<N>(path, &ReadOptions::default())
+    }
+
+    /// [`Forest::from_csv_streaming`] following `options`, whose order must
+    /// be the default one: names cannot be reindexed before the whole
+    /// definition is read.
+    pub fn from_csv_streaming_with<N: SerializedNode<ProblemType = P>>(
+        path: impl AsRef<Path>,
+        options: &ReadOptions,
+    ) -> Result<Self> {
+        Self::from_reader_streaming::<N>(fs::File::open(path.as_ref())?, options)
+    }
+
+    /// [`Forest::from_csv_streaming_with`] from any source, e.g. stdin.
+    ///
+    /// Each tree is checked once its rows are read, so this fails on the
+    /// first tree with an error, and only checks the counts declared by the
+    /// header at the end.
+    #[tracing::instrument(name = "flatten", skip_all)]
+    pub fn from_reader_streaming<N: SerializedNode<ProblemType = P>>(
+        rdr: impl io::Read,
+        options: &ReadOptions,
+    ) -> Result<Self> {
+        if options.order != IndexOrder::default() {
+            return Err(eyre!(
+                "Names cannot be reordered when reading a definition one tree at a time"
+            ));
+        }
+
+        let mut problem = P::default();
+        let mut tree_indices = Vec::new();
+        let mut trees = Vec::new();
+        let mut pending: Option<PendingTree<N>> = None;
+        let (declared, split_rule) =
+            read_rows(rdr, &mut problem, options, |node: N, line, problem| {
+                let tree_idx = node.tree_idx();
+                let tree = match pending.take() {
+                    Some(tree) if tree.tree_idx == tree_idx => tree,
+                    Some(tree) if tree.tree_idx > tree_idx => {
+                        return Err(eyre!(
+                            "Line {line} belongs to tree {tree_idx}, after tree {}: \
+                         the rows of each tree must be together, in increasing tree order",
+                            tree.tree_idx
+                        ));
+                    }
+                    Some(tree) => {
+                        tree_indices.push(tree.tree_idx);
+                        trees.push(tree.finish(problem)?);
+                        PendingTree::new(tree_idx)
+                    }
+                    None => PendingTree::new(tree_idx),
+                };
+
+                let tree = pending.insert(tree);
+                tree.nodes.push(node);
+                tree.lines.push(line);
+                Ok(())
+            })?;
+        if let Some(tree) = pending {
+            tree_indices.push(tree.tree_idx);
+            trees.push(tree.finish(&problem)?);
+        }
+
+        let counts = HeaderCounts {
+            num_trees: Some(trees.len()),
+            num_features: Some(problem.features().len()),
+            num_targets: N::num_targets(&problem),
+        };
+        report(&diagnose_counts(
+            declared,
+            counts,
+            options.allow_count_mismatch,
+        ))?;
+        warn_missing_trees(&tree_indices);
+
+        let forest = Self::from_trees(trees, problem).with_split_rule(split_rule);
+        forest.warn_suspicious();
+
+        Ok(forest)
+    }
+}
@@ -0,0 +1,196 @@
+use std::{fmt, fs, path::Path};
+
+use aligned_vec::AVec;
+use color_eyre::{
+    Result,
+    eyre::{Context, Report, eyre},
+};
+use embedded_rforest::{
+    Error, ErrorKind,
+    forest::{
+        AnyOptimizedForest, Classification, ProblemKind, Regression,
+        abi::FORMAT_ABI,
+        deserialize::{BUFFER_ALIGN, FORMAT_VERSION, ForestHeader},
+        encryption::decrypt_in_place,
+        signature::split_signature,
+    },
+};
+
+use crate::{
+    encryption::EncryptionKey,
+    problem_type::PredictionType,
+    sections::{SectionSize, SizeTable, size_breakdown},
+};
+
+/// Read a serialized forest from disk into a buffer aligned for
+/// [`OptimizedForest::deserialize`](embedded_rforest::forest::OptimizedForest::deserialize).
+pub fn read_model(path: impl AsRef<Path>) -> Result<AVec<u8>> {
+    let bytes = fs::read(path.as_ref()).context("Could not read serialized forest file")?;
+    Ok(AVec::from_slice(BUFFER_ALIGN, &bytes))
+}
+
+/// What the [`Error::code`] `code` means, e.g. to decode the codes firmware
+/// built without the `fmt` feature logged.
+pub fn explain_error_code(code: u8) -> &'static str {
+    ErrorKind::from_code(code).map_or("unknown error code", ErrorKind::description)
+}
+
+/// Summary of a serialized forest, as printed by `forest-optimizer info`.
+#[derive(Debug, serde::Serialize)]
+pub struct ForestInfo {
+    pub problem_type: PredictionType,
+    pub num_trees: u32,
+    pub num_features: u8,
+    pub num_targets: Option<u8>,
+    /// `standard`, `narrow` or `compact` branches
+    pub layout: String,
+    /// How the outputs of the trees are combined, e.g. `mean value`
+    pub aggregation: String,
+    /// Transform of the combined output, e.g. `sigmoid`
+    pub transform: String,
+    /// Whether the class probabilities are calibrated. Classification only
+    pub calibrated: bool,
+    /// Whether the number of training samples of every leaf is recorded
+    pub support: bool,
+    /// How branches compare features to their thresholds, `<=` or `<`
+    pub split_rule: String,
+    /// Whether everything following the header is encrypted
+    pub encrypted: bool,
+    /// Whether the forest ends with a signature, not verified, see
+    /// [`signature`](crate::signature)
+    pub signed: bool,
+    pub format_version: u8,
+    /// Size of the header, in bytes, see
+    /// [`FORMAT_ABI`](embedded_rforest::forest::abi::FORMAT_ABI)
+    pub header_size: usize,
+    /// Size of a branch of the layout, in bytes
+    pub branch_size: usize,
+    pub node_count: usize,
+    /// Number of values of the leaf table, 0 if leaves are stored in the
+    /// child pointers
+    pub leaf_count: usize,
+    pub serialized_size: usize,
+    /// Bytes taken by every section of the forest, in order
+    pub sections: Vec<SectionSize>,
+    /// Outcome of the structural validation pass. `None` if the forest is
+    /// valid, otherwise the reason it was rejected.
+    pub validation_error: Option<String>,
+}
+
+impl ForestInfo {
+    pub fn is_valid(&self) -> bool {
+        self.validation_error.is_none()
+    }
+}
+
+/// Inspect a serialized forest.
+///
+/// Only a malformed header is an error; a forest whose nodes fail
+/// validation is still reported, with the failure recorded in
+/// [`ForestInfo::validation_error`].
+pub fn inspect(buffer: &[u8]) -> Result<ForestInfo> {
+    inspect_with(buffer, None)
+}
+
+/// [`inspect`] a serialized forest, decrypted with `key` to be validated if
+/// it is encrypted. Encrypted forests fail validation without a key.
+pub fn inspect_with(buffer: &[u8], key: Option<&EncryptionKey>) -> Result<ForestInfo> {
+    // A signed forest is inspected without its signature
+    let (buffer, signed) = match split_signature(buffer) {
+        Ok((forest, _)) => (forest, true),
+        Err(_) => (buffer, false),
+    };
+    let header = ForestHeader::peek(buffer).map_err(|e| match e {
+        Error::UnsupportedVersion { found, .. } if found < FORMAT_VERSION => eyre!(
+            "Forest was serialized in format version {found}, older than {FORMAT_VERSION}. Convert it again, or upgrade it with `forest-optimizer migrate`"
+        ),
+        e @ Error::UnsupportedVersion { .. } => {
+            Report::new(e).wrap_err("Forest was serialized by a newer version of forest-optimizer")
+        }
+        e => Report::new(e).wrap_err("Malformed forest header"),
+    })?;
+
+    let validate = |buffer: &[u8]| match header.problem_kind() {
+        ProblemKind::Classification => {
+            AnyOptimizedForest::<Classification>::deserialize(buffer).map(|_| ())
+        }
+        ProblemKind::Regression => {
+            AnyOptimizedForest::<Regression>::deserialize(buffer).map(|_| ())
+        }
+    };
+    let validation = match key {
+        Some(key) if header.encrypted => {
+            let mut decrypted = AVec::<u8>::from_slice(BUFFER_ALIGN, buffer);
+            decrypt_in_place(&mut decrypted, &key.0).and_then(|()| validate(&decrypted))
+        }
+        _ => validate(buffer),
+    };
+
+    Ok(ForestInfo {
+        problem_type: header.problem_kind().into(),
+        num_trees: header.num_trees,
+        num_features: header.num_features,
+        num_targets: header.num_targets.map(|t| t.get()),
+        layout: header.layout.to_string(),
+        aggregation: header.aggregation.to_string(),
+        transform: header.transform.to_string(),
+        calibrated: header.calibrated,
+        support: header.support,
+        split_rule: header.split_rule.to_string(),
+        encrypted: header.encrypted,
+        signed,
+        format_version: FORMAT_VERSION,
+        header_size: FORMAT_ABI.header.size,
+        branch_size: FORMAT_ABI.branch(header.layout).size,
+        node_count: header.node_count,
+        leaf_count: header.num_leaves,
+        serialized_size: buffer.len(),
+        sections: size_breakdown(&header),
+        validation_error: validation.err().map(|e| format!("{e:?}")),
+    })
+}
+
+impl fmt::Display for ForestInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Forest is a {} problem.", self.problem_type)?;
+        writeln!(f, "------------")?;
+        writeln!(f, "Trees:           {}", self.num_trees)?;
+        writeln!(f, "Features:        {}", self.num_features)?;
+        if let Some(targets) = self.num_targets {
+            writeln!(f, "Targets:         {targets}")?;
+        }
+        writeln!(f, "Layout:          {}", self.layout)?;
+        writeln!(f, "Aggregation:     {}", self.aggregation)?;
+        writeln!(f, "Transform:       {}", self.transform)?;
+        writeln!(f, "Split rule:      {}", self.split_rule)?;
+        if self.num_targets.is_some() {
+            let calibration = if self.calibrated { "platt" } else { "none" };
+            writeln!(f, "Calibration:     {calibration}")?;
+        }
+        writeln!(f, "Format version:  {}", self.format_version)?;
+        writeln!(f, "Header size:     {} bytes", self.header_size)?;
+        writeln!(f, "Branch size:     {} bytes", self.branch_size)?;
+        writeln!(f, "Nodes:           {}", self.node_count)?;
+        if self.leaf_count > 0 {
+            writeln!(f, "Leaf table:      {} values", self.leaf_count)?;
+        }
+        if self.support {
+            writeln!(f, "Leaf support:    recorded")?;
+        }
+        if self.encrypted {
+            writeln!(f, "Encryption:      chacha20")?;
+        }
+        if self.signed {
+            writeln!(f, "Signature:       ed25519, not verified")?;
+        }
+        writeln!(f, "Serialized size: {} bytes", self.serialized_size)?;
+        write!(f, "{}", SizeTable(&self.sections))?;
+        match &self.validation_error {
+            None => writeln!(f, "Validation:      OK")?,
+            Some(e) => writeln!(f, "Validation:      FAILED ({e})")?,
+        }
+        writeln!(f, "------------")?;
+
+        Ok(())
+    }
+}
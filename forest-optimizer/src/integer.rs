@@ -0,0 +1,460 @@
+//! Conversion of a forest to integer-only arithmetic, for targets without a
+//! floating-point unit.
+//!
+//! Every feature gets a fixed-point scale `2^shift`, the finest that keeps
+//! its thresholds within `±2^30`, and every threshold is stored as
+//! `floor(threshold * 2^shift)` in an
+//! [`IntegerBranch`](embedded_rforest::forest::IntegerBranch). Regression
+//! leaves get a single scale of their own, which predictions are made in.
+//! The scales are written to the metadata of the forest and to its source
+//! code artifacts, so that firmware converts its readings consistently.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use aligned_vec::AVec;
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::{
+    forest::{
+        self as embedded, Branch, IntegerBranch, OptimizedForest,
+        integer::{SHIFT_RANGE, to_fixed},
+    },
+    ptr::{F32, NodePointer},
+};
+
+use crate::{
+    emit::{OutputFormat, emit_all},
+    forest::Forest,
+    problem_type::{Classification, PredictionType, Regression},
+    prune::{LayoutWriter, Prune, Validation, layout_file},
+    quantize::{RoundingStats, ValidationChange},
+    serialized_forest::ReadOptions,
+    write_forest::{Endianness, WriteForest, fingerprint, group_trees, num_features, num_trees},
+};
+
+/// Largest magnitude of a threshold or leaf in fixed point, leaving one bit
+/// of headroom for features beyond the thresholds
+const MAX_FIXED: f64 = (1u32 << 30) as f64;
+
+/// Fixed-point scales of an integer forest, as powers of two.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FixedPointScales {
+    /// Shift of every feature, positioned by feature index: feature `j` is
+    /// passed to `predict_i32` as `floor(value * 2^feature_shifts[j])`
+    pub feature_shifts: Vec<i8>,
+    /// Shift of the regression leaves, which predictions are made in: a
+    /// prediction `p` stands for `p / 2^leaf_shift`. Only present for
+    /// regression forests.
+    pub leaf_shift: Option<i8>,
+}
+
+/// `2^shift`
+fn scale(shift: i8) -> f64 {
+    2f64.powi(shift.into())
+}
+
+/// The finest shift keeping values of magnitude up to `max_abs` within
+/// [`MAX_FIXED`].
+fn shift_for(max_abs: f64) -> Result<i8> {
+    if !max_abs.is_finite() {
+        return Err(eyre!("{max_abs} has no fixed-point representation"));
+    }
+    if max_abs == 0.0 {
+        return Ok(0);
+    }
+
+    let mut shift = (MAX_FIXED / max_abs)
+        .log2()
+        .floor()
+        .clamp((*SHIFT_RANGE.start()).into(), (*SHIFT_RANGE.end()).into())
+        as i8;
+    // Correct the rounding of the logarithm
+    while shift > *SHIFT_RANGE.start() && max_abs * scale(shift) > MAX_FIXED {
+        shift -= 1;
+    }
+    if max_abs * scale(shift) > MAX_FIXED {
+        return Err(eyre!("{max_abs} is too large for a fixed-point scale"));
+    }
+    Ok(shift)
+}
+
+/// Shift of every feature, from the largest threshold it is compared to.
+/// Features no branch splits on get a shift of 0.
+fn feature_shifts(nodes: &[Branch], num_features: usize) -> Result<Vec<i8>> {
+    let mut max_abs = vec![0.0f64; num_features];
+    for branch in nodes {
+        let max = max_abs
+            .get_mut(branch.split_with() as usize)
+            .ok_or_else(|| eyre!("Malformed forest"))?;
+        *max = max.max(f64::from(branch.split_at()).abs());
+    }
+
+    max_abs.into_iter().map(shift_for).collect()
+}
+
+/// Problem types whose forests can be converted to integer arithmetic.
+pub trait Integer: Prune {
+    /// Shift of the leaves, from the optimized leaf table, if leaves are
+    /// approximated at all.
+    fn leaf_shift(leaves: &[F32]) -> Result<Option<i8>>;
+
+    /// Optimize a forest, and serialize it with integer branches in the
+    /// given scales.
+    fn serialize_integer(forest: &Forest<Self>, scales: &FixedPointScales) -> Result<AVec<u8>>;
+
+    /// Prediction of an integer forest for features in its scales, converted
+    /// back to an output of the original forest.
+    fn predict_fixed(
+        forest: &OptimizedForest<'_, Self::OptimizedType, IntegerBranch>,
+        scales: &FixedPointScales,
+        features: &[i32],
+    ) -> Self::Output;
+
+    /// Largest difference between predictions of the original and the
+    /// integer forest, for outputs where it is meaningful.
+    fn max_difference(original: &[Self::Output], integer: &[Self::Output]) -> Option<f64>;
+}
+
+impl Integer for Classification {
+    /// Class indices are integers already.
+    fn leaf_shift(_: &[F32]) -> Result<Option<i8>> {
+        Ok(None)
+    }
+
+    fn serialize_integer(forest: &Forest<Self>, scales: &FixedPointScales) -> Result<AVec<u8>> {
+        let (nodes, tree_offsets) =
+            group_trees(&Self::optimize(forest).0, forest.num_trees(), &[])?;
+        let nodes = integer_branches(&nodes, scales, |ptr| Ok(ptr.index()))?;
+        let num_targets = forest
+            .num_targets()
+            .try_into()
+            .map_err(|_| eyre!("Forest has more than 255 targets"))?;
+        let problem =
+            embedded::Classification::new(num_targets).context("Forest has no targets")?;
+
+        let optimized = OptimizedForest::<embedded::Classification, IntegerBranch>::new(
+            num_trees(forest)?,
+            &nodes,
+            num_features(forest)?,
+            problem,
+        )
+        .and_then(|optimized| optimized.with_tree_offsets(&tree_offsets))
+        .map(|optimized| {
+            optimized
+                .with_fingerprint(fingerprint(forest))
+                .with_split_rule(forest.split_rule())
+        })
+        .context("Malformed forest")?;
+
+        Ok(optimized.to_bytes())
+    }
+
+    fn predict_fixed(
+        forest: &OptimizedForest<'_, embedded::Classification, IntegerBranch>,
+        _: &FixedPointScales,
+        features: &[i32],
+    ) -> u32 {
+        forest.predict_i32(features)
+    }
+
+    /// Classes differ or not, as counted by the flips.
+    fn max_difference(_: &[u32], _: &[u32]) -> Option<f64> {
+        None
+    }
+}
+
+impl Integer for Regression {
+    fn leaf_shift(leaves: &[F32]) -> Result<Option<i8>> {
+        let max_abs = leaves
+            .iter()
+            .map(|leaf| f64::from(leaf.get()).abs())
+            .fold(0.0, f64::max);
+        shift_for(max_abs).map(Some)
+    }
+
+    fn serialize_integer(forest: &Forest<Self>, scales: &FixedPointScales) -> Result<AVec<u8>> {
+        let leaf_shift = scales
+            .leaf_shift
+            .ok_or_else(|| eyre!("Regression forests need a leaf scale"))?;
+        // Leaf pointers hold the predictions rather than table indices
+        let (nodes, leaves) = Self::optimize(forest);
+        let (nodes, tree_offsets) = group_trees(&nodes, forest.num_trees(), &[])?;
+        let nodes = integer_branches(&nodes, scales, |ptr| {
+            let leaf = leaves
+                .get(ptr.index() as usize)
+                .ok_or_else(|| eyre!("Malformed forest"))?;
+            // Within ±2^30, as the shift was chosen for the largest leaf
+            Ok((f64::from(leaf.get()) * scale(leaf_shift)).round() as i32 as u32)
+        })?;
+
+        let optimized = OptimizedForest::<embedded::Regression, IntegerBranch>::new(
+            num_trees(forest)?,
+            &nodes,
+            num_features(forest)?,
+        )
+        .and_then(|optimized| optimized.with_tree_offsets(&tree_offsets))
+        .map(|optimized| {
+            optimized
+                .with_fingerprint(fingerprint(forest))
+                .with_split_rule(forest.split_rule())
+        })
+        .context("Malformed forest")?;
+
+        Ok(optimized.to_bytes())
+    }
+
+    fn predict_fixed(
+        forest: &OptimizedForest<'_, embedded::Regression, IntegerBranch>,
+        scales: &FixedPointScales,
+        features: &[i32],
+    ) -> f32 {
+        let leaf_shift = scales.leaf_shift.unwrap_or_default();
+        (f64::from(forest.predict_i32(features)) / scale(leaf_shift)) as f32
+    }
+
+    fn max_difference(original: &[f32], integer: &[f32]) -> Option<f64> {
+        Some(
+            original
+                .iter()
+                .zip(integer)
+                .map(|(&a, &b)| (f64::from(a) - f64::from(b)).abs())
+                .fold(0.0, f64::max),
+        )
+    }
+}
+
+/// Convert standard branches to integer ones in `scales`. `leaf` converts a
+/// leaf pointer.
+fn integer_branches(
+    nodes: &[Branch],
+    scales: &FixedPointScales,
+    leaf: impl Fn(NodePointer) -> Result<u32>,
+) -> Result<Vec<IntegerBranch>> {
+    let child = |ptr: NodePointer, is_prediction: bool| {
+        if is_prediction {
+            leaf(ptr)
+        } else {
+            Ok(ptr.index())
+        }
+    };
+
+    nodes
+        .iter()
+        .map(|branch| {
+            let split_with = u8::try_from(branch.split_with())
+                .map_err(|_| eyre!("Feature index {} does not fit 8 bits", branch.split_with()))?;
+            let shift = scales.feature_shifts[usize::from(split_with)];
+
+            Ok(IntegerBranch::new(
+                split_with,
+                to_fixed(branch.split_at(), shift),
+                child(branch.left_ptr(), branch.left_is_prediction())?,
+                child(branch.right_ptr(), branch.right_is_prediction())?,
+                branch.left_is_prediction(),
+                branch.right_is_prediction(),
+            ))
+        })
+        .collect()
+}
+
+/// Scales of the optimized form of `forest`, and the rounding of its
+/// thresholds to them.
+fn choose_scales<P: Integer>(forest: &Forest<P>) -> Result<(FixedPointScales, RoundingStats)> {
+    let (nodes, leaves) = P::optimize(forest);
+    let scales = FixedPointScales {
+        feature_shifts: feature_shifts(&nodes, forest.num_features())?,
+        leaf_shift: P::leaf_shift(&leaves)?,
+    };
+
+    let (mut max, mut sum) = (0.0f64, 0.0);
+    for branch in &nodes {
+        let shift = scales.feature_shifts[branch.split_with() as usize];
+        let fixed = f64::from(to_fixed(branch.split_at(), shift)) / scale(shift);
+        let error = (f64::from(branch.split_at()) - fixed).abs();
+        max = max.max(error);
+        sum += error;
+    }
+    let thresholds = RoundingStats {
+        max,
+        mean: if nodes.is_empty() {
+            0.0
+        } else {
+            sum / nodes.len() as f64
+        },
+    };
+
+    Ok((scales, thresholds))
+}
+
+/// Outcome of converting a forest to integer arithmetic.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct IntegerReport {
+    /// Scales the forest was converted to
+    pub scales: FixedPointScales,
+    /// Rounding of the thresholds to the scales of their features, in the
+    /// units of the features
+    pub thresholds: RoundingStats,
+    /// Largest rounding of a regression leaf to the leaf scale, which bounds
+    /// the error of predictions taking the same paths as the original forest
+    pub leaf_rounding: Option<f64>,
+    /// Name of the validation score
+    pub score: &'static str,
+    /// Effect of the conversion on the validation set, if any
+    pub validation: Option<ValidationChange>,
+    /// Largest difference between a regression prediction of the original
+    /// forest and of the integer one on the validation set, if any
+    pub max_prediction_error: Option<f64>,
+    /// Size of the written forest, in bytes
+    pub written_size: usize,
+}
+
+impl fmt::Display for IntegerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Layout:             integer (16-byte branches)")?;
+        writeln!(f, "Feature shifts:     {:?}", self.scales.feature_shifts)?;
+        if let Some(leaf_shift) = self.scales.leaf_shift {
+            writeln!(f, "Leaf shift:         {leaf_shift}")?;
+        }
+        writeln!(
+            f,
+            "Threshold rounding: max {:.4e}, mean {:.4e}",
+            self.thresholds.max, self.thresholds.mean
+        )?;
+        if let Some(leaf_rounding) = self.leaf_rounding {
+            writeln!(f, "Leaf rounding:      max {leaf_rounding:.4e}")?;
+        }
+        if let Some(validation) = self.validation {
+            writeln!(
+                f,
+                "{}: {:.3} -> {:.3}",
+                self.score, validation.before, validation.after
+            )?;
+            writeln!(
+                f,
+                "Prediction flips:   {} of {}",
+                validation.flips, validation.rows
+            )?;
+        }
+        if let Some(error) = self.max_prediction_error {
+            writeln!(f, "Prediction error:   max {error:.4e}")?;
+        }
+        writeln!(f, "Size:               {} bytes", self.written_size)
+    }
+}
+
+/// Convert a forest to integer arithmetic, and write it along with its
+/// metadata, which records the scales chosen.
+///
+/// With `validation`, the predictions of the integer forest on the dataset,
+/// converted to the scales, are compared to those of `forest`. With
+/// `max_drop` as well, nothing is written if the score drops by more than
+/// `max_drop` (see [`Prune::is_acceptable`]).
+pub fn write_integer<P: Integer>(
+    forest: &Forest<P>,
+    validation: Option<&Validation<P>>,
+    max_drop: Option<f64>,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: impl AsRef<Path>,
+) -> Result<IntegerReport> {
+    let (scales, thresholds) = choose_scales(forest)?;
+    let serialized = P::serialize_integer(forest, &scales)?;
+    let optimized = OptimizedForest::<P::OptimizedType, IntegerBranch>::deserialize(&serialized)
+        .context("Malformed forest")?;
+
+    let mut report = IntegerReport {
+        leaf_rounding: scales.leaf_shift.map(|shift| 0.5 / scale(shift)),
+        scales,
+        thresholds,
+        score: P::SCORE,
+        validation: None,
+        max_prediction_error: None,
+        written_size: serialized.len(),
+    };
+
+    if let Some(validation) = validation {
+        let original = validation.predict(forest);
+        let integer = validation
+            .rows()
+            .iter()
+            .map(|row| {
+                let features = row
+                    .iter()
+                    .zip(&report.scales.feature_shifts)
+                    .map(|(&value, &shift)| to_fixed(value, shift))
+                    .collect::<Vec<_>>();
+                P::predict_fixed(&optimized, &report.scales, &features)
+            })
+            .collect::<Vec<_>>();
+        let change = ValidationChange::new(validation, &original, &integer);
+        report.validation = Some(change);
+        report.max_prediction_error = P::max_difference(&original, &integer);
+
+        if let Some(max_drop) = max_drop
+            && !P::is_acceptable(change.before, change.after, max_drop)
+        {
+            return Err(eyre!(
+                "{} drops from {:.3} to {:.3}",
+                P::SCORE,
+                change.before,
+                change.after
+            ));
+        }
+    }
+
+    let mut metadata = P::metadata(forest);
+    metadata.fixed_point = Some(report.scales.clone());
+    emit_all(
+        &optimized,
+        &metadata,
+        Endianness::Little,
+        None,
+        artifacts,
+        metadata_path.as_ref(),
+    )?;
+
+    Ok(report)
+}
+
+/// [`write_integer`] for a forest definition file (CSV) whose problem type is
+/// detected from its header, or given by `problem_type`.
+///
+/// `validation` is a labeled dataset (CSV) and the name of its label column.
+pub fn integer_file(
+    input: impl AsRef<Path>,
+    problem_type: Option<PredictionType>,
+    options: &ReadOptions,
+    validation: Option<(&Path, &str)>,
+    max_drop: Option<f64>,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: impl AsRef<Path>,
+) -> Result<IntegerReport> {
+    layout_file::<IntegerWriter, _>(
+        input.as_ref(),
+        problem_type,
+        options,
+        validation,
+        max_drop,
+        artifacts,
+        metadata_path.as_ref(),
+    )
+}
+
+/// [`LayoutWriter`] of [`write_integer`].
+struct IntegerWriter;
+
+impl<P: Integer> LayoutWriter<P> for IntegerWriter {
+    type Report = IntegerReport;
+
+    fn write(
+        forest: &Forest<P>,
+        validation: Option<&Validation<P>>,
+        max_drop: Option<f64>,
+        artifacts: &[(OutputFormat, PathBuf)],
+        metadata_path: &Path,
+    ) -> Result<IntegerReport> {
+        write_integer(forest, validation, max_drop, artifacts, metadata_path)
+    }
+}
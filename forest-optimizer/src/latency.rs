@@ -0,0 +1,188 @@
+//! Worst-case latency of a prediction on the target, estimated from the
+//! depth of the trees and a cost per visited branch, without running it.
+//!
+//! Defaults are rough Cortex-M4 figures, with the forest in flash at zero
+//! wait states:
+//!
+//! | Layout   | Cycles per branch | Why                                       |
+//! |----------|-------------------|-------------------------------------------|
+//! | standard | 10                | load the feature and threshold, compare, branch |
+//! | narrow   | 11                | widen the 16-bit child pointer            |
+//! | relative | 12                | add the offset of the tree to the pointer |
+//! | compact  | 14                | dequantize the 16-bit threshold           |
+//! | integer  | 8                 | integer compare, no FPU                   |
+//!
+//! Measure a few predictions on the real target and pass
+//! `--cycles-per-node` for anything better than an order of magnitude.
+
+use std::fmt;
+
+use color_eyre::{Result, eyre::eyre};
+use embedded_rforest::forest::NodeLayout;
+
+use crate::{
+    forest::{Forest, profile::TraversalProfile},
+    problem_type::ProblemType,
+};
+
+/// Clock of the target, in MHz, unless told otherwise
+pub const DEFAULT_CLOCK_MHZ: f64 = 64.0;
+
+/// Cycles to add the output of a tree to the aggregate
+pub const CYCLES_PER_TREE: f64 = 4.0;
+
+/// Cycles to pick the most voted class, per class
+pub const CYCLES_PER_CLASS: f64 = 3.0;
+
+/// Cycles to visit one branch of a forest of `layout`, see the
+/// [module](self) documentation.
+pub fn default_cycles_per_node(layout: NodeLayout) -> f64 {
+    match layout {
+        NodeLayout::Standard => 10.0,
+        NodeLayout::Narrow => 11.0,
+        NodeLayout::Relative => 12.0,
+        NodeLayout::Compact => 14.0,
+        NodeLayout::Integer => 8.0,
+    }
+}
+
+/// Cycles to combine the outputs of `num_trees` trees: adding each to the
+/// aggregate, then picking the most voted of `num_targets` classes, if the
+/// forest classifies.
+pub fn aggregation_cycles(num_trees: usize, num_targets: Option<usize>) -> f64 {
+    num_trees as f64 * CYCLES_PER_TREE + num_targets.unwrap_or(0) as f64 * CYCLES_PER_CLASS
+}
+
+/// Cost of a prediction on the target.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct LatencyModel {
+    /// Cycles to visit one branch
+    pub cycles_per_node: f64,
+    pub clock_mhz: f64,
+}
+
+impl LatencyModel {
+    /// The defaults for a forest of `layout`
+    pub fn for_layout(layout: NodeLayout) -> Self {
+        Self {
+            cycles_per_node: default_cycles_per_node(layout),
+            clock_mhz: DEFAULT_CLOCK_MHZ,
+        }
+    }
+
+    /// Time to visit `visits` branches and spend `extra_cycles` more, in
+    /// microseconds
+    pub fn micros(&self, visits: f64, extra_cycles: f64) -> f64 {
+        (visits * self.cycles_per_node + extra_cycles) / self.clock_mhz
+    }
+}
+
+/// Number of branches of the optimized forest a prediction visits, over all
+/// its trees.
+///
+/// Single-leaf trees are optimized as one branch, which is always visited.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct NodeVisits {
+    /// Along the shortest path of every tree
+    pub best: usize,
+    /// Along the mean path of every tree: each leaf weighs the same, or as
+    /// many rows reach it when profiled
+    pub expected: f64,
+    /// Along the longest path of every tree
+    pub worst: usize,
+    /// Whether `expected` comes from a [`TraversalProfile`]
+    pub profiled: bool,
+}
+
+/// Count the branches a prediction with `forest` visits, weighting its
+/// paths by `profile` if given.
+///
+/// Fails if the profile was not made from a forest of the same shape, or
+/// from no rows.
+pub fn node_visits<P: ProblemType>(
+    forest: &Forest<P>,
+    profile: Option<&TraversalProfile>,
+) -> Result<NodeVisits> {
+    let (mut best, mut expected, mut worst) = (0, 0.0, 0);
+    for tree in forest.trees() {
+        let depths = tree.leaf_depths().collect::<Vec<_>>();
+        best += depths.iter().min().copied().unwrap_or(0).max(1);
+        worst += depths.iter().max().copied().unwrap_or(0).max(1);
+        expected += (depths.iter().sum::<usize>() as f64 / depths.len() as f64).max(1.0);
+    }
+
+    if let Some(profile) = profile {
+        if profile.counts.len() != forest.nodes().len() || profile.rows == 0 {
+            return Err(eyre!(
+                "The profile does not match the forest: it was made from another forest, or from no rows"
+            ));
+        }
+        let visits = profile.counts.iter().flatten().sum::<u64>();
+        let single_leaves = forest.trees().filter(|tree| tree.depth() == 0).count();
+        expected = visits as f64 / profile.rows as f64 + single_leaves as f64;
+    }
+
+    Ok(NodeVisits {
+        best,
+        expected,
+        worst,
+        profiled: profile.is_some(),
+    })
+}
+
+/// Best, expected and worst time of a prediction on the target.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct LatencyEstimate {
+    pub model: LatencyModel,
+    pub visits: NodeVisits,
+    /// Cycles to combine the outputs of the trees, see
+    /// [`aggregation_cycles`]
+    pub aggregation_cycles: f64,
+    pub best_us: f64,
+    pub expected_us: f64,
+    pub worst_us: f64,
+}
+
+impl LatencyEstimate {
+    pub fn new(model: LatencyModel, visits: NodeVisits, aggregation_cycles: f64) -> Self {
+        Self {
+            model,
+            visits,
+            aggregation_cycles,
+            best_us: model.micros(visits.best as f64, aggregation_cycles),
+            expected_us: model.micros(visits.expected, aggregation_cycles),
+            worst_us: model.micros(visits.worst as f64, aggregation_cycles),
+        }
+    }
+}
+
+impl fmt::Display for LatencyEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} cycles per branch at {} MHz, {} cycles to aggregate",
+            self.model.cycles_per_node, self.model.clock_mhz, self.aggregation_cycles
+        )?;
+        let weighting = if self.visits.profiled {
+            "profiled"
+        } else {
+            "leaves weigh the same"
+        };
+        writeln!(f, "{:<10}  Branches  Microseconds", "")?;
+        writeln!(
+            f,
+            "{:<10}  {:>8}  {:>12.2}",
+            "Best", self.visits.best, self.best_us
+        )?;
+        writeln!(
+            f,
+            "{:<10}  {:>8.1}  {:>12.2}  ({weighting})",
+            "Expected", self.visits.expected, self.expected_us
+        )?;
+        writeln!(
+            f,
+            "{:<10}  {:>8}  {:>12.2}",
+            "Worst", self.visits.worst, self.worst_us
+        )
+    }
+}
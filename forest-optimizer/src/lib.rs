@@ -1,7 +1,36 @@
 pub use embedded_rforest;
 
+pub mod agreement;
+pub mod analyze;
+pub mod batch;
+pub mod bench;
+pub mod build;
+pub mod bundle;
+pub mod calibration;
+pub mod cli;
+pub mod compact;
+pub mod compare;
+pub mod conversion;
+pub mod dataset;
+pub mod diff;
+pub mod emit;
+pub mod encryption;
+pub mod evaluate;
 pub mod forest;
+pub mod inspect;
+pub mod integer;
+pub mod latency;
+pub mod metadata;
+pub mod metrics;
+pub mod migrate;
 pub mod problem_type;
+pub mod prune;
+pub mod quantize;
+pub mod sections;
 pub mod serialized_forest;
+pub mod signature;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod test_vectors;
 pub mod typelevel;
 pub mod write_forest;
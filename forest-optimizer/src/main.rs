@@ -0,0 +1,14 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+use color_eyre::Result;
+
+use forest_optimizer::cli::Cli;
+
+fn main() -> Result<ExitCode> {
+    color_eyre::install()?;
+
+    let cli = Cli::parse();
+    cli.verbosity.init_tracing();
+    cli.command.run()
+}
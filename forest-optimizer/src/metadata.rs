@@ -0,0 +1,98 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{Result, eyre::Context};
+use embedded_rforest::forest::{NodeLayout, abi::FORMAT_ABI, fingerprint::fingerprint};
+
+use crate::{integer::FixedPointScales, problem_type::PredictionType};
+
+/// Names of the features and targets of an optimized forest.
+///
+/// The serialized forest only knows indices, so this is written next to it
+/// as a JSON sidecar file, letting tools map dataset columns and class labels
+/// back onto those indices.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ForestMetadata {
+    pub problem_type: PredictionType,
+    /// Feature names, positioned by feature index
+    pub features: Vec<String>,
+    /// Target names, positioned by target index. Only present for
+    /// classification forests.
+    pub targets: Option<Vec<String>>,
+    /// [`fingerprint`] of the feature and target names, which the header of
+    /// the forest records too. 0 in metadata written before fingerprints
+    #[serde(default)]
+    pub fingerprint: u32,
+    /// Fixed-point scales of the features and leaves of an integer forest,
+    /// see [`integer`](crate::integer)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_point: Option<FixedPointScales>,
+    /// Layout of the serialized format the forest was written in. Absent in
+    /// metadata written before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<AbiMetadata>,
+}
+
+/// Sizes of the structs of the serialized format, from [`FORMAT_ABI`], for
+/// readers of the forest which do not link embedded-rforest to check that
+/// they decode the same layout.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AbiMetadata {
+    pub format_version: u8,
+    /// Alignment the forest must be loaded at, in bytes
+    pub buffer_align: usize,
+    pub header_size: usize,
+    pub node_pointer_size: usize,
+    /// Size of a branch of each layout, by layout name
+    pub branch_sizes: BTreeMap<String, usize>,
+}
+
+impl AbiMetadata {
+    /// The layout of [`FORMAT_ABI`], which this optimizer writes
+    pub fn current() -> Self {
+        let branch_sizes = (0..)
+            .map_while(|layout| NodeLayout::try_from(layout).ok())
+            .map(|layout| (layout.to_string(), FORMAT_ABI.branch(layout).size))
+            .collect();
+
+        Self {
+            format_version: FORMAT_ABI.version,
+            buffer_align: FORMAT_ABI.buffer_align,
+            header_size: FORMAT_ABI.header.size,
+            node_pointer_size: FORMAT_ABI.node_pointer.size,
+            branch_sizes,
+        }
+    }
+}
+
+impl ForestMetadata {
+    /// `features` and `targets` are the names, positioned by index, e.g.
+    /// [`Forest::features_by_index`](crate::forest::Forest::features_by_index)
+    pub fn new(problem_type: PredictionType, features: &[&str], targets: Option<&[&str]>) -> Self {
+        let fingerprint = fingerprint(features, targets.unwrap_or_default());
+
+        Self {
+            problem_type,
+            features: features.iter().map(|name| name.to_string()).collect(),
+            targets: targets.map(|targets| targets.iter().map(|name| name.to_string()).collect()),
+            fingerprint,
+            fixed_point: None,
+            abi: Some(AbiMetadata::current()),
+        }
+    }
+
+    /// Path of the sidecar file belonging to a serialized forest
+    pub fn sidecar_path(model: impl AsRef<Path>) -> PathBuf {
+        let mut path = model.as_ref().as_os_str().to_owned();
+        path.push(".meta.json");
+        path.into()
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let file = fs::File::open(path.as_ref()).context("Could not open forest metadata file")?;
+        serde_json::from_reader(file).context("Malformed forest metadata file")
+    }
+}
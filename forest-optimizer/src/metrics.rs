@@ -0,0 +1,212 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// Precision, recall and F1 score of a single class.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ClassMetrics {
+    /// Fraction of the predictions of this class which were correct. Zero if
+    /// the class was never predicted.
+    pub precision: f64,
+    /// Fraction of the observations of this class which were predicted
+    /// correctly. Zero if the class never occurs in the truth set.
+    pub recall: f64,
+    /// Harmonic mean of precision and recall. Zero if both are.
+    pub f1: f64,
+}
+
+/// Accuracy metrics of a classification model.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClassificationMetrics {
+    pub accuracy: f64,
+    /// Metrics of each class, positioned by class index
+    pub per_class: Vec<ClassMetrics>,
+    /// `confusion[truth][predicted]` counts the observations of class `truth`
+    /// which were predicted as class `predicted`
+    pub confusion: Vec<Vec<usize>>,
+}
+
+/// Compute the accuracy metrics of a classifier's predictions against the
+/// expected classes.
+///
+/// # Panics
+///
+/// Panics if the slices differ in length, or if a class index is not below
+/// `num_classes`.
+pub fn classification_metrics(
+    predicted: &[u32],
+    truth: &[u32],
+    num_classes: usize,
+) -> ClassificationMetrics {
+    assert_eq!(
+        predicted.len(),
+        truth.len(),
+        "Prediction and truth counts differ"
+    );
+
+    let mut confusion = vec![vec![0; num_classes]; num_classes];
+    for (&p, &t) in predicted.iter().zip(truth) {
+        confusion[t as usize][p as usize] += 1;
+    }
+
+    let correct = (0..num_classes).map(|c| confusion[c][c]).sum::<usize>();
+    let per_class = (0..num_classes)
+        .map(|c| {
+            let true_positives = confusion[c][c] as f64;
+            let predicted_count = confusion.iter().map(|row| row[c]).sum::<usize>();
+            let truth_count = confusion[c].iter().sum::<usize>();
+
+            let precision = ratio(true_positives, predicted_count);
+            let recall = ratio(true_positives, truth_count);
+            let f1 = if precision + recall == 0.0 {
+                0.0
+            } else {
+                2.0 * precision * recall / (precision + recall)
+            };
+
+            ClassMetrics {
+                precision,
+                recall,
+                f1,
+            }
+        })
+        .collect();
+
+    ClassificationMetrics {
+        accuracy: ratio(correct as f64, predicted.len()),
+        per_class,
+        confusion,
+    }
+}
+
+fn ratio(numerator: f64, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator / denominator as f64
+    }
+}
+
+impl ClassificationMetrics {
+    /// Human-readable report, naming classes by `labels` (positioned by class
+    /// index) if given, or by index otherwise.
+    pub fn report<'a>(&'a self, labels: Option<&'a [String]>) -> impl fmt::Display + 'a {
+        ClassificationReport {
+            metrics: self,
+            labels,
+        }
+    }
+}
+
+struct ClassificationReport<'a> {
+    metrics: &'a ClassificationMetrics,
+    labels: Option<&'a [String]>,
+}
+
+impl fmt::Display for ClassificationReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = (0..self.metrics.per_class.len())
+            .map(|c| match self.labels.and_then(|l| l.get(c)) {
+                Some(label) => label.clone(),
+                None => c.to_string(),
+            })
+            .collect::<Vec<_>>();
+        let width = names.iter().map(|n| n.len()).max().unwrap_or(0).max(9);
+
+        writeln!(f, "Accuracy: {:.2}%", self.metrics.accuracy * 100.0)?;
+        writeln!(f, "{:<width$}  Precision   Recall       F1", "Class")?;
+        for (name, class) in names.iter().zip(&self.metrics.per_class) {
+            writeln!(
+                f,
+                "{name:<width$}  {:>8.2}%  {:>6.2}%  {:>6.2}%",
+                class.precision * 100.0,
+                class.recall * 100.0,
+                class.f1 * 100.0
+            )?;
+        }
+
+        writeln!(f, "Confusion matrix (rows: truth, columns: predicted):")?;
+        write!(f, "{:<width$}", "")?;
+        for name in &names {
+            write!(f, "  {name:>width$}")?;
+        }
+        writeln!(f)?;
+        for (name, row) in names.iter().zip(&self.metrics.confusion) {
+            write!(f, "{name:<width$}")?;
+            for count in row {
+                write!(f, "  {count:>width$}")?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Error metrics of a regression model.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RegressionMetrics {
+    /// Root mean squared error
+    pub rmse: f64,
+    /// Mean absolute error
+    pub mae: f64,
+    /// Largest absolute error
+    pub max_error: f64,
+    /// Coefficient of determination: 1 for perfect predictions, 0 for
+    /// predicting the mean of the truth set. If the truth set is constant,
+    /// 1 for perfect predictions and 0 otherwise.
+    pub r2: f64,
+}
+
+/// Compute the error metrics of a regressor's predictions against the
+/// expected values. All metrics are zero for empty inputs.
+///
+/// # Panics
+///
+/// Panics if the slices differ in length.
+pub fn regression_metrics(predicted: &[f32], truth: &[f32]) -> RegressionMetrics {
+    assert_eq!(
+        predicted.len(),
+        truth.len(),
+        "Prediction and truth counts differ"
+    );
+
+    let mut squared = 0.0;
+    let mut absolute = 0.0;
+    let mut max_error = 0.0f64;
+    for (&p, &t) in predicted.iter().zip(truth) {
+        let error = (p as f64 - t as f64).abs();
+        squared += error * error;
+        absolute += error;
+        max_error = max_error.max(error);
+    }
+
+    let mean = ratio(truth.iter().map(|&t| t as f64).sum(), truth.len());
+    let variance = truth
+        .iter()
+        .map(|&t| (t as f64 - mean).powi(2))
+        .sum::<f64>();
+    let r2 = if truth.is_empty() {
+        0.0
+    } else if variance == 0.0 {
+        if squared == 0.0 { 1.0 } else { 0.0 }
+    } else {
+        1.0 - squared / variance
+    };
+
+    RegressionMetrics {
+        rmse: ratio(squared, predicted.len()).sqrt(),
+        mae: ratio(absolute, predicted.len()),
+        max_error,
+        r2,
+    }
+}
+
+impl fmt::Display for RegressionMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "RMSE:      {}", self.rmse)?;
+        writeln!(f, "MAE:       {}", self.mae)?;
+        writeln!(f, "Max error: {}", self.max_error)?;
+        writeln!(f, "R²:        {}", self.r2)
+    }
+}
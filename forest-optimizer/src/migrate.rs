@@ -0,0 +1,209 @@
+//! Upgrades of forests serialized in older versions of the format, which
+//! devices no longer read, see [`FORMAT_VERSION`] for what each version
+//! adds.
+//!
+//! Older nodes are decoded and written again in the current encoding, so
+//! an upgraded forest predicts exactly like the original. Forests of the
+//! current version are returned unchanged, and newer ones are rejected.
+
+use std::collections::HashMap;
+
+use aligned_vec::AVec;
+use color_eyre::{
+    Result,
+    eyre::{Context, Report, eyre},
+};
+use embedded_rforest::{
+    Error,
+    forest::{
+        Branch, BranchLayout, Classification, CompactBranch, NodeLayout, OptimizedForest,
+        Regression,
+        deserialize::{
+            BUFFER_ALIGN, FORMAT_VERSION, ForestHeader, LEGACY_HEADER_LEN, peek_version,
+        },
+    },
+    ptr::{F32, NodeIndex, U16, U32},
+};
+use half::f16;
+use zerocopy::FromBytes;
+
+/// Header of a forest of [`FORMAT_VERSION`] 0 to 2
+struct LegacyHeader {
+    num_trees: u32,
+    num_features: u8,
+    /// 0 for regression
+    num_targets: u8,
+    layout: NodeLayout,
+    header_len: usize,
+    /// 0 if leaves are stored in the child pointers
+    num_leaves: usize,
+}
+
+impl LegacyHeader {
+    fn parse(buffer: &[u8], version: u8) -> Result<Self> {
+        let malformed = || eyre!("Malformed forest header of format version {version}");
+        let u16_at = |at: usize| {
+            let bytes = buffer.get(at..at + 2).ok_or_else(malformed)?;
+            Ok::<_, Report>(u16::from_le_bytes([bytes[0], bytes[1]]))
+        };
+        let u32_at = |at: usize| {
+            let bytes = buffer.get(at..at + 4).ok_or_else(malformed)?;
+            Ok::<_, Report>(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+
+        // Version 1 added the offset of the nodes, version 2 the leaf table
+        let header_len = match version {
+            0 => LEGACY_HEADER_LEN,
+            _ => usize::from(u16_at(8)?),
+        };
+        let num_leaves = match version {
+            0 | 1 => 0,
+            _ => u32_at(12)? as usize,
+        };
+        if header_len < LEGACY_HEADER_LEN || header_len > buffer.len() {
+            return Err(malformed());
+        }
+
+        let layout = NodeLayout::try_from(buffer[6]).map_err(|_| malformed())?;
+        if !matches!(
+            layout,
+            NodeLayout::Standard | NodeLayout::Narrow | NodeLayout::Compact
+        ) {
+            return Err(malformed());
+        }
+
+        Ok(Self {
+            num_trees: u32_at(0)?,
+            num_features: buffer[4],
+            num_targets: buffer[5],
+            layout,
+            header_len,
+            num_leaves,
+        })
+    }
+}
+
+/// Upgrade the serialized forest in `buffer` to [`FORMAT_VERSION`].
+///
+/// Fails if the forest is malformed, or was serialized in a newer version.
+pub fn migrate(buffer: &[u8]) -> Result<AVec<u8>> {
+    let version = peek_version(buffer).context("Not a serialized forest")?;
+    if version > FORMAT_VERSION {
+        return Err(Report::new(Error::UnsupportedVersion {
+            found: version,
+            max_supported: FORMAT_VERSION,
+        })
+        .wrap_err("Forest was serialized by a newer version of forest-optimizer"));
+    }
+    if version == FORMAT_VERSION {
+        ForestHeader::peek(buffer).context("Malformed forest header")?;
+        return Ok(AVec::from_slice(BUFFER_ALIGN, buffer));
+    }
+
+    let header = LegacyHeader::parse(buffer, version)?;
+    let leaves_len = header.num_leaves * size_of::<F32>();
+    let nodes = buffer[header.header_len..]
+        .len()
+        .checked_sub(leaves_len)
+        .map(|len| &buffer[header.header_len..header.header_len + len])
+        .filter(|nodes| nodes.len() % header.layout.branch_size() == 0)
+        .ok_or_else(|| eyre!("Malformed forest of format version {version}"))?;
+    let leaves = <[F32]>::ref_from_bytes(&buffer[buffer.len() - leaves_len..])
+        .map(|leaves| leaves.to_vec())
+        .map_err(|_| eyre!("Malformed leaf table"))?;
+
+    match header.layout {
+        NodeLayout::Standard => upgrade_branches::<U32>(&header, nodes, leaves),
+        NodeLayout::Narrow => upgrade_branches::<U16>(&header, nodes, leaves),
+        // Compact branches have not changed since the first releases
+        _ => {
+            let nodes = AVec::<u8>::from_slice(BUFFER_ALIGN, nodes);
+            let nodes = <[CompactBranch]>::ref_from_bytes(&nodes)
+                .map_err(|_| eyre!("Malformed compact branches"))?;
+            write(&header, nodes, &[])
+        }
+    }
+}
+
+/// Decode the [`Branch`]es of a forest older than version 3, whose split
+/// variable flags the leaf children in its top two bits, and tag their
+/// pointers instead. Regression leaves held by the pointers move to a leaf
+/// table.
+fn upgrade_branches<Ptr: NodeIndex>(
+    header: &LegacyHeader,
+    nodes: &[u8],
+    mut leaves: Vec<F32>,
+) -> Result<AVec<u8>> {
+    let width = size_of::<Ptr>();
+    let inline_leaves = header.num_targets == 0 && header.num_leaves == 0;
+    let mut leaf_indices = HashMap::new();
+    let mut leaf_index = |raw: u32| {
+        let value = match width {
+            4 => f32::from_bits(raw),
+            _ => f16::from_bits(raw as u16).to_f32(),
+        };
+        *leaf_indices.entry(value.to_bits()).or_insert_with(|| {
+            leaves.push(F32::new(value));
+            leaves.len() as u32 - 1
+        })
+    };
+
+    let mut branches = Vec::with_capacity(nodes.len() / (2 * width + 8));
+    for node in nodes.chunks_exact(2 * width + 8) {
+        let raw = |at: usize| {
+            Ptr::read_from_bytes(&node[at..at + width])
+                .expect("Pointers are as wide as their type")
+                .to_u32()
+        };
+        let word = |at: usize| u32::from_le_bytes(node[at..at + 4].try_into().unwrap());
+        let (left, right) = (raw(0), raw(width));
+        let split_at = f32::from_bits(word(2 * width));
+        let flags = word(2 * width + 4);
+
+        let mut pointer = |raw: u32, is_leaf: bool| {
+            let index = if is_leaf && inline_leaves {
+                leaf_index(raw)
+            } else {
+                raw
+            };
+            Ptr::tagged(index, is_leaf).ok_or_else(|| {
+                eyre!("Index {index} does not fit the pointers of the current format")
+            })
+        };
+        let left = pointer(left, flags >> 31 != 0)?;
+        let right = pointer(right, (flags >> 30) & 1 != 0)?;
+        branches.push(Branch::from_ptrs(
+            flags & (u32::MAX >> 2),
+            split_at,
+            left,
+            right,
+        ));
+    }
+
+    write(header, &branches, &leaves)
+}
+
+/// Serialize the upgraded nodes of the forest of `header`.
+fn write<B: BranchLayout>(header: &LegacyHeader, nodes: &[B], leaves: &[F32]) -> Result<AVec<u8>> {
+    let bytes = match header.num_targets {
+        0 => OptimizedForest::<Regression, B>::with_leaves(
+            header.num_trees,
+            nodes,
+            header.num_features,
+            leaves,
+        )
+        .map(|forest| forest.to_bytes()),
+        targets => Classification::new(targets)
+            .and_then(|problem| {
+                OptimizedForest::<Classification, B>::new(
+                    header.num_trees,
+                    nodes,
+                    header.num_features,
+                    problem,
+                )
+            })
+            .map(|forest| forest.to_bytes()),
+    };
+
+    bytes.context("Malformed forest")
+}
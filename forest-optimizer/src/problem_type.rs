@@ -1,11 +1,19 @@
 use std::{
     collections::HashMap,
-    fmt::{Debug, Display},
+    fmt::{self, Debug, Display},
+    hash::Hash,
+    ops,
 };
 
+use color_eyre::{Result, eyre::eyre};
+use embedded_rforest::forest::ProblemKind;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+
+/// Names of the features or targets of a problem, by name.
+#[deprecated(note = "use `FeatureMap` or `TargetMap`")]
 pub type Map = HashMap<String, u32>;
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PredictionType {
     #[serde(alias = "classification")]
     Classification,
@@ -13,64 +21,380 @@ pub enum PredictionType {
     Regression,
 }
 
-pub trait ProblemType: Default + Clone {
-    type Output: Debug + Display + Copy;
-    type OptimizedType: embedded_rforest::forest::ProblemType;
+impl From<ProblemKind> for PredictionType {
+    fn from(kind: ProblemKind) -> Self {
+        match kind {
+            ProblemKind::Classification => Self::Classification,
+            ProblemKind::Regression => Self::Regression,
+        }
+    }
+}
+
+impl Display for PredictionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Classification => write!(f, "CLASSIFICATION"),
+            Self::Regression => write!(f, "REGRESSION"),
+        }
+    }
+}
+
+/// Index types of a [`NameMap`]. Features and targets have their own, so
+/// that a class is never looked up among the features, or the other way
+/// around.
+pub trait NameIndex:
+    Copy + Eq + Ord + Hash + Debug + Display + From<u32> + Into<u32> + Send + Sync
+{
+    /// What the names are, in error messages
+    const KIND: &'static str;
+}
+
+macro_rules! name_index {
+    ($(#[$doc:meta])* $name:ident, $kind:literal) => {
+        $(#[$doc])*
+        #[derive(
+            Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+        )]
+        #[serde(transparent)]
+        pub struct $name(pub u32);
+
+        impl $name {
+            pub fn as_usize(self) -> usize {
+                self.0 as usize
+            }
+        }
+
+        impl NameIndex for $name {
+            const KIND: &'static str = $kind;
+        }
+
+        impl From<u32> for $name {
+            fn from(index: u32) -> Self {
+                Self(index)
+            }
+        }
+
+        impl From<$name> for u32 {
+            fn from(index: $name) -> Self {
+                index.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                Display::fmt(&self.0, f)
+            }
+        }
+    };
+}
+
+name_index!(
+    /// Index of a feature, which the branches of a forest split on
+    FeatureIdx,
+    "feature"
+);
+name_index!(
+    /// Index of a class, which the leaves of a classification forest predict
+    TargetIdx,
+    "target"
+);
+
+/// Names indexed densely from 0, in the order they were inserted unless
+/// reordered.
+///
+/// Serialized as a map from name to index, which is checked for holes when
+/// deserialized.
+#[derive(Clone, PartialEq, Eq)]
+pub struct NameMap<I> {
+    /// Names, positioned by index
+    names: Vec<String>,
+    indices: HashMap<String, I>,
+}
+
+/// Names of the features of a problem
+pub type FeatureMap = NameMap<FeatureIdx>;
+/// Names of the classes of a classification problem
+pub type TargetMap = NameMap<TargetIdx>;
+
+impl<I: NameIndex> NameMap<I> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A map indexing `names` by their position. Fails if a name is given
+    /// twice.
+    pub fn from_names<S: Into<String>>(names: impl IntoIterator<Item = S>) -> Result<Self> {
+        let mut map = Self::new();
+        for name in names {
+            let name = name.into();
+            if map.indices.contains_key(&name) {
+                return Err(eyre!("The {} '{name}' is given twice", I::KIND));
+            }
+            map.insert_next(&name);
+        }
+        Ok(map)
+    }
+
+    /// A map of the names and indices of `indices`, which must be exactly
+    /// `0..indices.len()`.
+    fn from_indices(indices: HashMap<String, u32>) -> Result<Self> {
+        let len = indices.len();
+        let mut names = vec![None; len];
+        for (name, &index) in &indices {
+            let slot = names.get_mut(index as usize).ok_or_else(|| {
+                eyre!(
+                    "The {kind} '{name}' has index {index}, past the {len} {kind}s",
+                    kind = I::KIND
+                )
+            })?;
+            if let Some(other) = slot.replace(name.clone()) {
+                return Err(eyre!(
+                    "The {}s '{other}' and '{name}' share index {index}",
+                    I::KIND
+                ));
+            }
+        }
+
+        // As many names as slots, none sharing one: every slot is filled
+        Ok(Self {
+            names: names.into_iter().flatten().collect(),
+            indices: indices
+                .into_iter()
+                .map(|(name, index)| (name, index.into()))
+                .collect(),
+        })
+    }
+
+    /// Index `name` after the names already in the map, unless it is there,
+    /// and return its index.
+    pub fn insert_next(&mut self, name: &str) -> I {
+        if let Some(&index) = self.indices.get(name) {
+            return index;
+        }
+        let index = I::from(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.indices.insert(name.to_string(), index);
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.indices.contains_key(name)
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<I> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn name_of(&self, index: I) -> Option<&str> {
+        let index: u32 = index.into();
+        self.names.get(index as usize).map(String::as_str)
+    }
+
+    /// Indices and names, in the order of the indices
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (I, &str)> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (I::from(index as u32), name.as_str()))
+    }
+
+    /// Names, positioned by index
+    pub fn names(&self) -> Vec<&str> {
+        self.names.iter().map(String::as_str).collect()
+    }
+
+    /// Replace the indices of the names by their positions in `order`, which
+    /// must list every name once.
+    pub fn reorder(&mut self, order: &[&str]) -> Result<()> {
+        let kind = I::KIND;
+        let mut missing = self
+            .names
+            .iter()
+            .map(String::as_str)
+            .filter(|name| !order.contains(name))
+            .collect::<Vec<_>>();
+        missing.sort_unstable();
+        let extra = order
+            .iter()
+            .filter(|name| !self.contains(name))
+            .copied()
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() || !extra.is_empty() {
+            return Err(eyre!(
+                "The {kind} order does not match the forest. Missing: [{}], not in the forest: [{}]",
+                missing.join(", "),
+                extra.join(", ")
+            ));
+        }
+        if let Some(name) = order
+            .iter()
+            .enumerate()
+            .find_map(|(i, name)| order[..i].contains(name).then_some(name))
+        {
+            return Err(eyre!("The {kind} order names '{name}' twice"));
+        }
+
+        *self = Self::from_names(order.iter().copied())?;
+        Ok(())
+    }
+
+    /// Replace the indices of the names by their positions in alphabetical
+    /// order.
+    pub fn sort(&mut self) {
+        let mut names = std::mem::take(&mut self.names);
+        names.sort_unstable();
+        *self = Self::from_names(names).expect("Names of a map are distinct");
+    }
+
+    /// A map of the names and indices of `map`, which must be exactly
+    /// `0..map.len()`.
+    #[deprecated(note = "build the map with `NameMap::from_names` or `NameMap::insert_next`")]
+    pub fn from_hash_map(map: HashMap<String, u32>) -> Result<Self> {
+        Self::from_indices(map)
+    }
+
+    /// The index of every name
+    #[deprecated(note = "look names up with `NameMap::index_of`, or iterate in order")]
+    pub fn to_hash_map(&self) -> HashMap<String, u32> {
+        self.iter()
+            .map(|(index, name)| (name.to_string(), index.into()))
+            .collect()
+    }
+}
+
+impl<I> Default for NameMap<I> {
+    fn default() -> Self {
+        Self {
+            names: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+}
+
+/// The index of `name`, panicking if the map has no such name
+impl<I: NameIndex> ops::Index<&str> for NameMap<I> {
+    type Output = I;
+
+    fn index(&self, name: &str) -> &I {
+        &self.indices[name]
+    }
+}
+
+impl<I: NameIndex> Debug for NameMap<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(index, name)| (name, index)))
+            .finish()
+    }
+}
+
+impl<I: NameIndex> Serialize for NameMap<I> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter().map(|(index, name)| (name, index.into())))
+    }
+}
+
+impl<'de, I: NameIndex> Deserialize<'de> for NameMap<I> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let indices = HashMap::<String, u32>::deserialize(deserializer)?;
+        Self::from_indices(indices).map_err(serde::de::Error::custom)
+    }
+}
+
+pub trait ProblemType:
+    Default + Clone + Debug + Send + Sync + Serialize + DeserializeOwned
+{
+    type Output: Debug + Display + Copy + PartialEq + Send + Sync + Serialize + DeserializeOwned;
+    /// What the leaves of a forest hold: the output itself, or the index of
+    /// a class, which converts to the class output
+    type Prediction: Debug
+        + Display
+        + Copy
+        + PartialEq
+        + Send
+        + Sync
+        + Serialize
+        + DeserializeOwned
+        + From<Self::Output>
+        + Into<Self::Output>;
+    /// The problem type of the optimized forest, which predicts the same
+    /// outputs
+    type OptimizedType: embedded_rforest::forest::ProblemType<Output = Self::Output>;
 
     const TYPE: PredictionType;
 
-    fn features(&self) -> &Map;
+    fn features(&self) -> &FeatureMap;
 
-    fn features_mut(&mut self) -> &mut Map;
+    fn features_mut(&mut self) -> &mut FeatureMap;
+
+    /// Whether a leaf may predict `output`, e.g. a class this problem has
+    fn is_valid_output(&self, _output: Self::Output) -> bool {
+        true
+    }
 }
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Classification {
-    targets: Map,
-    features: Map,
+    targets: TargetMap,
+    features: FeatureMap,
 }
 
 impl Classification {
-    pub fn targets(&self) -> &Map {
+    pub fn targets(&self) -> &TargetMap {
         &self.targets
     }
 
-    pub(crate) fn targets_mut(&mut self) -> &mut Map {
+    pub(crate) fn targets_mut(&mut self) -> &mut TargetMap {
         &mut self.targets
     }
 }
 
 impl ProblemType for Classification {
     type Output = u32;
+    type Prediction = TargetIdx;
     type OptimizedType = embedded_rforest::forest::Classification;
 
     const TYPE: PredictionType = PredictionType::Classification;
 
-    fn features(&self) -> &Map {
+    fn features(&self) -> &FeatureMap {
         &self.features
     }
 
-    fn features_mut(&mut self) -> &mut Map {
+    fn features_mut(&mut self) -> &mut FeatureMap {
         &mut self.features
     }
+
+    fn is_valid_output(&self, class: u32) -> bool {
+        (class as usize) < self.targets.len()
+    }
 }
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Regression {
-    features: Map,
+    features: FeatureMap,
 }
 
 impl ProblemType for Regression {
     type Output = f32;
+    type Prediction = f32;
     type OptimizedType = embedded_rforest::forest::Regression;
 
     const TYPE: PredictionType = PredictionType::Regression;
 
-    fn features(&self) -> &Map {
+    fn features(&self) -> &FeatureMap {
         &self.features
     }
 
-    fn features_mut(&mut self) -> &mut Map {
+    fn features_mut(&mut self) -> &mut FeatureMap {
         &mut self.features
     }
 }
@@ -0,0 +1,673 @@
+//! Passes shrinking a forest by removing nodes or whole trees.
+//!
+//! [`prune`] runs the passes in a fixed order, each of them optional:
+//! [`truncate_depth`], then [`collapse_redundant`], then
+//! [`keep_first_trees`], then [`prune_by_validation`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use embedded_rforest::forest::SplitRule;
+use tracing::debug;
+
+use crate::{
+    analyze::estimate_serialized_size,
+    compare::PredictLike,
+    dataset::{LabeledDataset, read_labeled},
+    emit::OutputFormat,
+    forest::{BranchNode, Forest, LeafNode, Node, cache::read_definition},
+    metadata::ForestMetadata,
+    metrics::regression_metrics,
+    problem_type::{Classification, PredictionType, ProblemType, Regression},
+    serialized_forest::{
+        ReadOptions, SerializedClassificationNode, SerializedForest, SerializedNode,
+        SerializedRegressionNode, resolve_problem_type,
+    },
+    write_forest::{WriteForest, write_artifacts},
+};
+
+/// Problem types whose forests can be scored, and pruned, against a labeled
+/// dataset.
+pub trait Prune: WriteForest {
+    /// Name of the score, as printed in reports
+    const SCORE: &'static str;
+
+    /// Parse the labels of a dataset into outputs of `forest`.
+    fn parse_labels(forest: &Forest<Self>, labels: &[String]) -> Result<Vec<Self::Output>>;
+
+    /// Combine the predictions of several trees into the forest's prediction.
+    fn aggregate(predictions: impl Iterator<Item = Self::Output>) -> Self::Output;
+
+    /// Score predictions against the expected outputs.
+    fn score(predicted: &[Self::Output], truth: &[Self::Output]) -> f64;
+
+    /// Whether `score` is within `max_loss` of `baseline`.
+    fn is_acceptable(baseline: f64, score: f64, max_loss: f64) -> bool;
+}
+
+impl Prune for Classification {
+    const SCORE: &'static str = "Accuracy (%)";
+
+    fn parse_labels(forest: &Forest<Self>, labels: &[String]) -> Result<Vec<u32>> {
+        labels
+            .iter()
+            .map(|label| {
+                forest
+                    .targets()
+                    .index_of(label)
+                    .map(u32::from)
+                    .ok_or_else(|| eyre!("Dataset label '{label}' is not a target of the forest"))
+            })
+            .collect()
+    }
+
+    /// Majority vote. Ties go to the lowest class index.
+    fn aggregate(predictions: impl Iterator<Item = u32>) -> u32 {
+        let mut votes = BTreeMap::new();
+        for class in predictions {
+            *votes.entry(class).or_insert(0) += 1;
+        }
+
+        votes
+            .into_iter()
+            .max_by(|(a, a_votes), (b, b_votes)| a_votes.cmp(b_votes).then(b.cmp(a)))
+            .map(|(class, _)| class)
+            .unwrap_or_default()
+    }
+
+    fn score(predicted: &[u32], truth: &[u32]) -> f64 {
+        let correct = predicted.iter().zip(truth).filter(|(p, t)| p == t).count();
+        correct as f64 / truth.len().max(1) as f64 * 100.0
+    }
+
+    /// `max_loss` is in percentage points of accuracy
+    fn is_acceptable(baseline: f64, score: f64, max_loss: f64) -> bool {
+        score >= baseline - max_loss
+    }
+}
+
+impl Prune for Regression {
+    const SCORE: &'static str = "RMSE";
+
+    fn parse_labels(_: &Forest<Self>, labels: &[String]) -> Result<Vec<f32>> {
+        labels
+            .iter()
+            .map(|label| label.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| eyre!("Dataset label is not numeric: {e}"))
+    }
+
+    fn aggregate(predictions: impl Iterator<Item = f32>) -> f32 {
+        let (sum, count) = predictions.fold((0.0, 0), |(sum, count), p| (sum + p, count + 1));
+        sum / count.max(1) as f32
+    }
+
+    fn score(predicted: &[f32], truth: &[f32]) -> f64 {
+        regression_metrics(predicted, truth).rmse
+    }
+
+    /// `max_loss` is in percent of the baseline RMSE
+    fn is_acceptable(baseline: f64, score: f64, max_loss: f64) -> bool {
+        score <= baseline * (1.0 + max_loss / 100.0)
+    }
+}
+
+/// A labeled dataset, mapped onto the outputs of a forest.
+pub struct Validation<P: Prune> {
+    rows: Vec<Vec<f32>>,
+    truth: Vec<P::Output>,
+}
+
+impl<P: Prune> Validation<P> {
+    pub fn new(forest: &Forest<P>, dataset: LabeledDataset) -> Result<Self> {
+        Ok(Self {
+            truth: P::parse_labels(forest, &dataset.labels)?,
+            rows: dataset.rows,
+        })
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Features of every row of this dataset
+    pub fn rows(&self) -> &[Vec<f32>] {
+        &self.rows
+    }
+
+    /// Predictions of a forest on every row of this dataset
+    pub fn predict(&self, forest: &Forest<P>) -> Vec<P::Output> {
+        self.rows
+            .iter()
+            .map(|row| forest.predict_like(row))
+            .collect()
+    }
+
+    /// Score of a forest on this dataset
+    pub fn score(&self, forest: &Forest<P>) -> f64 {
+        self.score_predictions(&self.predict(forest))
+    }
+
+    /// Score of predictions made on this dataset
+    pub fn score_predictions(&self, predicted: &[P::Output]) -> f64 {
+        P::score(predicted, &self.truth)
+    }
+}
+
+/// Replace every branch whose two subtrees make the same prediction with a
+/// leaf holding that prediction. Predictions are unchanged.
+pub fn collapse_redundant<P: WriteForest>(forest: &Forest<P>) -> Forest<P> {
+    let trees = forest
+        .trees()
+        .map(|tree| {
+            let nodes = tree.to_nodes();
+            let mut collapsed = Vec::with_capacity(nodes.len());
+            collapse(&nodes, 0, &mut collapsed);
+            collapsed
+        })
+        .collect();
+
+    Forest::from_trees(trees, forest.problem().clone()).with_split_rule(forest.split_rule())
+}
+
+/// Append the collapsed subtree rooted at `nodes[idx]` to `out`, with child
+/// pointers relative to the start of `out`.
+fn collapse<P: WriteForest>(nodes: &[Node<P>], idx: usize, out: &mut Vec<Node<P>>) {
+    let branch = match &nodes[idx] {
+        Node::Leaf(_) => return out.push(nodes[idx].clone()),
+        Node::Branch(b) => b,
+    };
+
+    let mut left = Vec::new();
+    collapse(nodes, branch.left as usize, &mut left);
+    let mut right = Vec::new();
+    collapse(nodes, branch.right as usize, &mut right);
+
+    if let (Node::Leaf(l), Node::Leaf(r)) = (&left[0], &right[0])
+        && l.prediction == r.prediction
+    {
+        return out.push(left.swap_remove(0));
+    }
+
+    let root = out.len();
+    out.push(nodes[idx].clone());
+    let left_start = append_shifted(out, left);
+    let right_start = append_shifted(out, right);
+    out[root] = Node::Branch(BranchNode {
+        left: left_start,
+        right: right_start,
+        ..branch.clone()
+    });
+}
+
+/// Append a subtree to `out`, returning the index of its root
+fn append_shifted<P: WriteForest>(out: &mut Vec<Node<P>>, subtree: Vec<Node<P>>) -> u32 {
+    let start = out.len() as u32;
+    out.extend(subtree.into_iter().map(|node| match node {
+        Node::Branch(b) => Node::Branch(BranchNode {
+            left: b.left + start,
+            right: b.right + start,
+            ..b
+        }),
+        leaf => leaf,
+    }));
+    start
+}
+
+/// Replace every branch `max_depth` branches below the root of its tree with
+/// a leaf, so that no tree is deeper than `max_depth`.
+///
+/// The leaf predicts what the rows of `validation` reaching the branch
+/// predict as a whole (majority class, or mean value, see
+/// [`Prune::aggregate`]). Without a dataset, or if no row reaches the
+/// branch, it predicts what the leaves below the branch do.
+pub fn truncate_depth<P: Prune>(
+    forest: &Forest<P>,
+    max_depth: usize,
+    validation: Option<&Validation<P>>,
+) -> Forest<P> {
+    let trees = forest
+        .trees()
+        .map(|tree| {
+            let nodes = tree.to_nodes();
+            // Shallow trees are kept in their original order
+            if tree.depth() <= max_depth {
+                return nodes;
+            }
+            let rows = validation.map_or_else(Vec::new, |v| (0..v.rows.len()).collect());
+            let mut truncated = Vec::with_capacity(nodes.len());
+            truncate(
+                &nodes,
+                0,
+                max_depth,
+                validation,
+                forest.split_rule(),
+                rows,
+                &mut truncated,
+            );
+            truncated
+        })
+        .collect();
+
+    Forest::from_trees(trees, forest.problem().clone()).with_split_rule(forest.split_rule())
+}
+
+/// Append the subtree rooted at `nodes[idx]`, truncated `depth_left`
+/// branches down, to `out`, with child pointers relative to the start of
+/// `out`. `rows` are the indices of the rows of `validation` reaching it,
+/// following `split_rule`.
+fn truncate<P: Prune>(
+    nodes: &[Node<P>],
+    idx: usize,
+    depth_left: usize,
+    validation: Option<&Validation<P>>,
+    split_rule: SplitRule,
+    rows: Vec<usize>,
+    out: &mut Vec<Node<P>>,
+) {
+    let branch = match &nodes[idx] {
+        Node::Leaf(_) => return out.push(nodes[idx].clone()),
+        Node::Branch(b) => b,
+    };
+
+    if depth_left == 0 {
+        let prediction = match validation {
+            Some(validation) if !rows.is_empty() => {
+                P::aggregate(rows.iter().map(|&row| validation.truth[row]))
+            }
+            _ => P::aggregate(subtree_leaves(nodes, idx)),
+        };
+        return out.push(Node::Leaf(LeafNode::new(prediction.into())));
+    }
+
+    let (left_rows, right_rows) = match validation {
+        Some(validation) => rows.into_iter().partition(|&row| {
+            let value = validation.rows[row][branch.split_with.as_usize()];
+            split_rule.goes_left(value, branch.split_at)
+        }),
+        None => (Vec::new(), Vec::new()),
+    };
+    let mut left = Vec::new();
+    truncate(
+        nodes,
+        branch.left as usize,
+        depth_left - 1,
+        validation,
+        split_rule,
+        left_rows,
+        &mut left,
+    );
+    let mut right = Vec::new();
+    truncate(
+        nodes,
+        branch.right as usize,
+        depth_left - 1,
+        validation,
+        split_rule,
+        right_rows,
+        &mut right,
+    );
+
+    let root = out.len();
+    out.push(nodes[idx].clone());
+    let left_start = append_shifted(out, left);
+    let right_start = append_shifted(out, right);
+    out[root] = Node::Branch(BranchNode {
+        left: left_start,
+        right: right_start,
+        ..branch.clone()
+    });
+}
+
+/// Predictions of the leaves of the subtree rooted at `nodes[idx]`.
+fn subtree_leaves<P: ProblemType>(
+    nodes: &[Node<P>],
+    idx: usize,
+) -> impl Iterator<Item = P::Output> {
+    let mut stack = vec![idx];
+    std::iter::from_fn(move || {
+        loop {
+            match &nodes[stack.pop()?] {
+                Node::Branch(b) => stack.extend([b.right as usize, b.left as usize]),
+                Node::Leaf(l) => return Some(l.prediction.into()),
+            }
+        }
+    })
+}
+
+/// Keep only the first `max_trees` trees of a forest.
+pub fn keep_first_trees<P: WriteForest>(forest: &Forest<P>, max_trees: usize) -> Forest<P> {
+    let trees = forest
+        .trees()
+        .take(max_trees)
+        .map(|tree| tree.to_nodes())
+        .collect();
+
+    Forest::from_trees(trees, forest.problem().clone()).with_split_rule(forest.split_rule())
+}
+
+/// Remove trees as long as the score of the forest on `validation` stays
+/// within `max_loss` of its original score.
+///
+/// Trees are tried for removal from the last one to the first, and at least
+/// one tree is always kept.
+pub fn prune_by_validation<P: Prune>(
+    forest: &Forest<P>,
+    validation: &Validation<P>,
+    max_loss: f64,
+) -> Forest<P> {
+    // Predictions of every tree on every row, so each candidate forest is
+    // only a matter of aggregating them
+    let predictions = forest
+        .trees()
+        .map(|tree| {
+            validation
+                .rows
+                .iter()
+                .map(|row| tree.predict(row))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let score = |kept: &[bool]| {
+        let predicted = (0..validation.rows.len())
+            .map(|row| {
+                P::aggregate(
+                    predictions
+                        .iter()
+                        .zip(kept)
+                        .filter(|(_, kept)| **kept)
+                        .map(|(tree, _)| tree[row]),
+                )
+            })
+            .collect::<Vec<_>>();
+        P::score(&predicted, &validation.truth)
+    };
+
+    let mut kept = vec![true; predictions.len()];
+    let baseline = score(&kept);
+    for tree in (1..kept.len()).rev() {
+        kept[tree] = false;
+        if !P::is_acceptable(baseline, score(&kept), max_loss) {
+            kept[tree] = true;
+        }
+    }
+
+    let trees = forest
+        .trees()
+        .zip(&kept)
+        .filter(|(_, kept)| **kept)
+        .map(|(tree, _)| tree.to_nodes())
+        .collect();
+
+    Forest::from_trees(trees, forest.problem().clone()).with_split_rule(forest.split_rule())
+}
+
+/// Pruning passes to run, see [`prune`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PruneOptions {
+    /// Largest depth of the trees, see [`truncate_depth`]. Leaves replacing
+    /// deeper branches are computed from the validation set, if any.
+    pub max_depth: Option<usize>,
+    pub collapse_redundant: bool,
+    pub max_trees: Option<usize>,
+    /// Largest loss of validation score allowed when removing trees. Requires
+    /// a validation set.
+    pub max_loss: Option<f64>,
+}
+
+/// Run the requested pruning passes, in order.
+pub fn prune<P: Prune>(
+    forest: Forest<P>,
+    options: &PruneOptions,
+    validation: Option<&Validation<P>>,
+) -> Result<Forest<P>> {
+    let mut forest = forest;
+
+    if let Some(max_depth) = options.max_depth {
+        forest = truncate_depth(&forest, max_depth, validation);
+        debug!(nodes = forest.nodes().len(), "Truncated deep trees");
+    }
+
+    if options.collapse_redundant {
+        forest = collapse_redundant(&forest);
+        debug!(nodes = forest.nodes().len(), "Collapsed redundant branches");
+    }
+
+    if let Some(max_trees) = options.max_trees {
+        forest = keep_first_trees(&forest, max_trees);
+        debug!(trees = forest.num_trees(), "Kept the first trees");
+    }
+
+    if let Some(max_loss) = options.max_loss {
+        let validation =
+            validation.ok_or_else(|| eyre!("Pruning by validation requires a dataset"))?;
+        forest = prune_by_validation(&forest, validation, max_loss);
+        debug!(trees = forest.num_trees(), "Pruned trees by validation");
+    }
+
+    Ok(forest)
+}
+
+/// Size and score of a forest.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct ForestSummary {
+    pub trees: usize,
+    pub nodes: usize,
+    /// Size of the serialized optimized forest, in bytes
+    pub serialized_size: usize,
+    /// Score on the validation set, if any
+    pub score: Option<f64>,
+}
+
+impl ForestSummary {
+    pub fn new<P: Prune>(forest: &Forest<P>, validation: Option<&Validation<P>>) -> Self {
+        Self {
+            trees: forest.num_trees(),
+            nodes: forest.nodes().len(),
+            serialized_size: estimate_serialized_size(forest).serialized_size,
+            score: validation.map(|v| v.score(forest)),
+        }
+    }
+}
+
+/// A forest before and after pruning.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct PruneReport {
+    /// Name of the score
+    pub score: &'static str,
+    pub before: ForestSummary,
+    pub after: ForestSummary,
+}
+
+impl fmt::Display for PruneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let score = |s: &ForestSummary| s.score.map_or("-".to_string(), |s| format!("{s:.3}"));
+
+        writeln!(f, "{:<13}  {:>10}  {:>10}", "", "Before", "After")?;
+        writeln!(
+            f,
+            "{:<13}  {:>10}  {:>10}",
+            "Trees", self.before.trees, self.after.trees
+        )?;
+        writeln!(
+            f,
+            "{:<13}  {:>10}  {:>10}",
+            "Nodes", self.before.nodes, self.after.nodes
+        )?;
+        writeln!(
+            f,
+            "{:<13}  {:>10}  {:>10}",
+            "Bytes", self.before.serialized_size, self.after.serialized_size
+        )?;
+        writeln!(
+            f,
+            "{:<13}  {:>10}  {:>10}",
+            self.score,
+            score(&self.before),
+            score(&self.after)
+        )
+    }
+}
+
+/// Prune a forest definition file (CSV) whose problem type is detected from
+/// its header, or given by `problem_type`, and write the pruned forest to
+/// `output` along with its metadata.
+///
+/// With `cache`, the input is read through its cache, see
+/// [`read_cached`](crate::forest::cache::read_cached).
+/// `validation` is a labeled dataset (CSV) and the name of its label column,
+/// used to score the forest and, with [`PruneOptions::max_loss`], to prune
+/// it.
+pub fn prune_file(
+    input: impl AsRef<Path>,
+    problem_type: Option<PredictionType>,
+    cache: bool,
+    options: &PruneOptions,
+    validation: Option<(&Path, &str)>,
+    output: impl AsRef<Path>,
+) -> Result<PruneReport> {
+    fn prune_forest<N>(
+        input: &Path,
+        cache: bool,
+        options: &PruneOptions,
+        validation: Option<(&Path, &str)>,
+        output: &Path,
+    ) -> Result<PruneReport>
+    where
+        N: SerializedNode,
+        N::ProblemType: Prune,
+    {
+        let forest = read_definition::<N>(input, cache)?;
+
+        let validation = validation
+            .map(|(data, label_column)| {
+                let features = forest.features_by_index();
+                let dataset = read_labeled(data, &features, label_column)?;
+                Validation::new(&forest, dataset)
+            })
+            .transpose()?;
+
+        let before = ForestSummary::new(&forest, validation.as_ref());
+        let pruned = prune(forest, options, validation.as_ref())?;
+        let after = ForestSummary::new(&pruned, validation.as_ref());
+
+        write_artifacts(
+            &pruned,
+            &[(OutputFormat::Rforest, output.to_path_buf())],
+            ForestMetadata::sidecar_path(output),
+        )?;
+
+        Ok(PruneReport {
+            score: N::ProblemType::SCORE,
+            before,
+            after,
+        })
+    }
+
+    let (input, output) = (input.as_ref(), output.as_ref());
+    match resolve_problem_type(input, problem_type)? {
+        PredictionType::Classification => {
+            prune_forest::<SerializedClassificationNode>(input, cache, options, validation, output)
+        }
+        PredictionType::Regression => {
+            prune_forest::<SerializedRegressionNode>(input, cache, options, validation, output)
+        }
+    }
+}
+
+/// Writer of a forest in a layout of its own, e.g.
+/// [`write_compact`](crate::compact::write_compact), see [`layout_file`].
+pub(crate) trait LayoutWriter<P: Prune> {
+    type Report;
+
+    /// Write `forest` to `artifacts` and its metadata to `metadata_path`,
+    /// giving up if its score on `validation` drops by more than
+    /// `max_drop`.
+    fn write(
+        forest: &Forest<P>,
+        validation: Option<&Validation<P>>,
+        max_drop: Option<f64>,
+        artifacts: &[(OutputFormat, PathBuf)],
+        metadata_path: &Path,
+    ) -> Result<Self::Report>;
+}
+
+/// Write a forest definition file (CSV) whose problem type is detected from
+/// its header, or given by `problem_type`, with the [`LayoutWriter`] `W`.
+///
+/// `validation` is a labeled dataset (CSV) and the name of its label column,
+/// which `max_drop` requires.
+pub(crate) fn layout_file<W, R>(
+    input: &Path,
+    problem_type: Option<PredictionType>,
+    options: &ReadOptions,
+    validation: Option<(&Path, &str)>,
+    max_drop: Option<f64>,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: &Path,
+) -> Result<R>
+where
+    W: LayoutWriter<Classification, Report = R> + LayoutWriter<Regression, Report = R>,
+{
+    fn layout<N, W, R>(
+        input: &Path,
+        options: &ReadOptions,
+        validation: Option<(&Path, &str)>,
+        max_drop: Option<f64>,
+        artifacts: &[(OutputFormat, PathBuf)],
+        metadata_path: &Path,
+    ) -> Result<R>
+    where
+        N: SerializedNode,
+        N::ProblemType: Prune,
+        W: LayoutWriter<N::ProblemType, Report = R>,
+    {
+        let serialized = SerializedForest::<N>::read_with(input, options)
+            .context("Could not read forest definition file (CSV).")?;
+        let forest = Forest::from_serialized(options.prepare(serialized)?)?;
+
+        let validation = validation
+            .map(|(data, label_column)| {
+                let features = forest.features_by_index();
+                let dataset = read_labeled(data, &features, label_column)?;
+                Validation::new(&forest, dataset)
+            })
+            .transpose()?;
+        if max_drop.is_some() && validation.is_none() {
+            return Err(eyre!("A maximum drop requires a validation dataset"));
+        }
+
+        W::write(
+            &forest,
+            validation.as_ref(),
+            max_drop,
+            artifacts,
+            metadata_path,
+        )
+    }
+
+    match resolve_problem_type(input, problem_type)? {
+        PredictionType::Classification => layout::<SerializedClassificationNode, W, R>(
+            input,
+            options,
+            validation,
+            max_drop,
+            artifacts,
+            metadata_path,
+        ),
+        PredictionType::Regression => layout::<SerializedRegressionNode, W, R>(
+            input,
+            options,
+            validation,
+            max_drop,
+            artifacts,
+            metadata_path,
+        ),
+    }
+}
@@ -0,0 +1,375 @@
+//! Passes rounding the thresholds and leaf values of a forest to what a
+//! narrower type can represent.
+//!
+//! The forest keeps its layout: values are still stored as 32-bit numbers,
+//! but hold exactly what the narrower type would, so the effect of a
+//! quantization on predictions can be measured before committing to it.
+
+use std::fmt;
+use std::path::Path;
+
+use color_eyre::{Result, eyre::eyre};
+use half::{bf16, f16};
+use tracing::debug;
+
+use crate::{
+    dataset::read_labeled,
+    emit::OutputFormat,
+    forest::{Forest, Node, cache::read_definition},
+    metadata::ForestMetadata,
+    problem_type::{Classification, PredictionType, Regression},
+    prune::{Prune, Validation},
+    serialized_forest::{
+        SerializedClassificationNode, SerializedNode, SerializedRegressionNode,
+        resolve_problem_type,
+    },
+    write_forest::write_artifacts,
+};
+
+/// Type thresholds are rounded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ThresholdType {
+    /// IEEE 754 half precision
+    F16,
+    /// bfloat16: the range of an f32 with 8 bits of mantissa
+    Bf16,
+}
+
+impl ThresholdType {
+    pub(crate) fn round(self, threshold: f32) -> f32 {
+        match self {
+            ThresholdType::F16 => f16::from_f32(threshold).to_f32(),
+            ThresholdType::Bf16 => bf16::from_f32(threshold).to_f32(),
+        }
+    }
+}
+
+/// Unsigned integer type leaves are stored as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LeafType {
+    U8,
+    U16,
+}
+
+impl LeafType {
+    /// Largest value of the type
+    fn max(self) -> u32 {
+        match self {
+            LeafType::U8 => u8::MAX.into(),
+            LeafType::U16 => u16::MAX.into(),
+        }
+    }
+
+    /// `value` rounded to the nearest of the levels of the type, spread
+    /// evenly from `min` to `max`.
+    pub(crate) fn round_affine(self, value: f32, min: f32, max: f32) -> f32 {
+        if min >= max {
+            return value;
+        }
+        let step = (max - min) / self.max() as f32;
+        let level = ((value - min) / step).round();
+        min + level * step
+    }
+}
+
+/// Error introduced by rounding the thresholds of a forest.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct RoundingStats {
+    /// Largest absolute difference between a threshold and its rounded value
+    pub max: f64,
+    /// Mean absolute difference between a threshold and its rounded value
+    pub mean: f64,
+}
+
+/// Round every threshold of a forest to `threshold_type`.
+///
+/// Fails if a threshold is out of the range of the type.
+pub fn quantize_thresholds<P: Prune>(
+    forest: &mut Forest<P>,
+    threshold_type: ThresholdType,
+) -> Result<RoundingStats> {
+    let (mut max, mut sum, mut count) = (0.0f64, 0.0, 0);
+
+    for node in forest.nodes_mut() {
+        let Node::Branch(branch) = node else {
+            continue;
+        };
+
+        let rounded = threshold_type.round(branch.split_at);
+        if rounded.is_infinite() && branch.split_at.is_finite() {
+            return Err(eyre!(
+                "Threshold {} is out of the range of {threshold_type:?}",
+                branch.split_at
+            ));
+        }
+
+        let error = (f64::from(branch.split_at) - f64::from(rounded)).abs();
+        max = max.max(error);
+        sum += error;
+        count += 1;
+        branch.split_at = rounded;
+    }
+
+    Ok(RoundingStats {
+        max,
+        mean: if count == 0 { 0.0 } else { sum / count as f64 },
+    })
+}
+
+/// Problem types whose leaves can be stored as small unsigned integers.
+pub trait QuantizeLeaves: Prune {
+    /// Round every leaf of a forest to what `leaf_type` can hold. Returns the
+    /// root mean squared difference between the leaves and their rounded
+    /// values, if leaves are approximated at all.
+    fn quantize_leaves(forest: &mut Forest<Self>, leaf_type: LeafType) -> Result<Option<f64>>;
+}
+
+impl QuantizeLeaves for Classification {
+    /// Leaves are class indices, and are kept as long as every class fits.
+    fn quantize_leaves(forest: &mut Forest<Self>, leaf_type: LeafType) -> Result<Option<f64>> {
+        let num_targets = forest.num_targets();
+        if num_targets > leaf_type.max() as usize + 1 {
+            return Err(eyre!(
+                "{num_targets} classes do not fit in {leaf_type:?} leaves"
+            ));
+        }
+
+        Ok(None)
+    }
+}
+
+impl QuantizeLeaves for Regression {
+    /// Leaf values are mapped linearly onto the levels of the type, between
+    /// the smallest and the largest leaf of the forest.
+    fn quantize_leaves(forest: &mut Forest<Self>, leaf_type: LeafType) -> Result<Option<f64>> {
+        let (min, max) = forest
+            .nodes()
+            .iter()
+            .filter_map(|n| n.take_leaf())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), leaf| {
+                (min.min(leaf.prediction), max.max(leaf.prediction))
+            });
+        if min >= max {
+            // No leaves, or all of them are equal
+            return Ok(Some(0.0));
+        }
+
+        let (mut squared, mut count) = (0.0f64, 0);
+        for node in forest.nodes_mut() {
+            let Node::Leaf(leaf) = node else {
+                continue;
+            };
+
+            let rounded = leaf_type.round_affine(leaf.prediction, min, max);
+            squared += (f64::from(leaf.prediction) - f64::from(rounded)).powi(2);
+            count += 1;
+            leaf.prediction = rounded;
+        }
+
+        Ok(Some((squared / count as f64).sqrt()))
+    }
+}
+
+/// Quantizations to apply, see [`quantize`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuantizeOptions {
+    pub thresholds: Option<ThresholdType>,
+    pub leaves: Option<LeafType>,
+}
+
+/// Error introduced by quantizing a forest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct QuantizationError {
+    /// Rounding of the thresholds, if they were quantized
+    pub thresholds: Option<RoundingStats>,
+    /// Root mean squared difference between the leaves and their quantized
+    /// values, if leaves were approximated
+    pub leaf_rmse: Option<f64>,
+}
+
+/// Apply the requested quantizations to a forest.
+pub fn quantize<P: QuantizeLeaves>(
+    forest: &mut Forest<P>,
+    options: &QuantizeOptions,
+) -> Result<QuantizationError> {
+    let mut error = QuantizationError::default();
+
+    if let Some(threshold_type) = options.thresholds {
+        let stats = quantize_thresholds(forest, threshold_type)?;
+        debug!(max = stats.max, mean = stats.mean, "Rounded thresholds");
+        error.thresholds = Some(stats);
+    }
+
+    if let Some(leaf_type) = options.leaves {
+        error.leaf_rmse = P::quantize_leaves(forest, leaf_type)?;
+        debug!(rmse = error.leaf_rmse, "Rounded leaves");
+    }
+
+    Ok(error)
+}
+
+/// Effect of quantizing a forest, measured on a validation set.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct ValidationChange {
+    /// Score of the original forest
+    pub before: f64,
+    /// Score of the quantized forest
+    pub after: f64,
+    /// Number of rows whose prediction changed
+    pub flips: usize,
+    /// Number of rows of the validation set
+    pub rows: usize,
+}
+
+impl ValidationChange {
+    /// Compare the predictions of a forest on `validation` before and after
+    /// a change.
+    pub fn new<P: Prune>(
+        validation: &Validation<P>,
+        original: &[P::Output],
+        changed: &[P::Output],
+    ) -> Self {
+        Self {
+            before: validation.score_predictions(original),
+            after: validation.score_predictions(changed),
+            flips: original.iter().zip(changed).filter(|(a, b)| a != b).count(),
+            rows: original.len(),
+        }
+    }
+}
+
+/// Outcome of quantizing a forest.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct QuantizeReport {
+    /// Name of the validation score
+    pub score: &'static str,
+    pub error: QuantizationError,
+    /// Effect on the validation set, if any
+    pub validation: Option<ValidationChange>,
+    /// Whether the score stayed within the allowed drop. Always true without
+    /// a maximum drop.
+    pub accepted: bool,
+    /// Whether the quantized forest was written
+    pub written: bool,
+}
+
+impl fmt::Display for QuantizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(thresholds) = self.error.thresholds {
+            writeln!(
+                f,
+                "Threshold rounding: max {:.4e}, mean {:.4e}",
+                thresholds.max, thresholds.mean
+            )?;
+        }
+        if let Some(leaf_rmse) = self.error.leaf_rmse {
+            writeln!(f, "Leaf RMSE delta:    {leaf_rmse:.4e}")?;
+        }
+        if let Some(validation) = self.validation {
+            writeln!(
+                f,
+                "{}: {:.3} -> {:.3}",
+                self.score, validation.before, validation.after
+            )?;
+            writeln!(
+                f,
+                "Prediction flips:   {} of {}",
+                validation.flips, validation.rows
+            )?;
+        }
+
+        match (self.accepted, self.written) {
+            (true, _) => Ok(()),
+            (false, true) => writeln!(f, "Exceeds the maximum drop, written anyway"),
+            (false, false) => writeln!(f, "Exceeds the maximum drop, nothing written"),
+        }
+    }
+}
+
+/// Quantize a forest definition file (CSV) whose problem type is detected
+/// from its header, or given by `problem_type`, and write it to `output`
+/// along with its metadata.
+///
+/// With `cache`, the input is read through its cache, see
+/// [`read_cached`](crate::forest::cache::read_cached).
+/// `validation` is a labeled dataset (CSV) and the name of its label column.
+/// With `max_drop`, the quantized forest is only written if its score on the
+/// dataset did not drop by more than `max_drop` (see
+/// [`Prune::is_acceptable`]), unless `force` is set.
+#[allow(clippy::too_many_arguments)]
+pub fn quantize_file(
+    input: impl AsRef<Path>,
+    problem_type: Option<PredictionType>,
+    cache: bool,
+    options: &QuantizeOptions,
+    validation: Option<(&Path, &str)>,
+    max_drop: Option<f64>,
+    force: bool,
+    output: impl AsRef<Path>,
+) -> Result<QuantizeReport> {
+    fn quantize_forest<N>(
+        input: &Path,
+        cache: bool,
+        options: &QuantizeOptions,
+        validation: Option<(&Path, &str)>,
+        max_drop: Option<f64>,
+        force: bool,
+        output: &Path,
+    ) -> Result<QuantizeReport>
+    where
+        N: SerializedNode,
+        N::ProblemType: QuantizeLeaves,
+    {
+        let mut forest = read_definition::<N>(input, cache)?;
+
+        let validation = validation
+            .map(|(data, label_column)| {
+                let features = forest.features_by_index();
+                let dataset = read_labeled(data, &features, label_column)?;
+                Validation::new(&forest, dataset)
+            })
+            .transpose()?;
+        if max_drop.is_some() && validation.is_none() {
+            return Err(eyre!("A maximum drop requires a validation dataset"));
+        }
+
+        let original = validation.as_ref().map(|v| v.predict(&forest));
+        let error = quantize(&mut forest, options)?;
+
+        let validation = validation.zip(original).map(|(validation, original)| {
+            ValidationChange::new(&validation, &original, &validation.predict(&forest))
+        });
+
+        let accepted = match (validation, max_drop) {
+            (Some(v), Some(max_drop)) => N::ProblemType::is_acceptable(v.before, v.after, max_drop),
+            _ => true,
+        };
+
+        let written = accepted || force;
+        if written {
+            write_artifacts(
+                &forest,
+                &[(OutputFormat::Rforest, output.to_path_buf())],
+                ForestMetadata::sidecar_path(output),
+            )?;
+        }
+
+        Ok(QuantizeReport {
+            score: N::ProblemType::SCORE,
+            error,
+            validation,
+            accepted,
+            written,
+        })
+    }
+
+    let (input, output) = (input.as_ref(), output.as_ref());
+    match resolve_problem_type(input, problem_type)? {
+        PredictionType::Classification => quantize_forest::<SerializedClassificationNode>(
+            input, cache, options, validation, max_drop, force, output,
+        ),
+        PredictionType::Regression => quantize_forest::<SerializedRegressionNode>(
+            input, cache, options, validation, max_drop, force, output,
+        ),
+    }
+}
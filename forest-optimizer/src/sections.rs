@@ -0,0 +1,240 @@
+//! Sections of a serialized forest and the bytes each takes, to see what to
+//! strip when a forest does not fit its budget, see [`FORMAT_ABI`] for the
+//! format.
+//!
+//! Sizes are computed from the parsed header, not by serializing again.
+//! Optional sections which only describe the forest, or adjust its output,
+//! can be [`strip`]ped without converting it again; the others are needed
+//! to predict.
+
+use std::fmt;
+
+use aligned_vec::AVec;
+use color_eyre::{Result, eyre::eyre};
+use embedded_rforest::forest::{
+    Aggregation, Transform,
+    abi::FORMAT_ABI,
+    calibration::Platt,
+    deserialize::{BUFFER_ALIGN, CALIBRATION, FINGERPRINT, ForestHeader, SUPPORT, TRANSFORM},
+    encryption::ENCRYPTION_LEN,
+};
+
+/// A section of a serialized forest, in the order they are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Section {
+    /// Counts, layout and flags of the forest
+    Header,
+    /// Offset of every tree in the node array
+    TreeTable,
+    /// Fingerprint of the feature and target names
+    Fingerprint,
+    /// Base score of forests summing their trees
+    BaseScore,
+    /// Transform of the combined output
+    Transform,
+    /// Platt scaling of every class
+    Calibration,
+    /// Nonce, key check and checksum of an encrypted forest
+    Encryption,
+    /// Bytes aligning the node array
+    Padding,
+    /// Branches of every tree
+    Nodes,
+    /// Distinct leaf values of a regression forest
+    LeafTable,
+    /// Number of training samples of every leaf
+    Support,
+}
+
+impl Section {
+    /// Whether the forest still predicts without the section, so it can be
+    /// stripped. Without a transform or calibration, it returns the output
+    /// before them.
+    pub fn is_optional(self) -> bool {
+        self.flag().is_some()
+    }
+
+    /// Bit of the header flags telling the section is there
+    fn flag(self) -> Option<u8> {
+        match self {
+            Section::Fingerprint => Some(FINGERPRINT),
+            Section::Transform => Some(TRANSFORM),
+            Section::Calibration => Some(CALIBRATION),
+            Section::Support => Some(SUPPORT),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Section::Header => "header",
+            Section::TreeTable => "tree table",
+            Section::Fingerprint => "fingerprint",
+            Section::BaseScore => "base score",
+            Section::Transform => "transform",
+            Section::Calibration => "calibration",
+            Section::Encryption => "encryption",
+            Section::Padding => "padding",
+            Section::Nodes => "nodes",
+            Section::LeafTable => "leaf table",
+            Section::Support => "support",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Bytes taken by one section of a serialized forest.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct SectionSize {
+    pub section: Section,
+    /// Offset of the section from the start of the buffer, in bytes
+    pub offset: usize,
+    pub bytes: usize,
+    /// Share of the serialized forest, in percent
+    pub percent: f64,
+}
+
+/// Size of every section of the forest `header` describes, in order. Absent
+/// sections are left out, and the sizes add up to the size of the forest.
+pub fn size_breakdown(header: &ForestHeader) -> Vec<SectionSize> {
+    let word = size_of::<u32>();
+    let tree_table = if header.tree_table {
+        header.num_trees as usize * word
+    } else {
+        0
+    };
+    let base_score = match header.aggregation {
+        Aggregation::Sum { .. } => word,
+        _ => 0,
+    };
+    let transform = if header.transform == Transform::Identity {
+        0
+    } else {
+        3 * word
+    };
+    let calibration = match header.num_targets {
+        Some(targets) if header.calibrated => usize::from(targets.get()) * size_of::<Platt>(),
+        _ => 0,
+    };
+    let sections = [
+        (Section::Header, FORMAT_ABI.header.size),
+        (Section::TreeTable, tree_table),
+        (Section::Fingerprint, header.fingerprint.map_or(0, |_| word)),
+        (Section::BaseScore, base_score),
+        (Section::Transform, transform),
+        (Section::Calibration, calibration),
+        (
+            Section::Encryption,
+            if header.encrypted { ENCRYPTION_LEN } else { 0 },
+        ),
+    ];
+    let unpadded = sections.iter().map(|(_, bytes)| bytes).sum::<usize>();
+    let sections = sections.into_iter().chain([
+        (Section::Padding, header.header_len - unpadded),
+        (
+            Section::Nodes,
+            header.node_count * header.layout.branch_size(),
+        ),
+        (Section::LeafTable, header.num_leaves * word),
+        (Section::Support, header.support_len()),
+    ]);
+
+    let total = header.serialized_len() as f64;
+    let mut offset = 0;
+    sections
+        .filter(|&(_, bytes)| bytes > 0)
+        .map(|(section, bytes)| {
+            let size = SectionSize {
+                section,
+                offset,
+                bytes,
+                percent: bytes as f64 / total * 100.0,
+            };
+            offset += bytes;
+            size
+        })
+        .collect()
+}
+
+/// Size of every section of a forest, printed as a table.
+pub struct SizeTable<'a>(pub &'a [SectionSize]);
+
+impl fmt::Display for SizeTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<12}  {:>10}  {:>6}", "Section", "Bytes", "Share")?;
+        for size in self.0 {
+            let optional = if size.section.is_optional() {
+                "  (optional)"
+            } else {
+                ""
+            };
+            writeln!(
+                f,
+                "{:<12}  {:>10}  {:>5.1}%{optional}",
+                size.section.to_string(),
+                size.bytes,
+                size.percent
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Copy of the serialized forest in `buffer` without the optional
+/// `sections`, which then predicts as before, but without the transform or
+/// calibration if they were stripped.
+///
+/// Fails if a section is required, see [`Section::is_optional`], or absent,
+/// and for the support of an encrypted forest, which is encrypted with its
+/// nodes.
+pub fn strip(buffer: &[u8], sections: &[Section]) -> Result<AVec<u8>> {
+    let header = ForestHeader::peek(buffer).map_err(|e| eyre!("Malformed forest header: {e:?}"))?;
+    let breakdown = size_breakdown(&header);
+    for section in sections {
+        if !section.is_optional() {
+            return Err(eyre!(
+                "Cannot strip the {section}: the forest needs it to predict"
+            ));
+        }
+        if header.encrypted && *section == Section::Support {
+            return Err(eyre!(
+                "Cannot strip the support of an encrypted forest: it is encrypted with the nodes"
+            ));
+        }
+        if !breakdown.iter().any(|size| size.section == *section) {
+            return Err(eyre!("The forest has no {section} to strip"));
+        }
+    }
+
+    let mut stripped = AVec::with_capacity(BUFFER_ALIGN, buffer.len());
+    let mut node_offset = 0;
+    for size in &breakdown {
+        let bytes = &buffer[size.offset..size.offset + size.bytes];
+        match size.section {
+            section if sections.contains(&section) => {}
+            // Padded again once the header is complete
+            Section::Padding => {}
+            Section::Nodes => {
+                node_offset = stripped.len().next_multiple_of(BUFFER_ALIGN);
+                stripped.resize(node_offset, 0);
+                stripped.extend_from_slice(bytes);
+            }
+            _ => stripped.extend_from_slice(bytes),
+        }
+    }
+
+    // The new length of the header, and flags without the stripped sections
+    let header_len = FORMAT_ABI.header.field("header_len").offset;
+    let node_offset = u16::try_from(node_offset).map_err(|_| eyre!("Header is too long"))?;
+    stripped[header_len..header_len + 2].copy_from_slice(&node_offset.to_le_bytes());
+    let flags = FORMAT_ABI.header.field("flags").offset;
+    for flag in sections.iter().filter_map(|section| section.flag()) {
+        stripped[flags] &= !flag;
+    }
+
+    ForestHeader::peek(&stripped).map_err(|e| eyre!("Stripped forest is malformed: {e:?}"))?;
+    Ok(stripped)
+}
@@ -1,42 +1,82 @@
-use crate::forest::{BranchNode, LeafNode, Node};
-use crate::problem_type::{Classification, Map, PredictionType, ProblemType, Regression};
+use crate::forest::{BranchNode, Forest, LeafNode, Node};
+use crate::problem_type::{
+    Classification, FeatureIdx, FeatureMap, PredictionType, ProblemType, Regression, TargetIdx,
+    TargetMap,
+};
 use crate::typelevel::private::Sealed;
-use std::collections::hash_map::Entry;
 use std::fmt::Debug;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 use std::{fs, io};
 
 use color_eyre::Result;
-use color_eyre::eyre::{Context, ContextCompat, OptionExt, eyre};
-use serde::{Deserialize, Deserializer};
+use color_eyre::eyre::{Context, OptionExt, eyre};
+use embedded_rforest::forest::SplitRule;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+
+use self::split_var::{SplitVarKind, SplitVars};
+use self::validate::{Severity, diagnose_row};
+
+#[cfg(feature = "parallel")]
+mod parallel;
+pub mod split_var;
+pub mod validate;
 
 pub trait NodeType {}
 
-pub trait SerializedNode: Sealed + Clone {
+pub trait SerializedNode: Sealed + Clone + Send + Sync + Serialize + DeserializeOwned {
     type ProblemType: ProblemType;
 
-    fn deserialize<R: io::Read>(
-        problem: &mut Self::ProblemType,
-        rdr: &mut csv::Reader<R>,
-    ) -> Result<Vec<Self>>;
+    /// Index the split variable of this node, and its prediction if it is a
+    /// classification leaf, in `problem`, unless they already are. Indices
+    /// follow the order names are encountered in.
+    fn register(&self, problem: &mut Self::ProblemType);
 
     /// Turn a serialized node into a [`Node`]. This function also
     /// renormalizes indices to use 0-indexing, and converts feature and target
     /// names to their indices.
     fn normalize(self, problem: &Self::ProblemType) -> Result<Node<Self::ProblemType>>;
 
+    /// The inverse of [`SerializedNode::normalize`]: turn the node of index
+    /// `node_idx` of tree `tree_idx`, both 1-indexed, into a serialized node.
+    /// `features` are the feature names, positioned by index.
+    fn denormalize(
+        node: &Node<Self::ProblemType>,
+        problem: &Self::ProblemType,
+        features: &[&str],
+        tree_idx: usize,
+        node_idx: usize,
+    ) -> Self;
+
     fn node_idx(&self) -> usize;
     fn tree_idx(&self) -> usize;
+
+    /// Split variable of this node, if it is a branch, to resolve before it
+    /// is registered, see [`split_var`].
+    fn split_var_mut(&mut self) -> Option<&mut String>;
+
+    /// Left and right daughters of this node, if it is a branch: it has a
+    /// split variable.
+    fn daughters(&self) -> Option<[u32; 2]>;
+
+    /// Problems of this row on its own, see [`SerializedForest::validate`].
+    fn diagnose(&self) -> Vec<(Severity, String)>;
+
+    /// Assign target indices following `targets`, see
+    /// [`SerializedForest::reorder`].
+    fn reorder_targets(problem: &mut Self::ProblemType, targets: &[&str]) -> Result<()>;
+
+    /// Assign target indices in alphabetical order of their names, if the
+    /// problem has targets.
+    fn sort_targets(problem: &mut Self::ProblemType);
+
+    /// Number of targets of `problem`, if it has targets.
+    fn num_targets(problem: &Self::ProblemType) -> Option<usize>;
 }
 
 /// A single node of a [`SerializedForest`] in classification mode
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct SerializedClassificationNode {
-    /// Tree index. 1-indexed.
-    pub tree_idx: usize,
-    /// Node index. 1-indexed.
-    pub node_idx: usize,
     /// Pointer to left branch node
     #[serde(rename = "left daughter")]
     pub left: u32,
@@ -44,28 +84,55 @@ pub struct SerializedClassificationNode {
     #[serde(rename = "right daughter")]
     pub right: u32,
     /// The variable on which to split
-    #[serde(rename = "split var", deserialize_with = "string_or_na")]
+    #[serde(
+        rename = "split var",
+        deserialize_with = "string_or_na",
+        serialize_with = "na_if_none"
+    )]
     pub split_on: Option<String>,
     /// The split point
-    #[serde(rename = "split point")]
+    #[serde(rename = "split point", deserialize_with = "decimal")]
     pub split_at: f32,
     /// The node status. A value of 1 represents a branch, and -1 represents a
     /// prediction
     pub status: i8,
     /// The predicted variable
-    #[serde(deserialize_with = "string_or_na")]
+    #[serde(deserialize_with = "string_or_na", serialize_with = "na_if_none")]
     pub prediction: Option<String>,
+    /// Tree index. 1-indexed.
+    pub tree_idx: usize,
+    /// Node index. 1-indexed.
+    pub node_idx: usize,
+    /// Training samples which reached the node, from an optional column
+    #[serde(
+        rename = "n",
+        alias = "samples",
+        alias = "n_samples",
+        default,
+        deserialize_with = "count_or_na",
+        skip_serializing
+    )]
+    pub samples: Option<u32>,
+    /// Impurity of the node, e.g. its Gini index, from an optional column
+    #[serde(
+        rename = "impurity",
+        alias = "gini",
+        default,
+        deserialize_with = "decimal_or_na",
+        skip_serializing
+    )]
+    pub impurity: Option<f32>,
 }
 
 impl SerializedClassificationNode {
     /// Find the feature ID of this node's split variable
-    pub fn feature_id(&self, features_map: &Map) -> Option<u32> {
-        features_map.get(self.split_on.as_ref()?).copied()
+    pub fn feature_id(&self, features_map: &FeatureMap) -> Option<FeatureIdx> {
+        features_map.index_of(self.split_on.as_ref()?)
     }
 
     /// Find the target ID of this node's prediction
-    pub fn target_id(&self, targets_map: &Map) -> Option<u32> {
-        targets_map.get(self.prediction.as_ref()?).copied()
+    pub fn target_id(&self, targets_map: &TargetMap) -> Option<TargetIdx> {
+        targets_map.index_of(self.prediction.as_ref()?)
     }
 }
 
@@ -74,43 +141,16 @@ impl Sealed for SerializedClassificationNode {}
 impl SerializedNode for SerializedClassificationNode {
     type ProblemType = Classification;
 
-    fn deserialize<R: io::Read>(
-        problem: &mut Self::ProblemType,
-        rdr: &mut csv::Reader<R>,
-    ) -> Result<Vec<Self>> {
-        let mut feat_count = 0;
-        let mut target_count = 0;
-
-        let mut nodes = Vec::new();
-
-        for result in rdr.deserialize() {
-            let record: SerializedClassificationNode = result?;
-
-            if let Some(feat) = &record.split_on {
-                assert_ne!(record.left, 0, "Node doesn't have a left daughter");
-                assert_ne!(record.right, 0, "Node doesn't have a right daughter");
-
-                // Map all available features and assign an index to each
-                if let Entry::Vacant(e) = problem.features_mut().entry(feat.clone()) {
-                    e.insert(feat_count);
-                    feat_count += 1;
-                }
+    fn register(&self, problem: &mut Self::ProblemType) {
+        match (&self.split_on, &self.prediction) {
+            (Some(feature), _) => {
+                problem.features_mut().insert_next(feature);
             }
-
-            // Map all available targets and assign an index to each
-            if let Some(target) = &record.prediction {
-                assert_eq!(record.status, -1, "Node is not a classification prediction");
-
-                if let Entry::Vacant(e) = problem.targets_mut().entry(target.clone()) {
-                    e.insert(target_count);
-                    target_count += 1;
-                }
+            (None, Some(target)) => {
+                problem.targets_mut().insert_next(target);
             }
-
-            nodes.push(record);
+            (None, None) => {}
         }
-
-        Ok(nodes)
     }
 
     fn normalize(self, problem: &Self::ProblemType) -> Result<Node<Self::ProblemType>> {
@@ -120,8 +160,10 @@ impl SerializedNode for SerializedClassificationNode {
                     .feature_id(problem.features())
                     .ok_or_eyre("Feature ID missing")?,
                 split_at: self.split_at,
-                left: self.left - 1,
-                right: self.right - 1,
+                left: daughter(self.left)?,
+                right: daughter(self.right)?,
+                samples: self.samples,
+                impurity: self.impurity,
             };
 
             return Ok(Node::Branch(branch));
@@ -130,6 +172,8 @@ impl SerializedNode for SerializedClassificationNode {
                 prediction: self
                     .target_id(problem.targets())
                     .ok_or_eyre("Target ID missing")?,
+                samples: self.samples,
+                impurity: self.impurity,
             };
 
             return Ok(Node::Leaf(leaf));
@@ -137,6 +181,41 @@ impl SerializedNode for SerializedClassificationNode {
         Err(eyre!("Node is not a branch nor a leaf"))
     }
 
+    fn denormalize(
+        node: &Node<Self::ProblemType>,
+        problem: &Self::ProblemType,
+        features: &[&str],
+        tree_idx: usize,
+        node_idx: usize,
+    ) -> Self {
+        match node {
+            Node::Branch(b) => Self {
+                left: b.left + 1,
+                right: b.right + 1,
+                split_on: Some(features[b.split_with.as_usize()].to_string()),
+                split_at: b.split_at,
+                status: 1,
+                prediction: None,
+                tree_idx,
+                node_idx,
+                samples: b.samples(),
+                impurity: b.impurity(),
+            },
+            Node::Leaf(l) => Self {
+                left: 0,
+                right: 0,
+                split_on: None,
+                split_at: 0.0,
+                status: -1,
+                prediction: problem.targets().name_of(l.prediction).map(str::to_string),
+                tree_idx,
+                node_idx,
+                samples: l.samples(),
+                impurity: l.impurity(),
+            },
+        }
+    }
+
     fn node_idx(&self) -> usize {
         self.node_idx
     }
@@ -144,15 +223,49 @@ impl SerializedNode for SerializedClassificationNode {
     fn tree_idx(&self) -> usize {
         self.tree_idx
     }
+
+    fn split_var_mut(&mut self) -> Option<&mut String> {
+        self.split_on.as_mut()
+    }
+
+    fn daughters(&self) -> Option<[u32; 2]> {
+        self.split_on.as_ref().map(|_| [self.left, self.right])
+    }
+
+    fn diagnose(&self) -> Vec<(Severity, String)> {
+        let mut diagnostics = diagnose_row(
+            [self.left, self.right],
+            self.split_on.as_deref(),
+            self.split_at,
+            self.status,
+            self.prediction.is_some(),
+            &[1],
+        );
+        if let (Some(_), Some(prediction)) = (&self.split_on, &self.prediction) {
+            diagnostics.push((
+                Severity::Warning,
+                format!("Branch predicts '{prediction}', which is ignored"),
+            ));
+        }
+        diagnostics
+    }
+
+    fn reorder_targets(problem: &mut Self::ProblemType, targets: &[&str]) -> Result<()> {
+        problem.targets_mut().reorder(targets)
+    }
+
+    fn sort_targets(problem: &mut Self::ProblemType) {
+        problem.targets_mut().sort();
+    }
+
+    fn num_targets(problem: &Self::ProblemType) -> Option<usize> {
+        Some(problem.targets().len())
+    }
 }
 
 /// A single node of a [`SerializedForest`] in regression mode
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct SerializedRegressionNode {
-    /// Tree index. 1-indexed.
-    pub tree_idx: usize,
-    /// Node index. 1-indexed.
-    pub node_idx: usize,
     /// Pointer to left branch node
     #[serde(rename = "left daughter")]
     pub left: u32,
@@ -160,22 +273,50 @@ pub struct SerializedRegressionNode {
     #[serde(rename = "right daughter")]
     pub right: u32,
     /// The variable on which to split
-    #[serde(rename = "split var", deserialize_with = "string_or_na")]
+    #[serde(
+        rename = "split var",
+        deserialize_with = "string_or_na",
+        serialize_with = "na_if_none"
+    )]
     pub split_on: Option<String>,
     /// The split point
-    #[serde(rename = "split point")]
+    #[serde(rename = "split point", deserialize_with = "decimal")]
     pub split_at: f32,
-    /// The node status. A value of 1 represents a branch, and -1 represents a
-    /// prediction
+    /// The node status. A value of 1 or -3 represents a branch, and -1
+    /// represents a prediction
     pub status: i8,
     /// The predicted variable
+    #[serde(deserialize_with = "decimal_or_na", serialize_with = "na_if_none")]
     pub prediction: Option<f32>,
+    /// Tree index. 1-indexed.
+    pub tree_idx: usize,
+    /// Node index. 1-indexed.
+    pub node_idx: usize,
+    /// Training samples which reached the node, from an optional column
+    #[serde(
+        rename = "n",
+        alias = "samples",
+        alias = "n_samples",
+        default,
+        deserialize_with = "count_or_na",
+        skip_serializing
+    )]
+    pub samples: Option<u32>,
+    /// Impurity of the node, e.g. its Gini index, from an optional column
+    #[serde(
+        rename = "impurity",
+        alias = "gini",
+        default,
+        deserialize_with = "decimal_or_na",
+        skip_serializing
+    )]
+    pub impurity: Option<f32>,
 }
 
 impl SerializedRegressionNode {
     /// Find the feature ID of this node's split variable
-    pub fn feature_id(&self, features_map: &Map) -> Option<u32> {
-        features_map.get(self.split_on.as_ref()?).copied()
+    pub fn feature_id(&self, features_map: &FeatureMap) -> Option<FeatureIdx> {
+        features_map.index_of(self.split_on.as_ref()?)
     }
 
     /// Find this node's prediction
@@ -189,31 +330,10 @@ impl Sealed for SerializedRegressionNode {}
 impl SerializedNode for SerializedRegressionNode {
     type ProblemType = Regression;
 
-    fn deserialize<R: io::Read>(
-        problem: &mut Self::ProblemType,
-        rdr: &mut csv::Reader<R>,
-    ) -> Result<Vec<Self>> {
-        let mut feat_count = 0;
-        let mut nodes = Vec::new();
-
-        for result in rdr.deserialize() {
-            let record: SerializedRegressionNode = result?;
-
-            if let Some(feat) = &record.split_on {
-                assert_ne!(record.left, 0, "Node doesn't have a left daughter");
-                assert_ne!(record.right, 0, "Node doesn't have a right daughter");
-
-                // Map all available features and assign an index to each
-                if let Entry::Vacant(e) = problem.features_mut().entry(feat.clone()) {
-                    e.insert(feat_count);
-                    feat_count += 1;
-                }
-            }
-
-            nodes.push(record);
+    fn register(&self, problem: &mut Self::ProblemType) {
+        if let Some(feature) = &self.split_on {
+            problem.features_mut().insert_next(feature);
         }
-
-        Ok(nodes)
     }
 
     fn normalize(self, problem: &Self::ProblemType) -> Result<Node<Self::ProblemType>> {
@@ -223,14 +343,18 @@ impl SerializedNode for SerializedRegressionNode {
                     .feature_id(problem.features())
                     .ok_or_eyre("Feature ID missing")?,
                 split_at: self.split_at,
-                left: self.left - 1,
-                right: self.right - 1,
+                left: daughter(self.left)?,
+                right: daughter(self.right)?,
+                samples: self.samples,
+                impurity: self.impurity,
             };
 
             return Ok(Node::Branch(branch));
         } else if self.prediction.is_some() {
             let leaf = LeafNode {
                 prediction: self.prediction.ok_or_eyre("Prediction missing")?,
+                samples: self.samples,
+                impurity: self.impurity,
             };
 
             return Ok(Node::Leaf(leaf));
@@ -238,6 +362,42 @@ impl SerializedNode for SerializedRegressionNode {
         Err(eyre!("Node is not a branch nor a leaf"))
     }
 
+    /// Branches have no prediction, as the mean of their rows is unknown
+    fn denormalize(
+        node: &Node<Self::ProblemType>,
+        _: &Self::ProblemType,
+        features: &[&str],
+        tree_idx: usize,
+        node_idx: usize,
+    ) -> Self {
+        match node {
+            Node::Branch(b) => Self {
+                left: b.left + 1,
+                right: b.right + 1,
+                split_on: Some(features[b.split_with.as_usize()].to_string()),
+                split_at: b.split_at,
+                status: -3,
+                prediction: None,
+                tree_idx,
+                node_idx,
+                samples: b.samples(),
+                impurity: b.impurity(),
+            },
+            Node::Leaf(l) => Self {
+                left: 0,
+                right: 0,
+                split_on: None,
+                split_at: 0.0,
+                status: -1,
+                prediction: Some(l.prediction),
+                tree_idx,
+                node_idx,
+                samples: l.samples(),
+                impurity: l.impurity(),
+            },
+        }
+    }
+
     fn node_idx(&self) -> usize {
         self.node_idx
     }
@@ -245,12 +405,60 @@ impl SerializedNode for SerializedRegressionNode {
     fn tree_idx(&self) -> usize {
         self.tree_idx
     }
+
+    fn split_var_mut(&mut self) -> Option<&mut String> {
+        self.split_on.as_mut()
+    }
+
+    fn daughters(&self) -> Option<[u32; 2]> {
+        self.split_on.as_ref().map(|_| [self.left, self.right])
+    }
+
+    /// Branches may have status 1, or -3 like R writes them
+    fn diagnose(&self) -> Vec<(Severity, String)> {
+        let mut diagnostics = diagnose_row(
+            [self.left, self.right],
+            self.split_on.as_deref(),
+            self.split_at,
+            self.status,
+            self.prediction.is_some(),
+            &[1, -3],
+        );
+        if let (None, Some(prediction)) = (&self.split_on, self.prediction)
+            && !prediction.is_finite()
+        {
+            diagnostics.push((
+                Severity::Error,
+                format!("Prediction {prediction} is not a finite number"),
+            ));
+        }
+        diagnostics
+    }
+
+    fn reorder_targets(_: &mut Self::ProblemType, _: &[&str]) -> Result<()> {
+        Err(eyre!("Regression forests have no targets to order"))
+    }
+
+    fn sort_targets(_: &mut Self::ProblemType) {}
+
+    fn num_targets(_: &Self::ProblemType) -> Option<usize> {
+        None
+    }
 }
 
 #[derive(Debug)]
 pub struct SerializedForest<N: SerializedNode> {
     nodes: Vec<N>,
     problem: N::ProblemType,
+    /// Line each node was read from, positioned like `nodes`. Empty if the
+    /// forest was not read from CSV
+    lines: Vec<u64>,
+    /// Counts declared by the header of the definition
+    declared: HeaderCounts,
+    /// Split rule declared by the header of the definition
+    split_rule: SplitRule,
+    /// Whether counts which do not match `declared` are only warned about
+    allow_count_mismatch: bool,
 }
 
 impl<N: SerializedNode> SerializedForest<N> {
@@ -259,61 +467,538 @@ impl<N: SerializedNode> SerializedForest<N> {
     }
 
     /// Get the features of this forest
-    pub fn features(&self) -> &Map {
+    pub fn features(&self) -> &FeatureMap {
         self.problem.features()
     }
 
+    /// Feature names, positioned by feature index
+    pub fn features_by_index(&self) -> Vec<&str> {
+        self.features().names()
+    }
+
+    /// Index of the feature named `name`, if the forest has one
+    pub fn feature_index(&self, name: &str) -> Option<FeatureIdx> {
+        self.features().index_of(name)
+    }
+
     pub fn nodes(&self) -> &[N] {
         &self.nodes
     }
 
+    /// How the branches compare features to their thresholds, as declared
+    /// by the header of the definition. `<=` if it declares none.
+    pub fn split_rule(&self) -> SplitRule {
+        self.split_rule
+    }
+
+    #[tracing::instrument(name = "read_csv", skip_all, fields(path = %path.as_ref().display()))]
     pub fn read(path: impl AsRef<Path>) -> Result<Self> {
-        Self::validate_header(&path)?;
+        Self::from_reader(fs::File::open(path.as_ref())?)
+    }
 
-        let rdr = fs::File::open(path.as_ref())?;
-        let mut rdr = csv::ReaderBuilder::new()
-            .comment(Some(b'#'))
-            .from_reader(rdr);
+    /// [`Self::read`], resolving split vars given as column indices
+    /// following `options`, see [`split_var`]. Indices are not ordered yet,
+    /// see [`ReadOptions::prepare`].
+    pub fn read_with(path: impl AsRef<Path>, options: &ReadOptions) -> Result<Self> {
+        Self::from_reader_with(fs::File::open(path.as_ref())?, options)
+    }
+
+    /// Read a forest definition (CSV) from any source, e.g. stdin. The
+    /// problem type header, if any, is checked like [`Self::read`] does.
+    ///
+    /// With the `parallel` feature, the whole definition is read into
+    /// memory, then its rows are parsed on every thread, see
+    /// `serialized_forest::parallel`.
+    pub fn from_reader(rdr: impl io::Read) -> Result<Self> {
+        Self::from_reader_with(rdr, &ReadOptions::default())
+    }
 
+    /// [`Self::from_reader`], resolving split vars like [`Self::read_with`].
+    pub fn from_reader_with(rdr: impl io::Read, options: &ReadOptions) -> Result<Self> {
         let mut problem = N::ProblemType::default();
+        let mut nodes = Vec::new();
+        let mut lines = Vec::new();
+        let row = |node: N, line, _: &_| {
+            nodes.push(node);
+            lines.push(line);
+            Ok(())
+        };
+        #[cfg(not(feature = "parallel"))]
+        let (declared, split_rule) = read_rows(rdr, &mut problem, options, row)?;
+        #[cfg(feature = "parallel")]
+        let (declared, split_rule) = {
+            let mut definition = Vec::new();
+            io::Read::read_to_end(&mut { rdr }, &mut definition)?;
+            parallel::read_rows(&definition, &mut problem, options, row)?
+        };
+        tracing::debug!(nodes = nodes.len(), "Read forest definition");
 
-        let nodes = N::deserialize(&mut problem, &mut rdr)?;
+        Ok(SerializedForest {
+            nodes,
+            problem,
+            lines,
+            declared,
+            split_rule,
+            allow_count_mismatch: false,
+        })
+    }
 
-        Ok(SerializedForest { nodes, problem })
+    /// Some rows of a definition, read from `lines`, e.g. those of one tree,
+    /// to be validated on their own. Their names are not registered.
+    pub(crate) fn from_rows(nodes: Vec<N>, lines: Vec<u64>) -> Self {
+        SerializedForest {
+            nodes,
+            problem: N::ProblemType::default(),
+            lines,
+            declared: HeaderCounts::default(),
+            split_rule: SplitRule::default(),
+            allow_count_mismatch: false,
+        }
     }
 
-    fn validate_header(path: impl AsRef<Path>) -> Result<()> {
-        let rdr = BufReader::new(fs::File::open(path.as_ref())?);
+    /// The definition of `forest`, the inverse of [`Forest::from_serialized`].
+    /// Trees and their nodes are numbered from 1, in order.
+    pub fn from_forest(forest: &Forest<N::ProblemType>) -> Self {
+        let features = forest.features_by_index();
+        let nodes = forest
+            .trees()
+            .enumerate()
+            .flat_map(|(tree_idx, tree)| {
+                tree.to_nodes()
+                    .iter()
+                    .enumerate()
+                    .map(|(node_idx, node)| {
+                        N::denormalize(
+                            node,
+                            forest.problem(),
+                            &features,
+                            tree_idx + 1,
+                            node_idx + 1,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        SerializedForest {
+            nodes,
+            problem: forest.problem().clone(),
+            lines: Vec::new(),
+            declared: HeaderCounts::default(),
+            split_rule: forest.split_rule(),
+            allow_count_mismatch: false,
+        }
+    }
 
-        let header = rdr
-            .lines()
-            .take(1)
-            .collect::<Result<Vec<_>, _>>()?
-            .join(" ");
+    /// Counts of the trees, features and targets of the nodes.
+    pub fn counts(&self) -> HeaderCounts {
+        let trees = self
+            .nodes
+            .iter()
+            .map(SerializedNode::tree_idx)
+            .collect::<std::collections::BTreeSet<_>>();
 
-        let header = header
-            .strip_prefix("#")
-            .context("Malformed forest definition file. First line doesn't start with '#'.")?;
+        HeaderCounts {
+            num_trees: Some(trees.len()),
+            num_features: Some(self.problem.features().len()),
+            num_targets: N::num_targets(&self.problem),
+        }
+    }
 
-        let prediction_type = &serde_json::from_str::<serde_json::Value>(header)
-            .context("Malformed forest definition file. First line doesn't contain valid json")?["problem_type"];
+    /// Counts declared by the header of the definition, checked against
+    /// [`Self::counts`] by [`Self::validate`].
+    pub fn declared_counts(&self) -> HeaderCounts {
+        self.declared
+    }
 
-        let prediction_type: PredictionType = serde_json::from_value(prediction_type.clone())?;
-        if prediction_type != N::ProblemType::TYPE {
-            return Err(color_eyre::eyre::eyre!(
-                "You are trying to solve a regression problem with classification methods, or a classification problem with regression methods!"
-            ));
+    /// Only warn about counts which do not match those declared by the
+    /// header, like `convert --allow-count-mismatch`.
+    pub fn allow_count_mismatch(self) -> Self {
+        Self {
+            allow_count_mismatch: true,
+            ..self
         }
+    }
+
+    /// Write this forest definition to `path`, see [`Self::to_csv`].
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = fs::File::create(path.as_ref()).context("Could not create CSV file")?;
+        self.to_csv(io::BufWriter::new(file))
+    }
+
+    /// Write this forest definition as CSV, the inverse of
+    /// [`Self::from_reader`]: the header, with the problem type,
+    /// [`Self::counts`] and the split rule unless it is `<=`, the columns of the R export, then every node in
+    /// order, with `NA` for missing split variables and predictions. The
+    /// output only depends on the nodes.
+    ///
+    /// Features and targets are indexed in the order a reader encounters
+    /// them, so a [`Forest`] indexed in another order reads back with other
+    /// indices, unless read with [`Self::read_with_order`].
+    pub fn to_csv(&self, mut wtr: impl io::Write) -> Result<()> {
+        writeln!(
+            wtr,
+            "{}",
+            header(N::ProblemType::TYPE, self.counts(), self.split_rule)
+        )?;
+
+        let mut wtr = csv::Writer::from_writer(wtr);
+        for node in &self.nodes {
+            wtr.serialize(node)?;
+        }
+        wtr.flush()?;
 
         Ok(())
     }
+
+    /// [`Self::read`], with feature indices, and target indices if given,
+    /// following the order of `features` and `targets` instead of the order
+    /// they appear in the file.
+    pub fn read_with_order(
+        path: impl AsRef<Path>,
+        features: &[&str],
+        targets: Option<&[&str]>,
+    ) -> Result<Self> {
+        Self::read(path)?.reorder(Some(features), targets)
+    }
+
+    /// Assign feature and target indices following the order of `features`
+    /// and `targets`, when given.
+    ///
+    /// Each list must name exactly the features (or targets) of the forest.
+    pub fn reorder(mut self, features: Option<&[&str]>, targets: Option<&[&str]>) -> Result<Self> {
+        if let Some(features) = features {
+            self.problem.features_mut().reorder(features)?;
+        }
+        if let Some(targets) = targets {
+            N::reorder_targets(&mut self.problem, targets)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Assign feature and target indices in alphabetical order of their
+    /// names, so that they do not depend on the order of the rows.
+    pub fn sort_names(mut self) -> Self {
+        self.problem.features_mut().sort();
+        N::sort_targets(&mut self.problem);
+        self
+    }
+
+    fn validate_header(declared: Option<PredictionType>) -> Result<()> {
+        match declared {
+            Some(prediction_type) if prediction_type != N::ProblemType::TYPE => {
+                Err(color_eyre::eyre::eyre!(
+                    "You are trying to solve a regression problem with classification methods, or a classification problem with regression methods!"
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The 0-indexed position of the 1-indexed `daughter` of a branch, 0 being
+/// none.
+fn daughter(daughter: u32) -> Result<u32> {
+    daughter
+        .checked_sub(1)
+        .ok_or_eyre("Branch has no daughter on one side (0)")
+}
+
+/// Order of the names no list of an [`IndexOrder`] pins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NameOrder {
+    /// The order names are first encountered in the definition
+    #[default]
+    Encounter,
+    /// Alphabetical order, see [`SerializedForest::sort_names`]
+    Alphabetical,
+}
+
+/// Feature and target names in the order their indices should follow, see
+/// [`SerializedForest::reorder`]. Names without a list follow `unlisted`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexOrder {
+    pub features: Option<Vec<String>>,
+    /// Classification only
+    pub targets: Option<Vec<String>>,
+    pub unlisted: NameOrder,
+}
+
+impl IndexOrder {
+    pub fn apply<N: SerializedNode>(
+        &self,
+        forest: SerializedForest<N>,
+    ) -> Result<SerializedForest<N>> {
+        let features = self
+            .features
+            .as_ref()
+            .map(|names| names.iter().map(String::as_str).collect::<Vec<_>>());
+        let targets = self
+            .targets
+            .as_ref()
+            .map(|names| names.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let forest = match self.unlisted {
+            NameOrder::Encounter => forest,
+            NameOrder::Alphabetical => forest.sort_names(),
+        };
+        forest.reorder(features.as_deref(), targets.as_deref())
+    }
+}
+
+/// Read a forest definition (CSV) row by row: its problem type header, if
+/// any, is checked, then the split var of each row is resolved following
+/// `options`, and the row registered in `problem` and passed to `row` along
+/// with its line and `problem`. Returns the counts and the split rule the
+/// header declares.
+pub(crate) fn read_rows<N: SerializedNode>(
+    rdr: impl io::Read,
+    problem: &mut N::ProblemType,
+    options: &ReadOptions,
+    mut row: impl FnMut(N, u64, &N::ProblemType) -> Result<()>,
+) -> Result<(HeaderCounts, SplitRule)> {
+    let mut rdr = BufReader::new(rdr);
+    let header = parse_header(&mut rdr)?;
+    SerializedForest::<N>::validate_header(header.as_ref().map(|header| header.problem_type))?;
+    let mut split_vars = SplitVars::new(header.as_ref(), options)?;
+    let declared = header
+        .as_ref()
+        .map(|header| header.counts)
+        .unwrap_or_default();
+    let split_rule = header
+        .as_ref()
+        .map(|header| header.split_rule)
+        .unwrap_or_default();
+    // The reader counts lines from after the header
+    let header_lines = u64::from(header.is_some());
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_reader(rdr);
+    let columns = rdr.headers()?.clone();
+
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        let line = record.position().map_or(0, |p| p.line()) + header_lines;
+        let mut node: N = record
+            .deserialize(Some(&columns))
+            .with_context(|| format!("Could not read the node on line {line}"))?;
+        split_vars.resolve(&mut node, line)?;
+        node.register(problem);
+        row(node, line, problem)?;
+    }
+    split_vars.finish();
+
+    Ok((declared, split_rule))
+}
+
+/// How a forest definition is read before it is flattened: its indices
+/// follow `order`, and with `allow_count_mismatch`, counts which do not match
+/// its header are only warned about.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadOptions {
+    pub order: IndexOrder,
+    pub allow_count_mismatch: bool,
+    /// Read split vars as column indices, like `"split_var": "index"` in
+    /// the header, see [`split_var`]
+    pub split_var_as_index: bool,
+    /// Names of the columns split vars index, in order, rather than the
+    /// `"feature_names"` of the header or the features of `order`
+    pub feature_names: Option<Vec<String>>,
+}
+
+impl ReadOptions {
+    /// Check `forest`, see [`SerializedForest::check`], and index its names
+    /// following `order`.
+    pub fn prepare<N: SerializedNode>(
+        &self,
+        forest: SerializedForest<N>,
+    ) -> Result<SerializedForest<N>> {
+        let forest = if self.allow_count_mismatch {
+            forest.allow_count_mismatch()
+        } else {
+            forest
+        };
+        forest.check()?;
+        self.order.apply(forest)
+    }
+}
+
+/// Problem type of a forest definition file, taken from `given` or from the
+/// file's header. Both must agree if both are present.
+pub fn resolve_problem_type(
+    path: impl AsRef<Path>,
+    given: Option<PredictionType>,
+) -> Result<PredictionType> {
+    let path = path.as_ref();
+    check_problem_type(path.display(), given, read_problem_type(path)?)
+}
+
+/// Problem type of a forest definition, taken from `given` or from the
+/// `declared` header of `source`. Both must agree if both are present.
+pub fn check_problem_type(
+    source: impl std::fmt::Display,
+    given: Option<PredictionType>,
+    declared: Option<PredictionType>,
+) -> Result<PredictionType> {
+    match (given, declared) {
+        (Some(given), Some(declared)) if given != declared => Err(eyre!(
+            "--problem-type is {given} but {source} declares a {declared} forest"
+        )),
+        (Some(problem_type), _) | (None, Some(problem_type)) => Ok(problem_type),
+        (None, None) => Err(eyre!(
+            "{source} has no problem type header, pass it with --problem-type"
+        )),
+    }
+}
+
+/// Read the problem type declared in the first line of a forest definition
+/// file, e.g. `# { "problem_type": "classification" }`.
+///
+/// Returns `None` if the file has no such header line, and an error if the
+/// header exists but is malformed.
+pub fn read_problem_type(path: impl AsRef<Path>) -> Result<Option<PredictionType>> {
+    parse_problem_type(&mut BufReader::new(fs::File::open(path.as_ref())?))
+}
+
+/// Counts which the header of a forest definition may declare after its
+/// problem type, like our exporter writes them, e.g.
+/// `# { "problem_type": "classification", "num_trees": 800, "num_features": 4, "num_targets": 3 }`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeaderCounts {
+    pub num_trees: Option<usize>,
+    pub num_features: Option<usize>,
+    /// Classification only
+    pub num_targets: Option<usize>,
+}
+
+/// What the first line of a forest definition declares, see
+/// [`parse_header`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefinitionHeader {
+    pub problem_type: PredictionType,
+    pub counts: HeaderCounts,
+    /// How branches compare features to their thresholds:
+    /// `"split_rule": "<="`, the default, or `"<"` like XGBoost
+    pub split_rule: SplitRule,
+    /// How split vars name their feature: `"split_var": "name"`, the
+    /// default, or `"index"` like R's `getTree(..., labelVar = FALSE)`
+    pub split_var: SplitVarKind,
+    /// `"feature_names"` of the columns split vars index, in order
+    pub feature_names: Option<Vec<String>>,
+}
+
+/// The header line declaring `problem_type`, `counts` and `split_rule`,
+/// read by [`parse_header`]. `<=` is the default, so it is not written.
+fn header(problem_type: PredictionType, counts: HeaderCounts, split_rule: SplitRule) -> String {
+    let problem_type = match problem_type {
+        PredictionType::Classification => "classification",
+        PredictionType::Regression => "regression",
+    };
+    let counts = [
+        ("num_trees", counts.num_trees),
+        ("num_features", counts.num_features),
+        ("num_targets", counts.num_targets),
+    ];
+
+    let mut header = format!("# {{ \"problem_type\": \"{problem_type}\"");
+    for (key, count) in counts {
+        if let Some(count) = count {
+            header.push_str(&format!(", \"{key}\": {count}"));
+        }
+    }
+    if split_rule == SplitRule::Less {
+        header.push_str(", \"split_rule\": \"<\"");
+    }
+    header.push_str(" }");
+    header
+}
+
+/// [`read_problem_type`] from any source. The header line is consumed, and
+/// nothing is consumed if there is none.
+pub fn parse_problem_type(rdr: &mut impl BufRead) -> Result<Option<PredictionType>> {
+    Ok(parse_header(rdr)?.map(|header| header.problem_type))
+}
+
+/// [`parse_problem_type`], along with the counts and the split rule the
+/// header declares, if any.
+pub fn parse_header(rdr: &mut impl BufRead) -> Result<Option<DefinitionHeader>> {
+    if rdr.fill_buf()?.first() != Some(&b'#') {
+        return Ok(None);
+    }
+
+    let mut header = String::new();
+    rdr.read_line(&mut header)?;
+    let header = &header[1..];
+
+    let header = serde_json::from_str::<serde_json::Value>(header)
+        .context("Malformed forest definition file. First line doesn't contain valid json")?;
+
+    let prediction_type: PredictionType = serde_json::from_value(header["problem_type"].clone())
+        .context("Malformed forest definition file. Header has no valid \"problem_type\"")?;
+
+    let count = |key: &str| -> Result<Option<usize>> {
+        header
+            .get(key)
+            .map(|count| {
+                serde_json::from_value(count.clone()).with_context(|| {
+                    format!("Malformed forest definition file. Header has an invalid \"{key}\"")
+                })
+            })
+            .transpose()
+    };
+    let counts = HeaderCounts {
+        num_trees: count("num_trees")?,
+        num_features: count("num_features")?,
+        num_targets: count("num_targets")?,
+    };
+    let split_rule = match header.get("split_rule").map(|rule| rule.as_str()) {
+        None | Some(Some("<=")) => SplitRule::LessOrEqual,
+        Some(Some("<")) => SplitRule::Less,
+        Some(_) => {
+            return Err(eyre!(
+                "Malformed forest definition file. Header has an invalid \"split_rule\", \
+                 expected \"<=\" or \"<\""
+            ));
+        }
+    };
+
+    let split_var = header
+        .get("split_var")
+        .map(|kind| serde_json::from_value(kind.clone()))
+        .transpose()
+        .context(
+            "Malformed forest definition file. Header has an invalid \"split_var\", expected \"name\" or \"index\"",
+        )?
+        .unwrap_or_default();
+    let feature_names = header
+        .get("feature_names")
+        .map(|names| serde_json::from_value(names.clone()))
+        .transpose()
+        .context(
+            "Malformed forest definition file. Header has an invalid \"feature_names\", expected a list of names",
+        )?;
+
+    Ok(Some(DefinitionHeader {
+        problem_type: prediction_type,
+        counts,
+        split_rule,
+        split_var,
+        feature_names,
+    }))
 }
 
 impl SerializedForest<SerializedClassificationNode> {
     /// Get the targets of this forest
-    pub fn targets(&self) -> &Map {
+    pub fn targets(&self) -> &TargetMap {
         self.problem.targets()
     }
+
+    /// Class names, positioned by class index
+    pub fn targets_by_index(&self) -> Vec<&str> {
+        self.targets().names()
+    }
 }
 
 /// Deserialize a string into an `Option<String>`, returning `None` if the
@@ -332,3 +1017,75 @@ where
         Ok(Some(s))
     }
 }
+
+/// Deserialize a count into an `Option<u32>`, returning `None` if the field
+/// is empty or the literal "NA".
+fn count_or_na<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    string_or_na(deserializer)?
+        .map(|s| {
+            s.trim()
+                .parse()
+                .map_err(|_| serde::de::Error::custom(format!("'{s}' is not a count")))
+        })
+        .transpose()
+}
+
+/// Deserialize a number, see [`parse_decimal`].
+fn decimal<'de, D>(deserializer: D) -> Result<f32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse_decimal(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// Deserialize a number into an `Option<f32>`, returning `None` if the field
+/// is empty or the literal "NA". See [`parse_decimal`].
+fn decimal_or_na<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    string_or_na(deserializer)?
+        .map(|s| parse_decimal(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Parse a number written with a decimal point, or with a decimal comma like
+/// exports made under European locales, e.g. `3,14`. A comma is only read as
+/// a decimal separator between two runs of digits: numbers such as `1,234.5`
+/// or `1,234,567`, whose commas may separate thousands, are rejected.
+fn parse_decimal(s: &str) -> Result<f32, String> {
+    let s = s.trim();
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+    let number = match s.split_once(',') {
+        None => s.to_string(),
+        Some((whole, fraction))
+            if digits(whole.strip_prefix('-').unwrap_or(whole)) && digits(fraction) =>
+        {
+            format!("{whole}.{fraction}")
+        }
+        Some(_) => {
+            return Err(format!(
+                "'{s}' is ambiguous: only one decimal comma between digits is read, not \
+                 thousands separators"
+            ));
+        }
+    };
+
+    number.parse().map_err(|_| format!("'{s}' is not a number"))
+}
+
+/// Serialize `None` as the literal "NA", like R writes missing values.
+fn na_if_none<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    match value {
+        Some(value) => value.serialize(serializer),
+        None => serializer.serialize_str("NA"),
+    }
+}
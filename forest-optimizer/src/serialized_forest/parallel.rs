@@ -0,0 +1,151 @@
+//! Parsing of a forest definition on every thread of the rayon pool, behind
+//! the `parallel` feature.
+//!
+//! Rows after the column headers are split into chunks at line ends, which
+//! are parsed concurrently, then registered one after the other in file
+//! order: features and targets are indexed exactly like
+//! [`read_rows`](super::read_rows) indexes them, whatever the number of
+//! threads. Fields may thus not span lines, which the exporter never writes.
+
+use color_eyre::{Result, eyre::Context};
+use rayon::prelude::*;
+
+use embedded_rforest::forest::SplitRule;
+
+use super::{
+    HeaderCounts, ReadOptions, SerializedForest, SerializedNode, parse_header, split_var::SplitVars,
+};
+
+/// Chunks per thread, so that threads given faster chunks pick up others
+const CHUNKS_PER_THREAD: usize = 4;
+
+/// [`read_rows`](super::read_rows), parsing the rows of `definition`
+/// concurrently. Rows are still passed to `row` in file order.
+pub(super) fn read_rows<N: SerializedNode>(
+    definition: &[u8],
+    problem: &mut N::ProblemType,
+    options: &ReadOptions,
+    mut row: impl FnMut(N, u64, &N::ProblemType) -> Result<()>,
+) -> Result<(HeaderCounts, SplitRule)> {
+    let mut rdr = definition;
+    let header = parse_header(&mut rdr)?;
+    SerializedForest::<N>::validate_header(header.as_ref().map(|header| header.problem_type))?;
+    let mut split_vars = SplitVars::new(header.as_ref(), options)?;
+    let declared = header
+        .as_ref()
+        .map(|header| header.counts)
+        .unwrap_or_default();
+    let split_rule = header
+        .as_ref()
+        .map(|header| header.split_rule)
+        .unwrap_or_default();
+
+    // Lines before the rows: the header, comments, and the column headers
+    let mut lines = u64::from(header.is_some());
+    let columns = loop {
+        let (line, rest) = split_line(rdr);
+        rdr = rest;
+        lines += 1;
+        let is_columns = line.first() != Some(&b'#') && !line.trim_ascii().is_empty();
+        if is_columns || rdr.is_empty() {
+            break csv::ReaderBuilder::new()
+                .has_headers(false)
+                .comment(Some(b'#'))
+                .from_reader(line)
+                .records()
+                .next()
+                .transpose()?
+                .unwrap_or_default();
+        }
+    };
+
+    let chunks = chunks(rdr, rayon::current_num_threads() * CHUNKS_PER_THREAD);
+    let mut first_lines = Vec::with_capacity(chunks.len());
+    for chunk in &chunks {
+        first_lines.push(lines);
+        lines += chunk.iter().filter(|&&b| b == b'\n').count() as u64;
+    }
+
+    let parsed = chunks
+        .par_iter()
+        .zip(first_lines)
+        .map(|(chunk, first_line)| parse_chunk::<N>(chunk, &columns, first_line))
+        .collect::<Vec<_>>();
+    for chunk in parsed {
+        for (mut node, line) in chunk? {
+            split_vars.resolve(&mut node, line)?;
+            node.register(problem);
+            row(node, line, problem)?;
+        }
+    }
+    split_vars.finish();
+
+    Ok((declared, split_rule))
+}
+
+/// The first line of `bytes`, and the bytes after its line end.
+fn split_line(bytes: &[u8]) -> (&[u8], &[u8]) {
+    match bytes.iter().position(|&b| b == b'\n') {
+        Some(end) => (&bytes[..end], &bytes[end + 1..]),
+        None => (bytes, &[]),
+    }
+}
+
+/// Split `rows` into about `count` chunks of whole lines.
+///
+/// No chunk ends with a comment: the CSV reader gives the row after comments
+/// the line of the first of them, which a chunk starting with the row would
+/// not.
+fn chunks(rows: &[u8], count: usize) -> Vec<&[u8]> {
+    let size = rows.len().div_ceil(count.max(1)).max(1);
+    let line_end = |from: usize, rest: &[u8]| match rest[from..].iter().position(|&b| b == b'\n') {
+        Some(offset) => from + offset + 1,
+        None => rest.len(),
+    };
+
+    let mut chunks = Vec::with_capacity(count);
+    let mut rest = rows;
+    while !rest.is_empty() {
+        let mut end = line_end(size.min(rest.len()), rest);
+        while end < rest.len() && last_line(&rest[..end]).first() == Some(&b'#') {
+            end = line_end(end, rest);
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
+
+/// The last line of `bytes`, without its line end.
+fn last_line(bytes: &[u8]) -> &[u8] {
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    match bytes.iter().rposition(|&b| b == b'\n') {
+        Some(start) => &bytes[start + 1..],
+        None => bytes,
+    }
+}
+
+/// Parse the rows of `chunk`, whose first line is the line after
+/// `first_line` of the definition, along with their lines.
+fn parse_chunk<N: SerializedNode>(
+    chunk: &[u8],
+    columns: &csv::StringRecord,
+    first_line: u64,
+) -> Result<Vec<(N, u64)>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .comment(Some(b'#'))
+        .from_reader(chunk);
+
+    let mut nodes = Vec::new();
+    let mut record = csv::StringRecord::new();
+    while rdr.read_record(&mut record)? {
+        let line = record.position().map_or(0, |p| p.line()) + first_line;
+        let node: N = record
+            .deserialize(Some(columns))
+            .with_context(|| format!("Could not read the node on line {line}"))?;
+        nodes.push((node, line));
+    }
+    Ok(nodes)
+}
@@ -0,0 +1,159 @@
+//! Split variables given as column indices rather than names, like R's
+//! `getTree(..., labelVar = FALSE)` writes them.
+//!
+//! Such a definition has to say so, with `convert --split-var-as-index` or
+//! `"split_var": "index"` in its header: a number alone may be the name of a
+//! feature. Each index is then replaced by the name of its column (from 1)
+//! as its row is read, before it is registered, so the forest is exactly
+//! the one its names would make. The names come from, in order of
+//! precedence, `--feature-names`, the `"feature_names"` of the header, or
+//! `--feature-order`.
+
+use color_eyre::{Result, eyre::eyre};
+use tracing::warn;
+
+use super::{DefinitionHeader, ReadOptions, SerializedNode};
+
+/// How the split variables of a definition name their feature, declared by
+/// `"split_var"` in its header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitVarKind {
+    /// The name of the feature
+    #[default]
+    Name,
+    /// The column of the feature in the training data, from 1
+    Index,
+}
+
+/// Resolves the split variables of the rows of a definition as they are
+/// read, see [`mod@self`].
+#[derive(Debug)]
+pub(crate) enum SplitVars {
+    /// Names, which must not mix with numbers. The first split var which
+    /// is a number and the first which is not, with their lines
+    Names {
+        first_index: Option<(u64, usize)>,
+        first_name: Option<(u64, String)>,
+    },
+    /// Column indices, into these names
+    Indices(Vec<String>),
+}
+
+impl SplitVars {
+    /// How to resolve the split vars of a definition with `header`, read
+    /// following `options`.
+    pub(crate) fn new(header: Option<&DefinitionHeader>, options: &ReadOptions) -> Result<Self> {
+        let declared = header.map(|header| header.split_var).unwrap_or_default();
+        if !options.split_var_as_index && declared == SplitVarKind::Name {
+            if options.feature_names.is_some() {
+                return Err(eyre!(
+                    "Feature names only resolve split vars given as column indices: pass --split-var-as-index if they are"
+                ));
+            }
+            return Ok(Self::Names {
+                first_index: None,
+                first_name: None,
+            });
+        }
+
+        let names = options
+            .feature_names
+            .as_ref()
+            .or_else(|| header.and_then(|header| header.feature_names.as_ref()))
+            .or(options.order.features.as_ref())
+            .ok_or_else(|| {
+                eyre!(
+                    "Split vars are column indices, but no feature names are given to resolve them: pass --feature-names or --feature-order, or declare \"feature_names\" in the header"
+                )
+            })?;
+        if let Some(name) = names
+            .iter()
+            .enumerate()
+            .find_map(|(i, name)| names[..i].contains(name).then_some(name))
+        {
+            return Err(eyre!("The feature names list '{name}' twice"));
+        }
+
+        Ok(Self::Indices(names.clone()))
+    }
+
+    /// Replace the split var of `node`, read from `line`, by the name of
+    /// its column if split vars are indices.
+    ///
+    /// Fails if split vars mix names and indices, or if an index is not a
+    /// column of the feature names.
+    pub(crate) fn resolve<N: SerializedNode>(&mut self, node: &mut N, line: u64) -> Result<()> {
+        let Some(var) = node.split_var_mut() else {
+            return Ok(());
+        };
+        let index = var.trim().parse::<usize>().ok();
+
+        match (self, index) {
+            (Self::Indices(names), Some(index)) => {
+                *var = index
+                    .checked_sub(1)
+                    .and_then(|column| names.get(column))
+                    .ok_or_else(|| {
+                        eyre!(
+                            "Line {line} splits on column {index}, but there are {} feature names, from column 1",
+                            names.len()
+                        )
+                    })?
+                    .clone();
+            }
+            (Self::Indices(_), None) => {
+                return Err(eyre!(
+                    "Line {line} splits on '{var}', but split vars are column indices: names and indices cannot be mixed"
+                ));
+            }
+            (
+                Self::Names {
+                    first_index,
+                    first_name,
+                },
+                Some(index),
+            ) => {
+                if let Some((name_line, name)) = first_name {
+                    return Err(mixed(line, index, *name_line, name));
+                }
+                first_index.get_or_insert((line, index));
+            }
+            (
+                Self::Names {
+                    first_index,
+                    first_name,
+                },
+                None,
+            ) => {
+                if let Some((index_line, index)) = first_index {
+                    return Err(mixed(*index_line, *index, line, var));
+                }
+                first_name.get_or_insert_with(|| (line, var.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Warn once every row is read if all split vars are numbers but were
+    /// read as names.
+    pub(crate) fn finish(&self) {
+        if let Self::Names {
+            first_index: Some((line, index)),
+            first_name: None,
+        } = self
+        {
+            warn!(
+                "Every split var is a number, e.g. {index} on line {line}: if they are column indices, like R's getTree(..., labelVar = FALSE) writes them, pass --split-var-as-index"
+            );
+        }
+    }
+}
+
+/// Error of a definition whose line `index_line` splits on column `index`,
+/// and line `name_line` on feature `name`.
+fn mixed(index_line: u64, index: usize, name_line: u64, name: &str) -> color_eyre::Report {
+    eyre!(
+        "Line {index_line} splits on column {index}, but line {name_line} on '{name}': names and indices cannot be mixed"
+    )
+}
@@ -0,0 +1,308 @@
+//! Checks of a forest definition before it is flattened, see
+//! [`SerializedForest::validate`].
+//!
+//! The counts the header declares, if any, are checked against the nodes,
+//! then each row on its own, then each tree: its node indices, and whether
+//! the daughters of its branches are rows of the same tree. Rows which do
+//! not parse at all, e.g. with a split point or a regression prediction
+//! which is not a number, are rejected when reading, naming their line.
+
+use std::collections::{BTreeMap, btree_map::Entry};
+use std::fmt;
+
+use color_eyre::{Result, eyre::eyre};
+use tracing::{error, warn};
+
+use super::{HeaderCounts, SerializedForest, SerializedNode};
+
+/// How bad a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The row is converted, though likely not as intended
+    Warning,
+    /// The forest cannot be converted
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem of a forest definition, found by [`SerializedForest::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Tree index, as in the definition
+    pub tree_idx: Option<usize>,
+    /// Node index, as in the definition. None for problems of a whole tree
+    pub node_idx: Option<usize>,
+    /// Line of the node's row, counted from 1 like the definition file's
+    /// lines. None for problems of a whole tree, or of a definition which was
+    /// not read from CSV
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = [
+            self.line.map(|line| format!("line {line}")),
+            self.tree_idx.map(|tree| format!("tree {tree}")),
+            self.node_idx.map(|node| format!("node {node}")),
+        ];
+        let location = location.into_iter().flatten().collect::<Vec<_>>();
+
+        if location.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", location.join(", "), self.message)
+        }
+    }
+}
+
+/// Problems of one row, whatever its tree, given its columns.
+/// `branch_statuses` are the statuses a branch may have.
+pub(super) fn diagnose_row(
+    [left, right]: [u32; 2],
+    split_on: Option<&str>,
+    split_at: f32,
+    status: i8,
+    has_prediction: bool,
+    branch_statuses: &[i8],
+) -> Vec<(Severity, String)> {
+    let mut diagnostics = Vec::new();
+    let mut error = |message: String| diagnostics.push((Severity::Error, message));
+
+    if split_on.is_some() {
+        if !branch_statuses.contains(&status) {
+            error(format!("Branch has status {status}"));
+        }
+        if left == 0 || right == 0 {
+            error("Branch has no daughter on one side (0)".to_string());
+        }
+        if !split_at.is_finite() {
+            error(format!("Split point {split_at} is not a finite number"));
+        }
+        return diagnostics;
+    }
+
+    if branch_statuses.contains(&status) {
+        error(format!(
+            "Row has the status of a branch ({status}) but no split variable"
+        ));
+        return diagnostics;
+    }
+    if status != -1 {
+        error(format!(
+            "Status {status} is neither a branch's nor a leaf's (-1)"
+        ));
+    }
+    if !has_prediction {
+        error("Leaf has no prediction".to_string());
+    }
+    if left != 0 || right != 0 {
+        diagnostics.push((
+            Severity::Warning,
+            format!("Leaf has daughters {left} and {right}, which are ignored"),
+        ));
+    }
+
+    diagnostics
+}
+
+impl<N: SerializedNode> SerializedForest<N> {
+    /// Check this definition before it is flattened, see [`mod@self`].
+    ///
+    /// Diagnostics of the header come first, then those of rows, in row
+    /// order, then those of each tree in tree order. The forest converts if
+    /// none is a [`Severity::Error`], though node indices which skip some
+    /// numbers are only checked when converting, see
+    /// [`NodeHoles`](crate::forest::flatten::NodeHoles).
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let diagnostic = |row: usize, severity, message| Diagnostic {
+            severity,
+            tree_idx: Some(self.nodes[row].tree_idx()),
+            node_idx: Some(self.nodes[row].node_idx()),
+            line: self.lines.get(row).copied(),
+            message,
+        };
+
+        let mut diagnostics = self.diagnose_counts();
+        for (row, node) in self.nodes.iter().enumerate() {
+            for (severity, message) in node.diagnose() {
+                diagnostics.push(diagnostic(row, severity, message));
+            }
+        }
+
+        let mut trees = BTreeMap::<usize, Vec<usize>>::new();
+        for (row, node) in self.nodes.iter().enumerate() {
+            trees.entry(node.tree_idx()).or_default().push(row);
+        }
+
+        for (tree_idx, rows) in trees {
+            // Row of each node index
+            let mut indices = BTreeMap::new();
+            for row in rows {
+                match indices.entry(self.nodes[row].node_idx()) {
+                    Entry::Vacant(e) => {
+                        e.insert(row);
+                    }
+                    Entry::Occupied(e) => {
+                        let first = self
+                            .lines
+                            .get(*e.get())
+                            .map_or(String::new(), |line| format!(", first on line {line}"));
+                        diagnostics.push(diagnostic(
+                            row,
+                            Severity::Error,
+                            format!("Node {} is defined again{first}", e.key()),
+                        ));
+                    }
+                }
+            }
+
+            let has_root = indices.contains_key(&1);
+            if !has_root {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    tree_idx: Some(tree_idx),
+                    node_idx: None,
+                    line: None,
+                    message: format!("Tree {tree_idx} has no root, node 1"),
+                });
+            }
+
+            let mut parents = indices.keys().map(|&i| (i, 0)).collect::<BTreeMap<_, _>>();
+            for (&node_idx, &row) in &indices {
+                let Some(daughters) = self.nodes[row].daughters() else {
+                    continue;
+                };
+                for daughter in daughters.into_iter().filter(|&d| d != 0) {
+                    let daughter = daughter as usize;
+                    let Some(count) = parents.get_mut(&daughter) else {
+                        diagnostics.push(diagnostic(
+                            row,
+                            Severity::Error,
+                            format!("Daughter {daughter} is not a node of tree {tree_idx}"),
+                        ));
+                        continue;
+                    };
+                    *count += 1;
+                    if daughter <= node_idx {
+                        diagnostics.push(diagnostic(
+                            row,
+                            Severity::Error,
+                            format!("Daughter {daughter} is not further down the tree"),
+                        ));
+                    }
+                }
+            }
+
+            // Without a root, every node would be unreached
+            let reached = parents.into_iter().filter(|(i, _)| has_root && *i != 1);
+            for (node_idx, count) in reached {
+                let row = indices[&node_idx];
+                match count {
+                    0 => diagnostics.push(diagnostic(
+                        row,
+                        Severity::Warning,
+                        "Node is no branch's daughter, so is never reached".to_string(),
+                    )),
+                    1 => {}
+                    _ => diagnostics.push(diagnostic(
+                        row,
+                        Severity::Error,
+                        format!("Node is the daughter of {count} branches"),
+                    )),
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Counts declared by the header which do not match the nodes, see
+    /// [`diagnose_counts`].
+    fn diagnose_counts(&self) -> Vec<Diagnostic> {
+        diagnose_counts(
+            self.declared_counts(),
+            self.counts(),
+            self.allow_count_mismatch,
+        )
+    }
+
+    /// Log the diagnostics of [`Self::validate`], warnings as warnings and
+    /// errors as errors, and fail if there is any error.
+    pub fn check(&self) -> Result<()> {
+        report(&self.validate())
+    }
+}
+
+/// Counts `declared` by a header which do not match the `counts` of the
+/// nodes. A file with fewer trees than declared was likely truncated, while a
+/// feature or target may be missing only because no node uses it.
+pub(crate) fn diagnose_counts(
+    declared: HeaderCounts,
+    counts: HeaderCounts,
+    allow_count_mismatch: bool,
+) -> Vec<Diagnostic> {
+    let mismatch = if allow_count_mismatch {
+        Severity::Warning
+    } else {
+        Severity::Error
+    };
+
+    let counts = [
+        ("trees", declared.num_trees, counts.num_trees),
+        ("features", declared.num_features, counts.num_features),
+        ("targets", declared.num_targets, counts.num_targets),
+    ];
+    counts
+        .into_iter()
+        .filter_map(|(kind, declared, count)| {
+            let (declared, count) = (declared?, count?);
+            let severity = match kind {
+                "trees" => mismatch,
+                _ if count > declared => mismatch,
+                _ => Severity::Warning,
+            };
+
+            (declared != count).then(|| Diagnostic {
+                severity,
+                tree_idx: None,
+                node_idx: None,
+                line: Some(1),
+                message: format!("Header declares {declared} {kind}, file contains {count}"),
+            })
+        })
+        .collect()
+}
+
+/// Log `diagnostics`, warnings as warnings and errors as errors, and fail if
+/// there is any error.
+pub(crate) fn report(diagnostics: &[Diagnostic]) -> Result<()> {
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Warning => warn!("{diagnostic}"),
+            Severity::Error => error!("{diagnostic}"),
+        }
+    }
+
+    let mut errors = diagnostics.iter().filter(|d| d.severity == Severity::Error);
+    match errors.next() {
+        Some(first) => {
+            let count = errors.count() + 1;
+            let plural = if count == 1 { "" } else { "s" };
+            Err(eyre!(
+                "The forest definition has {count} error{plural}, the first: {first}"
+            ))
+        }
+        None => Ok(()),
+    }
+}
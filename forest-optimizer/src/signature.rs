@@ -0,0 +1,59 @@
+//! Ed25519 signatures of serialized forests with `forest-optimizer sign`,
+//! which devices verify with
+//! [`verify_signature`](embedded_rforest::forest::signature::verify_signature),
+//! see [`signature`](embedded_rforest::forest::signature) for the format.
+//!
+//! Private keys are read from PEM files, as written by
+//! `openssl genpkey -algorithm ed25519`. The signature covers the forest as
+//! it will be deserialized, encrypted or not, and is written either to a
+//! file of its own or appended to the forest.
+
+use std::{fs, path::Path};
+
+use aligned_vec::AVec;
+use color_eyre::{
+    Result,
+    eyre::{Context, eyre},
+};
+use ed25519_compact::KeyPair;
+use embedded_rforest::forest::{
+    deserialize::{BUFFER_ALIGN, ForestHeader},
+    signature::{PUBLIC_KEY_LEN, SIGNATURE_LEN, SignatureTrailer, split_signature},
+};
+use zerocopy::IntoBytes;
+
+/// Read the Ed25519 private key of a PEM file.
+pub fn read_signing_key(path: impl AsRef<Path>) -> Result<KeyPair> {
+    let path = path.as_ref();
+    let pem = fs::read_to_string(path)
+        .with_context(|| format!("Could not read private key {}", path.display()))?;
+    KeyPair::from_pem(&pem)
+        .map_err(|e| eyre!("{} is not an Ed25519 private key: {e}", path.display()))
+}
+
+/// Public key of `key`, which devices verify signatures with.
+pub fn public_key(key: &KeyPair) -> [u8; PUBLIC_KEY_LEN] {
+    *key.pk
+}
+
+/// Signature of the serialized forest in `buffer` with `key`.
+///
+/// Refuses buffers which are not a forest, or are already signed.
+pub fn sign(buffer: &[u8], key: &KeyPair) -> Result<[u8; SIGNATURE_LEN]> {
+    if split_signature(buffer).is_ok() {
+        return Err(eyre!("The forest is already signed"));
+    }
+    ForestHeader::peek(buffer).map_err(|e| eyre!("Malformed forest header: {e:?}"))?;
+
+    Ok(*key.sk.sign(buffer, None))
+}
+
+/// Copy of the serialized forest in `buffer` followed by its `signature`,
+/// in a [`SignatureTrailer`].
+pub fn embed_signature(buffer: &[u8], signature: [u8; SIGNATURE_LEN]) -> AVec<u8> {
+    let trailer = SignatureTrailer::new(signature);
+    let mut signed = AVec::with_capacity(BUFFER_ALIGN, buffer.len() + size_of_val(&trailer));
+    signed.extend_from_slice(buffer);
+    signed.extend_from_slice(trailer.as_bytes());
+    signed
+}
@@ -0,0 +1,253 @@
+//! Random valid forests, and `proptest` strategies generating them, for
+//! property tests. Behind the `test-support` feature.
+//!
+//! Forests are built node by node rather than read from a definition file,
+//! so they reach shapes the fixtures do not: trees of a single leaf or
+//! branch, deep trees, tied votes. [`serialize_with_pathology`] breaks their serialized
+//! form on purpose, for tests of the validation.
+
+use aligned_vec::AVec;
+use color_eyre::{Result, eyre::Context};
+use embedded_rforest::{
+    forest::deserialize::{ForestHeader, RawHeader},
+    ptr::{NodeIndex, U32},
+};
+use proptest::{
+    collection::vec,
+    prelude::{Rng, RngExt, Strategy},
+};
+use zerocopy::IntoBytes;
+
+use crate::{
+    forest::{BranchNode, Forest, LeafNode, Node},
+    problem_type::{Classification, FeatureIdx, ProblemType, Regression},
+    write_forest::WriteForest,
+};
+
+/// Shape of the forests made by [`random_forest`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForestShape {
+    pub num_trees: usize,
+    /// Number of branches on the longest path from a root to a leaf. 1 for
+    /// trees of a single branch
+    pub max_depth: usize,
+    /// Chance that a child of a branch is a branch itself, rather than a
+    /// leaf, until `max_depth`
+    pub branch_probability: f64,
+    /// Chance that a tree is a single leaf
+    pub leaf_root_probability: f64,
+    pub num_features: usize,
+    /// Classes of classification forests, ignored for regression
+    pub num_classes: usize,
+}
+
+impl Default for ForestShape {
+    fn default() -> Self {
+        Self {
+            num_trees: 5,
+            max_depth: 6,
+            branch_probability: 0.6,
+            leaf_root_probability: 0.0,
+            num_features: 4,
+            num_classes: 3,
+        }
+    }
+}
+
+/// Problem types whose forests [`random_forest`] can generate.
+pub trait RandomForest: WriteForest {
+    /// A problem naming the features `x0`, `x1`, ... and, for
+    /// classification, the classes `c0`, `c1`, ...
+    fn random_problem(shape: &ForestShape) -> Self;
+
+    fn random_prediction<R: Rng + ?Sized>(shape: &ForestShape, rng: &mut R) -> Self::Output;
+}
+
+impl RandomForest for Classification {
+    fn random_problem(shape: &ForestShape) -> Self {
+        let mut problem = Self::default();
+        name_features(&mut problem, shape);
+        for class in 0..shape.num_classes {
+            problem.targets_mut().insert_next(&format!("c{class}"));
+        }
+        problem
+    }
+
+    fn random_prediction<R: Rng + ?Sized>(shape: &ForestShape, rng: &mut R) -> u32 {
+        rng.random_range(0..shape.num_classes as u32)
+    }
+}
+
+impl RandomForest for Regression {
+    fn random_problem(shape: &ForestShape) -> Self {
+        let mut problem = Self::default();
+        name_features(&mut problem, shape);
+        problem
+    }
+
+    /// Multiples of 0.25, so that trees share some of their leaf values
+    fn random_prediction<R: Rng + ?Sized>(_: &ForestShape, rng: &mut R) -> f32 {
+        rng.random_range(-16..16) as f32 * 0.25
+    }
+}
+
+fn name_features<P: ProblemType>(problem: &mut P, shape: &ForestShape) {
+    for feature in 0..shape.num_features {
+        problem.features_mut().insert_next(&format!("x{feature}"));
+    }
+}
+
+/// A random forest of the given shape.
+///
+/// Thresholds are multiples of 1/16 in `[0, 1)`, like the rows of [`rows`],
+/// so that some features fall exactly on a threshold.
+pub fn random_forest<P: RandomForest, R: Rng + ?Sized>(
+    shape: &ForestShape,
+    rng: &mut R,
+) -> Forest<P> {
+    assert!(shape.num_trees > 0 && shape.max_depth > 0 && shape.num_features > 0);
+
+    let trees = (0..shape.num_trees)
+        .map(|_| {
+            let mut nodes = Vec::new();
+            grow::<P, R>(&mut nodes, 0, shape, rng);
+            nodes
+        })
+        .collect();
+
+    Forest::from_trees(trees, P::random_problem(shape))
+}
+
+/// Push a random subtree, root first, and return the index of its root.
+fn grow<P: RandomForest, R: Rng + ?Sized>(
+    nodes: &mut Vec<Node<P>>,
+    depth: usize,
+    shape: &ForestShape,
+    rng: &mut R,
+) -> u32 {
+    let index = nodes.len() as u32;
+    let is_branch = if depth == 0 {
+        !rng.random_bool(shape.leaf_root_probability)
+    } else {
+        depth < shape.max_depth && rng.random_bool(shape.branch_probability)
+    };
+    if !is_branch {
+        nodes.push(Node::Leaf(LeafNode::new(
+            P::random_prediction(shape, rng).into(),
+        )));
+        return index;
+    }
+
+    nodes.push(Node::Branch(BranchNode::new(
+        FeatureIdx(rng.random_range(0..shape.num_features as u32)),
+        rng.random_range(0..16) as f32 / 16.0,
+        0,
+        0,
+    )));
+    let left = grow(nodes, depth + 1, shape, rng);
+    let right = grow(nodes, depth + 1, shape, rng);
+    if let Node::Branch(branch) = &mut nodes[index as usize] {
+        branch.left = left;
+        branch.right = right;
+    }
+
+    index
+}
+
+/// Shapes of small forests, from single-leaf trees to full trees of depth
+/// 8.
+pub fn shapes() -> impl Strategy<Value = ForestShape> {
+    (
+        1..=12usize,
+        1..=8usize,
+        0.0..=1.0f64,
+        0.0..=0.3f64,
+        1..=6usize,
+        1..=4usize,
+    )
+        .prop_map(
+            |(
+                num_trees,
+                max_depth,
+                branch_probability,
+                leaf_root_probability,
+                num_features,
+                num_classes,
+            )| ForestShape {
+                num_trees,
+                max_depth,
+                branch_probability,
+                leaf_root_probability,
+                num_features,
+                num_classes,
+            },
+        )
+}
+
+/// Random forests of the shapes generated by `shapes`.
+pub fn forests<P: RandomForest>(
+    shapes: impl Strategy<Value = ForestShape>,
+) -> impl Strategy<Value = Forest<P>> {
+    shapes.prop_perturb(|shape, mut rng| random_forest(&shape, &mut rng))
+}
+
+/// Up to `count` rows of `num_features` features, multiples of 1/16 in
+/// `[0, 1]`.
+pub fn rows(num_features: usize, count: usize) -> impl Strategy<Value = Vec<Vec<f32>>> {
+    let feature = (0..=16u8).prop_map(|k| f32::from(k) / 16.0);
+    vec(vec(feature, num_features), 1..=count)
+}
+
+/// A way to break a serialized forest, which the validation of
+/// [`OptimizedForest::deserialize`](embedded_rforest::forest::OptimizedForest::deserialize)
+/// rejects with [`Error::MalformedForest`](embedded_rforest::Error::MalformedForest).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pathology {
+    /// The left child of the first root points at a branch past its tree
+    BranchOutOfTree,
+    /// The left child of the first root points at a class the forest does
+    /// not predict, or past the leaf table
+    LeafOutOfRange,
+    /// The last byte of the forest is missing
+    Truncated,
+    /// The header sets a flag this version does not know
+    UnknownFlag,
+}
+
+impl Pathology {
+    pub const ALL: [Self; 4] = [
+        Self::BranchOutOfTree,
+        Self::LeafOutOfRange,
+        Self::Truncated,
+        Self::UnknownFlag,
+    ];
+}
+
+/// Serialize `forest` with standard branches, then break it with
+/// `pathology`.
+pub fn serialize_with_pathology<P: WriteForest>(
+    forest: &Forest<P>,
+    pathology: Pathology,
+) -> Result<AVec<u8>> {
+    let mut bytes = <P as WriteForest>::serialize(forest)?;
+    let header = ForestHeader::peek(&bytes).context("Malformed forest")?;
+    let left = header.header_len..header.header_len + size_of::<U32>();
+
+    match pathology {
+        Pathology::BranchOutOfTree => {
+            let first = forest.trees().next().map_or(0, |tree| tree.num_branches());
+            bytes[left].copy_from_slice(U32::new(first as u32).as_bytes());
+        }
+        Pathology::LeafOutOfRange => {
+            let past = match header.num_targets {
+                Some(targets) => u32::from(targets.get()),
+                None => header.num_leaves as u32,
+            };
+            bytes[left].copy_from_slice(U32::new(U32::LEAF_TAG | past).as_bytes());
+        }
+        Pathology::Truncated => bytes.truncate(bytes.len() - 1),
+        Pathology::UnknownFlag => bytes[core::mem::offset_of!(RawHeader, flags)] |= 0x80,
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,261 @@
+//! Expected-output fixtures for the unit tests of firmware embedding a
+//! forest: a sample of dataset rows, and what the optimized forest predicts
+//! for each of them.
+
+use std::fmt::{self, Write as _};
+
+use color_eyre::Result;
+use embedded_rforest::forest::{Predict, ProblemType};
+
+use crate::metadata::ForestMetadata;
+
+/// Output of an optimized forest which can be written as a Rust literal.
+pub trait VectorOutput: Copy + fmt::Debug + serde::Serialize {
+    /// Rust type of the output
+    const RUST_TYPE: &'static str;
+
+    /// Rust expression evaluating to this output, exactly
+    fn rust_literal(&self) -> String;
+
+    /// Class index of the output, to stratify samples by. `None` for
+    /// regression outputs.
+    fn class(&self) -> Option<u32>;
+}
+
+impl VectorOutput for u32 {
+    const RUST_TYPE: &'static str = "u32";
+
+    fn rust_literal(&self) -> String {
+        self.to_string()
+    }
+
+    fn class(&self) -> Option<u32> {
+        Some(*self)
+    }
+}
+
+impl VectorOutput for f32 {
+    const RUST_TYPE: &'static str = "f32";
+
+    fn rust_literal(&self) -> String {
+        f32_literal(*self)
+    }
+
+    fn class(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Rust expression for a float. Debug formatting is the shortest
+/// representation which parses back to the same value.
+fn f32_literal(value: f32) -> String {
+    if value.is_nan() {
+        "f32::NAN".to_string()
+    } else if value.is_infinite() {
+        format!("{}f32::INFINITY", if value < 0.0 { "-" } else { "" })
+    } else {
+        format!("{value:?}")
+    }
+}
+
+type Output<F> = <<F as Predict>::ProblemType as ProblemType>::Output;
+
+/// A sample of dataset rows, along with the prediction of a forest for each
+/// of them.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct TestVectors<O> {
+    pub num_features: usize,
+    /// Number of classes, 0 for regression
+    pub num_targets: usize,
+    /// Feature names, positioned by feature index
+    pub features: Vec<String>,
+    /// Class names, positioned by class index. Only present for
+    /// classification.
+    pub targets: Option<Vec<String>>,
+    pub vectors: Vec<TestVector<O>>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct TestVector<O> {
+    /// Feature values, positioned by feature index
+    pub features: Vec<f32>,
+    pub expected: O,
+}
+
+/// Sample `n` of `rows` and predict them with `forest`.
+///
+/// Classification rows are sampled evenly across the predicted classes, so
+/// every class is covered when `n` allows it. The sample only depends on the
+/// rows, `n` and `seed`.
+pub fn sample<F>(
+    forest: &F,
+    metadata: &ForestMetadata,
+    rows: &[Vec<f32>],
+    n: usize,
+    seed: u64,
+) -> TestVectors<Output<F>>
+where
+    F: Predict,
+    Output<F>: VectorOutput,
+{
+    let outputs = rows
+        .iter()
+        .map(|row| forest.predict(row))
+        .collect::<Vec<_>>();
+
+    // Shuffle the rows of each class, then take one row per class in turn
+    let mut strata = Vec::<(Option<u32>, Vec<usize>)>::new();
+    for (index, output) in outputs.iter().enumerate() {
+        let class = output.class();
+        match strata.iter_mut().find(|(c, _)| *c == class) {
+            Some((_, rows)) => rows.push(index),
+            None => strata.push((class, vec![index])),
+        }
+    }
+    strata.sort_by_key(|(class, _)| *class);
+
+    let mut rng = SplitMix64(seed);
+    for (_, rows) in &mut strata {
+        rng.shuffle(rows);
+    }
+
+    let mut selected = Vec::with_capacity(n.min(rows.len()));
+    let mut round = 0;
+    while selected.len() < n.min(rows.len()) {
+        for (_, rows) in &strata {
+            if let Some(&index) = rows.get(round)
+                && selected.len() < n
+            {
+                selected.push(index);
+            }
+        }
+        round += 1;
+    }
+    selected.sort_unstable();
+
+    TestVectors {
+        num_features: metadata.features.len(),
+        num_targets: metadata.targets.as_ref().map_or(0, Vec::len),
+        features: metadata.features.clone(),
+        targets: metadata.targets.clone(),
+        vectors: selected
+            .into_iter()
+            .map(|index| TestVector {
+                features: rows[index].clone(),
+                expected: outputs[index],
+            })
+            .collect(),
+    }
+}
+
+/// Format of exported test vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum VectorFormat {
+    /// Rust module with a `TEST_VECTORS` constant
+    Rust,
+    /// One row per vector: the features by name, then `expected`
+    Csv,
+    Json,
+}
+
+impl<O: VectorOutput> TestVectors<O> {
+    pub fn write(&self, format: VectorFormat, out: &mut impl std::io::Write) -> Result<()> {
+        match format {
+            VectorFormat::Rust => out.write_all(self.to_rust()?.as_bytes())?,
+            VectorFormat::Csv => {
+                let mut wtr = csv::Writer::from_writer(out);
+                wtr.write_record(self.features.iter().map(String::as_str).chain(["expected"]))?;
+                for vector in &self.vectors {
+                    wtr.write_record(
+                        vector
+                            .features
+                            .iter()
+                            .map(|f| format!("{f:?}"))
+                            .chain([format!("{:?}", vector.expected)]),
+                    )?;
+                }
+                wtr.flush()?;
+            }
+            VectorFormat::Json => serde_json::to_writer_pretty(out, self)?,
+        }
+
+        Ok(())
+    }
+
+    fn to_rust(&self) -> Result<String> {
+        let mut out = String::new();
+        // Plain comments, so the file can be `include!`d as well as used as
+        // a module
+        writeln!(
+            out,
+            "// Generated by forest-optimizer export-test-vectors. Do not edit."
+        )?;
+        writeln!(out, "//")?;
+        writeln!(out, "// Features: {}", self.features.join(", "))?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "pub const NUM_FEATURES: usize = {};",
+            self.num_features
+        )?;
+        writeln!(out, "pub const NUM_TARGETS: usize = {};", self.num_targets)?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "pub const TEST_VECTORS: [([f32; NUM_FEATURES], {}); {}] = [",
+            O::RUST_TYPE,
+            self.vectors.len()
+        )?;
+        for vector in &self.vectors {
+            let features = vector
+                .features
+                .iter()
+                .map(|&f| f32_literal(f))
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(
+                out,
+                "    ([{features}], {}),",
+                vector.expected.rust_literal()
+            )?;
+
+            let class = vector
+                .expected
+                .class()
+                .and_then(|class| self.targets.as_ref()?.get(class as usize));
+            match class {
+                Some(name) => writeln!(out, " // {name}")?,
+                None => writeln!(out)?,
+            }
+        }
+        writeln!(out, "];")?;
+
+        Ok(out)
+    }
+}
+
+/// Small deterministic generator, so samples do not change with the version
+/// of a dependency
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..bound`
+    fn below(&mut self, bound: usize) -> usize {
+        ((u128::from(self.next_u64()) * bound as u128) >> 64) as usize
+    }
+
+    /// Fisher-Yates shuffle
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
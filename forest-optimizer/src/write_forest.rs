@@ -1,66 +1,860 @@
+use aligned_vec::AVec;
 use color_eyre::{
-    eyre::{eyre, Context},
     Result,
+    eyre::{Context, eyre},
 };
 
-use std::{fs::File, io::Write, path::Path};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
-use embedded_rforest::forest::{Classification, OptimizedForest, Regression};
+use embedded_rforest::{
+    forest::{
+        self as embedded, Aggregation, AnyOptimizedForest, BranchLayout, OptimizedForest, Platt,
+        Transform, endian::ByteOrder,
+    },
+    ptr::{F32, NodeIndex, NodePointer, RelativeU16, U16, U32},
+};
 
 use crate::{
-    forest::Forest,
-    serialized_forest::{SerializedClassificationNode, SerializedForest, SerializedRegressionNode},
+    calibration::Calibration,
+    conversion::{ToOptimized, support_table, verify_optimization},
+    dataset::read_mapped_rows,
+    emit::{OutputFormat, emit_all},
+    encryption::EncryptionKey,
+    forest::{Forest, profile::TraversalProfile, stream::STREAMING_THRESHOLD},
+    metadata::ForestMetadata,
+    problem_type::{Classification, PredictionType, ProblemType, Regression},
+    serialized_forest::{
+        IndexOrder, ReadOptions, SerializedClassificationNode, SerializedForest, SerializedNode,
+        SerializedRegressionNode, check_problem_type, parse_problem_type, resolve_problem_type,
+    },
 };
 
-pub fn write_classification(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
-    // Read the input file
-    let serialized = SerializedForest::<SerializedClassificationNode>::read(input)
-        .context("Could not read forest definition file (CSV).")?;
-    let forest = Forest::from_serialized(serialized)?;
+/// Evaluate `$body` with `$forest` bound to the inner forest of an
+/// [`AnyOptimizedForest`], whatever its layout.
+macro_rules! dispatch {
+    ($any:expr, $forest:ident => $body:expr) => {
+        match $any {
+            AnyOptimizedForest::Standard($forest) => $body,
+            AnyOptimizedForest::Narrow($forest) => $body,
+            AnyOptimizedForest::Relative($forest) => $body,
+            AnyOptimizedForest::Compact($forest) => $body,
+            AnyOptimizedForest::Integer($forest) => $body,
+        }
+    };
+}
 
-    // Optimize the forest
-    let nodes = forest.optimize_nodes();
-    let optimized = OptimizedForest::<Classification>::new(
-        forest.num_trees().try_into().unwrap(),
-        &nodes,
-        forest.num_features().try_into().unwrap(),
-        Classification::new(forest.num_targets().try_into().unwrap()).unwrap(),
-    )
-    .map_err(|_| eyre!("Malformed forest"))?;
-
-    let serialized = optimized.to_bytes();
-    let ptr = serialized.as_ptr();
-    assert!(ptr as usize % align_of_val(&optimized) == 0);
-
-    // Write the transformed data to the output file
-    let mut output_file = File::create(output).context("Could not create output file")?;
-    output_file.write_all(&serialized)?;
+/// Problem types whose forests can be optimized and serialized.
+pub trait WriteForest: ToOptimized {
+    /// Optimize the nodes of a forest, see [`Forest::optimize_nodes`].
+    fn optimize(forest: &Forest<Self>) -> (Vec<embedded::Branch>, Vec<F32>);
+
+    /// Optimize a forest and serialize it into the `.rforest` format.
+    fn serialize(forest: &Forest<Self>) -> Result<AVec<u8>> {
+        Self::serialize_with(forest, PointerWidth::U32)
+    }
+
+    /// [`WriteForest::serialize`] with child pointers of the given width.
+    fn serialize_with(forest: &Forest<Self>, width: PointerWidth) -> Result<AVec<u8>> {
+        Self::serialize_ordered(forest, width, &[])
+    }
+
+    /// [`WriteForest::serialize_with`], storing the right child of the
+    /// branches flagged in `hot_right` first, see [`group_trees`]. Branches
+    /// are flagged in the order of [`WriteForest::optimize`], and those past
+    /// the end of `hot_right` store their left child first.
+    fn serialize_ordered(
+        forest: &Forest<Self>,
+        width: PointerWidth,
+        hot_right: &[bool],
+    ) -> Result<AVec<u8>>;
+
+    /// [`WriteForest::serialize_with`] the pointer width of `encoding`,
+    /// laying out its branches in the order of its layout, transforming the
+    /// output of the forest with its transform, or calibrating its class
+    /// probabilities with its calibration and taking the median of its votes
+    /// if ordinal, and recording the number of training samples of its
+    /// leaves if asked to. The byte order is left to the emitter.
+    fn serialize_encoded(forest: &Forest<Self>, encoding: &NodeEncoding) -> Result<AVec<u8>>;
+
+    /// Names of the features (and targets) of a forest.
+    fn metadata(forest: &Forest<Self>) -> ForestMetadata;
+}
+
+impl WriteForest for Classification {
+    fn optimize(forest: &Forest<Self>) -> (Vec<embedded::Branch>, Vec<F32>) {
+        forest.optimize_nodes()
+    }
+
+    /// Class indices fit 16-bit pointers as well as branch indices do.
+    fn serialize_ordered(
+        forest: &Forest<Self>,
+        width: PointerWidth,
+        hot_right: &[bool],
+    ) -> Result<AVec<u8>> {
+        serialize_classification(forest, width, hot_right, &Sections::default())
+    }
+
+    /// Classification forests vote, so have no output to transform.
+    fn serialize_encoded(forest: &Forest<Self>, encoding: &NodeEncoding) -> Result<AVec<u8>> {
+        if encoding.transform != Transform::Identity {
+            return Err(eyre!(
+                "Only regression forests transform their output, not classification ones"
+            ));
+        }
+        let hot_right = encoding.layout.hot_right(forest)?;
+        let calibration = match &encoding.calibration {
+            Some(calibration) => calibration.for_forest(forest)?,
+            None => Vec::new(),
+        };
+        let sections = Sections {
+            calibration: &calibration,
+            ordinal: encoding.ordinal,
+            support: encoding.support,
+            ..Sections::default()
+        };
+        serialize_classification(forest, encoding.width, &hot_right, &sections)
+    }
+
+    fn metadata(forest: &Forest<Self>) -> ForestMetadata {
+        ForestMetadata::new(
+            PredictionType::Classification,
+            &forest.features_by_index(),
+            Some(&forest.targets_by_index()),
+        )
+    }
+}
+
+/// [`WriteForest::serialize_ordered`] a classification forest, with the
+/// `sections` of [`WriteForest::serialize_encoded`].
+fn serialize_classification(
+    forest: &Forest<Classification>,
+    width: PointerWidth,
+    hot_right: &[bool],
+    sections: &Sections,
+) -> Result<AVec<u8>> {
+    let (nodes, _) = Classification::optimize(forest);
+    let standard = || {
+        let (nodes, tree_offsets) = group_trees(&nodes, forest.num_trees(), hot_right)?;
+        let optimized = OptimizedForest::<embedded::Classification>::new(
+            num_trees(forest)?,
+            &nodes,
+            num_features(forest)?,
+            classification_problem(forest)?,
+        )
+        .and_then(|optimized| optimized.with_tree_offsets(&tree_offsets))
+        .context("Malformed forest")?;
+
+        let align = align_of_val(&optimized);
+        let serialized = encode_classification(forest, optimized, sections)?;
+        assert!((serialized.as_ptr() as usize).is_multiple_of(align));
+
+        Ok(serialized)
+    };
+    let narrow = || {
+        let (nodes, tree_offsets) = group_trees(&nodes, forest.num_trees(), hot_right)?;
+        let nodes = narrow_branches(&nodes)?;
+        OptimizedForest::<embedded::Classification, embedded::Branch<U16>>::new(
+            num_trees(forest)?,
+            &nodes,
+            num_features(forest)?,
+            classification_problem(forest)?,
+        )
+        .and_then(|optimized| optimized.with_tree_offsets(&tree_offsets))
+        .context("Malformed forest")
+        .and_then(|optimized| encode_classification(forest, optimized, sections))
+    };
+    let relative = || {
+        let (nodes, tree_offsets) = relative_branches(&nodes, forest.num_trees(), hot_right)?;
+        OptimizedForest::<embedded::Classification, _>::new_relative(
+            &nodes,
+            &tree_offsets,
+            num_features(forest)?,
+            classification_problem(forest)?,
+        )
+        .context("Malformed forest")
+        .and_then(|optimized| encode_classification(forest, optimized, sections))
+    };
+
+    match width {
+        PointerWidth::U32 => standard(),
+        PointerWidth::U16 => narrow(),
+        PointerWidth::Relative => relative(),
+        PointerWidth::Auto => narrow().or_else(|_| relative()).or_else(|_| standard()),
+    }
+}
+
+/// Bytes of the classification forest `optimized`, made from `forest`,
+/// with its `sections`.
+fn encode_classification<B: BranchLayout>(
+    forest: &Forest<Classification>,
+    optimized: OptimizedForest<'_, embedded::Classification, B>,
+    sections: &Sections,
+) -> Result<AVec<u8>> {
+    let aggregation = if sections.ordinal {
+        Aggregation::OrdinalMedian
+    } else {
+        Aggregation::MajorityVote
+    };
+    optimized
+        .with_fingerprint(fingerprint(forest))
+        .with_split_rule(forest.split_rule())
+        .with_aggregation(aggregation)
+        .and_then(|optimized| optimized.with_calibration(sections.calibration))
+        .context("Could not encode the forest")
+        .and_then(|optimized| to_bytes_with_support(forest, optimized, sections.support))
+}
+
+impl WriteForest for Regression {
+    fn optimize(forest: &Forest<Self>) -> (Vec<embedded::Branch>, Vec<F32>) {
+        forest.optimize_nodes()
+    }
+
+    /// Leaf pointers hold indices into the leaf table, which fit 16-bit
+    /// pointers as long as the forest has at most 32768 distinct leaf values.
+    fn serialize_ordered(
+        forest: &Forest<Self>,
+        width: PointerWidth,
+        hot_right: &[bool],
+    ) -> Result<AVec<u8>> {
+        serialize_regression(forest, width, hot_right, &Sections::default())
+    }
+
+    fn serialize_encoded(forest: &Forest<Self>, encoding: &NodeEncoding) -> Result<AVec<u8>> {
+        if encoding.calibration.is_some() {
+            return Err(eyre!(
+                "Only classification forests calibrate their probabilities, not regression ones"
+            ));
+        }
+        if encoding.ordinal {
+            return Err(eyre!(
+                "Only classification forests have ordered classes, not regression ones"
+            ));
+        }
+        let hot_right = encoding.layout.hot_right(forest)?;
+        let sections = Sections {
+            transform: encoding.transform,
+            support: encoding.support,
+            ..Sections::default()
+        };
+        serialize_regression(forest, encoding.width, &hot_right, &sections)
+    }
+
+    fn metadata(forest: &Forest<Self>) -> ForestMetadata {
+        ForestMetadata::new(
+            PredictionType::Regression,
+            &forest.features_by_index(),
+            None,
+        )
+    }
+}
+
+/// [`WriteForest::serialize_ordered`] a regression forest, with the
+/// `sections` of [`WriteForest::serialize_encoded`].
+fn serialize_regression(
+    forest: &Forest<Regression>,
+    width: PointerWidth,
+    hot_right: &[bool],
+    sections: &Sections,
+) -> Result<AVec<u8>> {
+    let (nodes, leaves) = Regression::optimize(forest);
+    let standard = || {
+        let (nodes, tree_offsets) = group_trees(&nodes, forest.num_trees(), hot_right)?;
+        let optimized = OptimizedForest::<embedded::Regression>::with_leaves(
+            num_trees(forest)?,
+            &nodes,
+            num_features(forest)?,
+            &leaves,
+        )
+        .and_then(|optimized| optimized.with_tree_offsets(&tree_offsets))
+        .context("Malformed forest")?;
+
+        let align = align_of_val(&optimized);
+        let serialized = encode_regression(forest, optimized, sections)?;
+        assert!((serialized.as_ptr() as usize).is_multiple_of(align));
+
+        Ok(serialized)
+    };
+    let narrow = || {
+        let (nodes, tree_offsets) = group_trees(&nodes, forest.num_trees(), hot_right)?;
+        let nodes = narrow_branches(&nodes)?;
+        OptimizedForest::<embedded::Regression, embedded::Branch<U16>>::with_leaves(
+            num_trees(forest)?,
+            &nodes,
+            num_features(forest)?,
+            &leaves,
+        )
+        .and_then(|optimized| optimized.with_tree_offsets(&tree_offsets))
+        .context("Malformed forest")
+        .and_then(|optimized| encode_regression(forest, optimized, sections))
+    };
+    let relative = || {
+        let (nodes, tree_offsets) = relative_branches(&nodes, forest.num_trees(), hot_right)?;
+        OptimizedForest::<embedded::Regression, _>::new_relative(
+            &nodes,
+            &tree_offsets,
+            num_features(forest)?,
+            &leaves,
+        )
+        .context("Malformed forest")
+        .and_then(|optimized| encode_regression(forest, optimized, sections))
+    };
+
+    match width {
+        PointerWidth::U32 => standard(),
+        PointerWidth::U16 => narrow(),
+        PointerWidth::Relative => relative(),
+        PointerWidth::Auto => narrow().or_else(|_| relative()).or_else(|_| standard()),
+    }
+}
+
+/// Bytes of the regression forest `optimized`, made from `forest`, with its
+/// `sections`.
+fn encode_regression<B: BranchLayout>(
+    forest: &Forest<Regression>,
+    optimized: OptimizedForest<'_, embedded::Regression, B>,
+    sections: &Sections,
+) -> Result<AVec<u8>> {
+    optimized
+        .with_fingerprint(fingerprint(forest))
+        .with_split_rule(forest.split_rule())
+        .with_transform(sections.transform)
+        .context("Could not transform the output of the forest")
+        .and_then(|optimized| to_bytes_with_support(forest, optimized, sections.support))
+}
+
+/// Width of the child pointers of the branches of a serialized forest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PointerWidth {
+    /// 16-byte branches, for any forest
+    #[default]
+    #[value(name = "32")]
+    U32,
+    /// 12-byte branches, for forests of at most 32768 branches, classes and
+    /// distinct regression leaves
+    #[value(name = "16")]
+    U16,
+    /// 12-byte branches whose 16-bit pointers are relative to their tree,
+    /// for forests whose trees have at most 32768 branches each. The header
+    /// grows by 4 bytes per tree
+    Relative,
+    /// 16 bits if the forest fits them, relative 16 bits if its trees do,
+    /// 32 otherwise
+    Auto,
+}
+
+/// Byte order of the nodes of a serialized forest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Endianness {
+    /// Read by default on every target
+    #[default]
+    Little,
+    /// Read without swapping by big-endian targets built with the
+    /// `native-endian` feature of `embedded-rforest`
+    Big,
+}
+
+impl From<Endianness> for ByteOrder {
+    fn from(endianness: Endianness) -> Self {
+        match endianness {
+            Endianness::Little => Self::Little,
+            Endianness::Big => Self::Big,
+        }
+    }
+}
+
+/// How the branches of a serialized forest are encoded, and how its output
+/// is combined, transformed or calibrated
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NodeEncoding {
+    pub width: PointerWidth,
+    pub endianness: Endianness,
+    /// Regression only, see [`Transform`]
+    pub transform: Transform,
+    /// Classification only, see [`Calibration`]
+    pub calibration: Option<Calibration>,
+    /// Whether the classes are ordered, and the votes combined by their
+    /// median, see [`Aggregation::OrdinalMedian`]. Classification only
+    pub ordinal: bool,
+    /// Whether to record the number of training samples of every leaf, see
+    /// [`support_table`]
+    pub support: bool,
+    /// Order of the branches of every tree
+    pub layout: NodeOrder,
+    /// Key to encrypt everything following the header with, see
+    /// [`encryption`](crate::encryption)
+    pub encryption: Option<EncryptionKey>,
+}
+
+impl From<PointerWidth> for NodeEncoding {
+    fn from(width: PointerWidth) -> Self {
+        Self {
+            width,
+            ..Self::default()
+        }
+    }
+}
+
+/// Order of the branches of every tree of a serialized forest, which are
+/// always stored root first and depth first
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum NodeOrder {
+    /// Every branch followed by its left subtree
+    #[default]
+    DepthFirst,
+    /// Every branch followed by the subtree most rows of a profile go to,
+    /// so that the common paths are read sequentially, see
+    /// [`TraversalProfile`]
+    Profiled(ProfileSource),
+}
+
+/// Where the [`TraversalProfile`] of [`NodeOrder::Profiled`] comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileSource {
+    /// A dataset (CSV) with a column per feature, profiled once the forest
+    /// is read, and saved to `save` if given
+    Data {
+        path: PathBuf,
+        save: Option<PathBuf>,
+    },
+    /// A profile of the same forest definition, saved earlier
+    Saved(TraversalProfile),
+}
+
+impl NodeOrder {
+    /// Whether to store the right child of every branch of `forest` first,
+    /// see [`WriteForest::serialize_ordered`].
+    pub fn hot_right<P: WriteForest>(&self, forest: &Forest<P>) -> Result<Vec<bool>> {
+        match self {
+            NodeOrder::DepthFirst => Ok(Vec::new()),
+            NodeOrder::Profiled(ProfileSource::Saved(profile)) => profile.hot_right(forest),
+            NodeOrder::Profiled(ProfileSource::Data { path, save }) => {
+                let rows = read_mapped_rows(path, &forest.features_by_index())?;
+                let profile = forest.profile(&rows);
+                if let Some(save) = save {
+                    profile.write(save)?;
+                }
+                profile.hot_right(forest)
+            }
+        }
+    }
+}
+
+/// What [`WriteForest::serialize_encoded`] records in a serialized forest
+/// besides its branches and leaves, set on the optimized forest before it
+/// is serialized.
+#[derive(Default)]
+struct Sections<'a> {
+    /// Transform of the output of a regression forest
+    transform: Transform,
+    /// Platt parameters of every class, see [`Calibration`]
+    calibration: &'a [Platt],
+    /// Whether to combine the votes by their median
+    ordinal: bool,
+    /// Whether to record the number of training samples of every leaf
+    support: bool,
+}
+
+/// Bytes of `optimized`, made from `forest`, with the number of training
+/// samples of its leaves if `support`, see [`support_table`].
+fn to_bytes_with_support<P: ToOptimized, B: BranchLayout>(
+    forest: &Forest<P>,
+    optimized: OptimizedForest<'_, P::OptimizedType, B>,
+    support: bool,
+) -> Result<AVec<u8>> {
+    if !support {
+        return Ok(optimized.to_bytes());
+    }
 
+    let support = support_table(forest, &optimized)?;
+    optimized
+        .with_support(&support)
+        .map(|optimized| optimized.to_bytes())
+        .context("Could not record the support of the leaves")
+}
+
+fn classification_problem(forest: &Forest<Classification>) -> Result<embedded::Classification> {
+    let num_targets = forest
+        .num_targets()
+        .try_into()
+        .map_err(|_| eyre!("Forest has more than 255 targets"))?;
+    embedded::Classification::new(num_targets).context("Forest has no targets")
+}
+
+/// Convert branches to 16-bit pointers, if every index fits the 15 bits
+/// left next to the leaf tag.
+fn narrow_branches(nodes: &[embedded::Branch]) -> Result<Vec<embedded::Branch<U16>>> {
+    let narrow = |ptr: NodePointer| {
+        U16::tagged(ptr.index(), ptr.is_leaf())
+            .ok_or_else(|| eyre!("Index {} does not fit 15 bits", ptr.index()))
+    };
+
+    nodes
+        .iter()
+        .map(|branch| {
+            Ok(embedded::Branch::from_ptrs(
+                branch.split_with(),
+                branch.split_at(),
+                narrow(branch.left_ptr())?,
+                narrow(branch.right_ptr())?,
+            ))
+        })
+        .collect()
+}
+
+/// Group the branches of every tree, root first and depth first, so that
+/// each tree is a range of the branch array. Each branch is followed by its
+/// left subtree, or by its right one if flagged in `hot_right`, by index
+/// into `nodes`. Returns the branches, with their pointers updated, and the
+/// index of the root of every tree, which starts its range.
+pub(crate) fn group_trees(
+    nodes: &[embedded::Branch],
+    num_trees: usize,
+    hot_right: &[bool],
+) -> Result<(Vec<embedded::Branch>, Vec<U32>)> {
+    // Branches of each tree in depth-first order, as indices into `nodes`
+    let mut order = Vec::with_capacity(nodes.len());
+    let mut tree_offsets = Vec::with_capacity(num_trees);
+    for root in 0..num_trees {
+        tree_offsets.push(U32::new(order.len() as u32));
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            let branch = nodes.get(index).ok_or_else(|| eyre!("Malformed forest"))?;
+            // The child pushed last is stored next
+            let mut children = [branch.right_ptr(), branch.left_ptr()];
+            if hot_right.get(index).copied().unwrap_or(false) {
+                children.reverse();
+            }
+            for ptr in children {
+                if !ptr.is_leaf() {
+                    stack.push(ptr.index() as usize);
+                }
+            }
+        }
+    }
+    if order.len() != nodes.len() {
+        return Err(eyre!("Malformed forest"));
+    }
+
+    let mut new_index = vec![0; nodes.len()];
+    for (new, &old) in order.iter().enumerate() {
+        new_index[old] = new as u32;
+    }
+    let child = |ptr: NodePointer| {
+        if ptr.is_leaf() {
+            ptr
+        } else {
+            NodePointer::new_branch(new_index[ptr.index() as usize])
+        }
+    };
+    let grouped = order
+        .iter()
+        .map(|&old| {
+            let branch = &nodes[old];
+            embedded::Branch::new(
+                branch.split_with(),
+                branch.split_at(),
+                child(branch.left_ptr()),
+                child(branch.right_ptr()),
+            )
+        })
+        .collect();
+
+    Ok((grouped, tree_offsets))
+}
+
+/// Group the branches of every tree with [`group_trees`], and make their
+/// branch pointers relative to the root of their tree. Returns the branches
+/// and the index of the root of every tree, if every index fits the 15 bits
+/// left next to the leaf tag.
+fn relative_branches(
+    nodes: &[embedded::Branch],
+    num_trees: usize,
+    hot_right: &[bool],
+) -> Result<(Vec<embedded::Branch<RelativeU16>>, Vec<U32>)> {
+    let (nodes, tree_offsets) = group_trees(nodes, num_trees, hot_right)?;
+
+    let mut relative = Vec::with_capacity(nodes.len());
+    for (tree, start) in tree_offsets.iter().enumerate() {
+        let start = start.get() as usize;
+        let end = tree_offsets
+            .get(tree + 1)
+            .map_or(nodes.len(), |end| end.get() as usize);
+        if end - start > RelativeU16::MAX as usize + 1 {
+            return Err(eyre!(
+                "Tree {tree} has {} branches, more than 16-bit pointers fit",
+                end - start
+            ));
+        }
+
+        let child = |ptr: NodePointer| {
+            let index = if ptr.is_leaf() {
+                ptr.index()
+            } else {
+                // Fits, as the number of branches of the tree does
+                ptr.index() - start as u32
+            };
+            RelativeU16::tagged(index, ptr.is_leaf())
+                .ok_or_else(|| eyre!("Index {index} does not fit 15 bits"))
+        };
+        for branch in &nodes[start..end] {
+            relative.push(embedded::Branch::from_ptrs(
+                branch.split_with(),
+                branch.split_at(),
+                child(branch.left_ptr())?,
+                child(branch.right_ptr())?,
+            ));
+        }
+    }
+
+    Ok((relative, tree_offsets))
+}
+
+pub(crate) fn num_trees<P: ProblemType>(forest: &Forest<P>) -> Result<u32> {
+    forest
+        .num_trees()
+        .try_into()
+        .map_err(|_| eyre!("Forest has too many trees"))
+}
+
+/// Fingerprint of the feature and target names of a forest, recorded in
+/// the header of every forest this crate writes.
+pub(crate) fn fingerprint<P: WriteForest>(forest: &Forest<P>) -> u32 {
+    P::metadata(forest).fingerprint
+}
+
+pub(crate) fn num_features<P: ProblemType>(forest: &Forest<P>) -> Result<u8> {
+    forest
+        .num_features()
+        .try_into()
+        .map_err(|_| eyre!("Forest has more than 255 features"))
+}
+
+/// Read a forest definition file (CSV), optimize it, and write the
+/// serialized forest to `output` and its metadata next to it.
+pub fn write_forest<N>(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()>
+where
+    N: SerializedNode,
+    N::ProblemType: WriteForest,
+{
+    let output = output.as_ref();
+    convert_forest::<N>(
+        input,
+        &ReadOptions::default(),
+        NodeEncoding::default(),
+        &[(OutputFormat::Rforest, output.to_path_buf())],
+        ForestMetadata::sidecar_path(output),
+    )?;
     Ok(())
 }
 
-pub fn write_regression(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
-    // Read the input file
-    let serialized = SerializedForest::<SerializedRegressionNode>::read(input)
-        .context("Could not read forest definition file (CSV).")?;
-    let forest = Forest::from_serialized(serialized)?;
+/// Read a forest definition file (CSV), optimize it, and write it in every
+/// requested format, along with its metadata. Returns the size of the
+/// serialized forest, in bytes. The definition is read following `options`.
+///
+/// No file is written if any artifact could not be produced.
+pub fn convert_forest<N>(
+    input: impl AsRef<Path>,
+    options: &ReadOptions,
+    encoding: NodeEncoding,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: impl AsRef<Path>,
+) -> Result<usize>
+where
+    N: SerializedNode,
+    N::ProblemType: WriteForest,
+{
+    // Read the input file, one tree at a time if it is large
+    let input = input.as_ref();
+    let large = fs::metadata(input).is_ok_and(|m| m.len() > STREAMING_THRESHOLD);
+    let forest = if large && options.order == IndexOrder::default() {
+        tracing::debug!("Reading the forest definition one tree at a time");
+        Forest::from_csv_streaming_with::<N>(input, options)
+            .context("Could not read forest definition file (CSV).")?
+    } else {
+        let serialized = SerializedForest::<N>::read_with(input, options)
+            .context("Could not read forest definition file (CSV).")?;
+        Forest::from_serialized(options.prepare(serialized)?)?
+    };
+
+    write_artifacts_with(&forest, encoding, artifacts, metadata_path)
+}
+
+/// Optimize a forest, and write it in every requested format along with its
+/// metadata. Returns the size of the serialized forest, in bytes.
+///
+/// No file is written if any artifact could not be produced.
+pub fn write_artifacts<P: WriteForest>(
+    forest: &Forest<P>,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: impl AsRef<Path>,
+) -> Result<usize> {
+    write_artifacts_with(forest, NodeEncoding::default(), artifacts, metadata_path)
+}
+
+/// [`write_artifacts`] with branches of the given encoding. The returned
+/// size does not depend on their byte order.
+pub fn write_artifacts_with<P: WriteForest>(
+    forest: &Forest<P>,
+    encoding: impl Into<NodeEncoding>,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: impl AsRef<Path>,
+) -> Result<usize> {
+    let encoding = encoding.into();
 
     // Optimize the forest
-    let nodes = forest.optimize_nodes();
-    let optimized = OptimizedForest::<Regression>::new(
-        forest.num_trees().try_into().unwrap(),
-        &nodes,
-        forest.num_features().try_into().unwrap(),
-    )
-    .map_err(|_| eyre!("Malformed forest"))?;
-
-    let serialized = optimized.to_bytes();
-    let ptr = serialized.as_ptr();
-    assert!(ptr as usize % align_of_val(&optimized) == 0);
-
-    // Write the transformed data to the output file
-    let mut output_file = File::create(output).context("Could not create output file")?;
-    output_file.write_all(&serialized)?;
+    let serialized =
+        tracing::info_span!("serialize").in_scope(|| P::serialize_encoded(forest, &encoding))?;
+    let optimized = deserialize_verified(forest, &serialized)?;
 
-    Ok(())
+    // Write every artifact, along with the feature and target names
+    let _span = tracing::info_span!("emit").entered();
+    let (endianness, key) = (encoding.endianness, encoding.encryption.as_ref());
+    let (metadata, path) = (P::metadata(forest), metadata_path.as_ref());
+    dispatch!(&optimized, optimized => {
+        emit_all(optimized, &metadata, endianness, key, artifacts, path)
+    })?;
+
+    Ok(serialized.len())
+}
+
+/// Optimize a forest, and write it in a single format to `out`, without its
+/// metadata. Returns the size of the serialized forest, in bytes.
+pub fn write_to<P: WriteForest>(
+    forest: &Forest<P>,
+    encoding: NodeEncoding,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<usize> {
+    let (endianness, key) = (encoding.endianness, encoding.encryption.as_ref());
+    let serialized =
+        tracing::info_span!("serialize").in_scope(|| P::serialize_encoded(forest, &encoding))?;
+    let optimized = deserialize_verified(forest, &serialized)?;
+
+    let _span = tracing::info_span!("emit").entered();
+    let metadata = P::metadata(forest);
+    dispatch!(&optimized, optimized => {
+        format.emit(optimized, &metadata, endianness, key, "forest", out)
+    })
+    .with_context(|| format!("Could not write {format} output"))?;
+    out.flush()?;
+
+    Ok(serialized.len())
+}
+
+/// Deserialize the serialized form of `forest`, and check that it holds
+/// exactly the trees of `forest`, see [`verify_optimization`].
+fn deserialize_verified<'a, P: WriteForest>(
+    forest: &Forest<P>,
+    serialized: &'a [u8],
+) -> Result<AnyOptimizedForest<'a, P::OptimizedType>> {
+    let optimized = AnyOptimizedForest::<P::OptimizedType>::deserialize(serialized)
+        .context("Malformed forest")?;
+
+    let _span = tracing::info_span!("verify").entered();
+    dispatch!(&optimized, optimized => verify_optimization(forest, optimized))
+        .map_err(|divergence| eyre!("Optimized forest differs from the original: {divergence}"))?;
+
+    Ok(optimized)
+}
+
+/// Where [`convert_reader`] writes the optimized forest.
+pub enum Destination<'a> {
+    /// Files, along with the metadata, see [`write_artifacts`]
+    Files {
+        artifacts: &'a [(OutputFormat, PathBuf)],
+        metadata_path: &'a Path,
+    },
+    /// A single format to a stream, e.g. stdout, see [`write_to`]
+    Stream {
+        format: OutputFormat,
+        out: &'a mut dyn Write,
+    },
+}
+
+/// [`convert_file`] for a forest definition read from any source, e.g.
+/// stdin. `name` stands for the source in error messages.
+pub fn convert_reader(
+    mut input: impl Read,
+    name: &str,
+    problem_type: Option<PredictionType>,
+    options: &ReadOptions,
+    encoding: NodeEncoding,
+    destination: Destination<'_>,
+) -> Result<usize> {
+    fn convert<N>(
+        input: &[u8],
+        options: &ReadOptions,
+        encoding: NodeEncoding,
+        destination: Destination<'_>,
+    ) -> Result<usize>
+    where
+        N: SerializedNode,
+        N::ProblemType: WriteForest,
+    {
+        let serialized = SerializedForest::<N>::from_reader_with(input, options)
+            .context("Could not read forest definition (CSV).")?;
+        let forest = Forest::from_serialized(options.prepare(serialized)?)?;
+
+        match destination {
+            Destination::Files {
+                artifacts,
+                metadata_path,
+            } => write_artifacts_with(&forest, encoding, artifacts, metadata_path),
+            Destination::Stream { format, out } => write_to(&forest, encoding, format, out),
+        }
+    }
+
+    // The header has to be read before the type of the forest is known, so
+    // the whole definition is buffered
+    let mut csv = Vec::new();
+    input.read_to_end(&mut csv)?;
+    let declared = parse_problem_type(&mut csv.as_slice())?;
+
+    match check_problem_type(name, problem_type, declared)? {
+        PredictionType::Classification => {
+            convert::<SerializedClassificationNode>(&csv, options, encoding, destination)
+        }
+        PredictionType::Regression => {
+            convert::<SerializedRegressionNode>(&csv, options, encoding, destination)
+        }
+    }
+}
+
+/// [`convert_forest`] for a file whose problem type is detected from its
+/// header, or given by `problem_type`.
+pub fn convert_file(
+    input: impl AsRef<Path>,
+    problem_type: Option<PredictionType>,
+    options: &ReadOptions,
+    encoding: NodeEncoding,
+    artifacts: &[(OutputFormat, PathBuf)],
+    metadata_path: impl AsRef<Path>,
+) -> Result<usize> {
+    match resolve_problem_type(&input, problem_type)? {
+        PredictionType::Classification => convert_forest::<SerializedClassificationNode>(
+            input,
+            options,
+            encoding,
+            artifacts,
+            metadata_path,
+        ),
+        PredictionType::Regression => convert_forest::<SerializedRegressionNode>(
+            input,
+            options,
+            encoding,
+            artifacts,
+            metadata_path,
+        ),
+    }
+}
+
+pub fn write_classification(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
+    write_forest::<SerializedClassificationNode>(input, output)
+}
+
+pub fn write_regression(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
+    write_forest::<SerializedRegressionNode>(input, output)
 }
@@ -0,0 +1,92 @@
+use color_eyre::Result;
+use embedded_rforest::forest::{
+    Classification, NodeLayout, OptimizedForest, SplitRule,
+    abi::{FORMAT_ABI, StructAbi},
+    deserialize::ForestHeader,
+};
+use forest_optimizer::inspect::{inspect, read_model};
+use forest_optimizer::metadata::AbiMetadata;
+
+/// The little-endian field `name` of the struct `abi` starting at `bytes`
+fn field(bytes: &[u8], abi: &StructAbi, name: &str) -> u64 {
+    let field = abi.field(name);
+    bytes[field.offset..field.offset + field.size]
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | byte as u64)
+}
+
+#[test]
+fn fixture_header_decodes_by_the_abi() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let header = ForestHeader::peek(&buffer).unwrap();
+    let raw = |name| field(&buffer, &FORMAT_ABI.header, name);
+    let flag = |name| raw("flags") as u8 & FORMAT_ABI.header_flag(name) != 0;
+
+    assert_eq!(raw("num_trees"), 5);
+    assert_eq!(raw("num_features"), 4);
+    assert_eq!(raw("num_targets"), 3);
+    assert_eq!(raw("layout"), NodeLayout::Standard as u64);
+    assert_eq!(raw("version"), FORMAT_ABI.version as u64);
+    assert_eq!(raw("extra_flags"), 0);
+
+    assert_eq!(raw("num_trees"), header.num_trees as u64);
+    assert_eq!(raw("num_features"), header.num_features as u64);
+    assert_eq!(raw("num_targets"), header.num_targets.unwrap().get() as u64);
+    assert_eq!(raw("header_len"), header.header_len as u64);
+    assert_eq!(raw("num_leaves"), header.num_leaves as u64);
+    assert_eq!(flag("tree_table"), header.tree_table);
+    assert_eq!(flag("fingerprint"), header.fingerprint.is_some());
+    assert_eq!(flag("calibration"), header.calibrated);
+    assert_eq!(flag("support"), header.support);
+    assert!(!flag("big_endian"));
+    let less_than = raw("extra_flags") as u8 & FORMAT_ABI.extra_flag("less_than") != 0;
+    assert_eq!(less_than, header.split_rule == SplitRule::Less);
+    assert!(header.header_len.is_multiple_of(FORMAT_ABI.buffer_align));
+
+    Ok(())
+}
+
+#[test]
+fn fixture_branches_decode_by_the_abi() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let header = ForestHeader::peek(&buffer).unwrap();
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let abi = FORMAT_ABI.branch(header.layout);
+    assert_eq!(abi.size, header.layout.branch_size());
+
+    let nodes = buffer[header.header_len..].chunks_exact(abi.size);
+    assert_eq!(forest.nodes().len(), header.node_count);
+    for (bytes, branch) in nodes.zip(forest.nodes()) {
+        let raw = |name| field(bytes, abi, name);
+        assert_eq!(raw("left"), branch.left_ptr().as_ptr() as u64);
+        assert_eq!(raw("right"), branch.right_ptr().as_ptr() as u64);
+        assert_eq!(raw("split_at") as u32, branch.split_at().to_bits());
+        assert_eq!(raw("split_with") as u32, branch.split_with());
+
+        let is_leaf = raw("left") as u32 & FORMAT_ABI.leaf_tag != 0;
+        assert_eq!(is_leaf, branch.left_is_prediction());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn metadata_and_info_report_the_abi() -> Result<()> {
+    let abi = AbiMetadata::current();
+    assert_eq!(abi.format_version, FORMAT_ABI.version);
+    assert_eq!(abi.header_size, 16);
+    assert_eq!(abi.node_pointer_size, 4);
+    assert_eq!(abi.branch_sizes.len(), 5);
+    for (name, size) in [("standard", 16), ("narrow", 12), ("compact", 8)] {
+        assert_eq!(abi.branch_sizes[name], size);
+    }
+
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let info = inspect(&buffer)?;
+    assert_eq!(info.header_size, 16);
+    assert_eq!(info.branch_size, 16);
+    assert!(info.to_string().contains("Branch size:     16 bytes"));
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use aligned_vec::AVec;
+use embedded_rforest::Error;
+use embedded_rforest::forest::deserialize::{BUFFER_ALIGN, ForestHeader, HEADER_LEN, SUM};
+use embedded_rforest::forest::{
+    Aggregation, Classification, OptimizedForest, Predict, Regression, endian::swap_byte_order,
+};
+use embedded_rforest::ptr::F32;
+use forest_optimizer::inspect::inspect;
+
+use crate::helpers::{
+    FLAGS, ROWS, classifier, predictions, regression_forest, regression_stumps,
+    session_predictions, stumps,
+};
+
+/// Leaves of two [`regression_stumps`]: 1 or 2 for the first tree, 4 or -3
+/// for the second. They sum to 5 on the left, and to -1 on the right
+const LEAVES: [F32; 4] = [F32::new(1.0), F32::new(2.0), F32::new(4.0), F32::new(-3.0)];
+
+#[test]
+fn regression_forests_average_their_trees_by_default() {
+    let nodes = regression_stumps(2);
+    let forest = regression_forest(&nodes, &LEAVES);
+    assert_eq!(forest.aggregation(), Aggregation::MeanValue);
+    // 5 / 2 and -1 / 2
+    assert_eq!(predictions(&forest, &ROWS), [2.5, -0.5]);
+    assert_eq!(session_predictions(&forest, &ROWS), [2.5, -0.5]);
+
+    // Forests which average their trees are written without the flag, like
+    // forests written before it existed
+    let bytes = forest.to_bytes();
+    assert_eq!(bytes[FLAGS] & SUM, 0);
+    let header = ForestHeader::peek(&bytes).unwrap();
+    assert_eq!(header.aggregation, Aggregation::MeanValue);
+    let loaded = OptimizedForest::<Regression>::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.aggregation(), Aggregation::MeanValue);
+    assert_eq!(predictions(&loaded, &ROWS), [2.5, -0.5]);
+}
+
+#[test]
+fn summed_forests_add_their_base_score() {
+    let nodes = regression_stumps(2);
+    let summed = regression_forest(&nodes, &LEAVES)
+        .with_aggregation(Aggregation::Sum { base: 0.5 })
+        .unwrap();
+    assert_eq!(summed.aggregation(), Aggregation::Sum { base: 0.5 });
+    // 0.5 + 5 and 0.5 - 1
+    assert_eq!(predictions(&summed, &ROWS), [5.5, -0.5]);
+    assert_eq!(session_predictions(&summed, &ROWS), [5.5, -0.5]);
+
+    let unbiased = regression_forest(&nodes, &LEAVES)
+        .with_aggregation(Aggregation::Sum { base: 0.0 })
+        .unwrap();
+    assert_eq!(predictions(&unbiased, &ROWS), [5.0, -1.0]);
+
+    // The base score takes a word of the header
+    let bytes = summed.to_bytes();
+    assert_ne!(bytes[FLAGS] & SUM, 0);
+    assert_eq!(
+        bytes.len(),
+        regression_forest(&nodes, &LEAVES).to_bytes().len() + BUFFER_ALIGN
+    );
+    let header = ForestHeader::peek(&bytes).unwrap();
+    assert_eq!(header.aggregation, Aggregation::Sum { base: 0.5 });
+    assert_eq!(header.serialized_len(), bytes.len());
+    let loaded = OptimizedForest::<Regression>::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.aggregation(), Aggregation::Sum { base: 0.5 });
+    assert_eq!(predictions(&loaded, &ROWS), [5.5, -0.5]);
+    assert_eq!(loaded.to_bytes(), bytes);
+
+    // The base score is little-endian, whatever the byte order of the nodes
+    let mut swapped = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+    swap_byte_order(&mut swapped).unwrap();
+    assert_eq!(
+        ForestHeader::peek(&swapped).unwrap().aggregation,
+        Aggregation::Sum { base: 0.5 }
+    );
+
+    let info = inspect(&bytes).unwrap();
+    assert_eq!(info.aggregation, "sum, base score 0.5");
+    assert!(
+        info.to_string()
+            .contains("Aggregation:     sum, base score 0.5")
+    );
+
+    let averaged = summed.with_aggregation(Aggregation::MeanValue).unwrap();
+    assert_eq!(predictions(&averaged, &ROWS), [2.5, -0.5]);
+}
+
+#[test]
+fn summed_forests_need_a_finite_base_score() {
+    let nodes = regression_stumps(2);
+    assert_eq!(
+        regression_forest(&nodes, &LEAVES)
+            .with_aggregation(Aggregation::MajorityVote)
+            .err(),
+        Some(Error::WrongProblemType)
+    );
+    for base in [f32::NAN, f32::INFINITY] {
+        assert_eq!(
+            regression_forest(&nodes, &LEAVES)
+                .with_aggregation(Aggregation::Sum { base })
+                .err(),
+            Some(Error::MalformedForest)
+        );
+    }
+
+    let summed = regression_forest(&nodes, &LEAVES)
+        .with_aggregation(Aggregation::Sum { base: 0.5 })
+        .unwrap();
+    let mut bytes = summed.to_bytes();
+    // Right after the header, without a tree table nor a fingerprint
+    bytes[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&f32::NAN.to_le_bytes());
+    assert_eq!(
+        ForestHeader::peek(&bytes).err(),
+        Some(Error::MalformedForest)
+    );
+}
+
+#[test]
+fn classification_forests_always_vote() {
+    // Each tree predicts its class on both sides
+    let nodes = stumps(&[(1, 1), (1, 1), (0, 0)]);
+    let forest = classifier(&nodes, 2);
+    assert_eq!(forest.aggregation(), Aggregation::MajorityVote);
+    assert_eq!(forest.predict(&[0.0]), 1);
+
+    let bytes = forest.to_bytes();
+    assert_eq!(
+        ForestHeader::peek(&bytes).unwrap().aggregation,
+        Aggregation::MajorityVote
+    );
+    let info = inspect(&bytes).unwrap();
+    assert_eq!(info.aggregation, "majority vote");
+
+    // Classification forests have no base score to sum from
+    let mut flagged = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+    flagged[FLAGS] |= SUM;
+    assert_eq!(
+        OptimizedForest::<Classification>::deserialize(&flagged).err(),
+        Some(Error::MalformedForest)
+    );
+}
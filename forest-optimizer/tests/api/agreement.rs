@@ -0,0 +1,89 @@
+use color_eyre::Result;
+use forest_optimizer::agreement::check_agreement;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+
+use crate::helpers::get_forest;
+
+#[test]
+fn iris_forests_agree_with_the_trainer() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let agreement = check_agreement(&forest, "./tests/test-data/iris.csv", "Predicted", 0.0, 5)?;
+
+    assert!(agreement.is_unanimous());
+    assert_eq!(agreement.trainer_vs_forest.rows, 150);
+    assert!(agreement.first_disagreements.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn disagreeing_rows_are_listed_with_their_features() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let dir = tempfile::tempdir()?;
+    let data = dir.path().join("iris.csv");
+
+    // Pretend the trainer predicted otherwise on the first and last rows
+    let original = std::fs::read_to_string("./tests/test-data/iris.csv")?;
+    let mut lines = original.lines().map(String::from).collect::<Vec<_>>();
+    lines[1] = lines[1].replace("\"setosa\",\"setosa\"", "\"setosa\",\"virginica\"");
+    let last = lines.len() - 1;
+    lines[last] = lines[last].replace("\"virginica\",\"virginica\"", "\"virginica\",\"setosa\"");
+    std::fs::write(&data, lines.join("\n"))?;
+
+    let agreement = check_agreement(&forest, &data, "Predicted", 0.0, 1)?;
+    assert!(!agreement.is_unanimous());
+    assert_eq!(agreement.trainer_vs_forest.disagreements, [0, 149]);
+    assert_eq!(agreement.trainer_vs_optimized.disagreements, [0, 149]);
+    assert!(agreement.forest_vs_optimized.is_identical());
+
+    // Only the first K rows are listed
+    let [row] = &agreement.first_disagreements[..] else {
+        panic!("Expected a single row");
+    };
+    assert_eq!(row.row, 0);
+    assert_eq!(row.trainer, "virginica");
+    assert_eq!(row.forest, "setosa");
+    assert_eq!(row.optimized, "setosa");
+    // Sepal.Length of the first row, in forest feature order
+    let sepal_length = agreement
+        .features
+        .iter()
+        .position(|f| f == "Sepal.Length")
+        .unwrap();
+    assert_eq!(row.features[sepal_length], 5.1);
+    assert!(agreement.to_string().contains("  Sepal.Length = 5.1\n"));
+
+    Ok(())
+}
+
+#[test]
+fn regression_agreement_depends_on_the_tolerance() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let data = "./tests/test-data/airfoil.csv";
+
+    // The trainer computed in double precision
+    let strict = check_agreement(&forest, data, "Predicted", 0.001, 3)?;
+    assert!(!strict.trainer_vs_forest.is_identical());
+    assert_eq!(strict.trainer_vs_forest, strict.trainer_vs_optimized);
+    assert!(strict.forest_vs_optimized.is_identical());
+    assert!(strict.trainer_vs_forest.max_difference < 2.5);
+    assert_eq!(strict.first_disagreements.len(), 3);
+    assert!(
+        strict
+            .first_disagreements
+            .windows(2)
+            .all(|pair| pair[0].row < pair[1].row)
+    );
+    assert_eq!(
+        strict.first_disagreements[0].row,
+        strict.trainer_vs_forest.disagreements[0]
+    );
+
+    let tolerant = check_agreement(&forest, data, "Predicted", 2.5, 3)?;
+    assert!(tolerant.is_unanimous());
+
+    Ok(())
+}
@@ -0,0 +1,279 @@
+use std::collections::BTreeSet;
+
+use color_eyre::Result;
+use embedded_rforest::forest::deserialize::ForestHeader;
+use embedded_rforest::forest::{Branch, Classification, OptimizedForest, Regression};
+use embedded_rforest::ptr::{F32, NodePointer, U32};
+use forest_optimizer::analyze::{
+    Reachability, UNAVAILABLE_FROM_BINARY, analyze, analyze_binary, is_binary_forest, reachability,
+    tree_stats,
+};
+use forest_optimizer::forest::{Forest, stats::LeafStats};
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::metadata::ForestMetadata;
+use forest_optimizer::problem_type::PredictionType;
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedNode, SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+#[test]
+fn per_tree_node_counts_sum_to_forest_total() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let trees = tree_stats(&forest);
+
+    assert_eq!(trees.len(), forest.num_trees());
+    assert_eq!(
+        trees.iter().map(|t| t.nodes).sum::<usize>(),
+        forest.nodes().len()
+    );
+    assert!(trees.iter().all(|t| t.leaves == t.branches + 1));
+
+    let analysis = analyze(&forest)?;
+    let optimized_size = trees.iter().map(|t| t.optimized_size).sum::<usize>();
+    assert_eq!(
+        optimized_size,
+        analysis.optimized_nodes * size_of::<embedded_rforest::forest::Branch>()
+    );
+
+    Ok(())
+}
+
+fn branch(left: NodePointer, right: NodePointer) -> Branch {
+    Branch::new(0, 0.5, left, right)
+}
+
+#[test]
+fn unreachable_and_constant_branches_are_reported() -> Result<()> {
+    let (to, leaf) = (NodePointer::new_branch, NodePointer::new_leaf);
+    // Two trees rooted at 0 and 1. Branch 3 is constant, and nothing points
+    // to branches 4 and 5, though 4 points to 5
+    let nodes = [
+        branch(to(2), leaf(0)),
+        branch(leaf(1), leaf(2)),
+        branch(to(3), leaf(1)),
+        branch(leaf(2), leaf(2)),
+        branch(to(5), leaf(0)),
+        branch(leaf(0), leaf(1)),
+    ];
+    let forest =
+        OptimizedForest::<Classification>::new(2, &nodes, 1, Classification::new(3).unwrap())
+            .unwrap();
+    assert_eq!(
+        reachability(&forest)?,
+        Reachability {
+            unreachable: vec![4, 5],
+            constant: vec![3],
+        }
+    );
+
+    // Regression leaves are compared by value, and a constant root stands
+    // for a single-leaf tree
+    let nodes = [branch(leaf(0), leaf(1)), branch(leaf(0), leaf(2))];
+    let leaves = [F32::new(1.0), F32::new(1.0), F32::new(2.0)];
+    let forest = OptimizedForest::<Regression>::with_leaves(1, &nodes, 1, &leaves).unwrap();
+    assert_eq!(
+        reachability(&forest)?,
+        Reachability {
+            unreachable: vec![1],
+            constant: vec![],
+        }
+    );
+
+    // Trees of a tree table are walked from their offsets
+    let nodes = [
+        branch(to(1), leaf(0)),
+        branch(leaf(1), leaf(1)),
+        branch(leaf(0), leaf(1)),
+        branch(leaf(1), leaf(0)),
+    ];
+    let offsets = [U32::new(0), U32::new(2)];
+    let forest =
+        OptimizedForest::<Classification>::new(2, &nodes, 1, Classification::new(2).unwrap())
+            .and_then(|forest| forest.with_tree_offsets(&offsets))
+            .unwrap();
+    assert_eq!(
+        reachability(&forest)?,
+        Reachability {
+            unreachable: vec![3],
+            constant: vec![1],
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn optimized_definitions_have_no_unreachable_branches() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let analysis = analyze(&forest)?;
+    assert!(analysis.reachability.unreachable.is_empty());
+    assert!(analysis.to_string().contains("Unreachable branches: 0\n"));
+
+    let json = serde_json::to_value(&analysis)?;
+    assert!(json["reachability"]["constant"].is_array());
+
+    Ok(())
+}
+
+#[test]
+fn feature_usage_covers_every_feature_of_iris() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let analysis = analyze(&forest)?;
+    let usage = &analysis.feature_usage;
+
+    let names = usage
+        .iter()
+        .map(|u| u.name.as_str())
+        .collect::<BTreeSet<_>>();
+    assert_eq!(
+        names,
+        BTreeSet::from(["Petal.Length", "Petal.Width", "Sepal.Length", "Sepal.Width"])
+    );
+    assert_eq!(
+        usage.iter().map(|u| u.branches).sum::<usize>(),
+        analysis.branches
+    );
+    for feature in usage {
+        assert!(feature.trees <= forest.num_trees());
+        assert_eq!(
+            feature.tree_fraction,
+            feature.trees as f64 / forest.num_trees() as f64
+        );
+        assert_eq!(feature.min_depth.is_some(), feature.branches > 0);
+    }
+    // Every tree splits at its root
+    assert!(usage.iter().any(|u| u.min_depth == Some(0)));
+
+    let display = analysis.to_string();
+    assert!(display.contains("Feature               Branches   Trees  Min depth"));
+
+    Ok(())
+}
+
+/// The analysis of the serialized fixture `binary` agrees with that of the
+/// definition it was converted from, wherever it can tell.
+fn assert_binary_matches_definition<N>(definition: &str, binary: &str) -> Result<()>
+where
+    N: SerializedNode,
+    N::ProblemType: WriteForest + LeafStats,
+{
+    let forest: Forest<N::ProblemType> = get_forest::<N>(definition)?;
+    let expected = analyze(&forest)?;
+    let buffer = read_model(binary)?;
+    let analysis = analyze_binary(&buffer, None)?;
+
+    assert!(analysis.info.is_valid());
+    assert_eq!(analysis.info.node_count, expected.optimized_nodes);
+    assert_eq!(analysis.info.serialized_size, expected.serialized_size);
+    assert_eq!(analysis.reachability.as_ref(), Some(&expected.reachability));
+    assert_eq!(analysis.trees.len(), expected.trees.len());
+    for (tree, expected) in analysis.trees.iter().zip(&expected.trees) {
+        assert_eq!(tree.index, expected.index);
+        assert_eq!(tree.branches, expected.branches.max(1));
+        assert_eq!(tree.depth, expected.depth);
+        assert_eq!(tree.optimized_size, expected.optimized_size);
+    }
+    // Features are named by index, without metadata
+    for (usage, expected) in analysis.feature_usage.iter().zip(&expected.feature_usage) {
+        assert_eq!(usage.name, format!("feature {}", usage.index));
+        assert_eq!(
+            (usage.branches, usage.trees, usage.min_depth),
+            (expected.branches, expected.trees, expected.min_depth)
+        );
+        assert_eq!(usage.tree_fraction, expected.tree_fraction);
+    }
+    assert_eq!(analysis.unavailable, UNAVAILABLE_FROM_BINARY);
+    assert!(
+        analysis
+            .to_string()
+            .contains("Unavailable without the forest definition (CSV): unoptimized nodes")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn binary_analysis_of_classification_fixture() -> Result<()> {
+    assert_binary_matches_definition::<SerializedClassificationNode>(
+        "./tests/test-forests/forest_iris_5.csv",
+        "./tests/test-forests/forest_iris_5.rforest",
+    )
+}
+
+#[test]
+fn binary_analysis_of_regression_fixture() -> Result<()> {
+    assert_binary_matches_definition::<SerializedRegressionNode>(
+        "./tests/test-forests/airfoil_100_200.csv",
+        "./tests/test-forests/airfoil_100_200.rforest",
+    )
+}
+
+#[test]
+fn binary_analysis_names_features_after_matching_metadata() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let targets = forest.targets_by_index();
+    let metadata = ForestMetadata::new(
+        PredictionType::Classification,
+        &forest.features_by_index(),
+        Some(&targets),
+    );
+
+    let analysis = analyze_binary(&buffer, Some(&metadata))?;
+    let names = analysis
+        .feature_usage
+        .iter()
+        .map(|u| u.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, forest.features_by_index());
+
+    // Names of another forest are left out
+    let other = ForestMetadata {
+        fingerprint: metadata.fingerprint.wrapping_add(1),
+        ..metadata
+    };
+    if ForestHeader::peek(&buffer).unwrap().fingerprint.is_some() {
+        let analysis = analyze_binary(&buffer, Some(&other))?;
+        assert_eq!(analysis.feature_usage[0].name, "feature 0");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn binary_analysis_of_corrupted_forest_reports_the_header_only() -> Result<()> {
+    let mut buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let nodes = ForestHeader::peek(&buffer).unwrap().header_len;
+    buffer[nodes..nodes + 4].copy_from_slice(&(u32::MAX >> 1).to_le_bytes());
+
+    let analysis = analyze_binary(&buffer, None)?;
+    assert!(!analysis.info.is_valid());
+    assert_eq!(analysis.reachability, None);
+    assert!(analysis.trees.is_empty() && analysis.feature_usage.is_empty());
+    assert!(analysis.to_string().contains("Validation:      FAILED"));
+
+    Ok(())
+}
+
+#[test]
+fn binary_forests_are_told_from_definitions() -> Result<()> {
+    assert!(is_binary_forest(
+        "./tests/test-forests/forest_iris_5.rforest"
+    )?);
+    assert!(!is_binary_forest("./tests/test-forests/forest_iris_5.csv")?);
+
+    // Whatever their extension
+    let dir = tempfile::tempdir()?;
+    let renamed = dir.path().join("forest.bin");
+    std::fs::copy("./tests/test-forests/forest_iris_5.rforest", &renamed)?;
+    assert!(is_binary_forest(&renamed)?);
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use color_eyre::Result;
+use embedded_rforest::bench::{BenchReport, CycleCounter, bench_predict};
+use embedded_rforest::forest::{Classification, OptimizedForest, Regression};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+/// A counter starting at `now`, advanced by the next of `steps` before every
+/// read, and by 1 once they run out.
+struct MockCounter {
+    now: u32,
+    steps: std::vec::IntoIter<u32>,
+    reads: usize,
+}
+
+impl MockCounter {
+    fn new(now: u32, steps: Vec<u32>) -> Self {
+        Self {
+            now,
+            steps: steps.into_iter(),
+            reads: 0,
+        }
+    }
+}
+
+impl CycleCounter for MockCounter {
+    fn cycles(&mut self) -> u32 {
+        self.reads += 1;
+        self.now = self.now.wrapping_add(self.steps.next().unwrap_or(1));
+        self.now
+    }
+}
+
+/// Steps of a counter reading `overheads` while calibrating, then
+/// `predictions` plus the smallest overhead for every prediction.
+fn steps(overheads: [u32; 8], predictions: &[u32]) -> Vec<u32> {
+    let overhead = overheads.into_iter().min().unwrap();
+    overheads
+        .into_iter()
+        .chain(predictions.iter().map(|&cycles| cycles + overhead))
+        .flat_map(|cycles| [0, cycles])
+        .collect()
+}
+
+#[test]
+fn bench_reports_cycles_without_the_overhead() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    assert_eq!(forest.num_trees(), 5);
+
+    let predictions = [1000, 1200, 900, 1100];
+    let mut counter = MockCounter::new(0, steps([9, 7, 12, 7, 8, 30, 7, 7], &predictions));
+    let report = bench_predict(
+        &forest,
+        rows.iter().take(predictions.len()).map(Vec::as_slice),
+        &mut counter,
+    );
+
+    assert_eq!(
+        report,
+        Some(BenchReport {
+            runs: 4,
+            min: 900,
+            mean: 1050,
+            max: 1200,
+            cycles_per_tree: 210,
+            overhead: 7,
+        })
+    );
+    // Two reads per calibration and per prediction
+    assert_eq!(counter.reads, 2 * (8 + predictions.len()));
+
+    Ok(())
+}
+
+#[test]
+fn bench_counts_across_wrap_arounds() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let forest = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+
+    let predictions = [50_000, 70_001];
+    let mut counter = MockCounter::new(u32::MAX - 60_000, steps([4; 8], &predictions));
+    let report = bench_predict(
+        &forest,
+        rows.iter().take(2).map(Vec::as_slice),
+        &mut counter,
+    )
+    .unwrap();
+
+    assert_eq!(
+        (report.min, report.mean, report.max),
+        (50_000, 60_000, 70_001)
+    );
+    assert_eq!(report.cycles_per_tree, 60_000 / forest.num_trees());
+    assert_eq!(report.overhead, 4);
+
+    Ok(())
+}
+
+#[test]
+fn bench_of_no_input_reports_nothing() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+
+    let mut counter = MockCounter::new(0, Vec::new());
+    assert_eq!(bench_predict(&forest, [], &mut counter), None);
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use color_eyre::Result;
+use embedded_rforest::forest::{Branch, OptimizedForest, Regression};
+use embedded_rforest::ptr::RelativeU16;
+use forest_optimizer::build::Build;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::metadata::ForestMetadata;
+use forest_optimizer::write_forest::PointerWidth;
+
+#[test]
+fn build_writes_the_forest_and_its_module_to_the_output_directory() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = Build::new("./tests/test-forests/forest_iris_5.csv")
+        .with_out_dir(dir.path())
+        .with_rust_module()
+        .compile()?;
+
+    assert_eq!(output, dir.path().join("forest_iris_5.rforest"));
+    assert_eq!(
+        std::fs::read(&output)?,
+        std::fs::read("./tests/test-forests/forest_iris_5.rforest")?
+    );
+    assert!(ForestMetadata::sidecar_path(&output).exists());
+
+    let module = std::fs::read_to_string(dir.path().join("forest_iris_5.rs"))?;
+    assert!(module.contains("pub static FOREST_IRIS_5: BackingStorage<"));
+    assert!(module.contains("pub const FOREST_IRIS_5_VALIDATED: bool = true;"));
+    // Includable, which inner attributes would prevent
+    assert!(!module.contains("//!"));
+
+    Ok(())
+}
+
+#[test]
+fn build_detects_the_problem_type_and_takes_an_encoding() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = Build::new("./tests/test-forests/airfoil_100_200.csv")
+        .with_out_dir(dir.path())
+        .with_encoding(PointerWidth::Relative)
+        .compile()?;
+
+    let buffer = read_model(&output)?;
+    assert!(OptimizedForest::<Regression, Branch<RelativeU16>>::deserialize(&buffer).is_ok());
+    assert!(!dir.path().join("airfoil_100_200.rs").exists());
+
+    Ok(())
+}
+
+#[test]
+fn build_errors_name_the_forest() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let error = Build::new("./tests/test-forests/missing.csv")
+        .with_out_dir(dir.path())
+        .compile()
+        .unwrap_err();
+    assert!(
+        format!("{error:#}")
+            .starts_with("Could not compile forest ./tests/test-forests/missing.csv: ")
+    );
+
+    // Outside of build scripts, Cargo sets no `OUT_DIR`
+    let error =
+        forest_optimizer::build::compile("./tests/test-forests/forest_iris_5.csv").unwrap_err();
+    assert!(error.to_string().starts_with("OUT_DIR is not set"));
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use aligned_vec::AVec;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::bundle::bundle_header_len;
+use embedded_rforest::forest::deserialize::BUFFER_ALIGN;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Branch, Classification, ForestBundle, OptimizedForest, Predict,
+    ProblemKind, Regression,
+};
+use embedded_rforest::ptr::U16;
+use forest_optimizer::bundle::bundle;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::prune::keep_first_trees;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+use crate::helpers::get_forest;
+
+type Rows = Vec<Vec<f32>>;
+
+/// A classification forest, a regression forest of narrow branches, and
+/// the rows of their datasets.
+fn models() -> Result<(AVec<u8>, AVec<u8>, Rows, Rows)> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let iris = ClassificationProblem::serialize(&forest)?;
+    let iris_rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+
+    let forest = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        3,
+    );
+    let airfoil = RegressionProblem::serialize_with(&forest, PointerWidth::U16)?;
+    let airfoil_rows =
+        read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+
+    Ok((iris, airfoil, iris_rows, airfoil_rows))
+}
+
+#[test]
+fn bundled_forests_predict_like_the_originals() -> Result<()> {
+    let (iris, airfoil, iris_rows, airfoil_rows) = models()?;
+    let bundled = bundle(&[("activity", &iris), ("anomaly", &airfoil)])?;
+
+    let bundle = ForestBundle::deserialize(&bundled).unwrap();
+    assert_eq!(bundle.len(), 2);
+    assert_eq!(bundle.names().collect::<Vec<_>>(), ["activity", "anomaly"]);
+    for entry in bundle.entries() {
+        assert_eq!(entry.offset.get() as usize % BUFFER_ALIGN, 0);
+    }
+    assert_eq!(
+        bundle.entries()[0].offset.get() as usize,
+        bundle_header_len(2)
+    );
+
+    let activity = bundle.get::<Classification>("activity").unwrap();
+    let original = OptimizedForest::<Classification>::deserialize(&iris).unwrap();
+    for row in &iris_rows {
+        assert_eq!(activity.predict(row), original.predict(row));
+    }
+
+    let anomaly = bundle.get_any::<Regression>("anomaly").unwrap();
+    assert!(matches!(anomaly, AnyOptimizedForest::Narrow(_)));
+    let narrow = bundle
+        .get_layout::<Regression, Branch<U16>>("anomaly")
+        .unwrap();
+    let original = AnyOptimizedForest::<Regression>::deserialize(&airfoil).unwrap();
+    for row in &airfoil_rows {
+        assert_eq!(
+            anomaly.predict(row).to_bits(),
+            original.predict(row).to_bits()
+        );
+        assert_eq!(
+            narrow.predict(row).to_bits(),
+            original.predict(row).to_bits()
+        );
+    }
+
+    // Forests are type-checked, and looked up by their exact name
+    assert_eq!(
+        bundle.bytes("anomaly").unwrap(),
+        (&airfoil[..], ProblemKind::Regression)
+    );
+    assert!(matches!(
+        bundle.get::<Regression>("activity"),
+        Err(Error::WrongProblemType)
+    ));
+    assert!(matches!(
+        bundle.get::<Regression>("anomaly"),
+        Err(Error::WrongLayout)
+    ));
+    assert!(matches!(
+        bundle.get::<Classification>("wake"),
+        Err(Error::UnknownForest)
+    ));
+    assert!(matches!(
+        bundle.get::<Classification>("activit"),
+        Err(Error::UnknownForest)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn bundle_rejects_invalid_forests_and_names() -> Result<()> {
+    let (iris, airfoil, _, _) = models()?;
+
+    assert!(bundle(&[("activity", &iris), ("activity", &airfoil)]).is_err());
+    assert!(bundle(&[("", &iris)]).is_err());
+    assert!(bundle(&[(&"a".repeat(33), &iris)]).is_err());
+    assert!(bundle(&[("activity", &iris[..10])]).is_err());
+    assert!(bundle(&[(&"a".repeat(32), &iris)]).is_ok());
+
+    let empty = bundle(&[])?;
+    assert!(ForestBundle::deserialize(&empty).unwrap().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn corrupted_bundle_tables_are_rejected() -> Result<()> {
+    let (iris, airfoil, _, _) = models()?;
+    let bundled = bundle(&[("activity", &iris), ("anomaly", &airfoil)])?;
+    // The second entry of the table
+    let entry = bundle_header_len(0) + 48;
+    let corrupt = |change: &dyn Fn(&mut [u8])| {
+        let mut corrupted = AVec::<u8>::from_slice(BUFFER_ALIGN, &bundled);
+        change(&mut corrupted);
+        ForestBundle::deserialize(&corrupted).map(|_| ())
+    };
+
+    // Name collision
+    assert_eq!(
+        corrupt(&|b| b[entry..entry + 8].copy_from_slice(b"activity")),
+        Err(Error::DuplicateForest)
+    );
+    // Misaligned entry
+    let offset = entry + 32;
+    assert_eq!(corrupt(&|b| b[offset] += 4), Err(Error::Misaligned));
+    // Entries out of the buffer, or overlapping the table
+    assert_eq!(
+        corrupt(&|b| b[offset + 4..offset + 8].copy_from_slice(&u32::MAX.to_le_bytes())),
+        Err(Error::MalformedForest)
+    );
+    assert_eq!(
+        corrupt(&|b| b[offset..offset + 4].copy_from_slice(&8u32.to_le_bytes())),
+        Err(Error::MalformedForest)
+    );
+    // Unknown problem type, and a problem type the forest disagrees with
+    assert_eq!(corrupt(&|b| b[offset + 8] = 2), Err(Error::MalformedForest));
+    let mut mislabeled = AVec::<u8>::from_slice(BUFFER_ALIGN, &bundled);
+    mislabeled[offset + 8] = 0;
+    let bundle = ForestBundle::deserialize(&mislabeled).unwrap();
+    assert!(matches!(
+        bundle.get_any::<Classification>("anomaly"),
+        Err(Error::MalformedForest)
+    ));
+
+    // Not a bundle, or a truncated one
+    assert_eq!(corrupt(&|b| b[0] = b'X'), Err(Error::MalformedForest));
+    assert!(ForestBundle::deserialize(&bundled[..bundled.len() - 8]).is_err());
+    assert!(ForestBundle::deserialize(&iris).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use std::fs;
+use std::time::Duration;
+
+use color_eyre::Result;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::forest::cache::{cache_path, read_cached};
+use forest_optimizer::problem_type::{Classification, ProblemType, Regression};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+
+use crate::helpers::get_forest;
+
+fn assert_same_trees<P: ProblemType>(loaded: &Forest<P>, forest: &Forest<P>) {
+    assert_eq!(loaded.num_trees(), forest.num_trees());
+    assert_eq!(loaded.nodes(), forest.nodes());
+    assert_eq!(loaded.features(), forest.features());
+    for (loaded, tree) in loaded.trees().zip(forest.trees()) {
+        assert_eq!(loaded.num_nodes(), tree.num_nodes());
+    }
+}
+
+#[test]
+fn classification_forest_round_trips() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("iris.forest");
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+
+    forest.save(&path)?;
+    let loaded = Forest::<Classification>::load(&path)?;
+
+    assert_same_trees(&loaded, &forest);
+    assert_eq!(loaded.targets(), forest.targets());
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    for row in &rows {
+        assert_eq!(loaded.predict(row), forest.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn regression_forest_round_trips() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("airfoil.forest");
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+
+    forest.save(&path)?;
+    let loaded = Forest::<Regression>::load(&path)?;
+
+    assert_same_trees(&loaded, &forest);
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+    for row in &rows {
+        assert_eq!(loaded.predict(row), forest.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn loading_rejects_other_problems_and_truncated_files() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("iris.forest");
+    get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?
+        .save(&path)?;
+
+    let error = Forest::<Regression>::load(&path).unwrap_err();
+    assert!(error.to_string().contains("holds a CLASSIFICATION forest"));
+
+    let bytes = fs::read(&path)?;
+    fs::write(&path, &bytes[..bytes.len() / 2])?;
+    assert!(Forest::<Classification>::load(&path).is_err());
+
+    Ok(())
+}
+
+/// A regression forest of one tree, made of `nodes`, splitting on feature
+/// `x` only
+fn regression_trees(nodes: &str) -> String {
+    format!(r#"{{ "problem": {{ "features": {{ "x": 0 }} }}, "trees": [[{nodes}]] }}"#)
+}
+
+fn branch(split_with: u32, left: u32, right: u32) -> String {
+    format!(
+        r#"{{ "Branch": {{ "split_with": {split_with}, "split_at": 0.5, "left": {left}, "right": {right} }} }}"#
+    )
+}
+
+const LEAF: &str = r#"{ "Leaf": { "prediction": 1.0 } }"#;
+
+#[test]
+fn deserialized_trees_are_checked() -> Result<()> {
+    let load = |nodes: &[String]| {
+        serde_json::from_str::<Forest<Regression>>(&regression_trees(&nodes.join(",")))
+            .map_err(|e| e.to_string())
+    };
+    let leaf = || LEAF.to_string();
+
+    let forest = load(&[branch(0, 1, 2), leaf(), leaf()]).unwrap();
+    assert_eq!(forest.nodes().len(), 3);
+
+    let cases = [
+        (vec![branch(1, 1, 2), leaf(), leaf()], "unknown feature 1"),
+        (vec![branch(0, 0, 2), leaf(), leaf()], "points to node 0"),
+        (vec![branch(0, 1, 3), leaf(), leaf()], "points to node 3"),
+        (
+            vec![branch(0, 1, 1), leaf(), leaf()],
+            "Node 1 of tree 0 has 2 parents",
+        ),
+        (
+            vec![branch(0, 1, 2), leaf(), leaf(), leaf()],
+            "Node 3 of tree 0 has 0 parents",
+        ),
+        (vec![], "Tree 0 has no nodes"),
+    ];
+    for (nodes, message) in cases {
+        let error = load(&nodes).unwrap_err();
+        assert!(error.contains(message), "{error}");
+    }
+
+    let classes = r#"{ "problem": { "targets": { "a": 0 }, "features": { "x": 0 } },
+        "trees": [[{ "Leaf": { "prediction": 1 } }]] }"#;
+    let error = serde_json::from_str::<Forest<Classification>>(classes).unwrap_err();
+    assert!(error.to_string().contains("unknown output 1"));
+
+    Ok(())
+}
+
+#[test]
+fn cache_is_used_while_the_input_is_unchanged() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("forest.csv");
+    fs::copy("./tests/test-forests/forest_iris_5.csv", &input)?;
+    let cache = cache_path(&input);
+    assert_eq!(cache, dir.path().join("forest.csv.forest-cache"));
+
+    let forest = read_cached::<SerializedClassificationNode>(&input)?;
+    assert_same_trees(&Forest::<Classification>::load(&cache)?, &forest);
+
+    // Same size and modification time: the input is not read again
+    let original = fs::read(&input)?;
+    let modified = fs::metadata(&input)?.modified()?;
+    let set_modified = |time| {
+        fs::File::options()
+            .write(true)
+            .open(&input)?
+            .set_modified(time)
+    };
+    fs::write(&input, vec![b'#'; original.len()])?;
+    set_modified(modified)?;
+    assert_same_trees(
+        &read_cached::<SerializedClassificationNode>(&input)?,
+        &forest,
+    );
+
+    // Another modification time: the contents are compared
+    set_modified(modified + Duration::from_secs(60))?;
+    assert!(read_cached::<SerializedClassificationNode>(&input).is_err());
+    fs::write(&input, &original)?;
+    set_modified(modified + Duration::from_secs(120))?;
+    assert_same_trees(
+        &read_cached::<SerializedClassificationNode>(&input)?,
+        &forest,
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cache_is_rebuilt_when_stale_or_invalid() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("forest.csv");
+    fs::copy("./tests/test-forests/forest_iris_5.csv", &input)?;
+    let cache = cache_path(&input);
+    read_cached::<SerializedClassificationNode>(&input)?;
+
+    fs::copy("./tests/test-forests/forest_iris_800.csv", &input)?;
+    assert_eq!(
+        read_cached::<SerializedClassificationNode>(&input)?.num_trees(),
+        800
+    );
+    assert_eq!(Forest::<Classification>::load(&cache)?.num_trees(), 800);
+
+    // A cache which does not decode is replaced
+    let bytes = fs::read(&cache)?;
+    fs::write(&cache, &bytes[..bytes.len() / 2])?;
+    assert_eq!(
+        read_cached::<SerializedClassificationNode>(&input)?.num_trees(),
+        800
+    );
+    assert_eq!(Forest::<Classification>::load(&cache)?.num_trees(), 800);
+
+    // So is a cache of another problem type
+    let regression = dir.path().join("airfoil.csv");
+    fs::copy("./tests/test-forests/airfoil_100_200.csv", &regression)?;
+    fs::copy(&cache, cache_path(&regression))?;
+    let forest = read_cached::<SerializedRegressionNode>(&regression)?;
+    assert_eq!(
+        Forest::<Regression>::load(cache_path(&regression))?.nodes(),
+        forest.nodes()
+    );
+
+    Ok(())
+}
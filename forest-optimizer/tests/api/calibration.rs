@@ -0,0 +1,223 @@
+use aligned_vec::AVec;
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::deserialize::{BUFFER_ALIGN, CALIBRATION, ForestHeader, HEADER_LEN};
+use embedded_rforest::forest::{Classification, OptimizedForest, Platt};
+use forest_optimizer::calibration::{Calibration, PlattParameters};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::inspect::inspect;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{NodeEncoding, WriteForest};
+use predicates::str::contains;
+
+use crate::helpers::{FLAGS, assert_epsilon, classifier, get_forest, stumps};
+
+/// Platt parameters of the 3 classes of [`VOTES`]
+const PLATT: [Platt; 3] = [
+    Platt::new(-4.0, 2.0),
+    Platt::new(-3.5, 1.5),
+    Platt::new(-5.0, 2.5),
+];
+
+/// Leaves of four [`stumps`]: 3 votes for class 0 and 1 for class 1 on the
+/// left, 1 vote for class 1 and 3 for class 2 on the right
+const VOTES: [(u32, u32); 4] = [(0, 1), (0, 2), (1, 2), (0, 2)];
+
+fn probabilities(forest: &OptimizedForest<Classification>, x: f32) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    forest.predict_proba(&[x], &mut out).unwrap();
+    out
+}
+
+#[test]
+fn uncalibrated_forests_return_vote_fractions() {
+    let nodes = stumps(&VOTES);
+    let forest = classifier(&nodes, 3);
+    assert!(forest.calibration().is_empty());
+    assert_eq!(probabilities(&forest, 0.0), [0.75, 0.25, 0.0]);
+    assert_eq!(probabilities(&forest, 1.0), [0.0, 0.25, 0.75]);
+
+    let bytes = forest.to_bytes();
+    assert_eq!(bytes[FLAGS] & CALIBRATION, 0);
+    assert!(!ForestHeader::peek(&bytes).unwrap().calibrated);
+    assert!(
+        inspect(&bytes)
+            .unwrap()
+            .to_string()
+            .contains("Calibration:     none")
+    );
+
+    for len in [0, 2, 4] {
+        assert_eq!(
+            forest.predict_proba(&[0.0], &mut vec![0.0; len]),
+            Err(Error::WrongOutputLength)
+        );
+    }
+}
+
+#[test]
+fn calibrated_forests_match_the_reference() {
+    let nodes = stumps(&VOTES);
+    let calibrated = classifier(&nodes, 3).with_calibration(&PLATT).unwrap();
+    assert_eq!(calibrated.calibration(), PLATT);
+
+    // Computed with Python: each fraction through `1 / (1 + exp(a * f + b))`,
+    // then normalized
+    let reference = [
+        (0.0, [0.632_643_4, 0.301_710_5, 0.065_646_14]),
+        (1.0, [0.095_733_95, 0.280_003, 0.624_263_1]),
+    ];
+    let check = |forest: &OptimizedForest<Classification>| {
+        for (x, expected) in reference {
+            let probabilities = probabilities(forest, x);
+            for (p, expected) in probabilities.into_iter().zip(expected) {
+                assert_epsilon(p, expected, 1e-4);
+            }
+            assert_epsilon(probabilities.iter().sum(), 1.0, 1e-6);
+        }
+    };
+    check(&calibrated);
+
+    // The parameters take 8 bytes per class of the header
+    let bytes = calibrated.to_bytes();
+    assert_ne!(bytes[FLAGS] & CALIBRATION, 0);
+    assert_eq!(bytes.len(), classifier(&nodes, 3).to_bytes().len() + 3 * 8);
+    let header = ForestHeader::peek(&bytes).unwrap();
+    assert!(header.calibrated);
+    assert_eq!(header.serialized_len(), bytes.len());
+    let loaded = OptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.calibration(), PLATT);
+    assert_eq!(loaded.to_bytes(), bytes);
+    check(&loaded);
+
+    let info = inspect(&bytes).unwrap();
+    assert!(info.calibrated);
+    assert!(info.to_string().contains("Calibration:     platt"));
+
+    // Right after the header: the `a` then `b` of every class
+    let mut malformed = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+    malformed[HEADER_LEN + 4..HEADER_LEN + 8].copy_from_slice(&f32::NAN.to_le_bytes());
+    assert_eq!(
+        ForestHeader::peek(&malformed).err(),
+        Some(Error::MalformedForest)
+    );
+}
+
+#[test]
+fn calibrations_need_finite_parameters_for_every_class() {
+    let nodes = stumps(&VOTES);
+    assert_eq!(
+        classifier(&nodes, 3).with_calibration(&PLATT[..2]).err(),
+        Some(Error::MalformedForest)
+    );
+    assert_eq!(
+        classifier(&nodes, 3)
+            .with_calibration(&[PLATT[0], PLATT[1], Platt::new(f32::INFINITY, 0.0)])
+            .err(),
+        Some(Error::MalformedForest)
+    );
+}
+
+/// Calibration of the classes of the iris forests
+fn iris_calibration() -> Calibration {
+    Calibration::new([
+        ("setosa".to_string(), PlattParameters { a: -6.2, b: 3.1 }),
+        (
+            "versicolor".to_string(),
+            PlattParameters { a: -5.8, b: 2.9 },
+        ),
+        ("virginica".to_string(), PlattParameters { a: -6.0, b: 3.0 }),
+    ])
+}
+
+#[test]
+fn serialized_forests_carry_the_calibration() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+
+    let encoding = NodeEncoding {
+        calibration: Some(iris_calibration()),
+        ..Default::default()
+    };
+    let bytes = ClassificationProblem::serialize_encoded(&forest, &encoding)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+    let calibration = iris_calibration().for_forest(&forest)?;
+    assert_eq!(optimized.calibration(), calibration);
+
+    for row in &rows {
+        // Votes of the host forest, calibrated in f64
+        let mut fractions = [0.0f64; 3];
+        for tree in forest.trees() {
+            fractions[tree.predict(row) as usize] += 1.0 / forest.num_trees() as f64;
+        }
+        let calibrated = fractions
+            .iter()
+            .zip(&calibration)
+            .map(|(f, p)| 1.0 / (1.0 + (f64::from(p.a()) * f + f64::from(p.b())).exp()))
+            .collect::<Vec<_>>();
+        let sum = calibrated.iter().sum::<f64>();
+
+        let mut probabilities = [0.0; 3];
+        optimized.predict_proba(row, &mut probabilities).unwrap();
+        for (p, expected) in probabilities.into_iter().zip(&calibrated) {
+            assert_epsilon(p, (expected / sum) as f32, 1e-4);
+        }
+    }
+
+    // Every class needs parameters, and only classes of the forest
+    let partial = Calibration::new([
+        ("setosa".to_string(), PlattParameters { a: -6.2, b: 3.1 }),
+        (
+            "versicolor".to_string(),
+            PlattParameters { a: -5.8, b: 2.9 },
+        ),
+    ]);
+    assert!(partial.for_forest(&forest).is_err());
+    let extra = Calibration::new([("daisy".to_string(), PlattParameters { a: -1.0, b: 0.0 })]);
+    assert!(extra.for_forest(&forest).is_err());
+
+    // Regression forests have no classes to calibrate
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    assert!(RegressionProblem::serialize_encoded(&forest, &encoding).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn convert_records_the_calibration() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let calibration = dir.path().join("platt.json");
+    std::fs::write(&calibration, serde_json::to_string(&iris_calibration())?)?;
+    let output = dir.path().join("iris.rforest");
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .arg("--calibration")
+        .arg(&calibration)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    let bytes = AVec::<u8>::from_slice(BUFFER_ALIGN, &std::fs::read(&output)?);
+    assert!(ForestHeader::peek(&bytes).unwrap().calibrated);
+
+    std::fs::write(&calibration, r#"{ "setosa": { "a": -6.2 } }"#)?;
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .arg("--calibration")
+        .arg(&calibration)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(contains("Could not parse calibration file"));
+
+    Ok(())
+}
@@ -0,0 +1,1505 @@
+use std::path::Path;
+
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::forest::ForestBundle;
+use forest_optimizer::{inspect::read_model, metadata::ForestMetadata};
+use predicates::{prelude::PredicateBooleanExt, str::contains};
+
+use crate::helpers::reversed_rows;
+
+fn forest_optimizer() -> Command {
+    Command::cargo_bin("forest-optimizer").unwrap()
+}
+
+#[test]
+fn convert_writes_forest_and_metadata() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+
+    forest_optimizer()
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["-p", "classification", "-o"])
+        .arg(&output)
+        .assert()
+        .success();
+
+    let expected = std::fs::read("./tests/test-forests/forest_iris_5.rforest")?;
+    assert_eq!(std::fs::read(&output)?, expected);
+    assert!(dir.path().join("iris.rforest.meta.json").exists());
+
+    Ok(())
+}
+
+#[test]
+fn convert_rejects_wrong_problem_type() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    forest_optimizer()
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["-p", "regression", "-o"])
+        .arg(dir.path().join("iris.rforest"))
+        .assert()
+        .failure()
+        .stderr(contains("declares a CLASSIFICATION forest"));
+
+    Ok(())
+}
+
+#[test]
+fn convert_detects_problem_type() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("airfoil.rforest");
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/airfoil_100_200.csv",
+            "-o",
+        ])
+        .arg(&output)
+        .assert()
+        .success();
+
+    let expected = std::fs::read("./tests/test-forests/airfoil_100_200.rforest")?;
+    assert_eq!(std::fs::read(&output)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn convert_requires_problem_type_without_header() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let definition = std::fs::read_to_string("./tests/test-forests/forest_iris_5.csv")?;
+    let input = dir.path().join("headerless.csv");
+    std::fs::write(&input, definition.split_once('\n').unwrap().1)?;
+
+    forest_optimizer()
+        .args(["convert", "-i"])
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("iris.rforest"))
+        .assert()
+        .failure()
+        .stderr(contains("--problem-type"));
+
+    forest_optimizer()
+        .args(["convert", "-p", "classification", "-i"])
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("iris.rforest"))
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn convert_emits_every_format() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+            "-o",
+        ])
+        .arg(&output)
+        .args([
+            "-f",
+            "rforest",
+            "-f",
+            "c-header",
+            "-f",
+            "rust-module",
+            "-f",
+            "json",
+        ])
+        .assert()
+        .success();
+
+    let bytes = std::fs::read(&output)?;
+    assert_eq!(
+        bytes,
+        std::fs::read("./tests/test-forests/forest_iris_5.rforest")?
+    );
+    assert!(dir.path().join("iris.rforest.meta.json").exists());
+
+    // The C header holds the same bytes
+    let header = std::fs::read_to_string(dir.path().join("iris.h"))?;
+    let array = header.split_once('{').unwrap().1.split_once('}').unwrap().0;
+    let header_bytes = array
+        .split(',')
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(|b| u8::from_str_radix(b.trim_start_matches("0x"), 16))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(header_bytes, bytes);
+    assert!(header.contains(&format!("iris_len = {};", bytes.len())));
+
+    let module = std::fs::read_to_string(dir.path().join("iris.rs"))?;
+    assert!(module.contains(&format!("pub static IRIS: BackingStorage<{}>", bytes.len())));
+    assert!(module.contains("pub const IRIS_VALIDATED: bool = true;"));
+
+    // The JSON dump describes the same nodes
+    let info = forest_optimizer::inspect::inspect(&read_model(&output)?)?;
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.path().join("iris.json"))?)?;
+    assert_eq!(json["num_trees"], info.num_trees);
+    assert_eq!(json["nodes"].as_array().unwrap().len(), info.node_count);
+    assert_eq!(json["problem_type"], "Classification");
+
+    Ok(())
+}
+
+#[test]
+fn convert_writes_nothing_if_an_artifact_fails() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+            "-o",
+        ])
+        .arg(&output)
+        .args(["-f", "rforest", "-f"])
+        .arg(format!(
+            "json={}",
+            dir.path().join("missing/iris.json").display()
+        ))
+        .assert()
+        .failure();
+
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn convert_writes_nothing_if_serialization_fails() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+
+    // Classification forests do not transform their output
+    forest_optimizer()
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args([
+            "--transform",
+            "sigmoid",
+            "-f",
+            "rforest",
+            "-f",
+            "c-header",
+            "-o",
+        ])
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(contains("transform"));
+
+    // Not even a temporary file
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn convert_refuses_to_overwrite_without_force() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+    let convert = |input: &str, force: bool| {
+        let mut command = forest_optimizer();
+        command.args(["convert", "-i", input, "-o"]).arg(&output);
+        if force {
+            command.arg("--force");
+        }
+        command.assert()
+    };
+
+    convert("./tests/test-forests/forest_iris_5.csv", false).success();
+    let written = std::fs::read(&output)?;
+    convert("./tests/test-forests/forest_iris_800.csv", false)
+        .failure()
+        .stderr(contains("already exists. Pass --force to overwrite it"));
+    assert_eq!(std::fs::read(&output)?, written);
+
+    // Nor its metadata alone
+    std::fs::remove_file(&output)?;
+    convert("./tests/test-forests/forest_iris_800.csv", false)
+        .failure()
+        .stderr(contains("iris.rforest.meta.json already exists"));
+
+    convert("./tests/test-forests/forest_iris_800.csv", true).success();
+    assert_ne!(std::fs::read(&output)?, written);
+
+    Ok(())
+}
+
+#[test]
+fn convert_batch_refuses_to_overwrite_without_force() -> Result<()> {
+    let input = tempfile::tempdir()?;
+    let output = tempfile::tempdir()?;
+    std::fs::copy(
+        "./tests/test-forests/forest_iris_5.csv",
+        input.path().join("iris.csv"),
+    )?;
+    let existing = output.path().join("iris.rforest");
+    std::fs::write(&existing, "not ours")?;
+    let convert = |force: bool| -> Result<_> {
+        let mut command = forest_optimizer();
+        command.args(["convert", "--input-dir"]).arg(input.path());
+        command.arg("--output-dir").arg(output.path());
+        if force {
+            command.arg("--force");
+        }
+        Ok(command.assert())
+    };
+
+    convert(false)?
+        .failure()
+        .stdout(contains("iris.csv  FAILED"))
+        .stdout(contains("already exists. Pass --force to overwrite it"));
+    assert_eq!(std::fs::read(&existing)?, b"not ours");
+    assert!(!output.path().join("iris.rforest.meta.json").exists());
+
+    convert(true)?.success();
+    let expected = std::fs::read("./tests/test-forests/forest_iris_5.rforest")?;
+    assert_eq!(std::fs::read(&existing)?, expected);
+
+    // The metadata sidecar is not replaced either
+    std::fs::remove_file(&existing)?;
+    convert(false)?
+        .failure()
+        .stdout(contains("meta.json already exists"));
+    assert!(!existing.exists());
+
+    Ok(())
+}
+
+#[test]
+fn convert_batch_reports_failures() -> Result<()> {
+    let input = tempfile::tempdir()?;
+    let output = tempfile::tempdir()?;
+    std::fs::copy(
+        "./tests/test-forests/forest_iris_5.csv",
+        input.path().join("good.csv"),
+    )?;
+    std::fs::write(
+        input.path().join("corrupt.csv"),
+        "# { \"problem_type\": \"classification\" }\nnot,a,forest\n",
+    )?;
+
+    forest_optimizer()
+        .args(["convert", "--jobs", "2", "--input-dir"])
+        .arg(input.path())
+        .arg("--output-dir")
+        .arg(output.path())
+        .assert()
+        .failure()
+        .stdout(contains("good.csv"))
+        .stdout(contains("corrupt.csv  FAILED"))
+        .stdout(contains("1 converted, 1 failed"));
+
+    let expected = std::fs::read("./tests/test-forests/forest_iris_5.rforest")?;
+    assert_eq!(std::fs::read(output.path().join("good.rforest"))?, expected);
+    assert!(output.path().join("good.rforest.meta.json").exists());
+    assert!(!output.path().join("corrupt.rforest").exists());
+
+    Ok(())
+}
+
+#[test]
+fn convert_dry_run_matches_real_size() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("airfoil.rforest");
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/airfoil_100_200.csv",
+            "-o",
+        ])
+        .arg(&output)
+        .assert()
+        .success();
+    let size = std::fs::metadata(&output)?.len();
+
+    let dry_output = dir.path().join("dry.rforest");
+    forest_optimizer()
+        .args([
+            "convert",
+            "--dry-run",
+            "-i",
+            "./tests/test-forests/airfoil_100_200.csv",
+        ])
+        .arg("-o")
+        .arg(&dry_output)
+        .assert()
+        .success()
+        .stdout(contains(format!("Serialized size: {size} bytes")));
+    assert!(!dry_output.exists());
+    assert!(!ForestMetadata::sidecar_path(&dry_output).exists());
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "--dry-run",
+            "-i",
+            "./tests/test-forests/airfoil_100_200.csv",
+        ])
+        .arg("--max-size-bytes")
+        .arg((size - 1).to_string())
+        .assert()
+        .failure()
+        .stdout(contains("Exceeds the maximum size"));
+
+    Ok(())
+}
+
+#[test]
+fn convert_dry_run_matches_real_size_of_every_encoding() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let key = "0f".repeat(32);
+    let cases: [(&str, &[&str]); 6] = [
+        ("forest_iris_5.csv", &["--pointer-width", "16"]),
+        (
+            "forest_iris_5_stats.csv",
+            &["--support", "--pointer-width", "auto"],
+        ),
+        (
+            "airfoil_100_200.csv",
+            &["--transform", "exp", "--pointer-width", "relative"],
+        ),
+        (
+            "forest_iris_5.csv",
+            &[
+                "--layout",
+                "profiled",
+                "--profile-data",
+                "./tests/test-data/iris.csv",
+            ],
+        ),
+        ("forest_iris_5.csv", &["--encrypt-key", &key]),
+        (
+            "airfoil_100_200.csv",
+            &["--encrypt-key", &key, "--transform", "sigmoid"],
+        ),
+    ];
+
+    for (input, encoding) in cases {
+        let input = format!("./tests/test-forests/{input}");
+        let output = dir.path().join("forest.rforest");
+        forest_optimizer()
+            .args(["convert", "--force", "-i", &input])
+            .args(encoding)
+            .arg("-o")
+            .arg(&output)
+            .assert()
+            .success();
+        let size = std::fs::metadata(&output)?.len();
+
+        forest_optimizer()
+            .args(["convert", "--dry-run", "-i", &input])
+            .args(encoding)
+            .assert()
+            .success()
+            .stdout(contains(format!("Serialized size: {size} bytes")));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn analyze_reports_pruning() {
+    forest_optimizer()
+        .args(["analyze", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["-p", "classification"])
+        .assert()
+        .success()
+        .stdout(contains("Forest is a CLASSIFICATION problem."))
+        .stdout(contains("Pruned"));
+}
+
+#[test]
+fn analyze_caches_the_forest() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("iris.csv");
+    std::fs::copy("./tests/test-forests/forest_iris_5.csv", &input)?;
+
+    let analyze = || {
+        forest_optimizer()
+            .args(["analyze", "--cache", "-i"])
+            .arg(&input)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone()
+    };
+    let uncached = analyze();
+    assert!(dir.path().join("iris.csv.forest-cache").exists());
+    assert_eq!(analyze(), uncached);
+
+    Ok(())
+}
+
+#[test]
+fn legacy_binaries_still_work() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("airfoil.rforest");
+
+    Command::cargo_bin("optimize_forest")?
+        .args(["-i", "./tests/test-forests/airfoil_100_200.csv"])
+        .args(["-p", "regression", "-o"])
+        .arg(&output)
+        .assert()
+        .success()
+        .stderr(contains("deprecated"));
+    assert!(output.exists());
+
+    Command::cargo_bin("analyze_forest")?
+        .args(["-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["-p", "classification"])
+        .assert()
+        .success()
+        .stdout(contains("Pruned"));
+
+    Ok(())
+}
+
+#[test]
+fn info_reports_fixture() {
+    forest_optimizer()
+        .args(["info", "./tests/test-forests/forest_iris_5.rforest"])
+        .assert()
+        .success()
+        .stdout(contains("Trees:           5"));
+}
+
+#[test]
+fn bundle_writes_named_forests() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("device.rfbundle");
+
+    forest_optimizer()
+        .args([
+            "bundle",
+            "-i",
+            "activity=./tests/test-forests/forest_iris_5.rforest",
+        ])
+        .args(["-i", "./tests/test-forests/airfoil_100_200.rforest", "-o"])
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(contains("activity").and(contains("airfoil_100_200")));
+
+    let bundled = read_model(&output)?;
+    let bundle = ForestBundle::deserialize(&bundled).unwrap();
+    assert_eq!(
+        bundle.names().collect::<Vec<_>>(),
+        ["activity", "airfoil_100_200"]
+    );
+
+    // Names are unique
+    forest_optimizer()
+        .args([
+            "bundle",
+            "-i",
+            "a=./tests/test-forests/forest_iris_5.rforest",
+        ])
+        .args(["-i", "a=./tests/test-forests/airfoil_100_200.rforest", "-o"])
+        .arg(dir.path().join("twice.rfbundle"))
+        .assert()
+        .failure()
+        .stderr(contains("twice"));
+
+    Ok(())
+}
+
+#[test]
+fn bench_runs_on_iris() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+            "-o",
+        ])
+        .arg(&model)
+        .assert()
+        .success();
+
+    let output = forest_optimizer()
+        .args([
+            "bench",
+            "-d",
+            "./tests/test-data/iris.csv",
+            "-n",
+            "2",
+            "--json",
+            "-m",
+        ])
+        .arg(&model)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let report: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(report["predictions"], 2 * 150);
+    assert!(report["mean_visits"].as_f64().unwrap() >= 5.0);
+
+    // Through the storage cache, every branch is visited the same
+    let output = forest_optimizer()
+        .args([
+            "bench",
+            "-d",
+            "./tests/test-data/iris.csv",
+            "-n",
+            "2",
+            "--json",
+            "--compare-storage",
+            "-m",
+        ])
+        .arg(&model)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let reports: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(reports["cached"]["predictions"], 2 * 150);
+    assert_eq!(
+        reports["cached"]["mean_visits"],
+        reports["slice"]["mean_visits"]
+    );
+
+    // Loading is timed instead of predicting
+    let output = forest_optimizer()
+        .args([
+            "bench",
+            "-d",
+            "./tests/test-data/iris.csv",
+            "-n",
+            "2",
+            "--json",
+            "--compare-load",
+            "-m",
+        ])
+        .arg(&model)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let comparison: serde_json::Value = serde_json::from_slice(&output)?;
+    assert!(comparison["checked_ns"].as_f64().unwrap() > 0.0);
+    assert!(comparison["unchecked_ns"].as_f64().unwrap() > 0.0);
+
+    // Tree walks against QuickScorer, whose trees must fit the leaf cap
+    let output = forest_optimizer()
+        .args([
+            "bench",
+            "-d",
+            "./tests/test-data/iris.csv",
+            "-n",
+            "2",
+            "--json",
+            "--compare-quickscorer",
+            "-m",
+        ])
+        .arg(&model)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let reports: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(reports["quickscorer"]["predictions"], 2 * 150);
+    assert_eq!(reports["walk"]["predictions"], 2 * 150);
+
+    forest_optimizer()
+        .args([
+            "bench",
+            "-d",
+            "./tests/test-data/iris.csv",
+            "--compare-quickscorer",
+            "--qs-max-leaves",
+            "2",
+            "-m",
+        ])
+        .arg(&model)
+        .assert()
+        .failure()
+        .stderr(contains("TooManyLeaves"));
+
+    Ok(())
+}
+
+#[test]
+fn bench_times_the_conversion_of_a_definition() -> Result<()> {
+    // No model nor dataset needed
+    let output = forest_optimizer()
+        .args([
+            "bench",
+            "-n",
+            "2",
+            "--json",
+            "--optimize",
+            "./tests/test-forests/forest_iris_5.csv",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let report: serde_json::Value = serde_json::from_slice(&output)?;
+    assert!(report["mean_ns"].as_f64().unwrap() > 0.0);
+    assert!(report["branches"].as_u64().unwrap() < report["nodes"].as_u64().unwrap());
+    assert!(report["allocated_bytes"].as_u64().unwrap() < report["forest_bytes"].as_u64().unwrap());
+
+    // Grouping the rows by tree, on a forest of three times the trees
+    let output = forest_optimizer()
+        .args([
+            "bench",
+            "-n",
+            "2",
+            "--json",
+            "--repeat",
+            "3",
+            "--flatten",
+            "./tests/test-forests/forest_iris_5.csv",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let report: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(report["trees"], 15);
+    assert!(report["mean_ns"].as_f64().unwrap() > 0.0);
+
+    // Both layouts of a definition, profiled on the dataset
+    let output = forest_optimizer()
+        .args([
+            "bench",
+            "-n",
+            "2",
+            "--json",
+            "-d",
+            "./tests/test-data/iris.csv",
+            "--compare-layout",
+            "./tests/test-forests/forest_iris_5.csv",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let comparison: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(comparison["profiled"]["predictions"], 2 * 150);
+    assert_eq!(
+        comparison["profiled"]["mean_visits"],
+        comparison["depth_first"]["mean_visits"]
+    );
+    assert!(
+        comparison["profiled_sequential"].as_f64().unwrap()
+            > comparison["depth_first_sequential"].as_f64().unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn analyze_writes_per_tree_csv() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let csv = dir.path().join("trees.csv");
+
+    forest_optimizer()
+        .args([
+            "analyze",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+            "--per-tree-csv",
+        ])
+        .arg(&csv)
+        .assert()
+        .success()
+        .stdout(contains("Largest trees"));
+
+    let rows = std::fs::read_to_string(&csv)?;
+    let rows = rows.lines().collect::<Vec<_>>();
+    // Header, 5 trees, totals
+    assert_eq!(rows.len(), 7);
+    assert!(rows[6].starts_with("total,"));
+
+    Ok(())
+}
+
+#[test]
+fn analyze_estimates_latency_with_the_given_model() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (profile, report) = (
+        dir.path().join("profile.json"),
+        dir.path().join("report.json"),
+    );
+
+    forest_optimizer()
+        .args(["convert", "--layout", "profiled", "--profile-data"])
+        .arg("./tests/test-data/iris.csv")
+        .args(["-i", "./tests/test-forests/forest_iris_5.csv", "-o"])
+        .arg(dir.path().join("iris.rforest"))
+        .arg("--save-profile")
+        .arg(&profile)
+        .assert()
+        .success();
+    forest_optimizer()
+        .args(["analyze", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["--cycles-per-node", "20", "--clock-mhz", "100", "--profile"])
+        .arg(&profile)
+        .arg("--report-json")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(contains("20 cycles per branch at 100 MHz").and(contains("(profiled)")));
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report)?)?;
+    let latency = &report["latency"];
+    assert_eq!(latency["model"]["cycles_per_node"], 20.0);
+    assert_eq!(latency["visits"]["profiled"], true);
+    let (best, expected, worst) = (
+        latency["best_us"].as_f64().unwrap(),
+        latency["expected_us"].as_f64().unwrap(),
+        latency["worst_us"].as_f64().unwrap(),
+    );
+    assert!(best <= expected && expected <= worst, "{latency}");
+
+    forest_optimizer()
+        .args(["analyze", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["--clock-mhz", "0"])
+        .assert()
+        .failure()
+        .stderr(contains("expected a positive number"));
+
+    Ok(())
+}
+
+#[test]
+fn analyze_reads_serialized_forests() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (model, report) = (
+        dir.path().join("iris.rforest"),
+        dir.path().join("report.json"),
+    );
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+            "-o",
+        ])
+        .arg(&model)
+        .assert()
+        .success();
+    // Features are named after the metadata written next to the forest
+    forest_optimizer()
+        .args(["analyze", "-i"])
+        .arg(&model)
+        .arg("--report-json")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(
+            contains("Petal.Length").and(contains("Unavailable without the forest definition")),
+        );
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report)?)?;
+    assert_eq!(report["info"]["num_trees"], 5);
+    assert_eq!(report["trees"].as_array().unwrap().len(), 5);
+    assert_eq!(report["feature_usage"][0]["name"], "Petal.Length");
+
+    forest_optimizer()
+        .args([
+            "analyze",
+            "-i",
+            "./tests/test-forests/forest_iris_5.rforest",
+        ])
+        .args([
+            "--verify-data",
+            "./tests/test-data/iris.csv",
+            "-l",
+            "Species",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("--verify-data needs the forest definition (CSV)"));
+
+    Ok(())
+}
+
+#[test]
+fn prune_shrinks_iris_within_accuracy_budget() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+    let report = dir.path().join("report.json");
+
+    forest_optimizer()
+        .args([
+            "prune",
+            "-i",
+            "./tests/test-forests/forest_iris_800.csv",
+            "--collapse-redundant",
+            "--max-trees",
+            "200",
+            "--validation",
+            "./tests/test-data/iris.csv",
+            "--label-column",
+            "Species",
+            "--max-accuracy-drop",
+            "0.5",
+            "-o",
+        ])
+        .arg(&model)
+        .arg("--report-json")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(contains("Accuracy (%)"));
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report)?)?;
+    let (before, after) = (&report["before"], &report["after"]);
+    assert_eq!(before["trees"], 800);
+    assert!(after["trees"].as_u64().unwrap() <= 200);
+    assert!(after["serialized_size"].as_u64() < before["serialized_size"].as_u64());
+    assert!(after["score"].as_f64().unwrap() >= before["score"].as_f64().unwrap() - 0.5);
+
+    let metadata = ForestMetadata::read(ForestMetadata::sidecar_path(&model))?;
+    assert_eq!(metadata.features.len(), 4);
+    read_model(&model)?;
+
+    Ok(())
+}
+
+#[test]
+fn prune_truncates_deep_trees() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+    let report = dir.path().join("report.json");
+
+    forest_optimizer()
+        .args([
+            "prune",
+            "-i",
+            "./tests/test-forests/forest_iris_800.csv",
+            "--max-depth",
+            "2",
+            "--validation",
+            "./tests/test-data/iris.csv",
+            "--label-column",
+            "Species",
+            "-o",
+        ])
+        .arg(&model)
+        .arg("--report-json")
+        .arg(&report)
+        .assert()
+        .success();
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report)?)?;
+    let (before, after) = (&report["before"], &report["after"]);
+    assert_eq!(after["trees"], 800);
+    assert!(after["nodes"].as_u64() < before["nodes"].as_u64());
+    assert!(after["score"].is_number());
+    read_model(&model)?;
+
+    Ok(())
+}
+
+#[test]
+fn quantize_writes_forest_within_metric_drop() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+
+    forest_optimizer()
+        .args([
+            "quantize",
+            "-i",
+            "./tests/test-forests/forest_iris_800.csv",
+            "--thresholds",
+            "f16",
+            "--leaves",
+            "u8",
+            "--validation",
+            "./tests/test-data/iris.csv",
+            "--label-column",
+            "Species",
+            "--max-metric-drop",
+            "0.3",
+            "-o",
+        ])
+        .arg(&model)
+        .assert()
+        .success()
+        .stdout(contains("Threshold rounding").and(contains("Prediction flips")));
+
+    read_model(&model)?;
+    assert!(ForestMetadata::sidecar_path(&model).exists());
+
+    Ok(())
+}
+
+#[test]
+fn quantize_refuses_to_exceed_metric_drop_unless_forced() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("airfoil.rforest");
+    let quantize = |force: bool| {
+        let mut cmd = forest_optimizer();
+        cmd.args([
+            "quantize",
+            "-i",
+            "./tests/test-forests/airfoil_100_200.csv",
+            "--thresholds",
+            "bf16",
+            "--leaves",
+            "u8",
+            "--validation",
+            "./tests/test-data/airfoil.csv",
+            "--label-column",
+            "f",
+            "--max-metric-drop",
+            "0.3",
+            "--json",
+            "-o",
+        ])
+        .arg(&model);
+        if force {
+            cmd.arg("--force");
+        }
+        cmd
+    };
+
+    let output = quantize(false)
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(report["accepted"], false);
+    assert_eq!(report["written"], false);
+    assert!(report["validation"]["after"].as_f64() > report["validation"]["before"].as_f64());
+    assert!(!model.exists());
+
+    let output = quantize(true)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(report["written"], true);
+    read_model(&model)?;
+
+    Ok(())
+}
+
+#[test]
+fn analyze_json_report_matches_snapshot() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let report = dir.path().join("report.json");
+
+    forest_optimizer()
+        .args(["analyze", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args([
+            "--verify-data",
+            "./tests/test-data/iris.csv",
+            "-l",
+            "Species",
+        ])
+        .arg("--report-json")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(contains("Feature usage").and(contains("Accuracy (%): 100.000")));
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report)?)?;
+    let snapshot: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(
+        "./tests/snapshots/analyze_iris_5.json",
+    )?)?;
+    assert_eq!(report, snapshot);
+    assert_eq!(
+        report["schema_version"],
+        forest_optimizer::analyze::ANALYSIS_SCHEMA_VERSION
+    );
+
+    Ok(())
+}
+
+#[test]
+fn convert_pipes_stdin_to_stdout() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+    let definition = std::fs::read("./tests/test-forests/forest_iris_5.csv")?;
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+            "-o",
+        ])
+        .arg(&output)
+        .assert()
+        .success();
+
+    // The problem type is detected from the streamed header
+    let piped = forest_optimizer()
+        .args(["convert", "-i", "-", "-o", "-"])
+        .write_stdin(definition.clone())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert_eq!(piped, std::fs::read(&output)?);
+
+    // Only stdin, with the metadata written next to the output file
+    let from_stdin = dir.path().join("stdin.rforest");
+    forest_optimizer()
+        .args(["convert", "-i", "-", "-o"])
+        .arg(&from_stdin)
+        .write_stdin(definition)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&from_stdin)?, piped);
+    assert!(ForestMetadata::sidecar_path(&from_stdin).exists());
+
+    // Only stdout
+    forest_optimizer()
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+            "-o",
+            "-",
+        ])
+        .assert()
+        .success()
+        .stdout(piped);
+
+    Ok(())
+}
+
+#[test]
+fn convert_from_headerless_stdin_requires_problem_type() -> Result<()> {
+    let definition = std::fs::read_to_string("./tests/test-forests/forest_iris_5.csv")?;
+    let headerless = definition.split_once('\n').unwrap().1.to_string();
+
+    forest_optimizer()
+        .args(["convert", "-i", "-", "-o", "-"])
+        .write_stdin(headerless.clone())
+        .assert()
+        .failure()
+        .stderr(contains("stdin has no problem type header"));
+
+    forest_optimizer()
+        .args([
+            "convert",
+            "-p",
+            "classification",
+            "-i",
+            "-",
+            "-o",
+            "-",
+            "-f",
+            "json",
+        ])
+        .write_stdin(headerless)
+        .assert()
+        .success()
+        .stdout(contains("\"num_trees\": 5"));
+
+    Ok(())
+}
+
+#[test]
+fn convert_reports_definition_diagnostics() {
+    let definition = "\
+        # { \"problem_type\": \"classification\" }\n\
+        left daughter,right daughter,split var,split point,status,prediction,tree_idx,node_idx\n\
+        2,3,x,0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,a,1,2\n\
+        4,5,NA,0,-1,b,1,3\n";
+
+    // Warnings are printed, and the forest converted
+    forest_optimizer()
+        .args(["convert", "-i", "-", "-o", "-"])
+        .write_stdin(definition)
+        .assert()
+        .success()
+        .stderr(contains(
+            "line 5, tree 1, node 3: Leaf has daughters 4 and 5, which are ignored",
+        ));
+
+    // Errors stop the conversion
+    forest_optimizer()
+        .args(["convert", "-i", "-", "-o", "-"])
+        .write_stdin(definition.replace("2,3,x", "2,6,x"))
+        .assert()
+        .failure()
+        .stderr(contains(
+            "line 3, tree 1, node 1: Daughter 6 is not a node of tree 1",
+        ))
+        .stderr(contains("The forest definition has 1 error,"));
+}
+
+#[test]
+fn convert_checks_header_counts() {
+    let definition = "\
+        # { \"problem_type\": \"classification\", \"num_trees\": 2 }\n\
+        left daughter,right daughter,split var,split point,status,prediction,tree_idx,node_idx\n\
+        2,3,x,0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,a,1,2\n\
+        0,0,NA,0,-1,b,1,3\n";
+
+    forest_optimizer()
+        .args(["convert", "-i", "-", "-o", "-", "-f", "json"])
+        .write_stdin(definition)
+        .assert()
+        .failure()
+        .stderr(contains("line 1: Header declares 2 trees, file contains 1"));
+
+    forest_optimizer()
+        .args(["convert", "-i", "-", "-o", "-", "-f", "json"])
+        .arg("--allow-count-mismatch")
+        .write_stdin(definition)
+        .assert()
+        .success()
+        .stderr(contains("Header declares 2 trees, file contains 1"));
+}
+
+#[test]
+fn convert_pins_feature_and_target_order() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let reversed = reversed_rows("./tests/test-forests/forest_iris_5.csv", dir.path())?;
+
+    let order = dir.path().join("features.txt");
+    std::fs::write(
+        &order,
+        "Sepal.Length\nSepal.Width\nPetal.Length\nPetal.Width\n",
+    )?;
+
+    let mut outputs = Vec::new();
+    for input in [
+        Path::new("./tests/test-forests/forest_iris_5.csv"),
+        &reversed,
+    ] {
+        let output = dir.path().join(format!("{}.rforest", outputs.len()));
+        forest_optimizer()
+            .args(["convert", "--target-order", "setosa,versicolor,virginica"])
+            .arg(format!("--feature-order=@{}", order.display()))
+            .arg("-i")
+            .arg(input)
+            .arg("-o")
+            .arg(&output)
+            .assert()
+            .success();
+        outputs.push(output);
+    }
+
+    assert_eq!(std::fs::read(&outputs[0])?, std::fs::read(&outputs[1])?);
+    let metadata = ForestMetadata::read(ForestMetadata::sidecar_path(&outputs[1]))?;
+    assert_eq!(
+        metadata.features,
+        ["Sepal.Length", "Sepal.Width", "Petal.Length", "Petal.Width"]
+    );
+
+    forest_optimizer()
+        .args(["convert", "--feature-order", "Sepal.Length", "-o", "-"])
+        .args(["-f", "json", "-i"])
+        .arg(&reversed)
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Missing: [Petal.Length, Petal.Width, Sepal.Width]",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn convert_orders_names_alphabetically() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let reversed = reversed_rows("./tests/test-forests/forest_iris_5.csv", dir.path())?;
+
+    let mut outputs = Vec::new();
+    for input in [
+        Path::new("./tests/test-forests/forest_iris_5.csv"),
+        &reversed,
+    ] {
+        // Same stem, as identifiers in the sources are derived from it
+        let output = dir.path().join(outputs.len().to_string());
+        std::fs::create_dir(&output)?;
+        let output = output.join("iris.rforest");
+        forest_optimizer()
+            .args(["convert", "--order", "alphabetical"])
+            .args(["--target-order", "virginica,versicolor,setosa"])
+            .args([
+                "-f",
+                "rforest",
+                "-f",
+                "c-header",
+                "-f",
+                "rust-module",
+                "-f",
+                "json",
+            ])
+            .arg("-i")
+            .arg(input)
+            .arg("-o")
+            .arg(&output)
+            .assert()
+            .success();
+        outputs.push(output);
+    }
+
+    for extension in ["rforest", "h", "rs", "json", "rforest.meta.json"] {
+        let [first, second] =
+            [&outputs[0], &outputs[1]].map(|output| output.with_extension(extension));
+        assert_eq!(std::fs::read(first)?, std::fs::read(second)?, "{extension}");
+    }
+
+    // The pinned targets, and the other names in alphabetical order
+    let metadata = ForestMetadata::read(ForestMetadata::sidecar_path(&outputs[1]))?;
+    assert_eq!(
+        metadata.features,
+        ["Petal.Length", "Petal.Width", "Sepal.Length", "Sepal.Width"]
+    );
+    assert_eq!(
+        metadata.targets.as_deref(),
+        Some(
+            ["virginica", "versicolor", "setosa"]
+                .map(String::from)
+                .as_slice()
+        )
+    );
+
+    forest_optimizer()
+        .args(["convert", "--target-order", "setosa,virginica", "-o", "-"])
+        .args(["-f", "json", "-i"])
+        .arg(&reversed)
+        .assert()
+        .failure()
+        .stderr(contains("Missing: [versicolor]"));
+
+    Ok(())
+}
+
+#[test]
+fn convert_compact_writes_compact_layout() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+
+    forest_optimizer()
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["--compact", "--validation", "./tests/test-data/iris.csv"])
+        .args(["-l", "Species", "-o"])
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(contains("compact (8-byte branches)").and(contains("520 -> 280 bytes")));
+
+    forest_optimizer()
+        .arg("info")
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(contains("Layout:          compact").and(contains("Validation:      OK")));
+
+    Ok(())
+}
+
+#[test]
+fn convert_integer_reports_the_scales() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+
+    forest_optimizer()
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["--integer", "--validation", "./tests/test-data/iris.csv"])
+        .args(["-l", "Species", "-f", "rforest", "-f", "c-header", "-o"])
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(
+            contains("integer (16-byte branches)")
+                .and(contains("Feature shifts:     [27, 29, 27, 28]"))
+                .and(contains("Prediction flips:   0 of 150")),
+        );
+
+    forest_optimizer()
+        .arg("info")
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(contains("Layout:          integer").and(contains("Validation:      OK")));
+    let header = std::fs::read_to_string(output.with_extension("h"))?;
+    assert!(header.contains("static const int8_t iris_feature_shifts[4] = {"));
+
+    forest_optimizer()
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["--integer", "--compact", "-o"])
+        .arg(&output)
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn convert_writes_big_endian_forest() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+
+    forest_optimizer()
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["--endianness", "big", "-o"])
+        .arg(&output)
+        .assert()
+        .success();
+
+    // Same size, but not readable by a little-endian build
+    let expected = std::fs::read("./tests/test-forests/forest_iris_5.rforest")?;
+    assert_eq!(std::fs::read(&output)?.len(), expected.len());
+    forest_optimizer()
+        .arg("info")
+        .arg(&output)
+        .assert()
+        .failure()
+        .stdout(contains("Validation:      FAILED (WrongByteOrder)"));
+
+    forest_optimizer()
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["--endianness", "big", "--compact", "-o"])
+        .arg(&output)
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn validate_writes_metrics_as_json() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let report = dir.path().join("report.json");
+
+    forest_optimizer()
+        .args([
+            "validate",
+            "-m",
+            "./tests/test-forests/forest_iris_5.rforest",
+        ])
+        .args(["-d", "./tests/test-data/iris.csv", "-l", "Species"])
+        .args([
+            "--csv",
+            "./tests/test-forests/forest_iris_5.csv",
+            "--report-json",
+        ])
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(contains("F1").and(contains("Confusion matrix")));
+
+    let json = serde_json::from_slice::<serde_json::Value>(&std::fs::read(&report)?)?;
+    assert_eq!(json["classes"][0], "setosa");
+    assert!(json["accuracy"].as_f64().unwrap() > 0.9);
+    assert_eq!(json["per_class"].as_array().unwrap().len(), 3);
+    assert!(json["per_class"][0]["f1"].as_f64().unwrap() > 0.9);
+    let observations = json["confusion"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|row| row.as_array().unwrap())
+        .map(|count| count.as_u64().unwrap())
+        .sum::<u64>();
+    assert_eq!(observations, 150);
+
+    forest_optimizer()
+        .args([
+            "validate",
+            "-m",
+            "./tests/test-forests/airfoil_100_200.rforest",
+        ])
+        .args(["-d", "./tests/test-data/airfoil.csv", "-l", "f"])
+        .args([
+            "--csv",
+            "./tests/test-forests/airfoil_100_200.csv",
+            "--report-json",
+        ])
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(contains("R²"));
+
+    let json = serde_json::from_slice::<serde_json::Value>(&std::fs::read(&report)?)?;
+    assert!(json.get("classes").is_none());
+    assert!(json["rmse"].as_f64().unwrap() > 0.0);
+    assert!(json["r2"].as_f64().unwrap() <= 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn check_agreement_fails_on_disagreement() {
+    forest_optimizer()
+        .args([
+            "check-agreement",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+        ])
+        .args(["-d", "./tests/test-data/iris.csv"])
+        .assert()
+        .success()
+        .stdout(contains(
+            "--- Forest vs optimized ---\nDisagreements: 0 / 150 rows",
+        ));
+
+    forest_optimizer()
+        .args([
+            "check-agreement",
+            "-i",
+            "./tests/test-forests/airfoil_100_200.csv",
+        ])
+        .args(["-d", "./tests/test-data/airfoil.csv", "--max-rows", "1"])
+        .assert()
+        .failure()
+        .stdout(contains("Row 0: trainer 2813.4345703125").and(contains("  alpha = 0\n")));
+
+    forest_optimizer()
+        .args([
+            "check-agreement",
+            "-i",
+            "./tests/test-forests/airfoil_100_200.csv",
+        ])
+        .args(["-d", "./tests/test-data/airfoil.csv", "--tolerance", "2.5"])
+        .assert()
+        .success();
+}
@@ -0,0 +1,150 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    Classification, CompactBranch, NodeLayout, OptimizedForest, Predict, Regression,
+    deserialize::ForestHeader,
+};
+use forest_optimizer::compact::{Compact, write_compact};
+use forest_optimizer::dataset::{read_labeled, read_mapped_rows};
+use forest_optimizer::emit::OutputFormat;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::metadata::ForestMetadata;
+use forest_optimizer::problem_type::Regression as RegressionProblem;
+use forest_optimizer::prune::{Validation, keep_first_trees};
+use forest_optimizer::quantize::{ThresholdType, quantize_thresholds};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+
+use crate::helpers::get_forest;
+
+#[test]
+fn compact_iris_halves_size_with_unchanged_predictions() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+
+    let report = write_compact(
+        &forest,
+        None,
+        0.0,
+        &[(OutputFormat::Rforest, output.clone())],
+        ForestMetadata::sidecar_path(&output),
+    )?;
+    assert_eq!(report.layout, NodeLayout::Compact);
+    assert_eq!(report.fallback, None);
+
+    let compact = read_model(&output)?;
+    let standard = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    assert_eq!(report.written_size, compact.len());
+    assert_eq!(report.standard_size, standard.len());
+    assert!(compact.len() * 100 <= standard.len() * 55);
+
+    let header = ForestHeader::peek(&compact).unwrap();
+    assert_eq!(header.layout, NodeLayout::Compact);
+    assert_eq!(header.serialized_len(), compact.len());
+
+    let compact = OptimizedForest::<Classification, CompactBranch>::deserialize(&compact).unwrap();
+    let standard = OptimizedForest::<Classification>::deserialize(&standard).unwrap();
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+    for row in &rows {
+        assert_eq!(compact.predict(row), standard.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn compact_regression_predicts_like_the_rounded_forest() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("airfoil.rforest");
+    let forest = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        3,
+    );
+
+    let report = write_compact(
+        &forest,
+        None,
+        0.0,
+        &[(OutputFormat::Rforest, output.clone())],
+        ForestMetadata::sidecar_path(&output),
+    )?;
+    assert_eq!(report.layout, NodeLayout::Compact);
+    assert!(report.error.unwrap().leaf_rmse.is_some());
+
+    let mut rounded = forest.clone();
+    quantize_thresholds(&mut rounded, ThresholdType::F16)?;
+    RegressionProblem::round_leaves(&mut rounded)?;
+
+    let features = forest.features_by_index();
+    let dataset = read_labeled("./tests/test-data/airfoil.csv", &features, "f")?;
+    let rows = dataset.rows.clone();
+    let expected = Validation::new(&rounded, dataset)?.predict(&rounded);
+
+    let buffer = read_model(&output)?;
+    let compact = OptimizedForest::<Regression, CompactBranch>::deserialize(&buffer).unwrap();
+    for (row, expected) in rows.iter().zip(expected) {
+        assert_eq!(compact.predict(row), expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn compact_forest_is_rejected_as_standard() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    write_compact(
+        &forest,
+        None,
+        0.0,
+        &[(OutputFormat::Rforest, output.clone())],
+        ForestMetadata::sidecar_path(&output),
+    )?;
+
+    let compact = read_model(&output)?;
+    assert_eq!(
+        OptimizedForest::<Classification>::deserialize(&compact).err(),
+        Some(Error::WrongLayout)
+    );
+
+    let standard = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    assert_eq!(
+        OptimizedForest::<Classification, CompactBranch>::deserialize(&standard).err(),
+        Some(Error::WrongLayout)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn compact_falls_back_when_the_score_drops() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("airfoil.rforest");
+    let forest = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        3,
+    );
+    let features = forest.features_by_index();
+    let dataset = read_labeled("./tests/test-data/airfoil.csv", &features, "f")?;
+    let validation = Validation::new(&forest, dataset)?;
+
+    let report = write_compact(
+        &forest,
+        Some(&validation),
+        0.0,
+        &[(OutputFormat::Rforest, output.clone())],
+        ForestMetadata::sidecar_path(&output),
+    )?;
+    assert_eq!(report.layout, NodeLayout::Standard);
+    assert!(report.fallback.unwrap().starts_with("RMSE drops"));
+
+    let buffer = read_model(&output)?;
+    assert_eq!(buffer.len(), report.standard_size);
+    assert!(OptimizedForest::<Regression>::deserialize(&buffer).is_ok());
+
+    Ok(())
+}
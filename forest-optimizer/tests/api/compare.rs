@@ -0,0 +1,109 @@
+use color_eyre::Result;
+use embedded_rforest::forest::{Branch, Classification, OptimizedForest, Predict, Regression};
+use embedded_rforest::ptr::{F32, NodeIndex, U32};
+use forest_optimizer::compare::{PredictLike, compare, compare_within};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::diff::diff_behavior;
+use forest_optimizer::problem_type::Classification as ClassificationProblem;
+use forest_optimizer::prune::keep_first_trees;
+use forest_optimizer::serialized_forest::SerializedClassificationNode;
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+/// A single tree: `x0 <= split_at ? 0 : 1`, leaf indices for regression.
+fn stump(split_at: f32) -> [Branch; 1] {
+    let leaf = |index| U32::tagged(index, true).unwrap();
+    [Branch::from_ptrs(0, split_at, leaf(0), leaf(1))]
+}
+
+const ROWS: [f32; 5] = [0.0, 0.3, 0.6, 0.4, 1.0];
+
+fn rows() -> Vec<Vec<f32>> {
+    ROWS.iter().map(|&x| vec![x]).collect()
+}
+
+#[test]
+fn classification_disagreements_are_counted_and_located() {
+    let classes = || Classification::new(2).unwrap();
+    let wide = stump(0.5);
+    let wide = OptimizedForest::<Classification>::new(1, &wide, 1, classes()).unwrap();
+    let narrow = stump(0.25);
+    let narrow = OptimizedForest::<Classification>::new(1, &narrow, 1, classes()).unwrap();
+
+    // Only 0.3 and 0.4 fall between the two thresholds
+    let comparison = compare(&wide, &narrow, &rows());
+    assert_eq!(comparison.rows, 5);
+    assert_eq!(comparison.disagreements, [1, 3]);
+    assert_eq!(comparison.disagreement_rate(), 0.4);
+    // Class indices are not quantities
+    assert_eq!(comparison.max_difference, 0.0);
+    assert_eq!(comparison.mean_difference, 0.0);
+    assert_eq!(compare_within(&wide, &narrow, &rows(), 1.0), comparison);
+
+    assert!(compare(&wide, &wide, &rows()).is_identical());
+    assert!(compare(&wide, &narrow, &[]).is_identical());
+}
+
+#[test]
+fn regression_differences_are_measured() {
+    let nodes = stump(0.5);
+    let low = [F32::new(1.0), F32::new(2.0)];
+    let low = OptimizedForest::<Regression>::with_leaves(1, &nodes, 1, &low).unwrap();
+    let high = [F32::new(1.0), F32::new(2.5)];
+    let high = OptimizedForest::<Regression>::with_leaves(1, &nodes, 1, &high).unwrap();
+
+    // 0.6 and 1.0 reach the right leaf
+    let comparison = compare(&low, &high, &rows());
+    assert_eq!(comparison.disagreements, [2, 4]);
+    assert_eq!(comparison.max_difference, 0.5);
+    assert_eq!(comparison.mean_difference, 0.2);
+
+    assert_eq!(
+        compare_within(&low, &high, &rows(), 0.4).disagreements,
+        [2, 4]
+    );
+    let within = compare_within(&low, &high, &rows(), 0.5);
+    assert!(within.is_identical());
+    assert_eq!(within.max_difference, 0.5);
+}
+
+#[test]
+fn host_and_optimized_forests_compare_with_each_other() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    assert!(compare(&forest, &optimized, &rows).is_identical());
+    // Host forests predict class indices too, named by `class_name`
+    for row in &rows {
+        let class = forest.predict_like(row);
+        assert_eq!(class, optimized.predict(row));
+        assert_eq!(forest.class_name(class), Some(forest.predict(row).as_str()));
+    }
+    assert_eq!(forest.class_name(forest.num_targets() as u32), None);
+
+    // A single tree of the forest disagrees with it on some rows
+    let first = keep_first_trees(&forest, 1);
+    let first_buffer = ClassificationProblem::serialize(&first)?;
+    let first_optimized = OptimizedForest::<Classification>::deserialize(&first_buffer).unwrap();
+    let expected = rows
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| first.predict(row) != forest.predict(row))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    assert!(!expected.is_empty());
+
+    let comparison = compare(&forest, &first, &rows);
+    assert_eq!(comparison.disagreements, expected);
+    assert_eq!(compare(&optimized, &first, &rows), comparison);
+    assert_eq!(compare(&forest, &first_optimized, &rows), comparison);
+    assert_eq!(
+        diff_behavior(&optimized, &first_optimized, &rows, 0.0)?,
+        comparison
+    );
+
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use aligned_vec::AVec;
+use color_eyre::Result;
+use embedded_rforest::forest::{
+    Branch, Classification, CompactBranch, OptimizedForest, ProblemType, Regression,
+    deserialize::{BUFFER_ALIGN, ForestHeader},
+};
+use embedded_rforest::ptr::{F32, NodeIndex, RelativeU16, U16};
+use forest_optimizer::compare::compare;
+use forest_optimizer::conversion::{Child, Divergence, HostBranch, Mismatch, verify_optimization};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::diff::diff_structure;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::prune::keep_first_trees;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+use half::f16;
+
+use crate::helpers::get_forest;
+
+#[test]
+fn classification_forest_round_trips_through_its_optimized_form() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let host = Forest::from_optimized(&optimized, forest.problem().clone())?;
+    assert_eq!(host.num_trees(), forest.num_trees());
+    assert_eq!(host.nodes().len(), forest.nodes().len());
+    assert!(compare(&host, &forest, &rows).is_identical());
+
+    // Optimizing it again gives the same trees
+    let again = ClassificationProblem::serialize(&host)?;
+    let again = OptimizedForest::<Classification>::deserialize(&again).unwrap();
+    assert!(diff_structure(&optimized, &again).is_identical());
+
+    // Relative pointers are read back relative to their tree
+    let buffer = ClassificationProblem::serialize_with(&forest, PointerWidth::Relative)?;
+    let relative =
+        OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&buffer).unwrap();
+    let host = Forest::from_optimized(&relative, forest.problem().clone())?;
+    assert!(compare(&host, &forest, &rows).is_identical());
+
+    Ok(())
+}
+
+#[test]
+fn regression_forest_round_trips_through_its_optimized_form() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+    let host = Forest::from_optimized(&optimized, forest.problem().clone())?;
+    assert_eq!(host.nodes().len(), forest.nodes().len());
+    assert!(compare(&host, &forest, &rows).is_identical());
+
+    // The same leaf values, in the order the reordered nodes reach them
+    let bits = |leaves: &[F32]| {
+        let mut bits = leaves.iter().map(|l| l.get().to_bits()).collect::<Vec<_>>();
+        bits.sort();
+        bits
+    };
+    let (_, leaves) = host.optimize_nodes();
+    assert_eq!(bits(&leaves), bits(optimized.leaves()));
+    let again = RegressionProblem::serialize(&host)?;
+    let again = OptimizedForest::<Regression>::deserialize(&again).unwrap();
+    assert!(diff_structure(&optimized, &again).is_identical());
+
+    Ok(())
+}
+
+#[test]
+fn branches_of_every_layout_decode_their_children() {
+    let narrow = Branch::<U16>::from_ptrs(
+        3,
+        0.5,
+        U16::tagged(U16::MAX, true).unwrap(),
+        U16::tagged(7, false).unwrap(),
+    );
+    assert_eq!(
+        HostBranch::from(&narrow),
+        HostBranch {
+            split_with: 3,
+            split_at: 0.5,
+            left: Child::Leaf(U16::MAX),
+            right: Child::Branch(7),
+        }
+    );
+
+    let compact = CompactBranch::new(2, f16::from_f32(-1.5), 4, 1, false, true);
+    assert_eq!(
+        HostBranch::from(&compact),
+        HostBranch {
+            split_with: 2,
+            split_at: -1.5,
+            left: Child::Branch(4),
+            right: Child::Leaf(1),
+        }
+    );
+}
+
+#[test]
+fn optimized_forest_needs_named_features() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+
+    let unnamed = Forest::from_optimized(&optimized, ClassificationProblem::default());
+    assert!(unnamed.unwrap_err().to_string().contains("features"));
+
+    Ok(())
+}
+
+/// Follow the turns of `path` from the root of `tree` to a branch.
+fn follow<P: ProblemType>(optimized: &OptimizedForest<P>, tree: usize, path: &str) -> usize {
+    let base = optimized.tree_base(tree as u32);
+    path.chars()
+        .fold(optimized.tree_root(tree as u32), |branch, turn| {
+            let branch = HostBranch::from(&optimized.nodes()[branch]);
+            match if turn == 'L' {
+                branch.left
+            } else {
+                branch.right
+            } {
+                Child::Branch(ptr) => base + ptr as usize,
+                Child::Leaf(_) => panic!("'{path}' leads to a leaf"),
+            }
+        })
+}
+
+#[test]
+fn verification_locates_a_corrupted_branch() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    verify_optimization(&forest, &optimized).unwrap();
+    let host = Forest::from_optimized(&optimized, forest.problem().clone())?;
+    verify_optimization(&host, &optimized).unwrap();
+    let buffer_relative = ClassificationProblem::serialize_with(&forest, PointerWidth::Relative)?;
+    let relative =
+        OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&buffer_relative)
+            .unwrap();
+    verify_optimization(&forest, &relative).unwrap();
+
+    let header_len = ForestHeader::peek(&buffer).unwrap().header_len;
+    let corrupt = |branch: usize, offset: usize, bytes: [u8; 4]| {
+        let mut corrupted = AVec::<u8>::from_slice(BUFFER_ALIGN, &buffer);
+        let at = header_len + branch * 16 + offset;
+        corrupted[at..at + 4].copy_from_slice(&bytes);
+        corrupted
+    };
+
+    // The threshold of the last branch
+    let last = optimized.nodes().len() - 1;
+    let corrupted = corrupt(last, 8, 100.0f32.to_le_bytes());
+    let corrupted = OptimizedForest::<Classification>::deserialize(&corrupted).unwrap();
+    let divergence = verify_optimization(&forest, &corrupted).unwrap_err();
+    assert_eq!(divergence.branch, last);
+    assert!(
+        corrupted
+            .tree_range(divergence.tree as u32)
+            .unwrap()
+            .contains(&last)
+    );
+    assert_eq!(follow(&corrupted, divergence.tree, &divergence.path), last);
+    assert!(forest.nodes()[divergence.node].is_branch());
+    assert_eq!(
+        divergence.mismatch,
+        Mismatch::SplitAt {
+            expected: optimized.nodes()[last].split_at(),
+            found: 100.0,
+        }
+    );
+
+    // The feature of the root of the second tree
+    let root = optimized.tree_root(1);
+    let feature = optimized.nodes()[root].split_with();
+    let other = (feature + 1) % optimized.num_features() as u32;
+    let corrupted = corrupt(root, 12, other.to_le_bytes());
+    let corrupted = OptimizedForest::<Classification>::deserialize(&corrupted).unwrap();
+    let divergence = verify_optimization(&forest, &corrupted).unwrap_err();
+    assert_eq!(
+        divergence,
+        Divergence {
+            tree: 1,
+            path: String::new(),
+            node: 1,
+            branch: root,
+            mismatch: Mismatch::SplitWith {
+                expected: feature,
+                found: other,
+            },
+        }
+    );
+    assert!(divergence.to_string().starts_with("tree 1, path ''"));
+
+    // A forest of fewer trees
+    assert_eq!(
+        verify_optimization(&keep_first_trees(&forest, 2), &optimized)
+            .unwrap_err()
+            .mismatch,
+        Mismatch::TreeCount {
+            expected: 2,
+            found: 5,
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn verification_locates_a_corrupted_leaf() -> Result<()> {
+    let forest = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        3,
+    );
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let header = ForestHeader::peek(&buffer).unwrap();
+
+    // The first value of the leaf table, which the first tree predicts
+    let mut corrupted = AVec::<u8>::from_slice(BUFFER_ALIGN, &buffer);
+    let at = header.serialized_len() - header.num_leaves * 4;
+    let original = f32::from_le_bytes(corrupted[at..at + 4].try_into()?);
+    corrupted[at..at + 4].copy_from_slice(&1000.0f32.to_le_bytes());
+    let corrupted = OptimizedForest::<Regression>::deserialize(&corrupted).unwrap();
+
+    let divergence = verify_optimization(&forest, &corrupted).unwrap_err();
+    assert_eq!(divergence.tree, 0);
+    assert!(forest.nodes()[divergence.node].is_leaf());
+    assert_eq!(
+        divergence.mismatch,
+        Mismatch::Prediction {
+            expected: original.to_string(),
+            found: "1000".to_owned(),
+        }
+    );
+
+    // The leaf hangs off the branch the divergence names
+    let (parent, turn) = divergence.path.split_at(divergence.path.len() - 1);
+    assert_eq!(follow(&corrupted, 0, parent), divergence.branch);
+    let parent = HostBranch::from(&corrupted.nodes()[divergence.branch]);
+    let leaf = if turn == "L" {
+        parent.left
+    } else {
+        parent.right
+    };
+    assert_eq!(leaf, Child::Leaf(0));
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use forest_optimizer::problem_type::FeatureMap;
 
 /// Datapoints and forest generated using the `iris` R sample dataset
 #[derive(serde::Deserialize, Debug)]
@@ -15,11 +15,12 @@ pub(crate) struct DataPoint {
     #[expect(dead_code)]
     pub true_f: f32,
     #[serde(rename = "Predicted")]
+    #[expect(dead_code)]
     pub forest_prediction: f32,
 }
 
 impl DataPoint {
-    pub fn transform_features(&self, feature_map: &HashMap<String, u32>) -> [f32; 5] {
+    pub fn transform_features(&self, feature_map: &FeatureMap) -> [f32; 5] {
         let mut features = [0.0, 0.0, 0.0, 0.0, 0.0];
 
         let feats = [
@@ -31,8 +32,7 @@ impl DataPoint {
         ];
 
         for feat in feats {
-            let position = feature_map.get(feat.1).unwrap();
-            features[*position as usize] = feat.0;
+            features[feature_map[feat.1].as_usize()] = feat.0;
         }
 
         features
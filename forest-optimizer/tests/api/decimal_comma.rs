@@ -0,0 +1,88 @@
+use color_eyre::Result;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedRegressionNode,
+};
+
+use crate::helpers::get_forest;
+
+const AIRFOIL: &str = "./tests/test-forests/airfoil_100_200.csv";
+
+/// The definition at `path`, with the split points and predictions written
+/// with a decimal comma, quoted like a locale-aware export quotes them.
+fn with_decimal_comma(path: &str) -> Result<String> {
+    let definition = std::fs::read_to_string(path)?;
+    let mut lines = definition.lines();
+    // Problem type and column headers
+    let mut out = lines.by_ref().take(2).collect::<Vec<_>>().join("\n");
+
+    for line in lines {
+        let mut fields = line.split(',').map(String::from).collect::<Vec<_>>();
+        // Split point and prediction
+        for i in [3, 5] {
+            if fields[i].contains('.') {
+                fields[i] = format!("\"{}\"", fields[i].replace('.', ","));
+            }
+        }
+        out.push('\n');
+        out.push_str(&fields.join(","));
+    }
+
+    Ok(out)
+}
+
+#[test]
+fn decimal_commas_read_as_the_same_forest() -> Result<()> {
+    let definition = with_decimal_comma(AIRFOIL)?;
+    assert!(definition.contains(",\"63,4\","), "{definition:.300}");
+
+    let serialized =
+        SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())?;
+    assert_eq!(serialized.validate(), []);
+    let forest = Forest::from_serialized(serialized)?;
+
+    let expected = get_forest::<SerializedRegressionNode>(AIRFOIL)?;
+    assert_eq!(forest.num_trees(), expected.num_trees());
+    assert_eq!(forest.nodes(), expected.nodes());
+
+    Ok(())
+}
+
+#[test]
+fn decimal_commas_are_read_in_classification_split_points() -> Result<()> {
+    let definition = "\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        2,3,\"x\",\"-0,5\",1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n";
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())?;
+    let forest = Forest::from_serialized(serialized)?;
+    let expected = Forest::from_serialized(
+        SerializedForest::<SerializedClassificationNode>::from_reader(
+            definition.replace("\"-0,5\"", "-0.5").as_bytes(),
+        )?,
+    )?;
+    assert_eq!(forest.nodes(), expected.nodes());
+
+    Ok(())
+}
+
+#[test]
+fn thousands_separators_are_rejected_with_their_line() -> Result<()> {
+    let definition = with_decimal_comma(AIRFOIL)?;
+
+    for ambiguous in ["\"2,611.48\"", "\"2,611,481\"", "\"2611,\""] {
+        // The prediction of the second node, on line 4
+        let definition = definition.replacen("\"2611,48183556405\"", ambiguous, 1);
+
+        let error =
+            SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())
+                .unwrap_err();
+        let error = format!("{error:#}");
+        assert!(error.contains("on line 4"), "{error}");
+        assert!(error.contains("ambiguous"), "{error}");
+    }
+
+    Ok(())
+}
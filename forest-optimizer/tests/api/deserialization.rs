@@ -0,0 +1,274 @@
+//! Deserialization of forests built in memory, without reading any file, so
+//! that these tests also run under Miri:
+//! `cargo +nightly miri test -p forest-optimizer --test api deserialization::`
+
+use std::fmt::Debug;
+
+use aligned_vec::AVec;
+use embedded_rforest::Error;
+use embedded_rforest::forest::abi::FORMAT_ABI;
+use embedded_rforest::forest::deserialize::{BUFFER_ALIGN, ForestHeader, HEADER_LEN};
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Branch, BranchLayout, Classification, MAX_TARGETS, NodeLayout,
+    OptimizedForest, Predict, ProblemKind, ProblemType, Regression, endian::swap_byte_order,
+};
+use embedded_rforest::ptr::{F32, NodeIndex, RelativeU16, U16, U32};
+
+const ROWS: [[f32; 2]; 4] = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+
+/// Two trees: `x0 <= 0.5 ? 0 : (x1 <= 0.5 ? 2 : 1)` and `x1 <= 0.5 ? 1 : 2`.
+/// With `relative`, the second branch of the first tree directly follows its
+/// root, and the pointers of the second tree start from 0.
+fn branches<Ptr: NodeIndex>(relative: bool) -> Vec<Branch<Ptr>> {
+    let ptr = |index, is_leaf| Ptr::tagged(index, is_leaf).unwrap();
+    let (inner, second_root) = if relative { (1, 2) } else { (2, 1) };
+    let mut nodes = vec![
+        Branch::from_ptrs(0, 0.5, ptr(0, true), ptr(inner, false)),
+        Branch::from_ptrs(1, 0.5, ptr(1, true), ptr(2, true)),
+        Branch::from_ptrs(1, 0.5, ptr(2, true), ptr(1, true)),
+    ];
+    nodes.swap(1, second_root);
+    nodes
+}
+
+/// Predictions of the forest on [`ROWS`].
+fn predictions<F: Predict>(forest: &F) -> Vec<<F::ProblemType as ProblemType>::Output> {
+    ROWS.iter().map(|row| forest.predict(row)).collect()
+}
+
+/// Check that `forest` deserializes from its bytes into an identical forest,
+/// and that no prefix of its bytes, nor the bytes at an unaligned address,
+/// nor its bytes in the other byte order, deserialize at all.
+fn assert_round_trips<P, B>(forest: &OptimizedForest<P, B>)
+where
+    P: ProblemType,
+    P::Output: PartialEq + Debug,
+    B: BranchLayout,
+    for<'a> OptimizedForest<'a, P, B>: Predict<ProblemType = P>,
+{
+    let bytes = forest.to_bytes();
+    let header = ForestHeader::peek(&bytes).unwrap();
+    assert_eq!(header.layout, B::NODE_LAYOUT);
+    assert_eq!(header.serialized_len(), bytes.len());
+
+    let loaded = OptimizedForest::<P, B>::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.nodes().len(), forest.nodes().len());
+    assert_eq!(loaded.node_count(), forest.nodes().len());
+    assert_eq!(loaded.size_in_bytes(), bytes.len());
+    assert_eq!(loaded.problem_kind(), forest.problem_kind());
+    assert_eq!(predictions(&loaded), predictions(forest));
+
+    // SAFETY: `bytes` just passed `deserialize`
+    let unchecked = unsafe { OptimizedForest::<P, B>::deserialize_unchecked(&bytes) }.unwrap();
+    assert_eq!(unchecked.to_bytes(), bytes);
+    assert_eq!(predictions(&unchecked), predictions(forest));
+
+    for len in 0..bytes.len() {
+        assert!(OptimizedForest::<P, B>::deserialize(&bytes[..len]).is_err());
+    }
+
+    let mut shifted = AVec::<u8>::with_capacity(BUFFER_ALIGN, bytes.len() + 1);
+    shifted.push(0);
+    shifted.extend_from_slice(&bytes);
+    assert_eq!(
+        OptimizedForest::<P, B>::deserialize(&shifted[1..]).err(),
+        Some(Error::Misaligned)
+    );
+
+    let mut swapped = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+    swap_byte_order(&mut swapped).unwrap();
+    assert_eq!(
+        OptimizedForest::<P, B>::deserialize(&swapped).err(),
+        Some(Error::WrongByteOrder)
+    );
+    swap_byte_order(&mut swapped).unwrap();
+    assert_eq!(swapped[..], bytes[..]);
+}
+
+#[test]
+fn every_layout_deserializes_from_its_bytes() {
+    let classes = || Classification::new(3).unwrap();
+
+    let nodes = branches::<U32>(false);
+    let standard = OptimizedForest::<Classification>::new(2, &nodes, 2, classes()).unwrap();
+    // Every row is a tie, which the lowest class wins
+    assert_eq!(predictions(&standard), [0, 0, 1, 1]);
+    assert_round_trips(&standard);
+
+    let nodes = branches::<U16>(false);
+    let narrow = OptimizedForest::<Classification, _>::new(2, &nodes, 2, classes()).unwrap();
+    assert_eq!(predictions(&narrow), predictions(&standard));
+    assert_round_trips(&narrow);
+
+    let nodes = branches::<RelativeU16>(true);
+    let offsets = [U32::new(0), U32::new(2)];
+    let relative =
+        OptimizedForest::<Classification, _>::new_relative(&nodes, &offsets, 2, classes()).unwrap();
+    assert_eq!(predictions(&relative), predictions(&standard));
+    assert_round_trips(&relative);
+
+    let nodes = branches::<U32>(false);
+    let leaves = [F32::new(-1.0), F32::new(0.25), F32::new(3.0)];
+    let regression = OptimizedForest::<Regression>::with_leaves(2, &nodes, 2, &leaves).unwrap();
+    assert_eq!(predictions(&regression), [-0.375, 1.0, 1.625, 1.625]);
+    assert_round_trips(&regression);
+}
+
+#[test]
+fn forests_report_their_kind_and_prediction_ram() {
+    let nodes = branches::<U32>(false);
+    let classification =
+        OptimizedForest::<Classification>::new(2, &nodes, 2, Classification::new(3).unwrap())
+            .unwrap();
+    assert_eq!(classification.problem_kind(), ProblemKind::Classification);
+    assert_eq!(classification.num_trees(), 2);
+    assert_eq!(classification.node_count(), 3);
+    // `predict` counts votes for every possible class, two bytes each
+    assert_eq!(classification.predict_ram_estimate(), 2 * MAX_TARGETS);
+
+    let leaves = [F32::new(-1.0), F32::new(0.25), F32::new(3.0)];
+    let regression = OptimizedForest::<Regression>::with_leaves(2, &nodes, 2, &leaves).unwrap();
+    assert_eq!(regression.problem_kind(), ProblemKind::Regression);
+    assert_eq!(regression.predict_ram_estimate(), size_of::<f32>());
+}
+
+#[test]
+fn deserialization_checks_every_pointer() {
+    let nodes = branches::<U32>(false);
+    let forest =
+        OptimizedForest::<Classification>::new(2, &nodes, 2, Classification::new(3).unwrap())
+            .unwrap();
+    let bytes = forest.to_bytes();
+    let header_len = ForestHeader::peek(&bytes).unwrap().header_len;
+    assert!(AnyOptimizedForest::<Classification>::deserialize(&bytes).is_ok());
+
+    // Point the right child of the first root past the node array, then at
+    // a class the forest does not have
+    for ptr in [3, U32::LEAF_TAG | 3] {
+        let mut corrupted = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+        corrupted[header_len + 4..header_len + 8].copy_from_slice(&ptr.to_le_bytes());
+        assert_eq!(
+            OptimizedForest::<Classification>::deserialize(&corrupted).err(),
+            Some(Error::MalformedForest)
+        );
+    }
+
+    // A regression forest needs as many leaf values as its pointers index
+    let leaves = [F32::new(0.0); 2];
+    assert_eq!(
+        OptimizedForest::<Regression>::with_leaves(2, &nodes, 2, &leaves).err(),
+        Some(Error::MalformedForest)
+    );
+}
+
+#[test]
+fn deserialization_rejects_what_predictions_cannot_walk() {
+    let nodes = branches::<U32>(false);
+    let forest =
+        OptimizedForest::<Classification>::new(2, &nodes, 2, Classification::new(3).unwrap())
+            .unwrap();
+    let bytes = forest.to_bytes();
+    let header_len = ForestHeader::peek(&bytes).unwrap().header_len;
+    let corrupt = |offset: usize, value: u32| {
+        let mut corrupted = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+        let at = header_len + offset;
+        corrupted[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        OptimizedForest::<Classification>::deserialize(&corrupted).err()
+    };
+
+    // The right child of the first root pointing at itself, and the left
+    // child of its inner branch pointing back at the root, would never
+    // reach a leaf
+    assert_eq!(corrupt(4, 0), Some(Error::MalformedForest));
+    assert_eq!(corrupt(2 * 16, 0), Some(Error::MalformedForest));
+    assert_eq!(corrupt(2 * 16, 2), Some(Error::MalformedForest));
+
+    // Splitting on a feature past `num_features` would read past the row
+    assert_eq!(corrupt(12, 1), None);
+    assert_eq!(corrupt(12, 2), Some(Error::MalformedForest));
+    assert_eq!(corrupt(12, u32::MAX), Some(Error::MalformedForest));
+}
+
+#[test]
+fn header_fields_are_read_little_endian() {
+    // One tree of one branch: `x1 <= 0.5 ? 1 : 2`, built byte by byte
+    let mut bytes = AVec::<u8>::new(BUFFER_ALIGN);
+    bytes.extend_from_slice(&[1, 0, 0, 0]); // num_trees
+    // num_features, num_targets, layout, version
+    bytes.extend_from_slice(&[2, 3, NodeLayout::Standard as u8, 3]);
+    bytes.extend_from_slice(&[16, 0]); // header_len
+    bytes.extend_from_slice(&[0, 0]); // flags, padding
+    bytes.extend_from_slice(&[0, 0, 0, 0]); // num_leaves
+    bytes.extend_from_slice(&[1, 0, 0, 0x80]); // left: leaf 1
+    bytes.extend_from_slice(&[2, 0, 0, 0x80]); // right: leaf 2
+    bytes.extend_from_slice(&[0, 0, 0, 0x3f]); // split_at: 0.5
+    bytes.extend_from_slice(&[1, 0, 0, 0]); // split_with
+    assert_eq!(bytes.len(), HEADER_LEN + 16);
+
+    let header = ForestHeader::peek(&bytes).unwrap();
+    assert_eq!(header.num_trees, 1);
+    assert_eq!(header.header_len, HEADER_LEN);
+    assert_eq!(header.node_count, 1);
+    assert_eq!(header.num_targets.map(|t| t.get()), Some(3));
+
+    let forest = OptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+    let branch = &forest.nodes()[0];
+    assert_eq!(branch.split_with(), 1);
+    assert_eq!(branch.split_at(), 0.5);
+    assert_eq!(predictions(&forest), [1, 2, 1, 2]);
+    // And it serializes back to the same bytes
+    assert_eq!(forest.to_bytes()[..], bytes[..]);
+
+    // Multi-byte fields are not read in the byte order of the host
+    let mut many = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+    many[..4].copy_from_slice(&[4, 3, 2, 1]);
+    assert_eq!(ForestHeader::peek(&many).unwrap().num_trees, 0x0102_0304);
+    many[8..10].copy_from_slice(&[0, 1]);
+    assert_eq!(
+        ForestHeader::peek(&many).err(),
+        Some(Error::MalformedForest)
+    );
+}
+
+#[test]
+fn huge_leaf_counts_are_malformed() {
+    // Leaf tables whose size overflows 32 bits must not wrap around
+    let nodes = branches::<U32>(false);
+    let leaves = [F32::new(-1.0), F32::new(0.25), F32::new(3.0)];
+    let forest = OptimizedForest::<Regression>::with_leaves(2, &nodes, 2, &leaves).unwrap();
+    let bytes = forest.to_bytes();
+    assert!(ForestHeader::peek(&bytes).is_ok());
+    for num_leaves in [1 << 30, u32::MAX / 4 + 1, u32::MAX] {
+        let mut huge = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+        let at = FORMAT_ABI.header.field("num_leaves").offset;
+        huge[at..at + 4].copy_from_slice(&num_leaves.to_le_bytes());
+        assert_eq!(
+            ForestHeader::peek(&huge).err(),
+            Some(Error::MalformedForest)
+        );
+    }
+}
+
+#[test]
+fn huge_tree_tables_are_malformed() {
+    // Each tree a range of the branches
+    let nodes = branches::<U32>(true);
+    let classes = Classification::new(3).unwrap();
+    let offsets = [U32::new(0), U32::new(2)];
+    let forest = OptimizedForest::<Classification>::new(2, &nodes, 2, classes)
+        .and_then(|forest| forest.with_tree_offsets(&offsets))
+        .unwrap();
+    let bytes = forest.to_bytes();
+    assert!(ForestHeader::peek(&bytes).unwrap().tree_table);
+
+    // Tables whose size overflows 32 bits, or the header, are never read
+    for num_trees in [1 << 14, 1 << 30, u32::MAX / 4 + 1, u32::MAX] {
+        let mut huge = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+        let at = FORMAT_ABI.header.field("num_trees").offset;
+        huge[at..at + 4].copy_from_slice(&num_trees.to_le_bytes());
+        assert_eq!(
+            ForestHeader::peek(&huge).err(),
+            Some(Error::MalformedForest)
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use color_eyre::Result;
+use color_eyre::eyre::Context;
+use embedded_rforest::forest::{Classification, OptimizedForest, deserialize::ForestHeader};
+use forest_optimizer::diff::{Change, HeaderDiff, diff_behavior, diff_structure};
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::serialized_forest::SerializedClassificationNode;
+
+use crate::datasets::iris;
+use crate::helpers::{get_forest, get_test_data};
+
+fn iris_rows() -> Result<Vec<Vec<f32>>> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let test_data: Vec<iris::DataPoint> = get_test_data("./tests/test-data/iris.csv")?;
+
+    Ok(test_data
+        .iter()
+        .map(|d| d.transform_features(forest.features()).to_vec())
+        .collect())
+}
+
+#[test]
+fn identical_forests_have_no_differences() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let forest =
+        OptimizedForest::<Classification>::deserialize(&buffer).context("Malformed forest")?;
+
+    assert!(HeaderDiff::new(&buffer, &buffer)?.is_identical());
+    assert!(diff_structure(&forest, &forest).is_identical());
+
+    let behavior = diff_behavior(&forest, &forest, &iris_rows()?, 0.0)?;
+    assert!(behavior.is_identical());
+    assert_eq!(behavior.rows, 150);
+
+    Ok(())
+}
+
+#[test]
+fn changed_threshold_is_located_and_changes_behavior() -> Result<()> {
+    let old_buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let mut new_buffer = old_buffer.clone();
+
+    // Move the root split of the first tree above every petal length, so
+    // that tree always goes left
+    let root = ForestHeader::peek(&old_buffer).unwrap().header_len;
+    new_buffer[root + 8..root + 12].copy_from_slice(&100.0f32.to_le_bytes());
+
+    let old =
+        OptimizedForest::<Classification>::deserialize(&old_buffer).context("Malformed forest")?;
+    let new =
+        OptimizedForest::<Classification>::deserialize(&new_buffer).context("Malformed forest")?;
+
+    assert!(HeaderDiff::new(&old_buffer, &new_buffer)?.is_identical());
+
+    let structure = diff_structure(&old, &new);
+    assert_eq!(structure.trees.len(), 1);
+    assert_eq!(structure.trees[0].tree, 0);
+    assert_eq!(structure.thresholds_changed(), 1);
+    assert_eq!(structure.splits_changed(), 0);
+    assert!(matches!(
+        &structure.trees[0].changes[0],
+        Change::ThresholdChanged { path, new, .. } if path.is_empty() && *new == 100.0
+    ));
+
+    let behavior = diff_behavior(&old, &new, &iris_rows()?, 0.0)?;
+    assert!(!behavior.is_identical());
+    assert!(behavior.disagreement_rate() > 0.0 && behavior.disagreement_rate() < 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn larger_forest_reports_added_trees() -> Result<()> {
+    let small = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let (nodes, _) = forest.optimize_nodes();
+    let large = OptimizedForest::<Classification>::new(
+        forest.num_trees().try_into().unwrap(),
+        &nodes,
+        forest.num_features().try_into().unwrap(),
+        Classification::new(forest.num_targets().try_into().unwrap()).unwrap(),
+    )
+    .context("Malformed forest")?;
+    let large_bytes = large.to_bytes();
+
+    let header = HeaderDiff::new(&small, &large_bytes)?;
+    assert!(!header.is_identical());
+    assert!(header.is_comparable());
+
+    let small =
+        OptimizedForest::<Classification>::deserialize(&small).context("Malformed forest")?;
+    let structure = diff_structure(&small, &large);
+    assert_eq!(structure.trees_added, 795);
+    assert_eq!(structure.trees_removed, 0);
+
+    let reverse = diff_structure(&large, &small);
+    assert_eq!(reverse.trees_removed, 795);
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use aligned_vec::AVec;
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, OptimizedForest, Predict, Regression, Transform,
+    deserialize::{BUFFER_ALIGN, ForestHeader},
+    encryption::decrypt_in_place,
+};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::encryption::{EncryptionKey, encrypt, encrypt_with_nonce, encrypted_len};
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::sections::{Section, size_breakdown, strip};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{NodeEncoding, WriteForest};
+use predicates::{prelude::PredicateBooleanExt, str::contains};
+
+use crate::helpers::get_forest;
+
+const IRIS_STATS: &str = "./tests/test-forests/forest_iris_5_stats.csv";
+const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+fn key() -> EncryptionKey {
+    KEY.parse().unwrap()
+}
+
+/// Zeroed buffer aligned for deserialization
+fn scratch(len: usize) -> AVec<u8> {
+    let mut scratch = AVec::with_capacity(BUFFER_ALIGN, len);
+    scratch.resize(len, 0);
+    scratch
+}
+
+#[test]
+fn encrypted_forests_predict_like_the_original() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(IRIS_STATS)?;
+    let encoding = NodeEncoding {
+        support: true,
+        ..Default::default()
+    };
+    let plain = ClassificationProblem::serialize_encoded(&forest, &encoding)?;
+    let encrypted = encrypt_with_nonce(&plain, &key(), [7; 12])?;
+
+    // The header stays readable, everything after it is not
+    let (before, header) = (
+        ForestHeader::peek(&plain).unwrap(),
+        ForestHeader::peek(&encrypted).unwrap(),
+    );
+    assert!(header.encrypted);
+    assert_eq!(
+        ForestHeader {
+            encrypted: false,
+            header_len: before.header_len,
+            ..header
+        },
+        before
+    );
+    let nodes = &plain[before.header_len..];
+    assert_eq!(
+        encrypted.len() - encrypted[header.header_len..].len(),
+        header.header_len
+    );
+    assert_ne!(&encrypted[header.header_len..], nodes);
+    assert!(
+        encrypted[header.header_len..]
+            .windows(16)
+            .all(|window| !nodes.starts_with(window))
+    );
+    assert!(matches!(
+        OptimizedForest::<Classification>::deserialize(&encrypted),
+        Err(Error::Encrypted)
+    ));
+    // The support is encrypted with the nodes, the fingerprint is not
+    let message = strip(&encrypted, &[Section::Support])
+        .unwrap_err()
+        .to_string();
+    assert!(message.contains("encrypted with the nodes"), "{message}");
+    let stripped = strip(&encrypted, &[Section::Fingerprint])?;
+    assert!(ForestHeader::peek(&stripped).unwrap().encrypted);
+
+    let mut ram = scratch(encrypted.len() + 8);
+    let decrypted =
+        OptimizedForest::<Classification>::deserialize_encrypted(&encrypted, &key().0, &mut ram)
+            .unwrap();
+    let original = OptimizedForest::<Classification>::deserialize(&plain).unwrap();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    for row in &rows {
+        assert_eq!(decrypted.predict(row), original.predict(row));
+    }
+    assert_eq!(decrypted.to_bytes()[..], plain[..]);
+
+    // In place, leaving the decrypted forest behind
+    let mut buffer = AVec::<u8>::from_slice(BUFFER_ALIGN, &encrypted);
+    let decrypted =
+        OptimizedForest::<Classification>::deserialize_encrypted_in_place(&mut buffer, &key().0)
+            .unwrap();
+    assert_eq!(decrypted.predict(&rows[0]), original.predict(&rows[0]));
+    assert!(!ForestHeader::peek(&buffer).unwrap().encrypted);
+
+    Ok(())
+}
+
+#[test]
+fn encrypted_leaf_tables_decrypt() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let encoding = NodeEncoding {
+        transform: Transform::Exp,
+        ..Default::default()
+    };
+    let plain = RegressionProblem::serialize_encoded(&forest, &encoding)?;
+    let encrypted = encrypt(&plain, &key())?;
+
+    // Every section is accounted for, the encryption after the transform
+    let sizes = size_breakdown(&ForestHeader::peek(&encrypted).unwrap());
+    assert_eq!(
+        sizes.iter().map(|size| size.bytes).sum::<usize>(),
+        encrypted.len()
+    );
+    let order = sizes.iter().map(|size| size.section).collect::<Vec<_>>();
+    let at = |section| order.iter().position(|s| *s == section).unwrap();
+    assert_eq!(at(Section::Encryption), at(Section::Transform) + 1);
+
+    let mut ram = scratch(encrypted.len());
+    let decrypted =
+        OptimizedForest::<Regression>::deserialize_encrypted(&encrypted, &key().0, &mut ram)
+            .unwrap();
+    let original = AnyOptimizedForest::<Regression>::deserialize(&plain).unwrap();
+    for row in [[0.0, 0.0, 0.0, 0.0, 0.0], [2000.0, 5.0, 0.1, 40.0, 0.01]] {
+        assert_eq!(decrypted.predict(&row), original.predict(&row));
+    }
+
+    // Random nonces: the same forest encrypts to other bytes every time
+    assert_ne!(encrypt(&plain, &key())?, encrypted);
+    assert_eq!(encrypted_len(&plain)?, encrypted.len());
+    assert!(encrypted_len(&encrypted).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn wrong_keys_and_corrupt_forests_are_rejected() -> Result<()> {
+    let plain = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let encrypted = encrypt(&plain, &key())?;
+    let deserialize = |buffer: &[u8], key: &EncryptionKey, ram: &mut [u8]| {
+        OptimizedForest::<Classification>::deserialize_encrypted(buffer, &key.0, ram).err()
+    };
+    let mut ram = scratch(encrypted.len());
+
+    // Every wrong key fails, rather than decrypting to garbage
+    for byte in [0, 1, 31] {
+        let mut wrong = key();
+        wrong.0[byte] ^= 1;
+        assert_eq!(
+            deserialize(&encrypted, &wrong, &mut ram),
+            Some(Error::WrongKey)
+        );
+    }
+    // Leaving the buffer as it was
+    let mut buffer = AVec::<u8>::from_slice(BUFFER_ALIGN, &encrypted);
+    let wrong = EncryptionKey([0; 32]);
+    assert_eq!(
+        decrypt_in_place(&mut buffer, &wrong.0),
+        Err(Error::WrongKey)
+    );
+    assert_eq!(buffer[..], encrypted[..]);
+
+    let mut corrupt = encrypted.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    assert_eq!(
+        deserialize(&corrupt, &key(), &mut ram),
+        Some(Error::MalformedForest)
+    );
+
+    assert_eq!(
+        deserialize(&encrypted, &key(), &mut ram[..encrypted.len() - 1]),
+        Some(Error::BufferTooSmall {
+            needed: encrypted.len()
+        })
+    );
+    let mut ram = scratch(encrypted.len() + 1);
+    assert_eq!(
+        deserialize(&encrypted, &key(), &mut ram[1..]),
+        Some(Error::Misaligned)
+    );
+
+    // Nothing to decrypt
+    assert!(deserialize(&plain, &key(), &mut ram).is_none());
+    assert!(encrypt(&encrypted, &key()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn keys_are_64_hex_digits() {
+    let key = key();
+    assert_eq!(key.0[0], 0);
+    assert_eq!(key.0[31], 0x1f);
+    assert_eq!(format!("{key:?}"), "EncryptionKey(..)");
+    assert_eq!(KEY.to_uppercase().parse::<EncryptionKey>(), Ok(key));
+
+    for invalid in ["", &KEY[2..], &format!("{KEY}00"), &KEY.replace('a', "g")] {
+        let message = invalid.parse::<EncryptionKey>().unwrap_err();
+        assert!(message.contains("64 hex digits"), "{message}");
+    }
+}
+
+#[test]
+fn convert_encrypts_with_the_given_key() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+    let key_file = dir.path().join("key.hex");
+    std::fs::write(&key_file, format!("{KEY}\n"))?;
+    let plain = dir.path().join("plain.rforest");
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--support", "-i", IRIS_STATS, "-o"])
+        .arg(&plain)
+        .assert()
+        .success();
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--support", "-i", IRIS_STATS, "-o"])
+        .arg(&model)
+        .arg("--encrypt-key")
+        .arg(format!("@{}", key_file.display()))
+        .assert()
+        .success();
+
+    let encrypted = read_model(&model)?;
+    assert!(ForestHeader::peek(&encrypted).unwrap().encrypted);
+    let mut decrypted = encrypted.clone();
+    decrypt_in_place(&mut decrypted, &key().0).unwrap();
+    assert_eq!(
+        OptimizedForest::<Classification>::deserialize(&decrypted)
+            .unwrap()
+            .to_bytes(),
+        read_model(&plain)?
+    );
+
+    // Only validated with the key
+    Command::cargo_bin("forest-optimizer")?
+        .arg("info")
+        .arg(&model)
+        .assert()
+        .failure()
+        .stdout(contains("Encryption:      chacha20").and(contains("Encrypted")));
+    Command::cargo_bin("forest-optimizer")?
+        .arg("info")
+        .arg(&model)
+        .args(["--decrypt-key", KEY])
+        .assert()
+        .success()
+        .stdout(contains("Validation:      OK"));
+    Command::cargo_bin("forest-optimizer")?
+        .arg("info")
+        .arg(&model)
+        .args(["--decrypt-key", &"0".repeat(64)])
+        .assert()
+        .failure()
+        .stdout(contains("WrongKey"));
+
+    // Source code embeds the encrypted bytes, and a JSON dump is refused
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--force", "-f", "rust-module", "-i", IRIS_STATS])
+        .args(["--encrypt-key", KEY, "-o"])
+        .arg(dir.path().join("iris.rs"))
+        .assert()
+        .success();
+    let module = std::fs::read_to_string(dir.path().join("iris.rs"))?;
+    assert!(module.contains("pub const IRIS_ENCRYPTED: bool = true;"));
+    assert!(!module.contains("_VALIDATED"));
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "-f", "json", "-i", IRIS_STATS])
+        .args(["--encrypt-key", KEY, "-o"])
+        .arg(dir.path().join("iris.json"))
+        .assert()
+        .failure()
+        .stderr(contains("in the clear"));
+
+    Command::cargo_bin("forest-optimizer")?
+        .args([
+            "convert",
+            "--force",
+            "-i",
+            IRIS_STATS,
+            "--encrypt-key",
+            "abc",
+            "-o",
+        ])
+        .arg(&model)
+        .assert()
+        .failure()
+        .stderr(contains("64 hex digits"));
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use aligned_vec::AVec;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, NodeLayout, OptimizedForest, Predict, Regression,
+    deserialize::{BIG_ENDIAN, BUFFER_ALIGN, ForestHeader, HEADER_LEN},
+    endian::{ByteOrder, NODE_BYTE_ORDER, swap_byte_order},
+};
+use forest_optimizer::compact::Compact;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::emit::OutputFormat;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::metadata::ForestMetadata;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::prune::keep_first_trees;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{
+    Endianness, NodeEncoding, PointerWidth, WriteForest, write_artifacts_with,
+};
+
+use crate::helpers::get_forest;
+
+#[test]
+fn big_endian_forest_is_rejected_by_the_default_reader() -> Result<()> {
+    assert_eq!(NODE_BYTE_ORDER, ByteOrder::Little);
+
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+
+    let encoding = NodeEncoding {
+        width: PointerWidth::U32,
+        endianness: Endianness::Big,
+        ..Default::default()
+    };
+    write_artifacts_with(
+        &forest,
+        encoding,
+        &[(OutputFormat::Rforest, output.clone())],
+        ForestMetadata::sidecar_path(&output),
+    )?;
+    let big = read_model(&output)?;
+    let little = ClassificationProblem::serialize(&forest)?;
+
+    // Only the byte order differs
+    let header = ForestHeader::peek(&big).unwrap();
+    assert_eq!(header.byte_order, ByteOrder::Big);
+    assert_eq!(big[10], little[10] | BIG_ENDIAN);
+    assert_eq!(big.len(), little.len());
+    assert_ne!(big[HEADER_LEN..], little[HEADER_LEN..]);
+
+    assert_eq!(
+        OptimizedForest::<Classification>::deserialize(&big).err(),
+        Some(Error::WrongByteOrder)
+    );
+    assert!(matches!(
+        AnyOptimizedForest::<Classification>::deserialize(&big),
+        Err(Error::WrongByteOrder)
+    ));
+
+    // Swapping it back gives the little-endian forest
+    let mut swapped = AVec::<u8>::from_slice(BUFFER_ALIGN, &big);
+    swap_byte_order(&mut swapped).unwrap();
+    assert_eq!(swapped[..], little[..]);
+    let swapped = OptimizedForest::<Classification>::deserialize(&swapped).unwrap();
+    let original = OptimizedForest::<Classification>::deserialize(&little).unwrap();
+    for row in &rows {
+        assert_eq!(swapped.predict(row), original.predict(row));
+    }
+
+    Ok(())
+}
+
+/// Check that every field of every branch, and every value of the tree and
+/// leaf tables, was byte-swapped, given the size of each field of a branch.
+fn assert_swapped(little: &[u8], fields: &[usize]) {
+    let header = ForestHeader::peek(little).unwrap();
+    assert_eq!(fields.iter().sum::<usize>(), header.layout.branch_size());
+
+    let mut big = AVec::<u8>::from_slice(BUFFER_ALIGN, little);
+    swap_byte_order(&mut big).unwrap();
+    assert_eq!(
+        ForestHeader::peek(&big).unwrap(),
+        ForestHeader {
+            byte_order: ByteOrder::Big,
+            ..header
+        }
+    );
+    assert_eq!(big[..10], little[..10]);
+    assert_eq!(big[11..HEADER_LEN], little[11..HEADER_LEN]);
+
+    let swapped = |start: usize, size: usize| {
+        let mut field = little[start..start + size].to_vec();
+        field.reverse();
+        assert_eq!(big[start..start + size], field, "field at byte {start}");
+    };
+
+    assert!(header.tree_table);
+    for tree in 0..header.num_trees as usize {
+        swapped(HEADER_LEN + tree * 4, 4);
+    }
+    let mut start = header.header_len;
+    for _ in 0..header.node_count {
+        for &size in fields {
+            swapped(start, size);
+            start += size;
+        }
+    }
+    for _ in 0..header.num_leaves {
+        swapped(start, 4);
+        start += 4;
+    }
+    assert_eq!(start, little.len());
+
+    // And that swapping again restores the forest
+    swap_byte_order(&mut big).unwrap();
+    assert_eq!(big[..], little[..]);
+}
+
+#[test]
+fn swapping_covers_every_field_of_a_branch() -> Result<()> {
+    let airfoil = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        3,
+    );
+    let iris =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+
+    // left, right, split_at, split_with
+    let standard = RegressionProblem::serialize(&airfoil)?;
+    assert!(ForestHeader::peek(&standard).unwrap().num_leaves > 0);
+    assert_swapped(&standard, &[4, 4, 4, 4]);
+
+    for width in [PointerWidth::U16, PointerWidth::Relative] {
+        let narrow = RegressionProblem::serialize_with(&airfoil, width)?;
+        assert_ne!(
+            ForestHeader::peek(&narrow).unwrap().layout,
+            NodeLayout::Standard
+        );
+        assert_swapped(&narrow, &[2, 2, 4, 4]);
+    }
+
+    // left, right, split_at, split_with, flags
+    let compact = ClassificationProblem::serialize_compact(&iris)?;
+    assert_eq!(
+        ForestHeader::peek(&compact).unwrap().layout,
+        NodeLayout::Compact
+    );
+    assert_swapped(&compact, &[2, 2, 2, 1, 1]);
+
+    // Rejected until swapped back, and truncated forests are not swapped
+    let mut big = AVec::<u8>::from_slice(BUFFER_ALIGN, &standard);
+    swap_byte_order(&mut big).unwrap();
+    assert!(matches!(
+        OptimizedForest::<Regression>::deserialize(&big),
+        Err(Error::WrongByteOrder)
+    ));
+    assert!(swap_byte_order(&mut big[..HEADER_LEN - 1]).is_err());
+
+    Ok(())
+}
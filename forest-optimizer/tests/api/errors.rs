@@ -0,0 +1,131 @@
+use assert_cmd::Command;
+use color_eyre::{Report, Result};
+use embedded_rforest::{Error, ErrorKind};
+use forest_optimizer::bundle::bundle;
+use forest_optimizer::inspect::{explain_error_code, read_model};
+use predicates::str::contains;
+
+const IRIS: &str = "./tests/test-forests/forest_iris_5.csv";
+const IRIS_MODEL: &str = "./tests/test-forests/forest_iris_5.rforest";
+
+/// Whether a cause of `report` names `variant`
+fn names(report: &Report, variant: &str) -> bool {
+    report
+        .chain()
+        .any(|cause| cause.to_string().contains(variant))
+}
+
+#[test]
+fn embedded_errors_name_their_variant() {
+    assert_eq!(
+        Error::WrongTargetCount.to_string(),
+        "the forest predicts another number of classes than expected (WrongTargetCount)"
+    );
+
+    // Every check of the embedded crate is a cause of the reports built on it
+    let report = Report::new(Error::Misaligned).wrap_err("Malformed forest");
+    assert_eq!(report.to_string(), "Malformed forest");
+    assert!(names(&report, "Misaligned"));
+}
+
+#[test]
+fn error_codes_are_stable() {
+    // Firmware logs these codes: never change one, nor give a retired code
+    // to a new variant
+    let codes = [
+        (Error::WrongProblemType, 1),
+        (Error::MalformedForest, 2),
+        (Error::WrongLayout, 3),
+        (
+            Error::UnsupportedVersion {
+                found: 9,
+                max_supported: 3,
+            },
+            4,
+        ),
+        (Error::Misaligned, 5),
+        (Error::WrongTargetCount, 6),
+        (Error::UnknownForest, 7),
+        (Error::DuplicateForest, 8),
+        (Error::WrongByteOrder, 9),
+        (Error::WrongFingerprint, 10),
+        (Error::Storage, 11),
+        (Error::WrongOutputLength, 12),
+        (Error::TooManyLeaves, 13),
+        (Error::BufferTooSmall { needed: 64 }, 14),
+        (Error::Encrypted, 15),
+        (Error::WrongKey, 16),
+        (Error::Unsigned, 17),
+        (Error::BadSignature, 18),
+        (Error::RegionInUse, 19),
+    ];
+    for (error, code) in codes {
+        assert_eq!(error.code(), code, "{error:?}");
+        assert_eq!(ErrorKind::from_code(code), Some(error.kind()));
+        assert!(error.to_string().starts_with(explain_error_code(code)));
+    }
+
+    assert_eq!(ErrorKind::from_code(0), None);
+    assert_eq!(ErrorKind::from_code(20), None);
+    assert_eq!(explain_error_code(2), "the forest is malformed");
+    assert_eq!(explain_error_code(0), "unknown error code");
+}
+
+#[test]
+fn library_reports_keep_the_failed_check() -> Result<()> {
+    let model = read_model(IRIS_MODEL)?;
+    let report = bundle(&[("iris", &model[..20])]).unwrap_err();
+    assert!(
+        report
+            .to_string()
+            .contains("Forest 'iris' is not a serialized forest")
+    );
+    assert!(names(&report, "MalformedForest"), "{report:?}");
+
+    Ok(())
+}
+
+#[test]
+fn cli_reports_keep_the_failed_check() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let big = dir.path().join("big.rforest");
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--endianness", "big", "-i", IRIS, "-o"])
+        .arg(&big)
+        .assert()
+        .success();
+
+    // Nodes in the byte order of another target
+    Command::cargo_bin("forest-optimizer")?
+        .args(["bench", "-n", "1", "-d", "./tests/test-data/iris.csv", "-m"])
+        .arg(&big)
+        .assert()
+        .failure()
+        .stderr(contains("Malformed forest"))
+        .stderr(contains("WrongByteOrder"));
+
+    // A truncated forest, next to its metadata
+    let truncated = dir.path().join("truncated.rforest");
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "-i", IRIS, "-o"])
+        .arg(&truncated)
+        .assert()
+        .success();
+    let model = read_model(&truncated)?;
+    std::fs::write(&truncated, &model[..100])?;
+    Command::cargo_bin("forest-optimizer")?
+        .args([
+            "validate",
+            "-d",
+            "./tests/test-data/iris.csv",
+            "-l",
+            "species",
+            "-m",
+        ])
+        .arg(&truncated)
+        .assert()
+        .failure()
+        .stderr(contains("MalformedForest"));
+
+    Ok(())
+}
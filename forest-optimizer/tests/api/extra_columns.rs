@@ -0,0 +1,128 @@
+use color_eyre::Result;
+use forest_optimizer::forest::{Forest, Node};
+use forest_optimizer::problem_type::Classification;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedForest};
+
+use crate::helpers::get_forest;
+
+const IRIS: &str = "./tests/test-forests/forest_iris_5.csv";
+/// `IRIS` with the sample count (`n`), Gini index and improvement of every
+/// node
+const IRIS_STATS: &str = "./tests/test-forests/forest_iris_5_stats.csv";
+
+#[test]
+fn sample_counts_and_impurities_are_read() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(IRIS_STATS)?;
+    // The statistics do not change the forest
+    assert_eq!(
+        forest.nodes(),
+        get_forest::<SerializedClassificationNode>(IRIS)?.nodes()
+    );
+
+    let tree = forest.trees().next().unwrap();
+    let nodes = tree.to_nodes();
+    let Node::Branch(root) = &nodes[0] else {
+        panic!("The first tree is a single leaf");
+    };
+    assert_eq!(root.samples(), Some(123));
+    assert_eq!(root.impurity(), Some(0.26));
+    let Node::Leaf(leaf) = &nodes[1] else {
+        panic!("The left daughter of the root is a branch");
+    };
+    assert_eq!(leaf.samples(), Some(22));
+    assert_eq!(leaf.impurity(), Some(0.0));
+
+    // Every node has its statistics
+    assert!(forest.nodes().iter().all(|node| node.samples().is_some()));
+
+    Ok(())
+}
+
+#[test]
+fn definitions_without_statistics_have_none() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(IRIS)?;
+    assert!(forest.nodes().iter().all(|node| node.samples().is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn statistics_are_cached_but_not_written_back() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let forest = get_forest::<SerializedClassificationNode>(IRIS_STATS)?;
+
+    let path = dir.path().join("iris.forest");
+    forest.save(&path)?;
+    let loaded = Forest::<Classification>::load(&path)?;
+    let samples = |forest: &Forest<Classification>| {
+        forest.nodes().iter().map(Node::samples).collect::<Vec<_>>()
+    };
+    assert_eq!(samples(&loaded), samples(&forest));
+
+    // The definition keeps the columns of the R export
+    let mut csv = Vec::new();
+    SerializedForest::<SerializedClassificationNode>::from_forest(&forest).to_csv(&mut csv)?;
+    let csv = String::from_utf8(csv)?;
+    assert!(
+        csv.lines().nth(1).unwrap().ends_with(",tree_idx,node_idx"),
+        "{csv}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unknown_columns_are_ignored() -> Result<()> {
+    let definition = std::fs::read_to_string(IRIS)?;
+    let mut lines = definition.lines();
+    let mut junk = vec![lines.next().unwrap().to_string()];
+    // Columns before, between and after the known ones
+    junk.push(format!(
+        "\"id\",{},\"note\"",
+        lines
+            .next()
+            .unwrap()
+            .replace(",\"status\",", ",\"status\",\"weight\",")
+    ));
+    for (row, line) in lines.enumerate() {
+        let fields = line.split(',').collect::<Vec<_>>();
+        junk.push(format!(
+            "{row},{},{},junk {row}",
+            fields[..5].join(","),
+            ["NA"]
+                .iter()
+                .chain(&fields[5..])
+                .copied()
+                .collect::<Vec<_>>()
+                .join(",")
+        ));
+    }
+    let junk = junk.join("\n");
+
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(junk.as_bytes())?;
+    assert_eq!(serialized.validate(), []);
+    let forest = Forest::from_serialized(serialized)?;
+    assert_eq!(
+        forest.nodes(),
+        get_forest::<SerializedClassificationNode>(IRIS)?.nodes()
+    );
+    assert!(forest.nodes().iter().all(|node| node.samples().is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn malformed_sample_counts_name_their_line() {
+    let definition = "\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\",\"n\"\n\
+        2,3,\"x\",0.5,1,NA,1,1,10\n\
+        0,0,NA,0,-1,\"a\",1,2,4.5\n\
+        0,0,NA,0,-1,\"b\",1,3,NA\n";
+    let error =
+        SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())
+            .unwrap_err();
+    let error = format!("{error:#}");
+    assert!(error.contains("on line 3"), "{error}");
+    assert!(error.contains("'4.5' is not a count"), "{error}");
+}
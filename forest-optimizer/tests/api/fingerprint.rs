@@ -0,0 +1,205 @@
+use aligned_vec::AVec;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, OptimizedForest, Regression,
+    deserialize::{BUFFER_ALIGN, FINGERPRINT, ForestHeader, HEADER_LEN, TREE_TABLE},
+    endian::swap_byte_order,
+    fingerprint::fingerprint,
+};
+use forest_optimizer::compact::Compact;
+use forest_optimizer::emit::OutputFormat;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{NodeEncoding, PointerWidth, WriteForest, write_to};
+
+use crate::helpers::get_forest;
+
+const IRIS_FEATURES: [&str; 4] = ["Petal.Length", "Petal.Width", "Sepal.Length", "Sepal.Width"];
+
+#[test]
+fn fingerprint_definition_is_stable() {
+    // FNV-1a of the names, each followed by 0x00, with 0xFF between the
+    // features and the classes
+    assert_eq!(fingerprint(&[], &[]), 0x7a0b_824e);
+    assert_eq!(fingerprint(&["a", "b"], &[]), 0x6a77_464b);
+    assert_eq!(fingerprint(&["b", "a"], &[]), 0x580d_13a3);
+
+    // Order, boundaries between names, and whether a name is a feature or a
+    // class all matter
+    let fingerprints = [
+        fingerprint(&["a", "b"], &[]),
+        fingerprint(&["ab"], &[]),
+        fingerprint(&["a"], &["b"]),
+        fingerprint(&[], &["a", "b"]),
+        fingerprint(&["a", "b"], &["c", "d"]),
+        fingerprint(&["a", "b"], &["d", "c"]),
+    ];
+    for (i, a) in fingerprints.iter().enumerate() {
+        assert_ne!(*a, 0);
+        for b in &fingerprints[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+
+    // Usable in constants, e.g. by firmware
+    const EXPECTED: u32 = fingerprint(&["a", "b"], &[]);
+    assert_eq!(EXPECTED, 0x6a77_464b);
+}
+
+#[test]
+fn serialized_forests_record_their_fingerprint() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let metadata = ClassificationProblem::metadata(&forest);
+    let classes = metadata.targets.as_ref().unwrap();
+    let classes = classes.iter().map(String::as_str).collect::<Vec<_>>();
+    let expected = fingerprint(&IRIS_FEATURES, &classes);
+    assert_eq!(metadata.features, IRIS_FEATURES);
+    assert_eq!(metadata.fingerprint, expected);
+
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let header = ForestHeader::peek(&buffer).unwrap();
+    assert_eq!(buffer[10], TREE_TABLE | FINGERPRINT);
+    assert_eq!(header.fingerprint.map(|f| f.get()), Some(expected));
+    // After the table of the 5 trees
+    let at = HEADER_LEN + 5 * 4;
+    assert_eq!(buffer[at..at + 4], expected.to_le_bytes());
+
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    assert_eq!(optimized.feature_fingerprint(), expected);
+    assert_eq!(optimized.check_fingerprint(expected), Ok(()));
+    let swapped = fingerprint(
+        &["Petal.Width", "Petal.Length", "Sepal.Length", "Sepal.Width"],
+        &classes,
+    );
+    assert_eq!(
+        optimized.check_fingerprint(swapped),
+        Err(Error::WrongFingerprint)
+    );
+    assert_eq!(optimized.to_bytes()[..], buffer[..]);
+
+    // Every layout records it
+    for width in [PointerWidth::U16, PointerWidth::Relative] {
+        let buffer = ClassificationProblem::serialize_with(&forest, width)?;
+        let any = AnyOptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+        assert_eq!(any.check_fingerprint(expected), Ok(()));
+    }
+    let compact = ClassificationProblem::serialize_compact(&forest)?;
+    let compact = AnyOptimizedForest::<Classification>::deserialize(&compact).unwrap();
+    assert_eq!(compact.feature_fingerprint(), expected);
+
+    // As the header, it is little-endian whatever the byte order of nodes
+    let mut big = AVec::<u8>::from_slice(BUFFER_ALIGN, &buffer);
+    swap_byte_order(&mut big).unwrap();
+    assert_eq!(big[at..at + 4], expected.to_le_bytes());
+    assert_eq!(
+        ForestHeader::peek(&big).unwrap().fingerprint,
+        header.fingerprint
+    );
+
+    // Forests built without names have none, and fail every check
+    let (nodes, _) = forest.optimize_nodes();
+    let unnamed =
+        OptimizedForest::<Classification>::new(5, &nodes, 4, Classification::new(3).unwrap())
+            .unwrap();
+    assert_eq!(unnamed.feature_fingerprint(), 0);
+    assert_eq!(
+        unnamed.check_fingerprint(expected),
+        Err(Error::WrongFingerprint)
+    );
+    let bytes = unnamed.to_bytes();
+    assert_eq!(ForestHeader::peek(&bytes).unwrap().fingerprint, None);
+    assert_eq!(bytes.len(), unnamed.serialized_len());
+
+    Ok(())
+}
+
+#[test]
+fn regression_fingerprint_only_covers_features() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let metadata = RegressionProblem::metadata(&forest);
+    let features = metadata
+        .features
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+    assert_eq!(optimized.feature_fingerprint(), fingerprint(&features, &[]));
+    assert_eq!(optimized.serialized_len(), buffer.len());
+
+    Ok(())
+}
+
+#[test]
+fn generated_code_holds_the_expected_fingerprint() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let expected = ClassificationProblem::metadata(&forest).fingerprint;
+
+    let mut rust = Vec::new();
+    write_to(
+        &forest,
+        NodeEncoding::default(),
+        OutputFormat::RustModule,
+        &mut rust,
+    )?;
+    let rust = String::from_utf8(rust)?;
+    assert!(rust.contains(&format!(
+        "pub const FOREST_FINGERPRINT: u32 = {expected:#010x};"
+    )));
+
+    let mut c = Vec::new();
+    write_to(
+        &forest,
+        NodeEncoding::default(),
+        OutputFormat::CHeader,
+        &mut c,
+    )?;
+    let c = String::from_utf8(c)?;
+    assert!(c.contains(&format!(
+        "static const uint32_t forest_fingerprint = {expected:#010x}u;"
+    )));
+
+    let mut json = Vec::new();
+    write_to(
+        &forest,
+        NodeEncoding::default(),
+        OutputFormat::Json,
+        &mut json,
+    )?;
+    let json = serde_json::from_slice::<serde_json::Value>(&json)?;
+    assert_eq!(json["fingerprint"], expected);
+
+    Ok(())
+}
+
+#[test]
+fn flagged_fingerprint_must_fit_the_header_and_not_be_zero() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let at = HEADER_LEN + 5 * 4;
+
+    let mut zero = AVec::<u8>::from_slice(BUFFER_ALIGN, &buffer);
+    zero[at..at + 4].fill(0);
+    assert_eq!(
+        ForestHeader::peek(&zero).err(),
+        Some(Error::MalformedForest)
+    );
+
+    // Six trees fill the padding the fingerprint of five fits in
+    let mut cramped = AVec::<u8>::from_slice(BUFFER_ALIGN, &buffer);
+    cramped[..4].copy_from_slice(&6u32.to_le_bytes());
+    assert_eq!(
+        ForestHeader::peek(&cramped).err(),
+        Some(Error::MalformedForest)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,399 @@
+use color_eyre::Result;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::forest::flatten::{
+    FlattenedForest, NodeHoles, apply_offsets, check_invariants, flatten, group_by_tree,
+};
+use forest_optimizer::forest::{BranchNode, Forest, LeafNode, Node};
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, FeatureIdx, ProblemType,
+    Regression as RegressionProblem, TargetIdx,
+};
+use forest_optimizer::serialized_forest::{
+    IndexOrder, SerializedClassificationNode, SerializedForest, SerializedNode,
+    SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+const HEADER: &str = "# { \"problem_type\": \"classification\" }\n\
+    \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n";
+
+/// Two stumps splitting on `x` and `y`, with the rows of tree 2 first and
+/// the nodes of each tree out of order.
+fn shuffled_definition() -> Result<SerializedForest<SerializedClassificationNode>> {
+    let rows = "\
+        0,0,NA,0,-1,\"b\",2,3\n\
+        2,3,\"y\",0.5,1,NA,2,1\n\
+        0,0,NA,0,-1,\"b\",1,3\n\
+        0,0,NA,0,-1,\"a\",2,2\n\
+        2,3,\"x\",1.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n";
+    SerializedForest::from_reader(format!("{HEADER}{rows}").as_bytes())
+}
+
+fn branch(
+    split_with: FeatureIdx,
+    split_at: f32,
+    left: u32,
+    right: u32,
+) -> Node<ClassificationProblem> {
+    Node::Branch(BranchNode::new(split_with, split_at, left, right))
+}
+
+fn leaf(class: TargetIdx) -> Node<ClassificationProblem> {
+    Node::Leaf(LeafNode::new(class))
+}
+
+#[test]
+fn nodes_are_grouped_by_tree_in_node_order() -> Result<()> {
+    let serialized = shuffled_definition()?;
+    let grouped = group_by_tree(serialized.nodes());
+
+    let indices = grouped
+        .trees
+        .iter()
+        .map(|tree| {
+            tree.iter()
+                .map(|n| (n.tree_idx(), n.node_idx()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        indices,
+        [[(1, 1), (1, 2), (1, 3)], [(2, 1), (2, 2), (2, 3)]]
+    );
+
+    // Child pointers become 0-indexed, names become indices
+    let problem = serialized.problem();
+    let x = problem.features()["x"];
+    let y = problem.features()["y"];
+    let a = problem.targets()["a"];
+    let b = problem.targets()["b"];
+    let trees = grouped.normalize(problem, NodeHoles::Reject)?;
+    assert_eq!(
+        trees,
+        [
+            [branch(x, 1.5, 1, 2), leaf(a), leaf(b)],
+            [branch(y, 0.5, 1, 2), leaf(a), leaf(b)],
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn gaps_in_tree_indices_are_renumbered() -> Result<()> {
+    let rows = "\
+        0,0,NA,0,-1,\"b\",7,1\n\
+        0,0,NA,0,-1,\"a\",3,1\n";
+    let serialized = SerializedForest::<SerializedClassificationNode>::from_reader(
+        format!("{HEADER}{rows}").as_bytes(),
+    )?;
+
+    let grouped = group_by_tree(serialized.nodes());
+    assert_eq!(grouped.tree_indices, [3, 7]);
+    let problem = serialized.problem();
+    let trees = grouped.normalize(problem, NodeHoles::Reject)?;
+    assert_eq!(
+        trees,
+        [
+            [leaf(problem.targets()["a"])],
+            [leaf(problem.targets()["b"])]
+        ]
+    );
+
+    Ok(())
+}
+
+/// Error normalizing the nodes of `rows` with `holes`.
+fn normalization_error(rows: &str, holes: NodeHoles) -> Result<String> {
+    let serialized = SerializedForest::<SerializedClassificationNode>::from_reader(
+        format!("{HEADER}{rows}").as_bytes(),
+    )?;
+    let error = group_by_tree(serialized.nodes())
+        .normalize(serialized.problem(), holes)
+        .unwrap_err();
+    Ok(error.to_string())
+}
+
+#[test]
+fn duplicate_nodes_are_rejected() -> Result<()> {
+    // Two partial dumps of tree 2 concatenated
+    let rows = "\
+        0,0,NA,0,-1,\"a\",1,1\n\
+        2,3,\"x\",1.5,1,NA,2,1\n\
+        0,0,NA,0,-1,\"a\",2,2\n\
+        2,3,\"x\",1.5,1,NA,2,1\n\
+        0,0,NA,0,-1,\"a\",2,2\n\
+        0,0,NA,0,-1,\"b\",2,3\n";
+    for holes in [NodeHoles::Reject, NodeHoles::Renumber] {
+        assert_eq!(
+            normalization_error(rows, holes)?,
+            "Tree 2 has several nodes of index 1, 2"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn trees_without_root_are_rejected() -> Result<()> {
+    let rows = "\
+        0,0,NA,0,-1,\"a\",1,1\n\
+        0,0,NA,0,-1,\"b\",2,2\n\
+        0,0,NA,0,-1,\"b\",2,3\n";
+    assert_eq!(
+        normalization_error(rows, NodeHoles::Renumber)?,
+        "Tree 2 has no root: its nodes start at index 2 instead of 1"
+    );
+
+    let rows = "0,0,NA,0,-1,\"a\",1,0\n0,0,NA,0,-1,\"b\",1,1\n";
+    assert_eq!(
+        normalization_error(rows, NodeHoles::Reject)?,
+        "Tree 1 has no root: its nodes start at index 0 instead of 1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn holes_in_node_indices_are_rejected() -> Result<()> {
+    let rows = "\
+        2,5,\"x\",1.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,5\n\
+        2,7,\"y\",0.5,1,NA,1,6\n";
+    assert_eq!(
+        normalization_error(rows, NodeHoles::Reject)?,
+        "Tree 1 is missing nodes 3..=4"
+    );
+
+    // Renumbering cannot mend pointers into a hole
+    let rows = "\
+        2,4,\"x\",1.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,5\n";
+    assert_eq!(
+        normalization_error(rows, NodeHoles::Renumber)?,
+        "Tree 1 points to missing node 4"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn holes_in_node_indices_may_be_renumbered() -> Result<()> {
+    let rows = "\
+        2,5,\"x\",1.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        7,8,\"y\",0.5,1,NA,1,5\n\
+        0,0,NA,0,-1,\"a\",1,7\n\
+        0,0,NA,0,-1,\"b\",1,8\n";
+    let serialized = SerializedForest::<SerializedClassificationNode>::from_reader(
+        format!("{HEADER}{rows}").as_bytes(),
+    )?;
+    let problem = serialized.problem();
+    let (x, y) = (problem.features()["x"], problem.features()["y"]);
+    let (a, b) = (problem.targets()["a"], problem.targets()["b"]);
+
+    let trees = group_by_tree(serialized.nodes()).normalize(problem, NodeHoles::Renumber)?;
+    assert_eq!(
+        trees,
+        [[
+            branch(x, 1.5, 1, 2),
+            leaf(a),
+            branch(y, 0.5, 3, 4),
+            leaf(a),
+            leaf(b),
+        ]]
+    );
+
+    let forest = Forest::from_serialized_with(serialized, NodeHoles::Renumber)?;
+    assert_eq!(forest.trees().next().unwrap().predict(&[2.0, 1.0]), b.0);
+
+    Ok(())
+}
+
+#[test]
+fn normalization_fails_on_the_first_bad_node() -> Result<()> {
+    let rows = "\
+        2,3,\"x\",1.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,NA,1,3\n";
+    let serialized = SerializedForest::<SerializedClassificationNode>::from_reader(
+        format!("{HEADER}{rows}").as_bytes(),
+    )?;
+
+    let grouped = group_by_tree(serialized.nodes());
+    assert!(
+        grouped
+            .normalize(serialized.problem(), NodeHoles::Reject)
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn offsets_account_for_the_roots_and_preceding_trees() {
+    let tree_sizes = [3, 5, 1];
+
+    // Roots take the first 3 slots, and each tree's root is not part of its
+    // rest: node k > 0 of tree t lands at 3 + (sizes of the rests before t) +
+    // k - 1
+    assert_eq!(
+        branch(FeatureIdx(0), 0.0, 1, 2).offset(&tree_sizes, 0),
+        branch(FeatureIdx(0), 0.0, 3, 4)
+    );
+    assert_eq!(
+        branch(FeatureIdx(0), 0.0, 1, 4).offset(&tree_sizes, 1),
+        branch(FeatureIdx(0), 0.0, 5, 8)
+    );
+    assert_eq!(
+        branch(FeatureIdx(0), 0.0, 2, 3).offset(&tree_sizes, 1),
+        branch(FeatureIdx(0), 0.0, 6, 7)
+    );
+
+    // Leaves have no pointers
+    assert_eq!(
+        leaf(TargetIdx(1)).offset(&tree_sizes, 2),
+        leaf(TargetIdx(1))
+    );
+
+    // A single tree keeps its pointers
+    assert_eq!(
+        branch(FeatureIdx(0), 0.0, 1, 2).offset(&[3], 0),
+        branch(FeatureIdx(0), 0.0, 1, 2)
+    );
+}
+
+#[test]
+fn trees_are_flattened_roots_first() {
+    let trees = vec![
+        vec![
+            branch(FeatureIdx(0), 1.0, 1, 2),
+            leaf(TargetIdx(0)),
+            leaf(TargetIdx(1)),
+        ],
+        vec![leaf(TargetIdx(2))],
+        vec![
+            branch(FeatureIdx(1), 2.0, 1, 2),
+            leaf(TargetIdx(1)),
+            leaf(TargetIdx(2)),
+        ],
+    ];
+
+    let FlattenedForest { tree_sizes, nodes } = flatten(apply_offsets(trees));
+    assert_eq!(tree_sizes, [3, 1, 3]);
+    assert_eq!(
+        nodes,
+        [
+            branch(FeatureIdx(0), 1.0, 3, 4),
+            leaf(TargetIdx(2)),
+            branch(FeatureIdx(1), 2.0, 5, 6),
+            leaf(TargetIdx(0)),
+            leaf(TargetIdx(1)),
+            leaf(TargetIdx(1)),
+            leaf(TargetIdx(2)),
+        ]
+    );
+
+    check_invariants(&FlattenedForest { tree_sizes, nodes });
+}
+
+#[test]
+#[should_panic]
+fn branches_must_point_further_down() {
+    check_invariants(&FlattenedForest {
+        tree_sizes: vec![2],
+        nodes: vec![leaf(TargetIdx(0)), branch(FeatureIdx(0), 1.0, 0, 0)],
+    });
+}
+
+#[test]
+fn flattening_matches_the_recorded_forests() -> Result<()> {
+    let iris =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let snapshot = std::fs::read_to_string("./tests/snapshots/forest_iris_5.txt")?;
+    assert_eq!(iris.to_string(), snapshot);
+    let expected = std::fs::read("./tests/test-forests/forest_iris_5.rforest")?;
+    assert_eq!(ClassificationProblem::serialize(&iris)?[..], expected[..]);
+
+    let airfoil =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let expected = std::fs::read("./tests/test-forests/airfoil_100_200.rforest")?;
+    assert_eq!(RegressionProblem::serialize(&airfoil)?[..], expected[..]);
+
+    Ok(())
+}
+
+/// `path` read back with its rows in the given order, its features and
+/// targets indexed like in `forest`
+fn reordered<N: SerializedNode>(
+    path: &str,
+    forest: &Forest<N::ProblemType>,
+    order: impl FnOnce(&mut Vec<&str>),
+) -> Result<Forest<N::ProblemType>>
+where
+    N::ProblemType: WriteForest,
+{
+    let definition = std::fs::read_to_string(path)?;
+    let mut lines = definition.lines().collect::<Vec<_>>();
+    let mut rows = lines.split_off(2);
+    order(&mut rows);
+    lines.extend(rows);
+
+    let metadata = N::ProblemType::metadata(forest);
+    let order = IndexOrder {
+        features: Some(metadata.features),
+        targets: metadata.targets,
+        ..Default::default()
+    };
+    let serialized = SerializedForest::<N>::from_reader(lines.join("\n").as_bytes())?;
+    Forest::from_serialized(order.apply(serialized)?)
+}
+
+#[test]
+fn grouping_does_not_depend_on_the_order_of_the_rows() -> Result<()> {
+    const IRIS: &str = "./tests/test-forests/forest_iris_5.csv";
+    const AIRFOIL: &str = "./tests/test-forests/airfoil_100_200.csv";
+
+    let iris = get_forest::<SerializedClassificationNode>(IRIS)?;
+    let expected = ClassificationProblem::serialize(&iris)?;
+    // Trees listed backwards, then rows interleaving every tree
+    let backwards = reordered::<SerializedClassificationNode>(IRIS, &iris, |rows| rows.reverse())?;
+    assert_eq!(ClassificationProblem::serialize(&backwards)?, expected);
+    let interleaved = reordered::<SerializedClassificationNode>(IRIS, &iris, |rows| {
+        rows.sort_by_key(|row| row.rsplit(',').next().unwrap().parse::<usize>().unwrap())
+    })?;
+    assert_eq!(ClassificationProblem::serialize(&interleaved)?, expected);
+
+    let airfoil = get_forest::<SerializedRegressionNode>(AIRFOIL)?;
+    let expected = RegressionProblem::serialize(&airfoil)?;
+    let backwards =
+        reordered::<SerializedRegressionNode>(AIRFOIL, &airfoil, |rows| rows.reverse())?;
+    assert_eq!(RegressionProblem::serialize(&backwards)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn forests_with_gaps_in_tree_indices_keep_their_trees() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let gaps =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5_gaps.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+
+    assert_eq!(gaps.num_trees(), 5);
+    assert_eq!(gaps.nodes(), forest.nodes());
+    for (tree, renumbered) in forest.trees().zip(gaps.trees()) {
+        assert_eq!(tree.index(), renumbered.index());
+        for row in &rows {
+            assert_eq!(tree.predict(row), renumbered.predict(row));
+        }
+    }
+
+    Ok(())
+}
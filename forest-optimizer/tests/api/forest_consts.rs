@@ -0,0 +1,56 @@
+use embedded_rforest::forest::deserialize::{
+    FORMAT_VERSION, peek_num_features, peek_num_targets, peek_num_trees,
+};
+use embedded_rforest::forest::{Classification, OptimizedForest, Predict, Regression};
+use embedded_rforest::{forest_consts, static_storage};
+
+const IRIS: &[u8] = static_storage!("../test-forests/forest_iris_5.rforest");
+const AIRFOIL: &[u8] = static_storage!("../test-forests/airfoil_100_200.rforest");
+
+forest_consts!(mod iris = IRIS);
+forest_consts!(mod airfoil = AIRFOIL);
+
+#[test]
+fn forest_consts_size_arrays_and_match_the_header() {
+    let features = [5.0; iris::NUM_FEATURES];
+    let mut votes = [0; iris::NUM_TARGETS];
+    let forest = OptimizedForest::<Classification>::deserialize(IRIS).unwrap();
+    assert_eq!(iris::NUM_FEATURES, usize::from(forest.num_features()));
+    assert_eq!(
+        Some(iris::NUM_TARGETS),
+        forest.num_targets().map(|t| usize::from(t.get()))
+    );
+    assert_eq!(iris::NUM_TREES, forest.num_trees() as usize);
+    votes[forest.predict(&features) as usize] += 1;
+    assert_eq!(votes.iter().sum::<u32>(), 1);
+
+    let features = [0.0; airfoil::NUM_FEATURES];
+    let forest = OptimizedForest::<Regression>::deserialize(AIRFOIL).unwrap();
+    assert_eq!(airfoil::NUM_FEATURES, usize::from(forest.num_features()));
+    assert_eq!(airfoil::NUM_TARGETS, 0);
+    assert_eq!(airfoil::NUM_TREES, forest.num_trees() as usize);
+    assert!(forest.predict(&features).is_finite());
+}
+
+#[test]
+fn header_peeks_are_const() {
+    const TREES: u32 = peek_num_trees(IRIS);
+    const FEATURES: u8 = peek_num_features(IRIS);
+    const TARGETS: u8 = peek_num_targets(IRIS);
+    assert_eq!((TREES, FEATURES, TARGETS), (5, 4, 3));
+}
+
+// In const context, the same panics fail the build with these messages
+#[test]
+#[should_panic(expected = "Not a serialized forest: shorter than its header")]
+fn short_buffers_have_no_consts() {
+    let _ = peek_num_features(&IRIS[..15]);
+}
+
+#[test]
+#[should_panic(expected = "Forest serialized in another format version: convert it again")]
+fn other_format_versions_have_no_consts() {
+    let mut bytes = IRIS.to_vec();
+    bytes[7] = FORMAT_VERSION + 1;
+    let _ = peek_num_trees(&bytes);
+}
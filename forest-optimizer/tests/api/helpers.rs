@@ -1,9 +1,24 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::task::Poll;
 
+use aligned_vec::AVec;
 use color_eyre::Result;
 
+use embedded_rforest::forest::abi::FORMAT_ABI;
+use embedded_rforest::forest::session::{PredictSession, Tally};
+use embedded_rforest::forest::{
+    self as embedded, Branch, Classification, OptimizedForest, Predict, Regression,
+};
+use embedded_rforest::ptr::{F32, NodePointer};
+use forest_optimizer::compare::{PredictLike, compare_recorded};
+use forest_optimizer::dataset::{read_labeled, read_mapped_rows};
 use forest_optimizer::forest::Forest;
-use forest_optimizer::serialized_forest::{SerializedForest, SerializedNode};
+use forest_optimizer::problem_type::{Classification as ClassificationProblem, ProblemType};
+use forest_optimizer::prune::Prune;
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedNode,
+};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
 use serde::de::DeserializeOwned;
 
 pub fn get_forest<N: SerializedNode>(path: impl AsRef<Path>) -> Result<Forest<N::ProblemType>> {
@@ -11,6 +26,89 @@ pub fn get_forest<N: SerializedNode>(path: impl AsRef<Path>) -> Result<Forest<N:
     Forest::from_serialized(serialized)
 }
 
+/// The iris forest serialized with pointers of `width`, and the rows of the
+/// iris dataset
+pub fn iris(width: PointerWidth) -> Result<(AVec<u8>, Vec<Vec<f32>>)> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    Ok((ClassificationProblem::serialize_with(&forest, width)?, rows))
+}
+
+/// Offset of the flags in the header of a serialized forest
+pub const FLAGS: usize = FORMAT_ABI.header.field("flags").offset;
+
+/// Trees of a single branch splitting on `x0 <= 0.5`, one per pair of its
+/// `(left, right)` leaves: the classes they vote for, or their indices in
+/// the leaf table of a regression forest
+pub fn stumps(leaves: &[(u32, u32)]) -> Vec<Branch> {
+    leaves
+        .iter()
+        .map(|&(left, right)| {
+            Branch::new(
+                0,
+                0.5,
+                NodePointer::new_leaf(left),
+                NodePointer::new_leaf(right),
+            )
+        })
+        .collect()
+}
+
+/// [`stumps`] of a regression forest of `num_trees` trees, each indexing
+/// its own two leaves of the leaf table
+pub fn regression_stumps(num_trees: u32) -> Vec<Branch> {
+    let leaves = (0..num_trees)
+        .map(|tree| (2 * tree, 2 * tree + 1))
+        .collect::<Vec<_>>();
+    stumps(&leaves)
+}
+
+/// A row on each side of the split of [`stumps`]
+pub const ROWS: [[f32; 1]; 2] = [[0.0], [1.0]];
+
+/// Regression forest of [`regression_stumps`], one tree each, and their
+/// `leaves`
+pub fn regression_forest<'a>(
+    nodes: &'a [Branch],
+    leaves: &'a [F32],
+) -> OptimizedForest<'a, Regression> {
+    OptimizedForest::<Regression>::with_leaves(nodes.len() as u32, nodes, 1, leaves).unwrap()
+}
+
+/// Classification forest of [`stumps`], one tree each, voting for one of
+/// `num_classes` classes
+pub fn classifier(nodes: &[Branch], num_classes: u8) -> OptimizedForest<'_, Classification> {
+    let problem = Classification::new(num_classes).unwrap();
+    OptimizedForest::<Classification>::new(nodes.len() as u32, nodes, 1, problem).unwrap()
+}
+
+/// Predictions of `forest` for `rows`
+pub fn predictions<F: Predict, const N: usize>(
+    forest: &F,
+    rows: &[[f32; N]],
+) -> Vec<<F::ProblemType as embedded::ProblemType>::Output> {
+    rows.iter().map(|row| forest.predict(row)).collect()
+}
+
+/// Predictions of `forest` for `rows` by [`PredictSession`]s polled one tree
+/// at a time
+pub fn session_predictions<P: Tally, const N: usize>(
+    forest: &OptimizedForest<P>,
+    rows: &[[f32; N]],
+) -> Vec<P::Output> {
+    rows.iter()
+        .map(|row| {
+            let mut session = PredictSession::new(forest, row);
+            loop {
+                if let Poll::Ready(prediction) = session.poll(1) {
+                    break prediction;
+                }
+            }
+        })
+        .collect()
+}
+
 pub fn get_test_data<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<Vec<T>> {
     let mut reader = csv::Reader::from_path(path.as_ref())?;
     let mut data = Vec::new();
@@ -21,6 +119,27 @@ pub fn get_test_data<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<Vec<
     Ok(data)
 }
 
+/// Assert that `forest`, host or optimized, makes the predictions the trainer
+/// of `host` recorded in the `Predicted` column of `data`: the same classes,
+/// or values within `tolerance`.
+pub fn assert_reproduces_recorded<P>(
+    host: &Forest<P>,
+    forest: &impl PredictLike<P::OptimizedType>,
+    data: impl AsRef<Path>,
+    tolerance: f64,
+) -> Result<()>
+where
+    P: Prune,
+    <P as ProblemType>::Output: Into<f64>,
+{
+    let dataset = read_labeled(data, &host.features_by_index(), "Predicted")?;
+    let recorded = P::parse_labels(host, &dataset.labels)?;
+
+    let comparison = compare_recorded(&recorded, forest, &dataset.rows, tolerance);
+    assert!(comparison.is_identical(), "{comparison}");
+    Ok(())
+}
+
 pub fn assert_epsilon(left: f32, right: f32, epsilon: f32) {
     println!(
         "left: {left}, right: {right}, epsilon: {epsilon}, |left - right| = {}",
@@ -28,3 +147,67 @@ pub fn assert_epsilon(left: f32, right: f32, epsilon: f32) {
     );
     assert!((left - right).abs() <= epsilon.abs());
 }
+
+/// Copy a forest definition file into `dir` with its rows in reverse order, so
+/// features and targets are encountered in a different order.
+pub fn reversed_rows(path: impl AsRef<Path>, dir: &Path) -> Result<PathBuf> {
+    let definition = std::fs::read_to_string(path.as_ref())?;
+    let mut lines = definition.lines();
+    // Problem type and column headers
+    let header = lines.by_ref().take(2).collect::<Vec<_>>();
+    let mut rows = lines.collect::<Vec<_>>();
+    rows.reverse();
+
+    let reversed = dir.join("reversed.csv");
+    std::fs::write(&reversed, [header, rows].concat().join("\n"))?;
+    Ok(reversed)
+}
+
+/// Write a classification forest definition (CSV) of `num_trees` complete
+/// trees of the given depth to `dir`, with features `x0` to `x3` and classes
+/// `a`, `b` and `c`.
+pub fn synthetic_forest(dir: &Path, num_trees: usize, depth: u32) -> Result<PathBuf> {
+    let mut definition = String::from(
+        "# { \"problem_type\": \"classification\" }\n\
+         \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n",
+    );
+    let branches = (1usize << depth) - 1;
+    for tree in 1..=num_trees {
+        for node in 1..=2 * branches + 1 {
+            let seed = tree * 31 + node * 17;
+            let line = if node <= branches {
+                format!(
+                    "{},{},\"x{}\",{},1,NA,{tree},{node}\n",
+                    2 * node,
+                    2 * node + 1,
+                    seed % 4,
+                    (seed % 97) as f32 / 97.0
+                )
+            } else {
+                let class = ["a", "b", "c"][seed % 3];
+                format!("0,0,NA,0,-1,\"{class}\",{tree},{node}\n")
+            };
+            definition.push_str(&line);
+        }
+    }
+
+    let path = dir.join("synthetic.csv");
+    std::fs::write(&path, definition)?;
+    Ok(path)
+}
+
+/// `count` rows of features in `[0, 1)` for [`synthetic_forest`], in the
+/// order of `features`.
+pub fn synthetic_rows(count: usize, features: &[&str]) -> Vec<Vec<f32>> {
+    (0..count)
+        .map(|row| {
+            features
+                .iter()
+                .map(|name| {
+                    let feature: usize = name[1..].parse().unwrap();
+                    ((row * 13 + feature * 29) % 101) as f32 / 101.0
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,145 @@
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::forest::{Classification, OptimizedForest, Predict};
+use embedded_rforest::{forest_consts, static_storage};
+use forest_optimizer::emit::{IndexConsts, const_identifier};
+use forest_optimizer::metadata::ForestMetadata;
+use forest_optimizer::problem_type::PredictionType;
+
+const IRIS: &[u8] = static_storage!("../test-forests/forest_iris_5.rforest");
+
+forest_consts!(mod iris = IRIS);
+
+// Not every index is needed by the tests
+#[allow(dead_code)]
+mod iris_indices {
+    include!("../snapshots/forest_iris_5_indices.rs");
+}
+
+// Indices generated for another version of the forest fail the build
+const _: () = assert!(iris::FINGERPRINT == iris_indices::FINGERPRINT);
+
+#[test]
+fn convert_emits_index_consts() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("forest_iris_5.rforest");
+
+    Command::cargo_bin("forest-optimizer")?
+        .args([
+            "convert",
+            "-i",
+            "./tests/test-forests/forest_iris_5.csv",
+            "-o",
+        ])
+        .arg(&output)
+        .args(["--emit-index-consts", "both"])
+        .assert()
+        .success();
+
+    assert_eq!(
+        std::fs::read(&output)?,
+        std::fs::read("./tests/test-forests/forest_iris_5.rforest")?
+    );
+    for extension in ["rs", "h"] {
+        assert_eq!(
+            std::fs::read_to_string(
+                dir.path()
+                    .join(format!("forest_iris_5_indices.{extension}"))
+            )?,
+            std::fs::read_to_string(format!(
+                "./tests/snapshots/forest_iris_5_indices.{extension}"
+            ))?,
+            "{extension}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn index_consts_address_features_and_classes() {
+    let forest = OptimizedForest::<Classification>::deserialize(IRIS).unwrap();
+    assert_eq!(iris_indices::NUM_FEATURES, iris::NUM_FEATURES);
+    assert_eq!(iris_indices::NUM_TARGETS, iris::NUM_TARGETS);
+    assert_eq!(iris_indices::FINGERPRINT, forest.feature_fingerprint());
+
+    // A small flower, as in tests/test-data/iris.csv
+    let mut features = [0.0; iris_indices::NUM_FEATURES];
+    features[iris_indices::FEATURE_SEPAL_LENGTH] = 5.1;
+    features[iris_indices::FEATURE_SEPAL_WIDTH] = 3.5;
+    features[iris_indices::FEATURE_PETAL_LENGTH] = 1.4;
+    features[iris_indices::FEATURE_PETAL_WIDTH] = 0.2;
+    assert_eq!(
+        forest.predict(&features) as usize,
+        iris_indices::CLASS_SETOSA
+    );
+}
+
+#[test]
+fn const_identifiers_are_sanitized() {
+    assert_eq!(
+        const_identifier("Sepal.Length").as_deref(),
+        Some("SEPAL_LENGTH")
+    );
+    assert_eq!(const_identifier(" mean  (x) ").as_deref(), Some("MEAN_X"));
+    assert_eq!(
+        const_identifier("Iris-setosa").as_deref(),
+        Some("IRIS_SETOSA")
+    );
+    assert_eq!(const_identifier("2nd").as_deref(), Some("2ND"));
+    assert_eq!(
+        const_identifier("température").as_deref(),
+        Some("TEMP_RATURE")
+    );
+    assert_eq!(const_identifier("--"), None);
+}
+
+#[test]
+fn colliding_identifiers_are_rejected() {
+    let metadata = |features: &[&str], targets: &[&str]| ForestMetadata {
+        problem_type: PredictionType::Classification,
+        features: features.iter().map(|name| name.to_string()).collect(),
+        targets: Some(targets.iter().map(|name| name.to_string()).collect()),
+        fingerprint: 1,
+        fixed_point: None,
+        abi: None,
+    };
+
+    let error = IndexConsts::new(&metadata(&["x", "a.b", "a b"], &["yes", "no"])).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Features 'a.b' and 'a b' would both be named FEATURE_A_B. Rename one of them"
+    );
+    let error = IndexConsts::new(&metadata(&["x"], &["Yes", "yes"])).unwrap_err();
+    assert!(error.to_string().contains("CLASS_YES"), "{error}");
+    let error = IndexConsts::new(&metadata(&["x", "?"], &["yes"])).unwrap_err();
+    assert!(error.to_string().contains("'?'"), "{error}");
+
+    // Features and classes do not collide with each other, nor with the counts
+    let consts = IndexConsts::new(&metadata(&["yes", "num features"], &["yes"])).unwrap();
+    assert_eq!(consts.features, ["FEATURE_YES", "FEATURE_NUM_FEATURES"]);
+    assert_eq!(consts.classes, ["CLASS_YES"]);
+}
+
+#[test]
+fn convert_fails_on_colliding_names() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let definition = dir.path().join("forest.csv");
+    let csv = std::fs::read_to_string("./tests/test-forests/forest_iris_5.csv")?;
+    std::fs::write(&definition, csv.replace("Petal.Width", "Petal Length"))?;
+    let output = dir.path().join("forest.rforest");
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--emit-index-consts", "c", "-i"])
+        .arg(&definition)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "'Petal.Length' and 'Petal Length' would both be named FEATURE_PETAL_LENGTH",
+        ));
+    assert!(!output.exists());
+
+    Ok(())
+}
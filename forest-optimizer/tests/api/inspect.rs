@@ -0,0 +1,109 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    ProblemKind,
+    deserialize::{BUFFER_ALIGN, FORMAT_VERSION, ForestHeader, HEADER_LEN, header_len},
+};
+use forest_optimizer::inspect::{inspect, read_model};
+use forest_optimizer::problem_type::PredictionType;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+
+use crate::helpers::get_forest;
+
+#[test]
+fn header_peek_matches_classification_fixture() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+
+    let header = ForestHeader::peek(&buffer).unwrap();
+    assert_eq!(header.problem_kind(), ProblemKind::Classification);
+    assert_eq!(header.num_trees as usize, forest.num_trees());
+    assert_eq!(header.num_features as usize, forest.num_features());
+    assert_eq!(
+        header.num_targets.unwrap().get() as usize,
+        forest.num_targets()
+    );
+    assert_eq!(header.node_count, forest.optimize_nodes().0.len());
+    assert_eq!(header.serialized_len(), buffer.len());
+
+    Ok(())
+}
+
+#[test]
+fn inspect_reports_regression_fixture() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/airfoil_100_200.rforest")?;
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+
+    let info = inspect(&buffer)?;
+    assert_eq!(info.problem_type, PredictionType::Regression);
+    assert_eq!(info.num_trees as usize, forest.num_trees());
+    assert_eq!(info.num_features as usize, forest.num_features());
+    assert_eq!(info.num_targets, None);
+    assert_eq!(info.node_count, forest.optimize_nodes().0.len());
+    assert_eq!(info.serialized_size, buffer.len());
+    assert!(info.is_valid());
+
+    Ok(())
+}
+
+#[test]
+fn inspect_reports_validation_failure_of_corrupted_forest() -> Result<()> {
+    let mut buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+
+    // Point the first branch's left child far outside the node array, as a
+    // branch rather than a prediction.
+    let nodes = ForestHeader::peek(&buffer).unwrap().header_len;
+    buffer[nodes..nodes + 4].copy_from_slice(&(u32::MAX >> 1).to_le_bytes());
+
+    let info = inspect(&buffer)?;
+    assert_eq!(info.problem_type, PredictionType::Classification);
+    assert!(!info.is_valid());
+
+    Ok(())
+}
+
+#[test]
+fn inspect_rejects_truncated_header() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+
+    assert!(ForestHeader::peek(&buffer[..6]).is_err());
+    assert!(inspect(&buffer[..6]).is_err());
+    // A partial node at the end of the buffer is also rejected
+    assert!(ForestHeader::peek(&buffer[..buffer.len() - 1]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn header_aligns_nodes_and_rejects_other_versions() -> Result<()> {
+    let mut buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+
+    // The header is followed by the tree table
+    let header = ForestHeader::peek(&buffer).unwrap();
+    assert!(header.tree_table);
+    assert_eq!(header.header_len, header_len(5));
+    assert_eq!(header.header_len, HEADER_LEN + 24);
+    assert_eq!(header.header_len % BUFFER_ALIGN, 0);
+    assert_eq!(buffer[7], FORMAT_VERSION);
+
+    // Forests of the first releases had a padding byte in place of the
+    // version
+    buffer[7] = 0;
+    assert_eq!(
+        ForestHeader::peek(&buffer),
+        Err(Error::UnsupportedVersion {
+            found: 0,
+            max_supported: FORMAT_VERSION
+        })
+    );
+    assert!(
+        inspect(&buffer)
+            .unwrap_err()
+            .to_string()
+            .contains("Convert it again")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use color_eyre::Result;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, IntegerBranch, NodeLayout, OptimizedForest, Predict,
+    Regression, deserialize::ForestHeader, integer::to_fixed,
+};
+use forest_optimizer::dataset::{read_labeled, read_mapped_rows};
+use forest_optimizer::emit::OutputFormat;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::integer::write_integer;
+use forest_optimizer::metadata::ForestMetadata;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::prune::{Validation, keep_first_trees};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+/// `row` in the fixed-point scales `shifts`.
+fn fixed(row: &[f32], shifts: &[i8]) -> Vec<i32> {
+    row.iter()
+        .zip(shifts)
+        .map(|(&value, &shift)| to_fixed(value, shift))
+        .collect()
+}
+
+#[test]
+fn integer_iris_keeps_its_accuracy() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+    let module = dir.path().join("iris.rs");
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let features = forest.features_by_index();
+    let dataset = read_labeled("./tests/test-data/iris.csv", &features, "Species")?;
+    let validation = Validation::new(&forest, dataset)?;
+
+    let report = write_integer(
+        &forest,
+        Some(&validation),
+        Some(0.0),
+        &[
+            (OutputFormat::Rforest, output.clone()),
+            (OutputFormat::RustModule, module.clone()),
+        ],
+        ForestMetadata::sidecar_path(&output),
+    )?;
+    let change = report.validation.unwrap();
+    assert_eq!(change.before, change.after);
+    assert_eq!(change.flips, 0);
+    assert_eq!(report.scales.leaf_shift, None);
+    assert_eq!(report.leaf_rounding, None);
+    assert_eq!(report.max_prediction_error, None);
+
+    // The scales are recorded for the firmware
+    let metadata = ForestMetadata::read(ForestMetadata::sidecar_path(&output))?;
+    assert_eq!(metadata.fixed_point.as_ref(), Some(&report.scales));
+    let shifts = &report.scales.feature_shifts;
+    assert_eq!(shifts.len(), features.len());
+    let module = std::fs::read_to_string(module)?;
+    assert!(module.contains(&format!(
+        "pub const IRIS_FEATURE_SHIFTS: [i8; {}]",
+        shifts.len()
+    )));
+
+    let bytes = read_model(&output)?;
+    assert_eq!(report.written_size, bytes.len());
+    assert_eq!(
+        ForestHeader::peek(&bytes).unwrap().layout,
+        NodeLayout::Integer
+    );
+    let integer = OptimizedForest::<Classification, IntegerBranch>::deserialize(&bytes).unwrap();
+    let standard = ClassificationProblem::serialize(&forest)?;
+    let standard = OptimizedForest::<Classification>::deserialize(&standard).unwrap();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+    for row in &rows {
+        assert_eq!(
+            integer.predict_i32(&fixed(row, shifts)),
+            standard.predict(row)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn integer_regression_reports_and_bounds_its_error() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("airfoil.rforest");
+    let forest = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        10,
+    );
+    let features = forest.features_by_index();
+    let dataset = read_labeled("./tests/test-data/airfoil.csv", &features, "f")?;
+    let validation = Validation::new(&forest, dataset)?;
+
+    let report = write_integer(
+        &forest,
+        Some(&validation),
+        None,
+        &[(OutputFormat::Rforest, output.clone())],
+        ForestMetadata::sidecar_path(&output),
+    )?;
+    let leaf_shift = report.scales.leaf_shift.unwrap();
+    let resolution = 2f64.powi(-i32::from(leaf_shift));
+    assert_eq!(report.leaf_rounding, Some(resolution / 2.0));
+
+    // Leaves are rounded to half the resolution, and their mean once more,
+    // on top of the precision of the f32 predictions compared to
+    let standard = RegressionProblem::serialize(&forest)?;
+    let standard = OptimizedForest::<Regression>::deserialize(&standard).unwrap();
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &features)?;
+    let largest = rows
+        .iter()
+        .map(|row| standard.predict(row).abs())
+        .fold(0.0, f32::max);
+    let bound = resolution + f64::from(largest) * f64::from(f32::EPSILON) * 16.0;
+    let max_error = report.max_prediction_error.unwrap();
+    assert!(max_error <= bound, "{max_error} > {bound}");
+
+    let change = report.validation.unwrap();
+    assert!((change.after - change.before).abs() <= bound);
+
+    let bytes = read_model(&output)?;
+    let integer = OptimizedForest::<Regression, IntegerBranch>::deserialize(&bytes).unwrap();
+    for row in &rows {
+        let prediction = integer.predict_i32(&fixed(row, &report.scales.feature_shifts));
+        let error = (f64::from(prediction) * resolution - f64::from(standard.predict(row))).abs();
+        assert!(error <= bound, "{error} > {bound}");
+    }
+
+    // Any layout reads it
+    let any = AnyOptimizedForest::<Regression>::deserialize(&bytes).unwrap();
+    assert_eq!(any.layout(), NodeLayout::Integer);
+
+    Ok(())
+}
+
+#[test]
+fn fixed_point_conversion_floors_and_saturates() {
+    assert_eq!(to_fixed(1.5, 2), 6);
+    assert_eq!(to_fixed(1.3, 0), 1);
+    assert_eq!(to_fixed(-1.3, 0), -2);
+    assert_eq!(to_fixed(-0.25, 2), -1);
+    assert_eq!(to_fixed(12.0, -2), 3);
+    assert_eq!(to_fixed(-13.0, -2), -4);
+    assert_eq!(to_fixed(1e20, 0), i32::MAX);
+    assert_eq!(to_fixed(-1e20, 0), i32::MIN);
+    assert_eq!(to_fixed(f32::INFINITY, 30), i32::MAX);
+    assert_eq!(to_fixed(f32::NAN, 3), 0);
+
+    // Integer readings shift exactly
+    for reading in [-1000i32, -3, 0, 7, 4096] {
+        assert_eq!(to_fixed(reading as f32, 4), reading << 4);
+        assert_eq!(to_fixed(reading as f32, -3), reading >> 3);
+    }
+}
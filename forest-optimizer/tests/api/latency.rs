@@ -0,0 +1,113 @@
+use color_eyre::Result;
+use embedded_rforest::forest::NodeLayout;
+use forest_optimizer::analyze::analyze;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::forest::profile::TraversalProfile;
+use forest_optimizer::latency::{
+    LatencyEstimate, LatencyModel, NodeVisits, aggregation_cycles, node_visits,
+};
+use forest_optimizer::problem_type::Classification;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedForest};
+
+/// Two stumps on `x` around a tree splitting on `x` then `y`, so of depths
+/// 1, 2 and 1, then a single leaf.
+fn forest() -> Result<Forest<Classification>> {
+    let definition = "# { \"problem_type\": \"classification\" }\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n\
+        2,3,\"x\",0.5,1,NA,2,1\n\
+        0,0,NA,0,-1,\"a\",2,2\n\
+        4,5,\"y\",0.5,1,NA,2,3\n\
+        0,0,NA,0,-1,\"b\",2,4\n\
+        0,0,NA,0,-1,\"c\",2,5\n\
+        2,3,\"x\",1.5,1,NA,3,1\n\
+        0,0,NA,0,-1,\"c\",3,2\n\
+        0,0,NA,0,-1,\"a\",3,3\n\
+        0,0,NA,0,-1,\"b\",4,1\n";
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())?;
+    Forest::from_serialized(serialized)
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+}
+
+#[test]
+fn visits_follow_the_depth_of_the_trees() -> Result<()> {
+    let visits = node_visits(&forest()?, None)?;
+
+    // The single leaf is one branch once optimized
+    assert_eq!(visits.best, 4);
+    assert_eq!(visits.worst, 5);
+    assert_close(visits.expected, 1.0 + 5.0 / 3.0 + 1.0 + 1.0);
+    assert!(!visits.profiled);
+    Ok(())
+}
+
+#[test]
+fn estimate_adds_the_aggregation_to_the_visits() -> Result<()> {
+    // 4 trees voting for 3 classes
+    let aggregation = aggregation_cycles(4, Some(3));
+    assert_close(aggregation, 25.0);
+    assert_close(aggregation_cycles(4, None), 16.0);
+
+    let model = LatencyModel {
+        cycles_per_node: 10.0,
+        clock_mhz: 5.0,
+    };
+    let estimate = LatencyEstimate::new(model, node_visits(&forest()?, None)?, aggregation);
+    assert_close(estimate.best_us, (4.0 * 10.0 + 25.0) / 5.0);
+    assert_close(estimate.worst_us, (5.0 * 10.0 + 25.0) / 5.0);
+    assert_close(estimate.expected_us, (14.0 / 3.0 * 10.0 + 25.0) / 5.0);
+    assert!(estimate.best_us <= estimate.expected_us);
+    assert!(estimate.expected_us <= estimate.worst_us);
+
+    let display = estimate.to_string();
+    assert!(
+        display.contains("10 cycles per branch at 5 MHz"),
+        "{display}"
+    );
+    Ok(())
+}
+
+#[test]
+fn profile_weights_the_expected_visits() -> Result<()> {
+    let forest = forest()?;
+    // 3 branches for the first row, 4 for the second, which goes down `y`
+    let profile = forest.profile(&[vec![0.0, 0.0], vec![1.0, 1.0]]);
+    let visits = node_visits(&forest, Some(&profile))?;
+
+    assert_eq!((visits.best, visits.worst), (4, 5));
+    assert_close(visits.expected, 3.5 + 1.0);
+    assert!(visits.profiled);
+
+    let empty = TraversalProfile::default();
+    assert!(node_visits(&forest, Some(&empty)).is_err());
+    Ok(())
+}
+
+#[test]
+fn analyze_defaults_to_the_narrowest_layout() -> Result<()> {
+    let forest = forest()?;
+    let analysis = analyze(&forest)?;
+
+    assert_eq!(
+        analysis.latency.model,
+        LatencyModel::for_layout(NodeLayout::Narrow)
+    );
+    assert_eq!(
+        analysis.latency.visits,
+        NodeVisits {
+            best: 4,
+            expected: node_visits(&forest, None)?.expected,
+            worst: 5,
+            profiled: false,
+        }
+    );
+    assert_close(analysis.latency.aggregation_cycles, 25.0);
+    assert!(analysis.to_string().contains("--- Latency estimate ---"));
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{Classification, OptimizedForest};
+use forest_optimizer::conversion::{ToOptimized, leaf_ids};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::problem_type::Classification as ClassificationProblem;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+/// Check that the leaf ids of the serialized `forest` map to the leaves the
+/// host forest reaches, on every row
+fn assert_ids_match<P: WriteForest + ToOptimized>(
+    forest: &Forest<P>,
+    rows: &[Vec<f32>],
+) -> Result<()> {
+    let buffer = <P as WriteForest>::serialize(forest)?;
+    let optimized = OptimizedForest::<P::OptimizedType>::deserialize(&buffer).unwrap();
+    let map = leaf_ids(forest, &optimized).unwrap();
+
+    let mut ids = vec![0; forest.num_trees()];
+    for row in rows {
+        optimized.predict_leaf_ids(row, &mut ids).unwrap();
+        let mapped = ids.iter().map(|id| map[id]).collect::<Vec<_>>();
+        assert_eq!(mapped, forest.predict_leaf_ids(row));
+        for leaf in mapped {
+            assert!(forest.nodes()[leaf].is_leaf());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn leaf_ids_map_to_the_leaves_of_the_host_forest() -> Result<()> {
+    for path in [
+        "./tests/test-forests/forest_iris_5.csv",
+        "./tests/test-forests/forest_iris_800.csv",
+        "./tests/test-forests/forest_iris_single_leaf.csv",
+    ] {
+        let forest = get_forest::<SerializedClassificationNode>(path)?;
+        let features = forest.features_by_index();
+        let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+        assert_ids_match(&forest, &rows)?;
+    }
+
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &features)?;
+    assert_ids_match(&forest, &rows)
+}
+
+#[test]
+fn leaf_ids_follow_the_features() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+
+    let ids = |row: &[f32]| {
+        let mut ids = [0; 5];
+        optimized.predict_leaf_ids(row, &mut ids).unwrap();
+        ids
+    };
+    for row in &rows {
+        assert_eq!(ids(row), ids(&row.clone()));
+        assert_eq!(forest.predict_leaf_ids(row), forest.predict_leaf_ids(row));
+
+        // Features far below or above every split point reach the leftmost
+        // and the rightmost leaves of every tree, at least one of which the
+        // row does not reach
+        let shifted = |shift: f32| row.iter().map(|v| v + shift).collect::<Vec<_>>();
+        let (low, high) = (shifted(-10.0), shifted(10.0));
+        assert!(ids(row) != ids(&low) || ids(row) != ids(&high));
+        let host = forest.predict_leaf_ids(row);
+        assert!(host != forest.predict_leaf_ids(&low) || host != forest.predict_leaf_ids(&high));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn leaf_ids_need_one_slot_per_tree() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+
+    let features = [1.0; 4];
+    for len in [0, 4, 6] {
+        assert_eq!(
+            optimized.predict_leaf_ids(&features, &mut vec![0; len]),
+            Err(Error::WrongOutputLength)
+        );
+    }
+    assert_eq!(optimized.predict_leaf_ids(&features, &mut [0; 5]), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn leaf_ids_reject_other_forests() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let other =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let buffer = ClassificationProblem::serialize(&other)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+
+    assert!(leaf_ids(&forest, &optimized).is_err());
+
+    Ok(())
+}
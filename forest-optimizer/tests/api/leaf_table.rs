@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{OptimizedForest, Predict, Regression, deserialize::ForestHeader};
+use forest_optimizer::analyze::analyze;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::problem_type::Regression as RegressionProblem;
+use forest_optimizer::serialized_forest::SerializedRegressionNode;
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+#[test]
+fn airfoil_round_trips_leaves_exactly() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let buffer = read_model("./tests/test-forests/airfoil_100_200.rforest")?;
+    assert_eq!(
+        buffer.as_slice(),
+        RegressionProblem::serialize(&forest)?.as_slice()
+    );
+
+    let optimized = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+    let (_, leaves) = RegressionProblem::optimize(&forest);
+    let table = optimized
+        .leaves()
+        .iter()
+        .map(|value| value.get().to_bits())
+        .collect::<Vec<_>>();
+
+    // Every distinct value is stored exactly once
+    assert_eq!(
+        table,
+        leaves
+            .iter()
+            .map(|value| value.get().to_bits())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(table.iter().collect::<HashSet<_>>().len(), table.len());
+
+    // Predictions match those of the unoptimized forest, bit for bit
+    let features = forest.features_by_index();
+    for row in read_mapped_rows("./tests/test-data/airfoil.csv", &features)? {
+        assert_eq!(
+            optimized.predict(&row).to_bits(),
+            forest.predict(&row).to_bits()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn analyze_reports_leaf_table_deduplication() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+
+    let analysis = analyze(&forest)?;
+    let table = analysis.leaf_table.unwrap();
+    assert_eq!(table.leaves, analysis.leaves);
+    assert!(table.entries < table.leaves);
+    assert_eq!(table.size, table.entries * size_of::<f32>());
+    assert!(table.dedup_ratio > 1.0);
+    assert_eq!(
+        analysis.serialized_size,
+        read_model("./tests/test-forests/airfoil_100_200.rforest")?.len()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn leaf_index_out_of_the_table_is_rejected() -> Result<()> {
+    let mut buffer = read_model("./tests/test-forests/airfoil_100_200.rforest")?;
+    let header = ForestHeader::peek(&buffer).unwrap();
+
+    // Find a branch whose left child is a leaf, and point it past the table
+    let branch = (0..header.node_count)
+        .map(|i| header.header_len + i * 16)
+        .find(|&offset| buffer[offset + 3] & 0x80 != 0)
+        .unwrap();
+    let pointer = header.num_leaves as u32 | 1 << 31;
+    buffer[branch..branch + 4].copy_from_slice(&pointer.to_le_bytes());
+
+    assert!(matches!(
+        OptimizedForest::<Regression>::deserialize(&buffer),
+        Err(Error::MalformedForest)
+    ));
+
+    Ok(())
+}
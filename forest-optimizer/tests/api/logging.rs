@@ -0,0 +1,91 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use color_eyre::Result;
+use forest_optimizer::forest::DEEP_TREE_WARNING;
+use forest_optimizer::serialized_forest::SerializedClassificationNode;
+use tracing::level_filters::LevelFilter;
+
+use crate::helpers::get_forest;
+
+/// Log output shared between a test and its subscriber
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A classification tree which is a chain of `depth` branches, followed by an
+/// unreachable branch splitting on "rpm".
+fn deep_forest_definition(depth: usize) -> String {
+    let mut csv = String::from(
+        "# { \"problem_type\": \"classification\" }\n\
+         \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n",
+    );
+    for k in 1..=depth {
+        let branch = 2 * k - 1;
+        csv += &format!("{},{},\"a\",{k},1,NA,1,{branch}\n", branch + 1, branch + 2);
+        csv += &format!("0,0,NA,0,-1,\"x\",1,{}\n", branch + 1);
+    }
+    let last = 2 * depth + 1;
+    csv += &format!("0,0,NA,0,-1,\"y\",1,{last}\n");
+    csv += &format!("{},{},\"rpm\",1,1,NA,1,{}\n", last + 2, last + 3, last + 1);
+    csv += &format!("0,0,NA,0,-1,\"x\",1,{}\n", last + 2);
+    csv += &format!("0,0,NA,0,-1,\"y\",1,{}\n", last + 3);
+    csv
+}
+
+#[test]
+fn suspicious_forests_log_warnings() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("deep.csv");
+    std::fs::write(&input, deep_forest_definition(DEEP_TREE_WARNING + 1))?;
+
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::WARN)
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+
+    let forest = tracing::subscriber::with_default(subscriber, || {
+        get_forest::<SerializedClassificationNode>(&input)
+    })?;
+    assert_eq!(forest.num_trees(), 1);
+
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone())?;
+    assert!(logs.contains(&format!("tree 0 has depth {}", DEEP_TREE_WARNING + 1)));
+    assert!(logs.contains("feature 'rpm' declared but never used"));
+    assert!(!logs.contains("feature 'a'"));
+
+    Ok(())
+}
+
+#[test]
+fn gaps_in_tree_indices_log_a_warning() -> Result<()> {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::WARN)
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+
+    let forest = tracing::subscriber::with_default(subscriber, || {
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5_gaps.csv")
+    })?;
+    assert_eq!(forest.num_trees(), 5);
+
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone())?;
+    assert!(logs.contains("Tree indices 3, 6..=7 are missing, renumbering trees"));
+
+    Ok(())
+}
@@ -1,6 +1,77 @@
+mod abi;
+mod aggregation;
+mod agreement;
+mod analyze;
+#[cfg(feature = "bench")]
+mod bench;
+mod build;
+mod bundle;
+mod cache;
+mod calibration;
+mod cli;
+mod compact;
+mod compare;
+mod conversion;
+mod decimal_comma;
+mod deserialization;
+mod diff;
+mod encryption;
+mod endianness;
+mod errors;
+mod extra_columns;
+mod fingerprint;
+mod flatten;
 mod forest_accuracy;
+mod forest_consts;
+mod index_consts;
+mod inspect;
+mod integer;
+mod leaf_ids;
+mod leaf_table;
+mod latency;
+mod logging;
+mod metrics;
+mod migrate;
+mod monotonic;
+mod names;
+mod no_alloc;
+mod node_access;
+mod ordinal;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod pointer_encoding;
+mod pointer_width;
 mod problem_types;
+mod profile;
+#[cfg(feature = "avr-progmem")]
+mod progmem;
+#[cfg(feature = "test-support")]
+mod properties;
+mod prune;
+mod quantize;
+mod quickscorer;
+mod relative_pointers;
+mod sections;
 mod serialization;
+mod session;
+mod signature;
+mod single_leaf;
+mod slot;
+#[cfg(feature = "soa")]
+mod soa;
+mod split_rule;
+mod split_var;
+mod stats;
+mod streaming;
+mod subset;
+mod support;
+mod test_vectors;
+mod to_csv;
+mod transform;
+mod tree_table;
+mod validate;
+mod visit;
+mod votes;
 
 mod helpers;
 
@@ -0,0 +1,154 @@
+use color_eyre::Result;
+use color_eyre::eyre::Context;
+use embedded_rforest::forest::{Classification, OptimizedForest, Regression};
+use forest_optimizer::compare::compare;
+use forest_optimizer::dataset::read_labeled;
+use forest_optimizer::evaluate::{evaluate_classification, evaluate_regression};
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::metadata::ForestMetadata;
+use forest_optimizer::metrics::{classification_metrics, regression_metrics};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{write_classification, write_regression};
+
+use crate::helpers::{assert_epsilon, get_forest};
+
+#[test]
+fn classification_metrics_match_hand_computed_values() {
+    // truth:     0 0 0 1 1 2
+    // predicted: 0 0 1 1 2 2
+    let metrics = classification_metrics(&[0, 0, 1, 1, 2, 2], &[0, 0, 0, 1, 1, 2], 3);
+
+    assert_eq!(metrics.accuracy, 4.0 / 6.0);
+    assert_eq!(
+        metrics.confusion,
+        vec![vec![2, 1, 0], vec![0, 1, 1], vec![0, 0, 1]]
+    );
+
+    assert_eq!(metrics.per_class[0].precision, 1.0);
+    assert_eq!(metrics.per_class[0].recall, 2.0 / 3.0);
+    assert_eq!(metrics.per_class[1].precision, 0.5);
+    assert_eq!(metrics.per_class[1].recall, 0.5);
+    assert_eq!(metrics.per_class[2].precision, 0.5);
+    assert_eq!(metrics.per_class[2].recall, 1.0);
+
+    // 2PR / (P + R)
+    assert_eq!(metrics.per_class[0].f1, 0.8);
+    assert_eq!(metrics.per_class[1].f1, 0.5);
+    assert_eq!(metrics.per_class[2].f1, 2.0 / 3.0);
+}
+
+#[test]
+fn classification_metrics_of_absent_class_are_zero() {
+    let metrics = classification_metrics(&[0, 0], &[0, 0], 2);
+
+    assert_eq!(metrics.accuracy, 1.0);
+    assert_eq!(metrics.per_class[1].precision, 0.0);
+    assert_eq!(metrics.per_class[1].recall, 0.0);
+    assert_eq!(metrics.per_class[1].f1, 0.0);
+
+    // Nor does an empty truth set give NaN
+    let metrics = classification_metrics(&[], &[], 2);
+    assert_eq!(metrics.accuracy, 0.0);
+    assert!(metrics.per_class.iter().all(|c| c.f1 == 0.0));
+
+    let json = serde_json::to_value(&metrics).unwrap();
+    assert_eq!(json["per_class"][1]["f1"], 0.0);
+    assert_eq!(json["confusion"], serde_json::json!([[0, 0], [0, 0]]));
+}
+
+#[test]
+fn classification_report_names_classes() {
+    let metrics = classification_metrics(&[0, 1, 1], &[0, 1, 0], 2);
+    let labels = ["cat".to_string(), "dog".to_string()];
+    let report = metrics.report(Some(&labels)).to_string();
+
+    assert!(report.starts_with("Accuracy: 66.67%\n"));
+    assert!(report.contains("cat          100.00%   50.00%   66.67%\n"));
+    assert!(report.contains("dog           50.00%  100.00%   66.67%\n"));
+    assert!(report.contains("cat                1          1\n"));
+
+    let report = metrics.report(None).to_string();
+    assert!(report.contains("0            100.00%   50.00%   66.67%\n"));
+}
+
+#[test]
+fn regression_metrics_match_hand_computed_values() {
+    // Errors: 1, -2, 0, 3
+    let metrics = regression_metrics(&[2.0, 0.0, 5.0, 7.0], &[1.0, 2.0, 5.0, 4.0]);
+
+    assert_eq!(metrics.mae, 6.0 / 4.0);
+    assert_eq!(metrics.rmse, (14.0f64 / 4.0).sqrt());
+    assert_eq!(metrics.max_error, 3.0);
+    // Truth mean is 3, so its variance sums to 4 + 1 + 4 + 1
+    assert_eq!(metrics.r2, 1.0 - 14.0 / 10.0);
+}
+
+#[test]
+fn regression_r2_of_constant_truth_is_finite() {
+    assert_eq!(regression_metrics(&[1.0, 1.0], &[1.0, 1.0]).r2, 1.0);
+    assert_eq!(regression_metrics(&[1.0, 2.0], &[1.0, 1.0]).r2, 0.0);
+    assert_eq!(regression_metrics(&[], &[]).r2, 0.0);
+
+    let json = serde_json::to_value(regression_metrics(&[2.0], &[1.0])).unwrap();
+    assert_eq!(json["max_error"], 1.0);
+    assert_eq!(json["r2"], 0.0);
+}
+
+#[test]
+fn converted_classification_forest_reproduces_its_predictions() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+    write_classification("./tests/test-forests/forest_iris_800.csv", &output)?;
+
+    let buffer = read_model(&output)?;
+    let optimized =
+        OptimizedForest::<Classification>::deserialize(&buffer).context("Malformed forest")?;
+    let metadata = ForestMetadata::read(ForestMetadata::sidecar_path(&output))?;
+    let targets = metadata.targets.unwrap();
+
+    let dataset = read_labeled(
+        "./tests/test-data/iris.csv",
+        &metadata.features,
+        "Predicted",
+    )?;
+    let metrics = evaluate_classification(&optimized, &dataset, &targets)?;
+    assert_eq!(metrics.accuracy, 1.0);
+
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    assert!(compare(&forest, &optimized, &dataset.rows).is_identical());
+    // The host forest is evaluated alike
+    assert_eq!(
+        evaluate_classification(&forest, &dataset, &targets)?,
+        metrics
+    );
+
+    Ok(())
+}
+
+#[test]
+fn converted_regression_forest_is_close_to_its_predictions() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("airfoil.rforest");
+    write_regression("./tests/test-forests/airfoil_100_200.csv", &output)?;
+
+    let buffer = read_model(&output)?;
+    let optimized =
+        OptimizedForest::<Regression>::deserialize(&buffer).context("Malformed forest")?;
+    let metadata = ForestMetadata::read(ForestMetadata::sidecar_path(&output))?;
+
+    let dataset = read_labeled(
+        "./tests/test-data/airfoil.csv",
+        &metadata.features,
+        "Predicted",
+    )?;
+    let metrics = evaluate_regression(&optimized, &dataset)?;
+    assert_epsilon(metrics.max_error as f32, 0.0, 2.5);
+
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    assert!(compare(&forest, &optimized, &dataset.rows).is_identical());
+    assert_eq!(evaluate_regression(&forest, &dataset)?, metrics);
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use aligned_vec::AVec;
+use assert_cmd::Command;
+use color_eyre::{Report, Result};
+use embedded_rforest::Error;
+use embedded_rforest::forest::deserialize::{FORMAT_VERSION, ForestHeader, peek_version};
+use embedded_rforest::forest::{Classification, OptimizedForest, Predict, Regression};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::inspect::{inspect, read_model};
+use forest_optimizer::migrate::migrate;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::WriteForest;
+use predicates::str::contains;
+
+use crate::helpers::get_forest;
+
+const LEGACY: &str = "./tests/test-forests/legacy";
+
+/// The legacy fixtures, as written by the releases of each format version,
+/// and the version they were written in
+fn legacy(name: &str) -> Result<Vec<(u8, AVec<u8>)>> {
+    let mut forests = Vec::new();
+    for version in 0..FORMAT_VERSION {
+        let path = format!("{LEGACY}/{name}_v{version}.rforest");
+        if std::path::Path::new(&path).exists() {
+            forests.push((version, read_model(path)?));
+        }
+    }
+    Ok(forests)
+}
+
+#[test]
+fn devices_reject_older_versions() -> Result<()> {
+    let fixtures = legacy("forest_iris_5")?;
+    assert_eq!(fixtures.len(), 3);
+    for (version, bytes) in fixtures {
+        assert_eq!(peek_version(&bytes), Ok(version));
+        let unsupported = Error::UnsupportedVersion {
+            found: version,
+            max_supported: FORMAT_VERSION,
+        };
+        assert_eq!(ForestHeader::peek(&bytes), Err(unsupported));
+        assert_eq!(
+            OptimizedForest::<Classification>::deserialize(&bytes).err(),
+            Some(unsupported)
+        );
+        assert!(inspect(&bytes).unwrap_err().to_string().contains("migrate"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn migrated_classification_forests_predict_like_before() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+    let current = ClassificationProblem::serialize(&forest)?;
+    let current = OptimizedForest::<Classification>::deserialize(&current).unwrap();
+
+    let mut upgraded = Vec::new();
+    for (version, bytes) in legacy("forest_iris_5")? {
+        let migrated = migrate(&bytes)?;
+        let header = ForestHeader::peek(&migrated).unwrap();
+        assert_eq!(header.num_trees, 5);
+        let optimized = OptimizedForest::<Classification>::deserialize(&migrated).unwrap();
+        for row in &rows {
+            assert_eq!(optimized.predict(row), current.predict(row), "v{version}");
+        }
+        upgraded.push(migrated);
+    }
+    // Only the header changed between the first versions of classification
+    // forests
+    assert!(upgraded.windows(2).all(|pair| pair[0] == pair[1]));
+
+    // Forests of the current version are left as they are
+    let fixture = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    assert_eq!(migrate(&fixture)?, fixture);
+
+    Ok(())
+}
+
+#[test]
+fn migrated_regression_forests_predict_like_before() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("../bench-data/raw-forests/airfoil_100_50.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &features)?;
+    let current = RegressionProblem::serialize(&forest)?;
+    let current = OptimizedForest::<Regression>::deserialize(&current).unwrap();
+
+    // Leaves in the pointers, then in a leaf table
+    let fixtures = legacy("airfoil_100_50")?;
+    assert_eq!(fixtures.iter().map(|(v, _)| *v).collect::<Vec<_>>(), [0, 2]);
+    for (version, bytes) in fixtures {
+        let migrated = migrate(&bytes)?;
+        let optimized = OptimizedForest::<Regression>::deserialize(&migrated).unwrap();
+        assert_eq!(
+            ForestHeader::peek(&migrated).unwrap().num_leaves,
+            current.leaves().len(),
+            "v{version}"
+        );
+        for row in &rows {
+            assert_eq!(
+                optimized.predict(row).to_bits(),
+                current.predict(row).to_bits(),
+                "v{version}: {row:?}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn newer_and_malformed_forests_are_not_migrated() -> Result<()> {
+    let mut newer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    newer[7] = FORMAT_VERSION + 1;
+    let report: Report = migrate(&newer).unwrap_err();
+    assert!(report.chain().any(|cause| {
+        cause.downcast_ref::<Error>()
+            == Some(&Error::UnsupportedVersion {
+                found: FORMAT_VERSION + 1,
+                max_supported: FORMAT_VERSION,
+            })
+    }));
+
+    let (_, old) = legacy("forest_iris_5")?.remove(0);
+    assert!(migrate(&old[..7]).is_err());
+    assert!(migrate(&old[..old.len() - 3]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn cli_migrates_a_forest() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let new = dir.path().join("iris.rforest");
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["migrate", &format!("{LEGACY}/forest_iris_5_v0.rforest")])
+        .arg(&new)
+        .assert()
+        .success()
+        .stdout(contains(format!(
+            "from format version 0 to {FORMAT_VERSION}"
+        )));
+    Command::cargo_bin("forest-optimizer")?
+        .arg("info")
+        .arg(&new)
+        .assert()
+        .success();
+
+    // In place
+    Command::cargo_bin("forest-optimizer")?
+        .arg("migrate")
+        .arg(&new)
+        .assert()
+        .success()
+        .stdout(contains(format!(
+            "from format version {FORMAT_VERSION} to {FORMAT_VERSION}"
+        )));
+
+    Ok(())
+}
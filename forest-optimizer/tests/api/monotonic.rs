@@ -0,0 +1,174 @@
+use assert_cmd::Command;
+use color_eyre::Result;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::forest::monotonic::{Direction, Monotonicity, Violation};
+use forest_optimizer::problem_type::{Classification, Regression};
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedRegressionNode,
+};
+use predicates::str::contains;
+
+use crate::helpers::get_forest;
+
+const COLUMNS: &str = "\"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n";
+
+/// A tree increasing in `x`, whose left subtree splits on `y` into 1 and 2
+/// and whose right leaf predicts 3
+const INCREASING: &str = "\
+    2,3,\"x\",0.5,-3,0,1,1\n\
+    4,5,\"y\",0.5,-3,0,1,2\n\
+    0,0,NA,0,-1,3,1,3\n\
+    0,0,NA,0,-1,1,1,4\n\
+    0,0,NA,0,-1,2,1,5\n";
+
+/// A tree splitting on `y`, then on `x` into 5 and 4 on its right
+const DECREASING: &str = "\
+    2,3,\"y\",0.5,-3,0,2,1\n\
+    0,0,NA,0,-1,0,2,2\n\
+    4,5,\"x\",1.5,-3,0,2,3\n\
+    0,0,NA,0,-1,5,2,4\n\
+    0,0,NA,0,-1,4,2,5\n";
+
+fn definition(rows: &str) -> String {
+    format!("# {{ \"problem_type\": \"regression\" }}\n{COLUMNS}{rows}")
+}
+
+fn forest(rows: &str) -> Result<Forest<Regression>> {
+    let definition = definition(rows);
+    let serialized =
+        SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())?;
+    Forest::from_serialized(serialized)
+}
+
+#[test]
+fn monotonic_trees_have_no_violations() -> Result<()> {
+    let forest = forest(INCREASING)?;
+    assert_eq!(forest.verify_monotonic("x", Direction::Increasing)?, []);
+    // The split on `y` only orders 1 and 2
+    assert_eq!(forest.verify_monotonic("y", Direction::Increasing)?, []);
+    assert_eq!(
+        forest.verify_monotonic("y", Direction::Decreasing)?.len(),
+        1
+    );
+
+    let check = Regression::check_monotonic(&forest, "x", Direction::Increasing)?;
+    assert!(check.is_monotonic());
+    assert_eq!(check.to_string(), "x (increasing): monotonic\n");
+
+    Ok(())
+}
+
+#[test]
+fn violations_name_the_branch_and_its_leaves() -> Result<()> {
+    let forest = forest(&format!("{INCREASING}{DECREASING}"))?;
+
+    let violations = forest.verify_monotonic("x", Direction::Increasing)?;
+    let [violation] = violations[..] else {
+        panic!("expected one violation, got {violations:?}");
+    };
+    assert_eq!(
+        violation,
+        Violation {
+            tree: 1,
+            node: violation.node,
+            depth: 1,
+            split_at: 1.5,
+            left: 5.0,
+            right: 4.0,
+        }
+    );
+    assert!(forest.nodes()[violation.node].is_branch());
+
+    // Decreasing, the first tree breaks it: its left subtree predicts 1,
+    // below the 3 of its right leaf
+    let violations = forest.verify_monotonic("x", Direction::Decreasing)?;
+    assert_eq!(violations.len(), 1);
+    assert_eq!(
+        (violations[0].tree, violations[0].depth, violations[0].node),
+        (0, 0, 0)
+    );
+    assert_eq!((violations[0].left, violations[0].right), (1.0, 3.0));
+
+    let check = Regression::check_monotonic(&forest, "x", Direction::Increasing)?;
+    assert!(!check.is_monotonic());
+    assert_eq!(check.violating_trees(), 1);
+    assert!(
+        check
+            .to_string()
+            .contains("split at 1.5: left leaf 5 vs right leaf 4")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn ties_are_monotonic_both_ways() -> Result<()> {
+    let forest = forest(
+        "\
+        2,3,\"x\",0.5,-3,0,1,1\n\
+        0,0,NA,0,-1,2,1,2\n\
+        0,0,NA,0,-1,2,1,3\n",
+    )?;
+    assert_eq!(forest.verify_monotonic("x", Direction::Increasing)?, []);
+    assert_eq!(forest.verify_monotonic("x", Direction::Decreasing)?, []);
+
+    Ok(())
+}
+
+#[test]
+fn monotonicity_needs_a_known_feature_and_a_regression_forest() -> Result<()> {
+    let forest = forest(INCREASING)?;
+    assert!(forest.verify_monotonic("z", Direction::Increasing).is_err());
+
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let feature = forest.features_by_index()[0].to_string();
+    assert!(Classification::check_monotonic(&forest, &feature, Direction::Increasing).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn analyze_fails_on_violations() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("forest.csv");
+    std::fs::write(&input, definition(&format!("{INCREASING}{DECREASING}")))?;
+    let report = dir.path().join("analysis.json");
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["analyze", "--check-monotonic", "y:inc", "-i"])
+        .arg(&input)
+        .assert()
+        .success()
+        .stdout(contains("y (increasing): monotonic"));
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["analyze", "--check-monotonic", "y:inc,x:dec", "-i"])
+        .arg(&input)
+        .assert()
+        .failure()
+        .stdout(contains("y (increasing): monotonic"))
+        .stdout(contains("x (decreasing): 1 violations in 1 trees"));
+
+    // The violation is in the JSON report as well
+    Command::cargo_bin("forest-optimizer")?
+        .args(["analyze", "--check-monotonic", "x:inc", "-i"])
+        .arg(&input)
+        .arg("--report-json")
+        .arg(&report)
+        .assert()
+        .failure()
+        .stdout(contains("x (increasing): 1 violations in 1 trees"));
+    let json: serde_json::Value = serde_json::from_slice(&std::fs::read(&report)?)?;
+    assert_eq!(json["monotonicity"][0]["direction"], "increasing");
+    assert_eq!(json["monotonicity"][0]["violations"][0]["split_at"], 1.5);
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["analyze", "--check-monotonic", "x:up", "-i"])
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(contains("unknown direction 'up'"));
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use color_eyre::Result;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::problem_type::{
+    Classification, FeatureIdx, FeatureMap, Regression, TargetMap,
+};
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedRegressionNode,
+};
+
+use crate::helpers::get_forest;
+
+#[test]
+fn names_are_positioned_by_index() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+
+    let features = forest.features_by_index();
+    assert_eq!(features.len(), forest.num_features());
+    for (index, name) in features.iter().enumerate() {
+        assert_eq!(forest.feature_index(name), Some(FeatureIdx(index as u32)));
+    }
+    assert_eq!(forest.feature_index("not a feature"), None);
+
+    let targets = forest.targets_by_index();
+    assert_eq!(targets.len(), forest.num_targets());
+    for (index, name) in targets.iter().enumerate() {
+        assert_eq!(forest.class_name(index as u32), Some(*name));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn serialized_forests_agree_with_their_forest() -> Result<()> {
+    let path = "./tests/test-forests/forest_iris_5.csv";
+    let serialized = SerializedForest::<SerializedClassificationNode>::read(path)?;
+    let forest = get_forest::<SerializedClassificationNode>(path)?;
+    assert_eq!(serialized.features_by_index(), forest.features_by_index());
+    assert_eq!(serialized.targets_by_index(), forest.targets_by_index());
+    assert_eq!(
+        serialized.feature_index("Petal.Width"),
+        forest.feature_index("Petal.Width")
+    );
+
+    let path = "./tests/test-forests/airfoil_100_200.csv";
+    let serialized = SerializedForest::<SerializedRegressionNode>::read(path)?;
+    let forest = get_forest::<SerializedRegressionNode>(path)?;
+    assert_eq!(serialized.features_by_index(), forest.features_by_index());
+
+    Ok(())
+}
+
+#[test]
+fn non_contiguous_indices_are_rejected() {
+    let regression = |features: &str| {
+        let trees = format!(
+            r#"{{ "problem": {{ "features": {features} }},
+                "trees": [[{{ "Leaf": {{ "prediction": 1.0 }} }}]] }}"#
+        );
+        serde_json::from_str::<Forest<Regression>>(&trees).map_err(|e| e.to_string())
+    };
+
+    let forest = regression(r#"{ "x": 1, "y": 0 }"#).unwrap();
+    assert_eq!(forest.features_by_index(), ["y", "x"]);
+
+    let error = regression(r#"{ "x": 0, "y": 2 }"#).unwrap_err();
+    assert!(
+        error.contains("'y' has index 2, past the 2 features"),
+        "{error}"
+    );
+    let error = regression(r#"{ "x": 1, "y": 1 }"#).unwrap_err();
+    assert!(error.contains("share index 1"), "{error}");
+
+    let classes = r#"{ "problem": { "targets": { "a": 0, "b": 3 }, "features": { "x": 0 } },
+        "trees": [[{ "Leaf": { "prediction": 0 } }]] }"#;
+    let error = serde_json::from_str::<Forest<Classification>>(classes).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("'b' has index 3, past the 2 targets"),
+        "{error}"
+    );
+}
+
+#[test]
+fn name_maps_index_densely() -> Result<()> {
+    let mut features = FeatureMap::from_names(["b", "a"])?;
+    assert_eq!(features.insert_next("c"), FeatureIdx(2));
+    assert_eq!(features.insert_next("a"), FeatureIdx(1));
+    assert_eq!(features.len(), 3);
+    assert_eq!(features.index_of("c"), Some(FeatureIdx(2)));
+    assert_eq!(features.name_of(FeatureIdx(0)), Some("b"));
+    assert_eq!(features.name_of(FeatureIdx(3)), None);
+    assert_eq!(
+        features.iter().collect::<Vec<_>>(),
+        [
+            (FeatureIdx(0), "b"),
+            (FeatureIdx(1), "a"),
+            (FeatureIdx(2), "c")
+        ]
+    );
+
+    features.reorder(&["c", "b", "a"])?;
+    assert_eq!(features.names(), ["c", "b", "a"]);
+    assert!(features.reorder(&["c", "b"]).is_err());
+    features.sort();
+    assert_eq!(features.names(), ["a", "b", "c"]);
+
+    let error = TargetMap::from_names(["a", "b", "a"]).unwrap_err();
+    assert!(
+        error.to_string().contains("target 'a' is given twice"),
+        "{error}"
+    );
+
+    Ok(())
+}
+
+#[test]
+#[allow(deprecated)]
+fn name_maps_convert_from_hash_maps() -> Result<()> {
+    let targets = TargetMap::from_names(["a", "b"])?;
+    let map = targets.to_hash_map();
+    assert_eq!(
+        map,
+        HashMap::from([("a".to_string(), 0), ("b".to_string(), 1)])
+    );
+    assert_eq!(TargetMap::from_hash_map(map)?, targets);
+
+    let holes = HashMap::from([("a".to_string(), 0), ("b".to_string(), 2)]);
+    assert!(TargetMap::from_hash_map(holes).is_err());
+
+    Ok(())
+}
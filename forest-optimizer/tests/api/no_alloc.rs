@@ -0,0 +1,205 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::hint::black_box;
+
+use aligned_vec::AVec;
+use color_eyre::Result;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Branch, Classification, ClassifierN, CompactBranch, OptimizedForest,
+    Predict, PredictSession, Regression,
+    access::CachedNodes,
+    deserialize::{BUFFER_ALIGN, ForestHeader},
+    encryption::KEY_LEN,
+};
+use forest_optimizer::compact::Compact;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::encryption::{EncryptionKey, encrypt};
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+use crate::helpers::get_forest;
+
+/// Counts the allocations of threads in [`allocations_during`], and
+/// allocates with [`System`] as usual. Predictions must never allocate, as
+/// some targets have no allocator at all.
+struct PoisonedAlloc;
+
+thread_local! {
+    static WATCHED: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_allocation() {
+    // Allocations of threads being torn down are not watched
+    if WATCHED.try_with(Cell::get).unwrap_or(false) {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+    }
+}
+
+unsafe impl GlobalAlloc for PoisonedAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PoisonedAlloc = PoisonedAlloc;
+
+/// Number of allocations made by this thread while running `f`.
+fn allocations_during(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|count| count.set(0));
+    WATCHED.with(|watched| watched.set(true));
+    f();
+    WATCHED.with(|watched| watched.set(false));
+    ALLOCATIONS.with(Cell::get)
+}
+
+fn assert_predicts_without_allocating<F: Predict>(forest: &F, rows: &[Vec<f32>]) {
+    let allocations = allocations_during(|| {
+        for row in rows {
+            black_box(forest.predict(black_box(row)));
+            black_box(forest.predict_counting(black_box(row)));
+        }
+    });
+    assert_eq!(allocations, 0, "predictions allocated {allocations} times");
+}
+
+#[test]
+fn allocations_are_detected() {
+    assert_ne!(allocations_during(|| drop(black_box(vec![0u8; 16]))), 0);
+    assert_eq!(allocations_during(|| black_box([0u8; 16]).fill(1)), 0);
+}
+
+#[test]
+fn classification_predictions_do_not_allocate() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    assert_predicts_without_allocating(&optimized, &rows);
+    let classifier = ClassifierN::<3>::try_from(&optimized).unwrap();
+    assert_predicts_without_allocating(&classifier, &rows);
+    let allocations = allocations_during(|| {
+        for row in &rows {
+            black_box(optimized.predict_with::<3>(black_box(row)));
+        }
+    });
+    assert_eq!(allocations, 0);
+    let allocations = allocations_during(|| {
+        for row in &rows {
+            let mut session = PredictSession::new(&optimized, black_box(row));
+            while session.poll(2).is_pending() {}
+            assert!(black_box(session.poll(0)).is_ready());
+        }
+    });
+    assert_eq!(allocations, 0);
+
+    for width in [PointerWidth::U16, PointerWidth::Relative] {
+        let buffer = ClassificationProblem::serialize_with(&forest, width)?;
+        let any = AnyOptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+        assert_predicts_without_allocating(&any, &rows);
+    }
+
+    let compact = ClassificationProblem::serialize_compact(&forest)?;
+    let compact = OptimizedForest::<Classification, CompactBranch>::deserialize(&compact).unwrap();
+    assert_predicts_without_allocating(&compact, &rows);
+
+    // Nor do branches read from storage
+    let header = ForestHeader::peek(&buffer).unwrap();
+    let nodes = &buffer[header.header_len..][..header.node_count * size_of::<Branch>()];
+    let cached = CachedNodes::<_, Branch, 4>::new(nodes, header.node_count);
+    let from_storage = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(&cached)
+        .unwrap();
+    assert_predicts_without_allocating(&from_storage, &rows);
+
+    // Nor does loading a forest, which borrows the buffer
+    let allocations = allocations_during(|| {
+        let forest = OptimizedForest::<Classification>::deserialize(black_box(&buffer)).unwrap();
+        black_box(forest.check_fingerprint(forest.feature_fingerprint())).unwrap();
+    });
+    assert_eq!(allocations, 0);
+
+    // Nor does writing it back into a buffer of the caller
+    let mut out = vec![0; buffer.len()];
+    let allocations = allocations_during(|| {
+        let forest = OptimizedForest::<Classification>::deserialize(black_box(&buffer)).unwrap();
+        black_box(forest.serialize_into(black_box(&mut out))).unwrap();
+    });
+    assert_eq!(allocations, 0);
+    assert_eq!(out, &buffer[..]);
+
+    // Nor does decrypting it into RAM of the caller
+    let key = [1; KEY_LEN];
+    let encrypted = encrypt(&buffer, &EncryptionKey(key))?;
+    let mut ram = AVec::<u8>::from_slice(BUFFER_ALIGN, &encrypted);
+    let allocations = allocations_during(|| {
+        let forest = OptimizedForest::<Classification>::deserialize_encrypted(
+            black_box(&encrypted),
+            &key,
+            black_box(&mut ram),
+        )
+        .unwrap();
+        black_box(forest.num_trees());
+    });
+    assert_eq!(allocations, 0);
+
+    Ok(())
+}
+
+#[test]
+fn regression_predictions_do_not_allocate() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+    assert_predicts_without_allocating(&optimized, &rows);
+
+    // Too large for 16-bit pointers
+    let buffer = RegressionProblem::serialize_with(&forest, PointerWidth::Relative)?;
+    let any = AnyOptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+    assert_predicts_without_allocating(&any, &rows);
+
+    Ok(())
+}
+
+#[cfg(feature = "soa")]
+#[test]
+fn soa_predictions_do_not_allocate() -> Result<()> {
+    use embedded_rforest::forest::soa::SoAForest;
+
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let bytes = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .to_soa_bytes();
+    let soa = SoAForest::<Classification>::deserialize(&bytes).unwrap();
+    assert_predicts_without_allocating(&soa, &rows);
+
+    Ok(())
+}
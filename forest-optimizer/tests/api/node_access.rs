@@ -0,0 +1,203 @@
+use aligned_vec::AVec;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    Branch, Classification, OptimizedForest, Predict, Regression,
+    access::{CachedNodes, NodeStorage, copy_to_ram},
+    deserialize::{BUFFER_ALIGN, ForestHeader},
+};
+use embedded_rforest::ptr::RelativeU16;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::Regression as RegressionProblem;
+use forest_optimizer::serialized_forest::SerializedRegressionNode;
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+use crate::helpers::{get_forest, iris};
+
+/// The node array of a serialized forest of `branch_size`-byte branches.
+fn node_bytes(buffer: &[u8], branch_size: usize) -> &[u8] {
+    let header = ForestHeader::peek(buffer).unwrap();
+    &buffer[header.header_len..][..header.node_count * branch_size]
+}
+
+/// Storage whose reads start failing after `budget` of them.
+struct FailingStorage<'a> {
+    bytes: &'a [u8],
+    budget: usize,
+}
+
+impl NodeStorage for FailingStorage<'_> {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        if self.budget == 0 {
+            return Err(Error::Storage);
+        }
+        self.budget -= 1;
+        self.bytes.read(offset, bytes)
+    }
+}
+
+#[test]
+fn cached_nodes_predict_like_the_node_array() -> Result<()> {
+    let (buffer, rows) = iris(PointerWidth::U32)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let expected = rows
+        .iter()
+        .map(|row| forest.predict_counting(row))
+        .collect::<Vec<_>>();
+    let nodes = node_bytes(&buffer, size_of::<Branch>());
+
+    // From a single line, always missing, to more lines than branches
+    let one = CachedNodes::<_, Branch, 1>::new(nodes, forest.nodes().len());
+    let many = CachedNodes::<_, Branch, 256>::new(nodes, forest.nodes().len());
+    let one = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(&one)
+        .unwrap();
+    let many = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(&many)
+        .unwrap();
+    for (row, &expected) in rows.iter().zip(&expected) {
+        assert_eq!(one.predict_counting(row), expected);
+        assert_eq!(many.predict_counting(row), expected);
+        assert_eq!(many.try_predict(row), Ok(expected.0));
+    }
+
+    // Borrowing the node array through the trait is the forest itself
+    let slice = forest.nodes();
+    let same = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(slice)
+        .unwrap();
+    assert!(
+        rows.iter()
+            .all(|row| same.predict(row) == forest.predict(row))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cached_nodes_follow_relative_pointers() -> Result<()> {
+    let (buffer, rows) = iris(PointerWidth::Relative)?;
+    let relative =
+        OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&buffer).unwrap();
+
+    let nodes = node_bytes(&buffer, size_of::<Branch<RelativeU16>>());
+    let cached = CachedNodes::<_, Branch<RelativeU16>, 8>::new(nodes, relative.nodes().len());
+    let from_cache = OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(&cached)
+        .unwrap();
+    for row in &rows {
+        assert_eq!(from_cache.predict(row), relative.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn regression_reads_leaves_with_cached_nodes() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+
+    let nodes = node_bytes(&buffer, size_of::<Branch>());
+    let cached = CachedNodes::<_, Branch, 64>::new(nodes, optimized.nodes().len());
+    let from_cache = OptimizedForest::<Regression>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(&cached)
+        .unwrap();
+    for row in rows.iter().take(200) {
+        assert_eq!(from_cache.predict(row), optimized.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn nodes_copied_to_ram_predict_like_the_node_array() -> Result<()> {
+    let (buffer, rows) = iris(PointerWidth::U32)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let len = forest.nodes().len();
+
+    let mut storage = node_bytes(&buffer, size_of::<Branch>());
+    let mut ram = AVec::<u8>::from_iter(BUFFER_ALIGN, std::iter::repeat_n(0, len * 16));
+    let nodes = copy_to_ram::<_, Branch>(&mut storage, len, &mut ram).unwrap();
+    let copy = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(nodes)
+        .unwrap();
+    assert!(
+        rows.iter()
+            .all(|row| copy.predict(row) == forest.predict(row))
+    );
+
+    // Too small a buffer
+    let mut small = AVec::<u8>::from_iter(BUFFER_ALIGN, std::iter::repeat_n(0, 16));
+    assert_eq!(
+        copy_to_ram::<_, Branch>(&mut storage, len, &mut small).err(),
+        Some(Error::MalformedForest)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn storage_errors_are_reported() -> Result<()> {
+    let (buffer, rows) = iris(PointerWidth::U32)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let len = forest.nodes().len();
+    let (_, visits) = forest.predict_counting(&rows[0]);
+    let bytes = node_bytes(&buffer, size_of::<Branch>());
+
+    // Loading reads every branch
+    let failing = CachedNodes::<_, Branch, 1>::new(
+        FailingStorage {
+            bytes,
+            budget: len - 1,
+        },
+        len,
+    );
+    assert_eq!(
+        OptimizedForest::<Classification>::deserialize(&buffer)
+            .unwrap()
+            .with_node_access(&failing)
+            .err(),
+        Some(Error::Storage)
+    );
+
+    // A single line misses on every branch visited, so the first row is
+    // predicted on the remaining budget, and later ones fail
+    let failing = CachedNodes::<_, Branch, 1>::new(
+        FailingStorage {
+            bytes,
+            budget: len + visits as usize,
+        },
+        len,
+    );
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(&failing)
+        .unwrap();
+    let results = rows
+        .iter()
+        .map(|row| forest.try_predict(row))
+        .collect::<Vec<_>>();
+    assert!(results[0].is_ok());
+    assert_eq!(results.last(), Some(&Err(Error::Storage)));
+
+    // Another number of branches
+    let short = CachedNodes::<_, Branch, 1>::new(bytes, len - 1);
+    assert_eq!(
+        OptimizedForest::<Classification>::deserialize(&buffer)
+            .unwrap()
+            .with_node_access(&short)
+            .err(),
+        Some(Error::MalformedForest)
+    );
+
+    Ok(())
+}
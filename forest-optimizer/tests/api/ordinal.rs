@@ -0,0 +1,217 @@
+use aligned_vec::AVec;
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::deserialize::{BUFFER_ALIGN, ForestHeader, ORDINAL};
+use embedded_rforest::forest::{Aggregation, Classification, OptimizedForest, Predict, Votes};
+use embedded_rforest::ptr::F32;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::inspect::inspect;
+use forest_optimizer::problem_type::Regression as RegressionProblem;
+use forest_optimizer::serialized_forest::{
+    IndexOrder, SerializedClassificationNode, SerializedForest, SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::{NodeEncoding, WriteForest};
+use predicates::str::contains;
+
+use crate::helpers::{
+    FLAGS, ROWS, classifier, get_forest, predictions, regression_forest, regression_stumps,
+    session_predictions, stumps,
+};
+
+fn votes(classes: &[u32]) -> Votes<5> {
+    let mut votes = Votes::new();
+    for &class in classes {
+        votes.add(class);
+    }
+    votes
+}
+
+#[test]
+fn the_median_vote_can_differ_from_the_majority() {
+    // Between levels 1 and 3, the median settles on 2
+    let split = votes(&[1, 1, 3, 3]);
+    assert_eq!(split.winner(), 1);
+    assert_eq!(split.median(), 2);
+
+    // One more vote for 1 does not outweigh the levels above it
+    let skewed = votes(&[1, 1, 2, 4, 4]);
+    assert_eq!(skewed.winner(), 1);
+    assert_eq!(skewed.median(), 2);
+
+    let extremes = votes(&[0, 0, 0, 4, 4, 4, 4]);
+    assert_eq!(extremes.winner(), 4);
+    assert_eq!(extremes.median(), 4);
+
+    // The mean of the two middle votes is rounded down
+    assert_eq!(votes(&[1, 2]).median(), 1);
+    assert_eq!(votes(&[0, 3]).median(), 1);
+    assert_eq!(votes(&[3]).median(), 3);
+    assert_eq!(votes(&[]).median(), 0);
+}
+
+/// Leaves of four [`stumps`]: on the left, 2 votes for level 1 and 2 for
+/// level 3, and on the right 1 vote for level 0 and 3 for level 4
+const VOTES: [(u32, u32); 4] = [(1, 0), (1, 4), (3, 4), (3, 4)];
+
+#[test]
+fn ordinal_forests_predict_the_median_level() {
+    let nodes = stumps(&VOTES);
+    let plurality = classifier(&nodes, 5);
+    assert_eq!(plurality.aggregation(), Aggregation::MajorityVote);
+    assert_eq!(predictions(&plurality, &ROWS), [1, 4]);
+    assert_eq!(plurality.to_bytes()[FLAGS] & ORDINAL, 0);
+
+    let ordinal = classifier(&nodes, 5)
+        .with_aggregation(Aggregation::OrdinalMedian)
+        .unwrap();
+    assert_eq!(ordinal.aggregation(), Aggregation::OrdinalMedian);
+    // Levels 1, 1, 3, 3 on the left and 0, 4, 4, 4 on the right
+    assert_eq!(predictions(&ordinal, &ROWS), [2, 4]);
+    assert_eq!(session_predictions(&ordinal, &ROWS), [2, 4]);
+    assert_eq!(ordinal.predict_with::<5>(&[0.0]), 2);
+
+    // The flag adds no section to the header
+    let bytes = ordinal.to_bytes();
+    assert_ne!(bytes[FLAGS] & ORDINAL, 0);
+    assert_eq!(bytes.len(), plurality.to_bytes().len());
+    let header = ForestHeader::peek(&bytes).unwrap();
+    assert_eq!(header.aggregation, Aggregation::OrdinalMedian);
+    let loaded = OptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.aggregation(), Aggregation::OrdinalMedian);
+    assert_eq!(predictions(&loaded, &ROWS), [2, 4]);
+    assert_eq!(loaded.to_bytes(), bytes);
+    assert!(
+        inspect(&bytes)
+            .unwrap()
+            .to_string()
+            .contains("Aggregation:     ordinal median")
+    );
+
+    // Back to the majority vote
+    let restored = loaded.with_aggregation(Aggregation::MajorityVote).unwrap();
+    assert_eq!(restored.to_bytes(), plurality.to_bytes());
+}
+
+#[test]
+fn only_classification_forests_are_ordinal() -> Result<()> {
+    let nodes = stumps(&VOTES);
+    assert_eq!(
+        classifier(&nodes, 5)
+            .with_aggregation(Aggregation::MeanValue)
+            .err(),
+        Some(Error::WrongProblemType)
+    );
+
+    let leaves = [F32::new(1.0), F32::new(2.0)];
+    let nodes = regression_stumps(1);
+    let regression = regression_forest(&nodes, &leaves);
+    let mut bytes = AVec::<u8>::from_slice(BUFFER_ALIGN, &regression.to_bytes());
+    assert_eq!(
+        regression
+            .with_aggregation(Aggregation::OrdinalMedian)
+            .err(),
+        Some(Error::WrongProblemType)
+    );
+    bytes[FLAGS] |= ORDINAL;
+    assert_eq!(
+        ForestHeader::peek(&bytes).err(),
+        Some(Error::MalformedForest)
+    );
+
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let encoding = NodeEncoding {
+        ordinal: true,
+        ..Default::default()
+    };
+    assert!(RegressionProblem::serialize_encoded(&forest, &encoding).is_err());
+
+    Ok(())
+}
+
+const COLUMNS: &str = "\"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n";
+
+/// Five trees grading the severity of `x` on levels 0 to 4, each
+/// overestimating or underestimating some of them: the row of each tree
+/// lists the level of `x <= 1`, `x <= 2`, `x <= 3` and above
+const LEVELS: [[u32; 4]; 5] = [
+    [1, 1, 3, 4],
+    [0, 1, 2, 4],
+    [0, 3, 3, 2],
+    [1, 4, 1, 4],
+    [4, 2, 1, 3],
+];
+
+/// Definition of the trees of [`LEVELS`], in which the levels first appear
+/// out of order
+fn severity_definition() -> String {
+    let mut definition = format!("# {{ \"problem_type\": \"classification\" }}\n{COLUMNS}");
+    for (tree, levels) in LEVELS.iter().enumerate() {
+        let tree = tree + 1;
+        definition += &format!(
+            "2,3,\"x\",2,1,NA,{tree},1\n\
+             4,5,\"x\",1,1,NA,{tree},2\n\
+             6,7,\"x\",3,1,NA,{tree},3\n\
+             0,0,NA,0,-1,\"{}\",{tree},4\n\
+             0,0,NA,0,-1,\"{}\",{tree},5\n\
+             0,0,NA,0,-1,\"{}\",{tree},6\n\
+             0,0,NA,0,-1,\"{}\",{tree},7\n",
+            levels[0], levels[1], levels[2], levels[3]
+        );
+    }
+    definition
+}
+
+#[test]
+fn convert_pins_the_levels_of_ordinal_forests() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("severity.csv");
+    std::fs::write(&input, severity_definition())?;
+    let data = dir.path().join("severity_data.csv");
+    std::fs::write(&data, "x,severity\n0.5,1\n1.5,2\n2.5,2\n3.5,4\n")?;
+    let output = dir.path().join("severity.rforest");
+
+    // The levels are only ordered once pinned
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--ordinal", "-i"])
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(contains("--target-order"));
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--ordinal", "--target-order", "0,1,2,3,4", "-i"])
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    let bytes = AVec::<u8>::from_slice(BUFFER_ALIGN, &std::fs::read(&output)?);
+    let optimized = OptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+    assert_eq!(optimized.aggregation(), Aggregation::OrdinalMedian);
+
+    // Read back with the levels pinned in order, like the conversion did
+    let serialized = SerializedForest::<SerializedClassificationNode>::from_reader(
+        severity_definition().as_bytes(),
+    )?;
+    let order = IndexOrder {
+        targets: Some(["0", "1", "2", "3", "4"].map(String::from).to_vec()),
+        ..Default::default()
+    };
+    let forest = Forest::from_serialized(order.apply(serialized)?)?;
+    let rows = read_mapped_rows(&data, &forest.features_by_index())?;
+    let mut differs = 0;
+    for (row, expected) in rows.iter().zip([1, 2, 2, 4]) {
+        assert_eq!(forest.predict_ordinal(row), expected);
+        assert_eq!(optimized.predict(row), expected);
+        differs += usize::from(forest.predict(row) != expected.to_string());
+    }
+    // The majority vote picks another level on the first three rows
+    assert_eq!(differs, 3);
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use color_eyre::Result;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedNode, SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+/// Serialized forest of the definition at `path`, converted on `threads`
+/// threads.
+fn serialize_on<N>(path: &str, threads: usize) -> Result<Vec<u8>>
+where
+    N: SerializedNode,
+    N::ProblemType: WriteForest,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()?;
+    let forest = pool.install(|| get_forest::<N>(path))?;
+    Ok(N::ProblemType::serialize(&forest)?.to_vec())
+}
+
+/// Check that the definition at `path` serializes to `expected` whatever the
+/// number of threads, and like the serial parsing of the streaming path.
+fn assert_identical<N>(path: &str, expected: &[u8]) -> Result<()>
+where
+    N: SerializedNode,
+    N::ProblemType: WriteForest,
+{
+    let streamed = Forest::from_csv_streaming::<N>(path)?;
+    assert_eq!(N::ProblemType::serialize(&streamed)?.to_vec(), expected);
+
+    for threads in [1, 2, 3, 8] {
+        assert_eq!(
+            serialize_on::<N>(path, threads)?,
+            expected,
+            "{path} on {threads} threads"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn parallel_conversion_is_byte_identical() -> Result<()> {
+    assert_identical::<SerializedClassificationNode>(
+        "./tests/test-forests/forest_iris_5.csv",
+        &std::fs::read("./tests/test-forests/forest_iris_5.rforest")?,
+    )?;
+    assert_identical::<SerializedRegressionNode>(
+        "./tests/test-forests/airfoil_100_200.csv",
+        &std::fs::read("./tests/test-forests/airfoil_100_200.rforest")?,
+    )?;
+
+    let path = "./tests/test-forests/forest_iris_800.csv";
+    let streamed = Forest::from_csv_streaming::<SerializedClassificationNode>(path)?;
+    let expected =
+        <SerializedClassificationNode as SerializedNode>::ProblemType::serialize(&streamed)?;
+    assert_identical::<SerializedClassificationNode>(path, &expected)
+}
+
+#[test]
+fn parallel_parsing_keeps_lines_and_name_order() -> Result<()> {
+    let definition = "\
+        # { \"problem_type\": \"classification\" }\n\
+        # exported for testing\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        2,3,\"y\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"b\",1,2\n\
+        # a comment between rows\n\
+        4,5,NA,0,-1,\"a\",1,3\n\
+        2,3,\"x\",0.5,1,NA,2,1\n\
+        0,0,NA,0,-1,\"c\",2,2\n\
+        0,0,NA,0,-1,\"b\",2,3\n";
+
+    for threads in [1, 2, 8] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?;
+        let serialized = pool.install(|| {
+            SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())
+        })?;
+
+        // Like the serial reader, rows right after a comment are given its line
+        let lines = serialized
+            .validate()
+            .iter()
+            .map(|d| d.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [Some(6)], "{threads} threads");
+
+        let features = serialized.features();
+        assert_eq!((features["y"].0, features["x"].0), (0, 1));
+        let targets = serialized.targets();
+        assert_eq!(targets.names(), ["b", "a", "c"]);
+    }
+    Ok(())
+}
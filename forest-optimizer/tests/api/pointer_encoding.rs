@@ -0,0 +1,152 @@
+use color_eyre::Result;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Branch, BranchLayout, Classification, OptimizedForest, Regression,
+};
+use embedded_rforest::ptr::{NodeIndex, NodePointer, U16, U32};
+use forest_optimizer::compare::compare;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::prune::keep_first_trees;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+use crate::helpers::get_forest;
+
+/// Check that `ptr` holds `index` and `is_leaf`, and nothing else.
+fn assert_pointer<Ptr: NodeIndex>(ptr: Ptr, index: u32, is_leaf: bool) {
+    assert_eq!(ptr.index(), index);
+    assert_eq!(ptr.is_leaf(), is_leaf);
+    assert_eq!(ptr.to_u32(), index | (u32::from(is_leaf) * Ptr::LEAF_TAG));
+}
+
+#[test]
+fn narrow_pointers_hold_every_15_bit_index() {
+    assert_eq!(U16::LEAF_TAG, 1 << 15);
+
+    for index in 0..=U16::MAX {
+        for is_leaf in [false, true] {
+            let ptr = U16::tagged(index, is_leaf).unwrap();
+            assert_pointer(ptr, index, is_leaf);
+            assert_eq!(U16::from_u32(ptr.to_u32()).unwrap().to_u32(), ptr.to_u32());
+        }
+    }
+
+    for index in [U16::MAX + 1, u16::MAX.into(), u32::MAX] {
+        assert!(U16::tagged(index, false).is_none());
+        assert!(U16::tagged(index, true).is_none());
+    }
+}
+
+#[test]
+fn wide_pointers_hold_every_31_bit_index() {
+    assert_eq!(U32::LEAF_TAG, 1 << 31);
+
+    // Every power of two and its neighbours, and a sweep of the whole range
+    let powers = (0..31).flat_map(|bit| {
+        let power = 1u32 << bit;
+        [power - 1, power, power + 1]
+    });
+    let sweep = (0..=U32::MAX).step_by(65_521);
+    for index in powers.chain(sweep).chain([U32::MAX - 1, U32::MAX]) {
+        for is_leaf in [false, true] {
+            assert_pointer(U32::tagged(index, is_leaf).unwrap(), index, is_leaf);
+        }
+
+        let branch = NodePointer::new_branch(index);
+        assert!(!branch.is_leaf());
+        assert_eq!((branch.index(), branch.as_ptr()), (index, index));
+        let leaf = NodePointer::new_leaf(index);
+        assert!(leaf.is_leaf());
+        assert_eq!((leaf.index(), leaf.as_ptr()), (index, index | 1 << 31));
+    }
+
+    assert!(U32::tagged(U32::MAX + 1, false).is_none());
+    assert!(U32::tagged(u32::MAX, true).is_none());
+}
+
+#[test]
+#[should_panic]
+fn leaf_pointer_rejects_32_bit_index() {
+    NodePointer::new_leaf(1 << 31);
+}
+
+#[test]
+fn branches_store_tags_in_their_pointers() {
+    for (left_leaf, right_leaf) in [(false, false), (false, true), (true, false), (true, true)] {
+        let pointer = |index, is_leaf| {
+            if is_leaf {
+                NodePointer::new_leaf(index)
+            } else {
+                NodePointer::new_branch(index)
+            }
+        };
+        // The split variable takes all 32 bits
+        let branch = Branch::new(
+            u32::MAX,
+            0.5,
+            pointer(7, left_leaf),
+            pointer(U32::MAX, right_leaf),
+        );
+
+        assert_eq!(branch.split_with(), u32::MAX);
+        assert_eq!(BranchLayout::left(&branch), 7);
+        assert_eq!(BranchLayout::right(&branch), U32::MAX);
+        assert_eq!(branch.left_is_prediction(), left_leaf);
+        assert_eq!(branch.right_is_prediction(), right_leaf);
+
+        let mut bytes = Vec::new();
+        branch.write_bytes(|b| bytes.extend_from_slice(b));
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(bytes[3] & 0x80 != 0, left_leaf);
+        assert_eq!(bytes[7] & 0x80 != 0, right_leaf);
+        assert_eq!(bytes[12..16], u32::MAX.to_le_bytes());
+    }
+
+    let narrow = Branch::<U16>::from_ptrs(
+        u32::MAX,
+        0.5,
+        U16::tagged(U16::MAX, true).unwrap(),
+        U16::tagged(U16::MAX, false).unwrap(),
+    );
+    let mut bytes = Vec::new();
+    narrow.write_bytes(|b| bytes.extend_from_slice(b));
+    assert_eq!(bytes.len(), 12);
+    assert_eq!(bytes[..4], [0xff, 0xff, 0xff, 0x7f]);
+    assert_eq!(bytes[8..12], u32::MAX.to_le_bytes());
+    assert_eq!(narrow.split_with(), u32::MAX);
+    assert!(narrow.left_is_prediction() && !narrow.right_is_prediction());
+}
+
+#[test]
+fn optimized_regression_matches_the_forest_exactly() -> Result<()> {
+    let forest = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        3,
+    );
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &features)?;
+
+    for width in [PointerWidth::U32, PointerWidth::U16] {
+        let buffer = RegressionProblem::serialize_with(&forest, width)?;
+        let optimized = AnyOptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+        assert!(compare(&optimized, &forest, &rows).is_identical());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn optimized_classification_matches_the_forest() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    assert!(compare(&optimized, &forest, &rows).is_identical());
+
+    Ok(())
+}
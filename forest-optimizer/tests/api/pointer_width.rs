@@ -0,0 +1,120 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Branch, Classification, NodeLayout, OptimizedForest, Regression,
+    deserialize::ForestHeader,
+};
+use embedded_rforest::ptr::{U16, U32};
+use forest_optimizer::compare::compare;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::emit::OutputFormat;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::metadata::ForestMetadata;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::prune::keep_first_trees;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest, write_artifacts_with};
+
+use crate::helpers::get_forest;
+
+#[test]
+fn both_widths_make_the_same_predictions() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+
+    let wide = ClassificationProblem::serialize_with(&forest, PointerWidth::U32)?;
+    let narrow = ClassificationProblem::serialize_with(&forest, PointerWidth::U16)?;
+    assert_eq!(
+        ForestHeader::peek(&wide).unwrap().layout,
+        NodeLayout::Standard
+    );
+    assert_eq!(
+        ForestHeader::peek(&narrow).unwrap().layout,
+        NodeLayout::Narrow
+    );
+    assert_eq!(
+        narrow.len(),
+        ForestHeader::peek(&narrow).unwrap().serialized_len()
+    );
+    assert!(narrow.len() < wide.len());
+
+    let wide = OptimizedForest::<Classification, Branch<U32>>::deserialize(&wide).unwrap();
+    let narrow = OptimizedForest::<Classification, Branch<U16>>::deserialize(&narrow).unwrap();
+    narrow.validate().unwrap();
+    assert_eq!(narrow.nodes().len(), wide.nodes().len());
+    assert!(compare(&narrow, &wide, &rows).is_identical());
+
+    // Each width only deserializes as itself
+    assert!(matches!(
+        OptimizedForest::<Classification>::deserialize(&narrow.to_bytes()),
+        Err(Error::WrongLayout)
+    ));
+    assert!(matches!(
+        OptimizedForest::<Classification, Branch<U16>>::deserialize(&wide.to_bytes()),
+        Err(Error::WrongLayout)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn regression_narrows_through_the_leaf_table() -> Result<()> {
+    let forest = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        3,
+    );
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &features)?;
+
+    let narrow = RegressionProblem::serialize_with(&forest, PointerWidth::U16)?;
+    let auto = RegressionProblem::serialize_with(&forest, PointerWidth::Auto)?;
+    assert_eq!(auto, narrow);
+
+    let wide = RegressionProblem::serialize(&forest)?;
+    let wide = AnyOptimizedForest::<Regression>::deserialize(&wide).unwrap();
+    let narrow = AnyOptimizedForest::<Regression>::deserialize(&narrow).unwrap();
+    assert_eq!(wide.layout(), NodeLayout::Standard);
+    assert_eq!(narrow.layout(), NodeLayout::Narrow);
+    assert!(compare(&narrow, &wide, &rows).is_identical());
+
+    Ok(())
+}
+
+#[test]
+fn files_of_unknown_width_load_through_any_forest() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+    let standard = ClassificationProblem::serialize(&forest)?;
+    let standard = OptimizedForest::<Classification>::deserialize(&standard).unwrap();
+
+    for (width, layout) in [
+        (PointerWidth::U32, NodeLayout::Standard),
+        (PointerWidth::U16, NodeLayout::Narrow),
+        (PointerWidth::Auto, NodeLayout::Narrow),
+    ] {
+        let output = dir.path().join("iris.rforest");
+        let size = write_artifacts_with(
+            &forest,
+            width,
+            &[(OutputFormat::Rforest, output.clone())],
+            ForestMetadata::sidecar_path(&output),
+        )?;
+
+        let buffer = read_model(&output)?;
+        assert_eq!(buffer.len(), size);
+        let loaded = AnyOptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+        assert_eq!(loaded.layout(), layout);
+        assert_eq!(loaded.serialized_len(), size);
+        loaded.validate().unwrap();
+        assert!(compare(&loaded, &standard, &rows).is_identical());
+    }
+
+    Ok(())
+}
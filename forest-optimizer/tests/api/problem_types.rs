@@ -10,7 +10,7 @@ fn serialized_classification_rejects_regression_deserialization() {
         get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")
             .unwrap();
 
-    let nodes = forest.optimize_nodes();
+    let (nodes, _) = forest.optimize_nodes();
     let optimized = OptimizedForest::<Classification>::new(
         forest.num_trees().try_into().unwrap(),
         &nodes,
@@ -29,13 +29,15 @@ fn serialized_classification_rejects_regression_optimization() {
         get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")
             .unwrap();
 
-    let nodes = forest.optimize_nodes();
-    assert!(OptimizedForest::<Regression>::new(
-        forest.num_trees().try_into().unwrap(),
-        &nodes,
-        forest.num_features().try_into().unwrap(),
-    )
-    .is_err());
+    let (nodes, _) = forest.optimize_nodes();
+    assert!(
+        OptimizedForest::<Regression>::new(
+            forest.num_trees().try_into().unwrap(),
+            &nodes,
+            forest.num_features().try_into().unwrap(),
+        )
+        .is_err()
+    );
 }
 
 #[test]
@@ -43,11 +45,12 @@ fn serialized_regression_rejects_classification_deserialization() -> Result<()>
     let forest =
         get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv").unwrap();
 
-    let nodes = forest.optimize_nodes();
-    let optimized = OptimizedForest::<Regression>::new(
+    let (nodes, leaves) = forest.optimize_nodes();
+    let optimized = OptimizedForest::<Regression>::with_leaves(
         forest.num_trees().try_into().unwrap(),
         &nodes,
         forest.num_features().try_into().unwrap(),
+        &leaves,
     )
     .unwrap();
 
@@ -62,14 +65,16 @@ fn serialized_regression_rejects_classification_optimization() {
     let forest =
         get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv").unwrap();
 
-    let nodes = forest.optimize_nodes();
-    assert!(OptimizedForest::<Classification>::new(
-        forest.num_trees().try_into().unwrap(),
-        &nodes,
-        forest.num_features().try_into().unwrap(),
-        Classification::new(2).unwrap(),
-    )
-    .is_err());
+    let (nodes, _) = forest.optimize_nodes();
+    assert!(
+        OptimizedForest::<Classification>::new(
+            forest.num_trees().try_into().unwrap(),
+            &nodes,
+            forest.num_features().try_into().unwrap(),
+            Classification::new(2).unwrap(),
+        )
+        .is_err()
+    );
 }
 
 #[test]
@@ -0,0 +1,152 @@
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::forest::{AnyOptimizedForest, Classification, Predict, Regression};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::forest::profile::TraversalProfile;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{
+    NodeEncoding, NodeOrder, PointerWidth, ProfileSource, WriteForest,
+};
+use predicates::str::contains;
+
+use crate::helpers::get_forest;
+
+const IRIS: &str = "./tests/test-forests/forest_iris_5.csv";
+const IRIS_DATA: &str = "./tests/test-data/iris.csv";
+
+#[test]
+fn profiles_count_every_branch_visit() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(IRIS)?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows(IRIS_DATA, &features)?;
+
+    let profile = forest.profile(&rows);
+    assert_eq!(profile.rows, rows.len() as u64);
+    // Every row goes through the root of every tree, which is a branch
+    let mut visits = 0;
+    for tree in forest.trees() {
+        assert_eq!(profile.counts[tree.index()].iter().sum::<u64>(), 150);
+        for row in &rows {
+            tree.trace(row, |_, _| visits += 1);
+        }
+    }
+    assert_eq!(profile.counts.iter().flatten().sum::<u64>(), visits);
+
+    // Saved for reuse
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("profile.json");
+    profile.write(&path)?;
+    assert_eq!(TraversalProfile::read(&path)?, profile);
+
+    // Not for another forest
+    let other =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let message = profile.hot_right(&other).unwrap_err().to_string();
+    assert!(message.contains("does not match the forest"), "{message}");
+
+    Ok(())
+}
+
+#[test]
+fn profiled_layouts_predict_the_same() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(IRIS)?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows(IRIS_DATA, &features)?;
+    let profile = forest.profile(&rows);
+    assert!(profile.hot_right(&forest)?.contains(&true));
+    assert!(profile.sequential_share(true) > profile.sequential_share(false));
+
+    for width in [PointerWidth::U32, PointerWidth::U16, PointerWidth::Relative] {
+        let depth_first = ClassificationProblem::serialize_with(&forest, width)?;
+        let encoding = NodeEncoding {
+            width,
+            layout: NodeOrder::Profiled(ProfileSource::Saved(profile.clone())),
+            ..Default::default()
+        };
+        let profiled = ClassificationProblem::serialize_encoded(&forest, &encoding)?;
+        assert_eq!(profiled.len(), depth_first.len());
+        assert_ne!(profiled, depth_first, "{width:?}");
+
+        let depth_first = AnyOptimizedForest::<Classification>::deserialize(&depth_first).unwrap();
+        let profiled = AnyOptimizedForest::<Classification>::deserialize(&profiled).unwrap();
+        for row in &rows {
+            assert_eq!(profiled.predict(row), depth_first.predict(row));
+        }
+    }
+
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let encoding = NodeEncoding {
+        layout: NodeOrder::Profiled(ProfileSource::Data {
+            path: "./tests/test-data/airfoil.csv".into(),
+            save: None,
+        }),
+        ..Default::default()
+    };
+    let profiled = RegressionProblem::serialize_encoded(&forest, &encoding)?;
+    let profiled = AnyOptimizedForest::<Regression>::deserialize(&profiled).unwrap();
+    let depth_first = RegressionProblem::serialize(&forest)?;
+    let depth_first = AnyOptimizedForest::<Regression>::deserialize(&depth_first).unwrap();
+    let features = forest.features_by_index();
+    for row in read_mapped_rows("./tests/test-data/airfoil.csv", &features)? {
+        assert_eq!(
+            profiled.predict(&row).to_bits(),
+            depth_first.predict(&row).to_bits()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn convert_lays_out_the_profiled_branches() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (profiled, saved) = (
+        dir.path().join("profiled.rforest"),
+        dir.path().join("saved.rforest"),
+    );
+    let profile = dir.path().join("profile.json");
+
+    Command::cargo_bin("forest-optimizer")?
+        .args([
+            "convert",
+            "--layout",
+            "profiled",
+            "--profile-data",
+            IRIS_DATA,
+        ])
+        .args(["-i", IRIS, "--save-profile"])
+        .arg(&profile)
+        .arg("-o")
+        .arg(&profiled)
+        .assert()
+        .success();
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--layout", "profiled", "--profile"])
+        .arg(&profile)
+        .args(["-i", IRIS, "-o"])
+        .arg(&saved)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&saved)?, std::fs::read(&profiled)?);
+
+    let forest = get_forest::<SerializedClassificationNode>(IRIS)?;
+    let expected = ClassificationProblem::serialize_ordered(
+        &forest,
+        PointerWidth::U32,
+        &TraversalProfile::read(&profile)?.hot_right(&forest)?,
+    )?;
+    assert_eq!(std::fs::read(&profiled)?, expected.as_slice());
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--layout", "profiled", "-i", IRIS, "-o"])
+        .arg(&profiled)
+        .assert()
+        .failure()
+        .stderr(contains("needs --profile-data or --profile"));
+
+    Ok(())
+}
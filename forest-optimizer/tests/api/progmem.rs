@@ -0,0 +1,250 @@
+use std::cell::Cell;
+
+use aligned_vec::AVec;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    Branch, Classification, OptimizedForest, Predict, Regression,
+    access::{CachedNodes, NodeAccess, copy_to_ram},
+    deserialize::BUFFER_ALIGN,
+    progmem::{Progmem, ProgmemRead},
+};
+use embedded_rforest::ptr::RelativeU16;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::Regression as RegressionProblem;
+use forest_optimizer::serialized_forest::SerializedRegressionNode;
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+use crate::helpers::{get_forest, iris};
+
+/// Where the simulated program memory holds the forest.
+const FOREST_ADDR: usize = 0x100;
+
+/// Simulated program memory, counting the bytes read. Data memory holds
+/// nothing at the same addresses, so reading the forest through a reference
+/// instead of [`ProgmemRead`] would fail.
+#[derive(Clone, Copy)]
+struct Flash<'a> {
+    bytes: &'a [u8],
+    reads: &'a Cell<usize>,
+}
+
+impl ProgmemRead for Flash<'_> {
+    fn read_byte(&self, addr: usize) -> u8 {
+        self.reads.set(self.reads.get() + 1);
+        self.bytes[addr]
+    }
+}
+
+/// Program memory holding `forest` at [`FOREST_ADDR`], between filler bytes.
+fn flash(forest: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0xaa; FOREST_ADDR];
+    bytes.extend_from_slice(forest);
+    bytes.extend_from_slice(&[0xaa; 64]);
+    bytes
+}
+
+/// An aligned buffer of `len` bytes of RAM.
+fn ram(len: usize) -> AVec<u8> {
+    AVec::from_iter(BUFFER_ALIGN, std::iter::repeat_n(0, len))
+}
+
+#[test]
+fn progmem_forests_predict_like_the_node_array() -> Result<()> {
+    let (buffer, rows) = iris(PointerWidth::U32)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let bytes = flash(&buffer);
+    let reads = Cell::new(0);
+    let progmem = Progmem::new(
+        Flash {
+            bytes: &bytes,
+            reads: &reads,
+        },
+        FOREST_ADDR,
+        buffer.len(),
+    );
+
+    let nodes = progmem.nodes::<Branch>().unwrap();
+    let mut ram = ram(progmem.ram_len().unwrap());
+    let from_progmem = progmem
+        .deserialize::<Classification, _>(&nodes, &mut ram)
+        .unwrap();
+    assert_eq!(from_progmem.num_trees(), forest.num_trees());
+    for row in &rows {
+        assert_eq!(
+            from_progmem.predict_counting(row),
+            forest.predict_counting(row)
+        );
+        assert_eq!(from_progmem.try_predict(row), Ok(forest.predict(row)));
+    }
+
+    // Only the header and leaf table are copied to RAM
+    assert_eq!(
+        progmem.ram_len().unwrap(),
+        buffer.len() - size_of_val(forest.nodes())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn progmem_predictions_read_only_the_visited_branches() -> Result<()> {
+    let (buffer, rows) = iris(PointerWidth::U32)?;
+    let bytes = flash(&buffer);
+    let reads = Cell::new(0);
+    let progmem = Progmem::new(
+        Flash {
+            bytes: &bytes,
+            reads: &reads,
+        },
+        FOREST_ADDR,
+        buffer.len(),
+    );
+    let nodes = progmem.nodes::<Branch>().unwrap();
+    let mut ram = ram(progmem.ram_len().unwrap());
+    let forest = progmem
+        .deserialize::<Classification, _>(&nodes, &mut ram)
+        .unwrap();
+
+    for row in &rows {
+        reads.set(0);
+        let (_, visits) = forest.predict_counting(row);
+        assert_eq!(reads.get(), visits as usize * size_of::<Branch>());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn progmem_forests_of_other_layouts_and_problems() -> Result<()> {
+    let (buffer, rows) = iris(PointerWidth::Relative)?;
+    let relative =
+        OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&buffer).unwrap();
+    let bytes = flash(&buffer);
+    let reads = Cell::new(0);
+    let flash = Flash {
+        bytes: &bytes,
+        reads: &reads,
+    };
+    let progmem = Progmem::new(flash, FOREST_ADDR, buffer.len());
+    let nodes = progmem.nodes::<Branch<RelativeU16>>().unwrap();
+    let mut ram = self::ram(progmem.ram_len().unwrap());
+    let from_progmem = progmem
+        .deserialize::<Classification, _>(&nodes, &mut ram)
+        .unwrap();
+    for row in &rows {
+        assert_eq!(from_progmem.predict(row), relative.predict(row));
+    }
+
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+    let bytes = self::flash(&buffer);
+    let progmem = Progmem::new(
+        Flash {
+            bytes: &bytes,
+            reads: &reads,
+        },
+        FOREST_ADDR,
+        buffer.len(),
+    );
+    let nodes = progmem.nodes::<Branch>().unwrap();
+    let mut ram = self::ram(progmem.ram_len().unwrap());
+    let from_progmem = progmem
+        .deserialize::<Regression, _>(&nodes, &mut ram)
+        .unwrap();
+    for row in rows.iter().take(200) {
+        assert_eq!(from_progmem.predict(row), optimized.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn progmem_nodes_may_be_cached_or_copied_to_ram() -> Result<()> {
+    let (buffer, rows) = iris(PointerWidth::U32)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let len = forest.nodes().len();
+    let bytes = flash(&buffer);
+    let reads = Cell::new(0);
+    let progmem = Progmem::new(
+        Flash {
+            bytes: &bytes,
+            reads: &reads,
+        },
+        FOREST_ADDR,
+        buffer.len(),
+    );
+    let nodes = progmem.nodes::<Branch>().unwrap();
+    assert_eq!(nodes.len(), len);
+
+    let cached = CachedNodes::<_, Branch, 8>::new(nodes.clone(), len);
+    let cached = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(&cached)
+        .unwrap();
+    let mut ram = ram(size_of_val(forest.nodes()));
+    let copy = copy_to_ram::<_, Branch>(&mut nodes.clone(), len, &mut ram).unwrap();
+    let copy = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(copy)
+        .unwrap();
+    for row in &rows {
+        assert_eq!(cached.predict(row), forest.predict(row));
+        assert_eq!(copy.predict(row), forest.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn malformed_progmem_forests_are_rejected() -> Result<()> {
+    let (buffer, _) = iris(PointerWidth::U32)?;
+    let bytes = flash(&buffer);
+    let reads = Cell::new(0);
+    let flash = Flash {
+        bytes: &bytes,
+        reads: &reads,
+    };
+    let progmem = Progmem::new(flash, FOREST_ADDR, buffer.len());
+    let nodes = progmem.nodes::<Branch>().unwrap();
+
+    // Too little RAM
+    let mut small = ram(progmem.ram_len().unwrap() - 4);
+    assert_eq!(
+        progmem
+            .deserialize::<Classification, _>(&nodes, &mut small)
+            .err(),
+        Some(Error::MalformedForest)
+    );
+
+    // Another problem type or layout
+    let mut ram = ram(progmem.ram_len().unwrap());
+    assert_eq!(
+        progmem.deserialize::<Regression, _>(&nodes, &mut ram).err(),
+        Some(Error::WrongProblemType)
+    );
+    let relative = progmem.nodes::<Branch<RelativeU16>>().unwrap();
+    assert_eq!(
+        progmem
+            .deserialize::<Classification, _>(&relative, &mut ram)
+            .err(),
+        Some(Error::WrongLayout)
+    );
+
+    // Truncated
+    let truncated = Progmem::new(flash, FOREST_ADDR, buffer.len() - size_of::<Branch>());
+    let nodes = truncated.nodes::<Branch>().unwrap();
+    assert_eq!(
+        truncated
+            .deserialize::<Classification, _>(&nodes, &mut ram)
+            .err(),
+        Some(Error::MalformedForest)
+    );
+    let short = Progmem::new(flash, FOREST_ADDR, 8);
+    assert_eq!(short.nodes::<Branch>().err(), Some(Error::MalformedForest));
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+//! Properties of randomly generated forests, see
+//! [`forest_optimizer::test_support`]. Run with
+//! `cargo test --features test-support`.
+
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, OptimizedForest, Predict, Regression,
+};
+use forest_optimizer::forest::Forest;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::prune::collapse_redundant;
+use forest_optimizer::test_support::{
+    ForestShape, Pathology, RandomForest, forests, random_forest, rows, serialize_with_pathology,
+    shapes,
+};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+use proptest::prelude::*;
+
+/// Random forests, with rows of their features.
+fn forests_and_rows<P: RandomForest>() -> impl Strategy<Value = (Forest<P>, Vec<Vec<f32>>)> {
+    forests::<P>(shapes()).prop_flat_map(|forest| {
+        let rows = rows(forest.num_features(), 16);
+        (Just(forest), rows)
+    })
+}
+
+/// Class index of a prediction of a host forest.
+fn class(forest: &Forest<ClassificationProblem>, row: &[f32]) -> u32 {
+    forest.targets()[&forest.predict(row)].0
+}
+
+const WIDTHS: [PointerWidth; 3] = [PointerWidth::U32, PointerWidth::U16, PointerWidth::Relative];
+
+proptest! {
+    #[test]
+    fn serialized_classification_forests_predict_like_the_original(
+        (forest, rows) in forests_and_rows::<ClassificationProblem>()
+    ) {
+        for width in WIDTHS {
+            let buffer = ClassificationProblem::serialize_with(&forest, width).unwrap();
+            let optimized = AnyOptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+            for row in &rows {
+                prop_assert_eq!(optimized.predict(row), class(&forest, row));
+            }
+        }
+    }
+
+    #[test]
+    fn serialized_regression_forests_predict_like_the_original(
+        (forest, rows) in forests_and_rows::<RegressionProblem>()
+    ) {
+        for width in WIDTHS {
+            let buffer = RegressionProblem::serialize_with(&forest, width).unwrap();
+            let optimized = AnyOptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+            for row in &rows {
+                prop_assert_eq!(optimized.predict(row).to_bits(), forest.predict(row).to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn optimization_preserves_predictions(
+        (forest, rows) in forests_and_rows::<ClassificationProblem>()
+    ) {
+        let collapsed = collapse_redundant(&forest);
+        prop_assert!(collapsed.nodes().len() <= forest.nodes().len());
+
+        let buffer = ClassificationProblem::serialize(&forest).unwrap();
+        let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+        let host = Forest::from_optimized(&optimized, forest.problem().clone()).unwrap();
+        for row in &rows {
+            prop_assert_eq!(collapsed.predict(row), forest.predict(row));
+            prop_assert_eq!(host.predict(row), forest.predict(row));
+        }
+    }
+
+    #[test]
+    fn validation_accepts_every_generated_forest(
+        classification in forests::<ClassificationProblem>(shapes()),
+        regression in forests::<RegressionProblem>(shapes()),
+    ) {
+        for width in WIDTHS {
+            let buffer = ClassificationProblem::serialize_with(&classification, width).unwrap();
+            prop_assert!(AnyOptimizedForest::<Classification>::deserialize(&buffer).is_ok());
+            let buffer = RegressionProblem::serialize_with(&regression, width).unwrap();
+            prop_assert!(AnyOptimizedForest::<Regression>::deserialize(&buffer).is_ok());
+        }
+    }
+
+    #[test]
+    fn validation_rejects_every_pathology(
+        classification in forests::<ClassificationProblem>(shapes()),
+        regression in forests::<RegressionProblem>(shapes()),
+    ) {
+        for pathology in Pathology::ALL {
+            let buffer = serialize_with_pathology(&classification, pathology).unwrap();
+            prop_assert_eq!(
+                OptimizedForest::<Classification>::deserialize(&buffer).err(),
+                Some(Error::MalformedForest),
+                "{:?}", pathology
+            );
+            let buffer = serialize_with_pathology(&regression, pathology).unwrap();
+            prop_assert_eq!(
+                OptimizedForest::<Regression>::deserialize(&buffer).err(),
+                Some(Error::MalformedForest),
+                "{:?}", pathology
+            );
+        }
+    }
+}
+
+#[test]
+fn generated_forests_follow_their_shape() {
+    let mut rng = proptest::test_runner::TestRng::deterministic_rng(
+        proptest::test_runner::RngAlgorithm::ChaCha,
+    );
+
+    // Single branches
+    let shape = ForestShape {
+        num_trees: 7,
+        max_depth: 1,
+        ..ForestShape::default()
+    };
+    let forest = random_forest::<ClassificationProblem, _>(&shape, &mut rng);
+    assert_eq!(forest.num_trees(), 7);
+    assert_eq!(forest.num_targets(), 3);
+    assert!(forest.trees().all(|tree| tree.num_branches() == 1));
+
+    // Full trees
+    let shape = ForestShape {
+        max_depth: 5,
+        branch_probability: 1.0,
+        num_features: 2,
+        ..ForestShape::default()
+    };
+    let forest = random_forest::<RegressionProblem, _>(&shape, &mut rng);
+    assert_eq!(forest.num_features(), 2);
+    assert!(forest.trees().all(|tree| tree.num_branches() == 31));
+    assert!(forest.trees().all(|tree| tree.depth() == 5));
+
+    // Single leaves
+    let shape = ForestShape {
+        leaf_root_probability: 1.0,
+        ..ForestShape::default()
+    };
+    let forest = random_forest::<ClassificationProblem, _>(&shape, &mut rng);
+    assert!(forest.trees().all(|tree| tree.num_nodes() == 1));
+}
@@ -0,0 +1,153 @@
+use color_eyre::Result;
+use embedded_rforest::forest::{Classification, OptimizedForest, Predict};
+use forest_optimizer::dataset::{LabeledDataset, read_labeled};
+use forest_optimizer::forest::Forest;
+use forest_optimizer::problem_type::Classification as ClassificationProblem;
+use forest_optimizer::prune::{Validation, collapse_redundant, keep_first_trees, truncate_depth};
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::datasets::{airfoil, iris};
+use crate::helpers::{get_forest, get_test_data};
+
+#[test]
+fn collapsing_keeps_classification_predictions() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let collapsed = collapse_redundant(&forest);
+    assert_eq!(collapsed.num_trees(), forest.num_trees());
+    assert!(collapsed.nodes().len() <= forest.nodes().len());
+
+    let test_data: Vec<iris::DataPoint> = get_test_data("./tests/test-data/iris.csv")?;
+    for data_point in test_data {
+        let features = data_point.transform_features(forest.features());
+        assert_eq!(collapsed.predict(&features), forest.predict(&features));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn collapsing_keeps_regression_predictions() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let collapsed = collapse_redundant(&forest);
+
+    let test_data: Vec<airfoil::DataPoint> = get_test_data("./tests/test-data/airfoil.csv")?;
+    for data_point in test_data {
+        let features = data_point.transform_features(forest.features());
+        assert_eq!(collapsed.predict(&features), forest.predict(&features));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn keeping_first_trees_keeps_their_nodes() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let kept = keep_first_trees(&forest, 10);
+
+    assert_eq!(kept.num_trees(), 10);
+    for (kept, original) in kept.trees().zip(forest.trees()) {
+        assert_eq!(kept.num_nodes(), original.num_nodes());
+        assert_eq!(kept.depth(), original.depth());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn truncating_below_the_deepest_tree_changes_nothing() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let features = forest.features_by_index();
+    let dataset = read_labeled("./tests/test-data/iris.csv", &features, "Species")?;
+    let validation = Validation::new(&forest, dataset)?;
+    let depth = forest.trees().map(|tree| tree.depth()).max().unwrap();
+
+    for max_depth in [depth, depth + 10] {
+        assert_eq!(
+            truncate_depth(&forest, max_depth, None).nodes(),
+            forest.nodes()
+        );
+        assert_eq!(
+            truncate_depth(&forest, max_depth, Some(&validation)).nodes(),
+            forest.nodes()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn truncating_to_one_split_keeps_a_valid_forest() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let features = forest.features_by_index();
+    let dataset = read_labeled("./tests/test-data/iris.csv", &features, "Species")?;
+    let rows = dataset.rows.clone();
+    let validation = Validation::new(&forest, dataset)?;
+
+    for truncated in [
+        truncate_depth(&forest, 1, None),
+        truncate_depth(&forest, 1, Some(&validation)),
+    ] {
+        assert_eq!(truncated.num_trees(), forest.num_trees());
+        assert!(truncated.trees().all(|tree| tree.depth() <= 1));
+        assert!(truncated.nodes().len() < forest.nodes().len());
+
+        let buffer = ClassificationProblem::serialize(&truncated)?;
+        let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+        for row in &rows {
+            assert_eq!(
+                truncated.targets()[&truncated.predict(row)].0,
+                optimized.predict(row)
+            );
+        }
+    }
+
+    // Iris is mostly separated by a single split
+    let truncated = truncate_depth(&forest, 1, Some(&validation));
+    assert!(validation.score(&truncated) > 60.0);
+
+    Ok(())
+}
+
+#[test]
+fn truncated_branches_predict_the_rows_reaching_them() -> Result<()> {
+    // `x <= 0.5 ? 1 : (y <= 0.5 ? 2 : 6)`
+    let definition = "# { \"problem_type\": \"regression\" }\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        2,3,\"x\",0.5,-3,3,1,1\n\
+        0,0,NA,0,-1,1,1,2\n\
+        4,5,\"y\",0.5,-3,4,1,3\n\
+        0,0,NA,0,-1,2,1,4\n\
+        0,0,NA,0,-1,6,1,5\n";
+    let serialized =
+        SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())?;
+    let forest = Forest::from_serialized(serialized)?;
+
+    // Without rows, the mean of the leaves cut off
+    let truncated = truncate_depth(&forest, 1, None);
+    assert_eq!(truncated.nodes().len(), 3);
+    assert_eq!(truncated.predict(&[1.0, 0.0]), 4.0);
+
+    let dataset = LabeledDataset {
+        rows: vec![vec![0.0, 0.0], vec![1.0, 0.0], vec![1.0, 1.0]],
+        labels: ["0", "10", "20"].map(String::from).to_vec(),
+    };
+    let validation = Validation::new(&forest, dataset)?;
+    let truncated = truncate_depth(&forest, 1, Some(&validation));
+    assert_eq!(truncated.predict(&[1.0, 0.0]), 15.0);
+    // Leaves above the depth are kept as they are
+    assert_eq!(truncated.predict(&[0.0, 0.0]), 1.0);
+
+    let stump = truncate_depth(&forest, 0, Some(&validation));
+    assert_eq!(stump.nodes().len(), 1);
+    assert_eq!(stump.predict(&[0.0, 0.0]), 10.0);
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use color_eyre::Result;
+use forest_optimizer::quantize::{
+    LeafType, QuantizeOptions, ThresholdType, quantize, quantize_thresholds,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+
+use crate::datasets::airfoil;
+use crate::helpers::{assert_epsilon, get_forest, get_test_data};
+
+#[test]
+fn rounding_thresholds_reports_their_error() -> Result<()> {
+    let mut forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let stats = quantize_thresholds(&mut forest, ThresholdType::F16)?;
+
+    // Iris thresholds are below 8, where f16 values are 2^-8 apart at most
+    assert!(stats.max <= 1.0 / 512.0 + f64::EPSILON);
+    assert!(stats.mean <= stats.max);
+    assert_eq!(
+        quantize_thresholds(&mut forest, ThresholdType::F16)?.max,
+        0.0
+    );
+
+    Ok(())
+}
+
+#[test]
+fn u16_leaves_barely_change_regression_predictions() -> Result<()> {
+    let original =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let mut forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let error = quantize(
+        &mut forest,
+        &QuantizeOptions {
+            thresholds: None,
+            leaves: Some(LeafType::U16),
+        },
+    )?;
+    assert!(error.thresholds.is_none());
+    assert!(error.leaf_rmse.unwrap() > 0.0);
+
+    let test_data: Vec<airfoil::DataPoint> = get_test_data("./tests/test-data/airfoil.csv")?;
+    for data_point in test_data {
+        let features = data_point.transform_features(forest.features());
+        assert_epsilon(forest.predict(&features), original.predict(&features), 0.5);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::quickscorer::QuickScorer;
+use embedded_rforest::forest::{
+    Aggregation, Branch, Classification, OptimizedForest, Predict, Regression,
+};
+use embedded_rforest::ptr::{F32, NodePointer, RelativeU16};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+use crate::helpers::get_forest;
+
+/// `rows`, plus copies with a missing (NaN) feature and with features past
+/// every threshold
+fn with_edge_cases(rows: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+    let mut edges = Vec::new();
+    for row in rows.iter().take(20) {
+        for feature in 0..row.len() {
+            let mut missing = row.clone();
+            missing[feature] = f32::NAN;
+            edges.push(missing);
+        }
+    }
+    let width = rows[0].len();
+    edges.push(vec![f32::INFINITY; width]);
+    edges.push(vec![f32::NEG_INFINITY; width]);
+    rows.into_iter().chain(edges).collect()
+}
+
+#[test]
+fn quickscorer_classifies_like_the_tree_walks() -> Result<()> {
+    for path in [
+        "./tests/test-forests/forest_iris_5.csv",
+        "./tests/test-forests/forest_iris_800.csv",
+    ] {
+        let forest = get_forest::<SerializedClassificationNode>(path)?;
+        let features = forest.features_by_index();
+        let rows = with_edge_cases(read_mapped_rows("./tests/test-data/iris.csv", &features)?);
+
+        let bytes = ClassificationProblem::serialize(&forest)?;
+        let optimized = OptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+        let scorer = QuickScorer::new(&optimized, 64).unwrap();
+        let relative = ClassificationProblem::serialize_with(&forest, PointerWidth::Relative)?;
+        let relative =
+            OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&relative).unwrap();
+        let relative_scorer = QuickScorer::new(&relative, 64).unwrap();
+        assert_eq!(scorer.condition_count(), optimized.node_count());
+
+        for row in &rows {
+            let expected = optimized.predict(row);
+            assert_eq!(scorer.predict_qs(row), expected, "{path}: {row:?}");
+            assert_eq!(relative_scorer.predict_qs(row), expected, "{path}: {row:?}");
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn quickscorer_regresses_like_the_tree_walks() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let features = forest.features_by_index();
+    let rows = with_edge_cases(read_mapped_rows(
+        "./tests/test-data/airfoil.csv",
+        &features,
+    )?);
+
+    let bytes = RegressionProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Regression>::deserialize(&bytes).unwrap();
+    // Trees of up to 200 leaves, over at most four bitvector words
+    assert_eq!(
+        QuickScorer::new(&optimized, 199).err(),
+        Some(Error::TooManyLeaves)
+    );
+    let scorer = QuickScorer::new(&optimized, 200).unwrap();
+    assert!(scorer.bitvector_len() <= optimized.num_trees() as usize * 4 * 8);
+
+    let mut false_conditions = 0;
+    for row in &rows {
+        // Leaves are summed in the same order, so bit for bit
+        assert_eq!(
+            scorer.predict_qs(row).to_bits(),
+            optimized.predict(row).to_bits(),
+            "{row:?}"
+        );
+        false_conditions += scorer.predict_qs_counting(row).1;
+    }
+    assert!(false_conditions > 0);
+
+    Ok(())
+}
+
+#[test]
+fn quickscorer_follows_the_aggregation() {
+    // x0 <= 0.5 ? (x1 <= 2.0 ? 1.0 : 2.0) : 4.0, and a single-leaf tree
+    let nodes = [
+        Branch::new(0, 0.5, NodePointer::new_branch(2), NodePointer::new_leaf(2)),
+        Branch::new(0, 0.0, NodePointer::new_leaf(3), NodePointer::new_leaf(3)),
+        Branch::new(1, 2.0, NodePointer::new_leaf(0), NodePointer::new_leaf(1)),
+    ];
+    let leaves = [1.0, 2.0, 4.0, 0.5].map(F32::new);
+    let forest = OptimizedForest::<Regression>::with_leaves(2, &nodes, 2, &leaves)
+        .and_then(|forest| forest.with_aggregation(Aggregation::Sum { base: 10.0 }))
+        .unwrap();
+    let scorer = QuickScorer::new(&forest, 3).unwrap();
+    for (row, expected) in [
+        ([0.0, 1.0], 11.5),
+        ([0.0, 3.0], 12.5),
+        ([1.0, 1.0], 14.5),
+        ([f32::NAN, 1.0], 14.5),
+        ([0.0, f32::NAN], 12.5),
+    ] {
+        assert_eq!(scorer.predict_qs(&row), expected);
+        assert_eq!(forest.predict(&row), expected);
+    }
+
+    // A NaN threshold is never met, like in the tree walk
+    let nodes = [Branch::new(
+        0,
+        f32::NAN,
+        NodePointer::new_leaf(0),
+        NodePointer::new_leaf(1),
+    )];
+    let forest =
+        OptimizedForest::<Classification>::new(1, &nodes, 1, Classification::new(2).unwrap())
+            .unwrap();
+    let scorer = QuickScorer::new(&forest, 2).unwrap();
+    assert_eq!(scorer.condition_count(), 0);
+    assert_eq!(scorer.predict_qs(&[0.0]), 1);
+    assert_eq!(forest.predict(&[0.0]), 1);
+}
@@ -0,0 +1,103 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Branch, Classification, NodeLayout, OptimizedForest, Predict,
+    deserialize::{ForestHeader, HEADER_LEN},
+};
+use embedded_rforest::ptr::{RelativeU16, U16};
+use forest_optimizer::analyze::analyze;
+use forest_optimizer::emit::OutputFormat;
+use forest_optimizer::problem_type::Classification as ClassificationProblem;
+use forest_optimizer::serialized_forest::SerializedClassificationNode;
+use forest_optimizer::write_forest::{Endianness, PointerWidth, WriteForest};
+
+use crate::helpers::{get_forest, synthetic_forest, synthetic_rows};
+
+#[test]
+fn large_forest_of_small_trees_uses_relative_pointers() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    // 67500 branches, 15 per tree
+    let forest =
+        get_forest::<SerializedClassificationNode>(synthetic_forest(dir.path(), 4500, 4)?)?;
+    let rows = synthetic_rows(200, &forest.features_by_index());
+
+    assert!(ClassificationProblem::serialize_with(&forest, PointerWidth::U16).is_err());
+    let relative = ClassificationProblem::serialize_with(&forest, PointerWidth::Relative)?;
+    assert_eq!(
+        ClassificationProblem::serialize_with(&forest, PointerWidth::Auto)?,
+        relative
+    );
+    let header = ForestHeader::peek(&relative).unwrap();
+    assert_eq!(header.layout, NodeLayout::Relative);
+    assert_eq!(header.node_count, 67_500);
+    // The fingerprint, padded to 8 bytes, follows the tree table
+    assert_eq!(header.header_len, HEADER_LEN + 4500 * 4 + 8);
+    assert_eq!(relative.len(), header.serialized_len());
+
+    let standard = ClassificationProblem::serialize(&forest)?;
+    assert!(relative.len() < standard.len());
+    let standard = OptimizedForest::<Classification>::deserialize(&standard).unwrap();
+    let relative =
+        OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&relative).unwrap();
+    assert_eq!(relative.tree_offsets().len(), 4500);
+    assert_eq!(relative.tree_offsets()[1].get(), 15);
+    for row in &rows {
+        assert_eq!(relative.predict(row), standard.predict(row));
+        assert_eq!(
+            relative.predict_counting(row).1,
+            standard.predict_counting(row).1
+        );
+    }
+
+    // Round trips, and only deserializes as itself
+    let bytes = relative.to_bytes();
+    assert!(matches!(
+        OptimizedForest::<Classification, Branch<U16>>::deserialize(&bytes),
+        Err(Error::WrongLayout)
+    ));
+    let loaded = AnyOptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.layout(), NodeLayout::Relative);
+    assert_eq!(loaded.serialized_len(), bytes.len());
+
+    // JSON holds absolute branch indices, and where each tree starts
+    let mut json = Vec::new();
+    OutputFormat::Json.emit(
+        &relative,
+        &ClassificationProblem::metadata(&forest),
+        Endianness::Little,
+        None,
+        "forest",
+        &mut json,
+    )?;
+    let json: serde_json::Value = serde_json::from_slice(&json)?;
+    assert_eq!(json["tree_offsets"][4499], 4499 * 15);
+    assert_eq!(json["nodes"][15]["left"]["branch"], 16);
+
+    assert_eq!(analyze(&forest)?.pointer_layout, "relative");
+
+    Ok(())
+}
+
+#[test]
+fn relative_pointer_outside_its_tree_is_rejected() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let mut buffer = ClassificationProblem::serialize_with(&forest, PointerWidth::Relative)?;
+    let header = ForestHeader::peek(&buffer).unwrap();
+    assert_eq!(header.layout, NodeLayout::Relative);
+    let forest =
+        OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&buffer).unwrap();
+    let second_tree = forest.tree_offsets()[1].get() as u16;
+
+    // The root of the first tree points right at a branch: point it at the
+    // root of the second tree instead, inside the node array but not the tree
+    let right = header.header_len + 2;
+    assert_eq!(buffer[right + 1] & 0x80, 0);
+    buffer[right..right + 2].copy_from_slice(&second_tree.to_le_bytes());
+    assert!(matches!(
+        OptimizedForest::<Classification, Branch<RelativeU16>>::deserialize(&buffer),
+        Err(Error::MalformedForest)
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::forest::deserialize::ForestHeader;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, Predict, Regression, Transform,
+};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::inspect::{inspect, read_model};
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::sections::{Section, SectionSize, size_breakdown, strip};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{NodeEncoding, WriteForest};
+use predicates::{prelude::PredicateBooleanExt, str::contains};
+
+use crate::helpers::get_forest;
+
+const IRIS_STATS: &str = "./tests/test-forests/forest_iris_5_stats.csv";
+const AIRFOIL: &str = "./tests/test-forests/airfoil_100_200.rforest";
+
+fn sections(buffer: &[u8]) -> Vec<SectionSize> {
+    size_breakdown(&ForestHeader::peek(buffer).unwrap())
+}
+
+fn bytes_of(sections: &[SectionSize], section: Section) -> usize {
+    sections
+        .iter()
+        .find(|size| size.section == section)
+        .map_or(0, |size| size.bytes)
+}
+
+#[test]
+fn sections_add_up_to_the_forest() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let sizes = sections(&buffer);
+    let layout = sizes
+        .iter()
+        .map(|size| (size.section, size.offset, size.bytes))
+        .collect::<Vec<_>>();
+    // 5 trees, fingerprint, 30 branches of 16 bytes, without padding
+    assert_eq!(
+        layout,
+        [
+            (Section::Header, 0, 16),
+            (Section::TreeTable, 16, 20),
+            (Section::Fingerprint, 36, 4),
+            (Section::Nodes, 40, 480),
+        ]
+    );
+
+    for buffer in [buffer, read_model(AIRFOIL)?] {
+        let sizes = sections(&buffer);
+        assert_eq!(
+            sizes.iter().map(|size| size.bytes).sum::<usize>(),
+            buffer.len()
+        );
+        let percent = sizes.iter().map(|size| size.percent).sum::<f64>();
+        assert!((percent - 100.0).abs() < 1e-9, "{percent}");
+        assert_eq!(inspect(&buffer)?.sections, sizes);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn stripping_the_support_saves_its_size() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(IRIS_STATS)?;
+    let encoding = NodeEncoding {
+        support: true,
+        ..Default::default()
+    };
+    let buffer = ClassificationProblem::serialize_encoded(&forest, &encoding)?;
+    let support = bytes_of(&sections(&buffer), Section::Support);
+    assert!(support > 0);
+
+    let stripped = strip(&buffer, &[Section::Support])?;
+    assert_eq!(buffer.len() - stripped.len(), support);
+    assert!(!ForestHeader::peek(&stripped).unwrap().support);
+    // The forest is then the one written without support
+    assert_eq!(stripped, ClassificationProblem::serialize(&forest)?);
+
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    let (original, stripped) = (
+        AnyOptimizedForest::<Classification>::deserialize(&buffer).unwrap(),
+        AnyOptimizedForest::<Classification>::deserialize(&stripped).unwrap(),
+    );
+    for row in &rows {
+        assert_eq!(original.predict(row), stripped.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn stripping_from_the_header_saves_its_size_and_padding() -> Result<()> {
+    let buffer = read_model(AIRFOIL)?;
+    let before = sections(&buffer);
+    let stripped = strip(&buffer, &[Section::Fingerprint])?;
+    let after = sections(&stripped);
+
+    assert_eq!(bytes_of(&after, Section::Fingerprint), 0);
+    // The node array stays aligned
+    assert_eq!(
+        buffer.len() - stripped.len(),
+        bytes_of(&before, Section::Fingerprint) + bytes_of(&before, Section::Padding)
+            - bytes_of(&after, Section::Padding)
+    );
+    let (original, stripped) = (
+        AnyOptimizedForest::<Regression>::deserialize(&buffer).unwrap(),
+        AnyOptimizedForest::<Regression>::deserialize(&stripped).unwrap(),
+    );
+    for row in [[0.0, 0.0, 0.0, 0.0, 0.0], [2000.0, 5.0, 0.1, 40.0, 0.01]] {
+        assert_eq!(original.predict(&row), stripped.predict(&row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn stripping_the_transform_predicts_the_raw_output() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let encoding = NodeEncoding {
+        transform: Transform::Exp,
+        ..Default::default()
+    };
+    let buffer = RegressionProblem::serialize_encoded(&forest, &encoding)?;
+    let stripped = strip(&buffer, &[Section::Transform, Section::Fingerprint])?;
+
+    let header = ForestHeader::peek(&stripped).unwrap();
+    assert_eq!(header.transform, Transform::Identity);
+    assert_eq!(header.fingerprint, None);
+    let (original, stripped) = (
+        AnyOptimizedForest::<Regression>::deserialize(&buffer).unwrap(),
+        AnyOptimizedForest::<Regression>::deserialize(&stripped).unwrap(),
+    );
+    let row = [2000.0, 5.0, 0.1, 40.0, 0.01];
+    assert_eq!(original.predict(&row), stripped.predict(&row).exp());
+
+    Ok(())
+}
+
+#[test]
+fn required_and_absent_sections_are_not_stripped() -> Result<()> {
+    let buffer = read_model(AIRFOIL)?;
+
+    for section in [Section::Nodes, Section::TreeTable, Section::LeafTable] {
+        let message = strip(&buffer, &[section]).unwrap_err().to_string();
+        assert!(
+            message.contains("the forest needs it to predict"),
+            "{message}"
+        );
+    }
+    let message = strip(&buffer, &[Section::Support]).unwrap_err().to_string();
+    assert_eq!(message, "The forest has no support to strip");
+
+    Ok(())
+}
+
+#[test]
+fn rewrite_strips_the_given_sections() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+    let stripped = dir.path().join("stripped.rforest");
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--support", "-i", IRIS_STATS, "-o"])
+        .arg(&model)
+        .assert()
+        .success();
+    let support = bytes_of(&sections(&read_model(&model)?), Section::Support);
+
+    Command::cargo_bin("forest-optimizer")?
+        .arg("rewrite")
+        .arg(&model)
+        .arg(&stripped)
+        .args(["--strip", "support"])
+        .assert()
+        .success()
+        .stdout(contains("Stripped the support"));
+    assert_eq!(
+        std::fs::metadata(&model)?.len() - std::fs::metadata(&stripped)?.len(),
+        support as u64
+    );
+    assert!(dir.path().join("stripped.rforest.meta.json").exists());
+    Command::cargo_bin("forest-optimizer")?
+        .arg("info")
+        .arg(&stripped)
+        .assert()
+        .success()
+        .stdout(contains("fingerprint").and(contains("support").not()));
+
+    Command::cargo_bin("forest-optimizer")?
+        .arg("rewrite")
+        .arg(&model)
+        .args(["--strip", "tree-table"])
+        .assert()
+        .failure()
+        .stderr(contains("Cannot strip the tree table"));
+
+    Ok(())
+}
@@ -1,39 +1,48 @@
-use color_eyre::eyre::eyre;
+use aligned_vec::AVec;
 use color_eyre::Result;
-use embedded_rforest::forest::{Classification, OptimizedForest, Predict, Regression};
-use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use color_eyre::eyre::Context;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, OptimizedForest, Predict, ProblemType, Regression,
+    Transform, deserialize::BUFFER_ALIGN,
+};
+use forest_optimizer::analyze::estimate_serialized_size;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::{NodeEncoding, PointerWidth, WriteForest};
 
 use crate::datasets::{airfoil, iris};
-use crate::helpers::{assert_epsilon, get_forest, get_test_data};
+use crate::helpers::{assert_reproduces_recorded, get_forest, get_test_data, reversed_rows};
+
+const IRIS: &str = "./tests/test-data/iris.csv";
+const AIRFOIL: &str = "./tests/test-data/airfoil.csv";
 
 #[test]
 fn serialized_then_deserialized_classification_tree_is_accurate() -> Result<()> {
     let forest =
         get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
 
-    let nodes = forest.optimize_nodes();
+    let (nodes, _) = forest.optimize_nodes();
     let optimized = OptimizedForest::<Classification>::new(
         forest.num_trees().try_into().unwrap(),
         &nodes,
         forest.num_features().try_into().unwrap(),
         Classification::new(forest.num_targets().try_into().unwrap()).unwrap(),
     )
-    .map_err(|_| eyre!("Malformed forest"))?;
+    .context("Malformed forest")?;
 
     let serialized = optimized.to_bytes();
-    let optimized = OptimizedForest::<Classification>::deserialize(&serialized)
-        .map_err(|_| eyre!("Malfomed forest"))?;
-
-    let test_data: Vec<iris::DataPoint> = get_test_data("./tests/test-data/iris.csv")?;
-
-    for data_point in test_data {
-        let features = data_point.transform_features(forest.features());
-        let prediction = optimized.predict(&features);
-        let target = forest.targets().get(&data_point.forest_prediction).unwrap();
-        assert_eq!(prediction, *target);
-    }
+    let optimized =
+        OptimizedForest::<Classification>::deserialize(&serialized).context("Malformed forest")?;
 
-    Ok(())
+    assert_reproduces_recorded(&forest, &optimized, IRIS, 0.0)
 }
 
 #[test]
@@ -41,27 +50,20 @@ fn serialized_then_deserialized_regression_tree_is_accurate() -> Result<()> {
     let forest =
         get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
 
-    let nodes = forest.optimize_nodes();
-    let optimized = OptimizedForest::<Regression>::new(
+    let (nodes, leaves) = forest.optimize_nodes();
+    let optimized = OptimizedForest::<Regression>::with_leaves(
         forest.num_trees().try_into().unwrap(),
         &nodes,
         forest.num_features().try_into().unwrap(),
+        &leaves,
     )
-    .map_err(|_| eyre!("Malformed forest"))?;
+    .context("Malformed forest")?;
 
     let serialized = optimized.to_bytes();
-    let optimized = OptimizedForest::<Regression>::deserialize(&serialized)
-        .map_err(|_| eyre!("Malfomed forest"))?;
-
-    let test_data: Vec<airfoil::DataPoint> = get_test_data("./tests/test-data/airfoil.csv")?;
-
-    for data_point in test_data {
-        let features = data_point.transform_features(forest.features());
-        let prediction = optimized.predict(&features);
-        assert_epsilon(prediction, data_point.forest_prediction, 2.5);
-    }
+    let optimized =
+        OptimizedForest::<Regression>::deserialize(&serialized).context("Malformed forest")?;
 
-    Ok(())
+    assert_reproduces_recorded(&forest, &optimized, AIRFOIL, 2.5)
 }
 
 #[test]
@@ -71,38 +73,277 @@ fn classification_static_storage_deserializes_correctly() -> Result<()> {
     let forest =
         get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
 
-    let deserialized = OptimizedForest::<Classification>::deserialize(buf)
-        .map_err(|_| eyre!("Malformed forest"))?;
+    let deserialized =
+        OptimizedForest::<Classification>::deserialize(buf).context("Malformed forest")?;
+
+    assert_reproduces_recorded(&forest, &deserialized, IRIS, 0.0)
+}
+
+#[test]
+fn regression_static_storage_deserializes_correctly() -> Result<()> {
+    let buf = embedded_rforest::static_storage!("../test-forests/airfoil_100_200.rforest");
+
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+
+    let deserialized =
+        OptimizedForest::<Regression>::deserialize(buf).context("Malformed forest")?;
+
+    assert_reproduces_recorded(&forest, &deserialized, AIRFOIL, 2.5)
+}
 
+#[test]
+fn static_storage_deserializes_unchecked_like_checked() -> Result<()> {
+    let buf = embedded_rforest::static_storage!("../test-forests/forest_iris_5.rforest");
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let checked =
+        OptimizedForest::<Classification>::deserialize(buf).context("Malformed forest")?;
+    // SAFETY: `buf` just passed `deserialize`
+    let unchecked = unsafe { OptimizedForest::<Classification>::deserialize_unchecked(buf) }
+        .context("Malformed forest")?;
+    assert_eq!(unchecked.to_bytes(), checked.to_bytes());
     let test_data: Vec<iris::DataPoint> = get_test_data("./tests/test-data/iris.csv")?;
+    for data_point in test_data {
+        let features = data_point.transform_features(forest.features());
+        assert_eq!(unchecked.predict(&features), checked.predict(&features));
+    }
 
+    let buf = embedded_rforest::static_storage!("../test-forests/airfoil_100_200.rforest");
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let checked = OptimizedForest::<Regression>::deserialize(buf).context("Malformed forest")?;
+    // SAFETY: `buf` just passed `deserialize`
+    let unchecked = unsafe { OptimizedForest::<Regression>::deserialize_unchecked(buf) }
+        .context("Malformed forest")?;
+    assert_eq!(unchecked.to_bytes(), checked.to_bytes());
+    let test_data: Vec<airfoil::DataPoint> = get_test_data("./tests/test-data/airfoil.csv")?;
     for data_point in test_data {
         let features = data_point.transform_features(forest.features());
-        let prediction = deserialized.predict(&features);
-        let target = forest.targets().get(&data_point.forest_prediction).unwrap();
-        assert_eq!(prediction, *target);
+        assert_eq!(unchecked.predict(&features), checked.predict(&features));
+    }
+
+    // The header is still checked
+    // SAFETY: fails on the problem type, before any node is read
+    assert!(unsafe { OptimizedForest::<Classification>::deserialize_unchecked(buf) }.is_err());
+
+    Ok(())
+}
+
+/// Serialize the forest of `buffer` again into a buffer which is neither
+/// aligned nor of the exact size, and check it is `buffer`, and predicts
+/// every row the same.
+fn assert_serializes_into<P>(buffer: &[u8], rows: &[Vec<f32>]) -> Result<()>
+where
+    P: ProblemType,
+    P::Output: PartialEq + std::fmt::Debug,
+    for<'a> AnyOptimizedForest<'a, P>: Predict<ProblemType = P>,
+{
+    let forest = AnyOptimizedForest::<P>::deserialize(buffer).context("Malformed forest")?;
+    assert_eq!(forest.serialized_len(), buffer.len());
+
+    let mut out = vec![0xAA; buffer.len() + 9];
+    assert_eq!(forest.serialize_into(&mut out[1..]), Ok(buffer.len()));
+    assert_eq!(&out[1..=buffer.len()], buffer);
+    assert!(out[buffer.len() + 1..].iter().all(|&b| b == 0xAA));
+
+    let copy = AVec::<u8>::from_slice(BUFFER_ALIGN, &out[1..=buffer.len()]);
+    let copy = AnyOptimizedForest::<P>::deserialize(&copy).context("Malformed forest")?;
+    for row in rows {
+        assert_eq!(copy.predict(row), forest.predict(row));
     }
 
     Ok(())
 }
 
 #[test]
-fn regression_static_storage_deserializes_correctly() -> Result<()> {
-    let buf = embedded_rforest::static_storage!("../test-forests/airfoil_100_200.rforest");
+fn serialize_into_writes_the_deserialized_forest() -> Result<()> {
+    let iris =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5_stats.csv")?;
+    let rows = read_mapped_rows(IRIS, &iris.features_by_index())?;
+    let encoding = NodeEncoding {
+        support: true,
+        ..NodeEncoding::default()
+    };
+    for buffer in [
+        ClassificationProblem::serialize(&iris)?,
+        ClassificationProblem::serialize_encoded(&iris, &encoding)?,
+        ClassificationProblem::serialize_with(&iris, PointerWidth::U16)?,
+    ] {
+        assert_serializes_into::<Classification>(&buffer, &rows)?;
+    }
+
+    let airfoil =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let rows = read_mapped_rows(AIRFOIL, &airfoil.features_by_index())?;
+    let encoding = NodeEncoding {
+        transform: Transform::Exp,
+        ..NodeEncoding::from(PointerWidth::Relative)
+    };
+    for buffer in [
+        RegressionProblem::serialize(&airfoil)?,
+        RegressionProblem::serialize_encoded(&airfoil, &encoding)?,
+    ] {
+        assert_serializes_into::<Regression>(&buffer, &rows)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn serialize_into_rejects_undersized_buffers() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let needed = forest.serialized_len();
+
+    let mut out = vec![0xAA; needed - 1];
+    assert_eq!(
+        forest.serialize_into(&mut out),
+        Err(Error::BufferTooSmall { needed })
+    );
+    // Nothing is written
+    assert!(out.iter().all(|&b| b == 0xAA));
+    assert_eq!(
+        forest.serialize_into(&mut []),
+        Err(Error::BufferTooSmall { needed })
+    );
+
+    let mut out = vec![0; needed];
+    assert_eq!(forest.serialize_into(&mut out), Ok(needed));
+    assert_eq!(out, &buffer[..]);
+    assert_eq!(forest.to_bytes().as_slice(), &buffer[..]);
+
+    Ok(())
+}
+
+#[test]
+fn estimated_size_matches_serialized_size() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let report = estimate_serialized_size(&forest);
+    assert_eq!(
+        report.serialized_size,
+        WriteForest::serialize(&forest)?.len()
+    );
 
     let forest =
         get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let report = estimate_serialized_size(&forest);
+    assert_eq!(
+        report.serialized_size,
+        WriteForest::serialize(&forest)?.len()
+    );
 
-    let deserialized =
-        OptimizedForest::<Regression>::deserialize(buf).map_err(|_| eyre!("Malformed forest"))?;
+    Ok(())
+}
 
-    let test_data: Vec<airfoil::DataPoint> = get_test_data("./tests/test-data/airfoil.csv")?;
+#[test]
+fn pinned_order_assigns_indices() -> Result<()> {
+    let features = ["Sepal.Width", "Sepal.Length", "Petal.Width", "Petal.Length"];
+    let targets = ["virginica", "versicolor", "setosa"];
 
-    for data_point in test_data {
-        let features = data_point.transform_features(forest.features());
-        let prediction = deserialized.predict(&features);
-        assert_epsilon(prediction, data_point.forest_prediction, 2.5);
+    let forest = SerializedForest::<SerializedClassificationNode>::read_with_order(
+        "./tests/test-forests/forest_iris_5.csv",
+        &features,
+        Some(&targets),
+    )?;
+
+    for (index, name) in features.iter().enumerate() {
+        assert_eq!(forest.features()[*name].as_usize(), index);
+    }
+    for (index, name) in targets.iter().enumerate() {
+        assert_eq!(forest.targets()[*name].as_usize(), index);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn pinned_order_makes_exports_identical() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let reversed = reversed_rows("./tests/test-forests/forest_iris_5.csv", dir.path())?;
+    let features = ["Petal.Length", "Petal.Width", "Sepal.Length", "Sepal.Width"];
+    let targets = ["setosa", "versicolor", "virginica"];
+
+    let serialize = |path: &std::path::Path, order: Option<&[&str]>| -> Result<Vec<u8>> {
+        let forest = match order {
+            Some(order) => SerializedForest::<SerializedClassificationNode>::read_with_order(
+                path,
+                order,
+                Some(&targets),
+            )?,
+            None => SerializedForest::<SerializedClassificationNode>::read(path)?,
+        };
+        let forest = Forest::from_serialized(forest)?;
+        Ok(ClassificationProblem::serialize(&forest)?.to_vec())
+    };
+
+    let original = std::path::Path::new("./tests/test-forests/forest_iris_5.csv");
+    assert_ne!(serialize(original, None)?, serialize(&reversed, None)?);
+    assert_eq!(
+        serialize(original, Some(&features))?,
+        serialize(&reversed, Some(&features))?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn pinned_order_must_match_forest() -> Result<()> {
+    let error = SerializedForest::<SerializedClassificationNode>::read_with_order(
+        "./tests/test-forests/forest_iris_5.csv",
+        &["Petal.Length", "Petal.Width", "Sepal.Length", "Humidity"],
+        None,
+    )
+    .unwrap_err();
+
+    let message = error.to_string();
+    assert!(message.contains("Missing: [Sepal.Width]"), "{message}");
+    assert!(
+        message.contains("not in the forest: [Humidity]"),
+        "{message}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sorted_names_do_not_depend_on_row_order() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let reversed = reversed_rows("./tests/test-forests/forest_iris_5.csv", dir.path())?;
+
+    let mut exports = Vec::new();
+    for path in [
+        std::path::Path::new("./tests/test-forests/forest_iris_5.csv"),
+        &reversed,
+    ] {
+        let forest = SerializedForest::<SerializedClassificationNode>::read(path)?.sort_names();
+        assert_eq!(forest.features()["Petal.Length"].0, 0);
+        assert_eq!(forest.features()["Sepal.Width"].0, 3);
+        assert_eq!(forest.targets()["setosa"].0, 0);
+        assert_eq!(forest.targets()["virginica"].0, 2);
+
+        let forest = Forest::from_serialized(forest)?;
+        exports.push(ClassificationProblem::serialize(&forest)?.to_vec());
     }
+    assert_eq!(exports[0], exports[1]);
+
+    Ok(())
+}
+
+#[test]
+fn pinned_target_order_must_match_forest() -> Result<()> {
+    let forest = SerializedForest::<SerializedClassificationNode>::read(
+        "./tests/test-forests/forest_iris_5.csv",
+    )?;
+    let error = forest
+        .reorder(None, Some(&["setosa", "virginica", "Setosa"]))
+        .unwrap_err();
+
+    let message = error.to_string();
+    assert!(message.contains("target order"), "{message}");
+    assert!(message.contains("Missing: [versicolor]"), "{message}");
+    assert!(message.contains("not in the forest: [Setosa]"), "{message}");
 
     Ok(())
 }
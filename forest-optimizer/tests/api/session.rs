@@ -0,0 +1,171 @@
+use std::cell::Cell;
+use std::task::Poll;
+
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    Branch, Classification, OptimizedForest, Predict, PredictSession, Regression,
+    access::{CachedNodes, NodeStorage},
+    deserialize::ForestHeader,
+    session::Tally,
+};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+/// Poll `session` with `max_trees` until it is done, returning the prediction
+/// and the number of polls.
+fn run<P: Tally, const N: usize>(
+    session: &mut PredictSession<'_, '_, P, Branch, [Branch], N>,
+    max_trees: u32,
+) -> (P::Output, u32) {
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        if let Poll::Ready(prediction) = session.poll(max_trees) {
+            return (prediction, polls);
+        }
+    }
+}
+
+#[test]
+fn sessions_classify_like_predict() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let num_trees = forest.num_trees();
+
+    for row in &rows {
+        let expected = forest.predict(row);
+        for max_trees in [1, 2, num_trees, u32::MAX] {
+            let mut session = PredictSession::new(&forest, row);
+            let (prediction, polls) = run(&mut session, max_trees);
+            assert_eq!(prediction, expected);
+            assert_eq!(polls, num_trees.div_ceil(max_trees).max(1));
+            assert_eq!(session.trees_done(), num_trees);
+        }
+
+        let mut session = PredictSession::new_with::<3>(&forest, row);
+        assert_eq!(run(&mut session, 2).0, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn sessions_regress_like_predict() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let forest = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+
+    for row in rows.iter().take(200) {
+        let expected = forest.predict(row);
+        for max_trees in [1, 7, forest.num_trees()] {
+            let mut session = PredictSession::new(&forest, row);
+            assert_eq!(run(&mut session, max_trees).0, expected);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn sessions_stay_ready_once_done() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+
+    let mut session = PredictSession::new(&forest, &rows[0]);
+    // Polling no tree makes no progress
+    assert_eq!(session.poll(0), Poll::Pending);
+    assert_eq!(session.trees_done(), 0);
+    assert!(!session.is_done());
+
+    let (prediction, _) = run(&mut session, 2);
+    assert!(session.is_done());
+    for max_trees in [0, 1, u32::MAX] {
+        assert_eq!(session.poll(max_trees), Poll::Ready(prediction));
+        assert_eq!(session.try_poll(max_trees), Poll::Ready(Ok(prediction)));
+    }
+    assert_eq!(session.trees_done(), forest.num_trees());
+
+    Ok(())
+}
+
+#[test]
+#[should_panic]
+fn sessions_with_too_few_counters_panic() {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")
+            .unwrap();
+    let buffer = ClassificationProblem::serialize(&forest).unwrap();
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let _ = PredictSession::new_with::<2>(&forest, &[0.0; 4]);
+}
+
+/// Storage failing its reads while `failing` is set.
+struct FlakyStorage<'a> {
+    bytes: &'a [u8],
+    failing: &'a Cell<bool>,
+}
+
+impl NodeStorage for FlakyStorage<'_> {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        if self.failing.get() {
+            return Err(Error::Storage);
+        }
+        self.bytes.read(offset, bytes)
+    }
+}
+
+#[test]
+fn sessions_report_storage_errors_and_resume() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let header = ForestHeader::peek(&buffer).unwrap();
+    let bytes = &buffer[header.header_len..][..header.node_count * size_of::<Branch>()];
+
+    let failing = Cell::new(false);
+    let nodes = CachedNodes::<_, Branch, 1>::new(
+        FlakyStorage {
+            bytes,
+            failing: &failing,
+        },
+        forest.nodes().len(),
+    );
+    let cached = OptimizedForest::<Classification>::deserialize(&buffer)
+        .unwrap()
+        .with_node_access(&nodes)
+        .unwrap();
+
+    let row = &rows[rows.len() / 2];
+    let mut session = PredictSession::new(&cached, row);
+    assert_eq!(session.try_poll(1), Poll::Pending);
+    failing.set(true);
+    assert_eq!(session.try_poll(1), Poll::Ready(Err(Error::Storage)));
+    assert_eq!(session.trees_done(), 1);
+
+    failing.set(false);
+    let prediction = loop {
+        if let Poll::Ready(prediction) = session.try_poll(1) {
+            break prediction;
+        }
+    };
+    assert_eq!(prediction, Ok(forest.predict(row)));
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use aligned_vec::AVec;
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    Classification, OptimizedForest,
+    deserialize::BUFFER_ALIGN,
+    encryption::decrypt_in_place,
+    signature::{SIGNATURE_LEN, TRAILER_LEN, split_signature, verify_detached, verify_signature},
+};
+use forest_optimizer::encryption::{EncryptionKey, encrypt};
+use forest_optimizer::inspect::{inspect, read_model};
+use forest_optimizer::signature::{embed_signature, public_key, read_signing_key, sign};
+use predicates::str::contains;
+
+const IRIS_MODEL: &str = "./tests/test-forests/forest_iris_5.rforest";
+const KEY: &str = "./tests/test-keys/ed25519.pem";
+const OTHER_KEY: &str = "./tests/test-keys/ed25519_other.pem";
+/// Public key of `KEY`, as `openssl pkey -pubout` prints it
+const PUBLIC_KEY: &str = "1535ed20272dba22d5fb2065d26c40080d8549735337aaf6def8a78b2f2fdc9a";
+
+fn public_key_bytes() -> [u8; 32] {
+    let mut key = [0; 32];
+    for (byte, digits) in key.iter_mut().zip(PUBLIC_KEY.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap();
+    }
+    key
+}
+
+#[test]
+fn signed_forests_verify_and_deserialize() -> Result<()> {
+    let model = read_model(IRIS_MODEL)?;
+    let key = read_signing_key(KEY)?;
+    assert_eq!(public_key(&key), public_key_bytes());
+
+    let signature = sign(&model, &key)?;
+    verify_detached(&model, &signature, &public_key_bytes()).unwrap();
+
+    let signed = embed_signature(&model, signature);
+    assert_eq!(signed.len(), model.len() + TRAILER_LEN);
+    verify_signature(&signed, &public_key_bytes()).unwrap();
+    let (forest, embedded) = split_signature(&signed).unwrap();
+    assert_eq!(embedded, &signature);
+    assert_eq!(forest, &model[..]);
+    assert!(OptimizedForest::<Classification>::deserialize(forest).is_ok());
+
+    // Reported as the forest it signs
+    let info = inspect(&signed)?;
+    assert!(info.signed && info.is_valid());
+    assert_eq!(info.serialized_size, model.len());
+    assert!(sign(&signed, &key).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn corrupt_forests_and_wrong_keys_fail_to_verify() -> Result<()> {
+    let model = read_model(IRIS_MODEL)?;
+    let signature = sign(&model, &read_signing_key(KEY)?)?;
+    let signed = embed_signature(&model, signature);
+
+    for at in [0, model.len() / 2, model.len() - 1] {
+        let mut corrupt = signed.clone();
+        corrupt[at] ^= 1;
+        assert_eq!(
+            verify_signature(&corrupt, &public_key_bytes()),
+            Err(Error::BadSignature)
+        );
+    }
+    let mut corrupt = signed.clone();
+    corrupt[model.len()] ^= 1;
+    assert_eq!(
+        verify_signature(&corrupt, &public_key_bytes()),
+        Err(Error::BadSignature)
+    );
+
+    let other = public_key(&read_signing_key(OTHER_KEY)?);
+    assert_eq!(verify_signature(&signed, &other), Err(Error::BadSignature));
+    assert_eq!(
+        verify_detached(&model, &signature, &other),
+        Err(Error::BadSignature)
+    );
+    assert_eq!(
+        verify_detached(&model, &[0; SIGNATURE_LEN], &public_key_bytes()),
+        Err(Error::BadSignature)
+    );
+
+    assert_eq!(
+        verify_signature(&model, &public_key_bytes()),
+        Err(Error::Unsigned)
+    );
+    assert_eq!(
+        verify_signature(&signed[..TRAILER_LEN - 1], &public_key_bytes()),
+        Err(Error::Unsigned)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn encrypted_forests_are_verified_before_decryption() -> Result<()> {
+    let key = EncryptionKey([3; 32]);
+    let encrypted = encrypt(&read_model(IRIS_MODEL)?, &key)?;
+    let signature = sign(&encrypted, &read_signing_key(KEY)?)?;
+    let signed = embed_signature(&encrypted, signature);
+
+    verify_signature(&signed, &public_key_bytes()).unwrap();
+    let (forest, _) = split_signature(&signed).unwrap();
+    let mut decrypted = AVec::<u8>::from_slice(BUFFER_ALIGN, forest);
+    decrypt_in_place(&mut decrypted, &key.0).unwrap();
+    assert!(OptimizedForest::<Classification>::deserialize(&decrypted).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn sign_writes_detached_and_embedded_signatures() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+    std::fs::copy(IRIS_MODEL, &model)?;
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["sign", "-k", KEY, "-m"])
+        .arg(&model)
+        .assert()
+        .success()
+        .stdout(contains(format!("Public key: {PUBLIC_KEY}")));
+    let signature: [u8; SIGNATURE_LEN] = std::fs::read(dir.path().join("iris.sig"))?
+        .try_into()
+        .unwrap();
+    verify_detached(&read_model(&model)?, &signature, &public_key_bytes()).unwrap();
+
+    // Signing again needs --force
+    Command::cargo_bin("forest-optimizer")?
+        .args(["sign", "-k", KEY, "-m"])
+        .arg(&model)
+        .assert()
+        .failure()
+        .stderr(contains("--force"));
+
+    let signed = dir.path().join("iris.signed.rforest");
+    Command::cargo_bin("forest-optimizer")?
+        .args(["sign", "--embed", "-k", KEY, "-m"])
+        .arg(&model)
+        .assert()
+        .success();
+    verify_signature(&read_model(&signed)?, &public_key_bytes()).unwrap();
+    Command::cargo_bin("forest-optimizer")?
+        .arg("info")
+        .arg(&signed)
+        .assert()
+        .success()
+        .stdout(contains("Signature:       ed25519"));
+
+    // Neither signed twice, nor signed with something else than a key
+    Command::cargo_bin("forest-optimizer")?
+        .args(["sign", "--embed", "--force", "-k", KEY, "-m"])
+        .arg(&signed)
+        .args(["-o"])
+        .arg(dir.path().join("twice.rforest"))
+        .assert()
+        .failure()
+        .stderr(contains("already signed"));
+    Command::cargo_bin("forest-optimizer")?
+        .args(["sign", "-k", "./tests/test-forests/forest_iris_5.csv", "-m"])
+        .arg(&model)
+        .assert()
+        .failure()
+        .stderr(contains("not an Ed25519 private key"));
+
+    Ok(())
+}
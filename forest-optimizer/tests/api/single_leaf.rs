@@ -0,0 +1,96 @@
+use color_eyre::Result;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, CompactBranch, OptimizedForest, Predict, Regression,
+    deserialize::ForestHeader,
+};
+use forest_optimizer::analyze::analyze;
+use forest_optimizer::compact::Compact;
+use forest_optimizer::compare::compare;
+use forest_optimizer::conversion::verify_optimization;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+use crate::helpers::get_forest;
+
+/// Iris forest whose first and fourth trees are a single leaf, voting
+/// `versicolor` and `virginica` whatever the row.
+const SINGLE_LEAF_FOREST: &str = "./tests/test-forests/forest_iris_single_leaf.csv";
+
+#[test]
+fn single_leaf_trees_keep_later_trees_in_place() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(SINGLE_LEAF_FOREST)?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    assert_eq!(forest.num_trees(), 5);
+    assert_eq!(forest.trees().filter(|t| t.num_branches() == 0).count(), 2);
+
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    assert_eq!(optimized.num_trees(), 5);
+    verify_optimization(&forest, &optimized).unwrap();
+    assert!(compare(&forest, &optimized, &rows).is_identical());
+
+    // Each single leaf is a branch of its own
+    let branches = forest.nodes().iter().filter(|n| n.is_branch()).count();
+    let header = ForestHeader::peek(&buffer).unwrap();
+    assert_eq!(header.node_count, branches + 2);
+
+    for width in [PointerWidth::U16, PointerWidth::Relative] {
+        let buffer = ClassificationProblem::serialize_with(&forest, width)?;
+        let any = AnyOptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+        assert!(compare(&forest, &any, &rows).is_identical());
+    }
+
+    let compact = ClassificationProblem::serialize_compact(&forest)?;
+    let compact = OptimizedForest::<Classification, CompactBranch>::deserialize(&compact).unwrap();
+    assert!(compare(&forest, &compact, &rows).is_identical());
+
+    // Converting back keeps the predictions, the degenerate branches included
+    let host = Forest::from_optimized(&optimized, forest.problem().clone())?;
+    assert!(compare(&host, &forest, &rows).is_identical());
+
+    Ok(())
+}
+
+#[test]
+fn regression_forest_of_single_leaves_predicts_their_mean() -> Result<()> {
+    let definition = "# { \"problem_type\": \"regression\" }\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        0,0,NA,0,-1,1.5,1,1\n\
+        2,3,\"x\",0.5,-3,2,2,1\n\
+        0,0,NA,0,-1,3,2,2\n\
+        0,0,NA,0,-1,5,2,3\n\
+        0,0,NA,0,-1,4.5,3,1\n";
+    let serialized =
+        SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())?;
+    let forest = Forest::from_serialized(serialized)?;
+
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+    verify_optimization(&forest, &optimized).unwrap();
+    assert_eq!(optimized.predict(&[0.0]), 3.0);
+    assert_eq!(optimized.predict(&[1.0]), 11.0 / 3.0);
+
+    let rows = [vec![0.0], vec![0.5], vec![1.0]];
+    assert!(compare(&forest, &optimized, &rows).is_identical());
+
+    Ok(())
+}
+
+#[test]
+fn single_leaf_trees_are_analyzed() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(SINGLE_LEAF_FOREST)?;
+    let analysis = analyze(&forest)?;
+
+    let branches = forest.nodes().iter().filter(|n| n.is_branch()).count();
+    assert_eq!(analysis.branches, branches);
+    assert_eq!(analysis.optimized_nodes, branches + 2);
+
+    Ok(())
+}
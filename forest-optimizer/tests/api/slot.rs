@@ -0,0 +1,139 @@
+use aligned_vec::AVec;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    Classification, OptimizedForest, Predict, deserialize::BUFFER_ALIGN, slot::ModelSlot,
+};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::problem_type::Classification as ClassificationProblem;
+use forest_optimizer::serialized_forest::SerializedClassificationNode;
+use forest_optimizer::signature::{embed_signature, public_key, read_signing_key, sign};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+const IRIS_MODEL: &str = "./tests/test-forests/forest_iris_5.rforest";
+const SINGLE_LEAF_FOREST: &str = "./tests/test-forests/forest_iris_single_leaf.csv";
+
+/// Zeroed region of RAM aligned for deserialization
+fn region(len: usize) -> AVec<u8> {
+    let mut region = AVec::with_capacity(BUFFER_ALIGN, len);
+    region.resize(len, 0);
+    region
+}
+
+/// Write `model` to the region the next model goes to, and stage it
+fn receive(slot: &mut ModelSlot<Classification>, model: &[u8]) -> Result<usize, Error> {
+    let region = slot.inactive_region();
+    slot.region_mut(region)?[..model.len()].copy_from_slice(model);
+    slot.stage(region, model.len())?;
+    Ok(region)
+}
+
+#[test]
+fn committed_models_change_predictions() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(SINGLE_LEAF_FOREST)?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+    let (old, new) = (
+        read_model(IRIS_MODEL)?,
+        ClassificationProblem::serialize(&forest)?,
+    );
+    let predict = |model: &OptimizedForest<Classification>| {
+        rows.iter()
+            .map(|row| model.predict(row))
+            .collect::<Vec<_>>()
+    };
+    let old_predictions = predict(&OptimizedForest::deserialize(&old).unwrap());
+    let new_predictions = predict(&OptimizedForest::deserialize(&new).unwrap());
+    assert_ne!(old_predictions, new_predictions);
+
+    let (mut first, mut second) = (region(4096), region(4096));
+    let mut slot = ModelSlot::<Classification>::new(&mut first, &mut second);
+    assert!(slot.active().is_none());
+    assert!(!slot.commit());
+
+    assert_eq!(receive(&mut slot, &old), Ok(0));
+    assert!(slot.active().is_none());
+    assert!(slot.commit());
+    assert_eq!(predict(&slot.active().unwrap()), old_predictions);
+
+    // The next model goes to the other region, and takes over on commit
+    assert_eq!(receive(&mut slot, &new), Ok(1));
+    assert_eq!(predict(&slot.active().unwrap()), old_predictions);
+    assert!(slot.commit());
+    assert_eq!(slot.active_region(), Some(1));
+    assert_eq!(predict(&slot.active().unwrap()), new_predictions);
+
+    // And back
+    assert_eq!(receive(&mut slot, &old), Ok(0));
+    assert!(slot.commit());
+    assert_eq!(predict(&slot.active().unwrap()), old_predictions);
+
+    Ok(())
+}
+
+#[test]
+fn failed_stages_keep_the_active_model() -> Result<()> {
+    let model = read_model(IRIS_MODEL)?;
+    let row = [5.1, 3.5, 1.4, 0.2];
+    let (mut first, mut second) = (region(4096), region(4096));
+    let mut slot = ModelSlot::<Classification>::new(&mut first, &mut second);
+    receive(&mut slot, &model).unwrap();
+    slot.commit();
+    let class = slot.active().unwrap().predict(&row);
+
+    // Corrupt, truncated and too long models are never staged
+    let mut corrupt = model.to_vec();
+    let at = corrupt.len() - 20;
+    corrupt[at..].fill(0xff);
+    assert_eq!(receive(&mut slot, &corrupt), Err(Error::MalformedForest));
+    assert!(!slot.commit());
+    assert!(receive(&mut slot, &model[..model.len() / 2]).is_err());
+    assert_eq!(
+        slot.stage(1, 5000),
+        Err(Error::BufferTooSmall { needed: 5000 })
+    );
+    assert!(!slot.commit());
+    assert_eq!(slot.active_region(), Some(0));
+    assert_eq!(slot.active().unwrap().predict(&row), class);
+
+    // The active region cannot be written, nor staged again
+    assert_eq!(slot.region_mut(0).err(), Some(Error::RegionInUse));
+    assert_eq!(slot.stage(0, model.len()), Err(Error::RegionInUse));
+
+    // Writing to a staged region unstages it
+    receive(&mut slot, &model).unwrap();
+    slot.region_mut(1)?[0] ^= 1;
+    assert!(!slot.commit());
+
+    Ok(())
+}
+
+#[test]
+fn slots_check_fingerprints_and_signatures() -> Result<()> {
+    let model = read_model(IRIS_MODEL)?;
+    let fingerprint = OptimizedForest::<Classification>::deserialize(&model)
+        .unwrap()
+        .feature_fingerprint();
+    let key = read_signing_key("./tests/test-keys/ed25519.pem")?;
+    let signed = embed_signature(&model, sign(&model, &key)?);
+
+    let (mut first, mut second) = (region(4096), region(4096));
+    let mut slot =
+        ModelSlot::<Classification>::new(&mut first, &mut second).with_fingerprint(fingerprint ^ 1);
+    assert_eq!(receive(&mut slot, &model), Err(Error::WrongFingerprint));
+
+    let mut slot = ModelSlot::<Classification>::new(&mut first, &mut second)
+        .with_fingerprint(fingerprint)
+        .with_public_key(public_key(&key));
+    assert_eq!(receive(&mut slot, &model), Err(Error::Unsigned));
+    let mut tampered = signed.to_vec();
+    tampered[100] ^= 1;
+    assert_eq!(receive(&mut slot, &tampered), Err(Error::BadSignature));
+    assert_eq!(receive(&mut slot, &signed), Ok(0));
+    assert!(slot.commit());
+    assert_eq!(slot.active().unwrap().num_trees(), 5);
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, OptimizedForest, Predict, ProblemType, Regression,
+    soa::SoAForest,
+};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::inspect::read_model;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+
+use crate::helpers::get_forest;
+
+/// Check that a fixture predicts the same in both layouts, and that the two
+/// serializations are told apart.
+fn assert_equivalent<P: ProblemType>(model: &str, features: &[&str], data: &str) -> Result<()>
+where
+    P::Output: PartialEq + std::fmt::Debug,
+    for<'a> OptimizedForest<'a, P>: Predict<ProblemType = P>,
+    for<'a> SoAForest<'a, P>: Predict<ProblemType = P>,
+{
+    let buffer = read_model(model)?;
+    let aos = OptimizedForest::<P>::deserialize(&buffer).unwrap();
+    let bytes = aos.to_soa_bytes();
+    let soa = SoAForest::<P>::deserialize(&bytes).unwrap();
+
+    assert_eq!(soa.node_count(), aos.nodes().len());
+    assert_eq!(soa.num_trees(), aos.num_trees());
+    assert_eq!(soa.to_bytes().as_slice(), bytes.as_slice());
+
+    for row in read_mapped_rows(data, features)? {
+        assert_eq!(soa.predict_counting(&row), aos.predict_counting(&row));
+    }
+
+    assert!(matches!(
+        SoAForest::<P>::deserialize(&buffer),
+        Err(Error::WrongLayout)
+    ));
+    assert!(AnyOptimizedForest::<P>::deserialize(&bytes).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn soa_classification_predicts_like_aos() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    assert_equivalent::<Classification>(
+        "./tests/test-forests/forest_iris_5.rforest",
+        &forest.features_by_index(),
+        "./tests/test-data/iris.csv",
+    )
+}
+
+#[test]
+fn soa_regression_predicts_like_aos() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    assert_equivalent::<Regression>(
+        "./tests/test-forests/airfoil_100_200.rforest",
+        &forest.features_by_index(),
+        "./tests/test-data/airfoil.csv",
+    )
+}
@@ -0,0 +1,138 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, CompactBranch, OptimizedForest, Predict, Regression, SplitRule,
+    deserialize::ForestHeader, quickscorer::QuickScorer,
+};
+use forest_optimizer::compact::Compact;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::inspect::{inspect, read_model};
+use forest_optimizer::problem_type::Regression as RegressionProblem;
+use forest_optimizer::serialized_forest::{SerializedForest, SerializedRegressionNode};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+/// A tree splitting `x` at 0.5, predicting 1 on the left and 2 on the
+/// right, with `header` declared after the problem type.
+fn definition(header: &str) -> String {
+    format!(
+        "# {{ \"problem_type\": \"regression\"{header} }}\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        2,3,\"x\",0.5,-3,1.5,1,1\n\
+        0,0,NA,0,-1,1,1,2\n\
+        0,0,NA,0,-1,2,1,3\n"
+    )
+}
+
+fn read_forest(header: &str) -> Result<Forest<RegressionProblem>> {
+    let definition = definition(header);
+    let serialized =
+        SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())?;
+    Forest::from_serialized(serialized)
+}
+
+/// Every way of predicting with `forest` on a feature equal to the
+/// threshold: they all go left under `<=`, and right under `<`.
+fn assert_threshold_goes(forest: &Forest<RegressionProblem>, expected: f32) -> Result<()> {
+    let row = [0.5];
+    assert_eq!(forest.predict(&row), expected);
+    // Either side of the threshold is unaffected
+    assert_eq!(forest.predict(&[0.25]), 1.0);
+    assert_eq!(forest.predict(&[0.75]), 2.0);
+
+    let buffer = RegressionProblem::serialize(forest)?;
+    let optimized = OptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+    assert_eq!(optimized.split_rule(), forest.split_rule());
+    assert_eq!(optimized.predict(&row), expected);
+    let scorer = QuickScorer::new(&optimized, 64).unwrap();
+    assert_eq!(scorer.predict_qs(&row), expected);
+
+    for width in [PointerWidth::U16, PointerWidth::Relative] {
+        let buffer = RegressionProblem::serialize_with(forest, width)?;
+        let any = AnyOptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+        assert_eq!(any.predict(&row), expected, "{width:?}");
+    }
+
+    let compact = RegressionProblem::serialize_compact(forest)?;
+    let compact = OptimizedForest::<Regression, CompactBranch>::deserialize(&compact).unwrap();
+    assert_eq!(compact.predict(&row), expected);
+
+    #[cfg(feature = "soa")]
+    {
+        use embedded_rforest::forest::soa::SoAForest;
+
+        let soa = optimized.to_soa_bytes();
+        let soa = SoAForest::<Regression>::deserialize(&soa).unwrap();
+        assert_eq!(soa.predict(&row), expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn threshold_goes_left_by_default() -> Result<()> {
+    let forest = read_forest("")?;
+    assert_eq!(forest.split_rule(), SplitRule::LessOrEqual);
+    assert_threshold_goes(&forest, 1.0)?;
+
+    let forest = read_forest(", \"split_rule\": \"<=\"")?;
+    assert_threshold_goes(&forest, 1.0)
+}
+
+#[test]
+fn threshold_goes_right_when_declared_less_than() -> Result<()> {
+    let forest = read_forest(", \"split_rule\": \"<\"")?;
+    assert_eq!(forest.split_rule(), SplitRule::Less);
+    assert_threshold_goes(&forest, 2.0)?;
+
+    // Subsets and saved forests keep it
+    assert_eq!(forest.subset(&[0])?.split_rule(), SplitRule::Less);
+    let dir = tempfile::tempdir()?;
+    let saved = dir.path().join("forest.bin");
+    forest.save(&saved)?;
+    assert_threshold_goes(&Forest::<RegressionProblem>::load(&saved)?, 2.0)?;
+
+    let buffer = RegressionProblem::serialize(&forest)?;
+    let info = inspect(&buffer)?;
+    assert_eq!(info.split_rule, "<");
+    assert!(info.to_string().contains("Split rule:      <"));
+
+    // Written back to CSV, the header declares it
+    let mut csv = Vec::new();
+    SerializedForest::<SerializedRegressionNode>::from_forest(&forest).to_csv(&mut csv)?;
+    let csv = String::from_utf8(csv)?;
+    assert!(
+        csv.lines()
+            .next()
+            .unwrap()
+            .contains("\"split_rule\": \"<\"")
+    );
+    let serialized = SerializedForest::<SerializedRegressionNode>::from_reader(csv.as_bytes())?;
+    assert_eq!(serialized.split_rule(), SplitRule::Less);
+
+    Ok(())
+}
+
+#[test]
+fn invalid_split_rule_is_rejected() {
+    let definition = definition(", \"split_rule\": \">\"");
+    let err = SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())
+        .unwrap_err();
+    assert!(format!("{err:?}").contains("split_rule"), "{err:?}");
+}
+
+#[test]
+fn existing_forests_decode_as_less_or_equal() -> Result<()> {
+    let buffer = read_model("./tests/test-forests/forest_iris_5.rforest")?;
+    let header = ForestHeader::peek(&buffer).unwrap();
+    assert_eq!(header.split_rule, SplitRule::LessOrEqual);
+
+    // Bits of the extra flags this version does not know are rejected
+    let mut buffer = RegressionProblem::serialize(&read_forest("")?)?;
+    buffer[11] |= 1 << 2;
+    assert!(matches!(
+        ForestHeader::peek(&buffer),
+        Err(Error::MalformedForest)
+    ));
+
+    Ok(())
+}
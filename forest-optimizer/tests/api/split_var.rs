@@ -0,0 +1,197 @@
+use assert_cmd::Command;
+use color_eyre::Result;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::problem_type::Classification;
+use forest_optimizer::serialized_forest::{
+    IndexOrder, ReadOptions, SerializedClassificationNode, SerializedForest,
+};
+use forest_optimizer::write_forest::WriteForest;
+use predicates::str::contains;
+
+use crate::helpers::get_forest;
+
+const IRIS: &str = "./tests/test-forests/forest_iris_5.csv";
+/// [`IRIS`] exported with `getTree(..., labelVar = FALSE)`
+const IRIS_INDICES: &str = "./tests/test-forests/forest_iris_5_indices.csv";
+const IRIS_COLUMNS: [&str; 4] = ["Sepal.Length", "Sepal.Width", "Petal.Length", "Petal.Width"];
+
+fn columns() -> Vec<String> {
+    IRIS_COLUMNS.map(String::from).to_vec()
+}
+
+fn read(definition: &str, options: &ReadOptions) -> Result<Forest<Classification>> {
+    let serialized = SerializedForest::<SerializedClassificationNode>::from_reader_with(
+        definition.as_bytes(),
+        options,
+    )?;
+    Forest::from_serialized(options.prepare(serialized)?)
+}
+
+/// The definition of `forest`, and its serialized form
+fn written(forest: &Forest<Classification>) -> Result<(String, Vec<u8>)> {
+    let mut csv = Vec::new();
+    SerializedForest::<SerializedClassificationNode>::from_forest(forest).to_csv(&mut csv)?;
+    Ok((
+        String::from_utf8(csv)?,
+        Classification::serialize(forest)?.to_vec(),
+    ))
+}
+
+#[test]
+fn indices_resolve_to_the_forest_of_names() -> Result<()> {
+    let named = get_forest::<SerializedClassificationNode>(IRIS)?;
+    let definition = std::fs::read_to_string(IRIS_INDICES)?;
+
+    let options = ReadOptions {
+        split_var_as_index: true,
+        feature_names: Some(columns()),
+        ..ReadOptions::default()
+    };
+    let forest = read(&definition, &options)?;
+    assert_eq!(forest.features_by_index(), named.features_by_index());
+    assert_eq!(written(&forest)?, written(&named)?);
+
+    // Declared by the header instead
+    let hinted = definition.replacen(
+        "\"classification\"",
+        "\"classification\", \"split_var\": \"index\", \"feature_names\": \
+         [\"Sepal.Length\", \"Sepal.Width\", \"Petal.Length\", \"Petal.Width\"]",
+        1,
+    );
+    let forest = read(&hinted, &ReadOptions::default())?;
+    assert_eq!(written(&forest)?, written(&named)?);
+
+    // Named by the feature order, which also pins the indices
+    let options = ReadOptions {
+        order: IndexOrder {
+            features: Some(columns()),
+            ..IndexOrder::default()
+        },
+        split_var_as_index: true,
+        ..ReadOptions::default()
+    };
+    let forest = read(&definition, &options)?;
+    let by_name = ReadOptions {
+        split_var_as_index: false,
+        ..options.clone()
+    };
+    let named = read(&std::fs::read_to_string(IRIS)?, &by_name)?;
+    assert_eq!(forest.features_by_index(), IRIS_COLUMNS);
+    assert_eq!(written(&forest)?, written(&named)?);
+
+    Ok(())
+}
+
+#[test]
+fn indices_without_confirmation_are_names() -> Result<()> {
+    let forest = read(
+        &std::fs::read_to_string(IRIS_INDICES)?,
+        &ReadOptions::default(),
+    )?;
+    assert_eq!(forest.features_by_index(), ["3", "4", "1", "2"]);
+    Ok(())
+}
+
+#[test]
+fn invalid_indices_are_rejected() -> Result<()> {
+    let definition = std::fs::read_to_string(IRIS_INDICES)?;
+    let as_index = |feature_names: Option<Vec<String>>| ReadOptions {
+        split_var_as_index: true,
+        feature_names,
+        ..ReadOptions::default()
+    };
+    let error = |definition: &str, options: &ReadOptions| {
+        format!("{:?}", read(definition, options).unwrap_err())
+    };
+
+    let err = error(&definition, &as_index(None));
+    assert!(err.contains("no feature names"), "{err}");
+
+    let err = error(&definition, &as_index(Some(columns()[..3].to_vec())));
+    assert!(
+        err.contains("Line 6 splits on column 4, but there are 3 feature names"),
+        "{err}"
+    );
+
+    let err = error(&definition, &as_index(Some(vec!["a".into(); 4])));
+    assert!(err.contains("The feature names list 'a' twice"), "{err}");
+
+    let options = ReadOptions {
+        feature_names: Some(columns()),
+        ..ReadOptions::default()
+    };
+    let err = error(&definition, &options);
+    assert!(err.contains("--split-var-as-index"), "{err}");
+
+    let err = error(
+        "# { \"problem_type\": \"classification\", \"split_var\": \"column\" }\n",
+        &ReadOptions::default(),
+    );
+    assert!(err.contains("invalid \"split_var\""), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn names_and_indices_cannot_be_mixed() -> Result<()> {
+    let definition = std::fs::read_to_string(IRIS_INDICES)?;
+    let mixed = definition.replacen("4,5,3,4.95", "4,5,\"Petal.Length\",4.95", 1);
+
+    let err = format!("{:?}", read(&mixed, &ReadOptions::default()).unwrap_err());
+    assert!(
+        err.contains("Line 3 splits on column 3, but line 5 on 'Petal.Length'"),
+        "{err}"
+    );
+
+    let options = ReadOptions {
+        split_var_as_index: true,
+        feature_names: Some(columns()),
+        ..ReadOptions::default()
+    };
+    let err = format!("{:?}", read(&mixed, &options).unwrap_err());
+    assert!(
+        err.contains("Line 5 splits on 'Petal.Length', but split vars are column indices"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn convert_resolves_indices() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let named = dir.path().join("named.rforest");
+    let indexed = dir.path().join("indexed.rforest");
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "-i", IRIS, "-o"])
+        .arg(&named)
+        .assert()
+        .success();
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--split-var-as-index", "-i", IRIS_INDICES, "-o"])
+        .arg(&indexed)
+        .args(["--feature-names", &IRIS_COLUMNS.join(",")])
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&named)?, std::fs::read(&indexed)?);
+    assert_eq!(
+        std::fs::read(dir.path().join("named.rforest.meta.json"))?,
+        std::fs::read(dir.path().join("indexed.rforest.meta.json"))?
+    );
+
+    Command::cargo_bin("forest-optimizer")?
+        .args([
+            "convert",
+            "--split-var-as-index",
+            "--force",
+            "-i",
+            IRIS_INDICES,
+            "-o",
+        ])
+        .arg(&indexed)
+        .assert()
+        .failure()
+        .stderr(contains("no feature names"));
+
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use color_eyre::Result;
+use forest_optimizer::analyze::analyze;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::forest::stats::{
+    HistogramBin, LeafBin, LeafDistribution, LeafError, LeafPredictions, TreeShape,
+};
+use forest_optimizer::problem_type::Classification;
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedRegressionNode,
+};
+
+use crate::helpers::get_forest;
+
+const COLUMNS: &str = "\"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n";
+
+fn bin(value: usize, trees: usize) -> HistogramBin {
+    HistogramBin { value, trees }
+}
+
+/// Two stumps on `x`, around a tree splitting on `x` then `y`.
+fn classification_forest() -> Result<Forest<Classification>> {
+    let rows = "\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n\
+        2,3,\"x\",0.5,1,NA,2,1\n\
+        0,0,NA,0,-1,\"a\",2,2\n\
+        4,5,\"y\",0.5,1,NA,2,3\n\
+        0,0,NA,0,-1,\"b\",2,4\n\
+        0,0,NA,0,-1,\"c\",2,5\n\
+        2,3,\"x\",1.5,1,NA,3,1\n\
+        0,0,NA,0,-1,\"c\",3,2\n\
+        0,0,NA,0,-1,\"a\",3,3\n";
+    let definition = format!("# {{ \"problem_type\": \"classification\" }}\n{COLUMNS}{rows}");
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())?;
+    Forest::from_serialized(serialized)
+}
+
+#[test]
+fn stats_of_a_classification_forest() -> Result<()> {
+    let forest = classification_forest()?;
+    let stats = forest.stats();
+
+    let shape = |index, nodes, depth| TreeShape {
+        index,
+        nodes,
+        branches: nodes / 2,
+        leaves: nodes / 2 + 1,
+        depth,
+    };
+    assert_eq!(
+        stats.trees,
+        [shape(0, 3, 1), shape(1, 5, 2), shape(2, 3, 1)]
+    );
+    assert_eq!(stats.depth_histogram, [bin(1, 2), bin(2, 1)]);
+    assert_eq!(stats.size_histogram, [bin(3, 2), bin(5, 1)]);
+    assert_eq!(
+        (stats.nodes(), stats.branches(), stats.leaves()),
+        (11, 4, 7)
+    );
+    assert_eq!(stats.max_depth(), 2);
+
+    let usage = stats
+        .feature_usage
+        .iter()
+        .map(|f| (f.name.as_str(), f.branches, f.trees, f.min_depth))
+        .collect::<Vec<_>>();
+    assert_eq!(usage, [("x", 3, 3, Some(0)), ("y", 1, 1, Some(1))]);
+    assert_eq!(stats.feature_usage[1].tree_fraction, 1.0 / 3.0);
+
+    let LeafPredictions::Classes(classes) = &stats.leaf_predictions else {
+        panic!("expected leaves per class");
+    };
+    let leaves = classes
+        .iter()
+        .map(|c| (c.name.as_str(), c.leaves))
+        .collect::<Vec<_>>();
+    assert_eq!(leaves, [("a", 3), ("b", 2), ("c", 2)]);
+
+    let summary = stats.to_string();
+    assert!(summary.contains("Trees: 3 | Nodes: 11 | Branches: 4, leaves: 7 | Max depth: 2"));
+    let json = serde_json::to_value(&stats)?;
+    assert_eq!(json["leaf_predictions"]["classes"][2]["leaves"], 2);
+
+    Ok(())
+}
+
+#[test]
+fn stats_of_a_regression_forest() -> Result<()> {
+    let rows = "\
+        0,0,NA,0,-1,1.5,1,1\n\
+        2,3,\"x\",0.5,-3,2,2,1\n\
+        0,0,NA,0,-1,3,2,2\n\
+        0,0,NA,0,-1,5,2,3\n\
+        0,0,NA,0,-1,4.5,3,1\n";
+    let definition = format!("# {{ \"problem_type\": \"regression\" }}\n{COLUMNS}{rows}");
+    let serialized =
+        SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())?;
+    let stats = Forest::from_serialized(serialized)?.stats();
+
+    assert_eq!(stats.depth_histogram, [bin(0, 2), bin(1, 1)]);
+    assert_eq!(stats.size_histogram, [bin(1, 2), bin(3, 1)]);
+    assert_eq!(
+        stats.leaf_predictions,
+        LeafPredictions::Values {
+            min: 1.5,
+            max: 5.0,
+            mean: 3.5
+        }
+    );
+    assert!(
+        stats
+            .to_string()
+            .contains("Leaf values: 1.5 to 5, 3.5000 on average")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn stats_add_up_on_the_fixtures() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let stats = forest.stats();
+
+    assert_eq!(stats.trees.len(), forest.num_trees());
+    assert_eq!(stats.nodes(), forest.nodes().len());
+    let histogram_trees = |bins: &[HistogramBin]| bins.iter().map(|b| b.trees).sum::<usize>();
+    assert_eq!(histogram_trees(&stats.depth_histogram), forest.num_trees());
+    assert_eq!(histogram_trees(&stats.size_histogram), forest.num_trees());
+    assert_eq!(
+        stats
+            .feature_usage
+            .iter()
+            .map(|f| f.branches)
+            .sum::<usize>(),
+        stats.branches()
+    );
+    let LeafPredictions::Classes(classes) = &stats.leaf_predictions else {
+        panic!("expected leaves per class");
+    };
+    assert_eq!(classes.len(), forest.num_targets());
+    assert_eq!(
+        classes.iter().map(|c| c.leaves).sum::<usize>(),
+        stats.leaves()
+    );
+
+    // The analysis reports the same counts
+    let analysis = analyze(&forest)?;
+    assert_eq!(analysis.nodes, stats.nodes());
+    assert_eq!(analysis.branches, stats.branches());
+    assert_eq!(analysis.feature_usage, stats.feature_usage);
+    assert_eq!(analysis.tree_summary.max_depth, stats.max_depth());
+
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let stats = forest.stats();
+    assert_eq!(stats.nodes(), forest.nodes().len());
+    let LeafPredictions::Values { min, max, mean } = stats.leaf_predictions else {
+        panic!("expected leaf values");
+    };
+    assert!(min <= max && f64::from(min) <= mean && mean <= f64::from(max));
+
+    // The distribution covers every leaf, once per distinct value in the
+    // leaf table
+    let distribution = stats.leaf_distribution.as_ref().unwrap();
+    let analysis = analyze(&forest)?;
+    assert_eq!(distribution.leaves, stats.leaves());
+    assert_eq!(
+        distribution
+            .histogram
+            .iter()
+            .map(|b| b.leaves)
+            .sum::<usize>(),
+        stats.leaves()
+    );
+    assert_eq!(distribution.distinct, analysis.leaf_table.unwrap().entries);
+    assert_eq!(analysis.leaf_distribution.as_ref(), Some(distribution));
+    assert!(analysis.to_string().contains("--- Leaf values ---"));
+
+    Ok(())
+}
+
+#[test]
+fn leaf_distribution_bins_hand_chosen_values() {
+    let bin = |start, end, leaves| LeafBin { start, end, leaves };
+    let distribution = LeafDistribution::new(&[0.0, 1.0, 2.0, 3.0, 4.0, 4.0, 10.0], 5);
+
+    assert_eq!((distribution.leaves, distribution.distinct), (7, 6));
+    assert_eq!((distribution.min, distribution.max), (0.0, 10.0));
+    assert_eq!(distribution.mean, 24.0 / 7.0);
+    // Values on a boundary go to the bin above it, the largest to the last
+    assert_eq!(
+        distribution.histogram,
+        [
+            bin(0.0, 2.0, 2),
+            bin(2.0, 4.0, 2),
+            bin(4.0, 6.0, 2),
+            bin(6.0, 8.0, 0),
+            bin(8.0, 10.0, 1),
+        ]
+    );
+
+    // Equal leaves all fall in the first bin, and round to themselves
+    let constant = LeafDistribution::new(&[1.5, 1.5], 3);
+    assert_eq!(
+        constant
+            .histogram
+            .iter()
+            .map(|b| b.leaves)
+            .collect::<Vec<_>>(),
+        [2, 0, 0]
+    );
+    assert_eq!(constant.u16_error, LeafError::default());
+}
+
+#[test]
+fn leaf_quantization_errors_are_exact() {
+    // Levels of one unit from 0 to 65535: 0.25 rounds down to 0 and 100.5
+    // up to 101, the rest are levels already
+    let distribution = LeafDistribution::new(&[0.0, 0.25, 100.5, 65535.0], 1);
+    assert_eq!(
+        distribution.u16_error,
+        LeafError {
+            max: 0.5,
+            rms: ((0.25f64 * 0.25 + 0.5 * 0.5) / 4.0).sqrt(),
+        }
+    );
+
+    // Half precision numbers are 2 apart above 2048, so 2049 rounds to even
+    let distribution = LeafDistribution::new(&[1.0, 2049.0], 1);
+    assert_eq!(
+        distribution.f16_error,
+        LeafError {
+            max: 1.0,
+            rms: 0.5f64.sqrt(),
+        }
+    );
+}
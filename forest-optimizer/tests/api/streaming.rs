@@ -0,0 +1,97 @@
+use color_eyre::Result;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::serialized_forest::{
+    ReadOptions, SerializedClassificationNode, SerializedNode, SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::{get_forest, reversed_rows};
+
+/// Check that the definition at `path` converts to the same forest whether
+/// it is read one tree at a time or all at once.
+fn assert_streams<N>(path: &str) -> Result<()>
+where
+    N: SerializedNode,
+    N::ProblemType: WriteForest,
+{
+    let streamed = Forest::from_csv_streaming::<N>(path)?;
+    let forest = get_forest::<N>(path)?;
+
+    assert_eq!(streamed.num_trees(), forest.num_trees());
+    assert_eq!(streamed.nodes(), forest.nodes());
+    assert_eq!(
+        N::ProblemType::metadata(&streamed),
+        N::ProblemType::metadata(&forest)
+    );
+    Ok(())
+}
+
+#[test]
+fn streaming_matches_reading_all_rows() -> Result<()> {
+    assert_streams::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    assert_streams::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    assert_streams::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5_stats.csv")?;
+    assert_streams::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")
+}
+
+#[test]
+fn streaming_requires_trees_in_order() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let reversed = reversed_rows("./tests/test-forests/forest_iris_5.csv", dir.path())?;
+
+    let err = Forest::from_csv_streaming::<SerializedClassificationNode>(&reversed).unwrap_err();
+    assert!(
+        format!("{err}").contains("increasing tree order"),
+        "Unexpected error: {err}"
+    );
+    Ok(())
+}
+
+#[test]
+fn streaming_checks_each_tree() {
+    let definition = "\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        0,0,NA,0,-1,\"a\",1,1\n\
+        2,3,\"x\",0.5,1,NA,2,1\n\
+        0,0,NA,0,-1,NA,2,2\n\
+        0,0,NA,0,-1,\"b\",2,3\n";
+
+    let err = Forest::from_reader_streaming::<SerializedClassificationNode>(
+        definition.as_bytes(),
+        &ReadOptions::default(),
+    )
+    .unwrap_err();
+    assert!(
+        format!("{err}").contains("line 4, tree 2, node 2: Leaf has no prediction"),
+        "Unexpected error: {err}"
+    );
+}
+
+#[test]
+fn streaming_checks_header_counts() -> Result<()> {
+    let definition = "\
+        # { \"problem_type\": \"classification\", \"num_trees\": 2 }\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        0,0,NA,0,-1,\"a\",1,1\n";
+
+    let err = Forest::from_reader_streaming::<SerializedClassificationNode>(
+        definition.as_bytes(),
+        &ReadOptions::default(),
+    )
+    .unwrap_err();
+    assert!(
+        format!("{err}").contains("Header declares 2 trees, file contains 1"),
+        "Unexpected error: {err}"
+    );
+
+    let options = ReadOptions {
+        allow_count_mismatch: true,
+        ..ReadOptions::default()
+    };
+    let forest = Forest::from_reader_streaming::<SerializedClassificationNode>(
+        definition.as_bytes(),
+        &options,
+    )?;
+    assert_eq!(forest.num_trees(), 1);
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use color_eyre::Result;
+use embedded_rforest::forest::{Classification, OptimizedForest, Predict};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::Classification as ClassificationProblem;
+use forest_optimizer::serialized_forest::SerializedClassificationNode;
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+#[test]
+fn single_tree_subset_predicts_like_its_tree() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+
+    for index in [0, 417, forest.num_trees() - 1] {
+        let subset = forest.subset(&[index])?;
+        assert_eq!(subset.num_trees(), 1);
+        assert_eq!(subset.features(), forest.features());
+        assert_eq!(subset.targets(), forest.targets());
+
+        // It also optimizes and serializes like any other forest
+        let buffer = ClassificationProblem::serialize(&subset)?;
+        let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+
+        let tree = forest.trees().nth(index).unwrap();
+        let single = subset.trees().next().unwrap();
+        assert_eq!(single.num_nodes(), tree.num_nodes());
+        for row in &rows {
+            assert_eq!(single.predict(row), tree.predict(row));
+            assert_eq!(optimized.predict(row), tree.predict(row));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn full_subset_is_the_original_forest() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+
+    let subset = forest.subset(&(0..forest.num_trees()).collect::<Vec<_>>())?;
+    assert_eq!(subset.num_trees(), forest.num_trees());
+    assert_eq!(subset.nodes(), forest.nodes());
+
+    // Trees are kept in the order listed
+    let reversed = forest.subset(&[2, 1])?;
+    let trees = reversed
+        .trees()
+        .map(|tree| tree.to_nodes())
+        .collect::<Vec<_>>();
+    assert_eq!(trees[0], forest.trees().nth(2).unwrap().to_nodes());
+    assert_eq!(trees[1], forest.trees().nth(1).unwrap().to_nodes());
+
+    Ok(())
+}
+
+#[test]
+fn subsets_reject_bad_indices() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+
+    let out_of_range = forest.subset(&[1, 5]).unwrap_err();
+    assert!(out_of_range.to_string().contains("Tree 5 is out of range"));
+    let duplicate = forest.subset(&[3, 0, 3]).unwrap_err();
+    assert!(
+        duplicate
+            .to_string()
+            .contains("Tree 3 is listed more than once")
+    );
+    assert!(forest.subset(&[]).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use aligned_vec::AVec;
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::deserialize::{BUFFER_ALIGN, ForestHeader, SUPPORT};
+use embedded_rforest::forest::endian::swap_byte_order;
+use embedded_rforest::forest::{
+    Aggregation, AnyOptimizedForest, Classification, NodeLayout, OptimizedForest, Predict,
+};
+use embedded_rforest::ptr::{F32, U16};
+use forest_optimizer::compare::PredictLike;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::inspect::inspect;
+use forest_optimizer::problem_type::Classification as ClassificationProblem;
+use forest_optimizer::serialized_forest::SerializedClassificationNode;
+use forest_optimizer::write_forest::{NodeEncoding, PointerWidth, WriteForest};
+use predicates::str::contains;
+
+use crate::helpers::{FLAGS, classifier, get_forest, regression_forest, regression_stumps, stumps};
+
+/// Leaves of four [`stumps`]: on the left, 3 votes for class 0 and 1 for
+/// class 1, on the right 1 vote for class 1 and 3 for class 2
+const VOTES: [(u32, u32); 4] = [(0, 1), (0, 2), (1, 2), (0, 2)];
+
+/// Samples of the left then right leaf of each of the four stumps
+const SAMPLES: [U16; 8] = [
+    U16::new(10),
+    U16::new(7),
+    U16::new(20),
+    U16::new(5),
+    U16::new(3),
+    U16::new(40),
+    U16::new(u16::MAX),
+    U16::new(1),
+];
+
+#[test]
+fn support_is_zero_unless_recorded() {
+    let nodes = stumps(&VOTES);
+    let forest = classifier(&nodes, 3);
+    assert!(forest.support().is_empty());
+    assert_eq!(forest.predict_with_support(&[0.0]), (0, 0));
+    assert_eq!(forest.leaf_support(0), 0);
+
+    let bytes = forest.to_bytes();
+    assert_eq!(bytes[FLAGS] & SUPPORT, 0);
+    let header = ForestHeader::peek(&bytes).unwrap();
+    assert!(!header.support);
+    assert_eq!(header.support_len(), 0);
+}
+
+#[test]
+fn classification_support_counts_the_trees_of_the_winner() {
+    let nodes = stumps(&VOTES);
+    let forest = classifier(&nodes, 3).with_support(&SAMPLES).unwrap();
+    // Class 0 wins on the left, with the 10, 20 and 65535 samples of its
+    // trees but not the 3 of the tree voting for class 1
+    assert_eq!(
+        forest.predict_with_support(&[0.0]),
+        (0, 10 + 20 + u32::from(u16::MAX))
+    );
+    // Class 2 wins on the right, without the 7 samples of the first tree
+    assert_eq!(forest.predict_with_support(&[1.0]), (2, 5 + 40 + 1));
+    assert_eq!(forest.predict(&[1.0]), 2);
+
+    // Trees of an ordinal forest may all vote around their median: 0, 0, 1
+    // and 0 on the left have a median of 0, and 1, 2, 2 and 2 on the right
+    // of 2
+    let ordinal = forest.with_aggregation(Aggregation::OrdinalMedian).unwrap();
+    assert_eq!(ordinal.predict_with_support(&[1.0]), (2, 5 + 40 + 1));
+}
+
+#[test]
+fn regression_support_counts_every_tree() {
+    let leaves = [F32::new(1.0), F32::new(2.0), F32::new(4.0), F32::new(-3.0)];
+    let nodes = regression_stumps(2);
+    let support = [12, 4, 30, 2].map(U16::new);
+    let forest = regression_forest(&nodes, &leaves)
+        .with_support(&support)
+        .unwrap();
+
+    assert_eq!(forest.predict_with_support(&[0.0]), (2.5, 12 + 30));
+    assert_eq!(forest.predict_with_support(&[1.0]), (-0.5, 4 + 2));
+
+    let summed = forest
+        .with_aggregation(Aggregation::Sum { base: 1.0 })
+        .unwrap();
+    assert_eq!(summed.predict_with_support(&[0.0]), (6.0, 12 + 30));
+}
+
+#[test]
+fn regression_support_saturates() {
+    // 65538 leaves of 65535 samples sum past `u32::MAX`. Classification
+    // forests have too few trees to get there
+    let num_trees = 65538;
+    let nodes = regression_stumps(num_trees);
+    let leaves = vec![F32::new(0.0); nodes.len() * 2];
+    let support = vec![U16::new(u16::MAX); nodes.len() * 2];
+    let forest = regression_forest(&nodes, &leaves)
+        .with_support(&support)
+        .unwrap();
+    assert_eq!(forest.predict_with_support(&[0.0]), (0.0, u32::MAX));
+}
+
+#[test]
+fn support_follows_the_leaf_table() {
+    let nodes = stumps(&VOTES);
+    let forest = classifier(&nodes, 3).with_support(&SAMPLES).unwrap();
+    assert_eq!(forest.support(), SAMPLES);
+
+    // Two counts per branch, after the leaf table
+    let bytes = forest.to_bytes();
+    assert_ne!(bytes[FLAGS] & SUPPORT, 0);
+    assert_eq!(bytes.len(), classifier(&nodes, 3).to_bytes().len() + 8 * 2);
+    let header = ForestHeader::peek(&bytes).unwrap();
+    assert!(header.support);
+    assert_eq!(header.node_count, 4);
+    assert_eq!(header.serialized_len(), bytes.len());
+
+    let loaded = OptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.support(), SAMPLES);
+    assert_eq!(loaded.to_bytes(), bytes);
+    assert_eq!(loaded.predict_with_support(&[1.0]), (2, 46));
+    assert!(
+        inspect(&bytes)
+            .unwrap()
+            .to_string()
+            .contains("Leaf support:    recorded")
+    );
+
+    // The counts follow the byte order of the nodes
+    let mut swapped = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+    swap_byte_order(&mut swapped).unwrap();
+    let end = swapped.len();
+    assert_eq!(swapped[end - 2..], [0, 1]);
+    swap_byte_order(&mut swapped).unwrap();
+    assert_eq!(swapped[..], bytes[..]);
+
+    // A table without two counts per branch is malformed
+    assert_eq!(
+        classifier(&nodes, 3).with_support(&SAMPLES[..6]).err(),
+        Some(Error::MalformedForest)
+    );
+    let truncated = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes[..bytes.len() - 2]);
+    assert!(OptimizedForest::<Classification>::deserialize(&truncated).is_err());
+}
+
+const IRIS: &str = "./tests/test-forests/forest_iris_5.csv";
+/// `IRIS` with the sample count of every node
+const IRIS_STATS: &str = "./tests/test-forests/forest_iris_5_stats.csv";
+
+#[test]
+fn support_sums_the_sample_counts_of_the_csv() -> Result<()> {
+    let forest = get_forest::<SerializedClassificationNode>(IRIS_STATS)?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+
+    for width in [PointerWidth::U32, PointerWidth::U16, PointerWidth::Relative] {
+        let encoding = NodeEncoding {
+            width,
+            support: true,
+            ..Default::default()
+        };
+        let bytes = ClassificationProblem::serialize_encoded(&forest, &encoding)?;
+        assert!(ForestHeader::peek(&bytes).unwrap().support);
+        let expected = |row: &[f32]| {
+            let prediction = forest.predict_like(row);
+            let support = forest
+                .trees()
+                .zip(forest.predict_leaf_ids(row))
+                .filter(|(tree, _)| tree.predict(row) == prediction)
+                .map(|(_, leaf)| forest.nodes()[leaf].samples().unwrap())
+                .sum::<u32>();
+            (prediction, support)
+        };
+
+        let optimized = AnyOptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+        assert_eq!(
+            optimized.layout() == NodeLayout::Standard,
+            width == PointerWidth::U32
+        );
+        for row in &rows {
+            assert_eq!(optimized.predict_with_support(row), expected(row));
+        }
+    }
+
+    // Without the option, or without sample counts
+    let bytes = ClassificationProblem::serialize_encoded(&forest, &NodeEncoding::default())?;
+    assert!(!ForestHeader::peek(&bytes).unwrap().support);
+    assert_eq!(bytes, ClassificationProblem::serialize(&forest)?);
+    let forest = get_forest::<SerializedClassificationNode>(IRIS)?;
+    let encoding = NodeEncoding {
+        support: true,
+        ..Default::default()
+    };
+    let message = ClassificationProblem::serialize_encoded(&forest, &encoding)
+        .unwrap_err()
+        .to_string();
+    assert!(message.contains("has no sample count"), "{message}");
+
+    Ok(())
+}
+
+#[test]
+fn convert_needs_sample_counts_for_support() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("iris.rforest");
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--support", "-i", IRIS_STATS, "-o"])
+        .arg(&output)
+        .assert()
+        .success();
+    Command::cargo_bin("forest-optimizer")?
+        .arg("info")
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(contains("Leaf support:    recorded"));
+
+    // The forest already written is left as it was
+    let written = std::fs::read(&output)?;
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "--force", "--support", "-i", IRIS, "-o"])
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(contains("has no sample count"));
+    assert_eq!(std::fs::read(&output)?, written);
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::forest::{Classification, OptimizedForest};
+use forest_optimizer::{
+    dataset::read_mapped_rows,
+    inspect::read_model,
+    metadata::ForestMetadata,
+    test_vectors::sample,
+    write_forest::{write_classification, write_regression},
+};
+
+fn export(model: &Path, data: &str, n: &str, output: &Path, format: &str) {
+    Command::cargo_bin("forest-optimizer")
+        .unwrap()
+        .args(["export-test-vectors", "-m"])
+        .arg(model)
+        .args(["-d", data, "-n", n, "--seed", "7", "-f", format, "-o"])
+        .arg(output)
+        .assert()
+        .success();
+}
+
+/// Program asserting that the forest at `model` reproduces every vector of
+/// the Rust module at `vectors`.
+fn reproduction_program(model: &Path, vectors: &Path, problem_type: &str) -> Result<String> {
+    let mut program = String::new();
+    writeln!(program, "mod vectors {{ include!({:?}); }}", vectors)?;
+    writeln!(
+        program,
+        "use embedded_rforest::forest::{{OptimizedForest, Predict, {problem_type}}};"
+    )?;
+    writeln!(program, "fn main() {{")?;
+    writeln!(
+        program,
+        "    let buf = embedded_rforest::static_storage!({:?});",
+        model
+    )?;
+    writeln!(
+        program,
+        "    let forest = OptimizedForest::<{problem_type}>::deserialize(buf).unwrap();"
+    )?;
+    writeln!(
+        program,
+        "    for (features, expected) in vectors::TEST_VECTORS {{"
+    )?;
+    writeln!(
+        program,
+        "        assert_eq!(forest.predict(&features), expected);"
+    )?;
+    writeln!(program, "    }}")?;
+    writeln!(program, "}}")?;
+    Ok(program)
+}
+
+#[test]
+fn exported_rust_vectors_compile_and_are_reproduced() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let iris = dir.path().join("iris.rforest");
+    let airfoil = dir.path().join("airfoil.rforest");
+    write_classification("./tests/test-forests/forest_iris_5.csv", &iris)?;
+    write_regression("./tests/test-forests/airfoil_100_200.csv", &airfoil)?;
+
+    let cases = trybuild::TestCases::new();
+    for (model, data, problem_type) in [
+        (&iris, "./tests/test-data/iris.csv", "Classification"),
+        (&airfoil, "./tests/test-data/airfoil.csv", "Regression"),
+    ] {
+        let vectors = model.with_extension("rs");
+        export(model, data, "32", &vectors, "rust");
+
+        let program = model.with_extension("main.rs");
+        std::fs::write(
+            &program,
+            reproduction_program(model, &vectors, problem_type)?,
+        )?;
+        cases.pass(&program);
+    }
+    // Compiles and runs the programs
+    drop(cases);
+
+    Ok(())
+}
+
+#[test]
+fn sampling_is_deterministic_and_covers_every_class() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+    write_classification("./tests/test-forests/forest_iris_5.csv", &model)?;
+
+    let buffer = read_model(&model)?;
+    let forest = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let metadata = ForestMetadata::read(ForestMetadata::sidecar_path(&model))?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &metadata.features)?;
+
+    let vectors = sample(&forest, &metadata, &rows, 6, 7);
+    assert_eq!(vectors, sample(&forest, &metadata, &rows, 6, 7));
+    assert_eq!(vectors.vectors.len(), 6);
+    for class in 0..3 {
+        let count = vectors
+            .vectors
+            .iter()
+            .filter(|v| v.expected == class)
+            .count();
+        assert_eq!(count, 2, "class {class}");
+    }
+
+    // A different seed picks different rows
+    assert_ne!(vectors, sample(&forest, &metadata, &rows, 6, 8));
+
+    Ok(())
+}
+
+#[test]
+fn csv_and_json_vectors_hold_the_same_rows() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let model = dir.path().join("iris.rforest");
+    write_classification("./tests/test-forests/forest_iris_5.csv", &model)?;
+
+    let (csv, json) = (dir.path().join("v.csv"), dir.path().join("v.json"));
+    export(&model, "./tests/test-data/iris.csv", "10", &csv, "csv");
+    export(&model, "./tests/test-data/iris.csv", "10", &json, "json");
+
+    let mut reader = csv::Reader::from_path(&csv)?;
+    assert_eq!(reader.headers()?.iter().next_back(), Some("expected"));
+    let csv_rows = reader
+        .records()
+        .map(|r| {
+            Ok(r?
+                .iter()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json)?)?;
+    let json_rows = json["vectors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| {
+            let mut row = serde_json::from_value::<Vec<f32>>(v["features"].clone()).unwrap();
+            row.push(v["expected"].as_f64().unwrap() as f32);
+            row
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(csv_rows.len(), 10);
+    assert_eq!(csv_rows, json_rows);
+
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use color_eyre::Result;
+use forest_optimizer::forest::Forest;
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedNode, SerializedRegressionNode,
+};
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+fn to_csv<N: SerializedNode>(serialized: &SerializedForest<N>) -> Result<String> {
+    let mut csv = Vec::new();
+    serialized.to_csv(&mut csv)?;
+    Ok(String::from_utf8(csv)?)
+}
+
+/// Check that the forest of the definition at `path`, written back as CSV,
+/// reads back as the same forest, and so does the definition itself.
+fn assert_round_trips<N>(path: &str) -> Result<()>
+where
+    N: SerializedNode,
+    N::ProblemType: WriteForest,
+{
+    let forest = get_forest::<N>(path)?;
+    let csv = to_csv(&SerializedForest::<N>::from_forest(&forest))?;
+
+    let read = Forest::from_serialized(SerializedForest::<N>::from_reader(csv.as_bytes())?)?;
+    assert_eq!(read.num_trees(), forest.num_trees());
+    assert_eq!(read.nodes(), forest.nodes());
+    assert_eq!(
+        N::ProblemType::metadata(&read),
+        N::ProblemType::metadata(&forest)
+    );
+
+    // The writer is deterministic
+    assert_eq!(to_csv(&SerializedForest::<N>::from_forest(&read))?, csv);
+
+    let definition = to_csv(&SerializedForest::<N>::read(path)?)?;
+    let read = Forest::from_serialized(SerializedForest::<N>::from_reader(definition.as_bytes())?)?;
+    assert_eq!(read.nodes(), forest.nodes());
+
+    Ok(())
+}
+
+#[test]
+fn classification_forest_round_trips_through_csv() -> Result<()> {
+    assert_round_trips::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")
+}
+
+#[test]
+fn regression_forest_round_trips_through_csv() -> Result<()> {
+    assert_round_trips::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")
+}
+
+#[test]
+fn csv_follows_the_r_export() -> Result<()> {
+    let definition = "\
+        # { \"problem_type\": \"classification\" }\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b c\",1,3\n\
+        0,0,NA,0,-1,\"a\",2,1\n";
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())?;
+    let forest = Forest::from_serialized(serialized)?;
+
+    assert_eq!(
+        to_csv(&SerializedForest::<SerializedClassificationNode>::from_forest(&forest))?,
+        "\
+        # { \"problem_type\": \"classification\", \"num_trees\": 2, \"num_features\": 1, \"num_targets\": 2 }\n\
+        left daughter,right daughter,split var,split point,status,prediction,tree_idx,node_idx\n\
+        2,3,x,0.5,1,NA,1,1\n\
+        0,0,NA,0.0,-1,a,1,2\n\
+        0,0,NA,0.0,-1,b c,1,3\n\
+        0,0,NA,0.0,-1,a,2,1\n"
+    );
+
+    // Regression branches have no prediction of their own
+    let definition = "\
+        # { \"problem_type\": \"regression\" }\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        2,3,\"x\",0.5,-3,2,1,1\n\
+        0,0,NA,0,-1,1.5,1,2\n\
+        0,0,NA,0,-1,2.5,1,3\n";
+    let serialized =
+        SerializedForest::<SerializedRegressionNode>::from_reader(definition.as_bytes())?;
+    let forest = Forest::from_serialized(serialized)?;
+    let csv = to_csv(&SerializedForest::<SerializedRegressionNode>::from_forest(
+        &forest,
+    ))?;
+    assert!(csv.starts_with(
+        "# { \"problem_type\": \"regression\", \"num_trees\": 1, \"num_features\": 1 }\n"
+    ));
+    assert!(csv.contains("\n2,3,x,0.5,-3,NA,1,1\n0,0,NA,0.0,-1,1.5,1,2\n"));
+    let read = SerializedForest::<SerializedRegressionNode>::from_reader(csv.as_bytes())?;
+    assert_eq!(read.nodes()[0].prediction, None);
+
+    Ok(())
+}
+
+#[test]
+fn written_definition_reads_from_a_file() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("iris.csv");
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+
+    SerializedForest::<SerializedClassificationNode>::from_forest(&forest).write(&path)?;
+    let read = get_forest::<SerializedClassificationNode>(&path)?;
+    assert_eq!(read.nodes(), forest.nodes());
+    assert_eq!(read.targets(), forest.targets());
+
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use aligned_vec::AVec;
+use assert_cmd::Command;
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::deserialize::{BUFFER_ALIGN, ForestHeader, HEADER_LEN, TRANSFORM};
+use embedded_rforest::forest::{
+    Aggregation, OptimizedForest, Predict, Regression, Transform, transform,
+};
+use embedded_rforest::ptr::F32;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::inspect::inspect;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{NodeEncoding, WriteForest};
+use predicates::str::contains;
+
+use crate::helpers::{
+    FLAGS, ROWS, assert_epsilon, get_forest, predictions, regression_forest, regression_stumps,
+    session_predictions,
+};
+
+/// Leaves of two [`regression_stumps`]: 1 or 2 for the first tree, 1 or -6
+/// for the second. They average to 1 on the left, and to -2 on the right
+const LEAVES: [F32; 4] = [F32::new(1.0), F32::new(2.0), F32::new(1.0), F32::new(-6.0)];
+
+fn assert_predicts(forest: &OptimizedForest<Regression>, expected: [f32; 2]) {
+    for predictions in [
+        predictions(forest, &ROWS),
+        session_predictions(forest, &ROWS),
+    ] {
+        for (prediction, expected) in predictions.into_iter().zip(expected) {
+            assert_epsilon(prediction, expected, 1e-6);
+        }
+    }
+}
+
+#[test]
+fn transforms_apply_to_the_combined_output() {
+    let nodes = regression_stumps(2);
+    let identity = regression_forest(&nodes, &LEAVES);
+    assert_eq!(identity.transform(), Transform::Identity);
+    assert_eq!(predictions(&identity, &ROWS), [1.0, -2.0]);
+
+    assert_predicts(
+        &regression_forest(&nodes, &LEAVES)
+            .with_transform(Transform::Sigmoid)
+            .unwrap(),
+        [0.731_058_6, 0.119_202_92],
+    );
+    assert_predicts(
+        &regression_forest(&nodes, &LEAVES)
+            .with_transform(Transform::Exp)
+            .unwrap(),
+        [std::f32::consts::E, 0.135_335_28],
+    );
+    let custom = Transform::Custom {
+        scale: 2.0,
+        offset: 0.5,
+    };
+    let custom = regression_forest(&nodes, &LEAVES)
+        .with_transform(custom)
+        .unwrap();
+    assert_eq!(predictions(&custom, &ROWS), [2.5, -3.5]);
+
+    // After the base score of summed forests: 0.5 + 2 and 0.5 - 4
+    let summed = regression_forest(&nodes, &LEAVES)
+        .with_aggregation(Aggregation::Sum { base: 0.5 })
+        .unwrap()
+        .with_transform(Transform::Exp)
+        .unwrap();
+    assert_predicts(&summed, [12.182_494, 0.030_197_383]);
+}
+
+#[test]
+fn transforms_round_trip_through_the_header() {
+    let nodes = regression_stumps(2);
+    let plain = regression_forest(&nodes, &LEAVES).to_bytes();
+    assert_eq!(plain[FLAGS] & TRANSFORM, 0);
+    assert_eq!(
+        ForestHeader::peek(&plain).unwrap().transform,
+        Transform::Identity
+    );
+
+    for transform in [
+        Transform::Sigmoid,
+        Transform::Exp,
+        Transform::Custom {
+            scale: -1.5,
+            offset: 3.0,
+        },
+    ] {
+        let bytes = regression_forest(&nodes, &LEAVES)
+            .with_transform(transform)
+            .unwrap()
+            .to_bytes();
+        assert_ne!(bytes[FLAGS] & TRANSFORM, 0);
+        // The transform takes 12 bytes of the header, padded to the alignment
+        assert_eq!(bytes.len(), plain.len() + 2 * BUFFER_ALIGN);
+        let header = ForestHeader::peek(&bytes).unwrap();
+        assert_eq!(header.transform, transform);
+        assert_eq!(header.serialized_len(), bytes.len());
+
+        let loaded = OptimizedForest::<Regression>::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.transform(), transform);
+        assert_eq!(loaded.to_bytes(), bytes);
+
+        let info = inspect(&bytes).unwrap();
+        assert_eq!(info.transform, transform.to_string());
+        assert!(
+            info.to_string()
+                .contains(&format!("Transform:       {transform}"))
+        );
+    }
+}
+
+#[test]
+fn transforms_need_finite_parameters() {
+    let nodes = regression_stumps(2);
+    for (scale, offset) in [(f32::NAN, 0.0), (1.0, f32::INFINITY)] {
+        assert_eq!(
+            regression_forest(&nodes, &LEAVES)
+                .with_transform(Transform::Custom { scale, offset })
+                .err(),
+            Some(Error::MalformedForest)
+        );
+    }
+
+    let custom = Transform::Custom {
+        scale: 2.0,
+        offset: 0.5,
+    };
+    let bytes = regression_forest(&nodes, &LEAVES)
+        .with_transform(custom)
+        .unwrap()
+        .to_bytes();
+    // Right after the header: the kind, then the scale
+    let mut malformed = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+    malformed[HEADER_LEN + 4..HEADER_LEN + 8].copy_from_slice(&f32::NAN.to_le_bytes());
+    assert_eq!(
+        ForestHeader::peek(&malformed).err(),
+        Some(Error::MalformedForest)
+    );
+    let mut unknown = AVec::<u8>::from_slice(BUFFER_ALIGN, &bytes);
+    unknown[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&7u32.to_le_bytes());
+    assert_eq!(
+        ForestHeader::peek(&unknown).err(),
+        Some(Error::MalformedForest)
+    );
+}
+
+#[test]
+fn exp_and_sigmoid_are_accurate() {
+    let mut x = -80.0f32;
+    while x <= 80.0 {
+        let exact = f64::from(x).exp();
+        let error = (f64::from(transform::exp(x)) - exact).abs() / exact;
+        assert!(error < 2.5e-7, "exp({x}) is off by {error}");
+
+        let exact = 1.0 / (1.0 + (-f64::from(x)).exp());
+        let error = (f64::from(transform::sigmoid(x)) - exact).abs();
+        assert!(error < 1e-7, "sigmoid({x}) is off by {error}");
+
+        x += 0.001_3;
+    }
+
+    assert_eq!(transform::exp(0.0), 1.0);
+    assert_eq!(transform::exp(100.0), f32::INFINITY);
+    assert_eq!(transform::exp(-120.0), 0.0);
+    assert!(transform::exp(f32::NAN).is_nan());
+    assert_eq!(transform::sigmoid(-100.0), 0.0);
+    assert_eq!(transform::sigmoid(100.0), 1.0);
+}
+
+#[test]
+fn serialized_forests_carry_the_transform() -> Result<()> {
+    let forest =
+        get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &features)?;
+
+    let plain = <RegressionProblem as WriteForest>::serialize(&forest)?;
+    let encoding = NodeEncoding {
+        transform: Transform::Custom {
+            scale: 0.5,
+            offset: -10.0,
+        },
+        ..Default::default()
+    };
+    let transformed = RegressionProblem::serialize_encoded(&forest, &encoding)?;
+    let plain = OptimizedForest::<Regression>::deserialize(&plain).unwrap();
+    let transformed = OptimizedForest::<Regression>::deserialize(&transformed).unwrap();
+    for row in &rows {
+        assert_eq!(
+            transformed.predict(row),
+            0.5 * plain.predict(row) - 10.0,
+            "{row:?}"
+        );
+    }
+
+    // Classification forests vote for a class, which has nothing to transform
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let encoding = NodeEncoding {
+        transform: Transform::Sigmoid,
+        ..Default::default()
+    };
+    assert!(ClassificationProblem::serialize_encoded(&forest, &encoding).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn convert_records_the_transform() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("airfoil.rforest");
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "-i", "./tests/test-forests/airfoil_100_200.csv"])
+        .args(["--transform", "sigmoid", "-o"])
+        .arg(&output)
+        .assert()
+        .success();
+    let bytes = AVec::<u8>::from_slice(BUFFER_ALIGN, &std::fs::read(&output)?);
+    assert_eq!(
+        ForestHeader::peek(&bytes).unwrap().transform,
+        Transform::Sigmoid
+    );
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "-i", "./tests/test-forests/airfoil_100_200.csv"])
+        .args(["--transform", "custom=2,nan", "-o"])
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(contains("is not a finite number"));
+
+    Command::cargo_bin("forest-optimizer")?
+        .args(["convert", "-i", "./tests/test-forests/forest_iris_5.csv"])
+        .args(["--transform", "exp", "-o"])
+        .arg(dir.path().join("iris.rforest"))
+        .assert()
+        .failure()
+        .stderr(contains("Only regression forests transform their output"));
+
+    Ok(())
+}
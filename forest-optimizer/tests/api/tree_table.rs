@@ -0,0 +1,129 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, NodeLayout, OptimizedForest, Predict, Regression,
+    deserialize::{ForestHeader, HEADER_LEN, TREE_TABLE},
+};
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::{
+    Classification as ClassificationProblem, Regression as RegressionProblem,
+};
+use forest_optimizer::prune::keep_first_trees;
+use forest_optimizer::serialized_forest::{SerializedClassificationNode, SerializedRegressionNode};
+use forest_optimizer::write_forest::{PointerWidth, WriteForest};
+
+use crate::helpers::get_forest;
+
+#[test]
+fn serialized_forests_record_where_each_tree_lives() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &forest.features_by_index())?;
+
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let header = ForestHeader::peek(&buffer).unwrap();
+    assert!(header.tree_table);
+    assert_eq!(buffer[10] & TREE_TABLE, TREE_TABLE);
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+
+    // Trees follow each other, root first, and cover every branch
+    let mut next = 0;
+    for (tree, (nodes, original)) in optimized.iter_trees().zip(forest.trees()).enumerate() {
+        let range = optimized.tree_range(tree as u32).unwrap();
+        assert_eq!(range.start, next);
+        assert_eq!(optimized.tree_root(tree as u32), range.start);
+        assert_eq!(nodes.len(), original.num_branches());
+        next = range.end;
+    }
+    assert_eq!(next, optimized.nodes().len());
+    assert_eq!(optimized.iter_trees().count(), 5);
+    assert_eq!(optimized.tree_range(5), None);
+
+    // Forests without the table find their roots at the index of their tree
+    let (nodes, _) = forest.optimize_nodes();
+    let implicit =
+        OptimizedForest::<Classification>::new(5, &nodes, 4, Classification::new(3).unwrap())
+            .unwrap();
+    let bytes = implicit.to_bytes();
+    assert!(!ForestHeader::peek(&bytes).unwrap().tree_table);
+    assert_eq!(bytes.len() + 24, buffer.len());
+    let implicit = OptimizedForest::<Classification>::deserialize(&bytes).unwrap();
+    assert_eq!(implicit.tree_range(0), None);
+    assert_eq!(implicit.iter_trees().count(), 0);
+    assert_eq!(implicit.tree_root(3), 3);
+    for row in &rows {
+        assert_eq!(optimized.predict(row), implicit.predict(row));
+        assert_eq!(
+            optimized.predict_counting(row).1,
+            implicit.predict_counting(row).1
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn every_layout_keeps_the_tree_table() -> Result<()> {
+    let forest = keep_first_trees(
+        &get_forest::<SerializedRegressionNode>("./tests/test-forests/airfoil_100_200.csv")?,
+        3,
+    );
+    let rows = read_mapped_rows("./tests/test-data/airfoil.csv", &forest.features_by_index())?;
+    let standard = RegressionProblem::serialize(&forest)?;
+    let standard = AnyOptimizedForest::<Regression>::deserialize(&standard).unwrap();
+
+    for width in [PointerWidth::U16, PointerWidth::Relative] {
+        let buffer = RegressionProblem::serialize_with(&forest, width)?;
+        assert!(ForestHeader::peek(&buffer).unwrap().tree_table);
+
+        let optimized = AnyOptimizedForest::<Regression>::deserialize(&buffer).unwrap();
+        assert_ne!(optimized.layout(), NodeLayout::Standard);
+        for row in &rows {
+            assert_eq!(
+                optimized.predict(row).to_bits(),
+                standard.predict(row).to_bits()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn overlapping_trees_are_rejected() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let offset = |buffer: &[u8], tree: usize| {
+        let at = HEADER_LEN + tree * 4;
+        u32::from_le_bytes(buffer[at..at + 4].try_into().unwrap())
+    };
+    let corrupt = |change: &dyn Fn(&mut [u8])| {
+        let mut corrupted = buffer.clone();
+        change(&mut corrupted);
+        OptimizedForest::<Classification>::deserialize(&corrupted).map(|_| ())
+    };
+    let set = |buffer: &mut [u8], tree: usize, value: u32| {
+        let at = HEADER_LEN + tree * 4;
+        buffer[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    };
+
+    // An empty tree, and trees out of order
+    assert_eq!(corrupt(&|b| set(b, 1, 0)), Err(Error::MalformedForest));
+    let second = offset(&buffer, 2);
+    assert_eq!(
+        corrupt(&|b| set(b, 1, second + 1)),
+        Err(Error::MalformedForest)
+    );
+    // The root of the second tree in the first one, pointing out of it
+    let first = offset(&buffer, 1);
+    assert_eq!(
+        corrupt(&|b| set(b, 1, first + 1)),
+        Err(Error::MalformedForest)
+    );
+    // Trees must start at the first branch, and flags be known
+    assert_eq!(corrupt(&|b| set(b, 0, 1)), Err(Error::MalformedForest));
+    assert_eq!(corrupt(&|b| b[10] |= 0x80), Err(Error::MalformedForest));
+
+    Ok(())
+}
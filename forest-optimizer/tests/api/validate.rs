@@ -0,0 +1,363 @@
+use color_eyre::Result;
+use forest_optimizer::serialized_forest::validate::{Diagnostic, Severity};
+use forest_optimizer::serialized_forest::{
+    SerializedClassificationNode, SerializedForest, SerializedNode, SerializedRegressionNode,
+};
+
+use crate::helpers::get_forest;
+
+const COLUMNS: &str = "\"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n";
+
+/// Read `rows` after the header of `problem_type` and the columns, so that
+/// the first row is on line 3.
+fn read<N: SerializedNode>(problem_type: &str, rows: &str) -> Result<SerializedForest<N>> {
+    let definition = format!("# {{ \"problem_type\": \"{problem_type}\" }}\n{COLUMNS}{rows}");
+    SerializedForest::<N>::from_reader(definition.as_bytes())
+}
+
+/// Severity, line and message of each diagnostic of a classification forest
+/// made of `rows`.
+fn diagnose(rows: &str) -> Result<Vec<(Severity, Option<u64>, String)>> {
+    let serialized = read::<SerializedClassificationNode>("classification", rows)?;
+    Ok(serialized
+        .validate()
+        .into_iter()
+        .map(|d| (d.severity, d.line, d.message))
+        .collect())
+}
+
+fn error(line: u64, message: &str) -> (Severity, Option<u64>, String) {
+    (Severity::Error, Some(line), message.to_string())
+}
+
+fn warning(line: u64, message: &str) -> (Severity, Option<u64>, String) {
+    (Severity::Warning, Some(line), message.to_string())
+}
+
+#[test]
+fn fixtures_have_no_diagnostics() -> Result<()> {
+    let serialized = SerializedForest::<SerializedClassificationNode>::read(
+        "./tests/test-forests/forest_iris_800.csv",
+    )?;
+    assert_eq!(serialized.validate(), []);
+    serialized.check()?;
+
+    let serialized = SerializedForest::<SerializedRegressionNode>::read(
+        "./tests/test-forests/airfoil_100_200.csv",
+    )?;
+    assert_eq!(serialized.validate(), []);
+
+    // Nor do definitions written back
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_5.csv")?;
+    assert_eq!(
+        SerializedForest::<SerializedClassificationNode>::from_forest(&forest).validate(),
+        []
+    );
+
+    Ok(())
+}
+
+#[test]
+fn daughters_must_be_nodes_further_down_the_same_tree() -> Result<()> {
+    let rows = "\
+        2,4,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n\
+        0,0,NA,0,-1,\"a\",2,4\n";
+    assert_eq!(
+        diagnose(rows)?,
+        [
+            error(3, "Daughter 4 is not a node of tree 1"),
+            warning(5, "Node is no branch's daughter, so is never reached"),
+            (
+                Severity::Error,
+                None,
+                "Tree 2 has no root, node 1".to_string()
+            ),
+        ]
+    );
+
+    let rows = "\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        3,1,\"x\",0.5,1,NA,1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n";
+    assert_eq!(
+        diagnose(rows)?,
+        [
+            error(4, "Daughter 1 is not further down the tree"),
+            error(5, "Node is the daughter of 2 branches"),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn statuses_must_match_the_row() -> Result<()> {
+    let rows = "\
+        2,3,\"x\",0.5,2,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,0,\"b\",1,3\n";
+    assert_eq!(
+        diagnose(rows)?,
+        [
+            error(3, "Branch has status 2"),
+            error(5, "Status 0 is neither a branch's nor a leaf's (-1)"),
+        ]
+    );
+
+    // Regression branches may also have status -3, not classification ones
+    let rows = "\
+        2,3,\"x\",0.5,-3,2,1,1\n\
+        0,0,NA,0,-1,1.5,1,2\n\
+        0,0,NA,0,-1,2.5,1,3\n";
+    let serialized = read::<SerializedRegressionNode>("regression", rows)?;
+    assert_eq!(serialized.validate(), []);
+    assert_eq!(diagnose(&rows.replace("2,1,1\n", "NA,1,1\n"))?.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn branches_need_a_split_variable_and_daughters() -> Result<()> {
+    let rows = "\
+        2,3,NA,0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n";
+    assert_eq!(
+        diagnose(rows)?,
+        [
+            error(
+                3,
+                "Row has the status of a branch (1) but no split variable"
+            ),
+            warning(4, "Node is no branch's daughter, so is never reached"),
+            warning(5, "Node is no branch's daughter, so is never reached"),
+        ]
+    );
+
+    let rows = "\
+        2,0,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n";
+    assert_eq!(
+        diagnose(rows)?,
+        [error(3, "Branch has no daughter on one side (0)")]
+    );
+
+    // Flattening fails rather than panicking
+    let serialized = read::<SerializedClassificationNode>("classification", rows)?;
+    let error = forest_optimizer::forest::Forest::from_serialized(serialized).unwrap_err();
+    assert!(error.to_string().contains("no daughter"), "{error}");
+
+    Ok(())
+}
+
+#[test]
+fn leaves_with_daughters_are_warned_about() -> Result<()> {
+    let rows = "\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        2,3,NA,0,-1,\"b\",1,3\n\
+        0,0,NA,0,-1,NA,2,1\n";
+    assert_eq!(
+        diagnose(rows)?,
+        [
+            warning(5, "Leaf has daughters 2 and 3, which are ignored"),
+            error(6, "Leaf has no prediction"),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn numbers_must_be_finite() -> Result<()> {
+    let rows = "\
+        2,3,\"x\",Inf,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n";
+    assert_eq!(
+        diagnose(rows)?,
+        [error(3, "Split point inf is not a finite number")]
+    );
+
+    let rows = "\
+        2,3,\"x\",0.5,-3,NA,1,1\n\
+        0,0,NA,0,-1,NaN,1,2\n\
+        0,0,NA,0,-1,2.5,1,3\n";
+    let diagnostics = read::<SerializedRegressionNode>("regression", rows)?.validate();
+    assert_eq!(
+        diagnostics,
+        [Diagnostic {
+            severity: Severity::Error,
+            tree_idx: Some(1),
+            node_idx: Some(2),
+            line: Some(4),
+            message: "Prediction NaN is not a finite number".to_string(),
+        }]
+    );
+    assert_eq!(
+        diagnostics[0].to_string(),
+        "line 4, tree 1, node 2: Prediction NaN is not a finite number"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn rows_which_do_not_parse_name_their_line() {
+    // A classification forest read as a regression one
+    let rows = "\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"setosa\",1,2\n";
+    let error = read::<SerializedRegressionNode>("regression", rows).unwrap_err();
+    let error = format!("{error:#}");
+    assert!(error.contains("on line 4"), "{error}");
+    assert!(error.contains("'setosa' is not a number"), "{error}");
+
+    let rows = "2,3,\"x\",abc,1,NA,1,1\n";
+    let error = read::<SerializedClassificationNode>("classification", rows).unwrap_err();
+    assert!(format!("{error:#}").contains("on line 3"), "{error:#}");
+}
+
+#[test]
+fn lines_count_the_header_and_comments() -> Result<()> {
+    let definition = format!(
+        "{COLUMNS}\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        # A comment\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n\
+        0,0,NA,0,-1,\"a\",1,3\n"
+    );
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())?;
+    let diagnostics = serialized.validate();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].line, Some(6));
+    assert_eq!(
+        diagnostics[0].message,
+        "Node 3 is defined again, first on line 5"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn check_fails_on_errors_only() -> Result<()> {
+    let warned = "\
+        0,0,NA,0,-1,\"a\",1,1\n\
+        0,0,NA,0,-1,\"b\",1,2\n";
+    read::<SerializedClassificationNode>("classification", warned)?.check()?;
+
+    let invalid = "\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,NA,1,2\n\
+        0,0,NA,0,2,\"b\",1,3\n";
+    let error = read::<SerializedClassificationNode>("classification", invalid)?
+        .check()
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "The forest definition has 2 errors, the first: line 4, tree 1, node 2: Leaf has no \
+         prediction"
+    );
+
+    Ok(())
+}
+
+/// The iris_800 fixture, its header declaring its counts, without its trees
+/// from `keep_trees + 1` on.
+fn truncated_iris_800(keep_trees: usize) -> Result<String> {
+    let definition = std::fs::read_to_string("./tests/test-forests/forest_iris_800.csv")?;
+    let mut lines = definition.lines().skip(1);
+    let columns = lines.next().unwrap_or_default();
+    let rows = lines
+        .filter(|row| {
+            let tree_idx = row
+                .split(',')
+                .nth(6)
+                .and_then(|idx| idx.parse::<usize>().ok());
+            tree_idx.is_some_and(|idx| idx <= keep_trees)
+        })
+        .collect::<Vec<_>>();
+
+    Ok(format!(
+        "# {{ \"problem_type\": \"classification\", \"num_trees\": 800, \"num_features\": 4, \"num_targets\": 3 }}\n{columns}\n{}\n",
+        rows.join("\n")
+    ))
+}
+
+#[test]
+fn header_counts_are_checked() -> Result<()> {
+    let complete = truncated_iris_800(800)?;
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(complete.as_bytes())?;
+    assert_eq!(serialized.declared_counts(), serialized.counts());
+    assert_eq!(serialized.validate(), []);
+
+    let truncated = truncated_iris_800(750)?;
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(truncated.as_bytes())?;
+    assert_eq!(
+        serialized.validate(),
+        [Diagnostic {
+            severity: Severity::Error,
+            tree_idx: None,
+            node_idx: None,
+            line: Some(1),
+            message: "Header declares 800 trees, file contains 750".to_string(),
+        }]
+    );
+    let error = serialized.check().unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("line 1: Header declares 800 trees, file contains 750"),
+        "{error}"
+    );
+
+    // Unless allowed
+    let serialized = serialized.allow_count_mismatch();
+    assert_eq!(serialized.validate()[0].severity, Severity::Warning);
+    serialized.check()?;
+
+    Ok(())
+}
+
+#[test]
+fn fewer_features_or_targets_than_declared_are_warned_about() -> Result<()> {
+    let definition = "\
+        # { \"problem_type\": \"classification\", \"num_trees\": 1, \"num_features\": 3, \"num_targets\": 1 }\n\
+        \"left daughter\",\"right daughter\",\"split var\",\"split point\",\"status\",\"prediction\",\"tree_idx\",\"node_idx\"\n\
+        2,3,\"x\",0.5,1,NA,1,1\n\
+        0,0,NA,0,-1,\"a\",1,2\n\
+        0,0,NA,0,-1,\"b\",1,3\n";
+    let serialized =
+        SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())?;
+    let diagnostics = serialized
+        .validate()
+        .into_iter()
+        .map(|d| (d.severity, d.line, d.message))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        diagnostics,
+        [
+            warning(1, "Header declares 3 features, file contains 1"),
+            error(1, "Header declares 1 targets, file contains 2"),
+        ]
+    );
+
+    // Malformed counts fail the reading
+    let definition = definition.replace("\"num_trees\": 1", "\"num_trees\": -1");
+    let error =
+        SerializedForest::<SerializedClassificationNode>::from_reader(definition.as_bytes())
+            .unwrap_err();
+    assert!(
+        error.to_string().contains("invalid \"num_trees\""),
+        "{error}"
+    );
+
+    Ok(())
+}
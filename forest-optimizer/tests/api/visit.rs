@@ -0,0 +1,121 @@
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    Branch, BranchLayout, Child, Classification, NodeVisit, OptimizedForest, ProblemType,
+    Regression, access::NodeAccess,
+};
+use embedded_rforest::ptr::{F32, NodeIndex, NodePointer, RelativeU16, U32};
+
+/// A tree of two branches: the root splits feature 0 at 0.5, going left to
+/// a branch on feature 1 and right to leaf 2; that branch goes to leaves 0
+/// and 1.
+fn two_branches() -> [Branch; 2] {
+    [
+        Branch::new(0, 0.5, NodePointer::new_branch(1), NodePointer::new_leaf(2)),
+        Branch::new(1, 1.5, NodePointer::new_leaf(0), NodePointer::new_leaf(1)),
+    ]
+}
+
+fn visits<P: ProblemType, B: BranchLayout, A: NodeAccess<Branch = B> + ?Sized>(
+    forest: &OptimizedForest<'_, P, B, A>,
+) -> Vec<NodeVisit> {
+    let mut visits = Vec::new();
+    forest.visit(|visit| visits.push(visit)).unwrap();
+    visits
+}
+
+#[test]
+fn classification_children_are_classes() {
+    let nodes = two_branches();
+    let forest =
+        OptimizedForest::<Classification>::new(1, &nodes, 2, Classification::new(3).unwrap())
+            .unwrap();
+
+    assert_eq!(
+        visits(&forest),
+        [
+            NodeVisit {
+                index: 0,
+                split_with: 0,
+                split_at: 0.5,
+                left: Child::Branch(1),
+                right: Child::LeafClass(2),
+            },
+            NodeVisit {
+                index: 1,
+                split_with: 1,
+                split_at: 1.5,
+                left: Child::LeafClass(0),
+                right: Child::LeafClass(1),
+            },
+        ]
+    );
+    assert_eq!(
+        forest.children(1),
+        Ok((Child::LeafClass(0), Child::LeafClass(1)))
+    );
+    assert_eq!(forest.children(2), Err(Error::MalformedForest));
+
+    let display = forest.to_string();
+    assert!(
+        display.contains("0: Branch | split var: 0, split: 0.5, left: branch 1, right: class 2"),
+        "{display}"
+    );
+}
+
+#[test]
+fn regression_children_are_leaf_values() {
+    let nodes = two_branches();
+    let leaves = [F32::new(-1.0), F32::new(0.25), F32::new(4.0)];
+    let forest = OptimizedForest::<Regression>::with_leaves(1, &nodes, 2, &leaves).unwrap();
+
+    let visits = visits(&forest);
+    assert_eq!(visits[0].left, Child::Branch(1));
+    assert_eq!(visits[0].right, Child::LeafValue(4.0));
+    assert_eq!(
+        forest.children(1),
+        Ok((Child::LeafValue(-1.0), Child::LeafValue(0.25)))
+    );
+
+    // A leaf past the table does not decode, so the forest is rejected
+    assert_eq!(
+        OptimizedForest::<Regression>::with_leaves(1, &nodes, 2, &leaves[..2]).err(),
+        Some(Error::MalformedForest)
+    );
+}
+
+#[test]
+fn relative_children_are_resolved_against_their_tree() {
+    let ptr = |index, is_leaf| RelativeU16::tagged(index, is_leaf).unwrap();
+    // Two trees of two branches each, the second starting at branch 2
+    let tree = [
+        Branch::from_ptrs(0, 0.5, ptr(1, false), ptr(2, true)),
+        Branch::from_ptrs(1, 1.5, ptr(0, true), ptr(1, true)),
+    ];
+    let nodes = [tree.clone(), tree].concat();
+    let offsets = [U32::new(0), U32::new(2)];
+    let forest = OptimizedForest::<Classification, _>::new_relative(
+        &nodes,
+        &offsets,
+        2,
+        Classification::new(3).unwrap(),
+    )
+    .unwrap();
+
+    let lefts = visits(&forest)
+        .iter()
+        .map(|visit| visit.left)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lefts,
+        [
+            Child::Branch(1),
+            Child::LeafClass(0),
+            Child::Branch(3),
+            Child::LeafClass(0)
+        ]
+    );
+    assert_eq!(
+        forest.children(2),
+        Ok((Child::Branch(3), Child::LeafClass(2)))
+    );
+}
@@ -0,0 +1,133 @@
+use color_eyre::Result;
+use embedded_rforest::Error;
+use embedded_rforest::forest::{
+    Branch, Classification, ClassifierN, MAX_CLASSIFICATION_TREES, OptimizedForest, Predict, Votes,
+};
+use embedded_rforest::ptr::NodePointer;
+use forest_optimizer::dataset::read_mapped_rows;
+use forest_optimizer::problem_type::Classification as ClassificationProblem;
+use forest_optimizer::serialized_forest::SerializedClassificationNode;
+use forest_optimizer::write_forest::WriteForest;
+
+use crate::helpers::get_forest;
+
+/// One single-branch tree per vote, predicting its class on both sides.
+fn voting_trees(votes: &[u32]) -> Vec<Branch> {
+    votes
+        .iter()
+        .map(|&class| {
+            Branch::new(
+                0,
+                0.5,
+                NodePointer::new_leaf(class),
+                NodePointer::new_leaf(class),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn votes_count_every_tree_and_break_ties_on_the_lowest_class() -> Result<()> {
+    let mut votes = Votes::<4>::new();
+    for class in [2, 1, 3, 2, 1] {
+        votes.add(class);
+    }
+    assert_eq!(votes.counts(), &[0, 2, 2, 1]);
+    assert_eq!(votes.winner(), 1);
+
+    for (trees, winner) in [
+        (&[3][..], 3),
+        (&[2, 1, 1, 2], 1),
+        (&[2, 3, 3, 2, 2], 2),
+        (&[0, 3], 0),
+    ] {
+        let nodes = voting_trees(trees);
+        let forest = OptimizedForest::<Classification>::new(
+            trees.len() as u32,
+            &nodes,
+            1,
+            Classification::new(4).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(forest.predict(&[0.0]), winner);
+        assert_eq!(forest.predict_with::<4>(&[0.0]), winner);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn predict_with_fewer_counters_matches_predict() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    for row in &rows {
+        assert_eq!(optimized.predict_with::<3>(row), optimized.predict(row));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn classifier_of_known_class_count_matches_predict() -> Result<()> {
+    let forest =
+        get_forest::<SerializedClassificationNode>("./tests/test-forests/forest_iris_800.csv")?;
+    let features = forest.features_by_index();
+    let rows = read_mapped_rows("./tests/test-data/iris.csv", &features)?;
+
+    let buffer = ClassificationProblem::serialize(&forest)?;
+    let optimized = OptimizedForest::<Classification>::deserialize(&buffer).unwrap();
+    let classifier = ClassifierN::<3>::try_from(&optimized).unwrap();
+    for row in &rows {
+        assert_eq!(classifier.predict(row), optimized.predict(row));
+        assert_eq!(
+            classifier.predict_counting(row),
+            optimized.predict_counting(row)
+        );
+    }
+
+    // Iris has 3 classes, no more, no less
+    assert!(matches!(
+        ClassifierN::<2>::try_from(&optimized),
+        Err(Error::WrongTargetCount)
+    ));
+    assert!(matches!(
+        ClassifierN::<4>::try_from(&optimized),
+        Err(Error::WrongTargetCount)
+    ));
+
+    Ok(())
+}
+
+#[test]
+#[should_panic]
+fn predict_with_too_few_counters_panics() {
+    let nodes = voting_trees(&[0, 2]);
+    let forest =
+        OptimizedForest::<Classification>::new(2, &nodes, 1, Classification::new(3).unwrap())
+            .unwrap();
+    let _ = forest.predict_with::<2>(&[0.0]);
+}
+
+#[test]
+fn classification_forest_with_more_trees_than_votes_is_rejected() {
+    let nodes = voting_trees(&vec![0; MAX_CLASSIFICATION_TREES as usize + 1]);
+    let new = |num_trees| {
+        OptimizedForest::<Classification>::new(
+            num_trees,
+            &nodes,
+            1,
+            Classification::new(1).unwrap(),
+        )
+        .map(|_| ())
+    };
+    assert_eq!(new(MAX_CLASSIFICATION_TREES), Ok(()));
+    assert_eq!(
+        new(MAX_CLASSIFICATION_TREES + 1),
+        Err(Error::MalformedForest)
+    );
+}
@@ -0,0 +1,22 @@
+/* Generated by forest-optimizer. Do not edit. */
+/* Indices of the features and classes of the CLASSIFICATION forest */
+#ifndef ERF_FOREST_IRIS_5_INDICES_H
+#define ERF_FOREST_IRIS_5_INDICES_H
+
+/* Fingerprint of the feature and target names. Indices of another
+   version of the forest differ from the _FINGERPRINT of its header */
+#define ERF_FINGERPRINT 0xbe895139u
+
+#define ERF_NUM_FEATURES 4
+#define ERF_NUM_TARGETS 3
+
+#define ERF_FEATURE_PETAL_LENGTH 0
+#define ERF_FEATURE_PETAL_WIDTH 1
+#define ERF_FEATURE_SEPAL_LENGTH 2
+#define ERF_FEATURE_SEPAL_WIDTH 3
+
+#define ERF_CLASS_SETOSA 0
+#define ERF_CLASS_VERSICOLOR 1
+#define ERF_CLASS_VIRGINICA 2
+
+#endif /* ERF_FOREST_IRIS_5_INDICES_H */
@@ -0,0 +1,19 @@
+// Generated by forest-optimizer. Do not edit.
+// Indices of the features and classes of the CLASSIFICATION forest
+
+/// Fingerprint of the feature and target names. Indices of another
+/// version of the forest differ from its `FINGERPRINT`, see
+/// `embedded_rforest::forest_consts!`
+pub const FINGERPRINT: u32 = 0xbe895139;
+
+pub const NUM_FEATURES: usize = 4;
+pub const NUM_TARGETS: usize = 3;
+
+pub const FEATURE_PETAL_LENGTH: usize = 0;
+pub const FEATURE_PETAL_WIDTH: usize = 1;
+pub const FEATURE_SEPAL_LENGTH: usize = 2;
+pub const FEATURE_SEPAL_WIDTH: usize = 3;
+
+pub const CLASS_SETOSA: usize = 0;
+pub const CLASS_VERSICOLOR: usize = 1;
+pub const CLASS_VIRGINICA: usize = 2;
@@ -0,0 +1,15 @@
+#![no_main]
+
+use aligned_vec::{AVec, ConstAlign};
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, OptimizedForest, deserialize::BUFFER_ALIGN,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Forests are read from aligned buffers on the device too
+    let buffer = AVec::<u8, ConstAlign<BUFFER_ALIGN>>::from_slice(BUFFER_ALIGN, data);
+
+    let _ = OptimizedForest::<Classification>::deserialize(&buffer);
+    let _ = AnyOptimizedForest::<Classification>::deserialize(&buffer);
+});
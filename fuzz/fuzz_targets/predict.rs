@@ -0,0 +1,62 @@
+#![no_main]
+
+use aligned_vec::{AVec, ConstAlign};
+use embedded_rforest::forest::{
+    AnyOptimizedForest, Classification, Predict, ProblemKind, Regression,
+    deserialize::{BUFFER_ALIGN, ForestHeader},
+};
+use libfuzzer_sys::fuzz_target;
+
+/// Most features a forest can split on, as their number is a `u8`.
+const MAX_FEATURES: usize = u8::MAX as usize;
+
+/// Rows of [`MAX_FEATURES`] features: edge values, and pseudo-random values
+/// derived from the input, so that a crash reproduces from its input alone.
+fn rows(data: &[u8]) -> [[f32; MAX_FEATURES]; 5] {
+    // FNV-1a, then xorshift
+    let mut state = data.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3)
+    }) | 1;
+    let mut random = [0.0; MAX_FEATURES];
+    for feature in &mut random {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        *feature = f32::from_bits(state as u32);
+    }
+
+    [
+        [0.0; MAX_FEATURES],
+        [f32::NAN; MAX_FEATURES],
+        [f32::INFINITY; MAX_FEATURES],
+        [f32::NEG_INFINITY; MAX_FEATURES],
+        random,
+    ]
+}
+
+fuzz_target!(|data: &[u8]| {
+    let buffer = AVec::<u8, ConstAlign<BUFFER_ALIGN>>::from_slice(BUFFER_ALIGN, data);
+    let Ok(header) = ForestHeader::peek(&buffer) else {
+        return;
+    };
+
+    // A valid forest must predict every row of at least `num_features`
+    // features, without panicking nor looping forever
+    match header.problem_kind() {
+        ProblemKind::Classification => {
+            if let Ok(forest) = AnyOptimizedForest::<Classification>::deserialize(&buffer) {
+                for row in rows(data) {
+                    let class = forest.predict(&row);
+                    assert!(class < u32::from(header.num_targets.unwrap().get()));
+                }
+            }
+        }
+        ProblemKind::Regression => {
+            if let Ok(forest) = AnyOptimizedForest::<Regression>::deserialize(&buffer) {
+                for row in rows(data) {
+                    let _ = forest.predict(&row);
+                }
+            }
+        }
+    }
+});
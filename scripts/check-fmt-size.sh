@@ -0,0 +1,25 @@
+#!/bin/sh
+# Build examples/bench-cortex-m with and without the `fmt` feature of
+# embedded-rforest, and fail unless leaving it out shrinks `.text` by at
+# least MIN_SAVED bytes (2048 by default). Needs the thumbv7em-none-eabihf
+# target and llvm-size.
+set -eu
+
+min_saved=${MIN_SAVED:-2048}
+cd "$(dirname "$0")/../examples/bench-cortex-m"
+
+text() {
+    cargo build --release --quiet "$@"
+    llvm-size -A target/thumbv7em-none-eabihf/release/bench-cortex-m |
+        awk '$1 == ".text" { print $2 }'
+}
+
+with=$(text)
+without=$(text --no-default-features)
+saved=$((with - without))
+echo ".text: $with bytes with fmt, $without without, $saved saved"
+
+if [ "$saved" -lt "$min_saved" ]; then
+    echo "Leaving out fmt saves less than $min_saved bytes" >&2
+    exit 1
+fi
@@ -0,0 +1,12 @@
+//! Writes a forest into `OUT_DIR`, as a build script converting its model
+//! at build time would.
+
+use std::{env, fs, path::PathBuf};
+
+const FIXTURE: &str = "../../forest-optimizer/tests/test-forests/forest_iris_5.rforest";
+
+fn main() {
+    println!("cargo::rerun-if-changed={FIXTURE}");
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy(FIXTURE, out_dir.join("forest.rforest")).unwrap();
+}
@@ -0,0 +1,18 @@
+//! Embeds the forest `build.rs` writes into `OUT_DIR` with
+//! `static_storage!`, whose path is then not a literal.
+#![no_std]
+
+use embedded_rforest::static_storage;
+
+/// The forest written by `build.rs`
+pub fn forest() -> &'static [u8] {
+    static_storage!(concat!(env!("OUT_DIR"), "/forest.rforest"))
+}
+
+/// The same forest, in a section of its own
+pub fn forest_in_section() -> &'static [u8] {
+    static_storage!(
+        concat!(env!("OUT_DIR"), "/forest.rforest"),
+        unsafe(link_section = ".rodata.forest"),
+    )
+}
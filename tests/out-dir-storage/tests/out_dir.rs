@@ -0,0 +1,19 @@
+use embedded_rforest::forest::{Classification, OptimizedForest, deserialize::BUFFER_ALIGN};
+
+const FIXTURE: &str = "../../forest-optimizer/tests/test-forests/forest_iris_5.rforest";
+
+#[test]
+fn forests_written_to_out_dir_deserialize() {
+    let expected = std::fs::read(FIXTURE).unwrap();
+
+    for bytes in [
+        out_dir_storage::forest(),
+        out_dir_storage::forest_in_section(),
+    ] {
+        assert_eq!(bytes, &expected[..]);
+        assert!((bytes.as_ptr() as usize).is_multiple_of(BUFFER_ALIGN));
+
+        let forest = OptimizedForest::<Classification>::deserialize(bytes).unwrap();
+        assert_eq!(forest.num_trees(), 5);
+    }
+}